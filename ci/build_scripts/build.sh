@@ -48,6 +48,12 @@ Flags:
     --help|-h   Show this help
     --skip-build    Skip building the binaries and only package them (e.g. just create the linux packages)
 
+PKCS#11:
+    The support of device keys held by PKCS#11 tokens is only built for the GNU variants,
+    as the PKCS#11 modules cannot be loaded by the statically linked MUSL binaries.
+    When the GNU target matches the build host, the resulting binary is checked against a SoftHSM token,
+    which requires the softhsm2 and opensc packages to be installed.
+
 Env:
     GIT_SEMVER      Use a custom version when building the packages. Only use for dev/testing purposes!
 
@@ -80,6 +86,7 @@ ARCH=
 TARGET=()
 BUILD_OPTIONS=()
 BUILD=1
+CRYPTOKI=0
 
 REST_ARGS=()
 while [ $# -gt 0 ]
@@ -164,6 +171,16 @@ fi
 
 # Custom options for different targets
 case "$ARCH" in
+    *-linux-gnu*)
+        BUILD_OPTIONS+=(
+            --release
+            # Support the device keys held by PKCS#11 tokens.
+            # The PKCS#11 modules are shared libraries loaded at runtime,
+            # hence this is only possible with dynamically linked binaries.
+            --features tedge/cryptoki
+        )
+        CRYPTOKI=1
+        ;;
     *)
         BUILD_OPTIONS+=(
             --release
@@ -179,6 +196,16 @@ source ./ci/package_list.sh
 # GIT_SEMVER should be referenced in the build.rs scripts
 if [ "$BUILD" = 1 ]; then
     cargo zigbuild "${TARGET[@]}" "${BUILD_OPTIONS[@]}"
+
+    # Check that the binary built with PKCS#11 support can load a PKCS#11 module.
+    # This can only be done when the binary can be run on the build host.
+    if [ "$CRYPTOKI" = 1 ]; then
+        if [[ "$ARCH" == "$(uname -m)"* ]]; then
+            ./ci/build_scripts/check_pkcs11.sh "target/$ARCH/release/tedge"
+        else
+            echo "Skipping the PKCS#11 check of target/$ARCH/release/tedge, as it cannot be run on this host" >&2
+        fi
+    fi
 fi
 
 # Create release packages
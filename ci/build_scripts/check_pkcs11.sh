@@ -0,0 +1,69 @@
+#!/usr/bin/env bash
+set -eo pipefail
+
+help() {
+  cat <<EOF
+Check that a tedge binary built with the cryptoki feature can use a key held by a PKCS#11 token.
+
+A SoftHSM token is created in a temporary directory, with a device key,
+and the tedge binary is used to create a certificate signing request signed by this key.
+This requires the PKCS#11 module of SoftHSM to be loaded at runtime by the binary.
+
+Usage:
+    $0 TEDGE_BINARY
+
+Args:
+    TEDGE_BINARY    Path to the tedge binary to check, e.g. target/x86_64-unknown-linux-gnu/release/tedge
+
+Env:
+    PKCS11_MODULE   Path to the SoftHSM module, by default the first found in the usual locations
+
+Requirements:
+    softhsm2-util (softhsm2 package), pkcs11-tool (opensc package) and openssl
+
+Examples:
+    $0 target/x86_64-unknown-linux-gnu/release/tedge
+EOF
+}
+
+if [ $# -ne 1 ] || [ "$1" = "--help" ] || [ "$1" = "-h" ]; then
+    help
+    exit 1
+fi
+TEDGE="$1"
+
+if [ -z "$PKCS11_MODULE" ]; then
+    for MODULE in /usr/lib/softhsm/libsofthsm2.so /usr/lib/*/softhsm/libsofthsm2.so /usr/local/lib/softhsm/libsofthsm2.so; do
+        if [ -f "$MODULE" ]; then
+            PKCS11_MODULE="$MODULE"
+            break
+        fi
+    done
+fi
+if [ ! -f "$PKCS11_MODULE" ]; then
+    echo "The SoftHSM module cannot be found: install the softhsm2 package or set PKCS11_MODULE" >&2
+    exit 1
+fi
+
+WORK_DIR=$(mktemp -d)
+trap 'rm -rf "$WORK_DIR"' EXIT
+
+# Create a token with a device key, in a SoftHSM store private to this check
+mkdir -p "$WORK_DIR/tokens" "$WORK_DIR/tedge"
+cat > "$WORK_DIR/softhsm2.conf" <<EOF
+directories.tokendir = $WORK_DIR/tokens
+objectstore.backend = file
+EOF
+export SOFTHSM2_CONF="$WORK_DIR/softhsm2.conf"
+softhsm2-util --init-token --free --label tedge --pin 123456 --so-pin 12345678
+pkcs11-tool --module "$PKCS11_MODULE" --token-label tedge --login --pin 123456 \
+    --keypairgen --key-type EC:prime256v1 --label device-key
+
+# Create a certificate signing request signed by the key of the token
+"$TEDGE" --config-dir "$WORK_DIR/tedge" config set cryptoki.module_path "$PKCS11_MODULE"
+"$TEDGE" --config-dir "$WORK_DIR/tedge" config set cryptoki.pin 123456
+"$TEDGE" --config-dir "$WORK_DIR/tedge" config set device.key_path "pkcs11:token=tedge;object=device-key"
+"$TEDGE" --config-dir "$WORK_DIR/tedge" cert create-csr --device-id pkcs11-check --output-path "$WORK_DIR/device.csr"
+
+openssl req -in "$WORK_DIR/device.csr" -noout -verify
+echo "$TEDGE uses the keys held by the PKCS#11 module $PKCS11_MODULE"
@@ -1,10 +1,14 @@
 use crate::load_cert;
 use crate::load_pkey;
 use crate::read_trust_store;
-use crate::ssl_config;
+use crate::ssl_config_with_signing_key;
 use anyhow::anyhow;
 use anyhow::Context;
 use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use certificate::KeyProvider;
+use rustls::sign::SigningKey;
+use rustls::PrivateKey;
 use rustls::RootCertStore;
 use std::fmt::Debug;
 use std::fs::File;
@@ -13,13 +17,16 @@ use std::io::stderr;
 use std::io::Cursor;
 use std::io::IsTerminal;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tedge_config::OptionalConfig;
 use tracing::info;
 use yansi::Paint;
 
 /// Loads the relevant [rustls::ServerConfig] from configured values for `cert_path`, `key_path` and `ca_path`
 ///
-/// In production use, all the paths should be passed in as [OptionalConfig]s from [TEdgeConfig]
+/// In production use, all the paths should be passed in as [OptionalConfig]s from [TEdgeConfig].
+/// The private key can also be given as a [KeyProvider], for the key to be held by a PKCS#11 token.
 ///
 /// ```no_run
 /// # fn main() -> anyhow::Result<()> {
@@ -62,7 +69,7 @@ use yansi::Paint;
 ///
 pub fn load_ssl_config(
     cert_path: OptionalConfig<impl PemReader>,
-    key_path: OptionalConfig<impl PemReader>,
+    key_path: OptionalConfig<impl PrivateKeyLoader>,
     ca_path: OptionalConfig<impl TrustStoreLoader>,
     service_name: &'static str,
 ) -> anyhow::Result<Option<rustls::ServerConfig>> {
@@ -93,18 +100,18 @@ pub fn load_ssl_config(
         };
 
         info!(target: "HTTP Server", "{service_name} has HTTPS {enabled} (configured in `{cert_key}`/`{key_key}`) and certificate authentication {ca_state} (configured in `{ca_key}`)", );
-        Ok(Some(ssl_config(cert, key, trust_store)?))
+        Ok(Some(ssl_config_with_signing_key(cert, key, trust_store)))
     } else {
         info!(target: "HTTP Server", "{service_name} has HTTPS {disabled} (configured in `{cert_key}`/`{key_key}`) and certificate authentication {disabled} (configured in `{ca_key}`)");
         Ok(None)
     }
 }
 
-type CertKeyPair = (Vec<Vec<u8>>, Vec<u8>);
+type CertKeyPair = (Vec<Vec<u8>>, Arc<dyn SigningKey>);
 
 fn load_certificate_and_key(
     cert_path: OptionalConfig<impl PemReader>,
-    key_path: OptionalConfig<impl PemReader>,
+    key_path: OptionalConfig<impl PrivateKeyLoader>,
 ) -> anyhow::Result<Option<CertKeyPair>> {
     let paths = tedge_config::all_or_nothing((cert_path.as_ref(), key_path.as_ref()))
         .map_err(|e| anyhow!("{e}"))?;
@@ -114,7 +121,7 @@ fn load_certificate_and_key(
             load_cert(cert_file).with_context(|| {
                 format!("reading certificate configured in `{}`", cert_path.key())
             })?,
-            load_pkey(key_file).with_context(|| {
+            key_file.load_signing_key().with_context(|| {
                 format!("reading private key configured in `{}`", key_path.key())
             })?,
         )))
@@ -131,6 +138,11 @@ pub trait PemReader: Debug {
     fn open(&self) -> io::Result<Self::Read<'_>>;
 }
 
+/// A private key, stored in a PEM file or held by a PKCS#11 token
+pub trait PrivateKeyLoader: Debug {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>>;
+}
+
 pub trait TrustStoreLoader {
     fn load_trust_store(&self) -> anyhow::Result<RootCertStore>;
 }
@@ -161,12 +173,63 @@ impl PemReader for InjectedValue<String> {
 }
 
 impl<P: AsRef<Path> + Debug + ?Sized> PemReader for P {
-    type Read<'a> = File where Self: 'a;
+    type Read<'a>
+        = File
+    where
+        Self: 'a;
     fn open(&self) -> io::Result<File> {
         File::open(self)
     }
 }
 
+fn signing_key_from_pem(path: &(impl PemReader + ?Sized)) -> anyhow::Result<Arc<dyn SigningKey>> {
+    let key_der = load_pkey(path)?;
+    rustls::sign::any_supported_type(&PrivateKey(key_der))
+        .map_err(|_| anyhow!("unsupported private key in {path:?}"))
+}
+
+impl PrivateKeyLoader for InjectedValue<String> {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        signing_key_from_pem(self)
+    }
+}
+
+impl PrivateKeyLoader for Utf8Path {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        signing_key_from_pem(self)
+    }
+}
+
+impl PrivateKeyLoader for Utf8PathBuf {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        signing_key_from_pem(self)
+    }
+}
+
+impl PrivateKeyLoader for Path {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        signing_key_from_pem(self)
+    }
+}
+
+impl PrivateKeyLoader for PathBuf {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        signing_key_from_pem(self)
+    }
+}
+
+impl PrivateKeyLoader for KeyProvider {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        Ok(self.signing_key()?)
+    }
+}
+
+impl<K: PrivateKeyLoader + ?Sized> PrivateKeyLoader for &K {
+    fn load_signing_key(&self) -> anyhow::Result<Arc<dyn SigningKey>> {
+        (**self).load_signing_key()
+    }
+}
+
 impl<P: AsRef<Utf8Path> + 'static> TrustStoreLoader for P {
     fn load_trust_store(&self) -> anyhow::Result<RootCertStore> {
         read_trust_store(self.as_ref())
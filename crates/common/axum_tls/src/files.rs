@@ -2,7 +2,9 @@ use crate::config::PemReader;
 use anyhow::anyhow;
 use anyhow::Context;
 use camino::Utf8Path;
+use certificate::key_provider;
 use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::sign::SigningKey;
 use rustls::Certificate;
 use rustls::PrivateKey;
 use rustls::RootCertStore;
@@ -51,6 +53,23 @@ pub fn ssl_config(
     key_der: Vec<u8>,
     root_certs: Option<RootCertStore>,
 ) -> anyhow::Result<ServerConfig> {
+    let server_key = rustls::sign::any_supported_type(&PrivateKey(key_der))
+        .map_err(|_| anyhow!("invalid private key"))
+        .context("invalid key or certificate")?;
+
+    Ok(ssl_config_with_signing_key(
+        certificate_chain,
+        server_key,
+        root_certs,
+    ))
+}
+
+/// Load the SSL configuration for rustls, using a key that is possibly not held in memory (e.g. by a PKCS#11 token)
+pub fn ssl_config_with_signing_key(
+    certificate_chain: Vec<Vec<u8>>,
+    server_key: Arc<dyn SigningKey>,
+    root_certs: Option<RootCertStore>,
+) -> ServerConfig {
     // Trusted CA for client certificates
     let config = ServerConfig::builder().with_safe_defaults();
 
@@ -61,11 +80,7 @@ pub fn ssl_config(
     };
 
     let server_cert = certificate_chain.into_iter().map(Certificate).collect();
-    let server_key = PrivateKey(key_der);
-
-    config
-        .with_single_cert(server_cert, server_key)
-        .context("invalid key or certificate")
+    config.with_cert_resolver(key_provider::server_cert_resolver(server_cert, server_key))
 }
 
 /// Load the server certificate
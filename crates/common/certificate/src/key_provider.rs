@@ -0,0 +1,246 @@
+//! The private keys used to authenticate the device
+//!
+//! A private key is either stored in a PEM file or held by a PKCS#11 token.
+//! In the latter case, the key path set in the configuration is a PKCS#11 URI, e.g. `pkcs11:token=tedge;object=device-key`,
+//! and the token is accessed using the PKCS#11 module given by the [CryptokiConfig].
+use crate::parse_root_certificate::read_pvt_key;
+use crate::pkcs11::Pkcs11Key;
+use crate::pkcs11::Pkcs11Uri;
+use crate::CertificateError;
+use rcgen::KeyPair;
+use rustls::client::ResolvesClientCert;
+use rustls::server::ClientHello;
+use rustls::server::ResolvesServerCert;
+use rustls::sign::CertifiedKey;
+use rustls::sign::SigningKey;
+use rustls::Certificate;
+use rustls::SignatureScheme;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How to access the PKCS#11 token holding the private keys
+#[derive(Clone, PartialEq, Eq)]
+pub struct CryptokiConfig {
+    /// The PKCS#11 module, i.e. the shared library provided along the token
+    pub module_path: PathBuf,
+
+    /// The user PIN of the token
+    pub pin: Option<String>,
+}
+
+impl fmt::Debug for CryptokiConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The PIN is not displayed
+        f.debug_struct("CryptokiConfig")
+            .field("module_path", &self.module_path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A private key, stored in a PEM file or held by a PKCS#11 token
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyProvider {
+    /// A PEM-encoded key stored in a file
+    File(PathBuf),
+
+    /// A key held by a PKCS#11 token
+    Pkcs11 {
+        uri: Pkcs11Uri,
+        cryptoki: CryptokiConfig,
+    },
+}
+
+impl KeyProvider {
+    /// The key configured with the given path, which is either the path of a PEM file or a PKCS#11 URI
+    ///
+    /// The PKCS#11 module and PIN given by the URI, if any, take precedence over the cryptoki config.
+    pub fn new(
+        key_path: impl AsRef<Path>,
+        cryptoki: Option<&CryptokiConfig>,
+    ) -> Result<KeyProvider, CertificateError> {
+        let key_path = key_path.as_ref();
+        let uri = match key_path.to_str() {
+            Some(uri) if Pkcs11Uri::is_pkcs11_uri(uri) => uri.parse::<Pkcs11Uri>()?,
+            _ => return Ok(KeyProvider::File(key_path.to_path_buf())),
+        };
+
+        let module_path = uri
+            .module_path
+            .clone()
+            .or_else(|| cryptoki.map(|cryptoki| cryptoki.module_path.clone()))
+            .ok_or(CertificateError::CryptokiNotConfigured)?;
+        let pin = uri
+            .pin_value
+            .clone()
+            .or_else(|| cryptoki.and_then(|cryptoki| cryptoki.pin.clone()));
+        Ok(KeyProvider::Pkcs11 {
+            uri,
+            cryptoki: CryptokiConfig { module_path, pin },
+        })
+    }
+
+    /// The key to be used to sign certificates and certificate signing requests
+    pub fn key_pair(&self) -> Result<KeyPair, CertificateError> {
+        match self {
+            KeyProvider::File(path) => {
+                let keypair_pem = zeroize::Zeroizing::new(std::fs::read_to_string(path)?);
+                Ok(KeyPair::from_pem(&keypair_pem)?)
+            }
+            KeyProvider::Pkcs11 { uri, cryptoki } => {
+                let key = Pkcs11Key::open(uri, cryptoki)?;
+                Ok(KeyPair::from_remote(Box::new(key))?)
+            }
+        }
+    }
+
+    /// The key to be used to authenticate TLS connections
+    pub fn signing_key(&self) -> Result<Arc<dyn SigningKey>, CertificateError> {
+        match self {
+            KeyProvider::File(path) => {
+                let key = read_pvt_key(path)?;
+                rustls::sign::any_supported_type(&key)
+                    .map_err(|_| CertificateError::UnknownPrivateKeyFormat)
+            }
+            KeyProvider::Pkcs11 { uri, cryptoki } => Ok(Arc::new(Pkcs11Key::open(uri, cryptoki)?)),
+        }
+    }
+
+    /// The client certificate resolver to be used to authenticate a TLS client with this key and certificate chain
+    ///
+    /// Use it with `ClientConfig::builder()...with_client_cert_resolver(resolver)`.
+    pub fn client_cert_resolver(
+        &self,
+        cert_chain: Vec<Certificate>,
+    ) -> Result<Arc<dyn ResolvesClientCert>, CertificateError> {
+        Ok(client_cert_resolver(cert_chain, self.signing_key()?))
+    }
+
+    /// The server certificate resolver to be used to authenticate a TLS server with this key and certificate chain
+    ///
+    /// Use it with `ServerConfig::builder()...with_cert_resolver(resolver)`.
+    pub fn server_cert_resolver(
+        &self,
+        cert_chain: Vec<Certificate>,
+    ) -> Result<Arc<dyn ResolvesServerCert>, CertificateError> {
+        Ok(server_cert_resolver(cert_chain, self.signing_key()?))
+    }
+}
+
+/// A client certificate resolver always presenting the given certificate chain and key
+pub fn client_cert_resolver(
+    cert_chain: Vec<Certificate>,
+    key: Arc<dyn SigningKey>,
+) -> Arc<dyn ResolvesClientCert> {
+    Arc::new(SingleCertifiedKey(Arc::new(CertifiedKey::new(
+        cert_chain, key,
+    ))))
+}
+
+/// A server certificate resolver always presenting the given certificate chain and key
+pub fn server_cert_resolver(
+    cert_chain: Vec<Certificate>,
+    key: Arc<dyn SigningKey>,
+) -> Arc<dyn ResolvesServerCert> {
+    Arc::new(SingleCertifiedKey(Arc::new(CertifiedKey::new(
+        cert_chain, key,
+    ))))
+}
+
+/// Always present the same certificate chain and key, as rustls does for a key given as DER
+struct SingleCertifiedKey(Arc<CertifiedKey>);
+
+impl ResolvesClientCert for SingleCertifiedKey {
+    fn resolve(
+        &self,
+        _acceptable_issuers: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+impl ResolvesServerCert for SingleCertifiedKey {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn a_key_path_is_a_pem_file() {
+        let key = KeyProvider::new("/etc/tedge/device-certs/tedge-private-key.pem", None).unwrap();
+
+        assert_eq!(
+            key,
+            KeyProvider::File("/etc/tedge/device-certs/tedge-private-key.pem".into())
+        );
+    }
+
+    #[test]
+    fn a_pkcs11_uri_is_a_key_held_by_a_token() {
+        let cryptoki = CryptokiConfig {
+            module_path: "/usr/lib/softhsm/libsofthsm2.so".into(),
+            pin: Some("1234".to_string()),
+        };
+        let key = KeyProvider::new("pkcs11:token=tedge;object=device", Some(&cryptoki)).unwrap();
+
+        assert_matches!(key, KeyProvider::Pkcs11 { uri, cryptoki: config } => {
+            assert_eq!(uri.object.as_deref(), Some("device"));
+            assert_eq!(config, cryptoki);
+        });
+    }
+
+    #[test]
+    fn the_module_and_pin_of_the_uri_take_precedence() {
+        let cryptoki = CryptokiConfig {
+            module_path: "/usr/lib/softhsm/libsofthsm2.so".into(),
+            pin: Some("1234".to_string()),
+        };
+        let key = KeyProvider::new(
+            "pkcs11:object=device?module-path=/usr/lib/libtpm2_pkcs11.so&pin-value=5678",
+            Some(&cryptoki),
+        )
+        .unwrap();
+
+        assert_matches!(key, KeyProvider::Pkcs11 { cryptoki, .. } => {
+            assert_eq!(cryptoki.module_path, PathBuf::from("/usr/lib/libtpm2_pkcs11.so"));
+            assert_eq!(cryptoki.pin.as_deref(), Some("5678"));
+        });
+    }
+
+    #[test]
+    fn a_pkcs11_uri_requires_a_module() {
+        assert_matches!(
+            KeyProvider::new("pkcs11:object=device", None),
+            Err(CertificateError::CryptokiNotConfigured)
+        );
+    }
+
+    #[test]
+    fn the_key_pair_of_a_pem_file_is_the_one_used_to_sign() {
+        let key_pair = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let key_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(key_file.path(), key_pair.serialize_pem()).unwrap();
+
+        let key = KeyProvider::new(key_file.path(), None).unwrap();
+
+        assert_eq!(
+            key.key_pair().unwrap().public_key_raw(),
+            key_pair.public_key_raw()
+        );
+        assert_eq!(
+            key.signing_key().unwrap().algorithm(),
+            rustls::SignatureAlgorithm::ECDSA
+        );
+    }
+}
@@ -5,13 +5,21 @@ use rcgen::KeyPair;
 use rcgen::RcgenError;
 use sha1::Digest;
 use sha1::Sha1;
+use std::fmt;
+use std::net::IpAddr;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 use time::Duration;
 use time::OffsetDateTime;
 use zeroize::Zeroizing;
 pub mod device_id;
+pub mod key_provider;
 pub mod parse_root_certificate;
+pub mod pkcs11;
+pub mod pkcs7;
+pub use key_provider::CryptokiConfig;
+pub use key_provider::KeyProvider;
 pub struct PemCertificate {
     pem: x509_parser::pem::Pem,
 }
@@ -69,6 +77,12 @@ impl PemCertificate {
             .map_err(CertificateError::X509Error)
     }
 
+    /// The time after which the certificate is no more valid
+    pub fn not_after_datetime(&self) -> Result<OffsetDateTime, CertificateError> {
+        let x509 = PemCertificate::extract_certificate(&self.pem)?;
+        Ok(x509.tbs_certificate.validity.not_after.to_datetime())
+    }
+
     pub fn thumbprint(&self) -> Result<String, CertificateError> {
         let bytes = Sha1::digest(&self.pem.contents).as_slice().to_vec();
         let strs: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
@@ -100,6 +114,19 @@ pub enum KeyKind {
     New,
     /// Reuse the existing PEM-encoded key pair
     Reuse { keypair_pem: String },
+    /// Use the key of a key provider, e.g. a key held by a PKCS#11 token
+    Provided(KeyProvider),
+}
+
+impl KeyKind {
+    /// The existing key to be used, if any
+    fn key_pair(&self) -> Result<Option<KeyPair>, CertificateError> {
+        match self {
+            KeyKind::New => Ok(None),
+            KeyKind::Reuse { keypair_pem } => Ok(Some(KeyPair::from_pem(keypair_pem)?)),
+            KeyKind::Provided(key) => Ok(Some(key.key_pair()?)),
+        }
+    }
 }
 
 pub struct KeyCertPair {
@@ -124,11 +151,9 @@ impl KeyCertPair {
         cert_kind: &KeyKind,
     ) -> Result<KeyCertPair, CertificateError> {
         KeyCertPair::check_identifier(id, config.max_cn_size)?;
-        let mut distinguished_name = rcgen::DistinguishedName::new();
-        distinguished_name.push(rcgen::DnType::CommonName, id);
-        distinguished_name.push(rcgen::DnType::OrganizationName, &config.organization_name);
-        distinguished_name.push(
-            rcgen::DnType::OrganizationalUnitName,
+        let distinguished_name = KeyCertPair::distinguished_name(
+            id,
+            &config.organization_name,
             &config.organizational_unit_name,
         );
 
@@ -140,8 +165,12 @@ impl KeyCertPair {
         params.not_after = not_after;
         params.alg = &rcgen::PKCS_ECDSA_P256_SHA256; // ECDSA signing using the P-256 curves and SHA-256 hashing as per RFC 5758
         params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained); // IsCa::SelfSignedOnly is rejected by C8Y
-        if let KeyKind::Reuse { keypair_pem } = cert_kind {
-            params.key_pair = Some(KeyPair::from_pem(keypair_pem)?);
+        if let Some(key_pair) = cert_kind.key_pair()? {
+            params.alg = key_pair
+                .compatible_algs()
+                .next()
+                .ok_or(CertificateError::UnknownPrivateKeyFormat)?;
+            params.key_pair = Some(key_pair);
         }
 
         Ok(KeyCertPair {
@@ -149,10 +178,91 @@ impl KeyCertPair {
         })
     }
 
+    /// Prepare a certificate signing request for the given device id
+    ///
+    /// When a new key is created, its type is given by the config.
+    /// Otherwise, the signature algorithm is the one of the reused key.
+    pub fn new_certificate_signing_request(
+        config: &CsrConfig,
+        id: &str,
+        key_kind: &KeyKind,
+    ) -> Result<KeyCertPair, CertificateError> {
+        KeyCertPair::check_identifier(id, config.max_cn_size)?;
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name = KeyCertPair::distinguished_name(
+            id,
+            &config.organization_name,
+            &config.organizational_unit_name,
+        );
+        params.subject_alt_names = config
+            .subject_alt_names
+            .iter()
+            .map(|name| subject_alt_name(name))
+            .collect::<Result<_, _>>()?;
+        match key_kind.key_pair()? {
+            None => params.alg = config.key_type.signature_algorithm(),
+            Some(key_pair) => {
+                params.alg = key_pair
+                    .compatible_algs()
+                    .next()
+                    .ok_or(CertificateError::UnknownPrivateKeyFormat)?;
+                params.key_pair = Some(key_pair);
+            }
+        }
+
+        Ok(KeyCertPair {
+            certificate: Zeroizing::new(Certificate::from_params(params)?),
+        })
+    }
+
+    /// Prepare a certificate signing request to renew a certificate
+    ///
+    /// The request is for the same subject as the current certificate and reuses the current key.
+    pub fn new_renewal_request(
+        current: &PemCertificate,
+        key_pair: KeyPair,
+    ) -> Result<KeyCertPair, CertificateError> {
+        let x509 = PemCertificate::extract_certificate(&current.pem)?;
+        let mut distinguished_name = rcgen::DistinguishedName::new();
+        for attribute in x509.subject().iter_attributes() {
+            let Some(oid) = attribute.attr_type().iter() else {
+                continue;
+            };
+            let oid: Vec<u64> = oid.collect();
+            let value = attribute
+                .as_str()
+                .map_err(PemCertificate::wrap_x509_error)?;
+            distinguished_name.push(rcgen::DnType::from_oid(&oid), value);
+        }
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name = distinguished_name;
+        params.alg = key_pair
+            .compatible_algs()
+            .next()
+            .ok_or(CertificateError::UnknownPrivateKeyFormat)?;
+        params.key_pair = Some(key_pair);
+
+        Ok(KeyCertPair {
+            certificate: Zeroizing::new(Certificate::from_params(params)?),
+        })
+    }
+
     pub fn certificate_pem_string(&self) -> Result<String, CertificateError> {
         Ok(self.certificate.serialize_pem()?)
     }
 
+    /// The PKCS#10 certificate signing request, to be signed by a certificate authority
+    pub fn certificate_signing_request_pem_string(&self) -> Result<String, CertificateError> {
+        Ok(self.certificate.serialize_request_pem()?)
+    }
+
+    /// The DER encoding of the certificate signing request
+    pub fn certificate_signing_request_der(&self) -> Result<Vec<u8>, CertificateError> {
+        Ok(self.certificate.serialize_request_der()?)
+    }
+
     pub fn private_key_pem_string(&self) -> Result<Zeroizing<String>, CertificateError> {
         Ok(Zeroizing::new(self.certificate.serialize_private_key_pem()))
     }
@@ -160,6 +270,99 @@ impl KeyCertPair {
     fn check_identifier(id: &str, max_cn_size: usize) -> Result<(), CertificateError> {
         Ok(device_id::is_valid_device_id(id, max_cn_size)?)
     }
+
+    fn distinguished_name(
+        id: &str,
+        organization_name: &str,
+        organizational_unit_name: &str,
+    ) -> rcgen::DistinguishedName {
+        let mut distinguished_name = rcgen::DistinguishedName::new();
+        distinguished_name.push(rcgen::DnType::CommonName, id);
+        distinguished_name.push(rcgen::DnType::OrganizationName, organization_name);
+        distinguished_name.push(
+            rcgen::DnType::OrganizationalUnitName,
+            organizational_unit_name,
+        );
+        distinguished_name
+    }
+}
+
+/// Parse a subject alternative name, e.g. `DNS:device.local`, `IP:192.168.1.10` or `email:admin@acme.com`
+///
+/// A name with no prefix is an IP address, if it can be parsed as such, or a DNS name.
+fn subject_alt_name(name: &str) -> Result<rcgen::SanType, CertificateError> {
+    let invalid = || CertificateError::InvalidSubjectAltName(name.to_string());
+    let san = match name.split_once(':') {
+        Some(("DNS", dns_name)) => rcgen::SanType::DnsName(dns_name.to_string()),
+        Some(("IP", address)) => rcgen::SanType::IpAddress(address.parse().map_err(|_| invalid())?),
+        Some(("email", email)) => rcgen::SanType::Rfc822Name(email.to_string()),
+        Some(("URI", uri)) => rcgen::SanType::URI(uri.to_string()),
+        _ => match name.parse::<IpAddr>() {
+            Ok(address) => rcgen::SanType::IpAddress(address),
+            Err(_) if !name.is_empty() && !name.contains(':') => {
+                rcgen::SanType::DnsName(name.to_string())
+            }
+            Err(_) => return Err(invalid()),
+        },
+    };
+    Ok(san)
+}
+
+/// Check that a PEM-encoded certificate chain can be used along the given key pair
+///
+/// The first certificate of the chain is the device certificate, which must match the private key
+/// and be currently valid. Each of the following certificates must be the issuer of the previous one,
+/// i.e. its subject is the issuer of the previous certificate, which is signed with its public key.
+///
+/// Return the device certificate.
+pub fn validate_certificate_chain(
+    chain_pem: &str,
+    key_pair: &KeyPair,
+) -> Result<PemCertificate, CertificateError> {
+    let pems = x509_parser::pem::Pem::iter_from_buffer(chain_pem.as_bytes())
+        .collect::<Result<Vec<_>, _>>()?;
+    let certificates = pems
+        .iter()
+        .map(PemCertificate::extract_certificate)
+        .collect::<Result<Vec<_>, _>>()?;
+    let device_certificate = certificates
+        .first()
+        .ok_or(CertificateError::NoCertificate)?;
+
+    if device_certificate
+        .public_key()
+        .subject_public_key
+        .data
+        .as_ref()
+        != key_pair.public_key_raw()
+    {
+        return Err(CertificateError::CertificateKeyMismatch);
+    }
+
+    let validity = device_certificate.validity();
+    if !validity.is_valid() {
+        return Err(CertificateError::CertificateNotValidNow {
+            not_before: validity.not_before.to_string(),
+            not_after: validity.not_after.to_string(),
+        });
+    }
+
+    for (index, pair) in certificates.windows(2).enumerate() {
+        if pair[0].issuer().as_raw() != pair[1].subject().as_raw()
+            || pair[0]
+                .verify_signature(Some(pair[1].public_key()))
+                .is_err()
+        {
+            return Err(CertificateError::BrokenCertificateChain { index });
+        }
+    }
+
+    Ok(PemCertificate {
+        pem: pems
+            .into_iter()
+            .next()
+            .ok_or(CertificateError::NoCertificate)?,
+    })
 }
 
 pub fn translate_rustls_error(err: &(dyn std::error::Error + 'static)) -> Option<CertificateError> {
@@ -219,6 +422,41 @@ pub enum CertificateError {
 
     #[error(transparent)]
     CertParse(#[from] rustls::Error),
+
+    #[error("Invalid subject alternative name: {0}")]
+    InvalidSubjectAltName(String),
+
+    #[error("No certificate found")]
+    NoCertificate,
+
+    #[error("The certificate doesn't match the device private key")]
+    CertificateKeyMismatch,
+
+    #[error(
+        "The certificate is not currently valid, being valid from {not_before} to {not_after}"
+    )]
+    CertificateNotValidNow {
+        not_before: String,
+        not_after: String,
+    },
+
+    #[error("Invalid certificate chain: the certificate #{index} is not issued by the next one")]
+    BrokenCertificateChain { index: usize },
+
+    #[error("Invalid PKCS#7 certificate bundle: {0}")]
+    InvalidPkcs7(String),
+
+    #[error("Invalid PKCS#11 URI: {0}")]
+    InvalidPkcs11Uri(String),
+
+    #[error("A PKCS#11 module must be configured to use a key held by a PKCS#11 token")]
+    CryptokiNotConfigured,
+
+    #[error("Keys held by PKCS#11 tokens are not supported by this build")]
+    CryptokiNotSupported,
+
+    #[error("PKCS#11 error: {0}")]
+    Pkcs11Error(String),
 }
 
 pub struct NewCertificateConfig {
@@ -239,11 +477,83 @@ impl Default for NewCertificateConfig {
     }
 }
 
+/// The settings of a certificate signing request
+pub struct CsrConfig {
+    pub max_cn_size: usize,
+    pub organization_name: String,
+    pub organizational_unit_name: String,
+    /// The subject alternative names, e.g. `DNS:device.local` or `IP:192.168.1.10`
+    pub subject_alt_names: Vec<String>,
+    /// The type of the key, if a new one is created
+    pub key_type: KeyType,
+}
+
+impl Default for CsrConfig {
+    fn default() -> Self {
+        let certificate_config = NewCertificateConfig::default();
+        CsrConfig {
+            max_cn_size: certificate_config.max_cn_size,
+            organization_name: certificate_config.organization_name,
+            organizational_unit_name: certificate_config.organizational_unit_name,
+            subject_alt_names: vec![],
+            key_type: KeyType::default(),
+        }
+    }
+}
+
+/// The type of a new private key
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum KeyType {
+    /// ECDSA using the P-256 curve and SHA-256 hashing
+    #[default]
+    EcdsaP256,
+    /// ECDSA using the P-384 curve and SHA-384 hashing
+    EcdsaP384,
+    /// EdDSA using the Ed25519 curve
+    Ed25519,
+}
+
+impl KeyType {
+    fn signature_algorithm(&self) -> &'static rcgen::SignatureAlgorithm {
+        match self {
+            KeyType::EcdsaP256 => &rcgen::PKCS_ECDSA_P256_SHA256,
+            KeyType::EcdsaP384 => &rcgen::PKCS_ECDSA_P384_SHA384,
+            KeyType::Ed25519 => &rcgen::PKCS_ED25519,
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ecdsa-p256" => Ok(KeyType::EcdsaP256),
+            "ecdsa-p384" => Ok(KeyType::EcdsaP384),
+            "ed25519" => Ok(KeyType::Ed25519),
+            _ => Err(format!(
+                "unsupported key type {s}, expected one of ecdsa-p256, ecdsa-p384, ed25519"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::EcdsaP256 => write!(f, "ecdsa-p256"),
+            KeyType::EcdsaP384 => write!(f, "ecdsa-p384"),
+            KeyType::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::error::Error;
     use time::macros::datetime;
+    use x509_parser::prelude::FromDer;
 
     impl KeyCertPair {
         fn new_selfsigned_certificate_with_new_key(
@@ -404,6 +714,205 @@ mod tests {
         assert_eq!(thumbprint, expected_thumbprint);
     }
 
+    fn csr_of(request: &KeyCertPair) -> x509_parser::pem::Pem {
+        let pem_string = request
+            .certificate_signing_request_pem_string()
+            .expect("Fail to read the CSR PEM");
+        assert!(pem_string.starts_with("-----BEGIN CERTIFICATE REQUEST-----"));
+        let (pem, _) = x509_parser::pem::Pem::read(std::io::Cursor::new(pem_string.as_bytes()))
+            .expect("Fail to decode the CSR PEM");
+        pem
+    }
+
+    /// Sign a certificate for the key of the device, with a test CA
+    fn ca_signed_certificate_chain(device_key_pem: &str, not_after: OffsetDateTime) -> String {
+        let mut ca_params = CertificateParams::new(vec![]);
+        ca_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test CA");
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = Certificate::from_params(ca_params).unwrap();
+
+        let mut params = CertificateParams::new(vec![]);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "my-device");
+        params.not_after = not_after;
+        params.key_pair = Some(KeyPair::from_pem(device_key_pem).unwrap());
+        let device_certificate = Certificate::from_params(params).unwrap();
+
+        format!(
+            "{}{}",
+            device_certificate.serialize_pem_with_signer(&ca).unwrap(),
+            ca.serialize_pem().unwrap()
+        )
+    }
+
+    #[test]
+    fn csr_subject_is_the_device() {
+        let config = CsrConfig {
+            subject_alt_names: vec!["DNS:my-device.local".into(), "192.168.1.10".into()],
+            ..Default::default()
+        };
+        let request =
+            KeyCertPair::new_certificate_signing_request(&config, "my-device", &KeyKind::New)
+                .expect("Fail to create a CSR");
+
+        let pem = csr_of(&request);
+        let (_, csr) =
+            x509_parser::certification_request::X509CertificationRequest::from_der(&pem.contents)
+                .expect("Fail to parse the CSR");
+        assert_eq!(
+            csr.certification_request_info.subject.to_string(),
+            "CN=my-device, O=Thin Edge, OU=Test Device"
+        );
+        let extensions: Vec<_> = csr.requested_extensions().unwrap().collect();
+        assert_eq!(extensions.len(), 1);
+    }
+
+    #[test]
+    fn csr_reuses_the_existing_key() {
+        let config = CsrConfig {
+            key_type: KeyType::EcdsaP384,
+            ..Default::default()
+        };
+        let key = KeyCertPair::new_certificate_signing_request(&config, "my-device", &KeyKind::New)
+            .unwrap()
+            .private_key_pem_string()
+            .unwrap();
+
+        let request = KeyCertPair::new_certificate_signing_request(
+            &CsrConfig::default(),
+            "my-device",
+            &KeyKind::Reuse {
+                keypair_pem: key.to_string(),
+            },
+        )
+        .expect("Fail to create a CSR");
+        assert_eq!(*request.private_key_pem_string().unwrap(), *key);
+    }
+
+    #[test]
+    fn renewal_request_is_for_the_current_subject_and_key() {
+        let current = KeyCertPair::new_selfsigned_certificate_with_new_key(
+            &NewCertificateConfig::default(),
+            "my-device",
+        )
+        .unwrap();
+        let key = current.private_key_pem_string().unwrap();
+        let certificate = pem_of_keypair(&current);
+
+        let request =
+            KeyCertPair::new_renewal_request(&certificate, KeyPair::from_pem(&key).unwrap())
+                .unwrap();
+        assert_eq!(*request.private_key_pem_string().unwrap(), *key);
+
+        let der = request.certificate_signing_request_der().unwrap();
+        let (_, csr) = x509_parser::certification_request::X509CertificationRequest::from_der(&der)
+            .expect("Fail to parse the CSR");
+        assert_eq!(
+            csr.certification_request_info.subject.to_string(),
+            certificate.subject().unwrap()
+        );
+        assert!(certificate.not_after_datetime().unwrap() > OffsetDateTime::now_utc());
+    }
+
+    #[test]
+    fn parse_subject_alt_names() {
+        assert_eq!(
+            subject_alt_name("DNS:device.local").unwrap(),
+            rcgen::SanType::DnsName("device.local".into())
+        );
+        assert_eq!(
+            subject_alt_name("device.local").unwrap(),
+            rcgen::SanType::DnsName("device.local".into())
+        );
+        assert_eq!(
+            subject_alt_name("::1").unwrap(),
+            rcgen::SanType::IpAddress("::1".parse().unwrap())
+        );
+        assert_eq!(
+            subject_alt_name("email:admin@acme.com").unwrap(),
+            rcgen::SanType::Rfc822Name("admin@acme.com".into())
+        );
+        assert!(subject_alt_name("IP:device.local").is_err());
+        assert!(subject_alt_name("").is_err());
+    }
+
+    #[test]
+    fn validate_a_ca_signed_certificate_chain() {
+        let device_key = KeyCertPair::new_selfsigned_certificate_with_new_key(
+            &NewCertificateConfig::default(),
+            "my-device",
+        )
+        .unwrap()
+        .private_key_pem_string()
+        .unwrap();
+        let not_after = OffsetDateTime::now_utc() + Duration::days(30);
+        let chain = ca_signed_certificate_chain(&device_key, not_after);
+
+        let key_pair = KeyPair::from_pem(&device_key).unwrap();
+        let certificate = validate_certificate_chain(&chain, &key_pair).unwrap();
+        assert_eq!(certificate.subject_common_name().unwrap(), "my-device");
+        assert_eq!(certificate.issuer().unwrap(), "CN=Test CA");
+    }
+
+    #[test]
+    fn reject_invalid_certificate_chains() {
+        let config = NewCertificateConfig::default();
+        let device_key = KeyCertPair::new_selfsigned_certificate_with_new_key(&config, "my-device")
+            .unwrap()
+            .private_key_pem_string()
+            .unwrap();
+        let other_key = KeyCertPair::new_selfsigned_certificate_with_new_key(&config, "my-device")
+            .unwrap()
+            .private_key_pem_string()
+            .unwrap();
+        let key_pair = KeyPair::from_pem(&device_key).unwrap();
+        let tomorrow = OffsetDateTime::now_utc() + Duration::days(1);
+        let yesterday = OffsetDateTime::now_utc() - Duration::days(1);
+
+        let chain = ca_signed_certificate_chain(&other_key, tomorrow);
+        assert!(matches!(
+            validate_certificate_chain(&chain, &key_pair),
+            Err(CertificateError::CertificateKeyMismatch)
+        ));
+
+        let chain = ca_signed_certificate_chain(&device_key, yesterday);
+        assert!(matches!(
+            validate_certificate_chain(&chain, &key_pair),
+            Err(CertificateError::CertificateNotValidNow { .. })
+        ));
+
+        let chain = ca_signed_certificate_chain(&device_key, tomorrow);
+        let unrelated_ca = ca_signed_certificate_chain(&other_key, tomorrow);
+        let broken_chain = format!("{chain}{unrelated_ca}");
+        assert!(matches!(
+            validate_certificate_chain(&broken_chain, &key_pair),
+            Err(CertificateError::BrokenCertificateChain { index: 1 })
+        ));
+
+        // A CA with the expected name but not the key used to sign the device certificate
+        let device_certificate = chain
+            .split_inclusive("-----END CERTIFICATE-----")
+            .next()
+            .unwrap();
+        let forged_ca = unrelated_ca
+            .split_inclusive("-----END CERTIFICATE-----")
+            .nth(1)
+            .unwrap();
+        let forged_chain = format!("{device_certificate}{forged_ca}");
+        assert!(matches!(
+            validate_certificate_chain(&forged_chain, &key_pair),
+            Err(CertificateError::BrokenCertificateChain { index: 0 })
+        ));
+
+        assert!(matches!(
+            validate_certificate_chain("", &key_pair),
+            Err(CertificateError::NoCertificate)
+        ));
+    }
+
     #[test]
     fn check_translate_rustls_error() -> Result<(), anyhow::Error> {
         let expired_error = rustls::Error::InvalidCertificate(rustls::CertificateError::Expired);
@@ -13,20 +13,49 @@ use std::path::Path;
 use std::path::PathBuf;
 
 use crate::CertificateError;
+use crate::KeyProvider;
 
 pub fn create_tls_config(
     root_certificates: PathBuf,
-    client_private_key: PathBuf,
+    client_private_key: &KeyProvider,
     client_certificate: PathBuf,
 ) -> Result<ClientConfig, CertificateError> {
     let root_cert_store = new_root_store(&root_certificates)?;
-    let pvt_key = read_pvt_key(client_private_key)?;
     let cert_chain = read_cert_chain(client_certificate)?;
+    let cert_resolver = client_private_key.client_cert_resolver(cert_chain)?;
 
     Ok(ClientConfig::builder()
         .with_safe_defaults()
         .with_root_certificates(root_cert_store)
-        .with_client_auth_cert(cert_chain, pvt_key)?)
+        .with_client_cert_resolver(cert_resolver))
+}
+
+/// A TLS config trusting the system root certificates, and authenticating with the given certificate and key
+///
+/// The private key being possibly held by a PKCS#11 token, such a TLS config cannot be built by reqwest.
+pub fn create_tls_config_with_native_roots(
+    client_private_key: &KeyProvider,
+    client_certificate: impl AsRef<Path>,
+) -> Result<ClientConfig, CertificateError> {
+    let root_cert_store = native_root_store()?;
+    let cert_chain = read_cert_chain(client_certificate)?;
+    let cert_resolver = client_private_key.client_cert_resolver(cert_chain)?;
+
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_cert_store)
+        .with_client_cert_resolver(cert_resolver))
+}
+
+/// The root certificates trusted by the system
+pub fn native_root_store() -> Result<RootCertStore, CertificateError> {
+    let mut root_store = RootCertStore::empty();
+    for certificate in rustls_native_certs::load_native_certs()? {
+        root_store
+            .add(&Certificate(certificate.0))
+            .map_err(|_| CertificateError::RootStoreAdd)?;
+    }
+    Ok(root_store)
 }
 
 pub fn add_certs_from_file(
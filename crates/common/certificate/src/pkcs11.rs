@@ -0,0 +1,812 @@
+//! Private keys held by a PKCS#11 cryptographic token, e.g. an HSM, a TPM or a smart card
+//!
+//! A key is designated by a PKCS#11 URI (RFC 7512), e.g. `pkcs11:token=tedge;object=device-key`,
+//! and is only used through the signing operations of the token: the key never leaves the token.
+//!
+//! Accessing a token requires the `cryptoki` feature, which loads the PKCS#11 module of the token.
+use crate::pkcs7::encode_tlv;
+use crate::CertificateError;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The scheme of the URIs designating keys held by a PKCS#11 token
+pub const PKCS11_URI_SCHEME: &str = "pkcs11:";
+
+/// A PKCS#11 URI designating a private key
+///
+/// Only the attributes used to select a private key are supported:
+/// - the path attributes `token`, `serial`, `object`, `id` and `type` (which must be `private`)
+/// - the query attributes `pin-value` and `module-path`
+#[derive(Clone, PartialEq, Eq)]
+pub struct Pkcs11Uri {
+    uri: String,
+    pub token: Option<String>,
+    pub serial: Option<String>,
+    pub object: Option<String>,
+    pub id: Option<Vec<u8>>,
+    pub pin_value: Option<String>,
+    pub module_path: Option<PathBuf>,
+}
+
+impl Pkcs11Uri {
+    /// Check if the given key path is actually a PKCS#11 URI
+    pub fn is_pkcs11_uri(key_path: &str) -> bool {
+        key_path.starts_with(PKCS11_URI_SCHEME)
+    }
+}
+
+impl FromStr for Pkcs11Uri {
+    type Err = CertificateError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        // The URI is not part of the error, as it might contain a PIN
+        let invalid = |reason: String| CertificateError::InvalidPkcs11Uri(reason);
+        let attributes = uri
+            .strip_prefix(PKCS11_URI_SCHEME)
+            .ok_or_else(|| invalid(format!("expected the `{PKCS11_URI_SCHEME}` scheme")))?;
+        let (path, query) = attributes.split_once('?').unwrap_or((attributes, ""));
+
+        let mut parsed = Pkcs11Uri {
+            uri: uri.to_string(),
+            token: None,
+            serial: None,
+            object: None,
+            id: None,
+            pin_value: None,
+            module_path: None,
+        };
+        for attribute in path.split(';').filter(|attribute| !attribute.is_empty()) {
+            let (name, value) = split_attribute(attribute)?;
+            match name {
+                "token" => parsed.token = Some(utf8_value(name, value)?),
+                "serial" => parsed.serial = Some(utf8_value(name, value)?),
+                "object" => parsed.object = Some(utf8_value(name, value)?),
+                "id" => parsed.id = Some(value),
+                "type" if value == b"private" => {}
+                "type" => return Err(invalid("only private keys can be used".to_string())),
+                _ => return Err(invalid(format!("unsupported attribute `{name}`"))),
+            }
+        }
+        for attribute in query.split('&').filter(|attribute| !attribute.is_empty()) {
+            let (name, value) = split_attribute(attribute)?;
+            match name {
+                "pin-value" => parsed.pin_value = Some(utf8_value(name, value)?),
+                "module-path" => parsed.module_path = Some(utf8_value(name, value)?.into()),
+                _ => return Err(invalid(format!("unsupported query attribute `{name}`"))),
+            }
+        }
+
+        if parsed.object.is_none() && parsed.id.is_none() {
+            return Err(invalid(
+                "the key must be designated by an `object` label or an `id`".to_string(),
+            ));
+        }
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Pkcs11Uri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.uri)
+    }
+}
+
+impl fmt::Debug for Pkcs11Uri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The PIN is not displayed
+        f.debug_struct("Pkcs11Uri")
+            .field("token", &self.token)
+            .field("serial", &self.serial)
+            .field("object", &self.object)
+            .field("id", &self.id)
+            .field("module_path", &self.module_path)
+            .finish_non_exhaustive()
+    }
+}
+
+fn split_attribute(attribute: &str) -> Result<(&str, Vec<u8>), CertificateError> {
+    let (name, value) = attribute.split_once('=').ok_or_else(|| {
+        CertificateError::InvalidPkcs11Uri(format!("missing value for `{attribute}`"))
+    })?;
+    Ok((name, percent_decode(name, value)?))
+}
+
+fn percent_decode(name: &str, value: &str) -> Result<Vec<u8>, CertificateError> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next(), bytes.next()];
+            let hex = match hex {
+                [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                _ => None,
+            };
+            decoded.push(hex.ok_or_else(|| {
+                CertificateError::InvalidPkcs11Uri(format!("invalid percent-encoding of `{name}`"))
+            })?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    Ok(decoded)
+}
+
+fn utf8_value(name: &str, value: Vec<u8>) -> Result<String, CertificateError> {
+    String::from_utf8(value)
+        .map_err(|_| CertificateError::InvalidPkcs11Uri(format!("`{name}` is not valid UTF-8")))
+}
+
+/// Convert a raw ECDSA signature (`r || s`), as returned by PKCS#11, into its DER encoding
+#[cfg_attr(not(feature = "cryptoki"), allow(dead_code))]
+fn ecdsa_signature_to_der(signature: &[u8]) -> Vec<u8> {
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let mut content = der_integer(r);
+    content.extend(der_integer(s));
+    encode_tlv(0x30, &content)
+}
+
+/// The DER encoding of an RSA public key (PKCS#1), given its modulus and public exponent
+#[cfg_attr(not(feature = "cryptoki"), allow(dead_code))]
+fn rsa_public_key_der(modulus: &[u8], public_exponent: &[u8]) -> Vec<u8> {
+    let mut content = der_integer(modulus);
+    content.extend(der_integer(public_exponent));
+    encode_tlv(0x30, &content)
+}
+
+/// The DER encoding of an unsigned big-endian integer
+#[cfg_attr(not(feature = "cryptoki"), allow(dead_code))]
+fn der_integer(bytes: &[u8]) -> Vec<u8> {
+    let first_non_zero = bytes.iter().position(|byte| *byte != 0);
+    let bytes = &bytes[first_non_zero.unwrap_or(bytes.len().saturating_sub(1))..];
+    let mut content = Vec::with_capacity(bytes.len() + 1);
+    if bytes.first().map_or(true, |byte| byte & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend_from_slice(bytes);
+    encode_tlv(0x02, &content)
+}
+
+/// Extract an EC point from the value of a `CKA_EC_POINT` attribute
+///
+/// The point is expected to be wrapped into a DER OCTET STRING, but some tokens return it raw.
+#[cfg_attr(not(feature = "cryptoki"), allow(dead_code))]
+fn ec_point(value: &[u8], point_len: usize) -> &[u8] {
+    match value {
+        [0x04, len, point @ ..] if *len as usize == point_len && point.len() == point_len => point,
+        [0x04, 0x81, len, point @ ..] if *len as usize == point_len && point.len() == point_len => {
+            point
+        }
+        _ => value,
+    }
+}
+
+#[cfg(feature = "cryptoki")]
+pub(crate) use token::Pkcs11Key;
+
+/// Stands for a key held by a PKCS#11 token, which cannot be accessed without the `cryptoki` feature
+#[cfg(not(feature = "cryptoki"))]
+pub(crate) enum Pkcs11Key {}
+
+#[cfg(not(feature = "cryptoki"))]
+impl Pkcs11Key {
+    pub(crate) fn open(
+        _uri: &Pkcs11Uri,
+        _cryptoki: &crate::key_provider::CryptokiConfig,
+    ) -> Result<Pkcs11Key, CertificateError> {
+        Err(CertificateError::CryptokiNotSupported)
+    }
+}
+
+#[cfg(not(feature = "cryptoki"))]
+impl rustls::sign::SigningKey for Pkcs11Key {
+    fn choose_scheme(
+        &self,
+        _offered: &[rustls::SignatureScheme],
+    ) -> Option<Box<dyn rustls::sign::Signer>> {
+        match *self {}
+    }
+
+    fn algorithm(&self) -> rustls::SignatureAlgorithm {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "cryptoki"))]
+impl rcgen::RemoteKeyPair for Pkcs11Key {
+    fn public_key(&self) -> &[u8] {
+        match *self {}
+    }
+
+    fn sign(&self, _msg: &[u8]) -> Result<Vec<u8>, rcgen::RcgenError> {
+        match *self {}
+    }
+
+    fn algorithm(&self) -> &'static rcgen::SignatureAlgorithm {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "cryptoki")]
+mod token {
+    use super::*;
+    use crate::key_provider::CryptokiConfig;
+    use cryptoki::context::CInitializeArgs;
+    use cryptoki::context::Pkcs11;
+    use cryptoki::error::RvError;
+    use cryptoki::mechanism::rsa::PkcsMgfType;
+    use cryptoki::mechanism::rsa::PkcsPssParams;
+    use cryptoki::mechanism::Mechanism;
+    use cryptoki::mechanism::MechanismType;
+    use cryptoki::object::Attribute;
+    use cryptoki::object::AttributeType;
+    use cryptoki::object::KeyType;
+    use cryptoki::object::ObjectClass;
+    use cryptoki::object::ObjectHandle;
+    use cryptoki::session::Session;
+    use cryptoki::session::UserType;
+    use cryptoki::types::AuthPin;
+    use rustls::sign::Signer;
+    use rustls::sign::SigningKey;
+    use rustls::SignatureAlgorithm;
+    use rustls::SignatureScheme;
+    use sha2::Digest;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    /// The DER encoding of the OID of the P-256 curve, as found in `CKA_EC_PARAMS`
+    pub(super) const P256_PARAMS: &[u8] =
+        &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+    /// The DER encoding of the OID of the P-384 curve, as found in `CKA_EC_PARAMS`
+    const P384_PARAMS: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+
+    /// The PKCS#11 modules loaded by the process
+    ///
+    /// A module can only be initialized once per process, even if used for several keys.
+    static MODULES: Mutex<BTreeMap<PathBuf, Pkcs11>> = Mutex::new(BTreeMap::new());
+
+    #[derive(Clone, Copy)]
+    enum KeyAlgorithm {
+        EcdsaP256,
+        EcdsaP384,
+        Rsa,
+    }
+
+    /// A private key held by a PKCS#11 token
+    #[derive(Clone)]
+    pub(crate) struct Pkcs11Key(Arc<TokenKey>);
+
+    struct TokenKey {
+        session: Mutex<Session>,
+        key: ObjectHandle,
+        algorithm: KeyAlgorithm,
+        public_key: Vec<u8>,
+    }
+
+    impl Pkcs11Key {
+        /// Open a session on the token holding the key designated by the URI
+        pub(crate) fn open(
+            uri: &Pkcs11Uri,
+            cryptoki: &CryptokiConfig,
+        ) -> Result<Pkcs11Key, CertificateError> {
+            let module = load_module(&cryptoki.module_path)?;
+            let slot = module
+                .get_slots_with_token()
+                .map_err(pkcs11_error)?
+                .into_iter()
+                .find(|slot| match module.get_token_info(*slot) {
+                    Ok(info) => {
+                        uri.token
+                            .as_deref()
+                            .map_or(true, |label| info.label() == label)
+                            && uri
+                                .serial
+                                .as_deref()
+                                .map_or(true, |serial| info.serial_number() == serial)
+                    }
+                    Err(_) => false,
+                })
+                .ok_or_else(|| {
+                    CertificateError::Pkcs11Error(format!("no token matches {uri:?}"))
+                })?;
+
+            let session = module.open_ro_session(slot).map_err(pkcs11_error)?;
+            if let Some(pin) = uri.pin_value.as_ref().or(cryptoki.pin.as_ref()) {
+                match session.login(UserType::User, Some(&AuthPin::new(pin.clone()))) {
+                    Ok(())
+                    | Err(cryptoki::error::Error::Pkcs11(RvError::UserAlreadyLoggedIn, ..)) => {}
+                    Err(err) => return Err(pkcs11_error(err)),
+                }
+            }
+
+            let key = find_object(&session, uri, ObjectClass::PRIVATE_KEY)?.ok_or_else(|| {
+                CertificateError::Pkcs11Error(format!("no private key matches {uri:?}"))
+            })?;
+            let public_key = find_object(&session, uri, ObjectClass::PUBLIC_KEY)?.unwrap_or(key);
+
+            let key_type = session
+                .get_attributes(key, &[AttributeType::KeyType])
+                .map_err(pkcs11_error)?
+                .into_iter()
+                .find_map(|attribute| match attribute {
+                    Attribute::KeyType(key_type) => Some(key_type),
+                    _ => None,
+                });
+            let (algorithm, public_key) = match key_type {
+                Some(KeyType::EC) => ec_public_key(&session, public_key)?,
+                Some(KeyType::RSA) => rsa_public_key(&session, public_key)?,
+                _ => return Err(CertificateError::UnknownPrivateKeyFormat),
+            };
+
+            Ok(Pkcs11Key(Arc::new(TokenKey {
+                session: Mutex::new(session),
+                key,
+                algorithm,
+                public_key,
+            })))
+        }
+
+        fn sign_with(&self, mechanism: &Mechanism, data: &[u8]) -> Result<Vec<u8>, String> {
+            let session = self.0.session.lock().map_err(|err| err.to_string())?;
+            session
+                .sign(mechanism, self.0.key, data)
+                .map_err(|err| err.to_string())
+        }
+
+        fn sign_scheme(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, String> {
+            match scheme {
+                SignatureScheme::ECDSA_NISTP256_SHA256 => {
+                    let digest = sha2::Sha256::digest(message);
+                    let signature = self.sign_with(&Mechanism::Ecdsa, &digest)?;
+                    Ok(ecdsa_signature_to_der(&signature))
+                }
+                SignatureScheme::ECDSA_NISTP384_SHA384 => {
+                    let digest = sha2::Sha384::digest(message);
+                    let signature = self.sign_with(&Mechanism::Ecdsa, &digest)?;
+                    Ok(ecdsa_signature_to_der(&signature))
+                }
+                SignatureScheme::RSA_PSS_SHA256 => {
+                    let params = PkcsPssParams {
+                        hash_alg: MechanismType::SHA256,
+                        mgf: PkcsMgfType::MGF1_SHA256,
+                        s_len: 32.into(),
+                    };
+                    self.sign_with(&Mechanism::Sha256RsaPkcsPss(params), message)
+                }
+                SignatureScheme::RSA_PKCS1_SHA256 => {
+                    self.sign_with(&Mechanism::Sha256RsaPkcs, message)
+                }
+                _ => Err(format!("unsupported signature scheme {scheme:?}")),
+            }
+        }
+
+        /// The schemes supported by the key, by order of preference
+        fn schemes(&self) -> &'static [SignatureScheme] {
+            match self.0.algorithm {
+                KeyAlgorithm::EcdsaP256 => &[SignatureScheme::ECDSA_NISTP256_SHA256],
+                KeyAlgorithm::EcdsaP384 => &[SignatureScheme::ECDSA_NISTP384_SHA384],
+                KeyAlgorithm::Rsa => &[
+                    SignatureScheme::RSA_PSS_SHA256,
+                    SignatureScheme::RSA_PKCS1_SHA256,
+                ],
+            }
+        }
+    }
+
+    impl SigningKey for Pkcs11Key {
+        fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+            self.schemes()
+                .iter()
+                .find(|scheme| offered.contains(scheme))
+                .map(|scheme| {
+                    Box::new(Pkcs11Signer {
+                        key: self.clone(),
+                        scheme: *scheme,
+                    }) as Box<dyn Signer>
+                })
+        }
+
+        fn algorithm(&self) -> SignatureAlgorithm {
+            match self.0.algorithm {
+                KeyAlgorithm::EcdsaP256 | KeyAlgorithm::EcdsaP384 => SignatureAlgorithm::ECDSA,
+                KeyAlgorithm::Rsa => SignatureAlgorithm::RSA,
+            }
+        }
+    }
+
+    impl rcgen::RemoteKeyPair for Pkcs11Key {
+        fn public_key(&self) -> &[u8] {
+            &self.0.public_key
+        }
+
+        fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, rcgen::RcgenError> {
+            // Certificates and requests are signed using PKCS#1 v1.5 with RSA keys
+            let scheme = match self.0.algorithm {
+                KeyAlgorithm::EcdsaP256 => SignatureScheme::ECDSA_NISTP256_SHA256,
+                KeyAlgorithm::EcdsaP384 => SignatureScheme::ECDSA_NISTP384_SHA384,
+                KeyAlgorithm::Rsa => SignatureScheme::RSA_PKCS1_SHA256,
+            };
+            self.sign_scheme(scheme, msg)
+                .map_err(|_| rcgen::RcgenError::RemoteKeyError)
+        }
+
+        fn algorithm(&self) -> &'static rcgen::SignatureAlgorithm {
+            match self.0.algorithm {
+                KeyAlgorithm::EcdsaP256 => &rcgen::PKCS_ECDSA_P256_SHA256,
+                KeyAlgorithm::EcdsaP384 => &rcgen::PKCS_ECDSA_P384_SHA384,
+                KeyAlgorithm::Rsa => &rcgen::PKCS_RSA_SHA256,
+            }
+        }
+    }
+
+    struct Pkcs11Signer {
+        key: Pkcs11Key,
+        scheme: SignatureScheme,
+    }
+
+    impl Signer for Pkcs11Signer {
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+            self.key
+                .sign_scheme(self.scheme, message)
+                .map_err(|err| rustls::Error::General(format!("PKCS#11 signature failed: {err}")))
+        }
+
+        fn scheme(&self) -> SignatureScheme {
+            self.scheme
+        }
+    }
+
+    fn load_module(module_path: &Path) -> Result<Pkcs11, CertificateError> {
+        let mut modules = MODULES
+            .lock()
+            .map_err(|err| CertificateError::Pkcs11Error(err.to_string()))?;
+        if let Some(module) = modules.get(module_path) {
+            return Ok(module.clone());
+        }
+
+        let module = Pkcs11::new(module_path).map_err(|err| {
+            CertificateError::Pkcs11Error(format!(
+                "cannot load the PKCS#11 module {}: {err}",
+                module_path.display()
+            ))
+        })?;
+        module
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(pkcs11_error)?;
+        modules.insert(module_path.to_path_buf(), module.clone());
+        Ok(module)
+    }
+
+    fn find_object(
+        session: &Session,
+        uri: &Pkcs11Uri,
+        class: ObjectClass,
+    ) -> Result<Option<ObjectHandle>, CertificateError> {
+        let mut template = vec![Attribute::Class(class)];
+        if let Some(label) = &uri.object {
+            template.push(Attribute::Label(label.as_bytes().to_vec()));
+        }
+        if let Some(id) = &uri.id {
+            template.push(Attribute::Id(id.clone()));
+        }
+        let objects = session.find_objects(&template).map_err(pkcs11_error)?;
+        Ok(objects.into_iter().next())
+    }
+
+    fn ec_public_key(
+        session: &Session,
+        key: ObjectHandle,
+    ) -> Result<(KeyAlgorithm, Vec<u8>), CertificateError> {
+        let mut params = None;
+        let mut point = None;
+        for attribute in session
+            .get_attributes(key, &[AttributeType::EcParams, AttributeType::EcPoint])
+            .map_err(pkcs11_error)?
+        {
+            match attribute {
+                Attribute::EcParams(value) => params = Some(value),
+                Attribute::EcPoint(value) => point = Some(value),
+                _ => {}
+            }
+        }
+
+        let (algorithm, point_len) = match params.as_deref() {
+            Some(P256_PARAMS) => (KeyAlgorithm::EcdsaP256, 65),
+            Some(P384_PARAMS) => (KeyAlgorithm::EcdsaP384, 97),
+            _ => return Err(CertificateError::UnknownPrivateKeyFormat),
+        };
+        let point = point.ok_or(CertificateError::UnknownPrivateKeyFormat)?;
+        Ok((algorithm, ec_point(&point, point_len).to_vec()))
+    }
+
+    fn rsa_public_key(
+        session: &Session,
+        key: ObjectHandle,
+    ) -> Result<(KeyAlgorithm, Vec<u8>), CertificateError> {
+        let mut modulus = None;
+        let mut exponent = None;
+        for attribute in session
+            .get_attributes(
+                key,
+                &[AttributeType::Modulus, AttributeType::PublicExponent],
+            )
+            .map_err(pkcs11_error)?
+        {
+            match attribute {
+                Attribute::Modulus(value) => modulus = Some(value),
+                Attribute::PublicExponent(value) => exponent = Some(value),
+                _ => {}
+            }
+        }
+
+        match (modulus, exponent) {
+            (Some(modulus), Some(exponent)) => {
+                Ok((KeyAlgorithm::Rsa, rsa_public_key_der(&modulus, &exponent)))
+            }
+            _ => Err(CertificateError::UnknownPrivateKeyFormat),
+        }
+    }
+
+    fn pkcs11_error(err: cryptoki::error::Error) -> CertificateError {
+        CertificateError::Pkcs11Error(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn parse_a_key_uri() {
+        let uri: Pkcs11Uri =
+            "pkcs11:token=tedge;object=device%20key;id=%01%a2?pin-value=1234&module-path=/usr/lib/softhsm/libsofthsm2.so"
+                .parse()
+                .unwrap();
+
+        assert_eq!(uri.token.as_deref(), Some("tedge"));
+        assert_eq!(uri.object.as_deref(), Some("device key"));
+        assert_eq!(uri.id, Some(vec![0x01, 0xa2]));
+        assert_eq!(uri.pin_value.as_deref(), Some("1234"));
+        assert_eq!(
+            uri.module_path,
+            Some(PathBuf::from("/usr/lib/softhsm/libsofthsm2.so"))
+        );
+        assert!(!format!("{uri:?}").contains("1234"));
+    }
+
+    #[test]
+    fn reject_invalid_key_uris() {
+        for uri in [
+            "/etc/tedge/device-certs/tedge-private-key.pem",
+            "pkcs11:token=tedge",
+            "pkcs11:object=device;type=cert",
+            "pkcs11:object=device;slot-id=1",
+            "pkcs11:object=device%2",
+            "pkcs11:object=device?pin-source=file:/etc/pin",
+        ] {
+            assert_matches!(
+                uri.parse::<Pkcs11Uri>(),
+                Err(CertificateError::InvalidPkcs11Uri(_)),
+                "{uri}"
+            );
+        }
+    }
+
+    #[test]
+    fn encode_raw_ecdsa_signatures() {
+        let mut raw = vec![0x00, 0x7f];
+        raw.extend([0x80, 0x01]);
+
+        assert_eq!(
+            ecdsa_signature_to_der(&raw),
+            vec![0x30, 0x08, 0x02, 0x01, 0x7f, 0x02, 0x03, 0x00, 0x80, 0x01]
+        );
+    }
+
+    #[test]
+    fn encode_rsa_public_keys() {
+        assert_eq!(
+            rsa_public_key_der(&[0xc1, 0x02], &[0x01, 0x00, 0x01]),
+            vec![0x30, 0x0a, 0x02, 0x03, 0x00, 0xc1, 0x02, 0x02, 0x03, 0x01, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn unwrap_ec_points() {
+        let point = [0x04; 65];
+        let mut wrapped = vec![0x04, 65];
+        wrapped.extend(point);
+
+        assert_eq!(ec_point(&wrapped, 65), point);
+        assert_eq!(ec_point(&point, 65), point);
+    }
+
+    #[cfg(feature = "cryptoki")]
+    mod softhsm {
+        use crate::key_provider::CryptokiConfig;
+        use crate::CsrConfig;
+        use crate::KeyCertPair;
+        use crate::KeyKind;
+        use crate::KeyProvider;
+        use cryptoki::context::CInitializeArgs;
+        use cryptoki::context::Pkcs11;
+        use cryptoki::mechanism::Mechanism;
+        use cryptoki::object::Attribute;
+        use cryptoki::session::UserType;
+        use cryptoki::types::AuthPin;
+        use rustls::ClientConnection;
+        use rustls::Connection;
+        use rustls::ServerConnection;
+        use std::path::Path;
+        use std::path::PathBuf;
+        use std::sync::Arc;
+        use x509_parser::certification_request::X509CertificationRequest;
+        use x509_parser::prelude::FromDer;
+
+        const SOFTHSM_MODULES: &[&str] = &[
+            "/usr/lib/softhsm/libsofthsm2.so",
+            "/usr/lib/x86_64-linux-gnu/softhsm/libsofthsm2.so",
+            "/usr/lib/aarch64-linux-gnu/softhsm/libsofthsm2.so",
+            "/usr/local/lib/softhsm/libsofthsm2.so",
+        ];
+        const TOKEN_LABEL: &str = "tedge";
+        const KEY_LABEL: &str = "device-key";
+        const USER_PIN: &str = "123456";
+        const SO_PIN: &str = "12345678";
+
+        #[test]
+        #[ignore = "requires SoftHSM: install the softhsm2 package and run with --ignored"]
+        fn a_key_held_by_softhsm_signs_a_csr_and_authenticates_tls_connections() {
+            let module_path = SOFTHSM_MODULES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .expect("SoftHSM is not installed");
+            let token_dir = tempfile::tempdir().unwrap();
+            create_token_key(&module_path, token_dir.path());
+
+            let cryptoki = CryptokiConfig {
+                module_path,
+                pin: Some(USER_PIN.to_string()),
+            };
+            let key = KeyProvider::new(
+                format!("pkcs11:token={TOKEN_LABEL};object={KEY_LABEL}"),
+                Some(&cryptoki),
+            )
+            .unwrap();
+
+            // The CSR is signed by the token, with the key of the token
+            let request = KeyCertPair::new_certificate_signing_request(
+                &CsrConfig::default(),
+                "my-device",
+                &KeyKind::Provided(key.clone()),
+            )
+            .unwrap();
+            let der = request.certificate_signing_request_der().unwrap();
+            let (_, csr) = X509CertificationRequest::from_der(&der).unwrap();
+            csr.verify_signature().unwrap();
+            assert_eq!(
+                csr.certification_request_info
+                    .subject_pki
+                    .subject_public_key
+                    .data
+                    .as_ref(),
+                key.key_pair().unwrap().public_key_raw()
+            );
+
+            // The server and the client are both authenticated by the token
+            let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]);
+            params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+            params.key_pair = Some(key.key_pair().unwrap());
+            let certificate = rcgen::Certificate::from_params(params).unwrap();
+            let certificate = rustls::Certificate(certificate.serialize_der().unwrap());
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(&certificate).unwrap();
+
+            let server_config = rustls::ServerConfig::builder()
+                .with_safe_defaults()
+                .with_client_cert_verifier(
+                    rustls::server::AllowAnyAuthenticatedClient::new(roots.clone()).boxed(),
+                )
+                .with_cert_resolver(key.server_cert_resolver(vec![certificate.clone()]).unwrap());
+            let client_config = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_client_cert_resolver(key.client_cert_resolver(vec![certificate]).unwrap());
+
+            let mut server =
+                Connection::from(ServerConnection::new(Arc::new(server_config)).unwrap());
+            let mut client = Connection::from(
+                ClientConnection::new(Arc::new(client_config), "localhost".try_into().unwrap())
+                    .unwrap(),
+            );
+            for _ in 0..10 {
+                if !client.is_handshaking() && !server.is_handshaking() {
+                    break;
+                }
+                transfer(&mut client, &mut server);
+                transfer(&mut server, &mut client);
+            }
+            assert!(!client.is_handshaking());
+            assert!(!server.is_handshaking());
+            assert!(server.peer_certificates().is_some());
+        }
+
+        /// Initialize a SoftHSM token in the given directory and create an EC P-256 key
+        fn create_token_key(module_path: &Path, token_dir: &Path) {
+            let config = token_dir.join("softhsm2.conf");
+            std::fs::write(
+                &config,
+                format!("directories.tokendir = {}\n", token_dir.display()),
+            )
+            .unwrap();
+            std::env::set_var("SOFTHSM2_CONF", &config);
+
+            // The module is finalized when dropped, before being loaded again to open the key
+            let module = Pkcs11::new(module_path).unwrap();
+            module.initialize(CInitializeArgs::OsThreads).unwrap();
+            let slot = module.get_slots_with_token().unwrap()[0];
+            module
+                .init_token(slot, &AuthPin::new(SO_PIN.to_string()), TOKEN_LABEL)
+                .unwrap();
+
+            // SoftHSM moves an initialized token to a new slot
+            let slot = module
+                .get_slots_with_token()
+                .unwrap()
+                .into_iter()
+                .find(|slot| module.get_token_info(*slot).unwrap().label() == TOKEN_LABEL)
+                .unwrap();
+            let session = module.open_rw_session(slot).unwrap();
+            session
+                .login(UserType::So, Some(&AuthPin::new(SO_PIN.to_string())))
+                .unwrap();
+            session
+                .init_pin(&AuthPin::new(USER_PIN.to_string()))
+                .unwrap();
+            session.logout().unwrap();
+            session
+                .login(UserType::User, Some(&AuthPin::new(USER_PIN.to_string())))
+                .unwrap();
+
+            let label = Attribute::Label(KEY_LABEL.as_bytes().to_vec());
+            session
+                .generate_key_pair(
+                    &Mechanism::EccKeyPairGen,
+                    &[
+                        Attribute::Token(true),
+                        Attribute::Verify(true),
+                        Attribute::EcParams(super::super::token::P256_PARAMS.to_vec()),
+                        label.clone(),
+                    ],
+                    &[
+                        Attribute::Token(true),
+                        Attribute::Private(true),
+                        Attribute::Sensitive(true),
+                        Attribute::Sign(true),
+                        label,
+                    ],
+                )
+                .unwrap();
+        }
+
+        /// Pass the pending TLS records of a peer to the other
+        fn transfer(from: &mut Connection, to: &mut Connection) {
+            let mut records = vec![];
+            while from.wants_write() {
+                from.write_tls(&mut records).unwrap();
+            }
+            let mut records = records.as_slice();
+            while !records.is_empty() {
+                to.read_tls(&mut records).unwrap();
+                to.process_new_packets().unwrap();
+            }
+        }
+    }
+}
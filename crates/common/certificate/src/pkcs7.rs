@@ -0,0 +1,198 @@
+//! Certificate bundles encoded as degenerate PKCS#7 `SignedData` (RFC 2315)
+//!
+//! This is the format used by certificate enrollment protocols, such as EST (RFC 7030),
+//! to return the certificates issued to a device: a `SignedData` structure with no content
+//! and no signer, only a set of certificates.
+use crate::CertificateError;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_CONTEXT_0: u8 = 0xa0;
+
+/// The OID 1.2.840.113549.1.7.2 of PKCS#7 signed data
+const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+
+/// The OID 1.2.840.113549.1.7.1 of PKCS#7 data
+const OID_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01];
+
+/// Extract the DER-encoded certificates of a DER-encoded certs-only PKCS#7 structure
+///
+/// The certificates are returned in the order of the bundle.
+pub fn certificates_from_pkcs7(der: &[u8]) -> Result<Vec<Vec<u8>>, CertificateError> {
+    let (content_info, _) = read_tlv(der, TAG_SEQUENCE)?;
+    let (content_type, content_info) = read_tlv(content_info, TAG_OID)?;
+    if content_type != OID_SIGNED_DATA {
+        return Err(invalid("not a PKCS#7 signed data structure"));
+    }
+    let (signed_data, _) = read_tlv(content_info, TAG_CONTEXT_0)?;
+    let (signed_data, _) = read_tlv(signed_data, TAG_SEQUENCE)?;
+    let (_version, signed_data) = read_tlv(signed_data, TAG_INTEGER)?;
+    let (_digest_algorithms, signed_data) = read_tlv(signed_data, TAG_SET)?;
+    let (_content, signed_data) = read_tlv(signed_data, TAG_SEQUENCE)?;
+    let Ok((mut certificates, _)) = read_tlv(signed_data, TAG_CONTEXT_0) else {
+        return Err(CertificateError::NoCertificate);
+    };
+
+    let mut ders = vec![];
+    while !certificates.is_empty() {
+        let (_, rest) = read_tlv(certificates, TAG_SEQUENCE)?;
+        let certificate_len = certificates.len() - rest.len();
+        ders.push(certificates[..certificate_len].to_vec());
+        certificates = rest;
+    }
+    if ders.is_empty() {
+        return Err(CertificateError::NoCertificate);
+    }
+    Ok(ders)
+}
+
+/// Bundle DER-encoded certificates into a DER-encoded certs-only PKCS#7 structure
+pub fn certificates_to_pkcs7(certificates: &[Vec<u8>]) -> Vec<u8> {
+    let version = encode_tlv(TAG_INTEGER, &[0x01]);
+    let digest_algorithms = encode_tlv(TAG_SET, &[]);
+    let content = encode_tlv(TAG_SEQUENCE, &encode_tlv(TAG_OID, OID_DATA));
+    let certificates = encode_tlv(TAG_CONTEXT_0, &certificates.concat());
+    let signer_infos = encode_tlv(TAG_SET, &[]);
+    let signed_data = encode_tlv(
+        TAG_SEQUENCE,
+        &[
+            version,
+            digest_algorithms,
+            content,
+            certificates,
+            signer_infos,
+        ]
+        .concat(),
+    );
+    let content_info = [
+        encode_tlv(TAG_OID, OID_SIGNED_DATA),
+        encode_tlv(TAG_CONTEXT_0, &signed_data),
+    ]
+    .concat();
+    encode_tlv(TAG_SEQUENCE, &content_info)
+}
+
+/// PEM-encode DER-encoded certificates, one after the other
+pub fn certificates_to_pem(certificates: &[Vec<u8>]) -> String {
+    let mut pem = String::new();
+    for certificate in certificates {
+        pem.push_str("-----BEGIN CERTIFICATE-----\n");
+        let encoded = base64::encode(certificate);
+        for line in encoded.as_bytes().chunks(64) {
+            // base64 is ASCII
+            pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+            pem.push('\n');
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+    }
+    pem
+}
+
+/// Read a DER value with the expected tag, returning its content and the remaining input
+fn read_tlv(input: &[u8], expected_tag: u8) -> Result<(&[u8], &[u8]), CertificateError> {
+    let (&tag, input) = input
+        .split_first()
+        .ok_or_else(|| invalid("unexpected end of input"))?;
+    if tag != expected_tag {
+        return Err(invalid(&format!(
+            "unexpected tag 0x{tag:02x}, expected 0x{expected_tag:02x}"
+        )));
+    }
+
+    let (&first, input) = input
+        .split_first()
+        .ok_or_else(|| invalid("unexpected end of input"))?;
+    let (len, input) = if first < 0x80 {
+        (first as usize, input)
+    } else {
+        let len_len = (first & 0x7f) as usize;
+        if len_len == 0 || len_len > 4 || input.len() < len_len {
+            return Err(invalid("unsupported length encoding"));
+        }
+        let len = input[..len_len]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &input[len_len..])
+    };
+
+    if input.len() < len {
+        return Err(invalid("unexpected end of input"));
+    }
+    Ok(input.split_at(len))
+}
+
+pub(crate) fn encode_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut tlv = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        tlv.push(len as u8);
+    } else {
+        let len_bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte| *byte == 0)
+            .collect();
+        tlv.push(0x80 | len_bytes.len() as u8);
+        tlv.extend(len_bytes);
+    }
+    tlv.extend_from_slice(content);
+    tlv
+}
+
+fn invalid(reason: &str) -> CertificateError {
+    CertificateError::InvalidPkcs7(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCertPair;
+    use crate::KeyKind;
+    use crate::NewCertificateConfig;
+    use crate::PemCertificate;
+    use assert_matches::assert_matches;
+
+    fn certificate_der(id: &str) -> Vec<u8> {
+        let certificate = KeyCertPair::new_selfsigned_certificate(
+            &NewCertificateConfig::default(),
+            id,
+            &KeyKind::New,
+        )
+        .unwrap();
+        let pem = certificate.certificate_pem_string().unwrap();
+        pem::parse(pem).unwrap().contents
+    }
+
+    #[test]
+    fn extract_the_certificates_of_a_bundle() {
+        let certificates = vec![certificate_der("device"), certificate_der("issuer")];
+
+        let bundle = certificates_to_pkcs7(&certificates);
+        assert_eq!(certificates_from_pkcs7(&bundle).unwrap(), certificates);
+
+        let pem = certificates_to_pem(&certificates);
+        let device = PemCertificate::from_pem_string(&pem).unwrap();
+        assert_eq!(device.subject_common_name().unwrap(), "device");
+        assert_eq!(pem.matches("-----BEGIN CERTIFICATE-----").count(), 2);
+    }
+
+    #[test]
+    fn reject_invalid_bundles() {
+        let bundle = certificates_to_pkcs7(&[certificate_der("device")]);
+
+        assert_matches!(
+            certificates_from_pkcs7(&bundle[..bundle.len() - 10]),
+            Err(CertificateError::InvalidPkcs7(_))
+        );
+        assert_matches!(
+            certificates_from_pkcs7(&certificates_to_pkcs7(&[])),
+            Err(CertificateError::NoCertificate)
+        );
+        assert_matches!(
+            certificates_from_pkcs7(b"not a bundle"),
+            Err(CertificateError::InvalidPkcs7(_))
+        );
+    }
+}
@@ -10,7 +10,7 @@ use log::warn;
 use nix::sys::statvfs;
 pub use partial_response::InvalidResponseError;
 use reqwest::header;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fs;
@@ -101,13 +101,13 @@ pub struct Downloader {
     target_filename: PathBuf,
     target_permission: PermissionEntry,
     backoff: ExponentialBackoff,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl Downloader {
     /// Creates a new downloader which downloads to a target directory and uses
     /// default permissions.
-    pub fn new(target_path: PathBuf, identity: Option<Identity>) -> Self {
+    pub fn new(target_path: PathBuf, identity: Option<ClientConfig>) -> Self {
         Self {
             target_filename: target_path,
             target_permission: PermissionEntry::default(),
@@ -121,7 +121,7 @@ impl Downloader {
     pub fn with_permission(
         target_path: PathBuf,
         target_permission: PermissionEntry,
-        identity: Option<Identity>,
+        identity: Option<ClientConfig>,
     ) -> Self {
         Self {
             target_filename: target_path,
@@ -386,7 +386,7 @@ impl Downloader {
         let operation = || async {
             let mut client = reqwest::Client::builder();
             if let Some(identity) = &self.identity {
-                client = client.identity(identity.clone());
+                client = client.use_preconfigured_tls(identity.clone());
             }
             let mut request = client.build()?.get(url.url());
             if let Some(Auth::Bearer(token)) = &url.auth {
@@ -954,4 +954,53 @@ mod tests {
 
         Ok(file)
     }
+
+    #[tokio::test]
+    async fn downloader_authenticates_with_the_configured_client_certificate() {
+        let server_cert = rcgen::generate_simple_self_signed(["localhost".into()]).unwrap();
+        let client_cert = rcgen::generate_simple_self_signed(["a-client".into()]).unwrap();
+
+        // The server only accepts the clients authenticated by the client certificate
+        let mut client_roots = rustls::RootCertStore::empty();
+        client_roots
+            .add(&rustls::Certificate(client_cert.serialize_der().unwrap()))
+            .unwrap();
+        let server_config = axum_tls::ssl_config(
+            vec![server_cert.serialize_der().unwrap()],
+            server_cert.serialize_private_key_der(),
+            Some(client_roots),
+        )
+        .unwrap();
+        let app =
+            axum::Router::new().route("/some_file.txt", axum::routing::get(|| async { "hello" }));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum_tls::start_tls_server(listener, server_config, app));
+
+        // The client key is read from a file, as it would be from a PKCS#11 token
+        let ttd = TempDir::new().unwrap();
+        let key_path = ttd.path().join("key.pem");
+        std::fs::write(&key_path, client_cert.serialize_private_key_pem()).unwrap();
+        let key = certificate::KeyProvider::new(&key_path, None).unwrap();
+        let mut server_roots = rustls::RootCertStore::empty();
+        server_roots
+            .add(&rustls::Certificate(server_cert.serialize_der().unwrap()))
+            .unwrap();
+        let tls_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(server_roots)
+            .with_client_cert_resolver(
+                key.client_cert_resolver(vec![rustls::Certificate(
+                    client_cert.serialize_der().unwrap(),
+                )])
+                .unwrap(),
+            );
+
+        let target_path = ttd.path().join("test_download");
+        let url = DownloadInfo::new(&format!("https://localhost:{port}/some_file.txt"));
+        let downloader = Downloader::new(target_path, Some(tls_config));
+        downloader.download(&url).await.unwrap();
+
+        assert_eq!(std::fs::read(downloader.filename()).unwrap(), b"hello");
+    }
 }
@@ -58,6 +58,37 @@ impl JsonWriter {
         }
     }
 
+    pub fn write_i64(&mut self, value: i64) -> Result<(), JsonWriterError> {
+        self.maybe_separate();
+        serde_json::to_writer(&mut self.buffer, &value)?;
+        self.needs_separator = true;
+        Ok(())
+    }
+
+    pub fn write_u64(&mut self, value: u64) -> Result<(), JsonWriterError> {
+        self.maybe_separate();
+        serde_json::to_writer(&mut self.buffer, &value)?;
+        self.needs_separator = true;
+        Ok(())
+    }
+
+    pub fn write_bool(&mut self, value: bool) -> Result<(), JsonWriterError> {
+        self.maybe_separate();
+        serde_json::to_writer(&mut self.buffer, &value)?;
+        self.needs_separator = true;
+        Ok(())
+    }
+
+    pub fn write_open_array(&mut self) {
+        self.maybe_separate();
+        self.buffer.push(b'[');
+    }
+
+    pub fn write_close_array(&mut self) {
+        self.buffer.push(b']');
+        self.needs_separator = true;
+    }
+
     pub fn write_open_obj(&mut self) {
         self.maybe_separate();
         self.buffer.push(b'{');
@@ -166,4 +197,31 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn write_integers_booleans_and_arrays() -> anyhow::Result<()> {
+        let mut jw = JsonWriter::with_capacity(128);
+        jw.write_open_obj();
+        jw.write_key("counter")?;
+        jw.write_u64(u64::MAX)?;
+        jw.write_key("offset")?;
+        jw.write_i64(i64::MIN)?;
+        jw.write_key("running")?;
+        jw.write_bool(true)?;
+        jw.write_key("spectrum")?;
+        jw.write_open_array();
+        jw.write_f64(0.5)?;
+        jw.write_open_array();
+        jw.write_f64(1.5)?;
+        jw.write_close_array();
+        jw.write_close_array();
+        jw.write_close_obj();
+
+        assert_eq!(
+            jw.into_string()?,
+            r#"{"counter":18446744073709551615,"offset":-9223372036854775808,"running":true,"spectrum":[0.5,[1.5]]}"#
+        );
+
+        Ok(())
+    }
 }
@@ -1,9 +1,13 @@
 use crate::Message;
 use crate::TopicFilter;
+use crate::TopicPrefixTranslation;
+use certificate::key_provider;
 use certificate::parse_root_certificate;
 use certificate::CertificateError;
+use certificate::KeyProvider;
 use log::debug;
 use rumqttc::tokio_rustls::rustls;
+use rumqttc::tokio_rustls::rustls::sign::SigningKey;
 use rumqttc::tokio_rustls::rustls::Certificate;
 use rumqttc::LastWill;
 use std::fmt::Debug;
@@ -57,6 +61,11 @@ pub struct Config {
     ///
     /// Default: None
     pub initial_message: Option<InitMessageFn>,
+
+    /// Translation of the topic prefixes used by the client into those used on the broker
+    ///
+    /// Default: no translation
+    pub topic_prefix_translation: TopicPrefixTranslation,
 }
 
 #[derive(Debug, Clone)]
@@ -108,7 +117,16 @@ impl Default for AuthenticationConfig {
 #[derive(Clone)]
 struct ClientAuthConfig {
     cert_chain: Vec<Certificate>,
-    key: Zeroizing<PrivateKey>,
+    key: ClientKey,
+}
+
+#[derive(Clone)]
+enum ClientKey {
+    /// A key read from a PEM file
+    Pem(Zeroizing<PrivateKey>),
+
+    /// A key that cannot be read, e.g. because held by a PKCS#11 token
+    Provided(Arc<dyn SigningKey>),
 }
 
 impl Debug for ClientAuthConfig {
@@ -167,6 +185,7 @@ impl Default for Config {
             max_packet_size: 1024 * 1024,
             last_will_message: None,
             initial_message: None,
+            topic_prefix_translation: TopicPrefixTranslation::default(),
         }
     }
 }
@@ -248,6 +267,21 @@ impl Config {
         }
     }
 
+    /// Publish and subscribe to the topics under `client_prefix` as if under `broker_prefix`
+    ///
+    /// The client uses topics starting with `client_prefix`, e.g. `c8y/s/us`,
+    /// while the messages are actually exchanged with the broker on topics starting with `broker_prefix`,
+    /// e.g. `c8y-staging/s/us`.
+    pub fn with_translated_topic_prefix(
+        mut self,
+        client_prefix: impl Into<String>,
+        broker_prefix: impl Into<String>,
+    ) -> Self {
+        self.topic_prefix_translation
+            .add(client_prefix, broker_prefix);
+        self
+    }
+
     /// Set the initial message
     pub fn with_initial_message(
         self,
@@ -305,7 +339,39 @@ impl Config {
 
         let client_auth_config = ClientAuthConfig {
             cert_chain,
-            key: Zeroizing::new(PrivateKey(key)),
+            key: ClientKey::Pem(Zeroizing::new(PrivateKey(key))),
+        };
+
+        let authentication_config = self.broker.authentication.get_or_insert(Default::default());
+        authentication_config.client_auth = Some(client_auth_config);
+
+        Ok(self)
+    }
+
+    /// Provide client certificate and private key for authentication, the key
+    /// being either a PEM file or held by a PKCS#11 token.
+    ///
+    /// See [`Config::with_client_auth`].
+    pub fn with_client_auth_key(
+        &mut self,
+        cert_file: impl AsRef<Path>,
+        key: &KeyProvider,
+    ) -> Result<&mut Self, CertificateError> {
+        let key = match key {
+            KeyProvider::File(key_file) => {
+                return self.with_client_auth(cert_file.as_ref(), key_file)
+            }
+            KeyProvider::Pkcs11 { uri, .. } => {
+                debug!("Using client private key: {uri}");
+                key.signing_key()?
+            }
+        };
+        debug!("Using client certificate: {}", cert_file.as_ref().display());
+        let cert_chain = parse_root_certificate::read_cert_chain(cert_file)?;
+
+        let client_auth_config = ClientAuthConfig {
+            cert_chain,
+            key: ClientKey::Provided(key),
         };
 
         let authentication_config = self.broker.authentication.get_or_insert(Default::default());
@@ -314,6 +380,12 @@ impl Config {
         Ok(self)
     }
 
+    /// The subscriptions as expected by `rumqttc`, using the broker topic prefixes
+    pub(crate) fn subscription_filters(&self) -> Vec<rumqttc::SubscribeFilter> {
+        self.topic_prefix_translation
+            .filters_to_broker(&self.subscriptions)
+    }
+
     /// Wrap this config into an internal set of options for `rumqttc`.
     pub fn rumqttc_options(&self) -> Result<rumqttc::MqttOptions, rustls::Error> {
         let id = match &self.session_name {
@@ -341,10 +413,15 @@ impl Config {
                 .with_root_certificates(authentication_config.cert_store.clone());
 
             let tls_config = match authentication_config.client_auth.clone() {
-                Some(client_auth_config) => tls_config.with_client_auth_cert(
-                    client_auth_config.cert_chain,
-                    client_auth_config.key.deref().0.clone(),
-                )?,
+                Some(ClientAuthConfig {
+                    cert_chain,
+                    key: ClientKey::Pem(key),
+                }) => tls_config.with_client_auth_cert(cert_chain, key.deref().0.clone())?,
+                Some(ClientAuthConfig {
+                    cert_chain,
+                    key: ClientKey::Provided(key),
+                }) => tls_config
+                    .with_client_cert_resolver(key_provider::client_cert_resolver(cert_chain, key)),
                 None => tls_config.with_no_client_auth(),
             };
 
@@ -355,7 +432,7 @@ impl Config {
 
         if let Some(lwp) = &self.last_will_message {
             let last_will_message = LastWill {
-                topic: lwp.topic.clone().into(),
+                topic: self.topic_prefix_translation.to_broker(&lwp.topic.name),
                 message: lwp.payload().clone().into(),
                 qos: lwp.qos,
                 retain: lwp.retain,
@@ -4,6 +4,7 @@ use crate::Message;
 use crate::MqttError;
 use crate::PubChannel;
 use crate::SubChannel;
+use crate::TopicPrefixTranslation;
 use futures::channel::mpsc;
 use futures::channel::oneshot;
 use futures::SinkExt;
@@ -102,6 +103,7 @@ impl Connection {
             published_receiver,
             error_sender,
             config.last_will_message.clone(),
+            config.topic_prefix_translation.clone(),
             pub_done_sender,
         ));
 
@@ -149,7 +151,7 @@ impl Connection {
                     };
                     info!("MQTT connection established");
 
-                    let subscriptions = config.subscriptions.filters();
+                    let subscriptions = config.subscription_filters();
 
                     // Need check here otherwise it will hang waiting for a SubAck, and none will come when there is no subscription.
                     if subscriptions.is_empty() {
@@ -169,7 +171,12 @@ impl Connection {
                 Ok(Event::Incoming(Packet::Publish(msg))) => {
                     // Messages can be received before a sub ack
                     // Errors on send are ignored: it just means the client has closed the receiving channel.
-                    let _ = message_sender.send(msg.into()).await;
+                    if let Some(msg) = config
+                        .topic_prefix_translation
+                        .message_to_client(msg.into())
+                    {
+                        let _ = message_sender.send(msg).await;
+                    }
                 }
 
                 Err(err) => {
@@ -206,7 +213,12 @@ impl Connection {
                 Ok(Event::Incoming(Packet::Publish(msg))) => {
                     // Errors on send are ignored: it just means the client has closed the receiving channel.
                     // One has to continue the loop though, because rumqttc relies on this polling.
-                    let _ = message_sender.send(msg.into()).await;
+                    if let Some(msg) = config
+                        .topic_prefix_translation
+                        .message_to_client(msg.into())
+                    {
+                        let _ = message_sender.send(msg).await;
+                    }
                 }
 
                 Ok(Event::Incoming(Packet::ConnAck(ack))) => {
@@ -216,7 +228,9 @@ impl Connection {
                         info!("MQTT connection re-established");
                         if let Some(ref imsg_fn) = config.initial_message {
                             // publish the initial message on connect
-                            let message = imsg_fn.new_init_message();
+                            let message = config
+                                .topic_prefix_translation
+                                .message_to_broker(imsg_fn.new_init_message());
                             mqtt_client
                                 .publish(
                                     message.topic.name.clone(),
@@ -231,7 +245,7 @@ impl Connection {
                             // Workaround for  https://github.com/bytebeamio/rumqtt/issues/250
                             // If session_name is not provided, then re-subscribe
 
-                            let subscriptions = config.subscriptions.filters();
+                            let subscriptions = config.subscription_filters();
                             // Need check here otherwise it will hang waiting for a SubAck, and none will come when there is no subscription.
                             if subscriptions.is_empty() {
                                 break;
@@ -272,6 +286,7 @@ impl Connection {
         mut messages_receiver: mpsc::UnboundedReceiver<Message>,
         mut error_sender: mpsc::UnboundedSender<MqttError>,
         last_will: Option<Message>,
+        topic_prefix_translation: TopicPrefixTranslation,
         done: oneshot::Sender<()>,
     ) {
         loop {
@@ -282,6 +297,7 @@ impl Connection {
                     break;
                 }
                 Some(message) => {
+                    let message = topic_prefix_translation.message_to_broker(message);
                     let payload = Vec::from(message.payload_bytes());
                     if let Err(err) = mqtt_client
                         .publish(message.topic, message.qos, message.retain, payload)
@@ -296,6 +312,7 @@ impl Connection {
         // As the broker doesn't send the last will when the client disconnects gracefully
         // one has first to explicitly send the last will message.
         if let Some(last_will) = last_will {
+            let last_will = topic_prefix_translation.message_to_broker(last_will);
             let payload = Vec::from(last_will.payload_bytes());
             let _ = mqtt_client
                 .publish(last_will.topic, last_will.qos, last_will.retain, payload)
@@ -30,7 +30,7 @@ pub async fn init_session(config: &Config) -> Result<(), MqttError> {
                 if let Some(err) = MqttError::maybe_connection_error(&ack) {
                     return Err(err);
                 };
-                let subscriptions = config.subscriptions.filters();
+                let subscriptions = config.subscription_filters();
                 if subscriptions.is_empty() {
                     break;
                 }
@@ -154,6 +154,37 @@ async fn publishing_messages() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn translating_topic_prefixes() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    let mut staging_messages = broker.messages_published_on("c8y-staging/#").await;
+
+    // A client using `c8y` topics, that are actually `c8y-staging` topics on the broker
+    let mqtt_config = mqtt_config
+        .with_session_name("translating_topic_prefixes")
+        .with_subscriptions("c8y/s/ds".try_into()?)
+        .with_translated_topic_prefix("c8y", "c8y-staging");
+    let mut con = Connection::new(&mqtt_config).await?;
+
+    // Publishes its messages under the broker prefix
+    con.published.send(message("c8y/s/us", "101")).await?;
+    mqtt_tests::assert_received(&mut staging_messages, TIMEOUT, vec!["101"]).await;
+
+    // And receives only the messages published under the broker prefix
+    broker.publish("c8y/s/ds", "not for staging").await?;
+    broker.publish("c8y-staging/s/ds", "for staging").await?;
+    assert_eq!(
+        MaybeMessage::Next(message("c8y/s/ds", "for staging")),
+        next_message(&mut con.received).await
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn implementing_a_message_mapper() -> Result<(), anyhow::Error> {
@@ -199,6 +199,91 @@ impl TryInto<TopicFilter> for Vec<String> {
     }
 }
 
+/// Translation of the topic prefixes used by a client into the topic prefixes used on the broker
+///
+/// This lets several instances of a client share a broker, each with its own topic prefix,
+/// while the client code only knows the well-known prefix, e.g. `c8y`.
+///
+/// The messages received on the broker topics of a translated prefix are seen by the client
+/// as received on the client prefix; and the messages received on the client prefix,
+/// which belong to other instances, are hidden from the client.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TopicPrefixTranslation {
+    prefixes: Vec<(String, String)>,
+}
+
+impl TopicPrefixTranslation {
+    /// Translate the topics under `client_prefix` into topics under `broker_prefix`
+    pub fn add(&mut self, client_prefix: impl Into<String>, broker_prefix: impl Into<String>) {
+        self.prefixes
+            .push((client_prefix.into(), broker_prefix.into()));
+    }
+
+    /// Check if there is no prefix to translate
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// The broker topic (or topic filter) for a topic (or topic filter) used by the client
+    pub fn to_broker(&self, topic: &str) -> String {
+        self.prefixes
+            .iter()
+            .find_map(|(client, broker)| replace_prefix(topic, client, broker))
+            .unwrap_or_else(|| topic.to_string())
+    }
+
+    /// The topic seen by the client for a broker topic, if not hidden from the client
+    pub fn to_client(&self, topic: &str) -> Option<String> {
+        if let Some(topic) = self
+            .prefixes
+            .iter()
+            .find_map(|(client, broker)| replace_prefix(topic, broker, client))
+        {
+            return Some(topic);
+        }
+        if self
+            .prefixes
+            .iter()
+            .any(|(client, _)| replace_prefix(topic, client, client).is_some())
+        {
+            return None;
+        }
+        Some(topic.to_string())
+    }
+
+    pub(crate) fn message_to_broker(&self, mut message: Message) -> Message {
+        if !self.is_empty() {
+            message.topic = Topic::new_unchecked(&self.to_broker(&message.topic.name));
+        }
+        message
+    }
+
+    pub(crate) fn message_to_client(&self, mut message: Message) -> Option<Message> {
+        if !self.is_empty() {
+            message.topic = Topic::new_unchecked(&self.to_client(&message.topic.name)?);
+        }
+        Some(message)
+    }
+
+    pub(crate) fn filters_to_broker(&self, filter: &TopicFilter) -> Vec<SubscribeFilter> {
+        let mut filters = filter.filters();
+        for filter in filters.iter_mut() {
+            filter.path = self.to_broker(&filter.path);
+        }
+        filters
+    }
+}
+
+/// Replace the leading levels of a topic matching the given prefix
+fn replace_prefix(topic: &str, prefix: &str, replacement: &str) -> Option<String> {
+    let levels = topic.strip_prefix(prefix)?;
+    if levels.is_empty() || levels.starts_with('/') {
+        Some(format!("{replacement}{levels}"))
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -229,4 +314,36 @@ mod tests {
         assert!(TopicFilter::new("/a/#/b").is_err());
         assert!(TopicFilter::new("/a/#/+").is_err());
     }
+
+    #[test]
+    fn translate_topic_prefixes() {
+        let mut translation = TopicPrefixTranslation::default();
+        translation.add("c8y", "c8y-staging");
+
+        assert_eq!(translation.to_broker("c8y/s/us"), "c8y-staging/s/us");
+        assert_eq!(translation.to_broker("c8y/#"), "c8y-staging/#");
+        assert_eq!(translation.to_broker("c8y"), "c8y-staging");
+        assert_eq!(translation.to_broker("c8yfoo/s/us"), "c8yfoo/s/us");
+        assert_eq!(
+            translation.to_broker("te/device/main///m/"),
+            "te/device/main///m/"
+        );
+
+        assert_eq!(
+            translation.to_client("c8y-staging/s/ds"),
+            Some("c8y/s/ds".to_string())
+        );
+        assert_eq!(
+            translation.to_client("te/device/main///m/"),
+            Some("te/device/main///m/".to_string())
+        );
+    }
+
+    #[test]
+    fn hide_the_topics_of_the_untranslated_client_prefix() {
+        let mut translation = TopicPrefixTranslation::default();
+        translation.add("c8y", "c8y-staging");
+
+        assert_eq!(translation.to_client("c8y/s/ds"), None);
+    }
 }
@@ -113,7 +113,7 @@ impl ExecCommand {
         config: Vec<String>,
         service_cmd: ServiceCommand,
         config_path: Utf8PathBuf,
-        service: SystemService,
+        service: SystemService<'_>,
     ) -> Result<Self, SystemServiceError> {
         let replaced = replace_with_service_name(&config, service_cmd, &config_path, service)?;
         Self::try_new(replaced, service_cmd, config_path)
@@ -145,7 +145,7 @@ fn replace_with_service_name(
     input_args: &[String],
     service_cmd: ServiceCommand,
     config_path: impl Into<Utf8PathBuf>,
-    service: SystemService,
+    service: SystemService<'_>,
 ) -> Result<Vec<String>, SystemServiceError> {
     if !input_args.iter().any(|s| s == "{}") {
         return Err(SystemServiceError::SystemConfigInvalidSyntax {
@@ -158,7 +158,7 @@ fn replace_with_service_name(
     let mut args = input_args.to_owned();
     for item in args.iter_mut() {
         if item == "{}" {
-            *item = SystemService::as_service_name(service).to_string();
+            *item = SystemService::as_service_name(service);
         }
     }
 
@@ -166,17 +166,17 @@ fn replace_with_service_name(
 }
 
 #[derive(Debug, Copy, Clone)]
-enum ServiceCommand {
+enum ServiceCommand<'a> {
     CheckManager,
-    Stop(SystemService),
-    Start(SystemService),
-    Restart(SystemService),
-    Enable(SystemService),
-    Disable(SystemService),
-    IsActive(SystemService),
+    Stop(SystemService<'a>),
+    Start(SystemService<'a>),
+    Restart(SystemService<'a>),
+    Enable(SystemService<'a>),
+    Disable(SystemService<'a>),
+    IsActive(SystemService<'a>),
 }
 
-impl ServiceCommand {
+impl ServiceCommand<'_> {
     fn try_exec_command(
         &self,
         service_manager: &GeneralServiceManager,
@@ -228,7 +228,7 @@ impl ServiceCommand {
     }
 }
 
-impl fmt::Display for ServiceCommand {
+impl fmt::Display for ServiceCommand<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::CheckManager => write!(f, "is_available"),
@@ -1,25 +1,42 @@
+use crate::ProfileName;
+use std::fmt;
+
 /// An enumeration of all supported system services.
-#[derive(Debug, Copy, Clone, strum_macros::Display, strum_macros::IntoStaticStr)]
-pub enum SystemService {
-    #[strum(serialize = "mosquitto")]
+///
+/// The mapper of a cloud connection profile is an instance of the cloud mapper service,
+/// e.g. `tedge-mapper-c8y@staging` for the `staging` profile of Cumulocity.
+#[derive(Debug, Copy, Clone)]
+pub enum SystemService<'a> {
     /// Mosquitto broker
     Mosquitto,
-    #[strum(serialize = "tedge-mapper-az")]
     /// Azure TEdge mapper
-    TEdgeMapperAz,
-    #[strum(serialize = "tedge-mapper-aws")]
+    TEdgeMapperAz(Option<&'a ProfileName>),
     /// AWS TEdge mapper
-    TEdgeMapperAws,
-    #[strum(serialize = "tedge-mapper-c8y")]
+    TEdgeMapperAws(Option<&'a ProfileName>),
     /// Cumulocity TEdge mapper
-    TEdgeMapperC8y,
-    #[strum(serialize = "tedge-agent")]
+    TEdgeMapperC8y(Option<&'a ProfileName>),
     /// TEdge SM agent
     TEdgeSMAgent,
 }
 
-impl SystemService {
-    pub(crate) fn as_service_name(service: SystemService) -> &'static str {
-        service.into()
+impl fmt::Display for SystemService<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (service, profile) = match self {
+            Self::Mosquitto => ("mosquitto", None),
+            Self::TEdgeMapperAz(profile) => ("tedge-mapper-az", *profile),
+            Self::TEdgeMapperAws(profile) => ("tedge-mapper-aws", *profile),
+            Self::TEdgeMapperC8y(profile) => ("tedge-mapper-c8y", *profile),
+            Self::TEdgeSMAgent => ("tedge-agent", None),
+        };
+        match profile {
+            None => write!(f, "{service}"),
+            Some(profile) => write!(f, "{service}@{profile}"),
+        }
+    }
+}
+
+impl SystemService<'_> {
+    pub(crate) fn as_service_name(service: SystemService) -> String {
+        service.to_string()
     }
 }
@@ -5,4 +5,5 @@ pub mod tedge_config_repository;
 
 mod figment;
 pub mod models;
+pub mod profiles;
 pub mod tedge_config;
@@ -5,8 +5,10 @@ pub mod flag;
 pub mod host_port;
 pub mod ipaddress;
 pub mod port;
+pub mod profile_name;
 pub mod seconds;
 pub mod templates_set;
+pub mod topic_prefix;
 
 pub use tedge_utils::timestamp;
 
@@ -21,5 +23,7 @@ pub use self::flag::*;
 pub use self::host_port::HostPort;
 pub use self::ipaddress::*;
 pub use self::port::*;
+pub use self::profile_name::*;
 pub use self::seconds::*;
 pub use self::templates_set::*;
+pub use self::topic_prefix::*;
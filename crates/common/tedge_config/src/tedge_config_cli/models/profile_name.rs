@@ -0,0 +1,85 @@
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// The name of a cloud connection profile, e.g. `staging` for `c8y.profiles.staging`
+///
+/// As used to name services, files and MQTT sessions,
+/// a profile name is only made of ASCII letters, digits, `-` and `_`.
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProfileName(String);
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Invalid profile name: '{name}'. A profile name can only contain ASCII letters, digits, '-' and '_'")]
+pub struct InvalidProfileName {
+    name: String,
+}
+
+impl TryFrom<String> for ProfileName {
+    type Error = InvalidProfileName;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let is_valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if is_valid {
+            Ok(ProfileName(name))
+        } else {
+            Err(InvalidProfileName { name })
+        }
+    }
+}
+
+impl FromStr for ProfileName {
+    type Err = InvalidProfileName;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        ProfileName::try_from(name.to_string())
+    }
+}
+
+impl From<ProfileName> for String {
+    fn from(name: ProfileName) -> Self {
+        name.0
+    }
+}
+
+impl Deref for ProfileName {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for ProfileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("staging")]
+    #[test_case("tenant-2")]
+    #[test_case("second_hub")]
+    fn valid_profile_names(name: &str) {
+        assert_eq!(name.parse::<ProfileName>().unwrap().to_string(), name);
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("a/b" ; "slash")]
+    #[test_case("a b" ; "space")]
+    #[test_case("a.b" ; "dot")]
+    #[test_case("a@b" ; "at sign")]
+    fn invalid_profile_names(name: &str) {
+        assert!(name.parse::<ProfileName>().is_err());
+    }
+}
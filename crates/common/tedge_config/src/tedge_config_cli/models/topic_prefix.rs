@@ -0,0 +1,93 @@
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// The prefix of the local MQTT topics forwarded to and from a cloud, e.g. `c8y`
+///
+/// A prefix is a single topic level, so it cannot contain `/`, MQTT wildcards nor whitespace.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TopicPrefix(String);
+
+impl doku::Document for TopicPrefix {
+    fn ty() -> doku::Type {
+        String::ty()
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Invalid topic prefix: '{prefix}'. A topic prefix must be a non-empty topic level, without '/', '+', '#' nor whitespace")]
+pub struct InvalidTopicPrefix {
+    prefix: String,
+}
+
+impl TopicPrefix {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for TopicPrefix {
+    type Error = InvalidTopicPrefix;
+
+    fn try_from(prefix: String) -> Result<Self, Self::Error> {
+        let is_valid = !prefix.is_empty()
+            && !prefix
+                .chars()
+                .any(|c| c == '/' || c == '+' || c == '#' || c.is_whitespace());
+        if is_valid {
+            Ok(TopicPrefix(prefix))
+        } else {
+            Err(InvalidTopicPrefix { prefix })
+        }
+    }
+}
+
+impl FromStr for TopicPrefix {
+    type Err = InvalidTopicPrefix;
+
+    fn from_str(prefix: &str) -> Result<Self, Self::Err> {
+        TopicPrefix::try_from(prefix.to_string())
+    }
+}
+
+impl From<TopicPrefix> for String {
+    fn from(prefix: TopicPrefix) -> Self {
+        prefix.0
+    }
+}
+
+impl Deref for TopicPrefix {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for TopicPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("c8y")]
+    #[test_case("c8y-staging" ; "dash")]
+    fn valid_topic_prefixes(prefix: &str) {
+        assert_eq!(prefix.parse::<TopicPrefix>().unwrap().as_str(), prefix);
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("c8y/staging" ; "slash")]
+    #[test_case("c8y+" ; "plus wildcard")]
+    #[test_case("#" ; "hash wildcard")]
+    #[test_case("c8y staging" ; "space")]
+    fn invalid_topic_prefixes(prefix: &str) {
+        assert!(prefix.parse::<TopicPrefix>().is_err());
+    }
+}
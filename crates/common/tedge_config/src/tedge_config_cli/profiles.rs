@@ -0,0 +1,419 @@
+//! Named cloud connection profiles, e.g. `c8y.profiles.staging`
+//!
+//! A profile has the same settings as the cloud section it belongs to.
+//! These settings are stored in `tedge.toml` under `[c8y.profiles.<name>]`
+//! and are used, in place of the `[c8y]` settings, by the bridge and the mapper of the profile.
+use crate::ParseKeyError;
+use crate::ProfileName;
+use crate::ReadError;
+use crate::ReadableKey;
+use crate::TEdgeConfig;
+use crate::TEdgeConfigDto;
+use crate::TopicPrefix;
+use crate::WritableKey;
+use crate::WriteError;
+use std::fmt;
+use std::str::FromStr;
+
+/// A cloud supporting named connection profiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloudType {
+    C8y,
+    Az,
+    Aws,
+}
+
+impl CloudType {
+    /// The name of the cloud, as used for its configuration section and topic prefix
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CloudType::C8y => "c8y",
+            CloudType::Az => "az",
+            CloudType::Aws => "aws",
+        }
+    }
+
+    /// The cloud configured by the given key, if any
+    fn of_key(key: &str) -> Option<Self> {
+        match key.split('.').next()? {
+            "c8y" => Some(CloudType::C8y),
+            "az" => Some(CloudType::Az),
+            "aws" => Some(CloudType::Aws),
+            _ => None,
+        }
+    }
+
+    /// The topic prefix used by a profile when none is configured, e.g. `c8y-staging`
+    pub fn default_profile_topic_prefix(self, profile: &ProfileName) -> TopicPrefix {
+        // A profile name is a valid topic level
+        TopicPrefix::try_from(format!("{self}-{profile}")).unwrap()
+    }
+}
+
+impl fmt::Display for CloudType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("No {cloud} connection profile named '{profile}' is configured")]
+pub struct UnknownProfile {
+    pub cloud: CloudType,
+    pub profile: ProfileName,
+}
+
+impl TEdgeConfig {
+    /// The configuration to be used by the bridge and the mapper of a cloud profile
+    ///
+    /// The settings of the profile replace the settings of the cloud,
+    /// i.e. for the `staging` profile of `c8y`, `c8y.url` is read from `c8y.profiles.staging.url`.
+    pub fn profile(
+        &self,
+        cloud: CloudType,
+        profile: &ProfileName,
+    ) -> Result<TEdgeConfig, UnknownProfile> {
+        let unknown_profile = || UnknownProfile {
+            cloud,
+            profile: profile.clone(),
+        };
+        let mut dto = self.dto().clone();
+        let topic_prefix = match cloud {
+            CloudType::C8y => {
+                let profiles = dto.c8y.profiles.take().unwrap_or_default();
+                dto.c8y = profiles.get(profile).cloned().ok_or_else(unknown_profile)?;
+                &mut dto.c8y.bridge.topic_prefix
+            }
+            CloudType::Az => {
+                let profiles = dto.az.profiles.take().unwrap_or_default();
+                dto.az = profiles.get(profile).cloned().ok_or_else(unknown_profile)?;
+                &mut dto.az.bridge.topic_prefix
+            }
+            CloudType::Aws => {
+                let profiles = dto.aws.profiles.take().unwrap_or_default();
+                dto.aws = profiles.get(profile).cloned().ok_or_else(unknown_profile)?;
+                &mut dto.aws.bridge.topic_prefix
+            }
+        };
+        topic_prefix.get_or_insert_with(|| cloud.default_profile_topic_prefix(profile));
+
+        Ok(TEdgeConfig::from_dto(&dto, self.location()))
+    }
+
+    /// The names of the connection profiles configured for a cloud
+    pub fn profile_names(&self, cloud: CloudType) -> Vec<ProfileName> {
+        let dto = self.dto();
+        let profiles = match cloud {
+            CloudType::C8y => dto
+                .c8y
+                .profiles
+                .as_ref()
+                .map(|p| p.keys().cloned().collect()),
+            CloudType::Az => dto
+                .az
+                .profiles
+                .as_ref()
+                .map(|p| p.keys().cloned().collect()),
+            CloudType::Aws => dto
+                .aws
+                .profiles
+                .as_ref()
+                .map(|p| p.keys().cloned().collect()),
+        };
+        profiles.unwrap_or_default()
+    }
+
+    /// Read a configuration value, possibly from a cloud profile
+    pub fn read_profiled_string(
+        &self,
+        key: &ProfiledKey<ReadableKey>,
+    ) -> Result<String, ReadError> {
+        match key.profile() {
+            None => self.read_string(key.key),
+            Some((cloud, profile)) => self.profile(cloud, profile)?.read_string(key.key),
+        }
+    }
+}
+
+impl TEdgeConfigDto {
+    /// Update a configuration value, possibly of a cloud profile
+    pub fn try_update_profiled_str(
+        &mut self,
+        key: &ProfiledKey<WritableKey>,
+        value: &str,
+    ) -> Result<(), WriteError> {
+        match key.profile() {
+            None => self.try_update_str(key.key, value),
+            Some((cloud, profile)) => self.update_profile(cloud, profile, |profile_dto| {
+                profile_dto.try_update_str(key.key, value)
+            }),
+        }
+    }
+
+    /// Unset a configuration value, possibly of a cloud profile
+    pub fn unset_profiled_key(&mut self, key: &ProfiledKey<WritableKey>) {
+        match key.profile() {
+            None => self.unset_key(key.key),
+            Some((cloud, profile)) => {
+                self.update_profile(cloud, profile, |profile_dto| profile_dto.unset_key(key.key))
+            }
+        }
+    }
+
+    /// Update the settings of a profile, using a dto where the cloud section holds the profile settings
+    ///
+    /// A profile left with no settings is removed.
+    fn update_profile<T>(
+        &mut self,
+        cloud: CloudType,
+        profile: &ProfileName,
+        update: impl FnOnce(&mut TEdgeConfigDto) -> T,
+    ) -> T {
+        macro_rules! update_profile_of {
+            ($cloud:ident) => {{
+                let profiles = self.$cloud.profiles.get_or_insert_with(Default::default);
+                let mut profile_dto = TEdgeConfigDto::default();
+                profile_dto.$cloud = profiles.remove(profile).unwrap_or_default();
+                let result = update(&mut profile_dto);
+                profile_dto.$cloud.profiles = None;
+                if profile_dto.$cloud != Default::default() {
+                    profiles.insert(profile.clone(), profile_dto.$cloud);
+                }
+                if profiles.is_empty() {
+                    self.$cloud.profiles = None;
+                }
+                result
+            }};
+        }
+
+        match cloud {
+            CloudType::C8y => update_profile_of!(c8y),
+            CloudType::Az => update_profile_of!(az),
+            CloudType::Aws => update_profile_of!(aws),
+        }
+    }
+}
+
+/// A configuration key, possibly of a cloud profile, e.g. `c8y.url` or `c8y.profiles.staging.url`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfiledKey<K> {
+    /// The key of the setting, e.g. `c8y.url` for `c8y.profiles.staging.url`
+    pub key: K,
+
+    /// The profile of the setting, if any
+    pub profile: Option<ProfileName>,
+}
+
+/// The configuration keys that can be used with profiles
+pub trait ConfigKey: FromStr<Err = ParseKeyError> + Copy {
+    fn as_str(self) -> &'static str;
+}
+
+impl ConfigKey for ReadableKey {
+    fn as_str(self) -> &'static str {
+        ReadableKey::as_str(self)
+    }
+}
+
+impl ConfigKey for WritableKey {
+    fn as_str(self) -> &'static str {
+        WritableKey::as_str(self)
+    }
+}
+
+impl<K: ConfigKey> ProfiledKey<K> {
+    /// The cloud and the name of the profile, if the key is a profile key
+    pub fn profile(&self) -> Option<(CloudType, &ProfileName)> {
+        let profile = self.profile.as_ref()?;
+        let cloud = CloudType::of_key(self.key.as_str())?;
+        Some((cloud, profile))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseProfiledKeyError {
+    #[error(transparent)]
+    Key(#[from] ParseKeyError),
+
+    #[error(transparent)]
+    ProfileName(#[from] crate::InvalidProfileName),
+
+    #[error("Unknown key: '{0}'. Only the cloud settings can be set for a profile")]
+    NotACloudKey(String),
+}
+
+impl<K: ConfigKey> FromStr for ProfiledKey<K> {
+    type Err = ParseProfiledKeyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut segments = value.splitn(4, '.');
+        match (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) {
+            (Some(cloud), Some("profiles"), Some(profile), Some(key)) => {
+                let profile = profile.parse()?;
+                let key: K = format!("{cloud}.{key}").parse()?;
+                if CloudType::of_key(key.as_str()).is_none() {
+                    return Err(ParseProfiledKeyError::NotACloudKey(value.to_string()));
+                }
+                Ok(ProfiledKey {
+                    key,
+                    profile: Some(profile),
+                })
+            }
+            _ => Ok(ProfiledKey {
+                key: value.parse()?,
+                profile: None,
+            }),
+        }
+    }
+}
+
+impl<K: ConfigKey> fmt::Display for ProfiledKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = self.key.as_str();
+        match (&self.profile, key.split_once('.')) {
+            (Some(profile), Some((cloud, key))) => write!(f, "{cloud}.profiles.{profile}.{key}"),
+            _ => key.fmt(f),
+        }
+    }
+}
+
+impl<K: ConfigKey> From<K> for ProfiledKey<K> {
+    fn from(key: K) -> Self {
+        ProfiledKey { key, profile: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TEdgeConfigLocation;
+
+    #[test]
+    fn parse_profile_keys() {
+        let key: ProfiledKey<WritableKey> = "c8y.profiles.staging.url".parse().unwrap();
+        assert_eq!(key.key, WritableKey::C8yUrl);
+        assert_eq!(key.profile, Some("staging".parse().unwrap()));
+        assert_eq!(key.to_string(), "c8y.profiles.staging.url");
+
+        let key: ProfiledKey<ReadableKey> =
+            "az.profiles.hub-2.bridge.topic_prefix".parse().unwrap();
+        assert_eq!(key.key, ReadableKey::AzBridgeTopicPrefix);
+        assert_eq!(key.to_string(), "az.profiles.hub-2.bridge.topic_prefix");
+    }
+
+    #[test]
+    fn parse_keys_without_profile() {
+        let key: ProfiledKey<WritableKey> = "c8y.url".parse().unwrap();
+        assert_eq!(key, ProfiledKey::from(WritableKey::C8yUrl));
+        assert_eq!(key.to_string(), "c8y.url");
+    }
+
+    #[test]
+    fn reject_invalid_profile_keys() {
+        assert!("c8y.profiles.staging.unknown"
+            .parse::<ProfiledKey<WritableKey>>()
+            .is_err());
+        assert!("c8y.profiles.a@b.url"
+            .parse::<ProfiledKey<WritableKey>>()
+            .is_err());
+        assert!("mqtt.profiles.staging.bind.port"
+            .parse::<ProfiledKey<WritableKey>>()
+            .is_err());
+    }
+
+    #[test]
+    fn profile_settings_replace_the_cloud_settings() {
+        let mut dto = TEdgeConfigDto::default();
+        dto.try_update_str(WritableKey::C8yUrl, "prod.example.com")
+            .unwrap();
+        dto.try_update_str(WritableKey::C8yProxyBindPort, "8001")
+            .unwrap();
+        let staging = "c8y.profiles.staging.url".parse().unwrap();
+        dto.try_update_profiled_str(&staging, "staging.example.com")
+            .unwrap();
+
+        let config = TEdgeConfig::from_dto(&dto, &TEdgeConfigLocation::default());
+        assert_eq!(
+            config.read_string(ReadableKey::C8yUrl).unwrap(),
+            "prod.example.com"
+        );
+        assert_eq!(config.c8y.bridge.topic_prefix.as_str(), "c8y");
+
+        let profile = config
+            .profile(CloudType::C8y, &"staging".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            profile.read_string(ReadableKey::C8yUrl).unwrap(),
+            "staging.example.com"
+        );
+        assert_eq!(profile.c8y.bridge.topic_prefix.as_str(), "c8y-staging");
+        // The settings that are not set for the profile take their default value
+        assert_eq!(profile.c8y.proxy.bind.port, 8001);
+    }
+
+    #[test]
+    fn the_mqtt_clients_of_a_profile_use_the_topic_prefix_of_the_profile() {
+        let mut dto = TEdgeConfigDto::default();
+        let staging = "c8y.profiles.staging.url".parse().unwrap();
+        dto.try_update_profiled_str(&staging, "staging.example.com")
+            .unwrap();
+        let config = TEdgeConfig::from_dto(&dto, &TEdgeConfigLocation::default());
+        let translation = config.mqtt_config().unwrap().topic_prefix_translation;
+        assert!(translation.is_empty());
+
+        let profile = config
+            .profile(CloudType::C8y, &"staging".parse().unwrap())
+            .unwrap();
+        let translation = profile.mqtt_config().unwrap().topic_prefix_translation;
+        assert_eq!(translation.to_broker("c8y/s/us"), "c8y-staging/s/us");
+        assert_eq!(
+            translation.to_broker("te/device/main/service/mosquitto-c8y-bridge/status/health"),
+            "te/device/main/service/mosquitto-c8y-staging-bridge/status/health"
+        );
+        assert_eq!(
+            translation.to_broker("az/messages/events/"),
+            "az/messages/events/"
+        );
+    }
+
+    #[test]
+    fn read_profile_settings() {
+        let mut dto = TEdgeConfigDto::default();
+        let staging = "c8y.profiles.staging.url".parse().unwrap();
+        dto.try_update_profiled_str(&staging, "staging.example.com")
+            .unwrap();
+        let config = TEdgeConfig::from_dto(&dto, &TEdgeConfigLocation::default());
+
+        let key = "c8y.profiles.staging.url".parse().unwrap();
+        assert_eq!(
+            config.read_profiled_string(&key).unwrap(),
+            "staging.example.com"
+        );
+        let key = "c8y.profiles.unknown.url".parse().unwrap();
+        assert!(matches!(
+            config.read_profiled_string(&key),
+            Err(ReadError::UnknownProfile(_))
+        ));
+        assert_eq!(
+            config.profile_names(CloudType::C8y),
+            vec!["staging".parse::<ProfileName>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn unsetting_all_the_settings_of_a_profile_removes_the_profile() {
+        let mut dto = TEdgeConfigDto::default();
+        let staging = "c8y.profiles.staging.url".parse().unwrap();
+        dto.try_update_profiled_str(&staging, "staging.example.com")
+            .unwrap();
+        assert!(dto.c8y.profiles.is_some());
+
+        dto.unset_profiled_key(&staging);
+        assert_eq!(dto.c8y.profiles, None);
+    }
+}
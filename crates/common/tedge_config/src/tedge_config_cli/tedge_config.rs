@@ -3,20 +3,26 @@ use crate::AptConfig;
 use crate::AutoFlag;
 use crate::ConnectUrl;
 use crate::HostPort;
+use crate::ProfileName;
 use crate::Seconds;
 use crate::TEdgeConfigLocation;
 use crate::TemplatesSet;
+use crate::TopicPrefix;
 use crate::HTTPS_PORT;
 use crate::MQTT_TLS_PORT;
 use anyhow::anyhow;
 use anyhow::Context;
+use camino::Utf8Path;
 use camino::Utf8PathBuf;
+use certificate::parse_root_certificate::create_tls_config_with_native_roots;
 use certificate::CertificateError;
+use certificate::CryptokiConfig;
+use certificate::KeyProvider;
 use certificate::PemCertificate;
 use doku::Document;
 use once_cell::sync::Lazy;
 use std::borrow::Cow;
-use std::io::Read;
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::num::NonZeroU16;
@@ -42,19 +48,36 @@ impl<T> OptionalConfigError<T> for OptionalConfig<T> {
     }
 }
 
-pub struct TEdgeConfig(TEdgeConfigReader);
+pub struct TEdgeConfig {
+    reader: TEdgeConfigReader,
+    // Kept to build the configuration of the cloud profiles
+    dto: TEdgeConfigDto,
+    location: TEdgeConfigLocation,
+}
 
 impl std::ops::Deref for TEdgeConfig {
     type Target = TEdgeConfigReader;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.reader
     }
 }
 
 impl TEdgeConfig {
     pub fn from_dto(dto: &TEdgeConfigDto, location: &TEdgeConfigLocation) -> Self {
-        Self(TEdgeConfigReader::from_dto(dto, location))
+        Self {
+            reader: TEdgeConfigReader::from_dto(dto, location),
+            dto: dto.clone(),
+            location: location.clone(),
+        }
+    }
+
+    pub(crate) fn dto(&self) -> &TEdgeConfigDto {
+        &self.dto
+    }
+
+    pub(crate) fn location(&self) -> &TEdgeConfigLocation {
+        &self.location
     }
 
     pub fn mqtt_config(&self) -> Result<mqtt_channel::Config, CertificateError> {
@@ -80,12 +103,81 @@ impl TEdgeConfig {
             self.mqtt.client.auth.cert_file.as_ref(),
             self.mqtt.client.auth.key_file.as_ref(),
         )) {
-            mqtt_config.with_client_auth(client_cert, client_key)?;
+            mqtt_config.with_client_auth_key(client_cert, &self.key_provider(client_key)?)?;
+        }
+
+        // The cloud topics are exchanged under the configured topic prefixes,
+        // along with the health status of the corresponding bridges
+        let root = &self.mqtt.topic_root;
+        for (cloud, topic_prefix) in [
+            ("c8y", &self.c8y.bridge.topic_prefix),
+            ("az", &self.az.bridge.topic_prefix),
+            ("aws", &self.aws.bridge.topic_prefix),
+        ] {
+            if topic_prefix.as_str() != cloud {
+                mqtt_config = mqtt_config
+                    .with_translated_topic_prefix(cloud, topic_prefix.as_str())
+                    .with_translated_topic_prefix(
+                        format!("{root}/device/main/service/mosquitto-{cloud}-bridge"),
+                        format!("{root}/device/main/service/mosquitto-{topic_prefix}-bridge"),
+                    );
+            }
         }
 
         Ok(mqtt_config)
     }
 
+    /// How to access the PKCS#11 token holding the private keys, if any
+    pub fn cryptoki_config(&self) -> Option<CryptokiConfig> {
+        self.cryptoki
+            .module_path
+            .or_none()
+            .map(|module_path| CryptokiConfig {
+                module_path: module_path.clone().into(),
+                pin: self.cryptoki.pin.or_none().cloned(),
+            })
+    }
+
+    /// The private key configured with the given path, either a PEM file or a PKCS#11 URI
+    pub fn key_provider(&self, key_path: &Utf8Path) -> Result<KeyProvider, CertificateError> {
+        KeyProvider::new(key_path, self.cryptoki_config().as_ref())
+    }
+
+    /// The private key configured by an optional key path setting, either a PEM file or a PKCS#11 URI
+    pub fn optional_key_provider(
+        &self,
+        key_path: &OptionalConfig<Utf8PathBuf>,
+    ) -> Result<OptionalConfig<KeyProvider>, CertificateError> {
+        Ok(match key_path.or_none() {
+            Some(path) => OptionalConfig::present(self.key_provider(path)?, key_path.key()),
+            None => OptionalConfig::empty(key_path.key()),
+        })
+    }
+
+    /// The TLS config of the HTTP clients, when a client certificate is set by `http.client.auth`
+    ///
+    /// The private key can be a PEM file or a PKCS#11 URI, the system root certificates being trusted.
+    pub fn http_client_tls_config(&self) -> anyhow::Result<Option<rustls::ClientConfig>> {
+        use ReadableKey::*;
+
+        let client_cert_key = all_or_nothing((
+            self.http.client.auth.cert_file.as_ref(),
+            self.http.client.auth.key_file.as_ref(),
+        ))
+        .map_err(|e| anyhow!("{e}"))?;
+
+        let Some((cert, key)) = client_cert_key else {
+            return Ok(None);
+        };
+        let key = self.key_provider(key).with_context(|| {
+            format!("reading private key (from {HttpClientAuthKeyFile}): {key}")
+        })?;
+        let tls_config = create_tls_config_with_native_roots(&key, cert).with_context(|| {
+            format!("reading certificate (from {HttpClientAuthCertFile}): {cert}")
+        })?;
+        Ok(Some(tls_config))
+    }
+
     pub fn mqtt_client_auth_config(&self) -> MqttAuthConfig {
         let mut client_auth = MqttAuthConfig {
             ca_dir: self.mqtt.client.auth.ca_dir.or_none().cloned(),
@@ -330,6 +422,8 @@ define_tedge_config! {
 
         /// Path where the device's private key is stored
         #[tedge_config(example = "/etc/tedge/device-certs/tedge-private-key.pem", default(function = "default_device_key"))]
+        #[tedge_config(example = "pkcs11:token=tedge;object=device-key")]
+        #[tedge_config(note = "This can also be the PKCS#11 URI of a key held by the token accessed with `cryptoki.module_path`.")]
         #[doku(as = "PathBuf")]
         key_path: Utf8PathBuf,
 
@@ -338,12 +432,56 @@ define_tedge_config! {
         #[doku(as = "PathBuf")]
         cert_path: Utf8PathBuf,
 
+        /// Path where the device's certificate signing request is stored
+        #[tedge_config(example = "/etc/tedge/device-certs/tedge.csr", default(function = "default_device_csr"))]
+        #[doku(as = "PathBuf")]
+        csr_path: Utf8PathBuf,
+
         /// The default device type
         #[tedge_config(example = "thin-edge.io", default(value = "thin-edge.io"))]
         #[tedge_config(rename = "type")]
         ty: String,
     },
 
+    certificate: {
+        renewal: {
+            /// Whether the tedge-agent renews the device certificate before it expires
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// The URL of the EST server (RFC 7030) used to renew the device certificate
+            #[tedge_config(example = "https://est.example.com:8443")]
+            #[tedge_config(note = "The certificate is re-enrolled using the `/.well-known/est/simplereenroll` endpoint of this server.")]
+            est_url: String,
+
+            /// The path of the root certificate(s) trusted to authenticate the EST server
+            #[tedge_config(example = "/etc/tedge/est-root-ca.pem")]
+            #[tedge_config(note = "The value can be a directory path as well as the path of a certificate file. When not set, the system root certificates are trusted.")]
+            #[doku(as = "PathBuf")]
+            est_root_cert_path: Utf8PathBuf,
+
+            /// The interval in seconds between two checks of the device certificate expiry
+            #[tedge_config(example = "86400", default(value = 86400_u64))]
+            check_interval: Seconds,
+
+            /// How long in seconds before its expiry the device certificate is renewed
+            #[tedge_config(example = "2592000", default(value = 2592000_u64))]
+            renew_before: Seconds,
+        },
+    },
+
+    cryptoki: {
+        /// Path to the PKCS#11 module used to access the private keys held by a cryptographic token
+        #[tedge_config(example = "/usr/lib/softhsm/libsofthsm2.so")]
+        #[tedge_config(note = "A private key is held by the token when its path is a PKCS#11 URI, e.g. `pkcs11:token=tedge;object=device-key`.")]
+        #[doku(as = "PathBuf")]
+        module_path: Utf8PathBuf,
+
+        /// The user PIN of the cryptographic token
+        #[tedge_config(example = "123456")]
+        pin: String,
+    },
+
     c8y: {
         /// Endpoint URL of Cumulocity tenant
         #[tedge_config(example = "your-tenant.cumulocity.com")]
@@ -443,7 +581,12 @@ define_tedge_config! {
                 #[tedge_config(note = "If set to 'auto', this cleans the local session accordingly the detected version of mosquitto.")]
                 #[tedge_config(example = "auto", default(variable = "AutoFlag::Auto"))]
                 local_cleansession: AutoFlag,
-            }
+            },
+
+            /// The prefix of the local MQTT topics forwarded to and from Cumulocity
+            #[tedge_config(note = "The topic prefix of a connection profile defaults to `c8y-<profile>`.")]
+            #[tedge_config(example = "c8y", default(function = "default_c8y_topic_prefix"))]
+            topic_prefix: TopicPrefix,
         },
 
         entity_store: {
@@ -451,6 +594,24 @@ define_tedge_config! {
             #[tedge_config(example = "true", default(value = true))]
             auto_register: bool,
         },
+
+        store_forward: {
+            /// Whether the cloud-bound messages are spooled on disk while the Cumulocity bridge is down
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// The maximum number of messages spooled while the Cumulocity bridge is down
+            #[tedge_config(example = "10000", default(value = 10000u32))]
+            max_messages: u32,
+
+            /// The maximum number of spooled messages replayed per second when the Cumulocity bridge is up again (0 for no limit)
+            #[tedge_config(example = "100", default(value = 100u32))]
+            replay_rate: u32,
+        },
+
+        /// Named connection profiles, each profile having the same settings as `c8y`, e.g. `c8y.profiles.<name>.url`
+        #[tedge_config(reader(skip))]
+        profiles: BTreeMap<ProfileName, TEdgeConfigDtoC8y>,
     },
 
     #[tedge_config(deprecated_name = "azure")] // for 0.1.0 compatibility
@@ -480,8 +641,43 @@ define_tedge_config! {
 
         /// Set of MQTT topics the Azure IoT mapper should subscribe to
         #[tedge_config(example = "te/+/+/+/+/a/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+")]
-        #[tedge_config(default(value = "te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+,te/+/+/+/+/status/health"))]
+        #[tedge_config(default(value = "te/+/+/+/+,te/+/+/+/+/twin/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+,te/+/+/+/+/status/health"))]
         topics: TemplatesSet,
+
+        entity_store: {
+            /// Enable auto registration feature
+            #[tedge_config(example = "true", default(value = true))]
+            auto_register: bool,
+
+            /// Whether the child devices and services are reported in the device twin of the main device
+            #[tedge_config(example = "true", default(value = false))]
+            report_in_twin: bool,
+        },
+
+        store_forward: {
+            /// Whether the cloud-bound messages are spooled on disk while the Azure IoT bridge is down
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// The maximum number of messages spooled while the Azure IoT bridge is down
+            #[tedge_config(example = "10000", default(value = 10000u32))]
+            max_messages: u32,
+
+            /// The maximum number of spooled messages replayed per second when the Azure IoT bridge is up again (0 for no limit)
+            #[tedge_config(example = "100", default(value = 100u32))]
+            replay_rate: u32,
+        },
+
+        bridge: {
+            /// The prefix of the local MQTT topics forwarded to and from Azure IoT
+            #[tedge_config(note = "The topic prefix of a connection profile defaults to `az-<profile>`.")]
+            #[tedge_config(example = "az", default(function = "default_az_topic_prefix"))]
+            topic_prefix: TopicPrefix,
+        },
+
+        /// Named connection profiles, each profile having the same settings as `az`, e.g. `az.profiles.<name>.url`
+        #[tedge_config(reader(skip))]
+        profiles: BTreeMap<ProfileName, TEdgeConfigDtoAz>,
     },
 
     aws: {
@@ -510,8 +706,33 @@ define_tedge_config! {
 
         /// Set of MQTT topics the AWS IoT mapper should subscribe to
         #[tedge_config(example = "te/+/+/+/+/a/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+")]
-        #[tedge_config(default(value = "te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+,te/+/+/+/+/status/health"))]
+        #[tedge_config(default(value = "te/+/+/+/+,te/+/+/+/+/twin/+,te/+/+/+/+/m/+,te/+/+/+/+/e/+,te/+/+/+/+/a/+,te/+/+/+/+/status/health"))]
         topics: TemplatesSet,
+
+        store_forward: {
+            /// Whether the cloud-bound messages are spooled on disk while the AWS IoT bridge is down
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// The maximum number of messages spooled while the AWS IoT bridge is down
+            #[tedge_config(example = "10000", default(value = 10000u32))]
+            max_messages: u32,
+
+            /// The maximum number of spooled messages replayed per second when the AWS IoT bridge is up again (0 for no limit)
+            #[tedge_config(example = "100", default(value = 100u32))]
+            replay_rate: u32,
+        },
+
+        bridge: {
+            /// The prefix of the local MQTT topics forwarded to and from AWS IoT
+            #[tedge_config(note = "The topic prefix of a connection profile defaults to `aws-<profile>`.")]
+            #[tedge_config(example = "aws", default(function = "default_aws_topic_prefix"))]
+            topic_prefix: TopicPrefix,
+        },
+
+        /// Named connection profiles, each profile having the same settings as `aws`, e.g. `aws.profiles.<name>.url`
+        #[tedge_config(reader(skip))]
+        profiles: BTreeMap<ProfileName, TEdgeConfigDtoAws>,
     },
 
     mqtt: {
@@ -692,6 +913,20 @@ define_tedge_config! {
             #[tedge_config(example = "true", default(value = true))]
             log_upload: bool,
         },
+
+        measurements: {
+            /// Whether the tedge-agent stores the measurements locally, to be queried over HTTP
+            #[tedge_config(example = "true", default(value = false))]
+            store: bool,
+
+            /// The maximum size in kilobytes of the local measurement store
+            #[tedge_config(example = "10240", default(value = 10240u32))]
+            max_size: u32,
+
+            /// The maximum age in seconds of the measurements kept in the local measurement store
+            #[tedge_config(example = "86400", default(value = 86400_u64))]
+            max_age: Seconds,
+        },
     },
 
     software: {
@@ -840,6 +1075,25 @@ fn default_device_cert(location: &TEdgeConfigLocation) -> Utf8PathBuf {
         .join("tedge-certificate.pem")
 }
 
+fn default_device_csr(location: &TEdgeConfigLocation) -> Utf8PathBuf {
+    location
+        .tedge_config_root_path()
+        .join("device-certs")
+        .join("tedge.csr")
+}
+
+fn default_c8y_topic_prefix() -> TopicPrefix {
+    TopicPrefix::try_from("c8y".to_string()).unwrap()
+}
+
+fn default_az_topic_prefix() -> TopicPrefix {
+    TopicPrefix::try_from("az".to_string()).unwrap()
+}
+
+fn default_aws_topic_prefix() -> TopicPrefix {
+    TopicPrefix::try_from("aws".to_string()).unwrap()
+}
+
 fn default_mqtt_port() -> NonZeroU16 {
     NonZeroU16::try_from(1883).unwrap()
 }
@@ -857,6 +1111,9 @@ pub enum ReadError {
 
     #[error("Derivation for `{key}` failed: {cause}")]
     DerivationFailed { key: &'static str, cause: String },
+
+    #[error(transparent)]
+    UnknownProfile(#[from] crate::UnknownProfile),
 }
 
 /// An abstraction over the possible default functions for tedge config values
@@ -921,33 +1178,6 @@ pub struct MqttAuthClientConfig {
     pub key_file: Utf8PathBuf,
 }
 
-impl TEdgeConfigReaderHttpClientAuth {
-    pub fn identity(&self) -> anyhow::Result<Option<reqwest::Identity>> {
-        use ReadableKey::*;
-
-        let client_cert_key =
-            crate::all_or_nothing((self.cert_file.as_ref(), self.key_file.as_ref()))
-                .map_err(|e| anyhow!("{e}"))?;
-
-        Ok(match client_cert_key {
-            Some((cert, key)) => {
-                let mut pem = std::fs::read(key).with_context(|| {
-                    format!("reading private key (from {HttpClientAuthKeyFile}): {key}")
-                })?;
-                let mut cert_file = std::fs::File::open(cert).with_context(|| {
-                    format!("opening certificate (from {HttpClientAuthCertFile}): {cert}")
-                })?;
-                cert_file.read_to_end(&mut pem).with_context(|| {
-                    format!("reading certificate (from {HttpClientAuthCertFile}): {cert}")
-                })?;
-
-                Some(reqwest::Identity::from_pem(&pem)?)
-            }
-            None => None,
-        })
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,7 +49,7 @@ pub fn generate(
     }
 
     quote! {
-        #[derive(Debug, Default, ::serde::Deserialize, ::serde::Serialize, PartialEq)]
+        #[derive(Debug, Default, Clone, ::serde::Deserialize, ::serde::Serialize, PartialEq)]
         // We will add more configurations in the future, so this is
         // non_exhaustive (see
         // https://doc.rust-lang.org/reference/attributes/type_system.html)
@@ -48,7 +48,7 @@ fn generate_structs(
 
     for item in items {
         match item {
-            FieldOrGroup::Field(field) => {
+            FieldOrGroup::Field(field) if !field.reader().skip => {
                 let ty = field.ty();
                 attrs.push(field.attrs().to_vec());
                 idents.push(field.ident());
@@ -85,7 +85,7 @@ fn generate_structs(
                     false => parse_quote!(pub),
                 });
             }
-            FieldOrGroup::Group(_) => {
+            FieldOrGroup::Field(_) | FieldOrGroup::Group(_) => {
                 // Skipped
             }
         }
@@ -318,7 +318,7 @@ fn generate_conversions(
 
     for item in items {
         match item {
-            FieldOrGroup::Field(field) => {
+            FieldOrGroup::Field(field) if !field.reader().skip => {
                 let name = field.ident();
                 let value = reader_value_for_field(field, &parents, root_fields, Vec::new())?;
                 field_conversions.push(quote!(#name: #value));
@@ -334,7 +334,7 @@ fn generate_conversions(
                     generate_conversions(&sub_reader_name, &group.contents, parents, root_fields)?;
                 rest.push(sub_conversions);
             }
-            FieldOrGroup::Group(_) => {
+            FieldOrGroup::Field(_) | FieldOrGroup::Group(_) => {
                 // Skipped
             }
         }
@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use tedge_config_macros::*;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadError {
+    #[error(transparent)]
+    ConfigNotSet(#[from] ConfigNotSet),
+}
+
+define_tedge_config! {
+    c8y: {
+        #[tedge_config(example = "example.cumulocity.com")]
+        url: String,
+
+        #[tedge_config(reader(skip))]
+        profiles: BTreeMap<String, TEdgeConfigDtoC8y>,
+    }
+}
+
+#[test]
+fn fields_skipped_by_the_reader_are_stored_in_the_dto() {
+    let dto: TEdgeConfigDto = toml::from_str(
+        r#"
+        [c8y]
+        url = "prod.cumulocity.com"
+        [c8y.profiles.staging]
+        url = "staging.cumulocity.com"
+        "#,
+    )
+    .unwrap();
+
+    let profiles = dto.c8y.profiles.as_ref().unwrap();
+    assert_eq!(
+        profiles["staging"].url.as_deref(),
+        Some("staging.cumulocity.com")
+    );
+
+    let config = TEdgeConfigReader::from_dto(&dto, &TEdgeConfigLocation);
+    assert_eq!(
+        config.c8y.url.or_none().map(String::as_str),
+        Some("prod.cumulocity.com")
+    );
+}
+
+#[test]
+fn fields_skipped_by_the_reader_have_no_configuration_key() {
+    assert!("c8y.profiles".parse::<ReadableKey>().is_err());
+    assert!("c8y.profiles".parse::<WritableKey>().is_err());
+}
@@ -1,6 +1,13 @@
 use mqtt_channel::Message;
+use mqtt_channel::Topic;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
 use thiserror::Error;
 
+/// The fields copied into each part of a measurement split to fit under the threshold
+pub const MEASUREMENT_COMMON_FIELDS: &[&str] = &["time"];
+
 #[derive(Debug)]
 pub struct SizeThreshold(pub usize);
 
@@ -14,6 +21,145 @@ impl SizeThreshold {
             Ok(())
         }
     }
+
+    /// Build the messages for a measurement, spreading the series over several messages if too large
+    ///
+    /// A too large payload that is not a JSON object cannot be split and is rejected.
+    pub fn split_measurement(
+        &self,
+        topic: &Topic,
+        payload: String,
+    ) -> Result<Vec<Message>, SizeThresholdExceededError> {
+        if payload.len() <= self.0 {
+            return Ok(vec![Message::new(topic, payload)]);
+        }
+        let Ok(measurement) = serde_json::from_str::<Map<String, Value>>(&payload) else {
+            return Err(SizeThresholdExceededError {
+                size: payload.len(),
+                threshold: self.0,
+            });
+        };
+        let parts = self.split_json_object(&measurement, MEASUREMENT_COMMON_FIELDS)?;
+        Ok(parts
+            .into_iter()
+            .map(|part| Message::new(topic, part))
+            .collect())
+    }
+
+    /// Build the messages for a payload, splitting the payload into chunks if too large
+    ///
+    /// See [SizeThreshold::chunk_payload] for the format of the chunks.
+    pub fn chunk(
+        &self,
+        topic: &Topic,
+        chunk_id: &str,
+        payload: String,
+    ) -> Result<Vec<Message>, SizeThresholdExceededError> {
+        if payload.len() <= self.0 {
+            return Ok(vec![Message::new(topic, payload)]);
+        }
+        let chunks = self.chunk_payload(chunk_id, &payload)?;
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| Message::new(topic, chunk))
+            .collect())
+    }
+
+    /// Split a JSON object into several JSON objects, each under the threshold once serialized
+    ///
+    /// The `common` fields (e.g. `time` or `type`) are copied into each part,
+    /// while the other fields are spread over the parts, in order.
+    /// A field is never split: an error is returned if a single field doesn't fit under the threshold.
+    pub fn split_json_object(
+        &self,
+        object: &Map<String, Value>,
+        common: &[&str],
+    ) -> Result<Vec<String>, SizeThresholdExceededError> {
+        let threshold = self.0;
+        let mut base = Map::new();
+        for key in common {
+            if let Some(value) = object.get(*key) {
+                base.insert(key.to_string(), value.clone());
+            }
+        }
+        let base_size = json_size(&Value::Object(base.clone()));
+
+        let mut parts = Vec::new();
+        let mut part = base.clone();
+        let mut part_size = base_size;
+        for (key, value) in object.iter() {
+            if common.contains(&key.as_str()) {
+                continue;
+            }
+            // A field adds `"key":value` plus a separating comma
+            let field_size = json_size(&json!(key)) + 1 + json_size(value) + 1;
+            if base_size + field_size > threshold {
+                return Err(SizeThresholdExceededError {
+                    size: base_size + field_size,
+                    threshold,
+                });
+            }
+            if part.len() > base.len() && part_size + field_size > threshold {
+                parts.push(Value::Object(part).to_string());
+                part = base.clone();
+                part_size = base_size;
+            }
+            part.insert(key.clone(), value.clone());
+            part_size += field_size;
+        }
+        if part.len() > base.len() || parts.is_empty() {
+            parts.push(Value::Object(part).to_string());
+        }
+        Ok(parts)
+    }
+
+    /// Split a payload into chunks, each wrapped into a JSON envelope under the threshold
+    ///
+    /// ```json
+    /// {"chunk":{"id":"<id>","index":0,"count":3},"data":"<part of the payload>"}
+    /// ```
+    ///
+    /// The original payload is the concatenation of the `data` of the chunks sharing the same `id`,
+    /// ordered by `index`.
+    pub fn chunk_payload(
+        &self,
+        id: &str,
+        payload: &str,
+    ) -> Result<Vec<String>, SizeThresholdExceededError> {
+        let threshold = self.0;
+        // The envelope size, assuming the index and count can have as many digits as the payload size
+        let max_digits = payload.len().to_string().len();
+        let overhead = json_size(&envelope(id, 0, 0, "")) - 2 + 2 * max_digits;
+        let min_size = overhead + MAX_ESCAPED_CHAR_SIZE;
+        if threshold < min_size {
+            return Err(SizeThresholdExceededError {
+                size: min_size,
+                threshold,
+            });
+        }
+        let data_threshold = threshold - overhead;
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut data_size = 0;
+        for (position, c) in payload.char_indices() {
+            let char_size = escaped_char_size(c);
+            if data_size + char_size > data_threshold {
+                chunks.push(&payload[start..position]);
+                start = position;
+                data_size = 0;
+            }
+            data_size += char_size;
+        }
+        chunks.push(&payload[start..]);
+
+        let count = chunks.len();
+        Ok(chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| envelope(id, index, count, data).to_string())
+            .collect())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -22,3 +168,133 @@ pub struct SizeThresholdExceededError {
     pub size: usize,
     pub threshold: usize,
 }
+
+/// The maximum size of a char once escaped in a JSON string, e.g. `\u001f`
+const MAX_ESCAPED_CHAR_SIZE: usize = 6;
+
+fn envelope(id: &str, index: usize, count: usize, data: &str) -> Value {
+    json!({
+        "chunk": { "id": id, "index": index, "count": count },
+        "data": data,
+    })
+}
+
+fn json_size(value: &Value) -> usize {
+    value.to_string().len()
+}
+
+fn escaped_char_size(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{08}' | '\u{0c}' => 2,
+        c if c < '\u{20}' => MAX_ESCAPED_CHAR_SIZE,
+        c => c.len_utf8(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_json_object_keeping_common_fields() {
+        let object = json!({
+            "time": "2023-10-18T10:00:00Z",
+            "temperature": 21.5,
+            "pressure": 1013.2,
+            "humidity": 45,
+        });
+        let object = object.as_object().unwrap();
+
+        let parts = SizeThreshold(60)
+            .split_json_object(object, &["time"])
+            .unwrap();
+        let parts: Vec<Value> = parts
+            .iter()
+            .map(|part| {
+                assert!(part.len() <= 60, "{part} exceeds the threshold");
+                serde_json::from_str(part).unwrap()
+            })
+            .collect();
+        assert!(parts.len() > 1);
+
+        let mut merged = Map::new();
+        for part in parts {
+            assert_eq!(part["time"], "2023-10-18T10:00:00Z");
+            merged.extend(part.as_object().unwrap().clone());
+        }
+        assert_eq!(&merged, object);
+    }
+
+    #[test]
+    fn a_small_json_object_is_not_split() {
+        let object = json!({"time": 1697623200, "temperature": 21.5});
+        let parts = SizeThreshold(1024)
+            .split_json_object(object.as_object().unwrap(), &["time"])
+            .unwrap();
+        assert_eq!(parts, vec![object.to_string()]);
+    }
+
+    #[test]
+    fn a_json_field_is_never_split() {
+        let object = json!({"time": 1697623200, "location": {"x": 1.0, "y": 2.0, "z": 3.0}});
+        assert!(SizeThreshold(30)
+            .split_json_object(object.as_object().unwrap(), &["time"])
+            .is_err());
+    }
+
+    #[test]
+    fn split_measurement_messages() {
+        let topic = Topic::new_unchecked("aws/td/main/m/");
+        let measurement = json!({
+            "time": "2023-10-18T10:00:00Z",
+            "temperature": 21.5,
+            "pressure": 1013.2,
+        })
+        .to_string();
+
+        let messages = SizeThreshold(1024)
+            .split_measurement(&topic, measurement.clone())
+            .unwrap();
+        assert_eq!(messages, vec![Message::new(&topic, measurement.clone())]);
+
+        let messages = SizeThreshold(50)
+            .split_measurement(&topic, measurement)
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        for message in messages {
+            assert_eq!(message.topic, topic);
+            assert!(message
+                .payload_str()
+                .unwrap()
+                .contains("2023-10-18T10:00:00Z"));
+        }
+
+        // A payload that is not a JSON object cannot be split
+        assert!(SizeThreshold(10)
+            .split_measurement(&topic, "not a measurement".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn chunk_payload_under_threshold() {
+        let payload = r#"{"text":"a \"long\" event","details":"é\n"}"#.repeat(10);
+        let chunks = SizeThreshold(100).chunk_payload("1234", &payload).unwrap();
+        assert!(chunks.len() > 1);
+
+        let mut data = String::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.len() <= 100, "{chunk} exceeds the threshold");
+            let chunk: Value = serde_json::from_str(chunk).unwrap();
+            assert_eq!(chunk["chunk"]["id"], "1234");
+            assert_eq!(chunk["chunk"]["index"], index);
+            assert_eq!(chunk["chunk"]["count"], chunks.len());
+            data.push_str(chunk["data"].as_str().unwrap());
+        }
+        assert_eq!(data, payload);
+    }
+
+    #[test]
+    fn chunk_payload_requires_room_for_the_envelope() {
+        assert!(SizeThreshold(10).chunk_payload("1234", "payload").is_err());
+    }
+}
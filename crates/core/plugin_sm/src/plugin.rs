@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use csv::ReaderBuilder;
 use download::Downloader;
 use logged_command::LoggedCommand;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use serde::Deserialize;
 use std::error::Error;
 use std::path::Path;
@@ -77,7 +77,7 @@ pub trait Plugin {
         }
     }
 
-    fn identity(&self) -> Option<&Identity>;
+    fn identity(&self) -> Option<&ClientConfig>;
 
     async fn apply_all(
         &self,
@@ -150,7 +150,7 @@ pub trait Plugin {
         url: &DownloadInfo,
         logger: &mut BufWriter<File>,
         download_path: &Path,
-        identity: Option<&Identity>,
+        identity: Option<&ClientConfig>,
     ) -> Result<(), SoftwareError> {
         let downloader =
             Self::download_from_url(module, url, logger, download_path, identity).await?;
@@ -165,7 +165,7 @@ pub trait Plugin {
         url: &DownloadInfo,
         logger: &mut BufWriter<File>,
         download_path: &Path,
-        identity: Option<&Identity>,
+        identity: Option<&ClientConfig>,
     ) -> Result<Downloader, SoftwareError> {
         let sm_path = sm_path(&module.name, &module.version, download_path);
         let downloader = Downloader::new(sm_path, identity.map(|id| id.to_owned()));
@@ -237,7 +237,7 @@ pub struct ExternalPluginCommand {
     pub path: PathBuf,
     pub sudo: Option<PathBuf>,
     pub max_packages: u32,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl ExternalPluginCommand {
@@ -246,7 +246,7 @@ impl ExternalPluginCommand {
         path: impl Into<PathBuf>,
         sudo: Option<PathBuf>,
         max_packages: u32,
-        identity: Option<Identity>,
+        identity: Option<ClientConfig>,
     ) -> ExternalPluginCommand {
         ExternalPluginCommand {
             name: name.into(),
@@ -516,7 +516,7 @@ impl Plugin for ExternalPluginCommand {
         }
     }
 
-    fn identity(&self) -> Option<&Identity> {
+    fn identity(&self) -> Option<&ClientConfig> {
         self.identity.as_ref()
     }
 }
@@ -200,7 +200,7 @@ impl ExternalPlugins {
 
                 if let Some(file_name) = path.file_name() {
                     if let Some(plugin_name) = file_name.to_str() {
-                        let identity = config.http.client.auth.identity()?;
+                        let identity = config.http_client_tls_config()?;
                         let plugin = ExternalPluginCommand::new(
                             plugin_name,
                             &path,
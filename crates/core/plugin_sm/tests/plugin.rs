@@ -239,7 +239,7 @@ mod tests {
             &dummy_plugin_path,
             None,
             config.software.plugin.max_packages,
-            config.http.client.auth.identity()?,
+            config.http_client_tls_config()?,
         );
         assert_eq!(plugin.name, "test");
         assert_eq!(plugin.path, dummy_plugin_path);
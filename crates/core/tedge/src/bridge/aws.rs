@@ -1,8 +1,8 @@
 use super::BridgeConfig;
 use camino::Utf8PathBuf;
 use tedge_config::ConnectUrl;
-
-const MOSQUITTO_BRIDGE_TOPIC: &str = "te/device/main/service/mosquitto-aws-bridge/status/health";
+use tedge_config::ProfileName;
+use tedge_config::TopicPrefix;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct BridgeConfigAwsParams {
@@ -13,6 +13,8 @@ pub struct BridgeConfigAwsParams {
     pub bridge_root_cert_path: Utf8PathBuf,
     pub bridge_certfile: Utf8PathBuf,
     pub bridge_keyfile: Utf8PathBuf,
+    pub topic_prefix: TopicPrefix,
+    pub profile_name: Option<ProfileName>,
 }
 
 impl From<BridgeConfigAwsParams> for BridgeConfig {
@@ -25,35 +27,44 @@ impl From<BridgeConfigAwsParams> for BridgeConfig {
             remote_clientid,
             bridge_certfile,
             bridge_keyfile,
+            topic_prefix,
+            profile_name,
         } = params;
 
         let address = format!("{}:{}", connect_url, mqtt_tls_port);
         let user_name = remote_clientid.to_string();
 
         // telemetry/command topics for use by the user
-        let pub_msg_topic = format!("td/# out 1 aws/ thinedge/{remote_clientid}/");
-        let sub_msg_topic = format!("cmd/# in 1 aws/ thinedge/{remote_clientid}/");
+        let pub_msg_topic = format!("td/# out 1 {topic_prefix}/ thinedge/{remote_clientid}/");
+        let sub_msg_topic = format!("cmd/# in 1 {topic_prefix}/ thinedge/{remote_clientid}/");
 
         // topic to interact with the shadow of the device
-        let shadow_topic = format!("shadow/# both 1 aws/ $aws/things/{remote_clientid}/");
+        let shadow_topic =
+            format!("shadow/# both 1 {topic_prefix}/ $aws/things/{remote_clientid}/");
+
+        // topic to interact with the jobs of the device
+        let jobs_topic = format!("jobs/# both 1 {topic_prefix}/ $aws/things/{remote_clientid}/");
 
         // echo topic mapping to check the connection
         let connection_check_pub_msg_topic = format!(
-            r#""" out 1 aws/test-connection thinedge/devices/{remote_clientid}/test-connection"#
+            r#""" out 1 {topic_prefix}/test-connection thinedge/devices/{remote_clientid}/test-connection"#
         );
         let connection_check_sub_msg_topic = format!(
-            r#""" in 1 aws/connection-success thinedge/devices/{remote_clientid}/test-connection"#
+            r#""" in 1 {topic_prefix}/connection-success thinedge/devices/{remote_clientid}/test-connection"#
         );
 
         Self {
             cloud_name: "aws".into(),
             config_file,
-            connection: "edge_to_aws".into(),
+            connection: format!("edge_to_{topic_prefix}"),
             address,
             remote_username: Some(user_name),
             bridge_root_cert_path,
             remote_clientid,
-            local_clientid: "Aws".into(),
+            local_clientid: match profile_name {
+                None => "Aws".into(),
+                Some(profile) => format!("Aws@{profile}"),
+            },
             bridge_certfile,
             bridge_keyfile,
             use_mapper: true,
@@ -65,12 +76,15 @@ impl From<BridgeConfigAwsParams> for BridgeConfig {
             local_clean_session: false,
             notifications: true,
             notifications_local_only: true,
-            notification_topic: MOSQUITTO_BRIDGE_TOPIC.into(),
+            notification_topic: format!(
+                "te/device/main/service/mosquitto-{topic_prefix}-bridge/status/health"
+            ),
             bridge_attempt_unsubscribe: false,
             topics: vec![
                 pub_msg_topic,
                 sub_msg_topic,
                 shadow_topic,
+                jobs_topic,
                 connection_check_pub_msg_topic,
                 connection_check_sub_msg_topic,
             ],
@@ -90,6 +104,8 @@ fn test_bridge_config_from_aws_params() -> anyhow::Result<()> {
         bridge_root_cert_path: "./test_root.pem".into(),
         bridge_certfile: "./test-certificate.pem".into(),
         bridge_keyfile: "./test-private-key.pem".into(),
+        topic_prefix: "aws".parse()?,
+        profile_name: None,
     };
 
     let bridge = BridgeConfig::from(params);
@@ -111,6 +127,7 @@ fn test_bridge_config_from_aws_params() -> anyhow::Result<()> {
             "td/# out 1 aws/ thinedge/alpha/".into(),
             "cmd/# in 1 aws/ thinedge/alpha/".into(),
             "shadow/# both 1 aws/ $aws/things/alpha/".into(),
+            "jobs/# both 1 aws/ $aws/things/alpha/".into(),
             r#""" out 1 aws/test-connection thinedge/devices/alpha/test-connection"#.into(),
             r#""" in 1 aws/connection-success thinedge/devices/alpha/test-connection"#.into(),
         ],
@@ -121,7 +138,7 @@ fn test_bridge_config_from_aws_params() -> anyhow::Result<()> {
         local_clean_session: false,
         notifications: true,
         notifications_local_only: true,
-        notification_topic: MOSQUITTO_BRIDGE_TOPIC.into(),
+        notification_topic: "te/device/main/service/mosquitto-aws-bridge/status/health".into(),
         bridge_attempt_unsubscribe: false,
     };
 
@@ -1,8 +1,8 @@
 use super::BridgeConfig;
 use camino::Utf8PathBuf;
 use tedge_config::ConnectUrl;
-
-const MOSQUITTO_BRIDGE_TOPIC: &str = "te/device/main/service/mosquitto-az-bridge/status/health";
+use tedge_config::ProfileName;
+use tedge_config::TopicPrefix;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct BridgeConfigAzureParams {
@@ -13,6 +13,8 @@ pub struct BridgeConfigAzureParams {
     pub bridge_root_cert_path: Utf8PathBuf,
     pub bridge_certfile: Utf8PathBuf,
     pub bridge_keyfile: Utf8PathBuf,
+    pub topic_prefix: TopicPrefix,
+    pub profile_name: Option<ProfileName>,
 }
 
 impl From<BridgeConfigAzureParams> for BridgeConfig {
@@ -25,6 +27,8 @@ impl From<BridgeConfigAzureParams> for BridgeConfig {
             remote_clientid,
             bridge_certfile,
             bridge_keyfile,
+            topic_prefix,
+            profile_name,
         } = params;
 
         let address = format!("{}:{}", connect_url, mqtt_tls_port);
@@ -32,20 +36,22 @@ impl From<BridgeConfigAzureParams> for BridgeConfig {
             "{}/{}/?api-version=2018-06-30",
             connect_url, remote_clientid
         );
-        let pub_msg_topic = format!("messages/events/# out 1 az/ devices/{}/", remote_clientid);
-        let sub_msg_topic = format!(
-            "messages/devicebound/# in 1 az/ devices/{}/",
-            remote_clientid
-        );
+        let pub_msg_topic =
+            format!("messages/events/# out 1 {topic_prefix}/ devices/{remote_clientid}/");
+        let sub_msg_topic =
+            format!("messages/devicebound/# in 1 {topic_prefix}/ devices/{remote_clientid}/");
         Self {
             cloud_name: "az".into(),
             config_file,
-            connection: "edge_to_az".into(),
+            connection: format!("edge_to_{topic_prefix}"),
             address,
             remote_username: Some(user_name),
             bridge_root_cert_path,
             remote_clientid,
-            local_clientid: "Azure".into(),
+            local_clientid: match profile_name {
+                None => "Azure".into(),
+                Some(profile) => format!("Azure@{profile}"),
+            },
             bridge_certfile,
             bridge_keyfile,
             use_mapper: true,
@@ -57,7 +63,9 @@ impl From<BridgeConfigAzureParams> for BridgeConfig {
             local_clean_session: false,
             notifications: true,
             notifications_local_only: true,
-            notification_topic: MOSQUITTO_BRIDGE_TOPIC.into(),
+            notification_topic: format!(
+                "te/device/main/service/mosquitto-{topic_prefix}-bridge/status/health"
+            ),
             bridge_attempt_unsubscribe: false,
             topics: vec![
                 // See Azure IoT Hub documentation for detailed explanation on the topics
@@ -65,12 +73,13 @@ impl From<BridgeConfigAzureParams> for BridgeConfig {
                 pub_msg_topic,
                 sub_msg_topic,
                 // Direct methods (request/response)
-                r##"methods/POST/# in 1 az/ $iothub/"##.into(),
-                r##"methods/res/# out 1 az/ $iothub/"##.into(),
+                format!(r##"methods/POST/# in 1 {topic_prefix}/ $iothub/"##),
+                format!(r##"methods/res/# out 1 {topic_prefix}/ $iothub/"##),
                 // Digital twin
-                r##"twin/res/# in 1 az/ $iothub/"##.into(),
-                r##"twin/GET/# out 1 az/ $iothub/"##.into(),
-                r##"twin/PATCH/# out 1 az/ $iothub/"##.into(),
+                format!(r##"twin/res/# in 1 {topic_prefix}/ $iothub/"##),
+                format!(r##"twin/GET/# out 1 {topic_prefix}/ $iothub/"##),
+                format!(r##"twin/PATCH/properties/reported/# out 1 {topic_prefix}/ $iothub/"##),
+                format!(r##"twin/PATCH/properties/desired/# in 1 {topic_prefix}/ $iothub/"##),
             ],
         }
     }
@@ -88,6 +97,8 @@ fn test_bridge_config_from_azure_params() -> anyhow::Result<()> {
         bridge_root_cert_path: "./test_root.pem".into(),
         bridge_certfile: "./test-certificate.pem".into(),
         bridge_keyfile: "./test-private-key.pem".into(),
+        topic_prefix: "az".parse()?,
+        profile_name: None,
     };
 
     let bridge = BridgeConfig::from(params);
@@ -112,7 +123,8 @@ fn test_bridge_config_from_azure_params() -> anyhow::Result<()> {
             r##"methods/res/# out 1 az/ $iothub/"##.into(),
             r##"twin/res/# in 1 az/ $iothub/"##.into(),
             r##"twin/GET/# out 1 az/ $iothub/"##.into(),
-            r##"twin/PATCH/# out 1 az/ $iothub/"##.into(),
+            r##"twin/PATCH/properties/reported/# out 1 az/ $iothub/"##.into(),
+            r##"twin/PATCH/properties/desired/# in 1 az/ $iothub/"##.into(),
         ],
         try_private: false,
         start_type: "automatic".into(),
@@ -121,7 +133,7 @@ fn test_bridge_config_from_azure_params() -> anyhow::Result<()> {
         local_clean_session: false,
         notifications: true,
         notifications_local_only: true,
-        notification_topic: MOSQUITTO_BRIDGE_TOPIC.into(),
+        notification_topic: "te/device/main/service/mosquitto-az-bridge/status/health".into(),
         bridge_attempt_unsubscribe: false,
     };
 
@@ -3,12 +3,12 @@ use camino::Utf8PathBuf;
 use std::process::Command;
 use tedge_config::AutoFlag;
 use tedge_config::HostPort;
+use tedge_config::ProfileName;
 use tedge_config::TemplatesSet;
+use tedge_config::TopicPrefix;
 use tedge_config::MQTT_TLS_PORT;
 use which::which;
 
-const C8Y_BRIDGE_HEALTH_TOPIC: &str = "te/device/main/service/mosquitto-c8y-bridge/status/health";
-
 #[derive(Debug, Eq, PartialEq)]
 pub struct BridgeConfigC8yParams {
     pub mqtt_host: HostPort<MQTT_TLS_PORT>,
@@ -19,6 +19,8 @@ pub struct BridgeConfigC8yParams {
     pub bridge_keyfile: Utf8PathBuf,
     pub smartrest_templates: TemplatesSet,
     pub include_local_clean_session: AutoFlag,
+    pub topic_prefix: TopicPrefix,
+    pub profile_name: Option<ProfileName>,
 }
 
 impl From<BridgeConfigC8yParams> for BridgeConfig {
@@ -32,36 +34,38 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
             bridge_keyfile,
             smartrest_templates,
             include_local_clean_session,
+            topic_prefix,
+            profile_name,
         } = params;
 
         let mut topics: Vec<String> = vec![
             // Templates
-            r#"s/dt in 2 c8y/ """#.into(),
-            r#"s/ut/# out 2 c8y/ """#.into(),
+            format!(r#"s/dt in 2 {topic_prefix}/ """#),
+            format!(r#"s/ut/# out 2 {topic_prefix}/ """#),
             // Static templates
-            r#"s/us/# out 2 c8y/ """#.into(),
-            r#"t/us/# out 2 c8y/ """#.into(),
-            r#"q/us/# out 2 c8y/ """#.into(),
-            r#"c/us/# out 2 c8y/ """#.into(),
-            r#"s/ds in 2 c8y/ """#.into(),
+            format!(r#"s/us/# out 2 {topic_prefix}/ """#),
+            format!(r#"t/us/# out 2 {topic_prefix}/ """#),
+            format!(r#"q/us/# out 2 {topic_prefix}/ """#),
+            format!(r#"c/us/# out 2 {topic_prefix}/ """#),
+            format!(r#"s/ds in 2 {topic_prefix}/ """#),
             // Debug
-            r#"s/e in 0 c8y/ """#.into(),
+            format!(r#"s/e in 0 {topic_prefix}/ """#),
             // SmartRest2
-            r#"s/uc/# out 2 c8y/ """#.into(),
-            r#"t/uc/# out 2 c8y/ """#.into(),
-            r#"q/uc/# out 2 c8y/ """#.into(),
-            r#"c/uc/# out 2 c8y/ """#.into(),
-            r#"s/dc/# in 2 c8y/ """#.into(),
+            format!(r#"s/uc/# out 2 {topic_prefix}/ """#),
+            format!(r#"t/uc/# out 2 {topic_prefix}/ """#),
+            format!(r#"q/uc/# out 2 {topic_prefix}/ """#),
+            format!(r#"c/uc/# out 2 {topic_prefix}/ """#),
+            format!(r#"s/dc/# in 2 {topic_prefix}/ """#),
             // c8y JSON
-            r#"inventory/managedObjects/update/# out 2 c8y/ """#.into(),
-            r#"measurement/measurements/create out 2 c8y/ """#.into(),
-            r#"event/events/create out 2 c8y/ """#.into(),
-            r#"alarm/alarms/create out 2 c8y/ """#.into(),
-            r#"devicecontrol/notifications in 2 c8y/ """#.into(),
-            r#"error in 2 c8y/ """#.into(),
+            format!(r#"inventory/managedObjects/update/# out 2 {topic_prefix}/ """#),
+            format!(r#"measurement/measurements/create out 2 {topic_prefix}/ """#),
+            format!(r#"event/events/create out 2 {topic_prefix}/ """#),
+            format!(r#"alarm/alarms/create out 2 {topic_prefix}/ """#),
+            format!(r#"devicecontrol/notifications in 2 {topic_prefix}/ """#),
+            format!(r#"error in 2 {topic_prefix}/ """#),
             // c8y JWT token retrieval
-            r#"s/uat out 0 c8y/ """#.into(),
-            r#"s/dat in 0 c8y/ """#.into(),
+            format!(r#"s/uat out 0 {topic_prefix}/ """#),
+            format!(r#"s/dat in 0 {topic_prefix}/ """#),
         ];
 
         let templates_set = smartrest_templates
@@ -72,8 +76,8 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
                 // c8y/s/uc/template-1 (in from localhost), s/uc/template-1
                 // c8y/s/dc/template-1 (out to localhost), s/dc/template-1
                 [
-                    format!(r#"s/uc/{s} out 2 c8y/ """#),
-                    format!(r#"s/dc/{s} in 2 c8y/ """#),
+                    format!(r#"s/uc/{s} out 2 {topic_prefix}/ """#),
+                    format!(r#"s/dc/{s} in 2 {topic_prefix}/ """#),
                 ]
                 .into_iter()
             })
@@ -89,7 +93,7 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
         Self {
             cloud_name: "c8y".into(),
             config_file,
-            connection: "edge_to_c8y".into(),
+            connection: format!("edge_to_{topic_prefix}"),
             address: format!(
                 "{host}:{port}",
                 host = mqtt_host.host(),
@@ -98,7 +102,10 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
             remote_username: None,
             bridge_root_cert_path,
             remote_clientid,
-            local_clientid: "Cumulocity".into(),
+            local_clientid: match profile_name {
+                None => "Cumulocity".into(),
+                Some(profile) => format!("Cumulocity@{profile}"),
+            },
             bridge_certfile,
             bridge_keyfile,
             use_mapper: true,
@@ -112,7 +119,9 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
             notifications_local_only: true,
 
             // FIXME: doesn't account for custom topic root, use MQTT scheme API here
-            notification_topic: C8Y_BRIDGE_HEALTH_TOPIC.into(),
+            notification_topic: format!(
+                "te/device/main/service/mosquitto-{topic_prefix}-bridge/status/health"
+            ),
             bridge_attempt_unsubscribe: false,
             topics,
         }
@@ -149,27 +158,28 @@ pub fn is_mosquitto_version_above_2() -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::bridge::C8Y_CONFIG_FILENAME;
 
     #[test]
     fn test_bridge_config_from_c8y_params() -> anyhow::Result<()> {
         use std::convert::TryFrom;
         let params = BridgeConfigC8yParams {
             mqtt_host: HostPort::<MQTT_TLS_PORT>::try_from("test.test.io".to_string())?,
-            config_file: C8Y_CONFIG_FILENAME.into(),
+            config_file: "c8y-bridge.conf".into(),
             remote_clientid: "alpha".into(),
             bridge_root_cert_path: Utf8PathBuf::from("./test_root.pem"),
             bridge_certfile: "./test-certificate.pem".into(),
             bridge_keyfile: "./test-private-key.pem".into(),
             smartrest_templates: TemplatesSet::try_from(vec!["abc", "def"])?,
             include_local_clean_session: AutoFlag::False,
+            topic_prefix: "c8y".parse()?,
+            profile_name: None,
         };
 
         let bridge = BridgeConfig::from(params);
 
         let expected = BridgeConfig {
             cloud_name: "c8y".into(),
-            config_file: C8Y_CONFIG_FILENAME.into(),
+            config_file: "c8y-bridge.conf".into(),
             connection: "edge_to_c8y".into(),
             address: "test.test.io:8883".into(),
             remote_username: None,
@@ -223,7 +233,7 @@ mod tests {
             local_clean_session: false,
             notifications: true,
             notifications_local_only: true,
-            notification_topic: C8Y_BRIDGE_HEALTH_TOPIC.into(),
+            notification_topic: "te/device/main/service/mosquitto-c8y-bridge/status/health".into(),
             bridge_attempt_unsubscribe: false,
         };
 
@@ -231,4 +241,37 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_bridge_config_from_c8y_profile_params() -> anyhow::Result<()> {
+        use std::convert::TryFrom;
+        let params = BridgeConfigC8yParams {
+            mqtt_host: HostPort::<MQTT_TLS_PORT>::try_from("staging.test.io".to_string())?,
+            config_file: "c8y-staging-bridge.conf".into(),
+            remote_clientid: "alpha".into(),
+            bridge_root_cert_path: Utf8PathBuf::from("./test_root.pem"),
+            bridge_certfile: "./test-certificate.pem".into(),
+            bridge_keyfile: "./test-private-key.pem".into(),
+            smartrest_templates: TemplatesSet::default(),
+            include_local_clean_session: AutoFlag::False,
+            topic_prefix: "c8y-staging".parse()?,
+            profile_name: Some("staging".parse()?),
+        };
+
+        let bridge = BridgeConfig::from(params);
+
+        assert_eq!(bridge.config_file, "c8y-staging-bridge.conf");
+        assert_eq!(bridge.connection, "edge_to_c8y-staging");
+        assert_eq!(bridge.local_clientid, "Cumulocity@staging");
+        assert_eq!(
+            bridge.notification_topic,
+            "te/device/main/service/mosquitto-c8y-staging-bridge/status/health"
+        );
+        assert!(bridge
+            .topics
+            .contains(&r#"s/us/# out 2 c8y-staging/ """#.to_string()));
+        assert!(!bridge.topics.iter().any(|topic| topic.contains(" c8y/ ")));
+
+        Ok(())
+    }
 }
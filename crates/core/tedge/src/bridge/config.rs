@@ -1,4 +1,5 @@
 use camino::Utf8PathBuf;
+use certificate::pkcs11::Pkcs11Uri;
 use tedge_config::TEdgeConfigLocation;
 use tedge_utils::paths::DraftFile;
 use url::Url;
@@ -55,6 +56,11 @@ impl BridgeConfig {
         writeln!(writer, "remote_clientid {}", self.remote_clientid)?;
         writeln!(writer, "local_clientid {}", self.local_clientid)?;
         writeln!(writer, "bridge_certfile {}", self.bridge_certfile)?;
+        if self.bridge_key_held_by_token() {
+            // The key is loaded by the OpenSSL PKCS#11 engine, from its URI
+            writeln!(writer, "tls_engine pkcs11")?;
+            writeln!(writer, "tls_keyform engine")?;
+        }
         writeln!(writer, "bridge_keyfile {}", self.bridge_keyfile)?;
         writeln!(writer, "try_private {}", self.try_private)?;
         writeln!(writer, "start_type {}", self.start_type)?;
@@ -96,13 +102,18 @@ impl BridgeConfig {
             return Err(ConnectError::Certificate);
         }
 
-        if !self.bridge_keyfile.exists() {
+        if !self.bridge_key_held_by_token() && !self.bridge_keyfile.exists() {
             return Err(ConnectError::Certificate);
         }
 
         Ok(())
     }
 
+    /// True if the bridge key is not a file but a PKCS#11 URI
+    fn bridge_key_held_by_token(&self) -> bool {
+        Pkcs11Uri::is_pkcs11_uri(self.bridge_keyfile.as_str())
+    }
+
     /// Write the configuration file in a mosquitto configuration directory relative to the main
     /// tedge config location.
     pub fn save(
@@ -411,6 +422,53 @@ bridge_attempt_unsubscribe false
         Ok(())
     }
 
+    #[test]
+    fn test_serialize_with_a_key_held_by_a_token() -> anyhow::Result<()> {
+        let file = tempfile::NamedTempFile::new()?;
+        let bridge_root_cert_path = Utf8Path::from_path(file.path()).unwrap();
+
+        let bridge = BridgeConfig {
+            bridge_root_cert_path: bridge_root_cert_path.to_owned(),
+            bridge_certfile: "./test-certificate.pem".into(),
+            bridge_keyfile: "pkcs11:token=tedge;object=device-key".into(),
+            ..default_bridge_config()
+        };
+
+        let mut serialized_config = Vec::<u8>::new();
+        bridge.serialize(&mut serialized_config)?;
+
+        let serialized_config = std::str::from_utf8(&serialized_config).unwrap();
+        assert!(serialized_config.contains(
+            "bridge_certfile ./test-certificate.pem\n\
+             tls_engine pkcs11\n\
+             tls_keyform engine\n\
+             bridge_keyfile pkcs11:token=tedge;object=device-key\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_a_key_held_by_a_token() -> anyhow::Result<()> {
+        let ca_file = tempfile::NamedTempFile::new()?;
+        let bridge_ca_path = Utf8Path::from_path(ca_file.path()).unwrap();
+
+        let cert_file = tempfile::NamedTempFile::new()?;
+        let bridge_certfile = Utf8Path::from_path(cert_file.path()).unwrap().to_owned();
+
+        let config = BridgeConfig {
+            address: "http://test.com".into(),
+            bridge_root_cert_path: bridge_ca_path.to_owned(),
+            bridge_certfile,
+            bridge_keyfile: "pkcs11:token=tedge;object=device-key".into(),
+            ..default_bridge_config()
+        };
+
+        assert!(config.validate().is_ok());
+
+        Ok(())
+    }
+
     fn default_bridge_config() -> BridgeConfig {
         BridgeConfig {
             cloud_name: "az/c8y".into(),
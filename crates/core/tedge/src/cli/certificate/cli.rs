@@ -1,11 +1,17 @@
 use tedge_config::OptionalConfigError;
 
 use super::create::CreateCertCmd;
+use super::create_csr::CreateCsrCmd;
+use super::install::InstallCertCmd;
 use super::remove::RemoveCertCmd;
 use super::renew::RenewCertCmd;
 use super::show::ShowCertCmd;
 use super::upload::*;
 
+use camino::Utf8PathBuf;
+use certificate::CsrConfig;
+use certificate::KeyType;
+
 use crate::command::BuildCommand;
 use crate::command::BuildContext;
 use crate::command::Command;
@@ -20,6 +26,37 @@ pub enum TEdgeCertCli {
         id: String,
     },
 
+    /// Create a certificate signing request for the device
+    ///
+    /// The device private key is created if missing, and reused otherwise.
+    /// Once signed by a certificate authority, the certificate has to be installed with `tedge cert install`.
+    CreateCsr {
+        /// The device identifier to be used as the common name for the certificate,
+        /// by default the device id of the current certificate
+        #[clap(long = "device-id")]
+        id: Option<String>,
+
+        /// A subject alternative name, e.g. `DNS:device.local` or `IP:192.168.1.10`
+        #[clap(long = "san")]
+        subject_alt_names: Vec<String>,
+
+        /// The type of the private key, if a new one is created: ecdsa-p256, ecdsa-p384 or ed25519
+        #[clap(long, default_value_t = KeyType::EcdsaP256)]
+        key_type: KeyType,
+
+        /// The path where the request is stored, by default `device.csr_path`
+        #[clap(long)]
+        output_path: Option<Utf8PathBuf>,
+    },
+
+    /// Install a device certificate signed by a certificate authority
+    ///
+    /// The certificate must match the device private key.
+    Install {
+        /// The PEM file with the device certificate, followed by the intermediate certificates
+        cert_file: Utf8PathBuf,
+    },
+
     /// Renew the device certificate
     Renew,
 
@@ -44,6 +81,41 @@ impl BuildCommand for TEdgeCertCli {
                     id,
                     cert_path: config.device.cert_path.clone(),
                     key_path: config.device.key_path.clone(),
+                    cryptoki: config.cryptoki_config(),
+                };
+                cmd.into_boxed()
+            }
+
+            TEdgeCertCli::CreateCsr {
+                id,
+                subject_alt_names,
+                key_type,
+                output_path,
+            } => {
+                let id = match id {
+                    Some(id) => id,
+                    None => config.device.id.try_read(&config)?.clone(),
+                };
+                let cmd = CreateCsrCmd {
+                    id,
+                    key_path: config.device.key_path.clone(),
+                    cryptoki: config.cryptoki_config(),
+                    csr_path: output_path.unwrap_or_else(|| config.device.csr_path.clone()),
+                    config: CsrConfig {
+                        subject_alt_names,
+                        key_type,
+                        ..CsrConfig::default()
+                    },
+                };
+                cmd.into_boxed()
+            }
+
+            TEdgeCertCli::Install { cert_file } => {
+                let cmd = InstallCertCmd {
+                    cert_file,
+                    cert_path: config.device.cert_path.clone(),
+                    key_path: config.device.key_path.clone(),
+                    cryptoki: config.cryptoki_config(),
                 };
                 cmd.into_boxed()
             }
@@ -78,6 +150,7 @@ impl BuildCommand for TEdgeCertCli {
                 let cmd = RenewCertCmd {
                     cert_path: config.device.cert_path.clone(),
                     key_path: config.device.key_path.clone(),
+                    cryptoki: config.cryptoki_config(),
                 };
                 cmd.into_boxed()
             }
@@ -1,8 +1,10 @@
 use super::error::CertError;
 use crate::command::Command;
 use camino::Utf8PathBuf;
+use certificate::CryptokiConfig;
 use certificate::KeyCertPair;
 use certificate::KeyKind;
+use certificate::KeyProvider;
 use certificate::NewCertificateConfig;
 use std::fs::File;
 use std::fs::OpenOptions;
@@ -21,6 +23,9 @@ pub struct CreateCertCmd {
 
     /// The path where the device private key will be stored
     pub key_path: Utf8PathBuf,
+
+    /// How to access the device private key, when held by a PKCS#11 token
+    pub cryptoki: Option<CryptokiConfig>,
 }
 
 impl Command for CreateCertCmd {
@@ -38,13 +43,24 @@ impl Command for CreateCertCmd {
 
 impl CreateCertCmd {
     pub fn create_test_certificate(&self, config: &NewCertificateConfig) -> Result<(), CertError> {
-        self.create_test_certificate_for(config, &KeyKind::New)
+        let key_kind = match KeyProvider::new(&self.key_path, self.cryptoki.as_ref())? {
+            // A key held by a token cannot be created, only used
+            key @ KeyProvider::Pkcs11 { .. } => KeyKind::Provided(key),
+            KeyProvider::File(_) => KeyKind::New,
+        };
+        self.create_test_certificate_for(config, &key_kind)
     }
 
     pub fn renew_test_certificate(&self, config: &NewCertificateConfig) -> Result<(), CertError> {
-        let keypair_pem = std::fs::read_to_string(&self.key_path)
-            .map_err(|e| CertError::IoError(e).key_context(self.key_path.clone()))?;
-        self.create_test_certificate_for(config, &KeyKind::Reuse { keypair_pem })
+        let key_kind = match KeyProvider::new(&self.key_path, self.cryptoki.as_ref())? {
+            key @ KeyProvider::Pkcs11 { .. } => KeyKind::Provided(key),
+            KeyProvider::File(_) => {
+                let keypair_pem = std::fs::read_to_string(&self.key_path)
+                    .map_err(|e| CertError::IoError(e).key_context(self.key_path.clone()))?;
+                KeyKind::Reuse { keypair_pem }
+            }
+        };
+        self.create_test_certificate_for(config, &key_kind)
     }
 
     fn create_test_certificate_for(
@@ -53,7 +69,9 @@ impl CreateCertCmd {
         key_kind: &KeyKind,
     ) -> Result<(), CertError> {
         validate_parent_dir_exists(&self.cert_path).map_err(CertError::CertPathError)?;
-        validate_parent_dir_exists(&self.key_path).map_err(CertError::KeyPathError)?;
+        if let KeyKind::New = key_kind {
+            validate_parent_dir_exists(&self.key_path).map_err(CertError::KeyPathError)?;
+        }
 
         let cert = KeyCertPair::new_selfsigned_certificate(config, &self.id, key_kind)?;
 
@@ -70,27 +88,39 @@ impl CreateCertCmd {
         set_permission(&cert_file, 0o444)?;
 
         if let KeyKind::New = key_kind {
-            let mut key_file =
-                create_new_file(&self.key_path, crate::BROKER_USER, crate::BROKER_GROUP)
-                    .map_err(|err| err.key_context(self.key_path.clone()))?;
-
-            // Make sure the key is secret, before write
-            set_permission(&key_file, 0o600)?;
-
-            // Zero the private key on drop
-            let cert_key = cert.private_key_pem_string()?;
-            key_file.write_all(cert_key.as_bytes())?;
-            key_file.sync_all()?;
-
-            // Prevent the key to be overwritten
-            set_permission(&key_file, 0o400)?;
+            persist_new_private_key(&self.key_path, &cert)?;
         }
 
         Ok(())
     }
 }
 
-fn create_new_file(path: impl AsRef<Path>, user: &str, group: &str) -> Result<File, CertError> {
+/// Store a newly created private key, making sure it's secret and not overwritten
+pub(super) fn persist_new_private_key(
+    key_path: &Utf8PathBuf,
+    cert: &KeyCertPair,
+) -> Result<(), CertError> {
+    let mut key_file = create_new_file(key_path, crate::BROKER_USER, crate::BROKER_GROUP)
+        .map_err(|err| err.key_context(key_path.clone()))?;
+
+    // Make sure the key is secret, before write
+    set_permission(&key_file, 0o600)?;
+
+    // Zero the private key on drop
+    let cert_key = cert.private_key_pem_string()?;
+    key_file.write_all(cert_key.as_bytes())?;
+    key_file.sync_all()?;
+
+    // Prevent the key to be overwritten
+    set_permission(&key_file, 0o400)?;
+    Ok(())
+}
+
+pub(super) fn create_new_file(
+    path: impl AsRef<Path>,
+    user: &str,
+    group: &str,
+) -> Result<File, CertError> {
     let file = OpenOptions::new()
         .write(true)
         .create_new(true)
@@ -122,6 +152,7 @@ mod tests {
             id: String::from(id),
             cert_path: cert_path.clone(),
             key_path: key_path.clone(),
+            cryptoki: None,
         };
 
         assert_matches!(
@@ -149,6 +180,7 @@ mod tests {
             id: "my-device-id".into(),
             cert_path: cert_path.clone(),
             key_path: key_path.clone(),
+            cryptoki: None,
         };
 
         assert!(cmd
@@ -170,6 +202,7 @@ mod tests {
             id: "my-device-id".into(),
             cert_path,
             key_path,
+            cryptoki: None,
         };
 
         let cert_error = cmd
@@ -188,6 +221,7 @@ mod tests {
             id: "my-device-id".into(),
             cert_path,
             key_path,
+            cryptoki: None,
         };
 
         let cert_error = cmd
@@ -206,6 +240,7 @@ mod tests {
             id: "my-device-id".into(),
             cert_path,
             key_path,
+            cryptoki: None,
         };
 
         let cert_error = cmd
@@ -0,0 +1,154 @@
+use super::create::persist_new_private_key;
+use super::error::CertError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use certificate::CryptokiConfig;
+use certificate::CsrConfig;
+use certificate::KeyCertPair;
+use certificate::KeyKind;
+use certificate::KeyProvider;
+use tedge_utils::paths::validate_parent_dir_exists;
+
+/// Create a certificate signing request for the device, to be signed by a certificate authority
+pub struct CreateCsrCmd {
+    /// The device identifier
+    pub id: String,
+
+    /// The path of the device private key, created if missing and reused otherwise
+    pub key_path: Utf8PathBuf,
+
+    /// How to access the device private key, when held by a PKCS#11 token
+    pub cryptoki: Option<CryptokiConfig>,
+
+    /// The path where the certificate signing request will be stored
+    pub csr_path: Utf8PathBuf,
+
+    /// The settings of the request
+    pub config: CsrConfig,
+}
+
+impl Command for CreateCsrCmd {
+    fn description(&self) -> String {
+        format!(
+            "create a certificate signing request for the device {}.",
+            self.id
+        )
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        self.create_certificate_signing_request()?;
+        eprintln!(
+            "Certificate signing request was successfully created: {}",
+            self.csr_path
+        );
+        Ok(())
+    }
+}
+
+impl CreateCsrCmd {
+    fn create_certificate_signing_request(&self) -> Result<(), CertError> {
+        validate_parent_dir_exists(&self.csr_path).map_err(CertError::CsrPathError)?;
+
+        let key_kind = match KeyProvider::new(&self.key_path, self.cryptoki.as_ref())? {
+            key @ KeyProvider::Pkcs11 { .. } => KeyKind::Provided(key),
+            KeyProvider::File(_) => {
+                validate_parent_dir_exists(&self.key_path).map_err(CertError::KeyPathError)?;
+                match std::fs::read_to_string(&self.key_path) {
+                    Ok(keypair_pem) => KeyKind::Reuse { keypair_pem },
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => KeyKind::New,
+                    Err(err) => {
+                        return Err(CertError::IoError(err).key_context(self.key_path.clone()))
+                    }
+                }
+            }
+        };
+
+        let csr = KeyCertPair::new_certificate_signing_request(&self.config, &self.id, &key_kind)?;
+        if let KeyKind::New = key_kind {
+            persist_new_private_key(&self.key_path, &csr)?;
+        }
+
+        // The request is public and can be overwritten by a new one
+        let csr_pem = csr.certificate_signing_request_pem_string()?;
+        std::fs::write(&self.csr_path, csr_pem)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use certificate::KeyType;
+    use std::fs;
+    use tempfile::*;
+
+    #[test]
+    fn create_a_csr_and_a_new_key() {
+        let dir = tempdir().unwrap();
+        let cmd = CreateCsrCmd {
+            id: "my-device-id".into(),
+            key_path: temp_file_path(&dir, "my-device-key.pem"),
+            cryptoki: None,
+            csr_path: temp_file_path(&dir, "my-device.csr"),
+            config: CsrConfig {
+                key_type: KeyType::Ed25519,
+                subject_alt_names: vec!["DNS:my-device.local".into()],
+                ..Default::default()
+            },
+        };
+
+        assert_matches!(cmd.create_certificate_signing_request(), Ok(()));
+        assert_eq!(
+            parse_pem_file(&cmd.csr_path).unwrap().tag,
+            "CERTIFICATE REQUEST"
+        );
+        assert_eq!(parse_pem_file(&cmd.key_path).unwrap().tag, "PRIVATE KEY");
+    }
+
+    #[test]
+    fn the_existing_key_is_reused() {
+        let dir = tempdir().unwrap();
+        let cmd = CreateCsrCmd {
+            id: "my-device-id".into(),
+            key_path: temp_file_path(&dir, "my-device-key.pem"),
+            cryptoki: None,
+            csr_path: temp_file_path(&dir, "my-device.csr"),
+            config: CsrConfig::default(),
+        };
+
+        cmd.create_certificate_signing_request().unwrap();
+        let key = fs::read_to_string(&cmd.key_path).unwrap();
+        let first_csr = fs::read_to_string(&cmd.csr_path).unwrap();
+
+        // A new request can be created for the same key
+        cmd.create_certificate_signing_request().unwrap();
+        assert_eq!(fs::read_to_string(&cmd.key_path).unwrap(), key);
+        assert_ne!(fs::read_to_string(&cmd.csr_path).unwrap(), first_csr);
+    }
+
+    #[test]
+    fn create_csr_in_non_existent_directory() {
+        let dir = tempdir().unwrap();
+        let cmd = CreateCsrCmd {
+            id: "my-device-id".into(),
+            key_path: temp_file_path(&dir, "my-device-key.pem"),
+            cryptoki: None,
+            csr_path: Utf8PathBuf::from("/non/existent/csr/path"),
+            config: CsrConfig::default(),
+        };
+
+        let cert_error = cmd.create_certificate_signing_request().unwrap_err();
+        assert_matches!(cert_error, CertError::CsrPathError { .. });
+    }
+
+    fn temp_file_path(dir: &TempDir, filename: &str) -> Utf8PathBuf {
+        dir.path().join(filename).try_into().unwrap()
+    }
+
+    fn parse_pem_file(path: impl AsRef<std::path::Path>) -> Result<pem::Pem, String> {
+        let content = fs::read(path).map_err(|err| err.to_string())?;
+        pem::parse(content).map_err(|err| err.to_string())
+    }
+}
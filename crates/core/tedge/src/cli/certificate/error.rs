@@ -52,6 +52,15 @@ pub enum CertError {
     #[error("Invalid device.key_path path: {0}")]
     KeyPathError(PathsError),
 
+    #[error("Invalid device.csr_path path: {0}")]
+    CsrPathError(PathsError),
+
+    #[error("Fail to read the certificate file {path}: {error}")]
+    CertificateFileReadFailed {
+        path: Utf8PathBuf,
+        error: std::io::Error,
+    },
+
     #[error(transparent)]
     CertificateError(#[from] certificate::CertificateError),
 
@@ -82,7 +91,10 @@ pub enum CertError {
     #[error("Root certificate path {0} does not exist")]
     RootCertificatePathDoesNotExist(String),
 
-    #[error("This certificate {path} is not a self-signed certificate")]
+    #[error(
+        r#"This certificate {path} is not a self-signed certificate
+        Run `tedge cert create-csr` to request a new certificate from your certificate authority."#
+    )]
     NotASelfSignedCertificate { path: Utf8PathBuf },
 }
 
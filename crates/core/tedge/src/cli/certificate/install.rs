@@ -0,0 +1,166 @@
+use super::create::create_new_file;
+use super::error::CertError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use certificate::validate_certificate_chain;
+use certificate::CertificateError;
+use certificate::CryptokiConfig;
+use certificate::KeyProvider;
+use certificate::PemCertificate;
+use std::io::prelude::*;
+use tedge_utils::paths::set_permission;
+use tedge_utils::paths::validate_parent_dir_exists;
+
+/// Install a device certificate signed by a certificate authority
+pub struct InstallCertCmd {
+    /// The PEM file with the signed device certificate, followed by the intermediate certificates
+    pub cert_file: Utf8PathBuf,
+
+    /// The path where the device certificate will be stored
+    pub cert_path: Utf8PathBuf,
+
+    /// The path of the device private key
+    pub key_path: Utf8PathBuf,
+
+    /// How to access the device private key, when held by a PKCS#11 token
+    pub cryptoki: Option<CryptokiConfig>,
+}
+
+impl Command for InstallCertCmd {
+    fn description(&self) -> String {
+        format!("install the device certificate {}.", self.cert_file)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let certificate = self.install_certificate()?;
+        eprintln!("Certificate was successfully installed");
+        eprintln!("Subject: {}", certificate.subject()?);
+        eprintln!("Issuer: {}", certificate.issuer()?);
+        eprintln!("Valid up to: {}", certificate.not_after()?);
+        Ok(())
+    }
+}
+
+impl InstallCertCmd {
+    fn install_certificate(&self) -> Result<PemCertificate, CertError> {
+        validate_parent_dir_exists(&self.cert_path).map_err(CertError::CertPathError)?;
+
+        let chain_pem = std::fs::read_to_string(&self.cert_file).map_err(|error| {
+            CertError::CertificateFileReadFailed {
+                path: self.cert_file.clone(),
+                error,
+            }
+        })?;
+        let key_pair = KeyProvider::new(&self.key_path, self.cryptoki.as_ref())?
+            .key_pair()
+            .map_err(|err| match err {
+                CertificateError::IoError(e) => {
+                    CertError::IoError(e).key_context(self.key_path.clone())
+                }
+                err => err.into(),
+            })?;
+        let certificate = validate_certificate_chain(&chain_pem, &key_pair)?;
+
+        // Write the new certificate aside, before replacing the current one in a single step
+        let new_cert_path = Utf8PathBuf::from(format!("{}.new", self.cert_path));
+        let _ = std::fs::remove_file(&new_cert_path);
+        let mut cert_file =
+            create_new_file(&new_cert_path, crate::BROKER_USER, crate::BROKER_GROUP)
+                .map_err(|err| err.cert_context(new_cert_path.clone()))?;
+        cert_file.write_all(chain_pem.as_bytes())?;
+        cert_file.sync_all()?;
+
+        // Prevent the certificate to be overwritten
+        set_permission(&cert_file, 0o444)?;
+        std::fs::rename(&new_cert_path, &self.cert_path)?;
+
+        Ok(certificate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CreateCertCmd;
+    use assert_matches::assert_matches;
+    use certificate::NewCertificateConfig;
+    use std::fs;
+    use tempfile::*;
+
+    #[test]
+    fn a_certificate_matching_the_key_replaces_the_current_one() {
+        let dir = tempdir().unwrap();
+        let cert_path = temp_file_path(&dir, "my-device-cert.pem");
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        let create_cmd = CreateCertCmd {
+            id: "my-device-id".into(),
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+            cryptoki: None,
+        };
+        create_cmd
+            .create_test_certificate(&NewCertificateConfig::default())
+            .unwrap();
+
+        // A certificate for the same key, as would be returned by a CA
+        let cert_file = temp_file_path(&dir, "signed.pem");
+        let mut chain = fs::read_to_string(&cert_path).unwrap();
+        chain.push_str(&fs::read_to_string(&cert_path).unwrap());
+        fs::write(&cert_file, &chain).unwrap();
+
+        let cmd = InstallCertCmd {
+            cert_file,
+            cert_path: cert_path.clone(),
+            key_path,
+            cryptoki: None,
+        };
+        let certificate = cmd.install_certificate().unwrap();
+        assert_eq!(certificate.subject_common_name().unwrap(), "my-device-id");
+        assert_eq!(fs::read_to_string(&cert_path).unwrap(), chain);
+    }
+
+    #[test]
+    fn a_certificate_not_matching_the_key_is_rejected() {
+        let dir = tempdir().unwrap();
+        let cert_path = temp_file_path(&dir, "my-device-cert.pem");
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        CreateCertCmd {
+            id: "my-device-id".into(),
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+            cryptoki: None,
+        }
+        .create_test_certificate(&NewCertificateConfig::default())
+        .unwrap();
+
+        let other_dir = tempdir().unwrap();
+        let cert_file = temp_file_path(&other_dir, "other-cert.pem");
+        CreateCertCmd {
+            id: "my-device-id".into(),
+            cert_path: cert_file.clone(),
+            key_path: temp_file_path(&other_dir, "other-key.pem"),
+            cryptoki: None,
+        }
+        .create_test_certificate(&NewCertificateConfig::default())
+        .unwrap();
+
+        let current_cert = fs::read_to_string(&cert_path).unwrap();
+        let cmd = InstallCertCmd {
+            cert_file,
+            cert_path: cert_path.clone(),
+            key_path,
+            cryptoki: None,
+        };
+        assert_matches!(
+            cmd.install_certificate().err(),
+            Some(CertError::CertificateError(
+                certificate::CertificateError::CertificateKeyMismatch
+            ))
+        );
+        assert_eq!(fs::read_to_string(&cert_path).unwrap(), current_cert);
+    }
+
+    fn temp_file_path(dir: &TempDir, filename: &str) -> Utf8PathBuf {
+        dir.path().join(filename).try_into().unwrap()
+    }
+}
@@ -4,6 +4,7 @@ use std::io;
 use super::error::CertError;
 use crate::command::Command;
 use camino::Utf8PathBuf;
+use certificate::pkcs11::Pkcs11Uri;
 
 /// Remove the device certificate
 pub struct RemoveCertCmd {
@@ -30,7 +31,12 @@ impl Command for RemoveCertCmd {
 
 impl RemoveCertCmd {
     pub(crate) fn remove_certificate(&self) -> Result<RemoveCertResult, CertError> {
-        match fs::remove_file(&self.cert_path).and_then(|()| fs::remove_file(&self.key_path)) {
+        // A key held by a PKCS#11 token is left untouched
+        let remove_key = || match Pkcs11Uri::is_pkcs11_uri(self.key_path.as_str()) {
+            true => Ok(()),
+            false => fs::remove_file(&self.key_path),
+        };
+        match fs::remove_file(&self.cert_path).and_then(|()| remove_key()) {
             Ok(()) => Ok(RemoveCertResult::Removed),
             Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(RemoveCertResult::NotFound),
             Err(err) => Err(err.into()),
@@ -2,12 +2,14 @@ use super::error::CertError;
 use crate::command::Command;
 use crate::CreateCertCmd;
 use camino::Utf8PathBuf;
+use certificate::CryptokiConfig;
 use certificate::NewCertificateConfig;
 use certificate::PemCertificate;
 
 pub struct RenewCertCmd {
     pub cert_path: Utf8PathBuf,
     pub key_path: Utf8PathBuf,
+    pub cryptoki: Option<CryptokiConfig>,
 }
 
 impl Command for RenewCertCmd {
@@ -36,6 +38,7 @@ impl RenewCertCmd {
             id,
             cert_path: self.cert_path.clone(),
             key_path: self.key_path.clone(),
+            cryptoki: self.cryptoki.clone(),
         };
 
         create_cmd.renew_test_certificate(config)
@@ -77,6 +80,7 @@ mod tests {
             id: String::from(id),
             cert_path: cert_path.clone(),
             key_path: key_path.clone(),
+            cryptoki: None,
         };
 
         // First create both cert and key
@@ -95,6 +99,7 @@ mod tests {
         let cmd = RenewCertCmd {
             cert_path: cert_path.clone(),
             key_path: key_path.clone(),
+            cryptoki: None,
         };
         cmd.renew_test_certificate(&NewCertificateConfig::default())
             .unwrap();
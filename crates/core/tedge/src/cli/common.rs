@@ -1,4 +1,9 @@
 use tedge_config::system_services::SystemService;
+use tedge_config::CloudType;
+use tedge_config::ProfileName;
+use tedge_config::TEdgeConfig;
+use tedge_config::TopicPrefix;
+use tedge_config::UnknownProfile;
 
 #[derive(Copy, Clone, Debug, strum_macros::Display, strum_macros::IntoStaticStr)]
 pub enum Cloud {
@@ -9,11 +14,19 @@ pub enum Cloud {
 }
 
 impl Cloud {
-    pub fn mapper_service(&self) -> SystemService {
+    pub fn mapper_service<'a>(&self, profile: Option<&'a ProfileName>) -> SystemService<'a> {
         match self {
-            Cloud::Aws => SystemService::TEdgeMapperAws,
-            Cloud::Azure => SystemService::TEdgeMapperAz,
-            Cloud::C8y => SystemService::TEdgeMapperC8y,
+            Cloud::Aws => SystemService::TEdgeMapperAws(profile),
+            Cloud::Azure => SystemService::TEdgeMapperAz(profile),
+            Cloud::C8y => SystemService::TEdgeMapperC8y(profile),
+        }
+    }
+
+    pub fn cloud_type(self) -> CloudType {
+        match self {
+            Cloud::Aws => CloudType::Aws,
+            Cloud::Azure => CloudType::Az,
+            Cloud::C8y => CloudType::C8y,
         }
     }
 
@@ -21,11 +34,31 @@ impl Cloud {
         self.into()
     }
 
-    pub fn bridge_config_filename(&self) -> &str {
+    /// The configuration of the given profile of this cloud, or of the default connection if none
+    pub fn profile_config(
+        self,
+        config: TEdgeConfig,
+        profile: Option<&ProfileName>,
+    ) -> Result<TEdgeConfig, UnknownProfile> {
+        match profile {
+            None => Ok(config),
+            Some(profile) => config.profile(self.cloud_type(), profile),
+        }
+    }
+
+    /// The prefix of the local topics bridged to this cloud, e.g. `c8y` or `c8y-staging` for a profile
+    pub fn topic_prefix(self, config: &TEdgeConfig) -> &TopicPrefix {
         match self {
-            Self::C8y => crate::bridge::C8Y_CONFIG_FILENAME,
-            Self::Aws => crate::bridge::AWS_CONFIG_FILENAME,
-            Self::Azure => crate::bridge::AZURE_CONFIG_FILENAME,
+            Self::C8y => &config.c8y.bridge.topic_prefix,
+            Self::Aws => &config.aws.bridge.topic_prefix,
+            Self::Azure => &config.az.bridge.topic_prefix,
         }
     }
+
+    /// The name of the bridge configuration file, derived from the topic prefix of this cloud
+    ///
+    /// This is the default file name, unless the topic prefix has been changed (e.g. for a profile).
+    pub fn bridge_config_filename(self, config: &TEdgeConfig) -> String {
+        format!("{}-bridge.conf", self.topic_prefix(config))
+    }
 }
@@ -1,9 +1,10 @@
+use tedge_config::ProfiledKey;
 use tedge_config::ReadableKey;
 
 use crate::command::Command;
 
 pub struct GetConfigCommand {
-    pub key: ReadableKey,
+    pub key: ProfiledKey<ReadableKey>,
     pub config: tedge_config::TEdgeConfig,
 }
 
@@ -13,7 +14,7 @@ impl Command for GetConfigCommand {
     }
 
     fn execute(&self) -> anyhow::Result<()> {
-        match self.config.read_string(self.key) {
+        match self.config.read_profiled_string(&self.key) {
             Ok(value) => {
                 println!("{}", value);
             }
@@ -23,6 +24,9 @@ impl Command for GetConfigCommand {
             Err(tedge_config::ReadError::ReadOnlyNotFound { message, key }) => {
                 eprintln!("The provided config key: '{key}' is not configured: {message}",);
             }
+            Err(tedge_config::ReadError::UnknownProfile(err)) => {
+                eprintln!("The provided config key: '{}' is not set: {err}", self.key);
+            }
             Err(err) => return Err(err.into()),
         }
 
@@ -3,6 +3,8 @@ use crate::ConfigError;
 use pad::PadStr;
 use std::io::stdout;
 use std::io::IsTerminal;
+use tedge_config::CloudType;
+use tedge_config::ProfiledKey;
 use tedge_config::ReadableKey;
 use tedge_config::TEdgeConfig;
 use tedge_config::READABLE_KEYS;
@@ -41,6 +43,23 @@ fn print_config_list(config: &TEdgeConfig, all: bool) -> Result<(), ConfigError>
             }
         }
     }
+    for cloud in [CloudType::C8y, CloudType::Az, CloudType::Aws] {
+        for profile in config.profile_names(cloud) {
+            let profile_config = config.profile(cloud, &profile)?;
+            for key in ReadableKey::iter() {
+                let config_key = ProfiledKey {
+                    key,
+                    profile: Some(profile.clone()),
+                };
+                if config_key.profile().map(|(c, _)| c) != Some(cloud) {
+                    continue;
+                }
+                if let Ok(value) = profile_config.read_string(key) {
+                    println!("{}={}", config_key, value);
+                }
+            }
+        }
+    }
     if all && !keys_without_values.is_empty() {
         println!();
         for key in keys_without_values {
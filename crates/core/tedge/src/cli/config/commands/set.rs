@@ -1,9 +1,10 @@
 use crate::command::Command;
+use tedge_config::ProfiledKey;
 use tedge_config::TEdgeConfigRepository;
 use tedge_config::WritableKey;
 
 pub struct SetConfigCommand {
-    pub key: WritableKey,
+    pub key: ProfiledKey<WritableKey>,
     pub value: String,
     pub config_repository: TEdgeConfigRepository,
 }
@@ -12,14 +13,13 @@ impl Command for SetConfigCommand {
     fn description(&self) -> String {
         format!(
             "set the configuration key: '{}' with value: {}.",
-            self.key.as_str(),
-            self.value
+            self.key, self.value
         )
     }
 
     fn execute(&self) -> anyhow::Result<()> {
         self.config_repository.update_toml(&|dto| {
-            dto.try_update_str(self.key, &self.value)
+            dto.try_update_profiled_str(&self.key, &self.value)
                 .map_err(|e| e.into())
         })?;
         Ok(())
@@ -1,9 +1,10 @@
 use crate::command::Command;
+use tedge_config::ProfiledKey;
 use tedge_config::TEdgeConfigRepository;
 use tedge_config::WritableKey;
 
 pub struct UnsetConfigCommand {
-    pub key: WritableKey,
+    pub key: ProfiledKey<WritableKey>,
     pub config_repository: TEdgeConfigRepository,
 }
 
@@ -14,7 +15,7 @@ impl Command for UnsetConfigCommand {
 
     fn execute(&self) -> anyhow::Result<()> {
         self.config_repository.update_toml(&|dto| {
-            dto.unset_key(self.key);
+            dto.unset_profiled_key(&self.key);
             Ok(())
         })?;
         Ok(())
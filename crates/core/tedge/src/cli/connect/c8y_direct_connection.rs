@@ -2,6 +2,7 @@ use super::ConnectError;
 use crate::bridge::BridgeConfig;
 use crate::cli::connect::CONNECTION_TIMEOUT;
 use certificate::parse_root_certificate::create_tls_config;
+use certificate::KeyProvider;
 use rumqttc::tokio_rustls::rustls::AlertDescription;
 use rumqttc::tokio_rustls::rustls::CertificateError;
 use rumqttc::tokio_rustls::rustls::Error;
@@ -19,6 +20,7 @@ use rumqttc::Transport;
 // Connect directly to the c8y cloud over mqtt and publish device create message.
 pub fn create_device_with_direct_connection(
     bridge_config: &BridgeConfig,
+    bridge_key: &KeyProvider,
     device_type: &str,
 ) -> Result<(), ConnectError> {
     const DEVICE_ALREADY_EXISTS: &[u8] = b"41,100,Device already existing";
@@ -32,7 +34,7 @@ pub fn create_device_with_direct_connection(
 
     let tls_config = create_tls_config(
         bridge_config.bridge_root_cert_path.clone().into(),
-        bridge_config.bridge_keyfile.clone().into(),
+        bridge_key,
         bridge_config.bridge_certfile.clone().into(),
     )?;
     mqtt_options.set_transport(Transport::tls_with_config(tls_config.into()));
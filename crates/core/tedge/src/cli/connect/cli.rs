@@ -1,4 +1,5 @@
 use tedge_config::system_services::service_manager;
+use tedge_config::ProfileName;
 
 use crate::cli::common::Cloud;
 use crate::cli::connect::*;
@@ -15,6 +16,10 @@ pub enum TEdgeConnectOpt {
         /// Test connection to Cumulocity
         #[clap(long = "test")]
         is_test_connection: bool,
+
+        /// The Cumulocity profile to connect, as configured under `c8y.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
     },
 
     /// Create connection to Azure
@@ -24,6 +29,10 @@ pub enum TEdgeConnectOpt {
         /// Test connection to Azure
         #[clap(long = "test")]
         is_test_connection: bool,
+
+        /// The Azure profile to connect, as configured under `az.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
     },
 
     /// Create connection to AWS
@@ -33,30 +42,46 @@ pub enum TEdgeConnectOpt {
         /// Test connection to AWS
         #[clap(long = "test")]
         is_test_connection: bool,
+
+        /// The AWS profile to connect, as configured under `aws.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
     },
 }
 
 impl BuildCommand for TEdgeConnectOpt {
     fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, crate::ConfigError> {
         Ok(match self {
-            TEdgeConnectOpt::C8y { is_test_connection } => ConnectCommand {
+            TEdgeConnectOpt::C8y {
+                is_test_connection,
+                profile,
+            } => ConnectCommand {
                 config_location: context.config_location.clone(),
                 config_repository: context.config_repository,
                 cloud: Cloud::C8y,
+                profile,
                 is_test_connection,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
-            TEdgeConnectOpt::Az { is_test_connection } => ConnectCommand {
+            TEdgeConnectOpt::Az {
+                is_test_connection,
+                profile,
+            } => ConnectCommand {
                 config_location: context.config_location.clone(),
                 config_repository: context.config_repository,
                 cloud: Cloud::Azure,
+                profile,
                 is_test_connection,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
-            TEdgeConnectOpt::Aws { is_test_connection } => ConnectCommand {
+            TEdgeConnectOpt::Aws {
+                is_test_connection,
+                profile,
+            } => ConnectCommand {
                 config_location: context.config_location.clone(),
                 config_repository: context.config_repository,
                 cloud: Cloud::Aws,
+                profile,
                 is_test_connection,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
@@ -9,6 +9,7 @@ use crate::cli::connect::*;
 use crate::command::Command;
 use crate::ConfigError;
 use camino::Utf8PathBuf;
+use certificate::KeyProvider;
 use rumqttc::Event;
 use rumqttc::Incoming;
 use rumqttc::Outgoing;
@@ -25,9 +26,6 @@ use tedge_utils::paths::ok_if_not_found;
 use tedge_utils::paths::DraftFile;
 use which::which;
 
-use crate::bridge::AWS_CONFIG_FILENAME;
-use crate::bridge::AZURE_CONFIG_FILENAME;
-use crate::bridge::C8Y_CONFIG_FILENAME;
 use crate::bridge::TEDGE_BRIDGE_CONF_DIR_PATH;
 
 const WAIT_FOR_CHECK_SECONDS: u64 = 2;
@@ -40,6 +38,7 @@ pub struct ConnectCommand {
     pub config_location: TEdgeConfigLocation,
     pub config_repository: TEdgeConfigRepository,
     pub cloud: Cloud,
+    pub profile: Option<ProfileName>,
     pub is_test_connection: bool,
     pub service_manager: Arc<dyn SystemServiceManager>,
 }
@@ -51,16 +50,24 @@ pub enum DeviceStatus {
 
 impl Command for ConnectCommand {
     fn description(&self) -> String {
+        let cloud = match &self.profile {
+            None => self.cloud.as_str().to_string(),
+            Some(profile) => format!("{} (profile {profile})", self.cloud.as_str()),
+        };
         if self.is_test_connection {
-            format!("test connection to {} cloud.", self.cloud.as_str())
+            format!("test connection to {cloud} cloud.")
         } else {
-            format!("connect {} cloud.", self.cloud.as_str())
+            format!("connect {cloud} cloud.")
         }
     }
 
     fn execute(&self) -> anyhow::Result<()> {
-        let config = self.config_repository.load()?;
-        let bridge_config = bridge_config(&config, self.cloud)?;
+        let config = cloud_profile_config(
+            self.config_repository.load()?,
+            self.cloud,
+            self.profile.as_ref(),
+        )?;
+        let bridge_config = bridge_config(&config, self.cloud, self.profile.as_ref())?;
         let updated_mosquitto_config = CommonMosquittoConfig::from_tedge_config(&config);
 
         if self.is_test_connection {
@@ -83,9 +90,11 @@ impl Command for ConnectCommand {
         }
 
         let device_type = &config.device.ty;
+        let bridge_key = config.key_provider(&bridge_config.bridge_keyfile)?;
 
         match new_bridge(
             &bridge_config,
+            &bridge_key,
             &updated_mosquitto_config,
             self.service_manager.as_ref(),
             &self.config_location,
@@ -116,9 +125,10 @@ impl Command for ConnectCommand {
             if which("tedge-mapper").is_err() {
                 println!("Warning: tedge-mapper is not installed.\n");
             } else {
-                self.service_manager
-                    .as_ref()
-                    .start_and_enable_service(self.cloud.mapper_service(), std::io::stdout());
+                self.service_manager.as_ref().start_and_enable_service(
+                    self.cloud.mapper_service(self.profile.as_ref()),
+                    std::io::stdout(),
+                );
             }
         }
 
@@ -164,20 +174,49 @@ impl ConnectCommand {
     }
 }
 
+/// Return the configuration to be used to connect the given cloud profile
+///
+/// The settings of a profile are those of the cloud section of `tedge.toml`
+/// replaced by the settings of the profile, with its own topic prefix.
+pub fn cloud_profile_config(
+    config: TEdgeConfig,
+    cloud: Cloud,
+    profile: Option<&ProfileName>,
+) -> Result<TEdgeConfig, ConnectError> {
+    let config = cloud.profile_config(config, profile)?;
+    if let Some(profile) = profile {
+        let topic_prefix = cloud.topic_prefix(&config);
+        if topic_prefix.as_str() == cloud.cloud_type().as_str() {
+            return Err(ConnectError::ProfileTopicPrefixNotUnique {
+                cloud: cloud.cloud_type().to_string(),
+                profile: profile.clone(),
+                topic_prefix: topic_prefix.clone(),
+            });
+        }
+    }
+    Ok(config)
+}
+
 pub fn bridge_config(
     config: &TEdgeConfig,
     cloud: self::Cloud,
+    profile: Option<&ProfileName>,
 ) -> Result<BridgeConfig, ConfigError> {
+    let topic_prefix = cloud.topic_prefix(config).clone();
+    let config_file = cloud.bridge_config_filename(config);
+    let profile_name = profile.cloned();
     match cloud {
         Cloud::Azure => {
             let params = BridgeConfigAzureParams {
                 connect_url: config.az.url.or_config_not_set()?.clone(),
                 mqtt_tls_port: MQTT_TLS_PORT,
-                config_file: AZURE_CONFIG_FILENAME.into(),
+                config_file,
                 bridge_root_cert_path: config.az.root_cert_path.clone(),
                 remote_clientid: config.device.id.try_read(config)?.clone(),
                 bridge_certfile: config.device.cert_path.clone(),
                 bridge_keyfile: config.device.key_path.clone(),
+                topic_prefix,
+                profile_name,
             };
 
             Ok(BridgeConfig::from(params))
@@ -186,11 +225,13 @@ pub fn bridge_config(
             let params = BridgeConfigAwsParams {
                 connect_url: config.aws.url.or_config_not_set()?.clone(),
                 mqtt_tls_port: MQTT_TLS_PORT,
-                config_file: AWS_CONFIG_FILENAME.into(),
+                config_file,
                 bridge_root_cert_path: config.aws.root_cert_path.clone(),
                 remote_clientid: config.device.id.try_read(config)?.clone(),
                 bridge_certfile: config.device.cert_path.clone(),
                 bridge_keyfile: config.device.key_path.clone(),
+                topic_prefix,
+                profile_name,
             };
 
             Ok(BridgeConfig::from(params))
@@ -198,13 +239,15 @@ pub fn bridge_config(
         Cloud::C8y => {
             let params = BridgeConfigC8yParams {
                 mqtt_host: config.c8y.mqtt.or_config_not_set()?.clone(),
-                config_file: C8Y_CONFIG_FILENAME.into(),
+                config_file,
                 bridge_root_cert_path: config.c8y.root_cert_path.clone(),
                 remote_clientid: config.device.id.try_read(config)?.clone(),
                 bridge_certfile: config.device.cert_path.clone(),
                 bridge_keyfile: config.device.key_path.clone(),
                 smartrest_templates: config.c8y.smartrest.templates.clone(),
                 include_local_clean_session: config.c8y.bridge.include.local_cleansession.clone(),
+                topic_prefix,
+                profile_name,
             };
 
             Ok(BridgeConfig::from(params))
@@ -215,8 +258,9 @@ pub fn bridge_config(
 // Check the connection by using the jwt token retrieval over the mqtt.
 // If successful in getting the jwt token '71,xxxxx', the connection is established.
 fn check_device_status_c8y(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, ConnectError> {
-    const C8Y_TOPIC_BUILTIN_JWT_TOKEN_DOWNSTREAM: &str = "c8y/s/dat";
-    const C8Y_TOPIC_BUILTIN_JWT_TOKEN_UPSTREAM: &str = "c8y/s/uat";
+    let prefix = &tedge_config.c8y.bridge.topic_prefix;
+    let c8y_topic_builtin_jwt_token_downstream = format!("{prefix}/s/dat");
+    let c8y_topic_builtin_jwt_token_upstream = format!("{prefix}/s/uat");
     const CLIENT_ID: &str = "check_connection_c8y";
 
     let mut mqtt_options = tedge_config
@@ -234,14 +278,14 @@ fn check_device_status_c8y(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
         .set_connection_timeout(CONNECTION_TIMEOUT.as_secs());
     let mut acknowledged = false;
 
-    client.subscribe(C8Y_TOPIC_BUILTIN_JWT_TOKEN_DOWNSTREAM, AtLeastOnce)?;
+    client.subscribe(&c8y_topic_builtin_jwt_token_downstream, AtLeastOnce)?;
 
     for event in connection.iter() {
         match event {
             Ok(Event::Incoming(Packet::SubAck(_))) => {
                 // We are ready to get the response, hence send the request
                 client.publish(
-                    C8Y_TOPIC_BUILTIN_JWT_TOKEN_UPSTREAM,
+                    &c8y_topic_builtin_jwt_token_upstream,
                     rumqttc::QoS::AtMostOnce,
                     false,
                     "",
@@ -292,8 +336,9 @@ fn check_device_status_c8y(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
 // The result will be published by the iothub on the az/$iothub/twin/res/{status}/?$rid={request id}.
 // Here if the status is 200 then it's success.
 fn check_device_status_azure(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, ConnectError> {
-    const AZURE_TOPIC_DEVICE_TWIN_DOWNSTREAM: &str = r##"az/twin/res/#"##;
-    const AZURE_TOPIC_DEVICE_TWIN_UPSTREAM: &str = r#"az/twin/GET/?$rid=1"#;
+    let prefix = &tedge_config.az.bridge.topic_prefix;
+    let azure_topic_device_twin_downstream = format!(r##"{prefix}/twin/res/#"##);
+    let azure_topic_device_twin_upstream = format!(r#"{prefix}/twin/GET/?$rid=1"#);
     const CLIENT_ID: &str = "check_connection_az";
     const REGISTRATION_PAYLOAD: &[u8] = b"";
     const REGISTRATION_OK: &str = "200";
@@ -308,14 +353,14 @@ fn check_device_status_azure(tedge_config: &TEdgeConfig) -> Result<DeviceStatus,
     let (mut client, mut connection) = rumqttc::Client::new(mqtt_options, 10);
     let mut acknowledged = false;
 
-    client.subscribe(AZURE_TOPIC_DEVICE_TWIN_DOWNSTREAM, AtLeastOnce)?;
+    client.subscribe(&azure_topic_device_twin_downstream, AtLeastOnce)?;
 
     for event in connection.iter() {
         match event {
             Ok(Event::Incoming(Packet::SubAck(_))) => {
                 // We are ready to get the response, hence send the request
                 client.publish(
-                    AZURE_TOPIC_DEVICE_TWIN_UPSTREAM,
+                    &azure_topic_device_twin_upstream,
                     AtLeastOnce,
                     false,
                     REGISTRATION_PAYLOAD,
@@ -361,8 +406,9 @@ fn check_device_status_azure(tedge_config: &TEdgeConfig) -> Result<DeviceStatus,
 }
 
 fn check_device_status_aws(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, ConnectError> {
-    const AWS_TOPIC_PUB_CHECK_CONNECTION: &str = r#"aws/test-connection"#;
-    const AWS_TOPIC_SUB_CHECK_CONNECTION: &str = r#"aws/connection-success"#;
+    let prefix = &tedge_config.aws.bridge.topic_prefix;
+    let aws_topic_pub_check_connection = format!("{prefix}/test-connection");
+    let aws_topic_sub_check_connection = format!("{prefix}/connection-success");
     const CLIENT_ID: &str = "check_connection_aws";
     const REGISTRATION_PAYLOAD: &[u8] = b"";
 
@@ -375,14 +421,14 @@ fn check_device_status_aws(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
     let (mut client, mut connection) = rumqttc::Client::new(mqtt_options, 10);
     let mut acknowledged = false;
 
-    client.subscribe(AWS_TOPIC_SUB_CHECK_CONNECTION, AtLeastOnce)?;
+    client.subscribe(&aws_topic_sub_check_connection, AtLeastOnce)?;
 
     for event in connection.iter() {
         match event {
             Ok(Event::Incoming(Packet::SubAck(_))) => {
                 // We are ready to get the response, hence send the request
                 client.publish(
-                    AWS_TOPIC_PUB_CHECK_CONNECTION,
+                    &aws_topic_pub_check_connection,
                     AtLeastOnce,
                     false,
                     REGISTRATION_PAYLOAD,
@@ -428,6 +474,7 @@ fn check_device_status_aws(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
 
 fn new_bridge(
     bridge_config: &BridgeConfig,
+    bridge_key: &KeyProvider,
     common_mosquitto_config: &CommonMosquittoConfig,
     service_manager: &dyn SystemServiceManager,
     config_location: &TEdgeConfigLocation,
@@ -453,7 +500,11 @@ fn new_bridge(
 
     if bridge_config.cloud_name.eq("c8y") {
         println!("Creating the device in Cumulocity cloud.\n");
-        c8y_direct_connection::create_device_with_direct_connection(bridge_config, device_type)?;
+        c8y_direct_connection::create_device_with_direct_connection(
+            bridge_config,
+            bridge_key,
+            device_type,
+        )?;
     }
 
     println!("Saving configuration for requested bridge.\n");
@@ -58,4 +58,14 @@ pub enum ConnectError {
 
     #[error(transparent)]
     CertificateError(#[from] certificate::CertificateError),
+
+    #[error(transparent)]
+    UnknownProfile(#[from] tedge_config::UnknownProfile),
+
+    #[error("The topic prefix of the {cloud} profile '{profile}' cannot be '{topic_prefix}', as used by the default {cloud} connection. Use 'tedge config set {cloud}.profiles.{profile}.bridge.topic_prefix' to set another prefix")]
+    ProfileTopicPrefixNotUnique {
+        cloud: String,
+        profile: tedge_config::ProfileName,
+        topic_prefix: tedge_config::TopicPrefix,
+    },
 }
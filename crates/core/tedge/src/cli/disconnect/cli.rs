@@ -2,48 +2,46 @@ use crate::cli::common::Cloud;
 use crate::cli::disconnect::disconnect_bridge::*;
 use crate::command::*;
 use tedge_config::system_services::service_manager;
-
-use crate::bridge::AWS_CONFIG_FILENAME;
-use crate::bridge::AZURE_CONFIG_FILENAME;
-use crate::bridge::C8Y_CONFIG_FILENAME;
+use tedge_config::ProfileName;
 
 #[derive(clap::Subcommand, Debug)]
 pub enum TEdgeDisconnectBridgeCli {
     /// Remove bridge connection to Cumulocity.
-    C8y,
+    C8y {
+        /// The Cumulocity profile to disconnect, as configured under `c8y.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to Azure.
-    Az,
+    Az {
+        /// The Azure profile to disconnect, as configured under `az.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to AWS.
-    Aws,
+    Aws {
+        /// The AWS profile to disconnect, as configured under `aws.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
 }
 
 impl BuildCommand for TEdgeDisconnectBridgeCli {
     fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, crate::ConfigError> {
-        let cmd = match self {
-            TEdgeDisconnectBridgeCli::C8y => DisconnectBridgeCommand {
-                config_location: context.config_location.clone(),
-                config_file: C8Y_CONFIG_FILENAME.into(),
-                cloud: Cloud::C8y,
-                use_mapper: true,
-                use_agent: true,
-                service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
-            },
-            TEdgeDisconnectBridgeCli::Az => DisconnectBridgeCommand {
-                config_location: context.config_location.clone(),
-                config_file: AZURE_CONFIG_FILENAME.into(),
-                cloud: Cloud::Azure,
-                use_mapper: true,
-                use_agent: false,
-                service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
-            },
-            TEdgeDisconnectBridgeCli::Aws => DisconnectBridgeCommand {
-                config_location: context.config_location.clone(),
-                config_file: AWS_CONFIG_FILENAME.into(),
-                cloud: Cloud::Aws,
-                use_mapper: true,
-                use_agent: false,
-                service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
-            },
+        let (cloud, profile, use_agent) = match self {
+            TEdgeDisconnectBridgeCli::C8y { profile } => (Cloud::C8y, profile, true),
+            TEdgeDisconnectBridgeCli::Az { profile } => (Cloud::Azure, profile, false),
+            TEdgeDisconnectBridgeCli::Aws { profile } => (Cloud::Aws, profile, false),
+        };
+        let config = cloud.profile_config(context.config_repository.load()?, profile.as_ref())?;
+        let cmd = DisconnectBridgeCommand {
+            config_location: context.config_location.clone(),
+            config_file: cloud.bridge_config_filename(&config),
+            cloud,
+            profile,
+            use_mapper: true,
+            use_agent,
+            service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
         };
         Ok(cmd.into_boxed())
     }
@@ -3,6 +3,7 @@ use crate::cli::disconnect::error::*;
 use crate::command::*;
 use std::sync::Arc;
 use tedge_config::system_services::*;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfigLocation;
 use which::which;
 
@@ -13,6 +14,7 @@ pub struct DisconnectBridgeCommand {
     pub config_location: TEdgeConfigLocation,
     pub config_file: String,
     pub cloud: Cloud,
+    pub profile: Option<ProfileName>,
     pub use_mapper: bool,
     pub use_agent: bool,
     pub service_manager: Arc<dyn SystemServiceManager>,
@@ -56,9 +58,10 @@ impl DisconnectBridgeCommand {
         let mut failed = false;
         // Only C8Y changes the status of tedge-mapper
         if self.use_mapper && which("tedge-mapper").is_ok() {
-            failed = self
-                .service_manager()
-                .stop_and_disable_service(self.cloud.mapper_service(), std::io::stdout());
+            failed = self.service_manager().stop_and_disable_service(
+                self.cloud.mapper_service(self.profile.as_ref()),
+                std::io::stdout(),
+            );
         }
 
         match failed {
@@ -0,0 +1,129 @@
+use crate::cli::entity::deregister::DeregisterEntityCmd;
+use crate::cli::entity::get::GetEntityCmd;
+use crate::cli::entity::list::ListEntityCmd;
+use crate::cli::entity::register::RegisterEntityCmd;
+use crate::cli::entity::store::EntityClient;
+use crate::cli::entity::tree::EntityTreeCmd;
+use crate::command::BuildCommand;
+use crate::command::BuildContext;
+use crate::command::Command;
+use crate::ConfigError;
+use tedge_api::entity_store::EntityType;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeEntityCli {
+    /// List the registered entities
+    List,
+
+    /// Show the registration, twin data and capabilities of an entity
+    Get {
+        /// The entity topic id, e.g. `device/child01//`
+        topic_id: EntityTopicId,
+    },
+
+    /// Register a child device or a service
+    ///
+    /// The entity type and parent are derived from the topic id,
+    /// when this topic id follows the default topic scheme.
+    Register {
+        /// The entity topic id, e.g. `device/child01//`
+        topic_id: EntityTopicId,
+
+        /// The entity type
+        #[clap(long, value_enum)]
+        entity_type: Option<RegisteredType>,
+
+        /// The topic id of the parent device
+        #[clap(long)]
+        parent: Option<EntityTopicId>,
+
+        /// The cloud external id, derived from the topic id if not provided
+        #[clap(long)]
+        external_id: Option<String>,
+
+        /// The display name of the entity
+        #[clap(long)]
+        name: Option<String>,
+
+        /// The device or service type
+        #[clap(long = "type", value_name = "TYPE")]
+        ty: Option<String>,
+    },
+
+    /// Deregister an entity along its child devices and services
+    ///
+    /// The retained registration, twin and capability messages of these entities are cleared.
+    Deregister {
+        /// The entity topic id, e.g. `device/child01//`
+        topic_id: EntityTopicId,
+    },
+
+    /// Display the hierarchy of entities
+    Tree,
+}
+
+/// The types of entity that can be registered
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisteredType {
+    ChildDevice,
+    Service,
+}
+
+impl From<RegisteredType> for EntityType {
+    fn from(value: RegisteredType) -> Self {
+        match value {
+            RegisteredType::ChildDevice => EntityType::ChildDevice,
+            RegisteredType::Service => EntityType::Service,
+        }
+    }
+}
+
+impl BuildCommand for TEdgeEntityCli {
+    fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, ConfigError> {
+        let config = context.config_repository.load()?;
+
+        // The external ids are derived from the device id as done by the mappers.
+        // The device certificate might not be created yet, hence the fallback.
+        let main_device_xid = config
+            .device
+            .id
+            .try_read(&config)
+            .map(|id| id.to_string())
+            .unwrap_or_else(|_| "main".to_string());
+
+        let client = EntityClient {
+            mqtt_config: config.mqtt_config()?,
+            mqtt_schema: MqttSchema::with_root(config.mqtt.topic_root.clone()),
+            main_device_xid: main_device_xid.into(),
+        };
+
+        let cmd = match self {
+            TEdgeEntityCli::List => ListEntityCmd { client }.into_boxed(),
+            TEdgeEntityCli::Get { topic_id } => GetEntityCmd { client, topic_id }.into_boxed(),
+            TEdgeEntityCli::Register {
+                topic_id,
+                entity_type,
+                parent,
+                external_id,
+                name,
+                ty,
+            } => RegisterEntityCmd {
+                client,
+                topic_id,
+                entity_type: entity_type.map(EntityType::from),
+                parent,
+                external_id,
+                name,
+                ty,
+            }
+            .into_boxed(),
+            TEdgeEntityCli::Deregister { topic_id } => {
+                DeregisterEntityCmd { client, topic_id }.into_boxed()
+            }
+            TEdgeEntityCli::Tree => EntityTreeCmd { client }.into_boxed(),
+        };
+        Ok(cmd)
+    }
+}
@@ -0,0 +1,143 @@
+use crate::cli::entity::error::EntityError;
+use crate::cli::entity::store::Entities;
+use crate::cli::entity::store::EntityClient;
+use crate::cli::entity::tree::children;
+use crate::command::Command;
+use mqtt_channel::Message;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+
+/// Deregister an entity along its child devices and services
+pub struct DeregisterEntityCmd {
+    pub client: EntityClient,
+
+    /// The entity topic id
+    pub topic_id: EntityTopicId,
+}
+
+impl Command for DeregisterEntityCmd {
+    fn description(&self) -> String {
+        format!("deregister the entity {}", self.topic_id)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let entities = self.client.read_entities()?;
+        let messages = deregistration_messages(&entities, &self.topic_id)?;
+        let count = messages.len();
+        self.client.publish(messages)?;
+        println!(
+            "Deregistered {}: {count} retained messages cleared",
+            self.topic_id
+        );
+        Ok(())
+    }
+}
+
+/// The messages clearing the retained messages of an entity and of all its descendants
+///
+/// The descendants are cleared before their parent,
+/// and the twin and capability messages of an entity before its registration message.
+pub(crate) fn deregistration_messages(
+    entities: &Entities,
+    topic_id: &EntityTopicId,
+) -> Result<Vec<Message>, EntityError> {
+    entities.get(topic_id)?;
+    if topic_id == entities.store.main_device() {
+        return Err(EntityError::MainDevice);
+    }
+
+    let mut messages = vec![];
+    clear_retained_messages(entities, topic_id, &mut messages);
+    Ok(messages)
+}
+
+fn clear_retained_messages(
+    entities: &Entities,
+    topic_id: &EntityTopicId,
+    messages: &mut Vec<Message>,
+) {
+    for child in children(entities, topic_id) {
+        clear_retained_messages(entities, child, messages);
+    }
+
+    let mut topics: Vec<_> = entities
+        .retained_topics(topic_id)
+        .map(|(topic, _)| topic.clone())
+        .collect();
+    topics.sort_by(|a, b| a.name.cmp(&b.name));
+    topics.push(
+        entities
+            .mqtt_schema
+            .topic_for(topic_id, &Channel::EntityMetadata),
+    );
+
+    messages.extend(
+        topics
+            .into_iter()
+            .map(|topic| Message::new(&topic, "").with_retain()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::entity::store::tests::entities;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn deregistration_clears_descendants_twin_and_capabilities() {
+        let entities = entities(&[
+            ("te/device/child1//", r#"{"@type":"child-device"}"#),
+            ("te/device/child1///twin/name", r#""Child 1""#),
+            ("te/device/child1///cmd/restart", "{}"),
+            (
+                "te/device/child1/service/collectd",
+                r#"{"@type":"service","@parent":"device/child1//"}"#,
+            ),
+            (
+                "te/device/child11//",
+                r#"{"@type":"child-device","@parent":"device/child1//"}"#,
+            ),
+            ("te/device/child11///cmd/restart", "{}"),
+            ("te/device/child2//", r#"{"@type":"child-device"}"#),
+        ]);
+
+        let child1 = EntityTopicId::default_child_device("child1").unwrap();
+        let messages = deregistration_messages(&entities, &child1).unwrap();
+
+        assert!(messages
+            .iter()
+            .all(|message| message.retain && message.payload_bytes().is_empty()));
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.topic.name.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "te/device/child11///cmd/restart",
+                "te/device/child11//",
+                "te/device/child1/service/collectd",
+                "te/device/child1///cmd/restart",
+                "te/device/child1///twin/name",
+                "te/device/child1//",
+            ]
+        );
+    }
+
+    #[test]
+    fn main_and_unknown_entities_cannot_be_deregistered() {
+        let entities = entities(&[]);
+
+        assert_matches!(
+            deregistration_messages(&entities, &EntityTopicId::default_main_device()),
+            Err(EntityError::MainDevice)
+        );
+        assert_matches!(
+            deregistration_messages(
+                &entities,
+                &EntityTopicId::default_child_device("unknown").unwrap()
+            ),
+            Err(EntityError::UnknownEntity(_))
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use tedge_api::entity_store;
+use tedge_api::mqtt_topics::EntityTopicId;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EntityError {
+    #[error("Failed to create the MQTT client options")]
+    MqttOptions(#[from] rumqttc::tokio_rustls::rustls::Error),
+
+    #[error("MQTT client error")]
+    MqttClient(#[from] rumqttc::ClientError),
+
+    #[error("MQTT connection error: {0}\n\nHint: Is MQTT server running?")]
+    MqttConnection(#[from] rumqttc::ConnectionError),
+
+    #[error("Timeout while waiting for the MQTT server")]
+    Timeout,
+
+    #[error("Failed to create a temporary entity store")]
+    TempDir(#[source] std::io::Error),
+
+    #[error("Failed to build the entity store")]
+    EntityStoreInit(#[from] entity_store::InitError),
+
+    #[error(transparent)]
+    EntityStore(#[from] entity_store::Error),
+
+    #[error("No such entity: {0}")]
+    UnknownEntity(EntityTopicId),
+
+    #[error("The entity type of {0} cannot be derived from its topic id: use --entity-type")]
+    MissingEntityType(EntityTopicId),
+
+    #[error("The main device cannot be registered nor deregistered")]
+    MainDevice,
+}
@@ -0,0 +1,53 @@
+use crate::cli::entity::store::EntityClient;
+use crate::command::Command;
+use serde_json::Value as JsonValue;
+use tedge_api::mqtt_topics::EntityTopicId;
+
+/// Show the registration, twin data and capabilities of an entity
+pub struct GetEntityCmd {
+    pub client: EntityClient,
+
+    /// The entity topic id
+    pub topic_id: EntityTopicId,
+}
+
+impl Command for GetEntityCmd {
+    fn description(&self) -> String {
+        format!("get the entity {}", self.topic_id)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let entities = self.client.read_entities()?;
+        let entity = entities.get(&self.topic_id)?;
+
+        println!("topic_id={}", entity.topic_id);
+        println!("@type={}", entity.r#type);
+        println!("@id={}", entity.external_id.as_ref());
+        if let Some(parent) = &entity.parent {
+            println!("@parent={parent}");
+        }
+        let ancestors = entities.store.ancestors_external_ids(&entity.topic_id)?;
+        if !ancestors.is_empty() {
+            println!("ancestors={}", ancestors.join(","));
+        }
+        for (key, value) in entity.other.iter() {
+            println!("{key}={}", display_value(value));
+        }
+        for (key, value) in entity.twin_data.iter() {
+            println!("twin.{key}={}", display_value(value));
+        }
+        let capabilities = entities.capabilities(&entity.topic_id);
+        if !capabilities.is_empty() {
+            println!("capabilities={}", capabilities.join(","));
+        }
+        Ok(())
+    }
+}
+
+/// Display string values as is, and other values as JSON
+fn display_value(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,35 @@
+use crate::cli::entity::store::EntityClient;
+use crate::command::Command;
+
+/// List the registered entities
+pub struct ListEntityCmd {
+    pub client: EntityClient,
+}
+
+impl Command for ListEntityCmd {
+    fn description(&self) -> String {
+        "list the registered entities".to_string()
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let entities = self.client.read_entities()?;
+        println!(
+            "{:<40} {:<13} {:<40} PARENT",
+            "TOPIC ID", "TYPE", "EXTERNAL ID"
+        );
+        for entity in entities.sorted() {
+            println!(
+                "{:<40} {:<13} {:<40} {}",
+                entity.topic_id,
+                entity.r#type,
+                entity.external_id.as_ref(),
+                entity
+                    .parent
+                    .as_ref()
+                    .map(|parent| parent.as_str())
+                    .unwrap_or_default(),
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,10 @@
+pub use self::cli::TEdgeEntityCli;
+
+mod cli;
+mod deregister;
+mod error;
+mod get;
+mod list;
+mod register;
+mod store;
+mod tree;
@@ -0,0 +1,98 @@
+use crate::cli::entity::error::EntityError;
+use crate::cli::entity::store::EntityClient;
+use crate::command::Command;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityType;
+use tedge_api::mqtt_topics::EntityTopicId;
+
+/// Register a child device or a service
+pub struct RegisterEntityCmd {
+    pub client: EntityClient,
+
+    /// The entity topic id
+    pub topic_id: EntityTopicId,
+
+    /// The entity type, derived from the topic id if not provided
+    pub entity_type: Option<EntityType>,
+
+    /// The parent device, derived from the topic id if not provided
+    pub parent: Option<EntityTopicId>,
+
+    /// The cloud external id, derived from the topic id if not provided
+    pub external_id: Option<String>,
+
+    /// The display name of the entity
+    pub name: Option<String>,
+
+    /// The device or service type
+    pub ty: Option<String>,
+}
+
+impl Command for RegisterEntityCmd {
+    fn description(&self) -> String {
+        format!("register the entity {}", self.topic_id)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let mut entities = self.client.read_entities()?;
+        if &self.topic_id == entities.store.main_device() {
+            return Err(EntityError::MainDevice.into());
+        }
+
+        let entity_type = match &self.entity_type {
+            Some(entity_type) => entity_type.clone(),
+            None => default_entity_type(&self.topic_id)?,
+        };
+
+        // The parent must be registered before its child devices and services
+        let parent = self
+            .parent
+            .clone()
+            .or_else(|| match entity_type {
+                EntityType::Service => self.topic_id.default_parent_identifier(),
+                _ => None,
+            })
+            .unwrap_or_else(|| entities.store.main_device().clone());
+        entities.get(&parent)?;
+
+        let mut registration =
+            EntityRegistrationMessage::new_custom(self.topic_id.clone(), entity_type);
+        if let Some(parent) = &self.parent {
+            registration = registration.with_parent(parent.clone());
+        }
+        if let Some(external_id) = &self.external_id {
+            registration = registration.with_external_id(external_id.into());
+        }
+        if let Some(name) = &self.name {
+            registration =
+                registration.with_other_fragment("name".to_string(), name.clone().into());
+        }
+        if let Some(ty) = &self.ty {
+            registration = registration.with_other_fragment("type".to_string(), ty.clone().into());
+        }
+
+        // Check the registration as the mappers will do
+        entities.store.update(registration.clone())?;
+        let external_id = entities.get(&self.topic_id)?.external_id.clone();
+
+        let message = registration.to_mqtt_message(&entities.mqtt_schema);
+        self.client.publish(vec![message])?;
+        println!(
+            "Registered {} with external id {}",
+            self.topic_id,
+            external_id.as_ref()
+        );
+        Ok(())
+    }
+}
+
+/// Derive the entity type from a topic id following the default topic scheme
+fn default_entity_type(topic_id: &EntityTopicId) -> Result<EntityType, EntityError> {
+    if topic_id.default_service_name().is_some() {
+        Ok(EntityType::Service)
+    } else if topic_id.is_default_child_device() {
+        Ok(EntityType::ChildDevice)
+    } else {
+        Err(EntityError::MissingEntityType(topic_id.clone()))
+    }
+}
@@ -0,0 +1,373 @@
+use crate::cli::entity::error::EntityError;
+use mqtt_channel::Message;
+use mqtt_channel::Topic;
+use rumqttc::Event;
+use rumqttc::Packet;
+use rumqttc::QoS;
+use rumqttc::RecvTimeoutError;
+use rumqttc::SubscribeFilter;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::time::Duration;
+use tedge_api::entity_store::EntityExternalId;
+use tedge_api::entity_store::EntityMetadata;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityStore;
+use tedge_api::entity_store::EntityTwinMessage;
+use tedge_api::entity_store::EntityType;
+use tedge_api::entity_store::InvalidExternalIdError;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tempfile::TempDir;
+
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for more retained messages, before assuming all have been received
+const RETAINED_MESSAGES_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The characters that cannot be used in an external id, as used in MQTT topic filters
+const FORBIDDEN_ID_CHARS: [char; 3] = ['/', '+', '#'];
+
+/// A client to the local MQTT broker, where the entities are registered using retained messages
+pub struct EntityClient {
+    pub mqtt_config: mqtt_channel::Config,
+    pub mqtt_schema: MqttSchema,
+    pub main_device_xid: EntityExternalId,
+}
+
+impl EntityClient {
+    /// Read the entities from the retained registration, twin and capability messages
+    pub fn read_entities(&self) -> Result<Entities, EntityError> {
+        let messages = self.retained_messages()?;
+        Entities::from_retained_messages(
+            self.mqtt_schema.clone(),
+            self.main_device_xid.clone(),
+            messages,
+        )
+    }
+
+    /// Collect the retained messages published on the entity registration, twin and capability topics
+    fn retained_messages(&self) -> Result<Vec<Message>, EntityError> {
+        let twin_data = format!("{}/+/+/+/+/twin/+", self.mqtt_schema.root);
+        let filters: Vec<SubscribeFilter> = [
+            ChannelFilter::EntityMetadata,
+            ChannelFilter::AnyCommandMetadata,
+        ]
+        .into_iter()
+        .flat_map(|channel| {
+            self.mqtt_schema
+                .topics(EntityFilter::AnyEntity, channel)
+                .patterns
+        })
+        .chain(std::iter::once(twin_data))
+        .map(|pattern| SubscribeFilter::new(pattern, QoS::AtLeastOnce))
+        .collect();
+
+        let (mut client, mut connection) = self.connect("tedge-entity-read", 10)?;
+        client.subscribe_many(filters)?;
+
+        let mut messages = vec![];
+        let mut subscribed = false;
+        loop {
+            let timeout = if subscribed {
+                RETAINED_MESSAGES_TIMEOUT
+            } else {
+                CONNECTION_TIMEOUT
+            };
+            match connection.recv_timeout(timeout) {
+                Ok(Ok(Event::Incoming(Packet::SubAck(_)))) => subscribed = true,
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    if publish.retain && !publish.payload.is_empty() {
+                        messages.push(Message::from(publish))
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => return Err(err.into()),
+                Err(RecvTimeoutError::Timeout) if subscribed => break,
+                Err(RecvTimeoutError::Timeout) => return Err(EntityError::Timeout),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let _ = client.disconnect();
+        Ok(messages)
+    }
+
+    /// Publish the given messages, waiting for these messages to be acknowledged
+    pub fn publish(&self, messages: Vec<Message>) -> Result<(), EntityError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        // All the messages are queued before the connection is polled
+        let mut pending = messages.len();
+        let (mut client, mut connection) = self.connect("tedge-entity-publish", pending + 1)?;
+        for message in messages {
+            client.publish(
+                message.topic.name,
+                QoS::AtLeastOnce,
+                message.retain,
+                message.payload.as_bytes().to_vec(),
+            )?;
+        }
+
+        while pending > 0 {
+            match connection.recv_timeout(CONNECTION_TIMEOUT) {
+                Ok(Ok(Event::Incoming(Packet::PubAck(_)))) => pending -= 1,
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => return Err(err.into()),
+                Err(_) => return Err(EntityError::Timeout),
+            }
+        }
+
+        let _ = client.disconnect();
+        Ok(())
+    }
+
+    fn connect(
+        &self,
+        client_prefix: &str,
+        queue_capacity: usize,
+    ) -> Result<(rumqttc::Client, rumqttc::Connection), EntityError> {
+        let mqtt_options = self
+            .mqtt_config
+            .clone()
+            .with_session_name(format!("{client_prefix}-{}", std::process::id()))
+            .with_clean_session(true)
+            .rumqttc_options()?;
+
+        let (client, mut connection) = rumqttc::Client::new(mqtt_options, queue_capacity);
+        connection
+            .eventloop
+            .network_options
+            .set_connection_timeout(CONNECTION_TIMEOUT.as_secs());
+        Ok((client, connection))
+    }
+}
+
+/// The entities registered on the local MQTT broker
+///
+/// The entities are stored in an [EntityStore] as done by the mappers,
+/// along the retained twin and capability topics of each entity.
+pub struct Entities {
+    pub mqtt_schema: MqttSchema,
+    pub store: EntityStore,
+    retained_topics: HashMap<EntityTopicId, Vec<(Topic, Channel)>>,
+
+    // The entity store has to persist its content, even if used only temporarily
+    _log_dir: TempDir,
+}
+
+impl Entities {
+    pub fn from_retained_messages(
+        mqtt_schema: MqttSchema,
+        main_device_xid: EntityExternalId,
+        messages: Vec<Message>,
+    ) -> Result<Self, EntityError> {
+        let log_dir = tempfile::tempdir().map_err(EntityError::TempDir)?;
+        let main_device = EntityRegistrationMessage::main_device(main_device_xid.into());
+        let mut store = EntityStore::with_main_device_and_default_service_type(
+            mqtt_schema.clone(),
+            main_device,
+            "service".to_string(),
+            default_external_id,
+            validate_external_id,
+            0,
+            log_dir.path(),
+        )?;
+
+        let mut twin_messages = vec![];
+        let mut retained_topics: HashMap<EntityTopicId, Vec<(Topic, Channel)>> = HashMap::new();
+        for message in messages {
+            let Ok((entity, channel)) = mqtt_schema.entity_channel_of(&message.topic) else {
+                continue;
+            };
+            match channel {
+                Channel::EntityMetadata => {
+                    let Ok(registration) = EntityRegistrationMessage::try_from(&message) else {
+                        continue;
+                    };
+                    if registration.r#type != EntityType::MainDevice {
+                        if let Err(err) = store.update(registration) {
+                            eprintln!("WARNING: Ignoring the registration of {entity}: {err}");
+                        }
+                    }
+                }
+                Channel::EntityTwinData { ref fragment_key } => {
+                    if let Ok(value) = serde_json::from_slice::<JsonValue>(message.payload_bytes())
+                    {
+                        twin_messages.push(EntityTwinMessage::new(
+                            entity.clone(),
+                            fragment_key.clone(),
+                            value,
+                        ));
+                    }
+                    retained_topics
+                        .entry(entity)
+                        .or_default()
+                        .push((message.topic, channel));
+                }
+                Channel::CommandMetadata { .. } => {
+                    retained_topics
+                        .entry(entity)
+                        .or_default()
+                        .push((message.topic, channel));
+                }
+                _ => {}
+            }
+        }
+
+        // The twin data can only be attached to the entities once all are registered
+        for twin_message in twin_messages {
+            let _ = store.register_twin_data(twin_message);
+        }
+
+        Ok(Entities {
+            mqtt_schema,
+            store,
+            retained_topics,
+            _log_dir: log_dir,
+        })
+    }
+
+    /// Return the metadata of the given entity, or an error if not registered
+    pub fn get(&self, topic_id: &EntityTopicId) -> Result<&EntityMetadata, EntityError> {
+        self.store
+            .get(topic_id)
+            .ok_or_else(|| EntityError::UnknownEntity(topic_id.clone()))
+    }
+
+    /// The registered entities sorted by topic id
+    pub fn sorted(&self) -> Vec<&EntityMetadata> {
+        let mut entities: Vec<_> = self.store.iter().map(|(_, entity)| entity).collect();
+        entities.sort_by(|a, b| a.topic_id.as_str().cmp(b.topic_id.as_str()));
+        entities
+    }
+
+    /// The operations supported by an entity, as published on its capability topics
+    pub fn capabilities(&self, topic_id: &EntityTopicId) -> Vec<String> {
+        let mut capabilities: Vec<String> = self
+            .retained_topics(topic_id)
+            .filter_map(|(_, channel)| match channel {
+                Channel::CommandMetadata { operation } => Some(operation.to_string()),
+                _ => None,
+            })
+            .collect();
+        capabilities.sort();
+        capabilities
+    }
+
+    /// The retained twin and capability topics of an entity
+    pub fn retained_topics(
+        &self,
+        topic_id: &EntityTopicId,
+    ) -> impl Iterator<Item = &(Topic, Channel)> {
+        self.retained_topics.get(topic_id).into_iter().flatten()
+    }
+}
+
+/// Derive the external id of an entity from its topic id, as done by the mappers
+fn default_external_id(
+    entity_topic_id: &EntityTopicId,
+    main_device_xid: &EntityExternalId,
+) -> EntityExternalId {
+    if entity_topic_id.is_default_main_device() {
+        main_device_xid.clone()
+    } else {
+        format!(
+            "{}:{}",
+            main_device_xid.as_ref(),
+            entity_topic_id
+                .to_string()
+                .trim_end_matches('/')
+                .replace('/', ":")
+        )
+        .into()
+    }
+}
+
+fn validate_external_id(id: &str) -> Result<EntityExternalId, InvalidExternalIdError> {
+    match id.chars().find(|c| FORBIDDEN_ID_CHARS.contains(c)) {
+        Some(invalid_char) => Err(InvalidExternalIdError {
+            external_id: id.into(),
+            invalid_char,
+        }),
+        None => Ok(id.into()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn entities(messages: &[(&str, &str)]) -> Entities {
+        let messages = messages
+            .iter()
+            .map(|(topic, payload)| {
+                Message::new(&Topic::new_unchecked(topic), payload.to_string()).with_retain()
+            })
+            .collect();
+        Entities::from_retained_messages(MqttSchema::default(), "my-device".into(), messages)
+            .unwrap()
+    }
+
+    #[test]
+    fn entities_are_built_from_retained_messages() {
+        let entities = entities(&[
+            // a service registered before its parent
+            (
+                "te/device/child1/service/collectd",
+                r#"{"@type":"service","@parent":"device/child1//"}"#,
+            ),
+            ("te/device/child1//", r#"{"@type":"child-device"}"#),
+            ("te/device/child1///twin/name", r#""Child 1""#),
+            ("te/device/child1///cmd/restart", "{}"),
+            ("te/device/child1///cmd/software_update", "{}"),
+            (
+                "te/device/child2//",
+                r#"{"@type":"child-device","@id":"child-two"}"#,
+            ),
+        ]);
+
+        let child1 = EntityTopicId::default_child_device("child1").unwrap();
+        let service = EntityTopicId::default_child_service("child1", "collectd").unwrap();
+        let child2 = EntityTopicId::default_child_device("child2").unwrap();
+
+        assert_eq!(
+            entities.get(&child1).unwrap().external_id.as_ref(),
+            "my-device:device:child1"
+        );
+        assert_eq!(
+            entities.get(&child1).unwrap().twin_data.get("name"),
+            Some(&JsonValue::from("Child 1"))
+        );
+        assert_eq!(
+            entities.capabilities(&child1),
+            vec!["restart".to_string(), "software_update".to_string()]
+        );
+        assert_eq!(
+            entities.get(&service).unwrap().external_id.as_ref(),
+            "my-device:device:child1:service:collectd"
+        );
+        assert_eq!(
+            entities.get(&child2).unwrap().external_id.as_ref(),
+            "child-two"
+        );
+        assert_eq!(
+            entities
+                .sorted()
+                .iter()
+                .map(|e| e.topic_id.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "device/child1//",
+                "device/child1/service/collectd",
+                "device/child2//",
+                "device/main//"
+            ]
+        );
+    }
+}
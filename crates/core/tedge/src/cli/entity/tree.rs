@@ -0,0 +1,107 @@
+use crate::cli::entity::store::Entities;
+use crate::cli::entity::store::EntityClient;
+use crate::command::Command;
+use std::fmt::Write;
+use tedge_api::mqtt_topics::EntityTopicId;
+
+/// Display the hierarchy of entities
+pub struct EntityTreeCmd {
+    pub client: EntityClient,
+}
+
+impl Command for EntityTreeCmd {
+    fn description(&self) -> String {
+        "display the hierarchy of entities".to_string()
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let entities = self.client.read_entities()?;
+        print!("{}", render_tree(&entities));
+        Ok(())
+    }
+}
+
+/// Render the entity hierarchy, starting from the main device
+///
+/// Each entity is displayed with its external id and topic id,
+/// the child devices of a device being listed before its services.
+pub(crate) fn render_tree(entities: &Entities) -> String {
+    let mut output = String::new();
+    let main_device = entities.store.main_device();
+    write_entity(&mut output, entities, main_device);
+    write_children(&mut output, entities, main_device, "");
+    output
+}
+
+fn write_children(output: &mut String, entities: &Entities, parent: &EntityTopicId, indent: &str) {
+    let children = children(entities, parent);
+    let count = children.len();
+    for (i, child) in children.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let (branch, next_indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        output.push_str(indent);
+        output.push_str(branch);
+        write_entity(output, entities, child);
+        write_children(output, entities, child, &format!("{indent}{next_indent}"));
+    }
+}
+
+fn write_entity(output: &mut String, entities: &Entities, topic_id: &EntityTopicId) {
+    if let Some(entity) = entities.store.get(topic_id) {
+        let _ = writeln!(output, "{} ({topic_id})", entity.external_id.as_ref());
+    }
+}
+
+/// The child devices then the services of a device, each sorted by topic id
+pub(crate) fn children<'a>(
+    entities: &'a Entities,
+    parent: &EntityTopicId,
+) -> Vec<&'a EntityTopicId> {
+    let mut child_devices = entities.store.child_devices(parent);
+    child_devices.sort_by_key(|id| id.as_str());
+    let mut services = entities.store.services(parent);
+    services.sort_by_key(|id| id.as_str());
+    child_devices.extend(services);
+    child_devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::entity::store::tests::entities;
+
+    #[test]
+    fn render_entity_hierarchy() {
+        let entities = entities(&[
+            ("te/device/child1//", r#"{"@type":"child-device"}"#),
+            (
+                "te/device/child1/service/collectd",
+                r#"{"@type":"service","@parent":"device/child1//"}"#,
+            ),
+            (
+                "te/device/child11//",
+                r#"{"@type":"child-device","@parent":"device/child1//","@id":"nested"}"#,
+            ),
+            ("te/device/child2//", r#"{"@type":"child-device"}"#),
+            (
+                "te/device/main/service/tedge-agent",
+                r#"{"@type":"service","@parent":"device/main//"}"#,
+            ),
+        ]);
+
+        assert_eq!(
+            render_tree(&entities),
+            r#"my-device (device/main//)
+├── my-device:device:child1 (device/child1//)
+│   ├── nested (device/child11//)
+│   └── my-device:device:child1:service:collectd (device/child1/service/collectd)
+├── my-device:device:child2 (device/child2//)
+└── my-device:device:main:service:tedge-agent (device/main/service/tedge-agent)
+"#
+        );
+    }
+}
@@ -20,10 +20,13 @@ mod common;
 pub mod config;
 mod connect;
 mod disconnect;
+mod entity;
 mod init;
 mod mqtt;
+mod operation;
 mod reconnect;
 mod refresh_bridges;
+mod workflow;
 
 #[derive(clap::Parser, Debug)]
 #[clap(
@@ -112,6 +115,18 @@ pub enum TEdgeOpt {
     /// Publish a message on a topic and subscribe a topic.
     #[clap(subcommand)]
     Mqtt(mqtt::TEdgeMqttCli),
+
+    /// Check and display user-defined operation workflows
+    #[clap(subcommand)]
+    Workflow(workflow::TEdgeWorkflowCli),
+
+    /// Inspect and cancel the commands processed by the agent
+    #[clap(subcommand)]
+    Operation(operation::TEdgeOperationCli),
+
+    /// Register, inspect and deregister child devices and services
+    #[clap(subcommand)]
+    Entity(entity::TEdgeEntityCli),
 }
 
 fn styles() -> clap::builder::Styles {
@@ -172,6 +187,9 @@ impl BuildCommand for TEdgeOpt {
             TEdgeOpt::RefreshBridges => RefreshBridgesCmd::new(&context).map(Command::into_boxed),
             TEdgeOpt::Mqtt(opt) => opt.build_command(context),
             TEdgeOpt::Reconnect(opt) => opt.build_command(context),
+            TEdgeOpt::Workflow(opt) => opt.build_command(context),
+            TEdgeOpt::Operation(opt) => opt.build_command(context),
+            TEdgeOpt::Entity(opt) => opt.build_command(context),
         }
     }
 }
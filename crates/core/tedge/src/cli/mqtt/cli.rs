@@ -66,6 +66,7 @@ impl BuildCommand for TEdgeMqttCli {
                     ca_file: auth_config.ca_file.clone(),
                     ca_dir: auth_config.ca_dir,
                     client_auth_config: auth_config.client,
+                    cryptoki: config.cryptoki_config(),
                 }
                 .into_boxed(),
                 TEdgeMqttCli::Sub {
@@ -82,6 +83,7 @@ impl BuildCommand for TEdgeMqttCli {
                     ca_file: auth_config.ca_file,
                     ca_dir: auth_config.ca_dir,
                     client_auth_config: auth_config.client,
+                    cryptoki: config.cryptoki_config(),
                 }
                 .into_boxed(),
             }
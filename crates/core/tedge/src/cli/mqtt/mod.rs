@@ -1,5 +1,6 @@
 pub use self::cli::TEdgeMqttCli;
 pub use self::error::MqttError;
+pub use self::publish::MqttPublishCommand;
 
 mod cli;
 mod error;
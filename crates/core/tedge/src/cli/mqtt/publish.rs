@@ -2,6 +2,8 @@ use crate::cli::mqtt::MqttError;
 use crate::command::Command;
 use camino::Utf8PathBuf;
 use certificate::parse_root_certificate;
+use certificate::CryptokiConfig;
+use certificate::KeyProvider;
 use rumqttc::tokio_rustls::rustls::ClientConfig;
 use rumqttc::tokio_rustls::rustls::RootCertStore;
 use rumqttc::Event;
@@ -18,6 +20,7 @@ use tedge_config::MqttAuthClientConfig;
 const DEFAULT_QUEUE_CAPACITY: usize = 10;
 use super::MAX_PACKET_SIZE;
 
+#[derive(Clone)]
 pub struct MqttPublishCommand {
     pub host: String,
     pub port: u16,
@@ -30,6 +33,7 @@ pub struct MqttPublishCommand {
     pub ca_file: Option<Utf8PathBuf>,
     pub ca_dir: Option<Utf8PathBuf>,
     pub client_auth_config: Option<MqttAuthClientConfig>,
+    pub cryptoki: Option<CryptokiConfig>,
 }
 
 impl Command for MqttPublishCommand {
@@ -77,8 +81,8 @@ fn publish(cmd: &MqttPublishCommand) -> Result<(), MqttError> {
 
         let tls_config = if let Some(client_auth) = cmd.client_auth_config.as_ref() {
             let client_cert = parse_root_certificate::read_cert_chain(&client_auth.cert_file)?;
-            let client_key = parse_root_certificate::read_pvt_key(&client_auth.key_file)?;
-            tls_config.with_client_auth_cert(client_cert, client_key)?
+            let client_key = KeyProvider::new(&client_auth.key_file, cmd.cryptoki.as_ref())?;
+            tls_config.with_client_cert_resolver(client_key.client_cert_resolver(client_cert)?)
         } else {
             tls_config.with_no_client_auth()
         };
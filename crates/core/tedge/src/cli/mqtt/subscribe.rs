@@ -2,6 +2,8 @@ use crate::cli::mqtt::MqttError;
 use crate::command::Command;
 use camino::Utf8PathBuf;
 use certificate::parse_root_certificate;
+use certificate::CryptokiConfig;
+use certificate::KeyProvider;
 use rumqttc::tokio_rustls::rustls::ClientConfig;
 use rumqttc::tokio_rustls::rustls::RootCertStore;
 use rumqttc::Client;
@@ -25,6 +27,7 @@ pub struct MqttSubscribeCommand {
     pub ca_file: Option<Utf8PathBuf>,
     pub ca_dir: Option<Utf8PathBuf>,
     pub client_auth_config: Option<MqttAuthClientConfig>,
+    pub cryptoki: Option<CryptokiConfig>,
 }
 
 impl Command for MqttSubscribeCommand {
@@ -72,8 +75,8 @@ fn subscribe(cmd: &MqttSubscribeCommand) -> Result<(), MqttError> {
 
         let tls_config = if let Some(client_auth) = cmd.client_auth_config.as_ref() {
             let client_cert = parse_root_certificate::read_cert_chain(&client_auth.cert_file)?;
-            let client_key = parse_root_certificate::read_pvt_key(&client_auth.key_file)?;
-            tls_config.with_client_auth_cert(client_cert, client_key)?
+            let client_key = KeyProvider::new(&client_auth.key_file, cmd.cryptoki.as_ref())?;
+            tls_config.with_client_cert_resolver(client_key.client_cert_resolver(client_cert)?)
         } else {
             tls_config.with_no_client_auth()
         };
@@ -0,0 +1,63 @@
+use crate::cli::mqtt::MqttPublishCommand;
+use crate::cli::operation::cli::read_history;
+use crate::cli::operation::error::OperationError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::TopicName;
+
+/// Request the agent to cancel a pending command
+pub struct CancelOperationCmd {
+    /// The path to the command history
+    pub history: Utf8PathBuf,
+
+    /// The command id or topic
+    pub command: String,
+
+    /// The cancellation reason, if any
+    pub reason: Option<String>,
+
+    /// Used to publish the cancel request, once the command topic is known
+    pub publisher: MqttPublishCommand,
+}
+
+impl Command for CancelOperationCmd {
+    fn description(&self) -> String {
+        format!("cancel the command {}", self.command)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let topic = self.pending_command_topic()?;
+        let topic = mqtt_channel::Topic::new_unchecked(&topic);
+        let request = GenericCommandState::cancel_request(topic, self.reason.clone());
+
+        let publish = MqttPublishCommand {
+            topic: request.topic.name.clone(),
+            message: request.payload_str()?.to_string(),
+            ..self.publisher.clone()
+        };
+        publish.execute()?;
+        println!("Cancel request sent for {}", request.topic.name);
+        Ok(())
+    }
+}
+
+impl CancelOperationCmd {
+    fn pending_command_topic(&self) -> Result<TopicName, OperationError> {
+        let history = read_history(&self.history)?;
+        let record = history
+            .find(&self.command)
+            .ok_or_else(|| OperationError::UnknownCommand {
+                command: self.command.clone(),
+            })?;
+        match record.status() {
+            Some(status) if record.completed || status == "successful" || status == "failed" => {
+                Err(OperationError::NotPending {
+                    command: self.command.clone(),
+                    status: status.to_string(),
+                })
+            }
+            _ => Ok(record.topic.clone()),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::cli::mqtt::MqttPublishCommand;
+use crate::cli::operation::cancel::CancelOperationCmd;
+use crate::cli::operation::error::OperationError;
+use crate::cli::operation::list::ListOperationCmd;
+use crate::cli::operation::show::ShowOperationCmd;
+use crate::command::BuildCommand;
+use crate::command::BuildContext;
+use crate::command::Command;
+use crate::ConfigError;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use std::time::Duration;
+use tedge_api::workflow::CommandHistory;
+use tedge_api::workflow::COMMAND_HISTORY_FILE;
+
+const CANCEL_CLIENT_PREFIX: &str = "tedge-operation-cancel";
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeOperationCli {
+    /// List the pending and latest completed commands
+    List {
+        /// Only list the commands of this operation
+        #[clap(long)]
+        operation: Option<String>,
+    },
+
+    /// Show the state transitions of a command
+    Show {
+        /// The command id or topic
+        command: String,
+    },
+
+    /// Request the agent to cancel a pending command
+    ///
+    /// The agent moves the command to the `failed` state, with a cancellation reason.
+    Cancel {
+        /// The command id or topic
+        command: String,
+
+        /// The cancellation reason
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+impl BuildCommand for TEdgeOperationCli {
+    fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, ConfigError> {
+        let config = context.config_repository.load()?;
+        let history = history_path(
+            &config.agent.state.path,
+            &context.config_location.tedge_config_root_path,
+        );
+
+        let cmd = match self {
+            TEdgeOperationCli::List { operation } => {
+                ListOperationCmd { history, operation }.into_boxed()
+            }
+            TEdgeOperationCli::Show { command } => {
+                ShowOperationCmd { history, command }.into_boxed()
+            }
+            TEdgeOperationCli::Cancel { command, reason } => {
+                let auth_config = config.mqtt_client_auth_config();
+                let publisher = MqttPublishCommand {
+                    host: config.mqtt.client.host.clone(),
+                    port: config.mqtt.client.port.into(),
+                    topic: String::new(),
+                    message: String::new(),
+                    qos: rumqttc::QoS::AtLeastOnce,
+                    client_id: format!("{}-{}", CANCEL_CLIENT_PREFIX, std::process::id()),
+                    disconnect_timeout: DISCONNECT_TIMEOUT,
+                    retain: false,
+                    ca_file: auth_config.ca_file,
+                    ca_dir: auth_config.ca_dir,
+                    client_auth_config: auth_config.client,
+                    cryptoki: config.cryptoki_config(),
+                };
+                CancelOperationCmd {
+                    history,
+                    command,
+                    reason,
+                    publisher,
+                }
+                .into_boxed()
+            }
+        };
+        Ok(cmd)
+    }
+}
+
+/// The path to the command history persisted by the agent
+///
+/// As the agent, fallback to the `.agent` directory of the tedge root directory,
+/// if there is no history in the agent state directory.
+fn history_path(state_dir: &Utf8Path, config_dir: &Utf8Path) -> Utf8PathBuf {
+    let path = state_dir.join(COMMAND_HISTORY_FILE);
+    if path.exists() {
+        return path;
+    }
+    let fallback = config_dir.join(".agent").join(COMMAND_HISTORY_FILE);
+    if fallback.exists() {
+        return fallback;
+    }
+    path
+}
+
+/// Read the command history persisted by the agent
+///
+/// An empty history is returned if the agent has not processed any command yet.
+pub(crate) fn read_history(path: &Utf8Path) -> Result<CommandHistory, OperationError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(OperationError::ReadError {
+                path: path.to_owned(),
+                source,
+            })
+        }
+    };
+    Ok(CommandHistory::from_log(
+        &content,
+        CommandHistory::DEFAULT_CAPACITY,
+    ))
+}
+
+/// Format a unix timestamp using RFC 3339
+pub(crate) fn format_timestamp(unix_timestamp: Option<i64>) -> String {
+    unix_timestamp
+        .and_then(|t| time::OffsetDateTime::from_unix_timestamp(t).ok())
+        .and_then(|t| {
+            t.format(&time::format_description::well_known::Rfc3339)
+                .ok()
+        })
+        .unwrap_or_default()
+}
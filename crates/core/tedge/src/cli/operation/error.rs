@@ -0,0 +1,17 @@
+use camino::Utf8PathBuf;
+
+#[derive(thiserror::Error, Debug)]
+pub enum OperationError {
+    #[error("Failed to read the command history {path}")]
+    ReadError {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("No such command: {command}")]
+    UnknownCommand { command: String },
+
+    #[error("The {command} command is no more pending: {status}")]
+    NotPending { command: String, status: String },
+}
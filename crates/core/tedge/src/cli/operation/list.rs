@@ -0,0 +1,43 @@
+use crate::cli::operation::cli::format_timestamp;
+use crate::cli::operation::cli::read_history;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+
+/// List the pending and latest completed commands
+pub struct ListOperationCmd {
+    /// The path to the command history
+    pub history: Utf8PathBuf,
+
+    /// Only list the commands of this operation, if any
+    pub operation: Option<String>,
+}
+
+impl Command for ListOperationCmd {
+    fn description(&self) -> String {
+        format!("list the commands recorded in {}", self.history)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let history = read_history(&self.history)?;
+        println!(
+            "{:<24} {:<20} {:<12} {:<9} UPDATED",
+            "CMD ID", "OPERATION", "STATUS", "PENDING"
+        );
+        for record in history.iter() {
+            if let Some(operation) = &self.operation {
+                if &record.operation != operation {
+                    continue;
+                }
+            }
+            println!(
+                "{:<24} {:<20} {:<12} {:<9} {}",
+                record.cmd_id,
+                record.operation,
+                record.status().unwrap_or_default(),
+                if record.completed { "no" } else { "yes" },
+                format_timestamp(record.updated_at()),
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+pub use self::cli::TEdgeOperationCli;
+
+mod cancel;
+mod cli;
+mod error;
+mod list;
+mod show;
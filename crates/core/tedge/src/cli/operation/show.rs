@@ -0,0 +1,46 @@
+use crate::cli::operation::cli::format_timestamp;
+use crate::cli::operation::cli::read_history;
+use crate::cli::operation::error::OperationError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+
+/// Show the state transitions of a command
+pub struct ShowOperationCmd {
+    /// The path to the command history
+    pub history: Utf8PathBuf,
+
+    /// The command id or topic
+    pub command: String,
+}
+
+impl Command for ShowOperationCmd {
+    fn description(&self) -> String {
+        format!("show the state transitions of the command {}", self.command)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let history = read_history(&self.history)?;
+        let record = history
+            .find(&self.command)
+            .ok_or_else(|| OperationError::UnknownCommand {
+                command: self.command.clone(),
+            })?;
+
+        println!("topic:     {}", record.topic);
+        println!("operation: {}", record.operation);
+        println!("status:    {}", record.status().unwrap_or_default());
+        if let Some(reason) = &record.reason {
+            println!("reason:    {reason}");
+        }
+        println!("pending:   {}", !record.completed);
+        println!("transitions:");
+        for transition in record.transitions.iter() {
+            println!(
+                "  {} {}",
+                format_timestamp(Some(transition.unix_timestamp)),
+                transition.status
+            );
+        }
+        Ok(())
+    }
+}
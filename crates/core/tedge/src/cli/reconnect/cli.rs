@@ -2,21 +2,30 @@ use crate::bridge::CommonMosquittoConfig;
 use crate::cli::common::Cloud;
 use crate::command::*;
 use tedge_config::system_services::service_manager;
+use tedge_config::ProfileName;
 
 use super::command::ReconnectBridgeCommand;
 
-use crate::bridge::AWS_CONFIG_FILENAME;
-use crate::bridge::AZURE_CONFIG_FILENAME;
-use crate::bridge::C8Y_CONFIG_FILENAME;
-
 #[derive(clap::Subcommand, Debug)]
 pub enum TEdgeReconnectCli {
     /// Remove bridge connection to Cumulocity.
-    C8y,
+    C8y {
+        /// The Cumulocity profile to reconnect, as configured under `c8y.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to Azure.
-    Az,
+    Az {
+        /// The Azure profile to reconnect, as configured under `az.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to AWS.
-    Aws,
+    Aws {
+        /// The AWS profile to reconnect, as configured under `aws.profiles.<name>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
 }
 
 impl BuildCommand for TEdgeReconnectCli {
@@ -26,37 +35,23 @@ impl BuildCommand for TEdgeReconnectCli {
         let service_manager = service_manager(&context.config_location.tedge_config_root_path)?;
         let common_mosquitto_config = CommonMosquittoConfig::default();
 
-        let cmd = match self {
-            TEdgeReconnectCli::C8y => ReconnectBridgeCommand {
-                config_location,
-                config_repository,
-                service_manager,
-                common_mosquitto_config,
-                config_file: C8Y_CONFIG_FILENAME.into(),
-                cloud: Cloud::C8y,
-                use_mapper: true,
-                use_agent: true,
-            },
-            TEdgeReconnectCli::Az => ReconnectBridgeCommand {
-                config_location,
-                config_repository,
-                service_manager,
-                common_mosquitto_config,
-                config_file: AZURE_CONFIG_FILENAME.into(),
-                cloud: Cloud::Azure,
-                use_mapper: true,
-                use_agent: false,
-            },
-            TEdgeReconnectCli::Aws => ReconnectBridgeCommand {
-                config_location,
-                config_repository,
-                service_manager,
-                common_mosquitto_config,
-                config_file: AWS_CONFIG_FILENAME.into(),
-                cloud: Cloud::Aws,
-                use_mapper: true,
-                use_agent: false,
-            },
+        let (cloud, profile, use_agent) = match self {
+            TEdgeReconnectCli::C8y { profile } => (Cloud::C8y, profile, true),
+            TEdgeReconnectCli::Az { profile } => (Cloud::Azure, profile, false),
+            TEdgeReconnectCli::Aws { profile } => (Cloud::Aws, profile, false),
+        };
+        let config = cloud.profile_config(config_repository.load()?, profile.as_ref())?;
+
+        let cmd = ReconnectBridgeCommand {
+            config_location,
+            config_repository,
+            service_manager,
+            common_mosquitto_config,
+            config_file: cloud.bridge_config_filename(&config),
+            cloud,
+            profile,
+            use_mapper: true,
+            use_agent,
         };
         Ok(cmd.into_boxed())
     }
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use tedge_config::system_services::SystemServiceManager;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfigLocation;
 use tedge_config::TEdgeConfigRepository;
 
@@ -16,6 +17,7 @@ pub struct ReconnectBridgeCommand {
     pub config_repository: TEdgeConfigRepository,
     pub config_file: String,
     pub cloud: Cloud,
+    pub profile: Option<ProfileName>,
     pub common_mosquitto_config: CommonMosquittoConfig,
     pub use_mapper: bool,
     pub use_agent: bool,
@@ -44,6 +46,7 @@ impl From<&ReconnectBridgeCommand> for DisconnectBridgeCommand {
             config_location: reconnect_cmd.config_location.clone(),
             config_file: reconnect_cmd.config_file.clone(),
             cloud: reconnect_cmd.cloud,
+            profile: reconnect_cmd.profile.clone(),
             use_mapper: reconnect_cmd.use_mapper,
             use_agent: reconnect_cmd.use_agent,
             service_manager: reconnect_cmd.service_manager.clone(),
@@ -57,6 +60,7 @@ impl From<&ReconnectBridgeCommand> for ConnectCommand {
             config_location: reconnect_cmd.config_location.clone(),
             config_repository: reconnect_cmd.config_repository.clone(),
             cloud: reconnect_cmd.cloud,
+            profile: reconnect_cmd.profile.clone(),
             is_test_connection: false,
             service_manager: reconnect_cmd.service_manager.clone(),
         }
@@ -3,8 +3,10 @@ use std::sync::Arc;
 use camino::Utf8PathBuf;
 use tedge_config::system_services::SystemService;
 use tedge_config::system_services::SystemServiceManager;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfig;
 use tedge_config::TEdgeConfigLocation;
+use tedge_config::UnknownProfile;
 
 use super::common::Cloud;
 use super::connect::ConnectError;
@@ -26,9 +28,9 @@ impl Command for RefreshBridgesCmd {
     }
 
     fn execute(&self) -> anyhow::Result<()> {
-        let clouds = established_bridges(&self.config_location);
+        let bridges = established_bridges(&self.config, &self.config_location)?;
 
-        if clouds.is_empty() {
+        if bridges.is_empty() {
             println!("No bridges to refresh.");
             return Ok(());
         }
@@ -36,10 +38,18 @@ impl Command for RefreshBridgesCmd {
         let common_mosquitto_config = CommonMosquittoConfig::from_tedge_config(&self.config);
         common_mosquitto_config.save(&self.config_location)?;
 
-        for cloud in clouds {
-            println!("Refreshing bridge {cloud}");
-
-            let bridge_config = super::connect::bridge_config(&self.config, cloud)?;
+        for (cloud, profile) in bridges {
+            let bridge_config = match &profile {
+                None => {
+                    println!("Refreshing bridge {cloud}");
+                    super::connect::bridge_config(&self.config, cloud, None)?
+                }
+                Some(profile) => {
+                    println!("Refreshing bridge {cloud} (profile {profile})");
+                    let config = self.config.profile(cloud.cloud_type(), profile)?;
+                    super::connect::bridge_config(&config, cloud, Some(profile))?
+                }
+            };
             refresh_bridge(&bridge_config, &self.config_location)?;
         }
 
@@ -69,14 +79,27 @@ impl RefreshBridgesCmd {
     }
 }
 
-fn established_bridges(config_location: &TEdgeConfigLocation) -> Vec<Cloud> {
+/// The bridges to refresh, i.e. the clouds and cloud profiles with a bridge configuration file
+fn established_bridges(
+    config: &TEdgeConfig,
+    config_location: &TEdgeConfigLocation,
+) -> Result<Vec<(Cloud, Option<ProfileName>)>, UnknownProfile> {
     let possible_clouds = [Cloud::Aws, Cloud::Azure, Cloud::C8y];
 
     // if the bridge configuration file doesn't exist, then the bridge doesn't exist and we shouldn't try to update it
-    possible_clouds
-        .into_iter()
-        .filter(|c| get_bridge_config_file_path_cloud(config_location, c).exists())
-        .collect()
+    let mut bridges = Vec::new();
+    for cloud in possible_clouds {
+        if get_bridge_config_file_path_cloud(config_location, cloud, config).exists() {
+            bridges.push((cloud, None));
+        }
+        for profile in config.profile_names(cloud.cloud_type()) {
+            let profile_config = config.profile(cloud.cloud_type(), &profile)?;
+            if get_bridge_config_file_path_cloud(config_location, cloud, &profile_config).exists() {
+                bridges.push((cloud, Some(profile)));
+            }
+        }
+    }
+    Ok(bridges)
 }
 
 pub fn refresh_bridge(
@@ -91,10 +114,11 @@ pub fn refresh_bridge(
 
 pub fn get_bridge_config_file_path_cloud(
     config_location: &TEdgeConfigLocation,
-    cloud: &Cloud,
+    cloud: Cloud,
+    config: &TEdgeConfig,
 ) -> Utf8PathBuf {
     config_location
         .tedge_config_root_path
         .join(TEDGE_BRIDGE_CONF_DIR_PATH)
-        .join(cloud.bridge_config_filename())
+        .join(cloud.bridge_config_filename(config))
 }
@@ -0,0 +1,34 @@
+use crate::cli::workflow::cli::read_workflow;
+use crate::cli::workflow::error::WorkflowError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+
+/// Check a user-defined operation workflow for errors
+pub struct CheckWorkflowCmd {
+    /// The path to the workflow definition
+    pub file: Utf8PathBuf,
+}
+
+impl Command for CheckWorkflowCmd {
+    fn description(&self) -> String {
+        format!("check the operation workflow defined by {}", self.file)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = read_workflow(&self.file)?;
+        let issues = workflow.check();
+        if issues.is_empty() {
+            println!("{}: {} workflow is valid", self.file, workflow.operation);
+            return Ok(());
+        }
+
+        for issue in issues.iter() {
+            eprintln!("{}: {issue}", self.file);
+        }
+        Err(WorkflowError::IssuesFound {
+            path: self.file.clone(),
+            count: issues.len(),
+        }
+        .into())
+    }
+}
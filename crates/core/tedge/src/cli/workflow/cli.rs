@@ -0,0 +1,58 @@
+use crate::cli::workflow::check::CheckWorkflowCmd;
+use crate::cli::workflow::error::WorkflowError;
+use crate::cli::workflow::graph::GraphFormat;
+use crate::cli::workflow::graph::GraphWorkflowCmd;
+use crate::command::BuildCommand;
+use crate::command::BuildContext;
+use crate::command::Command;
+use crate::ConfigError;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use tedge_api::workflow::OperationWorkflow;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeWorkflowCli {
+    /// Check a user-defined operation workflow for errors
+    ///
+    /// Report the states that are undefined or unreachable,
+    /// the states from where no terminal state can be reached,
+    /// as well as ill-formed `${...}` parameters.
+    Check {
+        /// Path to the workflow definition TOML file
+        file: Utf8PathBuf,
+    },
+
+    /// Print the state machine of a user-defined operation workflow
+    Graph {
+        /// Path to the workflow definition TOML file
+        file: Utf8PathBuf,
+
+        /// Output format
+        #[clap(long, value_enum, default_value = "dot")]
+        format: GraphFormat,
+    },
+}
+
+impl BuildCommand for TEdgeWorkflowCli {
+    fn build_command(self, _context: BuildContext) -> Result<Box<dyn Command>, ConfigError> {
+        let cmd = match self {
+            TEdgeWorkflowCli::Check { file } => CheckWorkflowCmd { file }.into_boxed(),
+            TEdgeWorkflowCli::Graph { file, format } => {
+                GraphWorkflowCmd { file, format }.into_boxed()
+            }
+        };
+        Ok(cmd)
+    }
+}
+
+/// Read and parse a workflow definition file
+pub(crate) fn read_workflow(path: &Utf8Path) -> Result<OperationWorkflow, WorkflowError> {
+    let content = std::fs::read_to_string(path).map_err(|source| WorkflowError::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    toml::from_str(&content).map_err(|err| WorkflowError::InvalidDefinition {
+        path: path.to_owned(),
+        reason: err.message().to_string(),
+    })
+}
@@ -0,0 +1,17 @@
+use camino::Utf8PathBuf;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WorkflowError {
+    #[error("Failed to read the workflow definition {path}")]
+    ReadError {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid workflow definition {path}: {reason}")]
+    InvalidDefinition { path: Utf8PathBuf, reason: String },
+
+    #[error("{count} issue(s) found in the workflow definition {path}")]
+    IssuesFound { path: Utf8PathBuf, count: usize },
+}
@@ -0,0 +1,37 @@
+use crate::cli::workflow::cli::read_workflow;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz DOT language
+    Dot,
+
+    /// Mermaid state diagram
+    Mermaid,
+}
+
+/// Print the state machine of a user-defined operation workflow
+pub struct GraphWorkflowCmd {
+    /// The path to the workflow definition
+    pub file: Utf8PathBuf,
+
+    /// The output format
+    pub format: GraphFormat,
+}
+
+impl Command for GraphWorkflowCmd {
+    fn description(&self) -> String {
+        format!("print the operation workflow defined by {}", self.file)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = read_workflow(&self.file)?;
+        let graph = match self.format {
+            GraphFormat::Dot => workflow.to_dot(),
+            GraphFormat::Mermaid => workflow.to_mermaid(),
+        };
+        print!("{graph}");
+        Ok(())
+    }
+}
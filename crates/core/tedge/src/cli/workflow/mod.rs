@@ -0,0 +1,6 @@
+pub use self::cli::TEdgeWorkflowCli;
+
+mod check;
+mod cli;
+mod error;
+mod graph;
@@ -30,4 +30,10 @@ pub enum TEdgeError {
 
     #[error(transparent)]
     FromConfigNotSet(#[from] tedge_config::ConfigNotSet),
+
+    #[error(transparent)]
+    FromCertificate(#[from] certificate::CertificateError),
+
+    #[error(transparent)]
+    FromUnknownProfile(#[from] tedge_config::UnknownProfile),
 }
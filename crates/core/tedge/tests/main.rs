@@ -443,6 +443,93 @@ mod tests {
         assert!(output_str.contains("Example"));
     }
 
+    #[test]
+    fn run_workflow_check_and_graph() -> Result<(), Box<dyn std::error::Error>> {
+        let tempdir = tempfile::tempdir()?;
+        let valid_workflow = temp_path(&tempdir, "valid.toml");
+        std::fs::write(
+            &valid_workflow,
+            r#"
+operation = "check"
+
+[init]
+script = "/some/script.sh ${.payload.url}"
+on_success = "successful"
+"#,
+        )?;
+        let broken_workflow = temp_path(&tempdir, "broken.toml");
+        std::fs::write(
+            &broken_workflow,
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "typo"
+"#,
+        )?;
+
+        tedge_command(["workflow", "check", &valid_workflow])?
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("check workflow is valid"));
+
+        tedge_command(["workflow", "check", &broken_workflow])?
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                r#"the "init" state refers to an undefined state: "typo""#,
+            ));
+
+        tedge_command(["workflow", "graph", &valid_workflow])?
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                r#""init" -> "successful" [label="on_success"];"#,
+            ));
+
+        tedge_command(["workflow", "graph", "--format", "mermaid", &valid_workflow])?
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("init --> successful: on_success"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_operation_list_and_show() -> Result<(), Box<dyn std::error::Error>> {
+        let tempdir = tempfile::tempdir()?;
+        let config_dir = tempdir.path().to_str().unwrap();
+        std::fs::create_dir(tempdir.path().join(".agent"))?;
+        std::fs::write(
+            tempdir.path().join(".agent").join("workflow-history"),
+            r#"{"event":"state","topic":"te/device/main///cmd/restart/123","unix_timestamp":0,"status":"init"}
+{"event":"state","topic":"te/device/main///cmd/restart/123","unix_timestamp":60,"status":"failed","reason":"Cancelled"}
+{"event":"cleared","topic":"te/device/main///cmd/restart/123"}
+"#,
+        )?;
+
+        tedge_command(["--config-dir", config_dir, "operation", "list"])?
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("123"))
+            .stdout(predicate::str::contains("restart"))
+            .stdout(predicate::str::contains("1970-01-01T00:01:00Z"));
+
+        tedge_command(["--config-dir", config_dir, "operation", "show", "123"])?
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("reason:    Cancelled"))
+            .stdout(predicate::str::contains("1970-01-01T00:00:00Z init"));
+
+        tedge_command(["--config-dir", config_dir, "operation", "cancel", "123"])?
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("is no more pending"));
+
+        Ok(())
+    }
+
     fn tedge_command_with_test_home<I, S>(
         args: I,
     ) -> Result<assert_cmd::Command, Box<dyn std::error::Error>>
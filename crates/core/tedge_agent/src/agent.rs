@@ -1,5 +1,9 @@
+use crate::certificate_renewal::actor::CertificateRenewalBuilder;
+use crate::certificate_renewal::CertificateRenewalConfig;
 use crate::file_transfer_server::actor::FileTransferServerBuilder;
 use crate::file_transfer_server::actor::FileTransferServerConfig;
+use crate::measurement_store::actor::MeasurementStoreBuilder;
+use crate::measurement_store::MeasurementStoreConfig;
 use crate::operation_file_cache::FileCacheActorBuilder;
 use crate::restart_manager::builder::RestartManagerBuilder;
 use crate::restart_manager::config::RestartManagerConfig;
@@ -18,7 +22,7 @@ use flockfile::check_another_instance_is_not_running;
 use flockfile::Flockfile;
 use flockfile::FlockfileError;
 use log::error;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::net::SocketAddr;
@@ -53,6 +57,7 @@ use tedge_mqtt_ext::MqttConfig;
 use tedge_mqtt_ext::TopicFilter;
 use tedge_script_ext::ScriptActor;
 use tedge_signal_ext::SignalActor;
+use tedge_timer_ext::TimerActor;
 use tedge_uploader_ext::UploaderActor;
 use tedge_utils::file::create_directory_with_defaults;
 use tracing::info;
@@ -65,6 +70,8 @@ const TEDGE_AGENT: &str = "tedge-agent";
 pub(crate) struct AgentConfig {
     pub mqtt_config: MqttConfig,
     pub http_config: FileTransferServerConfig,
+    pub measurement_store_config: Option<MeasurementStoreConfig>,
+    pub certificate_renewal_config: Option<CertificateRenewalConfig>,
     pub restart_config: RestartManagerConfig,
     pub sw_update_config: SoftwareManagerConfig,
     pub operation_config: OperationConfig,
@@ -78,7 +85,7 @@ pub(crate) struct AgentConfig {
     pub mqtt_device_topic_id: EntityTopicId,
     pub mqtt_topic_root: Arc<str>,
     pub service: TEdgeConfigReaderService,
-    pub identity: Option<Identity>,
+    pub identity: Option<ClientConfig>,
     pub fts_url: Arc<str>,
     pub is_sudo_enabled: bool,
     pub capabilities: Capabilities,
@@ -121,11 +128,56 @@ impl AgentConfig {
         let http_config = FileTransferServerConfig {
             file_transfer_dir: data_dir.file_transfer_dir(),
             cert_path: tedge_config.http.cert_path.clone(),
-            key_path: tedge_config.http.key_path.clone(),
+            key_path: tedge_config.optional_key_provider(&tedge_config.http.key_path)?,
             ca_path: tedge_config.http.ca_path.clone(),
             bind_addr: SocketAddr::from((http_bind_address, http_port)),
         };
 
+        // Local measurement store config
+        let measurement_store_config =
+            tedge_config
+                .agent
+                .measurements
+                .store
+                .then(|| MeasurementStoreConfig {
+                    dir: data_dir.join("measurements"),
+                    max_size: u64::from(tedge_config.agent.measurements.max_size) * 1024,
+                    max_age: tedge_config.agent.measurements.max_age.duration(),
+                });
+
+        // Certificate renewal config
+        let certificate_renewal_config = if tedge_config.certificate.renewal.enable {
+            Some(CertificateRenewalConfig {
+                est_url: tedge_config
+                    .certificate
+                    .renewal
+                    .est_url
+                    .or_config_not_set()?
+                    .clone(),
+                est_root_cert_path: tedge_config
+                    .certificate
+                    .renewal
+                    .est_root_cert_path
+                    .or_none()
+                    .cloned(),
+                cert_path: tedge_config.device.cert_path.clone(),
+                key_path: tedge_config.device.key_path.clone(),
+                cryptoki: tedge_config.cryptoki_config(),
+                tmp_dir: tedge_config.tmp.path.clone(),
+                config_dir: config_dir.clone(),
+                check_interval: tedge_config.certificate.renewal.check_interval.duration(),
+                renew_before: tedge_config.certificate.renewal.renew_before.duration(),
+                tedge_command: "tedge".into(),
+                sudo: tedge_config
+                    .sudo
+                    .enable
+                    .then(|| which::which("sudo").ok())
+                    .flatten(),
+            })
+        } else {
+            None
+        };
+
         // Restart config
         let restart_config =
             RestartManagerConfig::from_tedge_config(&mqtt_device_topic_id, tedge_config_location)?;
@@ -145,7 +197,7 @@ impl AgentConfig {
         let log_dir = tedge_config.logs.path.join("agent");
         let operations_dir = config_dir.join("operations");
 
-        let identity = tedge_config.http.client.auth.identity()?;
+        let identity = tedge_config.http_client_tls_config()?;
 
         let is_sudo_enabled = tedge_config.sudo.enable;
 
@@ -163,6 +215,8 @@ impl AgentConfig {
         Ok(Self {
             mqtt_config,
             http_config,
+            measurement_store_config,
+            certificate_renewal_config,
             restart_config,
             sw_update_config,
             operation_config,
@@ -239,6 +293,9 @@ impl Agent {
         // Software update actor
         let mut software_update_builder = SoftwareManagerBuilder::new(self.config.sw_update_config);
 
+        // Timer actor, used to defer scheduled commands
+        let mut timer_actor = TimerActor::builder();
+
         // Converter actor
         let converter_actor_builder = TedgeOperationConverterBuilder::new(
             self.config.operation_config,
@@ -247,6 +304,7 @@ impl Agent {
             &mut restart_actor_builder,
             &mut mqtt_actor_builder,
             &mut script_runner,
+            &mut timer_actor,
         );
 
         // Shutdown on SIGINT
@@ -333,10 +391,40 @@ impl Agent {
 
             runtime.spawn(tedge_to_te_converter).await?;
 
-            let file_transfer_server_builder =
+            let mut file_transfer_server_builder =
                 FileTransferServerBuilder::try_bind(self.config.http_config).await?;
+
+            if let Some(measurement_store_config) = &self.config.measurement_store_config {
+                info!(
+                    "Storing the measurements locally in {}",
+                    measurement_store_config.dir
+                );
+                let store = measurement_store_config.open_store()?;
+                let measurement_store_builder = MeasurementStoreBuilder::new(
+                    mqtt_schema.clone(),
+                    store.clone(),
+                    &mut mqtt_actor_builder,
+                );
+                runtime.spawn(measurement_store_builder).await?;
+                file_transfer_server_builder =
+                    file_transfer_server_builder.with_measurement_store(store);
+            }
             runtime.spawn(file_transfer_server_builder).await?;
 
+            if let Some(certificate_renewal_config) = self.config.certificate_renewal_config {
+                info!(
+                    "Renewing the device certificate using the EST server {}",
+                    certificate_renewal_config.est_url
+                );
+                let certificate_renewal_builder = CertificateRenewalBuilder::new(
+                    certificate_renewal_config,
+                    &mqtt_schema,
+                    &self.config.mqtt_device_topic_id,
+                    &mut mqtt_actor_builder,
+                );
+                runtime.spawn(certificate_renewal_builder).await?;
+            }
+
             let operation_file_cache_builder = FileCacheActorBuilder::new(
                 mqtt_schema,
                 self.config.fts_url.clone(),
@@ -364,6 +452,7 @@ impl Agent {
         runtime.spawn(restart_actor_builder).await?;
         runtime.spawn(software_update_builder).await?;
         runtime.spawn(script_runner).await?;
+        runtime.spawn(timer_actor).await?;
         runtime.spawn(converter_actor_builder).await?;
         runtime.spawn(health_actor).await?;
 
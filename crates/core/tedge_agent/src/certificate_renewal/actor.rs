@@ -0,0 +1,537 @@
+use crate::certificate_renewal::error::CertificateRenewalError;
+use crate::certificate_renewal::est::EstClient;
+use crate::certificate_renewal::CertificateRenewalConfig;
+use async_trait::async_trait;
+use certificate::parse_root_certificate::read_cert_chain;
+use certificate::validate_certificate_chain;
+use certificate::KeyCertPair;
+use certificate::KeyProvider;
+use certificate::PemCertificate;
+use serde_json::json;
+use std::convert::Infallible;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use time::OffsetDateTime;
+use tokio::process::Command;
+use tokio::time::sleep;
+use tracing::error;
+use tracing::info;
+
+/// The type of the alarm raised when the certificate cannot be renewed
+const RENEWAL_ALARM_TYPE: &str = "certificate_renewal";
+
+pub struct CertificateRenewalActor {
+    config: CertificateRenewalConfig,
+    est_client: EstClient,
+    alarm_topic: tedge_mqtt_ext::Topic,
+    alarm_raised: bool,
+    messages: SimpleMessageBox<NoMessage, MqttMessage>,
+}
+
+#[async_trait]
+impl Actor for CertificateRenewalActor {
+    fn name(&self) -> &str {
+        "CertificateRenewal"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        loop {
+            self.check_certificate().await?;
+            tokio::select! {
+                _ = sleep(self.config.check_interval) => {}
+                _ = self.messages.recv() => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CertificateRenewalActor {
+    /// Renew the device certificate if it is about to expire, raising an alarm on failure
+    async fn check_certificate(&mut self) -> Result<(), RuntimeError> {
+        match self.renew_certificate_if_expiring().await {
+            Ok(()) => {
+                if self.alarm_raised {
+                    self.publish_alarm(String::new()).await?;
+                    self.alarm_raised = false;
+                }
+            }
+            Err(err) => {
+                error!("Failed to renew the device certificate: {err}");
+                let alarm = json!({
+                    "text": format!("Failed to renew the device certificate: {err}"),
+                    "severity": "major",
+                });
+                self.publish_alarm(alarm.to_string()).await?;
+                self.alarm_raised = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn renew_certificate_if_expiring(&self) -> Result<(), CertificateRenewalError> {
+        let certificate = PemCertificate::from_pem_file(&self.config.cert_path)?;
+        let not_after = certificate.not_after_datetime()?;
+        if not_after - OffsetDateTime::now_utc() > self.config.renew_before {
+            return Ok(());
+        }
+
+        info!(
+            "The device certificate expires on {}, renewing it",
+            certificate.not_after()?
+        );
+        let new_certificate = self.renew_certificate(&certificate).await?;
+        info!(
+            "The device certificate has been renewed, being now valid up to {}",
+            new_certificate.not_after()?
+        );
+        Ok(())
+    }
+
+    async fn renew_certificate(
+        &self,
+        current: &PemCertificate,
+    ) -> Result<PemCertificate, CertificateRenewalError> {
+        let key = KeyProvider::new(&self.config.key_path, self.config.cryptoki.as_ref())?;
+        let request = KeyCertPair::new_renewal_request(current, key.key_pair()?)?;
+        let csr_der = request.certificate_signing_request_der()?;
+
+        let cert_chain = read_cert_chain(&self.config.cert_path)?;
+        let chain_pem = self
+            .est_client
+            .simple_reenroll(cert_chain, &key, &csr_der)
+            .await?;
+        let new_certificate = validate_certificate_chain(&chain_pem, &key.key_pair()?)?;
+
+        let new_cert_path = self.config.tmp_dir.join("tedge-certificate-renewal.pem");
+        tokio::fs::write(&new_cert_path, chain_pem).await?;
+        let installed = self
+            .run_tedge_command(&["cert", "install", new_cert_path.as_str()])
+            .await;
+        let _ = tokio::fs::remove_file(&new_cert_path).await;
+        installed?;
+
+        // The bridges have to be reconnected to use the new certificate
+        self.run_tedge_command(&["refresh-bridges"]).await?;
+
+        Ok(new_certificate)
+    }
+
+    async fn run_tedge_command(&self, args: &[&str]) -> Result<(), CertificateRenewalError> {
+        let config_dir = self.config.config_dir.as_str();
+        let mut command = match &self.config.sudo {
+            Some(sudo) => {
+                let mut command = Command::new(sudo);
+                command.arg(&self.config.tedge_command);
+                command
+            }
+            None => Command::new(&self.config.tedge_command),
+        };
+        command.args(["--config-dir", config_dir]).args(args);
+
+        let command_line = format!("tedge {}", args.join(" "));
+        let output =
+            command
+                .output()
+                .await
+                .map_err(|err| CertificateRenewalError::CommandFailed {
+                    command: command_line.clone(),
+                    reason: err.to_string(),
+                })?;
+        if !output.status.success() {
+            return Err(CertificateRenewalError::CommandFailed {
+                command: command_line,
+                reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Raise the renewal alarm with the given payload, or clear it when the payload is empty
+    async fn publish_alarm(&mut self, payload: String) -> Result<(), RuntimeError> {
+        let message = MqttMessage::new(&self.alarm_topic, payload)
+            .with_qos(QoS::AtLeastOnce)
+            .with_retain();
+        self.messages.send(message).await?;
+        Ok(())
+    }
+}
+
+pub struct CertificateRenewalBuilder {
+    config: CertificateRenewalConfig,
+    alarm_topic: tedge_mqtt_ext::Topic,
+    message_box: SimpleMessageBoxBuilder<NoMessage, MqttMessage>,
+}
+
+impl CertificateRenewalBuilder {
+    pub fn new(
+        config: CertificateRenewalConfig,
+        mqtt_schema: &MqttSchema,
+        device_topic_id: &EntityTopicId,
+        mqtt: &mut impl MessageSink<MqttMessage, NoConfig>,
+    ) -> Self {
+        let mut message_box = SimpleMessageBoxBuilder::new("CertificateRenewal", 16);
+        message_box.add_sink(mqtt);
+        let alarm_topic = mqtt_schema.topic_for(
+            device_topic_id,
+            &Channel::Alarm {
+                alarm_type: RENEWAL_ALARM_TYPE.to_string(),
+            },
+        );
+        CertificateRenewalBuilder {
+            config,
+            alarm_topic,
+            message_box,
+        }
+    }
+}
+
+impl RuntimeRequestSink for CertificateRenewalBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<CertificateRenewalActor> for CertificateRenewalBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<CertificateRenewalActor, Self::Error> {
+        Ok(CertificateRenewalActor {
+            est_client: EstClient::new(
+                &self.config.est_url,
+                self.config.est_root_cert_path.clone(),
+            ),
+            config: self.config,
+            alarm_topic: self.alarm_topic,
+            // A stale alarm, raised before a restart, is cleared by the first successful check
+            alarm_raised: true,
+            messages: self.message_box.build(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::http::HeaderMap;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::Router;
+    use camino::Utf8PathBuf;
+    use certificate::pkcs7::certificates_to_pkcs7;
+    use certificate::KeyKind;
+    use certificate::NewCertificateConfig;
+    use serde_json::Value;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tedge_actors::test_helpers::MessageReceiverExt;
+    use tedge_actors::test_helpers::TimedMessageBox;
+    use tedge_test_utils::fs::TempTedgeDir;
+
+    const DAY: Duration = Duration::from_secs(24 * 3600);
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn an_expiring_certificate_is_renewed() {
+        let ttd = TempTedgeDir::new();
+        let key = create_device_certificate(&ttd, 10);
+        let est = EstStandIn::start((StatusCode::OK, issue_certificate(&key))).await;
+        let mut mqtt = spawn_actor(&ttd, &est, 30 * DAY).await;
+
+        // The alarm possibly raised before the renewal is cleared
+        let message = mqtt.recv().await.expect("alarm clearing");
+        assert_eq!(message.topic.name, "te/device/main///a/certificate_renewal");
+        assert!(message.payload_bytes().is_empty());
+        assert!(message.retain);
+
+        let requests = est.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(base64::decode(&requests[0]).is_ok());
+
+        let certificate = PemCertificate::from_pem_file(ttd.utf8_path().join("cert.pem")).unwrap();
+        assert_eq!(certificate.subject_common_name().unwrap(), "my-device");
+        assert_eq!(certificate.issuer().unwrap(), "CN=Test EST CA");
+
+        let dir = ttd.utf8_path();
+        let commands = std::fs::read_to_string(dir.join("tedge.log")).unwrap();
+        assert_eq!(
+            commands.lines().collect::<Vec<_>>(),
+            vec![
+                format!(
+                    "tedge --config-dir {dir} cert install {dir}/tedge-certificate-renewal.pem"
+                ),
+                format!("tedge --config-dir {dir} refresh-bridges"),
+            ]
+        );
+        assert!(!dir.join("tedge-certificate-renewal.pem").exists());
+    }
+
+    #[tokio::test]
+    async fn a_certificate_not_about_to_expire_is_kept() {
+        let ttd = TempTedgeDir::new();
+        let key = create_device_certificate(&ttd, 365);
+        let est = EstStandIn::start((StatusCode::OK, issue_certificate(&key))).await;
+        let mut mqtt = spawn_actor(&ttd, &est, 30 * DAY).await;
+
+        let message = mqtt.recv().await.expect("alarm clearing");
+        assert!(message.payload_bytes().is_empty());
+
+        assert!(est.requests().is_empty());
+        let certificate = PemCertificate::from_pem_file(ttd.utf8_path().join("cert.pem")).unwrap();
+        assert_eq!(
+            certificate.issuer().unwrap(),
+            certificate.subject().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn an_alarm_is_raised_when_the_renewal_fails() {
+        let ttd = TempTedgeDir::new();
+        create_device_certificate(&ttd, 10);
+        let est = EstStandIn::start((
+            StatusCode::FORBIDDEN,
+            "Unknown device certificate".to_string(),
+        ))
+        .await;
+        let mut mqtt = spawn_actor(&ttd, &est, 30 * DAY).await;
+
+        let message = mqtt.recv().await.expect("alarm");
+        assert_eq!(message.topic.name, "te/device/main///a/certificate_renewal");
+        assert!(message.retain);
+        let alarm: Value = serde_json::from_slice(message.payload_bytes()).unwrap();
+        assert_eq!(alarm["severity"], "major");
+        let text = alarm["text"].as_str().unwrap();
+        assert!(text.contains("Unknown device certificate"), "{text}");
+
+        assert_eq!(est.requests().len(), 1);
+        assert!(!ttd.utf8_path().join("tedge.log").exists());
+        let certificate = PemCertificate::from_pem_file(ttd.utf8_path().join("cert.pem")).unwrap();
+        assert_eq!(
+            certificate.issuer().unwrap(),
+            certificate.subject().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn the_est_server_is_authenticated_with_the_configured_root_certificate() {
+        let ttd = TempTedgeDir::new();
+        let key = create_device_certificate(&ttd, 10);
+        let est = EstStandIn::start_https(&ttd, (StatusCode::OK, issue_certificate(&key))).await;
+        let mut mqtt = spawn_actor(&ttd, &est, 30 * DAY).await;
+
+        let message = mqtt.recv().await.expect("alarm clearing");
+        assert!(message.payload_bytes().is_empty());
+
+        assert_eq!(est.requests().len(), 1);
+        let certificate = PemCertificate::from_pem_file(ttd.utf8_path().join("cert.pem")).unwrap();
+        assert_eq!(certificate.issuer().unwrap(), "CN=Test EST CA");
+    }
+
+    #[tokio::test]
+    async fn an_untrusted_est_server_is_not_used() {
+        let ttd = TempTedgeDir::new();
+        let key = create_device_certificate(&ttd, 10);
+        let mut est =
+            EstStandIn::start_https(&ttd, (StatusCode::OK, issue_certificate(&key))).await;
+        // The self-signed certificate of the server is not one of the system root certificates
+        est.root_cert_path = None;
+        let mut mqtt = spawn_actor(&ttd, &est, 30 * DAY).await;
+
+        let message = mqtt.recv().await.expect("alarm");
+        let alarm: Value = serde_json::from_slice(message.payload_bytes()).unwrap();
+        assert_eq!(alarm["severity"], "major");
+
+        assert!(est.requests().is_empty());
+        assert!(!ttd.utf8_path().join("tedge.log").exists());
+    }
+
+    /// Create a self-signed device certificate valid for the given number of days, returning the private key
+    fn create_device_certificate(ttd: &TempTedgeDir, validity_period_days: u32) -> String {
+        let config = NewCertificateConfig {
+            validity_period_days,
+            ..NewCertificateConfig::default()
+        };
+        let certificate =
+            KeyCertPair::new_selfsigned_certificate(&config, "my-device", &KeyKind::New).unwrap();
+        let key = certificate.private_key_pem_string().unwrap().to_string();
+        std::fs::write(
+            ttd.utf8_path().join("cert.pem"),
+            certificate.certificate_pem_string().unwrap(),
+        )
+        .unwrap();
+        std::fs::write(ttd.utf8_path().join("key.pem"), &key).unwrap();
+        key
+    }
+
+    /// Issue a certificate for the device key, signed by a test CA, as returned by an EST server
+    fn issue_certificate(key: &str) -> String {
+        let mut ca_params = rcgen::CertificateParams::new(vec![]);
+        ca_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test EST CA");
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "my-device");
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params.key_pair = Some(rcgen::KeyPair::from_pem(key).unwrap());
+        let device = rcgen::Certificate::from_params(params).unwrap();
+
+        let certificates = vec![
+            device.serialize_der_with_signer(&ca).unwrap(),
+            ca.serialize_der().unwrap(),
+        ];
+        base64::encode(certificates_to_pkcs7(&certificates))
+    }
+
+    /// A local EST server, responding to re-enrollment requests with a predefined response
+    struct EstStandIn {
+        url: String,
+        root_cert_path: Option<Utf8PathBuf>,
+        state: Arc<EstState>,
+    }
+
+    struct EstState {
+        response: (StatusCode, String),
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl EstStandIn {
+        async fn start(response: (StatusCode, String)) -> Self {
+            let (app, state) = Self::app(response);
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let server = axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service());
+            tokio::spawn(server);
+            EstStandIn {
+                url,
+                root_cert_path: None,
+                state,
+            }
+        }
+
+        /// Start a server authenticated by a self-signed certificate, stored in the given directory
+        async fn start_https(ttd: &TempTedgeDir, response: (StatusCode, String)) -> Self {
+            let server_cert = rcgen::generate_simple_self_signed(["localhost".into()]).unwrap();
+            let root_cert_path = ttd.utf8_path().join("est-root-ca.pem");
+            std::fs::write(&root_cert_path, server_cert.serialize_pem().unwrap()).unwrap();
+            let server_config = axum_tls::ssl_config(
+                vec![server_cert.serialize_der().unwrap()],
+                server_cert.serialize_private_key_der(),
+                None,
+            )
+            .unwrap();
+
+            let (app, state) = Self::app(response);
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!(
+                "https://localhost:{}/",
+                listener.local_addr().unwrap().port()
+            );
+            tokio::spawn(axum_tls::start_tls_server(listener, server_config, app));
+            EstStandIn {
+                url,
+                root_cert_path: Some(root_cert_path),
+                state,
+            }
+        }
+
+        fn app(response: (StatusCode, String)) -> (Router, Arc<EstState>) {
+            let state = Arc::new(EstState {
+                response,
+                requests: Mutex::new(vec![]),
+            });
+            let app = Router::new()
+                .route("/.well-known/est/simplereenroll", post(simple_reenroll))
+                .with_state(state.clone());
+            (app, state)
+        }
+
+        fn requests(&self) -> Vec<String> {
+            self.state.requests.lock().unwrap().clone()
+        }
+    }
+
+    async fn simple_reenroll(
+        State(state): State<Arc<EstState>>,
+        headers: HeaderMap,
+        body: String,
+    ) -> (StatusCode, String) {
+        if headers.get("content-type").map(|v| v.as_bytes()) != Some(b"application/pkcs10") {
+            return (StatusCode::UNSUPPORTED_MEDIA_TYPE, String::new());
+        }
+        state.requests.lock().unwrap().push(body);
+        state.response.clone()
+    }
+
+    async fn spawn_actor(
+        ttd: &TempTedgeDir,
+        est: &EstStandIn,
+        renew_before: Duration,
+    ) -> TimedMessageBox<SimpleMessageBox<MqttMessage, NoMessage>> {
+        let dir = ttd.utf8_path();
+
+        // A stand-in of the tedge command, logging its arguments and installing the certificate
+        let tedge_command = dir.join("tedge");
+        std::fs::write(
+            &tedge_command,
+            format!(
+                "#!/bin/sh\necho tedge \"$@\" >> {dir}/tedge.log\n\
+                if [ \"$3\" = cert ] && [ \"$4\" = install ]; then cp \"$5\" {dir}/cert.pem; fi\n"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&tedge_command, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = CertificateRenewalConfig {
+            est_url: est.url.clone(),
+            est_root_cert_path: est.root_cert_path.clone(),
+            cert_path: dir.join("cert.pem"),
+            key_path: dir.join("key.pem"),
+            cryptoki: None,
+            tmp_dir: dir.to_owned(),
+            config_dir: dir.to_owned(),
+            check_interval: DAY,
+            renew_before,
+            tedge_command: tedge_command.into(),
+            sudo: None,
+        };
+
+        let mut mqtt: SimpleMessageBoxBuilder<MqttMessage, NoMessage> =
+            SimpleMessageBoxBuilder::new("MQTT", 16);
+        let actor = CertificateRenewalBuilder::new(
+            config,
+            &MqttSchema::default(),
+            &EntityTopicId::default_main_device(),
+            &mut mqtt,
+        )
+        .build();
+        tokio::spawn(actor.run());
+        mqtt.build().with_timeout(TEST_TIMEOUT)
+    }
+}
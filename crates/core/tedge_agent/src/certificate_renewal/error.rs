@@ -0,0 +1,32 @@
+use certificate::CertificateError;
+use tedge_actors::RuntimeError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CertificateRenewalError {
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromCertificate(#[from] CertificateError),
+
+    #[error("Failed to reach the EST server: {0}")]
+    FromReqwest(#[from] reqwest::Error),
+
+    #[error("The EST server rejected the renewal request with {status}: {reason}")]
+    RenewalRejected {
+        status: reqwest::StatusCode,
+        reason: String,
+    },
+
+    #[error("Invalid response from the EST server: {0}")]
+    InvalidEstResponse(String),
+
+    #[error("Failed to run `{command}`: {reason}")]
+    CommandFailed { command: String, reason: String },
+}
+
+impl From<CertificateRenewalError> for RuntimeError {
+    fn from(error: CertificateRenewalError) -> Self {
+        RuntimeError::ActorError(Box::new(error))
+    }
+}
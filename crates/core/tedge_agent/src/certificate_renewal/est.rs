@@ -0,0 +1,102 @@
+use crate::certificate_renewal::error::CertificateRenewalError;
+use camino::Utf8PathBuf;
+use certificate::parse_root_certificate::add_certs_from_directory;
+use certificate::parse_root_certificate::add_certs_from_file;
+use certificate::parse_root_certificate::native_root_store;
+use certificate::pkcs7::certificates_from_pkcs7;
+use certificate::pkcs7::certificates_to_pem;
+use certificate::KeyProvider;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use rustls::Certificate;
+use rustls::ClientConfig;
+use rustls::RootCertStore;
+
+/// The path of the EST re-enrollment endpoint, relative to the server URL
+const SIMPLE_REENROLL_PATH: &str = ".well-known/est/simplereenroll";
+
+/// A client of an Enrollment over Secure Transport server (RFC 7030)
+pub struct EstClient {
+    url: String,
+    root_cert_path: Option<Utf8PathBuf>,
+}
+
+impl EstClient {
+    /// A client trusting the given root certificates, or the system ones if none is given
+    pub fn new(url: &str, root_cert_path: Option<Utf8PathBuf>) -> Self {
+        EstClient {
+            url: url.trim_end_matches('/').to_string(),
+            root_cert_path,
+        }
+    }
+
+    /// Request a new certificate for the given DER-encoded certificate signing request
+    ///
+    /// The client authenticates with the certificate being renewed and the device key.
+    /// Return the PEM-encoded certificates issued by the server, the device certificate first.
+    pub async fn simple_reenroll(
+        &self,
+        cert_chain: Vec<Certificate>,
+        key: &KeyProvider,
+        csr_der: &[u8],
+    ) -> Result<String, CertificateRenewalError> {
+        let root_store = self.root_cert_store()?;
+        let tls_config = client_tls_config(root_store, cert_chain, key)?;
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .build()?;
+        let response = client
+            .post(format!("{}/{SIMPLE_REENROLL_PATH}", self.url))
+            .header(CONTENT_TYPE, "application/pkcs10")
+            .header("Content-Transfer-Encoding", "base64")
+            .body(base64::encode(csr_der))
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if status != StatusCode::OK {
+            // A 202 Accepted response means the request is pending a manual approval:
+            // the renewal will be retried on the next check
+            return Err(CertificateRenewalError::RenewalRejected {
+                status,
+                reason: body.trim().to_string(),
+            });
+        }
+
+        // The base64 encoding of the response can be split over several lines
+        let body: String = body.split_whitespace().collect();
+        let pkcs7 = base64::decode(body)
+            .map_err(|err| CertificateRenewalError::InvalidEstResponse(err.to_string()))?;
+        let certificates = certificates_from_pkcs7(&pkcs7)?;
+        Ok(certificates_to_pem(&certificates))
+    }
+
+    /// The root certificates trusted to authenticate the EST server
+    fn root_cert_store(&self) -> Result<RootCertStore, CertificateRenewalError> {
+        let Some(path) = &self.root_cert_path else {
+            return Ok(native_root_store()?);
+        };
+        let mut root_store = RootCertStore::empty();
+        if path.is_dir() {
+            add_certs_from_directory(&mut root_store, path)?;
+        } else {
+            add_certs_from_file(&mut root_store, path)?;
+        }
+        Ok(root_store)
+    }
+}
+
+/// A TLS config trusting the given root certificates and authenticating with the device certificate and key
+///
+/// The device key being possibly held by a PKCS#11 token, the TLS config cannot be built by reqwest.
+fn client_tls_config(
+    root_store: RootCertStore,
+    cert_chain: Vec<Certificate>,
+    key: &KeyProvider,
+) -> Result<ClientConfig, CertificateRenewalError> {
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_client_cert_resolver(key.client_cert_resolver(cert_chain)?))
+}
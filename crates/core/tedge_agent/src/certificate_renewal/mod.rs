@@ -0,0 +1,52 @@
+//! Renews the device certificate before it expires.
+//!
+//! The expiry of the device certificate is checked on a schedule.
+//! When the certificate is about to expire, a new one is requested to an EST server (RFC 7030),
+//! authenticating with the current certificate. The new certificate is installed with `tedge cert install`
+//! and the cloud bridges are restarted with `tedge refresh-bridges` to use it.
+//!
+//! A failed renewal is reported by an alarm on the main device, and retried on the next check.
+pub mod actor;
+pub mod error;
+pub mod est;
+
+use camino::Utf8PathBuf;
+use certificate::CryptokiConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct CertificateRenewalConfig {
+    /// The URL of the EST server
+    pub est_url: String,
+
+    /// The root certificates trusted to authenticate the EST server, the system ones if not set
+    pub est_root_cert_path: Option<Utf8PathBuf>,
+
+    /// The path of the device certificate
+    pub cert_path: Utf8PathBuf,
+
+    /// The path of the device private key, or its PKCS#11 URI
+    pub key_path: Utf8PathBuf,
+
+    /// How to access the device private key, when held by a PKCS#11 token
+    pub cryptoki: Option<CryptokiConfig>,
+
+    /// The directory where a new certificate is stored before being installed
+    pub tmp_dir: Utf8PathBuf,
+
+    /// The tedge config directory, passed to the `tedge` commands
+    pub config_dir: Utf8PathBuf,
+
+    /// The interval between two checks of the certificate expiry
+    pub check_interval: Duration,
+
+    /// How long before its expiry the certificate is renewed
+    pub renew_before: Duration,
+
+    /// The `tedge` command used to install the new certificate and refresh the bridges
+    pub tedge_command: PathBuf,
+
+    /// The `sudo` command, if the `tedge` command has to be run with sudo
+    pub sudo: Option<PathBuf>,
+}
@@ -1,11 +1,14 @@
 use crate::file_transfer_server::error::FileTransferError;
 use crate::file_transfer_server::http_rest::http_file_transfer_server;
+use crate::measurement_store::SharedMeasurementStore;
 use anyhow::Context;
 use async_trait::async_trait;
 use axum_tls::config::load_ssl_config;
 use axum_tls::config::PemReader;
+use axum_tls::config::PrivateKeyLoader;
 use axum_tls::config::TrustStoreLoader;
 use camino::Utf8PathBuf;
+use certificate::KeyProvider;
 use rustls::ServerConfig;
 use std::convert::Infallible;
 use std::net::SocketAddr;
@@ -24,17 +27,22 @@ use tracing::log::info;
 pub struct FileTransferServerActor {
     file_transfer_dir: Utf8PathBuf,
     rustls_config: Option<ServerConfig>,
+    measurement_store: Option<SharedMeasurementStore>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     listener: TcpListener,
 }
 
 #[derive(Debug, Clone)]
-// In the tests, CertKeyPath is replaced with a String, and CaPath is replaced with a RootCertStore
+// In the tests, CertPath and Key are replaced with Strings, and CaPath is replaced with a RootCertStore
 // hence they need to be separate types
-pub(crate) struct FileTransferServerConfig<CertKeyPath = Utf8PathBuf, CaPath = Utf8PathBuf> {
+pub(crate) struct FileTransferServerConfig<
+    CertPath = Utf8PathBuf,
+    Key = KeyProvider,
+    CaPath = Utf8PathBuf,
+> {
     pub file_transfer_dir: Utf8PathBuf,
-    pub cert_path: OptionalConfig<CertKeyPath>,
-    pub key_path: OptionalConfig<CertKeyPath>,
+    pub cert_path: OptionalConfig<CertPath>,
+    pub key_path: OptionalConfig<Key>,
     pub ca_path: OptionalConfig<CaPath>,
     pub bind_addr: SocketAddr,
 }
@@ -47,8 +55,12 @@ impl Actor for FileTransferServerActor {
     }
 
     async fn run(mut self) -> Result<(), RuntimeError> {
-        let server =
-            http_file_transfer_server(self.listener, self.file_transfer_dir, self.rustls_config)?;
+        let server = http_file_transfer_server(
+            self.listener,
+            self.file_transfer_dir,
+            self.rustls_config,
+            self.measurement_store,
+        )?;
 
         tokio::select! {
             result = server => {
@@ -66,6 +78,7 @@ impl Actor for FileTransferServerActor {
 pub struct FileTransferServerBuilder {
     file_transfer_dir: Utf8PathBuf,
     rustls_config: Option<ServerConfig>,
+    measurement_store: Option<SharedMeasurementStore>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     listener: TcpListener,
@@ -73,7 +86,11 @@ pub struct FileTransferServerBuilder {
 
 impl FileTransferServerBuilder {
     pub(crate) async fn try_bind(
-        config: FileTransferServerConfig<impl PemReader, impl TrustStoreLoader>,
+        config: FileTransferServerConfig<
+            impl PemReader,
+            impl PrivateKeyLoader,
+            impl TrustStoreLoader,
+        >,
     ) -> Result<Self, anyhow::Error> {
         let listener = TcpListener::bind(config.bind_addr)
             .await
@@ -88,6 +105,7 @@ impl FileTransferServerBuilder {
                 "File transfer service",
             )?,
             file_transfer_dir: config.file_transfer_dir,
+            measurement_store: None,
             signal_sender,
             signal_receiver,
             listener,
@@ -95,6 +113,16 @@ impl FileTransferServerBuilder {
     }
 }
 
+impl FileTransferServerBuilder {
+    /// Serve the measurements of the given store on `/tedge/measurements`
+    pub(crate) fn with_measurement_store(self, measurement_store: SharedMeasurementStore) -> Self {
+        Self {
+            measurement_store: Some(measurement_store),
+            ..self
+        }
+    }
+}
+
 impl RuntimeRequestSink for FileTransferServerBuilder {
     fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
         Box::new(self.signal_sender.clone())
@@ -108,6 +136,7 @@ impl Builder<FileTransferServerActor> for FileTransferServerBuilder {
         Ok(FileTransferServerActor {
             file_transfer_dir: self.file_transfer_dir,
             rustls_config: self.rustls_config,
+            measurement_store: self.measurement_store,
             signal_receiver: self.signal_receiver,
             listener: self.listener,
         })
@@ -317,7 +346,11 @@ mod tests {
         }
     }
 
-    type TestConfig = FileTransferServerConfig<InjectedValue<String>, InjectedValue<RootCertStore>>;
+    type TestConfig = FileTransferServerConfig<
+        InjectedValue<String>,
+        InjectedValue<String>,
+        InjectedValue<RootCertStore>,
+    >;
 
     impl<Cert> TestFileTransferService<Cert> {
         fn temp_path_for(&self, file: &str) -> Utf8PathBuf {
@@ -54,6 +54,12 @@ pub enum FileTransferRequestError {
 
     #[error("Path rejection: {0}")]
     PathRejection(#[from] PathRejection),
+
+    #[error("Invalid measurement query: {0}")]
+    InvalidMeasurementQuery(String),
+
+    #[error("Fail to query the measurement store: {0}")]
+    MeasurementStore(String),
 }
 
 impl From<FileTransferError> for RuntimeError {
@@ -71,7 +77,7 @@ impl IntoResponse for FileTransferRequestError {
                 tracing::error!("{error_message}");
                 err.into_response()
             }
-            E::FromIo(_) | E::Delete { .. } | E::Upload { .. } => {
+            E::FromIo(_) | E::Delete { .. } | E::Upload { .. } | E::MeasurementStore(_) => {
                 tracing::error!("{error_message}");
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -86,6 +92,9 @@ impl IntoResponse for FileTransferRequestError {
             E::CannotUploadDirectory { .. } => {
                 (StatusCode::CONFLICT, error_message).into_response()
             }
+            E::InvalidMeasurementQuery(_) => {
+                (StatusCode::BAD_REQUEST, error_message).into_response()
+            }
         }
     }
 }
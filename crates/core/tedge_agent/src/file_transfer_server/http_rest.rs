@@ -1,8 +1,14 @@
 use crate::file_transfer_server::error::FileTransferError;
+use crate::measurement_store::store::MeasurementQuery;
+use crate::measurement_store::store::StoredMeasurement;
+use crate::measurement_store::SharedMeasurementStore;
 use anyhow::anyhow;
 use anyhow::Context;
 use axum::body::StreamBody;
+use axum::extract::Query;
+use axum::extract::State;
 use axum::routing::get;
+use axum::Json;
 use axum::Router;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
@@ -11,10 +17,16 @@ use hyper::Body;
 use hyper::Request;
 use hyper::StatusCode;
 use rustls::ServerConfig;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value;
 use std::future::Future;
 use std::io::ErrorKind;
 use tedge_actors::futures::StreamExt;
+use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_utils::paths::create_directories;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 use tokio::fs::File;
 use tokio::io;
 use tokio::io::AsyncBufReadExt;
@@ -131,12 +143,92 @@ async fn stream_request_body_to_path(
     Ok(())
 }
 
+/// The default maximum number of measurements returned by a query
+const DEFAULT_MEASUREMENT_LIMIT: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+struct MeasurementParams {
+    entity: Option<String>,
+    #[serde(rename = "type")]
+    measurement_type: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<usize>,
+}
+
+impl TryFrom<MeasurementParams> for MeasurementQuery {
+    type Error = Error;
+
+    fn try_from(params: MeasurementParams) -> Result<Self, Self::Error> {
+        let entity = params
+            .entity
+            .map(|entity| {
+                entity
+                    .parse::<EntityTopicId>()
+                    .map(|topic_id| topic_id.to_string())
+                    .map_err(|err| {
+                        Error::InvalidMeasurementQuery(format!("entity {entity}: {err}"))
+                    })
+            })
+            .transpose()?;
+        Ok(MeasurementQuery {
+            entity,
+            measurement_type: params.measurement_type,
+            since: params.since.as_deref().map(parse_time).transpose()?,
+            until: params.until.as_deref().map(parse_time).transpose()?,
+            limit: params.limit.unwrap_or(DEFAULT_MEASUREMENT_LIMIT),
+        })
+    }
+}
+
+fn parse_time(time: &str) -> Result<OffsetDateTime, Error> {
+    OffsetDateTime::parse(time, &Rfc3339)
+        .map_err(|err| Error::InvalidMeasurementQuery(format!("time {time}: {err}")))
+}
+
+async fn query_measurements(
+    State(store): State<SharedMeasurementStore>,
+    Query(params): Query<MeasurementParams>,
+) -> Result<Json<Vec<Value>>, Error> {
+    let query = MeasurementQuery::try_from(params)?;
+    let measurements = tokio::task::spawn_blocking(move || {
+        let store = store
+            .lock()
+            .map_err(|_| Error::MeasurementStore("the store is poisoned".to_string()))?;
+        store
+            .query(&query, OffsetDateTime::now_utc())
+            .map_err(|err| Error::MeasurementStore(err.to_string()))
+    })
+    .await
+    .map_err(|err| Error::MeasurementStore(err.to_string()))??;
+
+    Ok(Json(
+        measurements.into_iter().map(measurement_response).collect(),
+    ))
+}
+
+fn measurement_response(measurement: StoredMeasurement) -> Value {
+    let time = OffsetDateTime::from_unix_timestamp_nanos(measurement.time as i128 * 1_000_000)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok());
+    json!({
+        "time": time,
+        "entity": measurement.entity,
+        "type": measurement.measurement_type,
+        "measurement": measurement.measurement,
+    })
+}
+
 pub(crate) fn http_file_transfer_server(
     listener: TcpListener,
     file_transfer_dir: Utf8PathBuf,
     rustls_config: Option<ServerConfig>,
+    measurement_store: Option<SharedMeasurementStore>,
 ) -> Result<impl Future<Output = io::Result<()>>, FileTransferError> {
-    let router = http_file_transfer_router(file_transfer_dir);
+    let mut router = http_file_transfer_router(file_transfer_dir);
+    if let Some(store) = measurement_store {
+        router = router.merge(http_measurement_router(store));
+    }
     let listener = listener.into_std()?;
 
     let server = if let Some(rustls_config) = rustls_config {
@@ -159,9 +251,17 @@ fn http_file_transfer_router(file_transfer_dir: Utf8PathBuf) -> Router {
         .with_state(FileTransferDir::new(file_transfer_dir))
 }
 
+fn http_measurement_router(store: SharedMeasurementStore) -> Router {
+    Router::new()
+        .route("/tedge/measurements", get(query_measurements))
+        .with_state(store)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::measurement_store::store::unix_millis;
+    use crate::measurement_store::MeasurementStoreConfig;
     use axum::response::Response;
     use bytes::Bytes;
     use http_body::combinators::UnsyncBoxBody;
@@ -354,6 +454,55 @@ mod tests {
         assert_eq!(response.status(), status_code);
     }
 
+    #[tokio::test]
+    async fn stored_measurements_can_be_queried() {
+        let ttd = TempTedgeDir::new();
+        let store = MeasurementStoreConfig {
+            dir: ttd.utf8_path().join("measurements"),
+            max_size: 1024 * 1024,
+            max_age: std::time::Duration::from_secs(3600),
+        }
+        .open_store()
+        .unwrap();
+        let now = unix_millis(OffsetDateTime::now_utc());
+        for (entity, measurement_type, temperature) in [
+            ("device/main//", "environment", 20),
+            ("device/child01//", "environment", 21),
+            ("device/main//", "environment", 22),
+        ] {
+            store
+                .lock()
+                .unwrap()
+                .append(&StoredMeasurement {
+                    time: now,
+                    entity: entity.to_string(),
+                    measurement_type: measurement_type.to_string(),
+                    measurement: json!({ "temperature": temperature }),
+                })
+                .unwrap();
+        }
+        let mut app = http_measurement_router(store);
+
+        let req = Request::builder()
+            .uri("/tedge/measurements?entity=device/main&type=environment&limit=1")
+            .body(Body::empty())
+            .expect("request builder");
+        let response = app.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let measurements: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(measurements[0]["entity"], "device/main//");
+        assert_eq!(measurements[0]["measurement"], json!({"temperature": 22}));
+        assert_eq!(measurements.as_array().unwrap().len(), 1);
+
+        let req = Request::builder()
+            .uri("/tedge/measurements?since=yesterday")
+            .body(Body::empty())
+            .expect("request builder");
+        let response = app.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     async fn request_with(
         method: Method,
         app: &mut Router,
@@ -21,7 +21,9 @@ use tedge_config::DEFAULT_TEDGE_CONFIG_PATH;
 use tracing::log::warn;
 
 mod agent;
+mod certificate_renewal;
 mod file_transfer_server;
+mod measurement_store;
 mod operation_file_cache;
 mod restart_manager;
 mod software_manager;
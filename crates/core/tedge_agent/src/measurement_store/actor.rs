@@ -0,0 +1,136 @@
+use crate::measurement_store::error::MeasurementStoreError;
+use crate::measurement_store::store::unix_millis;
+use crate::measurement_store::store::StoredMeasurement;
+use crate::measurement_store::SharedMeasurementStore;
+use async_trait::async_trait;
+use std::convert::Infallible;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::payload_format::PayloadFormat;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+use time::OffsetDateTime;
+use tracing::error;
+use tracing::warn;
+
+pub struct MeasurementStoreActor {
+    mqtt_schema: MqttSchema,
+    store: SharedMeasurementStore,
+    messages: SimpleMessageBox<MqttMessage, NoMessage>,
+}
+
+#[async_trait]
+impl Actor for MeasurementStoreActor {
+    fn name(&self) -> &str {
+        "MeasurementStore"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        while let Some(message) = self.messages.recv().await {
+            let Some(measurement) = self.stored_measurement(&message) else {
+                continue;
+            };
+            // The measurements are appended to files, hence on a thread where blocking is acceptable
+            let store = self.store.clone();
+            let appended = tokio::task::spawn_blocking(move || {
+                store
+                    .lock()
+                    .map_err(|_| MeasurementStoreError::Poisoned)?
+                    .append(&measurement)
+            })
+            .await;
+            match appended {
+                Ok(Ok(())) => (),
+                Ok(Err(err @ MeasurementStoreError::Poisoned)) => {
+                    error!("{err}");
+                    break;
+                }
+                Ok(Err(err)) => error!("Failed to store a measurement: {err}"),
+                Err(err) => error!("Failed to store a measurement: {err}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MeasurementStoreActor {
+    fn stored_measurement(&self, message: &MqttMessage) -> Option<StoredMeasurement> {
+        let Ok((entity, Channel::Measurement { measurement_type })) =
+            self.mqtt_schema.entity_channel_of(&message.topic)
+        else {
+            return None;
+        };
+        // Binary-encoded measurements are stored as JSON, along the JSON ones of the same type
+        let (measurement_type, format) = PayloadFormat::split_type(&measurement_type);
+        match format.decode(format.payload_of(message)).ok() {
+            Some(measurement @ serde_json::Value::Object(_)) => Some(StoredMeasurement {
+                time: unix_millis(OffsetDateTime::now_utc()),
+                entity: entity.to_string(),
+                measurement_type: measurement_type.to_string(),
+                measurement,
+            }),
+            _ => {
+                warn!(
+                    "Not storing invalid measurement received on {}",
+                    message.topic.name
+                );
+                None
+            }
+        }
+    }
+}
+
+pub struct MeasurementStoreBuilder {
+    mqtt_schema: MqttSchema,
+    store: SharedMeasurementStore,
+    message_box: SimpleMessageBoxBuilder<MqttMessage, NoMessage>,
+}
+
+impl MeasurementStoreBuilder {
+    pub fn new(
+        mqtt_schema: MqttSchema,
+        store: SharedMeasurementStore,
+        mqtt: &mut impl MessageSource<MqttMessage, TopicFilter>,
+    ) -> Self {
+        let message_box = SimpleMessageBoxBuilder::new("MeasurementStore", 16);
+        let topics = mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::Measurement);
+        mqtt.register_peer(topics, message_box.get_sender());
+        MeasurementStoreBuilder {
+            mqtt_schema,
+            store,
+            message_box,
+        }
+    }
+}
+
+impl RuntimeRequestSink for MeasurementStoreBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<MeasurementStoreActor> for MeasurementStoreBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<MeasurementStoreActor, Self::Error> {
+        Ok(MeasurementStoreActor {
+            mqtt_schema: self.mqtt_schema,
+            store: self.store,
+            messages: self.message_box.build(),
+        })
+    }
+}
@@ -0,0 +1,19 @@
+use tedge_actors::RuntimeError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MeasurementStoreError {
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromSerdeJson(#[from] serde_json::Error),
+
+    #[error("The measurement store is poisoned")]
+    Poisoned,
+}
+
+impl From<MeasurementStoreError> for RuntimeError {
+    fn from(error: MeasurementStoreError) -> Self {
+        RuntimeError::ActorError(Box::new(error))
+    }
+}
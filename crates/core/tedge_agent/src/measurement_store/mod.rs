@@ -0,0 +1,33 @@
+//! Persists the measurements published on `te/+/+/+/+/m/+` in a bounded on-disk store,
+//! so local applications can fetch the recent measurements over HTTP.
+//!
+//! The store is queried via the `/tedge/measurements` endpoint of the agent HTTP server.
+pub mod actor;
+pub mod error;
+pub mod store;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use store::MeasurementStore;
+
+/// The store shared by the actor persisting the measurements and the HTTP server querying them
+pub type SharedMeasurementStore = Arc<Mutex<MeasurementStore>>;
+
+#[derive(Debug, Clone)]
+pub struct MeasurementStoreConfig {
+    /// The directory where the measurements are persisted
+    pub dir: camino::Utf8PathBuf,
+
+    /// The maximum size in bytes of the store
+    pub max_size: u64,
+
+    /// The maximum age of the stored measurements
+    pub max_age: std::time::Duration,
+}
+
+impl MeasurementStoreConfig {
+    pub fn open_store(&self) -> Result<SharedMeasurementStore, error::MeasurementStoreError> {
+        let store = MeasurementStore::open(&self.dir, self.max_size, self.max_age)?;
+        Ok(Arc::new(Mutex::new(store)))
+    }
+}
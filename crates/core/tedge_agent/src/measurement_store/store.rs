@@ -0,0 +1,414 @@
+use crate::measurement_store::error::MeasurementStoreError;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::warn;
+
+/// The number of segment files the store is split into
+const SEGMENT_COUNT: u64 = 8;
+
+/// The minimum size of a segment file
+const MIN_SEGMENT_SIZE: u64 = 4 * 1024;
+
+/// A measurement as stored on disk, one JSON object per line
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StoredMeasurement {
+    /// The time at which the measurement was received, in milliseconds since the epoch
+    pub time: i64,
+
+    /// The topic id of the source entity, e.g. `device/main//`
+    pub entity: String,
+
+    /// The measurement type
+    #[serde(rename = "type")]
+    pub measurement_type: String,
+
+    /// The thin-edge JSON measurement, as received
+    pub measurement: Value,
+}
+
+/// The criteria to select stored measurements
+#[derive(Debug, Default)]
+pub struct MeasurementQuery {
+    pub entity: Option<String>,
+    pub measurement_type: Option<String>,
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    /// The maximum number of measurements, the most recent ones being returned
+    pub limit: usize,
+}
+
+/// A bounded on-disk store of measurements
+///
+/// The measurements are appended to a ring of segment files, named after a sequence number.
+/// When the current segment is full, a new one is started,
+/// and the oldest segments are removed to keep the store under its maximum size.
+/// The segments only holding measurements older than the maximum age are removed too.
+pub struct MeasurementStore {
+    dir: Utf8PathBuf,
+    max_size: u64,
+    max_age: Duration,
+    segments: VecDeque<Segment>,
+}
+
+struct Segment {
+    seq: u64,
+    size: u64,
+    last_time: i64,
+}
+
+impl MeasurementStore {
+    /// Open the store persisted in the given directory, creating the directory if needed
+    pub fn open(
+        dir: impl Into<Utf8PathBuf>,
+        max_size: u64,
+        max_age: Duration,
+    ) -> Result<Self, MeasurementStoreError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut seqs = vec![];
+        for entry in dir.read_dir_utf8()? {
+            let entry = entry?;
+            if let Some(seq) = segment_seq(entry.path()) {
+                seqs.push(seq);
+            }
+        }
+        seqs.sort();
+
+        let mut store = MeasurementStore {
+            dir,
+            max_size,
+            max_age,
+            segments: VecDeque::new(),
+        };
+        for seq in seqs {
+            let path = store.segment_path(seq);
+            let size = truncate_incomplete_line(&path)?;
+            let last_time = read_segment(&path)?
+                .last()
+                .map(|measurement| measurement.time)
+                .unwrap_or_default();
+            store.segments.push_back(Segment {
+                seq,
+                size,
+                last_time,
+            });
+        }
+        Ok(store)
+    }
+
+    /// Append a measurement, removing the measurements that are too old or don't fit
+    pub fn append(&mut self, measurement: &StoredMeasurement) -> Result<(), MeasurementStoreError> {
+        let mut line = serde_json::to_string(measurement)?;
+        line.push('\n');
+        let line_size = line.len() as u64;
+
+        let segment_size = (self.max_size / SEGMENT_COUNT).max(MIN_SEGMENT_SIZE);
+        let must_rotate = match self.segments.back() {
+            None => true,
+            Some(current) => current.size > 0 && current.size + line_size > segment_size,
+        };
+        if must_rotate {
+            let seq = self.segments.back().map_or(0, |current| current.seq + 1);
+            self.segments.push_back(Segment {
+                seq,
+                size: 0,
+                last_time: measurement.time,
+            });
+        }
+
+        let current = self.segments.back_mut().expect("a current segment");
+        let path = segment_path(&self.dir, current.seq);
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(line.as_bytes())?;
+        current.size += line_size;
+        current.last_time = measurement.time;
+
+        self.apply_retention(measurement.time)
+    }
+
+    /// Return the stored measurements matching the query, from the oldest to the most recent
+    pub fn query(
+        &self,
+        query: &MeasurementQuery,
+        now: OffsetDateTime,
+    ) -> Result<Vec<StoredMeasurement>, MeasurementStoreError> {
+        if query.limit == 0 {
+            return Ok(vec![]);
+        }
+        let oldest = unix_millis(now) - self.max_age.as_millis() as i64;
+        let since = query
+            .since
+            .map_or(oldest, |since| unix_millis(since).max(oldest));
+        let until = query.until.map_or(i64::MAX, unix_millis);
+
+        let mut measurements = VecDeque::new();
+        for segment in self.segments.iter().filter(|s| s.last_time >= since) {
+            for measurement in read_segment(&self.segment_path(segment.seq))? {
+                if measurement.time < since || measurement.time > until {
+                    continue;
+                }
+                if query
+                    .entity
+                    .as_ref()
+                    .is_some_and(|e| e != &measurement.entity)
+                    || query
+                        .measurement_type
+                        .as_ref()
+                        .is_some_and(|t| t != &measurement.measurement_type)
+                {
+                    continue;
+                }
+                if measurements.len() == query.limit {
+                    measurements.pop_front();
+                }
+                measurements.push_back(measurement);
+            }
+        }
+        Ok(measurements.into())
+    }
+
+    /// The total size of the segment files
+    pub fn size(&self) -> u64 {
+        self.segments.iter().map(|s| s.size).sum()
+    }
+
+    fn apply_retention(&mut self, now: i64) -> Result<(), MeasurementStoreError> {
+        let oldest = now - self.max_age.as_millis() as i64;
+        while self.segments.len() > 1 {
+            let first = &self.segments[0];
+            if self.size() <= self.max_size && first.last_time >= oldest {
+                break;
+            }
+            std::fs::remove_file(self.segment_path(first.seq))?;
+            self.segments.pop_front();
+        }
+        Ok(())
+    }
+
+    fn segment_path(&self, seq: u64) -> Utf8PathBuf {
+        segment_path(&self.dir, seq)
+    }
+}
+
+pub fn unix_millis(time: OffsetDateTime) -> i64 {
+    (time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn segment_path(dir: &Utf8Path, seq: u64) -> Utf8PathBuf {
+    dir.join(format!("{seq:020}.jsonl"))
+}
+
+fn segment_seq(path: &Utf8Path) -> Option<u64> {
+    if path.extension() != Some("jsonl") {
+        return None;
+    }
+    path.file_stem()?.parse().ok()
+}
+
+/// Truncate a segment back to its last complete line, returning the size of the segment
+///
+/// A crash while appending a measurement might leave an incomplete last line,
+/// to which the next measurement would otherwise be appended.
+fn truncate_incomplete_line(path: &Utf8Path) -> Result<u64, MeasurementStoreError> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut reader = BufReader::new(&file);
+    let mut complete_len = 0;
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if !buffer.ends_with(b"\n") {
+            warn!("Truncating the incomplete last measurement of {path}");
+            file.set_len(complete_len)?;
+            break;
+        }
+        complete_len += read as u64;
+    }
+    Ok(complete_len)
+}
+
+/// Read the measurements of a segment, ignoring a trailing line left incomplete by a crash
+fn read_segment(path: &Utf8Path) -> Result<Vec<StoredMeasurement>, MeasurementStoreError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut measurements = vec![];
+    for line in BufReader::new(file).lines() {
+        if let Ok(measurement) = serde_json::from_str(&line?) {
+            measurements.push(measurement);
+        }
+    }
+    Ok(measurements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tedge_test_utils::fs::TempTedgeDir;
+    use time::macros::datetime;
+
+    const NOW: OffsetDateTime = datetime!(2023-10-18 10:00:00 UTC);
+
+    fn measurement(seconds_ago: i64, entity: &str, measurement_type: &str) -> StoredMeasurement {
+        StoredMeasurement {
+            time: unix_millis(NOW) - seconds_ago * 1000,
+            entity: entity.to_string(),
+            measurement_type: measurement_type.to_string(),
+            measurement: json!({"temperature": seconds_ago}),
+        }
+    }
+
+    fn all() -> MeasurementQuery {
+        MeasurementQuery {
+            limit: usize::MAX,
+            ..MeasurementQuery::default()
+        }
+    }
+
+    #[test]
+    fn query_measurements_by_entity_type_and_time() {
+        let ttd = TempTedgeDir::new();
+        let mut store = MeasurementStore::open(
+            ttd.utf8_path().join("m"),
+            1024 * 1024,
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+
+        let m1 = measurement(30, "device/main//", "environment");
+        let m2 = measurement(20, "device/child01//", "environment");
+        let m3 = measurement(10, "device/main//", "");
+        let m4 = measurement(5, "device/main//", "environment");
+        for m in [&m1, &m2, &m3, &m4] {
+            store.append(m).unwrap();
+        }
+
+        let query = MeasurementQuery {
+            entity: Some("device/main//".to_string()),
+            measurement_type: Some("environment".to_string()),
+            ..all()
+        };
+        assert_eq!(
+            store.query(&query, NOW).unwrap(),
+            vec![m1.clone(), m4.clone()]
+        );
+
+        let query = MeasurementQuery {
+            since: Some(NOW - time::Duration::seconds(25)),
+            until: Some(NOW - time::Duration::seconds(10)),
+            ..all()
+        };
+        assert_eq!(
+            store.query(&query, NOW).unwrap(),
+            vec![m2.clone(), m3.clone()]
+        );
+
+        // Only the most recent measurements are returned when the limit is reached
+        let query = MeasurementQuery { limit: 2, ..all() };
+        assert_eq!(store.query(&query, NOW).unwrap(), vec![m3, m4]);
+    }
+
+    #[test]
+    fn the_store_survives_a_restart() {
+        let ttd = TempTedgeDir::new();
+        let dir = ttd.utf8_path().join("m");
+        let m = measurement(10, "device/main//", "");
+
+        let mut store =
+            MeasurementStore::open(&dir, 1024 * 1024, Duration::from_secs(3600)).unwrap();
+        store.append(&m).unwrap();
+
+        let mut store =
+            MeasurementStore::open(&dir, 1024 * 1024, Duration::from_secs(3600)).unwrap();
+        let m2 = measurement(5, "device/main//", "");
+        store.append(&m2).unwrap();
+        assert_eq!(store.query(&all(), NOW).unwrap(), vec![m, m2]);
+    }
+
+    #[test]
+    fn an_incomplete_last_line_is_truncated_on_open() {
+        let ttd = TempTedgeDir::new();
+        let dir = ttd.utf8_path().join("m");
+        let m = measurement(10, "device/main//", "");
+
+        let mut store =
+            MeasurementStore::open(&dir, 1024 * 1024, Duration::from_secs(3600)).unwrap();
+        store.append(&m).unwrap();
+        let size = store.size();
+
+        // Simulate a crash while appending a measurement
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(segment_path(&dir, 0))
+            .unwrap();
+        file.write_all(br#"{"time":1697623190000,"enti"#).unwrap();
+
+        let mut store =
+            MeasurementStore::open(&dir, 1024 * 1024, Duration::from_secs(3600)).unwrap();
+        assert_eq!(store.size(), size);
+
+        let m2 = measurement(5, "device/main//", "");
+        store.append(&m2).unwrap();
+        assert_eq!(store.query(&all(), NOW).unwrap(), vec![m, m2]);
+    }
+
+    #[test]
+    fn the_oldest_measurements_are_removed_when_the_store_is_full() {
+        let ttd = TempTedgeDir::new();
+        let max_size = 8 * MIN_SEGMENT_SIZE;
+        let mut store = MeasurementStore::open(
+            ttd.utf8_path().join("m"),
+            max_size,
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+
+        for i in (0..3000).rev() {
+            store.append(&measurement(i, "device/main//", "")).unwrap();
+        }
+
+        assert!(store.size() <= max_size);
+        let measurements = store.query(&all(), NOW).unwrap();
+        assert!(measurements.len() < 3000);
+        assert_eq!(
+            measurements.last(),
+            Some(&measurement(0, "device/main//", ""))
+        );
+    }
+
+    #[test]
+    fn measurements_older_than_the_max_age_are_ignored() {
+        let ttd = TempTedgeDir::new();
+        let mut store = MeasurementStore::open(
+            ttd.utf8_path().join("m"),
+            1024 * 1024,
+            Duration::from_secs(60),
+        )
+        .unwrap();
+
+        let old = measurement(120, "device/main//", "");
+        let recent = measurement(30, "device/main//", "");
+        store.append(&old).unwrap();
+        store.append(&recent).unwrap();
+
+        assert_eq!(store.query(&all(), NOW).unwrap(), vec![recent]);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::state_repository::error::StateError;
+use camino::Utf8PathBuf;
+use tedge_api::workflow::CommandHistory;
+use tedge_api::workflow::HistoryEvent;
+use tedge_utils::fs::atomically_write_file_async;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Persist the command history as a log of events
+///
+/// The new events are appended to the history file,
+/// which is only rewritten when the log has grown far beyond the retained history.
+#[derive(Debug)]
+pub struct CommandHistoryRepository {
+    pub history_path: Utf8PathBuf,
+
+    /// The number of completed commands retained in the history
+    capacity: usize,
+
+    /// The number of events currently logged in the history file
+    logged_events: usize,
+}
+
+impl CommandHistoryRepository {
+    /// The history file is compacted when it logs that many times more events than required
+    const COMPACTION_RATIO: usize = 2;
+
+    pub fn new(history_path: Utf8PathBuf, capacity: usize) -> Self {
+        CommandHistoryRepository {
+            history_path,
+            capacity,
+            logged_events: 0,
+        }
+    }
+
+    /// Load the history, starting with an empty history if there is no history file yet
+    pub async fn load(&mut self) -> Result<CommandHistory, StateError> {
+        let log = match fs::read_to_string(&self.history_path).await {
+            Ok(log) => log,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(source) => {
+                return Err(StateError::LoadingFromFileFailed {
+                    path: self.history_path.as_path().into(),
+                    source,
+                })
+            }
+        };
+        self.logged_events = log.lines().count();
+        Ok(CommandHistory::from_log(&log, self.capacity))
+    }
+
+    /// Append to the history file the events recorded since the previous call
+    pub async fn append(&mut self, history: &mut CommandHistory) -> Result<(), StateError> {
+        let new_events = history.take_new_events();
+        if new_events.is_empty() {
+            return Ok(());
+        }
+
+        let retained_events = history.events();
+        let max_logged_events = Self::COMPACTION_RATIO * retained_events.len().max(self.capacity);
+        if self.logged_events + new_events.len() > max_logged_events {
+            return self.compact(&retained_events).await;
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.history_path)
+            .await?;
+        file.write_all(&log_lines(&new_events)?).await?;
+        file.flush().await?;
+        self.logged_events += new_events.len();
+        Ok(())
+    }
+
+    /// Rewrite the history file with only the events required to rebuild the history
+    async fn compact(&mut self, events: &[HistoryEvent]) -> Result<(), StateError> {
+        atomically_write_file_async(&self.history_path, &log_lines(events)?).await?;
+        self.logged_events = events.len();
+        Ok(())
+    }
+}
+
+fn log_lines(events: &[HistoryEvent]) -> Result<Vec<u8>, StateError> {
+    let mut lines = vec![];
+    for event in events {
+        serde_json::to_writer(&mut lines, event)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_api::workflow::GenericCommandState;
+    use tedge_mqtt_ext::Topic;
+    use tedge_test_utils::fs::TempTedgeDir;
+
+    fn init_command(cmd_id: usize) -> GenericCommandState {
+        GenericCommandState::from_command_message(&tedge_mqtt_ext::MqttMessage::new(
+            &Topic::new_unchecked(&format!("te/device/main///cmd/restart/{cmd_id}")),
+            r#"{"status":"init"}"#,
+        ))
+        .unwrap()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn new_events_are_appended() {
+        let temp_dir = TempTedgeDir::new();
+        let path = temp_dir.utf8_path().join("workflow-history");
+        let mut repository =
+            CommandHistoryRepository::new(path.clone(), CommandHistory::DEFAULT_CAPACITY);
+        let mut history = repository.load().await.unwrap();
+
+        history.record_state(&init_command(1));
+        repository.append(&mut history).await.unwrap();
+        history.record_clearing("te/device/main///cmd/restart/1");
+        repository.append(&mut history).await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        assert_eq!(
+            CommandHistory::from_log(&log, CommandHistory::DEFAULT_CAPACITY),
+            history
+        );
+        assert_eq!(repository.load().await.unwrap(), history);
+    }
+
+    #[tokio::test]
+    async fn history_file_is_compacted_when_too_large() {
+        let temp_dir = TempTedgeDir::new();
+        let path = temp_dir.utf8_path().join("workflow-history");
+        let mut repository =
+            CommandHistoryRepository::new(path.clone(), CommandHistory::DEFAULT_CAPACITY);
+        let mut history = repository.load().await.unwrap();
+
+        let capacity = CommandHistory::DEFAULT_CAPACITY;
+        for cmd_id in 0..(3 * capacity) {
+            history.record_state(&init_command(cmd_id));
+            history.record_clearing(&format!("te/device/main///cmd/restart/{cmd_id}"));
+            repository.append(&mut history).await.unwrap();
+        }
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.lines().count() <= 2 * history.events().len());
+        assert_eq!(history.iter().count(), capacity);
+        assert_eq!(
+            CommandHistory::from_log(&log, CommandHistory::DEFAULT_CAPACITY),
+            history
+        );
+    }
+}
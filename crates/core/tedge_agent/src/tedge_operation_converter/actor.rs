@@ -1,9 +1,11 @@
 use crate::software_manager::actor::SoftwareCommand;
+use crate::state_repository::history::CommandHistoryRepository;
 use crate::state_repository::state::AgentStateRepository;
 use async_trait::async_trait;
 use camino::Utf8PathBuf;
 use log::error;
 use log::info;
+use std::collections::HashSet;
 use std::process::Output;
 use std::time::Duration;
 use tedge_actors::fan_in_message_type;
@@ -25,23 +27,34 @@ use tedge_api::mqtt_topics::OperationType;
 use tedge_api::workflow::CommandBoard;
 use tedge_api::workflow::GenericCommandState;
 use tedge_api::workflow::OperationAction;
+use tedge_api::workflow::WhenClause;
 use tedge_api::workflow::WorkflowExecutionError;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
 use tedge_script_ext::Execute;
+use tedge_timer_ext::SetTimeout;
+use tedge_timer_ext::Timeout;
 use time::format_description;
 use time::OffsetDateTime;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::time::sleep;
 
-fan_in_message_type!(AgentInput[MqttMessage, GenericCommandState, SoftwareCommand, RestartCommand] : Debug);
+/// Request to resume a command when its maintenance window opens or its retry delay has elapsed
+pub type DeferCommand = SetTimeout<GenericCommandState>;
+
+/// A command whose maintenance window is open or whose retry delay has elapsed
+pub type DeferredCommand = Timeout<GenericCommandState>;
+
+fan_in_message_type!(AgentInput[MqttMessage, GenericCommandState, SoftwareCommand, RestartCommand, DeferredCommand] : Debug);
 
 pub struct TedgeOperationConverterActor {
     pub(crate) mqtt_schema: MqttSchema,
     pub(crate) device_topic_id: EntityTopicId,
     pub(crate) workflows: WorkflowSupervisor,
     pub(crate) state_repository: AgentStateRepository<CommandBoard>,
+    pub(crate) history_repository: CommandHistoryRepository,
     pub(crate) log_dir: Utf8PathBuf,
     pub(crate) input_receiver: LoggingReceiver<AgentInput>,
     pub(crate) software_sender: LoggingSender<SoftwareCommand>,
@@ -49,6 +62,8 @@ pub struct TedgeOperationConverterActor {
     pub(crate) command_sender: DynSender<GenericCommandState>,
     pub(crate) mqtt_publisher: LoggingSender<MqttMessage>,
     pub(crate) script_runner: ClientMessageBox<Execute, std::io::Result<Output>>,
+    pub(crate) timer_sender: LoggingSender<DeferCommand>,
+    pub(crate) retrying_commands: HashSet<String>,
 }
 
 #[async_trait]
@@ -78,6 +93,9 @@ impl Actor for TedgeOperationConverterActor {
                 AgentInput::RestartCommand(cmd) => {
                     self.process_restart_response(cmd).await?;
                 }
+                AgentInput::DeferredCommand(Timeout { event: command }) => {
+                    self.process_deferred_command(command).await?;
+                }
             }
         }
         Ok(())
@@ -98,6 +116,11 @@ impl TedgeOperationConverterActor {
     async fn process_mqtt_message(&mut self, message: MqttMessage) -> Result<(), RuntimeError> {
         let (operation, cmd_id) = match self.mqtt_schema.entity_channel_of(&message.topic) {
             Ok((_, Channel::Command { operation, cmd_id })) => (operation, cmd_id),
+            Ok((_, Channel::CommandCancel { operation, cmd_id })) => {
+                return self
+                    .process_cancel_request(operation, cmd_id, &message)
+                    .await;
+            }
 
             _ => {
                 log::error!("Unknown command channel: {}", message.topic.name);
@@ -106,6 +129,7 @@ impl TedgeOperationConverterActor {
         };
 
         let mut log_file = CommandLog::new(self.log_dir.clone(), &operation, &cmd_id).await;
+
         match self.workflows.apply_external_update(&operation, &message) {
             Ok(None) => {
                 if message.payload_bytes().is_empty() {
@@ -113,8 +137,19 @@ impl TedgeOperationConverterActor {
                         .log_step("", "The command has been fully processed")
                         .await;
                     self.persist_command_board().await?;
+                    self.start_queued_commands().await?;
                 }
             }
+            Ok(Some(state)) if state.is_queued() => {
+                info!(
+                    "Queuing {operation} operation {cmd_id}, the concurrency limit being reached"
+                );
+                log_file
+                    .log_step(&state.status, "Awaiting the completion of other commands")
+                    .await;
+                self.persist_command_board().await?;
+                self.mqtt_publisher.send(state.into_message()).await?;
+            }
             Ok(Some(state)) => {
                 self.persist_command_board().await?;
                 self.process_command_state_update(state).await?;
@@ -130,12 +165,73 @@ impl TedgeOperationConverterActor {
             }
         }
 
-        Ok(())
+        self.process_sub_command_update(&message).await
+    }
+
+    async fn process_cancel_request(
+        &mut self,
+        operation: OperationType,
+        cmd_id: String,
+        message: &MqttMessage,
+    ) -> Result<(), RuntimeError> {
+        let Some(cancelled) = self.workflows.apply_cancel_request(message) else {
+            info!(
+                "Ignoring cancel request for {operation} operation {cmd_id}, which is not pending"
+            );
+            return Ok(());
+        };
+        info!("Cancelling {operation} operation {cmd_id}");
+        let mut log_file = CommandLog::new(self.log_dir.clone(), &operation, &cmd_id).await;
+        log_file
+            .log_step("cancel", "The command has been cancelled")
+            .await;
+        self.publish_command_state(cancelled).await
+    }
+
+    async fn process_sub_command_update(
+        &mut self,
+        message: &MqttMessage,
+    ) -> Result<(), RuntimeError> {
+        match self.workflows.apply_sub_command_update(message) {
+            Ok(None) => Ok(()),
+            Ok(Some(invoking_command)) => {
+                info!(
+                    "Resuming {} on completion of the sub-operation {}",
+                    invoking_command.topic.name, message.topic.name
+                );
+                self.publish_command_state(invoking_command).await?;
+
+                // As the requester of the sub-operation, the invoking command has to clear it
+                let clear_request = MqttMessage::new(&message.topic, "")
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce);
+                self.mqtt_publisher.send(clear_request).await?;
+                Ok(())
+            }
+            Err(err) => {
+                error!(
+                    "Sub-operation {} cannot be processed: {err}",
+                    message.topic.name
+                );
+                Ok(())
+            }
+        }
     }
 
     async fn process_command_state_update(
         &mut self,
         state: GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        self.process_command_step(state, false).await
+    }
+
+    /// Process the current step of a command
+    ///
+    /// The `backoff_elapsed` flag tells if the command is resumed after a retry delay.
+    async fn process_command_step(
+        &mut self,
+        state: GenericCommandState,
+        backoff_elapsed: bool,
     ) -> Result<(), RuntimeError> {
         let (target, operation, cmd_id) = match self.mqtt_schema.entity_channel_of(&state.topic) {
             Ok((target, Channel::Command { operation, cmd_id })) => (target, operation, cmd_id),
@@ -147,6 +243,42 @@ impl TedgeOperationConverterActor {
         };
         let mut log_file = CommandLog::new(self.log_dir.clone(), &operation, &cmd_id).await;
 
+        if self.workflows.is_outdated(&state) {
+            info!(
+                "Ignoring {operation} operation {} step, the command having been cancelled",
+                state.status
+            );
+            return Ok(());
+        }
+
+        match state.schedule_delay(OffsetDateTime::now_utc()) {
+            Ok(delay) if delay.is_zero() => {}
+            Ok(delay) => {
+                info!(
+                    "Deferring {operation} operation {cmd_id} by {}s, till its maintenance window opens",
+                    delay.as_secs()
+                );
+                log_file
+                    .log_step(
+                        &state.status,
+                        &format!("Waiting {}s for the maintenance window", delay.as_secs()),
+                    )
+                    .await;
+                self.timer_sender
+                    .send(DeferCommand::new(delay, state))
+                    .await?;
+                return Ok(());
+            }
+            Err(err) => {
+                error!("{operation} operation {cmd_id} cannot be scheduled: {err}");
+                log_file
+                    .log_step(&state.status, &format!("Error: {err}\n"))
+                    .await;
+                let new_state = state.fail_with(err.to_string());
+                return self.publish_command_state(new_state).await;
+            }
+        }
+
         let action = match self.workflows.get_action(&state) {
             Ok(action) => action,
             Err(WorkflowExecutionError::UnknownStep { operation, step }) => {
@@ -178,6 +310,17 @@ impl TedgeOperationConverterActor {
                 let new_state = state.move_to(next_step);
                 self.publish_command_state(new_state).await
             }
+            OperationAction::Branch(clauses, otherwise) => {
+                let next_state = WhenClause::select(&clauses, &state)
+                    .map(|clause| clause.next.clone())
+                    .unwrap_or(otherwise);
+                info!(
+                    "Moving {operation} operation to state: {}",
+                    next_state.status
+                );
+                let new_state = state.update(next_state);
+                self.publish_command_state(new_state).await
+            }
             OperationAction::BuiltIn => {
                 let step = &state.status;
                 info!("Processing {operation} operation {step} step");
@@ -220,6 +363,35 @@ impl TedgeOperationConverterActor {
                 let step = &state.status;
                 info!("Processing {operation} operation {step} step with script: {script}");
 
+                if let Some(retry) = handlers.retry_policy().filter(|_| !backoff_elapsed) {
+                    let attempt = state.attempt();
+                    let delay = retry.delay(attempt);
+                    if !delay.is_zero() {
+                        info!(
+                            "Retrying {operation} operation {step} step in {}s (attempt {attempt}/{})",
+                            delay.as_secs(),
+                            retry.retries + 1
+                        );
+                        log_file
+                            .log_step(
+                                step,
+                                &format!(
+                                    "Retry in {}s (attempt {attempt}/{})",
+                                    delay.as_secs(),
+                                    retry.retries + 1
+                                ),
+                            )
+                            .await;
+                        // The retry is scheduled by the timer actor, so other commands,
+                        // and notably a cancel request, are processed in the meantime
+                        self.retrying_commands.insert(state.topic.name.clone());
+                        self.timer_sender
+                            .send(DeferCommand::new(delay, state))
+                            .await?;
+                        return Ok(());
+                    }
+                }
+
                 let script_name = script.command.clone();
                 let command = {
                     let command = Execute::new(script_name.clone(), script.args);
@@ -240,6 +412,38 @@ impl TedgeOperationConverterActor {
                 let new_state = state.update_with_script_output(script_name, output, handlers);
                 self.publish_command_state(new_state).await
             }
+            OperationAction::Operation(sub_operation, input, handlers) => {
+                let next_state = &handlers.on_exec.status;
+                info!(
+                    "Triggering {sub_operation} sub-operation and moving {operation} operation to {next_state} state"
+                );
+                let sub_command =
+                    match self
+                        .workflows
+                        .start_sub_command(&state, &sub_operation, input)
+                    {
+                        Ok(sub_command) => sub_command,
+                        Err(err) => {
+                            error!("{sub_operation} sub-operation cannot be created: {err}");
+                            let new_state = state.fail_with(format!(
+                                "{sub_operation} sub-operation cannot be created: {err}"
+                            ));
+                            return self.publish_command_state(new_state).await;
+                        }
+                    };
+
+                // The invoking command is moved to its next state before the sub-operation is triggered,
+                // so the sub-operation outcome is never received while the invoking command is not ready.
+                let new_state = state.update(handlers.on_exec);
+                self.publish_command_state(new_state).await?;
+                self.mqtt_publisher.send(sub_command.into_message()).await?;
+                Ok(())
+            }
+            OperationAction::AwaitOperationCompletion(_) => {
+                let step = &state.status;
+                info!("{operation} operation {step} waiting for sub-operation completion");
+                Ok(())
+            }
             OperationAction::BgScript(script, handlers) => {
                 let next_state = &handlers.on_exec.status;
                 info!(
@@ -257,6 +461,31 @@ impl TedgeOperationConverterActor {
         }
     }
 
+    /// Resume a scheduled or retried command, unless it has been cancelled or cleared while waiting
+    async fn process_deferred_command(
+        &mut self,
+        command: GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        let backoff_elapsed = self.retrying_commands.remove(&command.topic.name);
+        if self.workflows.pending_commands().get(&command.topic.name) != Some(&command) {
+            info!(
+                "Ignoring the deferred step of {}, the command having been updated",
+                command.topic.name
+            );
+            return Ok(());
+        }
+        if let Ok(Some(queued)) = self.workflows.queue_over_concurrency_limit(&command) {
+            info!(
+                "Queuing {}, the concurrency limit being reached when its maintenance window opens",
+                command.topic.name
+            );
+            self.persist_command_board().await?;
+            self.mqtt_publisher.send(queued.into_message()).await?;
+            return Ok(());
+        }
+        self.process_command_step(command, backoff_elapsed).await
+    }
+
     async fn process_internal_operation(
         &mut self,
         target: EntityTopicId,
@@ -329,17 +558,52 @@ impl TedgeOperationConverterActor {
         &mut self,
         new_state: GenericCommandState,
     ) -> Result<(), RuntimeError> {
+        if self.workflows.is_outdated(&new_state) {
+            info!(
+                "Ignoring {} update to {}, the command having been cancelled",
+                new_state.topic.name, new_state.status
+            );
+            return Ok(());
+        }
         if let Err(err) = self.workflows.apply_internal_update(new_state.clone()) {
             error!("Fail to persist workflow operation state: {err}");
         }
         self.persist_command_board().await?;
+        let is_terminal = new_state.is_terminal();
         self.command_sender.send(new_state.clone()).await?;
         self.mqtt_publisher.send(new_state.into_message()).await?;
+        if is_terminal {
+            self.start_queued_commands().await?;
+        }
+        Ok(())
+    }
+
+    /// Start the queued commands that are no more blocked by the concurrency limits
+    async fn start_queued_commands(&mut self) -> Result<(), RuntimeError> {
+        let started_commands = self.workflows.start_queued_commands();
+        if started_commands.is_empty() {
+            return Ok(());
+        }
+        self.persist_command_board().await?;
+        for command in started_commands {
+            info!("Starting queued command {}", command.topic.name);
+            self.command_sender.send(command.clone()).await?;
+            self.mqtt_publisher.send(command.into_message()).await?;
+        }
         Ok(())
     }
 
     /// Reload from disk the current state of the pending command requests
     async fn load_command_board(&mut self) -> Result<(), RuntimeError> {
+        match self.history_repository.load().await {
+            Ok(history) => self.workflows.load_command_history(history),
+            Err(err) => {
+                error!(
+                    "Fail to reload command history from {} due to: {}",
+                    self.history_repository.history_path, err
+                );
+            }
+        }
         match self.state_repository.load().await {
             Ok(Some(pending_commands)) => {
                 self.workflows.load_pending_commands(pending_commands);
@@ -349,6 +613,7 @@ impl TedgeOperationConverterActor {
                         self.command_sender.send(resumed_command).await?;
                     }
                 }
+                self.start_queued_commands().await?;
             }
             Ok(None) => {}
             Err(err) => {
@@ -370,6 +635,13 @@ impl TedgeOperationConverterActor {
                 self.state_repository.state_repo_path, err
             );
         }
+        let history = self.workflows.command_history_mut();
+        if let Err(err) = self.history_repository.append(history).await {
+            error!(
+                "Fail to persist command history in {} due to: {}",
+                self.history_repository.history_path, err
+            );
+        }
 
         Ok(())
     }
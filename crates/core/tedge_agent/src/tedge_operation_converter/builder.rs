@@ -1,9 +1,13 @@
 use crate::software_manager::actor::SoftwareCommand;
+use crate::state_repository::history::CommandHistoryRepository;
 use crate::state_repository::state::AgentStateRepository;
 use crate::tedge_operation_converter::actor::AgentInput;
+use crate::tedge_operation_converter::actor::DeferCommand;
+use crate::tedge_operation_converter::actor::DeferredCommand;
 use crate::tedge_operation_converter::actor::TedgeOperationConverterActor;
 use crate::tedge_operation_converter::config::OperationConfig;
 use log::error;
+use std::collections::HashSet;
 use std::process::Output;
 use tedge_actors::futures::channel::mpsc;
 use tedge_actors::Builder;
@@ -17,12 +21,15 @@ use tedge_actors::RuntimeRequest;
 use tedge_actors::RuntimeRequestSink;
 use tedge_actors::ServiceProvider;
 use tedge_api::mqtt_topics::ChannelFilter::AnyCommand;
+use tedge_api::mqtt_topics::ChannelFilter::AnyCommandCancel;
 use tedge_api::mqtt_topics::EntityFilter;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::CommandHistory;
 use tedge_api::workflow::GenericCommandState;
 use tedge_api::workflow::WorkflowSupervisor;
+use tedge_api::workflow::COMMAND_HISTORY_FILE;
 use tedge_api::RestartCommand;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::TopicFilter;
@@ -37,6 +44,7 @@ pub struct TedgeOperationConverterBuilder {
     command_sender: DynSender<GenericCommandState>,
     mqtt_publisher: LoggingSender<MqttMessage>,
     script_runner: ClientMessageBox<Execute, std::io::Result<Output>>,
+    timer_sender: LoggingSender<DeferCommand>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
 }
 
@@ -48,6 +56,7 @@ impl TedgeOperationConverterBuilder {
         restart_actor: &mut impl ServiceProvider<RestartCommand, RestartCommand, NoConfig>,
         mqtt_actor: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
         script_runner: &mut impl ServiceProvider<Execute, std::io::Result<Output>, NoConfig>,
+        timer: &mut impl ServiceProvider<DeferCommand, DeferredCommand, NoConfig>,
     ) -> Self {
         let (input_sender, input_receiver) = mpsc::channel(10);
         let (signal_sender, signal_receiver) = mpsc::channel(10);
@@ -66,6 +75,9 @@ impl TedgeOperationConverterBuilder {
         let restart_sender = LoggingSender::new("RestartSender".into(), restart_sender);
         let command_sender = input_sender.clone().into();
 
+        let timer_sender = timer.connect_consumer(NoConfig, input_sender.clone().into());
+        let timer_sender = LoggingSender::new("TimerSender".into(), timer_sender);
+
         let mqtt_publisher = mqtt_actor.connect_consumer(
             Self::subscriptions(&config.mqtt_schema, &config.device_topic_id),
             input_sender.into(),
@@ -91,6 +103,7 @@ impl TedgeOperationConverterBuilder {
             mqtt_publisher,
             signal_sender,
             script_runner,
+            timer_sender,
         }
     }
 
@@ -103,7 +116,9 @@ impl TedgeOperationConverterBuilder {
     }
 
     pub fn subscriptions(mqtt_schema: &MqttSchema, device_topic_id: &EntityTopicId) -> TopicFilter {
-        mqtt_schema.topics(EntityFilter::Entity(device_topic_id), AnyCommand)
+        let mut topics = mqtt_schema.topics(EntityFilter::Entity(device_topic_id), AnyCommand);
+        topics.add_all(mqtt_schema.topics(EntityFilter::Entity(device_topic_id), AnyCommandCancel));
+        topics
     }
 }
 
@@ -123,11 +138,19 @@ impl Builder<TedgeOperationConverterActor> for TedgeOperationConverterBuilder {
     fn build(self) -> TedgeOperationConverterActor {
         let repository =
             AgentStateRepository::new(self.config.state_dir, self.config.config_dir, "workflows");
+        // The command history is persisted along the pending commands
+        let history_repository = CommandHistoryRepository::new(
+            repository
+                .state_repo_path
+                .with_file_name(COMMAND_HISTORY_FILE),
+            CommandHistory::DEFAULT_CAPACITY,
+        );
         TedgeOperationConverterActor {
             mqtt_schema: self.config.mqtt_schema,
             device_topic_id: self.config.device_topic_id,
             workflows: self.workflows,
             state_repository: repository,
+            history_repository,
             log_dir: self.config.log_dir,
             input_receiver: self.input_receiver,
             software_sender: self.software_sender,
@@ -135,6 +158,8 @@ impl Builder<TedgeOperationConverterActor> for TedgeOperationConverterBuilder {
             mqtt_publisher: self.mqtt_publisher,
             command_sender: self.command_sender,
             script_runner: self.script_runner,
+            timer_sender: self.timer_sender,
+            retrying_commands: HashSet::new(),
         }
     }
 }
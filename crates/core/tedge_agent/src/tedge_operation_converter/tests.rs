@@ -2,8 +2,10 @@ use crate::software_manager::actor::SoftwareCommand;
 use crate::tedge_operation_converter::builder::TedgeOperationConverterBuilder;
 use crate::tedge_operation_converter::config::OperationConfig;
 use camino::Utf8Path;
+use std::os::unix::process::ExitStatusExt;
 use std::process::Output;
 use std::time::Duration;
+use std::time::Instant;
 use tedge_actors::test_helpers::MessageReceiverExt;
 use tedge_actors::test_helpers::TimedMessageBox;
 use tedge_actors::Actor;
@@ -22,6 +24,8 @@ use tedge_api::messages::SoftwareRequestResponseSoftwareList;
 use tedge_api::messages::SoftwareUpdateCommandPayload;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::OperationWorkflow;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_api::RestartCommand;
 use tedge_api::SoftwareUpdateCommand;
@@ -29,6 +33,9 @@ use tedge_mqtt_ext::test_helpers::assert_received_contains_str;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
 use tedge_script_ext::Execute;
+use tedge_timer_ext::TimerActor;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
 
@@ -237,6 +244,484 @@ async fn convert_outgoing_restart_response() -> Result<(), DynError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn cancel_pending_command() -> Result<(), DynError> {
+    let (_software_box, mut restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate Restart MQTT request, and let the restart actor take it
+    let topic = Topic::new_unchecked("te/device/main///cmd/restart/abc");
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{"status": "init"}"#))
+        .await?;
+    let restart_command = restart_box.recv().await.expect("RestartCommand");
+
+    // Simulate a cancel request
+    let cancel_request = GenericCommandState::cancel_request(topic, Some("not now".to_string()));
+    mqtt_box.send(cancel_request).await?;
+
+    // The command is moved to the failed state
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/restart/abc",
+                r#""status":"scheduled""#,
+            ),
+            ("te/device/main///cmd/restart/abc", r#""status":"failed""#),
+        ],
+    )
+    .await;
+
+    // The late response of the restart actor is ignored
+    restart_box
+        .send(restart_command.with_status(CommandStatus::Successful))
+        .await?;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), mqtt_box.recv())
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn queue_software_updates_beyond_concurrency_limit() -> Result<(), DynError> {
+    // The built-in software_update operation is limited to one command at a time
+    let (mut software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate two software update requests
+    for cmd_id in ["first", "second"] {
+        mqtt_box
+            .send(MqttMessage::new(
+                &Topic::new_unchecked(&format!("te/device/main///cmd/software_update/{cmd_id}")),
+                r#"{"status":"init","updateList":[]}"#,
+            ))
+            .await?;
+    }
+
+    // Only the first one is executed, the second one being queued
+    let first_command = match software_box.recv().await {
+        Some(SoftwareCommand::SoftwareUpdateCommand(command)) => command,
+        _ => panic!("Expect a SoftwareUpdateCommand"),
+    };
+    assert_eq!(first_command.cmd_id, "first");
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_update/first",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/software_update/second",
+                r#""status":"queued""#,
+            ),
+        ],
+    )
+    .await;
+
+    // The second one is started on completion of the first one
+    let response = first_command.with_status(CommandStatus::Successful);
+    software_box.send(response.into()).await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_update/first",
+                r#""status":"successful""#,
+            ),
+            (
+                "te/device/main///cmd/software_update/second",
+                r#""status":"init""#,
+            ),
+        ],
+    )
+    .await;
+    match software_box.recv().await {
+        Some(SoftwareCommand::SoftwareUpdateCommand(command)) => {
+            assert_eq!(command.cmd_id, "second")
+        }
+        _ => panic!("Expect a SoftwareUpdateCommand"),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn trigger_sub_operation_of_a_workflow_with_concurrency_limit() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "firmware_update"
+max_concurrent = 1
+
+[init]
+operation = "restart"
+on_exec = "restarting"
+
+[restarting]
+action = "await-operation-completion"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let (_software_box, mut restart_box, mut mqtt_box, _script_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            ("te/device/main///cmd/firmware_update", "{}"),
+            ("te/device/main///cmd/restart", "{}"),
+            ("te/device/main///cmd/software_list", "{}"),
+            ("te/device/main///cmd/software_update", "{}"),
+        ],
+    )
+    .await;
+
+    // Simulate a firmware update request, which triggers a restart sub-operation
+    let topic = Topic::new_unchecked("te/device/main///cmd/firmware_update/123");
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{"status":"init"}"#))
+        .await?;
+    let sub_topic = "te/device/main///cmd/restart/sub:firmware_update:123";
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/firmware_update/123",
+                r#""status":"restarting""#,
+            ),
+            (sub_topic, r#""status":"init""#),
+        ],
+    )
+    .await;
+
+    // The MQTT echo of the sub-operation request triggers its execution
+    let sub_topic = Topic::new_unchecked(sub_topic);
+    mqtt_box
+        .send(MqttMessage::new(&sub_topic, r#"{"status":"init"}"#))
+        .await?;
+    let restart_command = restart_box.recv().await.expect("RestartCommand");
+    assert_eq!(restart_command.cmd_id, "sub:firmware_update:123");
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(sub_topic.name.as_str(), r#""status":"scheduled""#)],
+    )
+    .await;
+
+    // On completion of the sub-operation, the invoking command is resumed
+    mqtt_box
+        .send(MqttMessage::new(&sub_topic, r#"{"status":"successful"}"#))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/firmware_update/123",
+            r#""status":"successful""#,
+        )],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn defer_scheduled_command_till_its_maintenance_window() -> Result<(), DynError> {
+    let (_software_box, mut restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate a restart request to be executed in 2 seconds
+    let window_start = OffsetDateTime::now_utc() + Duration::from_secs(2);
+    let scheduled_at = window_start.format(&Rfc3339)?;
+    let topic = Topic::new_unchecked("te/device/main///cmd/restart/later");
+    mqtt_box
+        .send(MqttMessage::new(
+            &topic,
+            format!(r#"{{"status":"init","scheduled_at":"{scheduled_at}"}}"#),
+        ))
+        .await?;
+
+    // The command is scheduled, but not executed till the maintenance window opens
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/restart/later",
+            r#""status":"scheduled""#,
+        )],
+    )
+    .await;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), restart_box.recv())
+            .await
+            .is_err()
+    );
+
+    let restart_command = restart_box.recv().await.expect("RestartCommand");
+    assert_eq!(restart_command.cmd_id, "later");
+    assert!(OffsetDateTime::now_utc() >= window_start);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scheduled_command_does_not_block_immediate_ones() -> Result<(), DynError> {
+    let (_software_box, mut restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // Simulate a restart request to be executed tomorrow
+    let scheduled_at = (OffsetDateTime::now_utc() + Duration::from_secs(86400)).format(&Rfc3339)?;
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/restart/later"),
+            format!(r#"{{"status":"init","scheduled_at":"{scheduled_at}"}}"#),
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/restart/later",
+            r#""status":"scheduled""#,
+        )],
+    )
+    .await;
+
+    // A restart request to be executed now is not queued behind the scheduled one
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/restart/now"),
+            r#"{"status":"init"}"#,
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/restart/now",
+            r#""status":"scheduled""#,
+        )],
+    )
+    .await;
+    let restart_command = restart_box.recv().await.expect("RestartCommand");
+    assert_eq!(restart_command.cmd_id, "now");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fail_command_whose_maintenance_window_is_closed() -> Result<(), DynError> {
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter("device/main//").await?;
+
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    let topic = Topic::new_unchecked("te/device/main///cmd/restart/missed");
+    mqtt_box
+        .send(MqttMessage::new(
+            &topic,
+            r#"{"status":"init","scheduled_at":"2020-01-01T02:00:00Z","expires_at":"2020-01-01T04:00:00Z"}"#,
+        ))
+        .await?;
+
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/restart/missed",
+                r#""status":"scheduled""#,
+            ),
+            (
+                "te/device/main///cmd/restart/missed",
+                r#""reason":"The maintenance window closed at 2020-01-01T04:00:00Z""#,
+            ),
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_command_awaiting_a_retry() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "firmware_update"
+
+[init]
+action = "proceed"
+on_success = "download"
+
+[download]
+script = "/usr/bin/false"
+retries = 3
+backoff_second = 60
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let (_software_box, _restart_box, mut mqtt_box, mut script_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            ("te/device/main///cmd/firmware_update", "{}"),
+            ("te/device/main///cmd/restart", "{}"),
+            ("te/device/main///cmd/software_list", "{}"),
+            ("te/device/main///cmd/software_update", "{}"),
+        ],
+    )
+    .await;
+
+    // Simulate a firmware update request, whose first download attempt fails
+    let topic = Topic::new_unchecked("te/device/main///cmd/firmware_update/retried");
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{"status":"init"}"#))
+        .await?;
+    script_box.recv().await.expect("Execute");
+    script_box
+        .send(Ok(Output {
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: vec![],
+        }))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/firmware_update/retried",
+                r#""status":"download""#,
+            ),
+            (
+                "te/device/main///cmd/firmware_update/retried",
+                r#""attempt":2"#,
+            ),
+        ],
+    )
+    .await;
+
+    // The cancel request is processed without waiting for the retry delay
+    let cancel_request = GenericCommandState::cancel_request(topic, Some("not now".to_string()));
+    mqtt_box.send(cancel_request).await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/firmware_update/retried",
+            r#""status":"failed""#,
+        )],
+    )
+    .await;
+
+    // Nor is the script retried once the command cancelled
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), script_box.recv())
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn retry_failed_script_after_backoff() -> Result<(), DynError> {
+    let workflow: OperationWorkflow = toml::from_str(
+        r#"
+operation = "firmware_update"
+
+[init]
+action = "proceed"
+on_success = "download"
+
+[download]
+script = "/usr/bin/false"
+retries = 1
+backoff_second = 1
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let (_software_box, _restart_box, mut mqtt_box, mut script_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows).await?;
+
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            ("te/device/main///cmd/firmware_update", "{}"),
+            ("te/device/main///cmd/restart", "{}"),
+            ("te/device/main///cmd/software_list", "{}"),
+            ("te/device/main///cmd/software_update", "{}"),
+        ],
+    )
+    .await;
+
+    let topic = Topic::new_unchecked("te/device/main///cmd/firmware_update/retried");
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{"status":"init"}"#))
+        .await?;
+
+    // The first attempt fails
+    script_box.recv().await.expect("Execute");
+    let failed_attempt = Instant::now();
+    script_box
+        .send(Ok(Output {
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: vec![],
+        }))
+        .await?;
+
+    // The script is retried, once the backoff delay elapsed
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/firmware_update/retried",
+                r#""status":"download""#,
+            ),
+            (
+                "te/device/main///cmd/firmware_update/retried",
+                r#""attempt":2"#,
+            ),
+        ],
+    )
+    .await;
+    script_box.recv().await.expect("Execute");
+    assert!(failed_attempt.elapsed() >= Duration::from_secs(1));
+
+    Ok(())
+}
+
 async fn spawn_mqtt_operation_converter(
     device_topic_id: &str,
 ) -> Result<
@@ -246,6 +731,27 @@ async fn spawn_mqtt_operation_converter(
         TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
     ),
     DynError,
+> {
+    let (software_box, restart_box, mqtt_box, _script_box) =
+        spawn_mqtt_operation_converter_with_workflows(
+            device_topic_id,
+            WorkflowSupervisor::default(),
+        )
+        .await?;
+    Ok((software_box, restart_box, mqtt_box))
+}
+
+async fn spawn_mqtt_operation_converter_with_workflows(
+    device_topic_id: &str,
+    workflows: WorkflowSupervisor,
+) -> Result<
+    (
+        TimedMessageBox<SimpleMessageBox<SoftwareCommand, SoftwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<RestartCommand, RestartCommand>>,
+        TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+        TimedMessageBox<SimpleMessageBox<Execute, std::io::Result<Output>>>,
+    ),
+    DynError,
 > {
     let mut software_builder: SimpleMessageBoxBuilder<SoftwareCommand, SoftwareCommand> =
         SimpleMessageBoxBuilder::new("Software", 5);
@@ -255,8 +761,7 @@ async fn spawn_mqtt_operation_converter(
         SimpleMessageBoxBuilder::new("MQTT", 5);
     let mut script_builder: SimpleMessageBoxBuilder<Execute, std::io::Result<Output>> =
         SimpleMessageBoxBuilder::new("Script", 5);
-
-    let workflows = WorkflowSupervisor::default();
+    let mut timer_builder = TimerActor::builder();
 
     let tmp_dir = tempfile::TempDir::new().unwrap();
     let tmp_path = Utf8Path::from_path(tmp_dir.path()).unwrap();
@@ -274,16 +779,20 @@ async fn spawn_mqtt_operation_converter(
         &mut restart_builder,
         &mut mqtt_builder,
         &mut script_builder,
+        &mut timer_builder,
     );
 
     let software_box = software_builder.build().with_timeout(TEST_TIMEOUT_MS);
     let restart_box = restart_builder.build().with_timeout(TEST_TIMEOUT_MS);
     let mqtt_message_box = mqtt_builder.build().with_timeout(TEST_TIMEOUT_MS);
+    let script_box = script_builder.build().with_timeout(TEST_TIMEOUT_MS);
 
     let converter_actor = converter_actor_builder.build();
     tokio::spawn(async move { converter_actor.run().await });
+    let timer_actor = timer_builder.build();
+    tokio::spawn(async move { timer_actor.run().await });
 
-    Ok((software_box, restart_box, mqtt_message_box))
+    Ok((software_box, restart_box, mqtt_message_box, script_box))
 }
 
 async fn skip_capability_messages(mqtt: &mut impl MessageReceiver<MqttMessage>, device: &str) {
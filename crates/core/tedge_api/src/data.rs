@@ -1,5 +1,6 @@
 //! The in-memory data model representing ThinEdge JSON.
 
+use crate::measurement::MeasurementValue;
 use time::OffsetDateTime;
 
 /// In-memory representation of parsed ThinEdge JSON.
@@ -28,7 +29,7 @@ pub enum ThinEdgeValue {
 #[derive(Debug, PartialEq)]
 pub struct SingleValueMeasurement {
     pub name: String,
-    pub value: f64,
+    pub value: MeasurementValue,
 }
 
 #[derive(Debug, PartialEq)]
@@ -42,6 +43,15 @@ where
     T: Into<String>,
 {
     fn from((name, value): (T, f64)) -> Self {
+        (name, MeasurementValue::Float(value)).into()
+    }
+}
+
+impl<T> From<(T, MeasurementValue)> for SingleValueMeasurement
+where
+    T: Into<String>,
+{
+    fn from((name, value): (T, MeasurementValue)) -> Self {
         SingleValueMeasurement {
             name: name.into(),
             value,
@@ -58,6 +68,15 @@ where
     }
 }
 
+impl<T> From<(T, MeasurementValue)> for ThinEdgeValue
+where
+    T: Into<String>,
+{
+    fn from((name, value): (T, MeasurementValue)) -> Self {
+        ThinEdgeValue::Single((name, value).into())
+    }
+}
+
 impl<T> From<(T, Vec<SingleValueMeasurement>)> for ThinEdgeValue
 where
     T: Into<String>,
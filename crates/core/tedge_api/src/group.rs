@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use time::OffsetDateTime;
 
+use crate::measurement::MeasurementValue;
 use crate::measurement::MeasurementVisitor;
 
 #[derive(Debug)]
@@ -32,11 +33,11 @@ impl MeasurementGroup {
     ) -> Option<f64> {
         match group_key {
             Some(group_key) => match self.values.get(group_key) {
-                Some(Measurement::Multi(map)) => map.get(measurement_key).cloned(),
+                Some(Measurement::Multi(map)) => map.get(measurement_key)?.as_f64(),
                 _ => None,
             },
             None => match self.values.get(measurement_key) {
-                Some(Measurement::Single(val)) => Some(*val),
+                Some(Measurement::Single(val)) => val.as_f64(),
                 _ => None,
             },
         }
@@ -54,12 +55,12 @@ impl MeasurementGroup {
         for (key, value) in self.values.iter() {
             match value {
                 Measurement::Single(sv) => {
-                    visitor.visit_measurement(key, *sv)?;
+                    visit_value(visitor, key, sv)?;
                 }
                 Measurement::Multi(m) => {
                     visitor.visit_start_group(key)?;
                     for (key, value) in m.iter() {
-                        visitor.visit_measurement(key, *value)?;
+                        visit_value(visitor, key, value)?;
                     }
                     visitor.visit_end_group()?;
                 }
@@ -69,6 +70,17 @@ impl MeasurementGroup {
     }
 }
 
+fn visit_value<V, E>(visitor: &mut V, key: &str, value: &MeasurementValue) -> Result<(), E>
+where
+    V: MeasurementVisitor<Error = E>,
+    E: std::error::Error + std::fmt::Debug,
+{
+    match value {
+        MeasurementValue::Float(value) => visitor.visit_measurement(key, *value),
+        value => visitor.visit_typed_measurement(key, value),
+    }
+}
+
 #[derive(Debug)]
 pub struct MeasurementGrouper {
     measurement_group: MeasurementGroup,
@@ -86,8 +98,8 @@ struct GroupState {
 
 #[derive(Debug)]
 pub enum Measurement {
-    Single(f64),
-    Multi(HashMap<String, f64>),
+    Single(MeasurementValue),
+    Multi(HashMap<String, MeasurementValue>),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -163,7 +175,16 @@ impl MeasurementVisitor for MeasurementGrouper {
     }
 
     fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error> {
+        self.visit_typed_measurement(name, &MeasurementValue::Float(value))
+    }
+
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
         let key = name.to_owned();
+        let value = value.clone();
 
         match self.group_state.in_group {
             false => {
@@ -215,6 +236,7 @@ mod tests {
 
             fn visit_timestamp(&mut self, value: OffsetDateTime) -> Result<(), TestError>;
             fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), TestError>;
+            fn visit_typed_measurement(&mut self, name: &str, value: &MeasurementValue) -> Result<(), TestError>;
             fn visit_start_group(&mut self, group: &str) -> Result<(), TestError>;
             fn visit_end_group(&mut self) -> Result<(), TestError>;
             fn visit_text_property(&mut self, _name: &str, _value:&str)-> Result<(), TestError>;
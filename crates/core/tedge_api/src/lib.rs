@@ -12,6 +12,7 @@ pub mod messages;
 pub mod mqtt_topics;
 pub mod parser;
 pub mod path;
+pub mod payload_format;
 pub mod pending_entity_store;
 mod ring_buffer;
 pub mod serialize;
@@ -80,6 +80,23 @@ pub trait MeasurementVisitor {
     /// Add a new measurement, attached to the current group if any.
     fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error>;
 
+    /// Add a new measurement which value cannot be represented as a float64 without loss:
+    /// an integer beyond 2^53, a boolean or an array of values.
+    ///
+    /// Each visitor defines how these values are encoded.
+    /// Defaults to `visit_measurement` with the float64 approximation of an integer,
+    /// booleans and arrays being ignored by the visitors that don't support them.
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
+        match value.as_f64() {
+            Some(value) => self.visit_measurement(name, value),
+            None => Ok(()),
+        }
+    }
+
     /// Add a text property, attached to the current group if any.
     fn visit_text_property(&mut self, _name: &str, _value: &str) -> Result<(), Self::Error>;
 
@@ -103,3 +120,59 @@ pub trait MeasurementVisitor {
         Ok(())
     }
 }
+
+/// A measurement value.
+///
+/// Most measurement values are floats, but thin-edge JSON also accepts
+/// integers that cannot be represented exactly as a float64, booleans,
+/// and arrays of values (e.g. a vibration spectrum).
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeasurementValue {
+    Float(f64),
+    Int(i64),
+    UInt(u64),
+    Bool(bool),
+    Array(Vec<MeasurementValue>),
+}
+
+impl MeasurementValue {
+    /// The float64 approximation of a number, `None` for a boolean or an array
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MeasurementValue::Float(value) => Some(*value),
+            MeasurementValue::Int(value) => Some(*value as f64),
+            MeasurementValue::UInt(value) => Some(*value as f64),
+            MeasurementValue::Bool(_) | MeasurementValue::Array(_) => None,
+        }
+    }
+}
+
+impl From<f64> for MeasurementValue {
+    fn from(value: f64) -> Self {
+        MeasurementValue::Float(value)
+    }
+}
+
+impl From<i64> for MeasurementValue {
+    fn from(value: i64) -> Self {
+        MeasurementValue::Int(value)
+    }
+}
+
+impl From<u64> for MeasurementValue {
+    fn from(value: u64) -> Self {
+        MeasurementValue::UInt(value)
+    }
+}
+
+impl From<bool> for MeasurementValue {
+    fn from(value: bool) -> Self {
+        MeasurementValue::Bool(value)
+    }
+}
+
+impl<T: Into<MeasurementValue>> From<Vec<T>> for MeasurementValue {
+    fn from(values: Vec<T>) -> Self {
+        MeasurementValue::Array(values.into_iter().map(Into::into).collect())
+    }
+}
@@ -33,9 +33,15 @@ impl MessageLogReader {
     where
         P: AsRef<Path>,
     {
-        let file = OpenOptions::new()
-            .read(true)
-            .open(log_dir.as_ref().join(LOG_FILE_NAME))?;
+        MessageLogReader::open(log_dir.as_ref().join(LOG_FILE_NAME))
+    }
+
+    /// Open a log file with a custom name
+    pub fn open<P>(log_file: P) -> Result<MessageLogReader, std::io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new().read(true).open(log_file)?;
         let mut reader = BufReader::new(file);
 
         let mut version_info = String::new();
@@ -69,13 +75,21 @@ pub struct MessageLogWriter {
 
 impl MessageLogWriter {
     pub fn new<P>(log_dir: P) -> Result<MessageLogWriter, std::io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        MessageLogWriter::open(log_dir.as_ref().join(LOG_FILE_NAME))
+    }
+
+    /// Open a log file with a custom name, creating it if missing
+    pub fn open<P>(log_file: P) -> Result<MessageLogWriter, std::io::Error>
     where
         P: AsRef<Path>,
     {
         let file = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(log_dir.as_ref().join(LOG_FILE_NAME))?;
+            .open(log_file)?;
 
         // If the file is empty append the version information as a header
         let metadata = file.metadata()?;
@@ -167,6 +167,7 @@ impl MqttSchema {
             ChannelFilter::AlarmMetadata => "/a/+/meta".to_string(),
             ChannelFilter::AnyCommand => "/cmd/+/+".to_string(),
             ChannelFilter::Command(operation) => format!("/cmd/{operation}/+"),
+            ChannelFilter::AnyCommandCancel => "/cmd/+/+/cancel".to_string(),
             ChannelFilter::AnyCommandMetadata => "/cmd/+".to_string(),
             ChannelFilter::CommandMetadata(operation) => format!("/cmd/{operation}"),
         };
@@ -506,6 +507,10 @@ pub enum Channel {
     CommandMetadata {
         operation: OperationType,
     },
+    CommandCancel {
+        operation: OperationType,
+        cmd_id: String,
+    },
     Health,
 }
 
@@ -546,6 +551,10 @@ impl FromStr for Channel {
                 operation: operation.parse().unwrap(), // Infallible
                 cmd_id: cmd_id.to_string(),
             }),
+            ["cmd", operation, cmd_id, "cancel"] => Ok(Channel::CommandCancel {
+                operation: operation.parse().unwrap(), // Infallible
+                cmd_id: cmd_id.to_string(),
+            }),
             ["status", "health"] => Ok(Channel::Health),
 
             _ => Err(ChannelError::InvalidCategory(channel.to_string())),
@@ -572,6 +581,9 @@ impl Display for Channel {
 
             Channel::Command { operation, cmd_id } => write!(f, "cmd/{operation}/{cmd_id}"),
             Channel::CommandMetadata { operation } => write!(f, "cmd/{operation}"),
+            Channel::CommandCancel { operation, cmd_id } => {
+                write!(f, "cmd/{operation}/{cmd_id}/cancel")
+            }
             Channel::Health => write!(f, "status/health"),
         }
     }
@@ -687,6 +699,7 @@ pub enum ChannelFilter {
     AlarmMetadata,
     AnyCommandMetadata,
     CommandMetadata(OperationType),
+    AnyCommandCancel,
 }
 
 pub struct IdGenerator {
@@ -917,6 +930,16 @@ mod tests {
             ),
             mqtt_channel::Topic::new_unchecked("te/device/main///cmd/log_upload")
         );
+        assert_eq!(
+            mqtt_schema.topic_for(
+                &device,
+                &Channel::CommandCancel {
+                    operation: OperationType::Restart,
+                    cmd_id: "123".to_string()
+                }
+            ),
+            mqtt_channel::Topic::new_unchecked("te/device/main///cmd/restart/123/cancel")
+        );
         assert_eq!(
             mqtt_schema.topic_for(&device, &Channel::Health),
             mqtt_channel::Topic::new_unchecked("te/device/main///status/health")
@@ -2,13 +2,17 @@
 //!
 //! [^1]: It only allocates in presence of escaped strings as keys.
 //!
+use crate::measurement::MeasurementValue;
 use crate::measurement::MeasurementVisitor;
+use crate::payload_format::PayloadFormat;
+use crate::payload_format::PayloadFormatError;
 use serde::de::DeserializeSeed;
 use serde::de::MapAccess;
+use serde::de::SeqAccess;
 use serde::de::{self};
+use serde::Deserialize;
 use serde::Deserializer;
 use std::borrow::Cow;
-use std::convert::TryFrom;
 use std::fmt;
 use tedge_utils::timestamp::IsoOrUnix;
 
@@ -27,6 +31,46 @@ pub fn parse_str<T: MeasurementVisitor>(
     Ok(())
 }
 
+/// Parses a payload encoded in the given `format`, yielding the parsed measurements to the `visitor`.
+///
+/// Binary payloads must have the same structure as ThinEdge JSON measurements.
+pub fn parse_payload<T: MeasurementVisitor>(
+    format: PayloadFormat,
+    input: &[u8],
+    visitor: &mut T,
+) -> Result<(), PayloadFormatError> {
+    match format {
+        PayloadFormat::Json => {
+            let input = std::str::from_utf8(input)
+                .map_err(|err| PayloadFormatError::InvalidMeasurement(err.to_string()))?;
+            Ok(parse_str(input, visitor)?)
+        }
+        format => {
+            let value = format.decode(input)?;
+            let parser = ThinEdgeJsonParser { visitor };
+            value
+                .deserialize_map(parser)
+                .map_err(|err| PayloadFormatError::InvalidMeasurement(err.to_string()))
+        }
+    }
+}
+
+/// Parses a CBOR encoded measurement, yielding the parsed measurements to the `visitor`.
+pub fn parse_cbor<T: MeasurementVisitor>(
+    input: &[u8],
+    visitor: &mut T,
+) -> Result<(), PayloadFormatError> {
+    parse_payload(PayloadFormat::Cbor, input, visitor)
+}
+
+/// Parses a MessagePack encoded measurement, yielding the parsed measurements to the `visitor`.
+pub fn parse_msgpack<T: MeasurementVisitor>(
+    input: &[u8],
+    visitor: &mut T,
+) -> Result<(), PayloadFormatError> {
+    parse_payload(PayloadFormat::MessagePack, input, visitor)
+}
+
 /// The error returned by `parse_str`.
 #[derive(Debug, thiserror::Error)]
 #[error("Invalid JSON: {error}: `{input_excerpt}`")]
@@ -37,13 +81,19 @@ pub struct ThinEdgeJsonParserError {
     input_excerpt: String,
 }
 
+/// The largest integer such that all the integers with a smaller magnitude
+/// can be represented exactly as float64 values: 2^53.
+const MAX_EXACT_FLOAT_INTEGER: u64 = 1 << 53;
+
 /// Parses top-level ThinEdge JSON:
 ///
 /// ```grammar
 /// {
 ///     time?: string,
-///     [key: string]: number | {[key: string]: number},
+///     [key: string]: value | {[key: string]: value},
 /// }
+///
+/// value: number | boolean | [value]
 /// ```
 ///
 struct ThinEdgeJsonParser<'vis, T>
@@ -53,10 +103,10 @@ where
     visitor: &'vis mut T,
 }
 
-/// Parses a single value (number) or multi-value measurement:
+/// Parses a single value or multi-value measurement:
 ///
 /// ```grammar
-/// number | {[key: string]: number}
+/// value | {[key: string]: value}
 /// ```
 ///
 struct ThinEdgeValueParser<'key, 'vis, T> {
@@ -187,27 +237,18 @@ where
     where
         E: serde::de::Error,
     {
-        if value != 0.0 && !value.is_normal() {
-            return Err(de::Error::custom(invalid_json_number(&self.key)));
-        }
-
-        self.visitor
-            .visit_measurement(self.key.as_ref(), value)
-            .map_err(de::Error::custom)?;
-
-        Ok(())
+        let value = float_value(&self.key, value)?;
+        self.visit_value(value)
     }
 
     /// Parses a single-value measurement. See `visit_f64`.
+    ///
+    /// Integers that cannot be represented exactly as float64 values are forwarded as such.
     fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        let value = i32::try_from(value)
-            .map_err(|_| de::Error::custom(invalid_json_number(&self.key)))?
-            .into();
-
-        self.visit_f64(value)
+        self.visit_value(int_value(value))
     }
 
     /// Parses a single-value measurement. See `visit_f64`.
@@ -215,11 +256,27 @@ where
     where
         E: serde::de::Error,
     {
-        let value = u32::try_from(value)
-            .map_err(|_| de::Error::custom(invalid_json_number(&self.key)))?
-            .into();
+        self.visit_value(uint_value(value))
+    }
 
-        self.visit_f64(value)
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.visit_value(MeasurementValue::Bool(value))
+    }
+
+    /// Parses an array of values, e.g. a vibration spectrum.
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::new();
+        while let Some(ArrayItem(value)) = seq.next_element()? {
+            values.push(value);
+        }
+
+        self.visit_value(MeasurementValue::Array(values))
     }
 
     fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
@@ -234,6 +291,115 @@ where
     }
 }
 
+impl<'key, 'vis, T> ThinEdgeValueParser<'key, 'vis, T>
+where
+    T: MeasurementVisitor,
+{
+    fn visit_value<E>(self, value: MeasurementValue) -> Result<(), E>
+    where
+        E: serde::de::Error,
+    {
+        match value {
+            MeasurementValue::Float(value) => self
+                .visitor
+                .visit_measurement(self.key.as_ref(), value)
+                .map_err(de::Error::custom),
+            value => self
+                .visitor
+                .visit_typed_measurement(self.key.as_ref(), &value)
+                .map_err(de::Error::custom),
+        }
+    }
+}
+
+/// An item of an array of values: a number, a boolean or a nested array
+struct ArrayItem(MeasurementValue);
+
+impl<'de> Deserialize<'de> for ArrayItem {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ArrayItemVisitor)
+    }
+}
+
+struct ArrayItemVisitor;
+
+impl<'de> de::Visitor<'de> for ArrayItemVisitor {
+    type Value = ArrayItem;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number, a boolean or an array")
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        float_value("array item", value).map(ArrayItem)
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(ArrayItem(int_value(value)))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(ArrayItem(uint_value(value)))
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(ArrayItem(MeasurementValue::Bool(value)))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::new();
+        while let Some(ArrayItem(value)) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(ArrayItem(MeasurementValue::Array(values)))
+    }
+}
+
+fn float_value<E>(key: &str, value: f64) -> Result<MeasurementValue, E>
+where
+    E: serde::de::Error,
+{
+    if value != 0.0 && !value.is_normal() {
+        return Err(de::Error::custom(invalid_json_number(key)));
+    }
+    Ok(MeasurementValue::Float(value))
+}
+
+/// Integers are forwarded as floats unless they cannot be represented exactly as float64 values
+fn int_value(value: i64) -> MeasurementValue {
+    if value.unsigned_abs() <= MAX_EXACT_FLOAT_INTEGER {
+        MeasurementValue::Float(value as f64)
+    } else {
+        MeasurementValue::Int(value)
+    }
+}
+
+fn uint_value(value: u64) -> MeasurementValue {
+    if value <= MAX_EXACT_FLOAT_INTEGER {
+        MeasurementValue::Float(value as f64)
+    } else {
+        MeasurementValue::UInt(value)
+    }
+}
+
 /// The `DeserializeSeed` trait enables us to inject state required for deserialization. In our case
 /// the state is the `visitor` that we want to use for callbacks and the `key` that we are currently
 /// parsing.
@@ -257,7 +423,7 @@ where
 
 fn invalid_json_number(key: &str) -> String {
     format!(
-        "Number out-of-range: the {:?} value is too large to be represented as a float64.",
+        "Number out-of-range: the {:?} value cannot be represented as a float64.",
         key
     )
 }
@@ -331,6 +497,127 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn it_deserializes_typed_values() -> anyhow::Result<()> {
+        use crate::builder::ThinEdgeJsonBuilder;
+        use crate::measurement::MeasurementValue;
+        let input = r#"{
+        "counter": 18446744073709551615,
+        "offset": -9007199254740993,
+        "exact": 9007199254740992,
+        "running": true,
+        "vibration": {
+            "spectrum": [0.5, 1, [2, false]]
+        }
+    }"#;
+
+        let mut builder = ThinEdgeJsonBuilder::default();
+
+        parse_str(input, &mut builder)?;
+
+        let output = builder.done()?;
+
+        assert_eq!(
+            output.values,
+            vec![
+                ("counter", MeasurementValue::UInt(u64::MAX)).into(),
+                ("offset", MeasurementValue::Int(-9007199254740993)).into(),
+                ("exact", 9007199254740992.0).into(),
+                ("running", MeasurementValue::Bool(true)).into(),
+                (
+                    "vibration",
+                    vec![(
+                        "spectrum",
+                        MeasurementValue::Array(vec![
+                            0.5.into(),
+                            1.0.into(),
+                            MeasurementValue::Array(vec![2.0.into(), false.into()]),
+                        ])
+                    )
+                        .into()]
+                )
+                    .into(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_non_numeric_array_items() {
+        use crate::builder::ThinEdgeJsonBuilder;
+
+        let input = r#"{"spectrum": [1.0, "2.0"]}"#;
+        let mut builder = ThinEdgeJsonBuilder::default();
+
+        assert!(parse_str(input, &mut builder).is_err());
+    }
+
+    #[test]
+    fn it_deserializes_binary_measurements() -> anyhow::Result<()> {
+        use crate::builder::ThinEdgeJsonBuilder;
+        use crate::measurement::MeasurementValue;
+        use crate::parser::parse_cbor;
+        use crate::parser::parse_msgpack;
+
+        let measurement = serde_json::json!({
+            "time": 1701949168,
+            "temperature": 24,
+            "running": true,
+            "coordinate": {"x": 1.5, "spectrum": [0.5, 1.0]}
+        });
+        let mut cbor = vec![];
+        ciborium::ser::into_writer(&measurement, &mut cbor)?;
+        let msgpack = rmp_serde::to_vec(&measurement)?;
+
+        for (format, output) in [
+            ("cbor", {
+                let mut builder = ThinEdgeJsonBuilder::default();
+                parse_cbor(&cbor, &mut builder)?;
+                builder.done()?
+            }),
+            ("msgpack", {
+                let mut builder = ThinEdgeJsonBuilder::default();
+                parse_msgpack(&msgpack, &mut builder)?;
+                builder.done()?
+            }),
+        ] {
+            assert_eq!(
+                output.timestamp,
+                Some(datetime!(2023-12-07 11:39:28 UTC)),
+                "{format}"
+            );
+            assert_eq!(
+                output.values,
+                vec![
+                    (
+                        "coordinate",
+                        vec![
+                            ("spectrum", MeasurementValue::from(vec![0.5, 1.0])).into(),
+                            ("x", 1.5).into(),
+                        ]
+                    )
+                        .into(),
+                    ("running", MeasurementValue::Bool(true)).into(),
+                    ("temperature", 24.0).into(),
+                ],
+                "{format}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_binary_payloads_that_are_not_measurements() {
+        use crate::builder::ThinEdgeJsonBuilder;
+        use crate::parser::parse_cbor;
+
+        let mut cbor = vec![];
+        ciborium::ser::into_writer(&serde_json::json!([1, 2, 3]), &mut cbor).unwrap();
+        let mut builder = ThinEdgeJsonBuilder::default();
+
+        assert!(parse_cbor(&cbor, &mut builder).is_err());
+    }
+
     #[test]
     fn it_shows_input_excerpt_on_error() {
         use crate::builder::ThinEdgeJsonBuilder;
@@ -0,0 +1,234 @@
+//! Binary encodings of thin-edge JSON telemetry payloads
+//!
+//! Constrained devices can publish measurements, events and alarms encoded as CBOR or MessagePack
+//! rather than JSON. The encoding is given by a suffix appended to the type segment of the topic:
+//!
+//! - `te/device/sensor01///m/environment.cbor` for a CBOR-encoded `environment` measurement
+//! - `te/device/sensor01///e/door_open.msgpack` for a MessagePack-encoded `door_open` event
+//!
+//! The binary payloads follow the same structure as their thin-edge JSON counterparts,
+//! and are translated into thin-edge JSON messages on the topics without the suffix
+//! by [decode_message], before any further processing.
+use crate::mqtt_topics::Channel;
+use crate::mqtt_topics::MqttSchema;
+use mqtt_channel::Message;
+use serde_json::Value;
+
+/// The encoding of a telemetry payload
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PayloadFormat {
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PayloadFormatError {
+    #[error("Invalid JSON payload: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Invalid CBOR payload: {0}")]
+    InvalidCbor(String),
+
+    #[error("Invalid MessagePack payload: {0}")]
+    InvalidMessagePack(#[from] rmp_serde::decode::Error),
+
+    #[error(transparent)]
+    InvalidThinEdgeJson(#[from] crate::parser::ThinEdgeJsonParserError),
+
+    #[error("Invalid measurement: {0}")]
+    InvalidMeasurement(String),
+}
+
+impl PayloadFormat {
+    const CBOR_SUFFIX: &'static str = ".cbor";
+    const MESSAGE_PACK_SUFFIX: &'static str = ".msgpack";
+
+    /// Split a measurement, event or alarm type into the actual type and the payload format
+    ///
+    /// A type with no known suffix is a JSON one.
+    pub fn split_type(telemetry_type: &str) -> (&str, PayloadFormat) {
+        if let Some(actual_type) = telemetry_type.strip_suffix(Self::CBOR_SUFFIX) {
+            (actual_type, PayloadFormat::Cbor)
+        } else if let Some(actual_type) = telemetry_type.strip_suffix(Self::MESSAGE_PACK_SUFFIX) {
+            (actual_type, PayloadFormat::MessagePack)
+        } else {
+            (telemetry_type, PayloadFormat::Json)
+        }
+    }
+
+    /// The payload of a message encoded in this format
+    ///
+    /// A trailing null char is ignored in a JSON payload, but is meaningful in a binary payload.
+    pub fn payload_of<'a>(&self, message: &'a Message) -> &'a [u8] {
+        match self {
+            PayloadFormat::Json => message.payload_bytes(),
+            PayloadFormat::Cbor | PayloadFormat::MessagePack => message.payload(),
+        }
+    }
+
+    /// Decode a payload into its JSON representation
+    pub fn decode(&self, payload: &[u8]) -> Result<Value, PayloadFormatError> {
+        match self {
+            PayloadFormat::Json => Ok(serde_json::from_slice(payload)?),
+            PayloadFormat::Cbor => ciborium::de::from_reader(payload)
+                .map_err(|err| PayloadFormatError::InvalidCbor(err.to_string())),
+            PayloadFormat::MessagePack => Ok(rmp_serde::from_slice(payload)?),
+        }
+    }
+}
+
+/// Translate a binary-encoded measurement, event or alarm into its thin-edge JSON counterpart
+///
+/// Return `None` if the message is not a binary-encoded telemetry message,
+/// i.e. when it has to be processed as is.
+///
+/// The decoded message is published on the topic without the format suffix,
+/// keeping the QoS and retain flag of the original message.
+/// An empty payload, as used to clear an alarm, is kept empty.
+pub fn decode_message(
+    mqtt_schema: &MqttSchema,
+    message: &Message,
+) -> Option<Result<Message, PayloadFormatError>> {
+    let (entity, channel) = mqtt_schema.entity_channel_of(&message.topic).ok()?;
+    let (channel, format) = match channel {
+        Channel::Measurement { measurement_type } => {
+            let (measurement_type, format) = PayloadFormat::split_type(&measurement_type);
+            let measurement_type = measurement_type.to_string();
+            (Channel::Measurement { measurement_type }, format)
+        }
+        Channel::Event { event_type } => {
+            let (event_type, format) = PayloadFormat::split_type(&event_type);
+            let event_type = event_type.to_string();
+            (Channel::Event { event_type }, format)
+        }
+        Channel::Alarm { alarm_type } => {
+            let (alarm_type, format) = PayloadFormat::split_type(&alarm_type);
+            let alarm_type = alarm_type.to_string();
+            (Channel::Alarm { alarm_type }, format)
+        }
+        _ => return None,
+    };
+    if format == PayloadFormat::Json {
+        return None;
+    }
+
+    let topic = mqtt_schema.topic_for(&entity, &channel);
+    let payload = format.payload_of(message);
+    let payload = if payload.is_empty() {
+        String::new()
+    } else {
+        match format.decode(payload) {
+            Ok(value) => value.to_string(),
+            Err(err) => return Some(Err(err)),
+        }
+    };
+
+    let mut decoded = Message::new(&topic, payload).with_qos(message.qos);
+    decoded.retain = message.retain;
+    Some(Ok(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    fn cbor(value: &Value) -> Vec<u8> {
+        let mut bytes = vec![];
+        ciborium::ser::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn split_types() {
+        assert_eq!(
+            PayloadFormat::split_type("environment.cbor"),
+            ("environment", PayloadFormat::Cbor)
+        );
+        assert_eq!(
+            PayloadFormat::split_type("door_open.msgpack"),
+            ("door_open", PayloadFormat::MessagePack)
+        );
+        assert_eq!(
+            PayloadFormat::split_type(".cbor"),
+            ("", PayloadFormat::Cbor)
+        );
+        assert_eq!(
+            PayloadFormat::split_type("environment"),
+            ("environment", PayloadFormat::Json)
+        );
+    }
+
+    #[test]
+    fn decode_binary_telemetry_messages() {
+        let mqtt_schema = MqttSchema::default();
+        let measurement = json!({"temperature": 21.5, "counter": 0});
+
+        let message = Message::new(
+            &Topic::new_unchecked("te/device/sensor01///m/environment.cbor"),
+            cbor(&measurement),
+        );
+        let decoded = decode_message(&mqtt_schema, &message).unwrap().unwrap();
+        assert_eq!(decoded.topic.name, "te/device/sensor01///m/environment");
+        assert_eq!(
+            serde_json::from_str::<Value>(decoded.payload_str().unwrap()).unwrap(),
+            measurement
+        );
+
+        let event = json!({"text": "Door open", "time": 1701949168});
+        let message = Message::new(
+            &Topic::new_unchecked("te/device/sensor01///e/door.msgpack"),
+            rmp_serde::to_vec(&event).unwrap(),
+        );
+        let decoded = decode_message(&mqtt_schema, &message).unwrap().unwrap();
+        assert_eq!(decoded.topic.name, "te/device/sensor01///e/door");
+        assert_eq!(
+            serde_json::from_str::<Value>(decoded.payload_str().unwrap()).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn alarms_are_cleared_with_an_empty_payload() {
+        let message = Message::new(
+            &Topic::new_unchecked("te/device/sensor01///a/high_temperature.cbor"),
+            "",
+        )
+        .with_retain();
+        let decoded = decode_message(&MqttSchema::default(), &message)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            decoded.topic.name,
+            "te/device/sensor01///a/high_temperature"
+        );
+        assert!(decoded.payload_bytes().is_empty());
+        assert!(decoded.retain);
+    }
+
+    #[test]
+    fn json_and_non_telemetry_messages_are_not_decoded() {
+        let mqtt_schema = MqttSchema::default();
+        for topic in [
+            "te/device/sensor01///m/environment",
+            "te/device/sensor01///cmd/restart/123.cbor",
+            "c8y/s/us",
+        ] {
+            let message = Message::new(&Topic::new_unchecked(topic), "{}");
+            assert!(decode_message(&mqtt_schema, &message).is_none());
+        }
+    }
+
+    #[test]
+    fn invalid_binary_payloads_are_rejected() {
+        let message = Message::new(
+            &Topic::new_unchecked("te/device/sensor01///m/environment.cbor"),
+            vec![0xff, 0x00],
+        );
+        assert!(decode_message(&MqttSchema::default(), &message)
+            .unwrap()
+            .is_err());
+    }
+}
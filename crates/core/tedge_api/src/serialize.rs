@@ -1,3 +1,4 @@
+use crate::measurement::MeasurementValue;
 use crate::measurement::MeasurementVisitor;
 use json_writer::JsonWriter;
 use json_writer::JsonWriterError;
@@ -85,6 +86,23 @@ impl ThinEdgeJsonSerializer {
         self.end()?;
         Ok(self.json.clone().into_string()?)
     }
+
+    fn write_value(&mut self, value: &MeasurementValue) -> Result<(), JsonWriterError> {
+        match value {
+            MeasurementValue::Float(value) => self.json.write_f64(*value),
+            MeasurementValue::Int(value) => self.json.write_i64(*value),
+            MeasurementValue::UInt(value) => self.json.write_u64(*value),
+            MeasurementValue::Bool(value) => self.json.write_bool(*value),
+            MeasurementValue::Array(values) => {
+                self.json.write_open_array();
+                for value in values {
+                    self.write_value(value)?;
+                }
+                self.json.write_close_array();
+                Ok(())
+            }
+        }
+    }
 }
 
 impl Default for ThinEdgeJsonSerializer {
@@ -117,6 +135,16 @@ impl MeasurementVisitor for ThinEdgeJsonSerializer {
         Ok(())
     }
 
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
+        self.json.write_key(name)?;
+        self.write_value(value)?;
+        Ok(())
+    }
+
     fn visit_start_group(&mut self, group: &str) -> Result<(), Self::Error> {
         if self.is_within_group {
             return Err(MeasurementStreamError::UnexpectedStartOfGroup.into());
@@ -209,6 +237,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serialize_typed_values() -> anyhow::Result<()> {
+        let mut serializer = ThinEdgeJsonSerializer::new();
+        serializer.visit_typed_measurement("counter", &u64::MAX.into())?;
+        serializer.visit_typed_measurement("running", &true.into())?;
+        serializer.visit_start_group("vibration")?;
+        serializer.visit_typed_measurement("spectrum", &vec![0.5, 1.5, 0.0].into())?;
+        serializer.visit_end_group()?;
+        let expected_output = r#"{"counter":18446744073709551615,"running":true,"vibration":{"spectrum":[0.5,1.5,0.0]}}"#;
+        let output = serializer.into_string()?;
+        assert_eq!(expected_output, output);
+        Ok(())
+    }
+
     #[test]
     fn serialize_empty_message() -> anyhow::Result<()> {
         let mut serializer = ThinEdgeJsonSerializer::new();
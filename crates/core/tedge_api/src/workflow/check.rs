@@ -0,0 +1,288 @@
+use crate::workflow::OperationAction;
+use crate::workflow::OperationWorkflow;
+use crate::workflow::StateName;
+use crate::workflow::Transition;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// An issue found by the static analysis of a workflow
+///
+/// Such issues don't prevent a workflow to be registered,
+/// but will make the commands fail or be stuck at runtime.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WorkflowIssue {
+    /// A state refers to a next state which is not defined
+    UnknownState { state: StateName, target: StateName },
+
+    /// No terminal state can be reached from a state, and a command in this state will be stuck
+    NoTerminalState { state: StateName },
+
+    /// A `${...}` parameter uses a path that is not supported
+    InvalidParameter { state: StateName, parameter: String },
+
+    /// A `${...}` parameter is not a whole script argument, hence will not be substituted
+    NonSubstitutedParameter { state: StateName, argument: String },
+}
+
+impl Display for WorkflowIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkflowIssue::UnknownState { state, target } => {
+                write!(f, "the {state:?} state refers to an undefined state: {target:?}")
+            }
+            WorkflowIssue::NoTerminalState { state } => write!(
+                f,
+                "no terminal state (\"successful\" or \"failed\") can be reached from the {state:?} state"
+            ),
+            WorkflowIssue::InvalidParameter { state, parameter } => write!(
+                f,
+                "the {state:?} state uses an unsupported parameter: {parameter:?}"
+            ),
+            WorkflowIssue::NonSubstitutedParameter { state, argument } => write!(
+                f,
+                "the {state:?} state uses a parameter which is not a whole argument, hence will not be substituted: {argument:?}"
+            ),
+        }
+    }
+}
+
+impl OperationWorkflow {
+    /// Check the workflow for issues that would only be detected at runtime
+    ///
+    /// - all the states referred to by the handlers must be defined
+    /// - from any state, a terminal state must be reachable
+    /// - the `${...}` parameters must use a supported path and must be whole script arguments
+    pub fn check(&self) -> Vec<WorkflowIssue> {
+        let mut issues = vec![];
+        let transitions = self.transitions();
+
+        for transition in transitions.iter() {
+            if !self.states.contains_key(&transition.to) {
+                let issue = WorkflowIssue::UnknownState {
+                    state: transition.from.clone(),
+                    target: transition.to.clone(),
+                };
+                if !issues.contains(&issue) {
+                    issues.push(issue);
+                }
+            }
+        }
+
+        // The states that cannot be reached from `init` are rejected when the workflow is loaded
+        let reachable = Self::reachable_states(&transitions, ["init"]);
+        for state in self.state_names() {
+            if !reachable.contains(state.as_str()) {
+                continue;
+            }
+            let from_state = Self::reachable_states(&transitions, [state.as_str()]);
+            if !from_state.contains("successful") && !from_state.contains("failed") {
+                issues.push(WorkflowIssue::NoTerminalState {
+                    state: state.clone(),
+                });
+            }
+        }
+
+        for state in self.state_names() {
+            for argument in self.states[state].parameters() {
+                issues.extend(check_parameter(state, &argument));
+            }
+        }
+
+        issues
+    }
+
+    pub(crate) fn reachable_states<'a>(
+        transitions: &'a [Transition],
+        from: impl IntoIterator<Item = &'a str>,
+    ) -> HashSet<&'a str> {
+        let mut reachable: HashSet<&str> = HashSet::new();
+        let mut pending: Vec<&str> = from.into_iter().collect();
+        while let Some(state) = pending.pop() {
+            if reachable.insert(state) {
+                for transition in transitions.iter().filter(|t| t.from == state) {
+                    pending.push(&transition.to);
+                }
+            }
+        }
+        reachable
+    }
+}
+
+impl OperationAction {
+    /// List the arguments and templates of this action where `${...}` parameters might be used
+    fn parameters(&self) -> Vec<String> {
+        match self {
+            OperationAction::Script(script, _) | OperationAction::BgScript(script, _) => {
+                let mut arguments = vec![script.command.clone()];
+                arguments.extend(script.args.iter().cloned());
+                arguments
+            }
+            OperationAction::Operation(_, input, _) => template_strings(input),
+            OperationAction::AwaitOperationCompletion(handlers) => handlers
+                .output
+                .as_ref()
+                .map(template_strings)
+                .unwrap_or_default(),
+            OperationAction::Branch(clauses, _) => clauses
+                .iter()
+                .filter_map(|clause| clause.guard.as_ref())
+                .map(|guard| format!("${{{}}}", guard.path))
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+fn template_strings(template: &Value) -> Vec<String> {
+    match template {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(items) => items.iter().flat_map(template_strings).collect(),
+        Value::Object(fields) => fields.values().flat_map(template_strings).collect(),
+        _ => vec![],
+    }
+}
+
+fn check_parameter(state: &StateName, argument: &str) -> Option<WorkflowIssue> {
+    if !argument.contains("${") {
+        return None;
+    }
+    match argument
+        .strip_prefix("${")
+        .and_then(|s| s.strip_suffix('}'))
+    {
+        None => Some(WorkflowIssue::NonSubstitutedParameter {
+            state: state.clone(),
+            argument: argument.to_string(),
+        }),
+        Some(path) if is_supported_path(path) => None,
+        Some(_) => Some(WorkflowIssue::InvalidParameter {
+            state: state.clone(),
+            parameter: argument.to_string(),
+        }),
+    }
+}
+
+/// Check a path against those supported by [GenericCommandState::inject_parameter](crate::workflow::GenericCommandState::inject_parameter)
+fn is_supported_path(path: &str) -> bool {
+    match path {
+        "." | ".topic" | ".topic.target" | ".topic.operation" | ".topic.cmd_id" | ".payload" => {
+            true
+        }
+        path => match path.strip_prefix(".payload.") {
+            None => false,
+            Some(keys) => keys.split('.').all(|key| {
+                !key.is_empty()
+                    && !key
+                        .chars()
+                        .any(|c| matches!(c, '[' | ']' | '{' | '}' | '$') || c.is_whitespace())
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(workflow: &str) -> Vec<WorkflowIssue> {
+        let workflow: OperationWorkflow = toml::from_str(workflow).unwrap();
+        workflow.check()
+    }
+
+    #[test]
+    fn a_well_defined_workflow_has_no_issues() {
+        let issues = check(
+            r#"
+operation = "check"
+
+[init]
+script = "/some/script.sh ${.payload.x.y} ${.topic.cmd_id}"
+on_success = "next"
+
+[next]
+action = "proceed"
+on_success = "successful"
+"#,
+        );
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn detect_undefined_states() {
+        let issues = check(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "typo"
+"#,
+        );
+        assert_eq!(
+            issues,
+            vec![
+                WorkflowIssue::UnknownState {
+                    state: "init".to_string(),
+                    target: "typo".to_string()
+                },
+                WorkflowIssue::NoTerminalState {
+                    state: "init".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_loops_with_no_exit() {
+        let issues = check(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "loop"
+
+[loop]
+action = "proceed"
+on_success = "init"
+"#,
+        );
+        assert!(issues.contains(&WorkflowIssue::NoTerminalState {
+            state: "init".to_string()
+        }));
+        assert!(issues.contains(&WorkflowIssue::NoTerminalState {
+            state: "loop".to_string()
+        }));
+    }
+
+    #[test]
+    fn detect_implausible_parameters() {
+        let issues = check(
+            r#"
+operation = "check"
+
+[init]
+script = "/some/script.sh --url=${.payload.url} ${.payload.list[0]} ${payload}"
+"#,
+        );
+        assert_eq!(
+            issues,
+            vec![
+                WorkflowIssue::NonSubstitutedParameter {
+                    state: "init".to_string(),
+                    argument: "--url=${.payload.url}".to_string()
+                },
+                WorkflowIssue::InvalidParameter {
+                    state: "init".to_string(),
+                    parameter: "${.payload.list[0]}".to_string()
+                },
+                WorkflowIssue::InvalidParameter {
+                    state: "init".to_string(),
+                    parameter: "${payload}".to_string()
+                },
+            ]
+        );
+    }
+}
@@ -12,6 +12,26 @@ pub enum WorkflowDefinitionError {
 
     #[error("Unknown action: {action}")]
     UnknownAction { action: String },
+
+    #[error("Invalid 'when' clause: {reason}")]
+    InvalidWhenClause { reason: String },
+
+    #[error("Unknown state {target} referenced by the {state} state")]
+    UnknownTargetState { state: String, target: String },
+
+    #[error(
+        "Unreachable 'when' clause #{index} on {state} state: a previous clause has no condition"
+    )]
+    UnreachableWhenClause { state: String, index: usize },
+
+    #[error("States not reachable from the init state: {}", states.join(", "))]
+    UnreachableStates { states: Vec<String> },
+
+    #[error("Invalid 'max_concurrent' value: at least one command must be executed at a time")]
+    InvalidConcurrencyLimit,
+
+    #[error("The {state} state is reserved for commands awaiting their turn to be executed")]
+    ReservedState { state: String },
 }
 
 /// Error related to a script definition
@@ -31,6 +51,12 @@ pub enum ScriptDefinitionError {
 
     #[error("Invalid exit code range '{from}-{to}' as {from}>{to}")]
     IncorrectRange { from: u8, to: u8 },
+
+    #[error("Exit code 0 cannot be used to trigger a retry")]
+    RetryOnSuccess,
+
+    #[error("Retry handlers provided for 'backoff_second' or 'retry_on' but no 'retries'")]
+    MissingRetries,
 }
 
 /// Error preventing a workflow to be registered
@@ -63,4 +89,10 @@ pub enum WorkflowExecutionError {
 
     #[error("No such step is defined for {operation}: {step}")]
     UnknownStep { operation: String, step: String },
+
+    #[error("Invalid '{property}' value, expecting an RFC3339 timestamp: {value}")]
+    InvalidSchedule { property: String, value: String },
+
+    #[error("The maintenance window closed at {expires_at}")]
+    MissedSchedule { expires_at: String },
 }
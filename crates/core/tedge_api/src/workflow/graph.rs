@@ -0,0 +1,252 @@
+use crate::workflow::OperationAction;
+use crate::workflow::OperationWorkflow;
+use crate::workflow::Predicate;
+use crate::workflow::StateName;
+use std::fmt::Write;
+
+/// A possible move of a command from one state to another
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Transition {
+    pub from: StateName,
+    pub to: StateName,
+    pub label: String,
+}
+
+impl OperationAction {
+    /// List the states to which a command can be moved by this action, along with the handler names
+    pub fn next_states(&self, state: &str) -> Vec<(String, StateName)> {
+        match self {
+            OperationAction::MoveTo(next) => vec![("proceed".to_string(), next.clone())],
+            OperationAction::Branch(clauses, otherwise) => clauses
+                .iter()
+                .map(|clause| {
+                    let label = match &clause.guard {
+                        None => "when".to_string(),
+                        Some(guard) => {
+                            let path = &guard.path;
+                            match &guard.predicate {
+                                Predicate::Equals(value) => format!("when {path} == {value}"),
+                                Predicate::NotEquals(value) => format!("when {path} != {value}"),
+                                Predicate::GreaterThan(value) => format!("when {path} > {value}"),
+                                Predicate::LessThan(value) => format!("when {path} < {value}"),
+                                Predicate::Exists(true) => format!("when {path} exists"),
+                                Predicate::Exists(false) => format!("when {path} is missing"),
+                            }
+                        }
+                    };
+                    (label, clause.next.status.clone())
+                })
+                .chain(std::iter::once((
+                    "otherwise".to_string(),
+                    otherwise.status.clone(),
+                )))
+                .collect(),
+            OperationAction::BuiltIn => {
+                // The builtin actions move a command from `scheduled` to `executing`,
+                // and then to one of the terminal states
+                let mut next_states = vec![];
+                if state != "executing" {
+                    next_states.push(("builtin".to_string(), "executing".to_string()));
+                }
+                next_states.push(("builtin".to_string(), "successful".to_string()));
+                next_states.push(("builtin".to_string(), "failed".to_string()));
+                next_states
+            }
+            OperationAction::AwaitingAgentRestart {
+                on_success,
+                on_timeout,
+                ..
+            } => vec![
+                ("on_success".to_string(), on_success.status.clone()),
+                ("on_timeout".to_string(), on_timeout.status.clone()),
+            ],
+            OperationAction::Restart {
+                on_exec,
+                on_success,
+                on_error,
+            } => vec![
+                ("on_exec".to_string(), on_exec.clone()),
+                ("on_success".to_string(), on_success.clone()),
+                ("on_error".to_string(), on_error.clone()),
+            ],
+            OperationAction::Script(_, handlers) => handlers.next_states(),
+            OperationAction::BgScript(_, handlers) => {
+                vec![("on_exec".to_string(), handlers.on_exec.status.clone())]
+            }
+            OperationAction::Operation(_, _, handlers) => {
+                vec![("on_exec".to_string(), handlers.on_exec.status.clone())]
+            }
+            OperationAction::AwaitOperationCompletion(handlers) => {
+                let on_error = handlers
+                    .on_error
+                    .as_ref()
+                    .map(|update| update.status.clone())
+                    .unwrap_or_else(|| "failed".to_string());
+                vec![
+                    ("on_success".to_string(), handlers.on_success.status.clone()),
+                    ("on_error".to_string(), on_error),
+                ]
+            }
+            OperationAction::Clear => vec![],
+        }
+    }
+}
+
+impl OperationWorkflow {
+    /// The names of the workflow states, in a deterministic order: `init` first, then alphabetically
+    pub fn state_names(&self) -> Vec<&StateName> {
+        let mut names: Vec<&StateName> = self.states.keys().collect();
+        names.sort_by_key(|name| (name.as_str() != "init", name.as_str()));
+        names
+    }
+
+    /// List all the possible transitions between the workflow states
+    ///
+    /// Duplicated transitions, e.g. `on_error` and `on_kill` both leading to `failed`, are merged.
+    pub fn transitions(&self) -> Vec<Transition> {
+        let mut transitions: Vec<Transition> = vec![];
+        for state in self.state_names() {
+            let action = &self.states[state];
+            for (label, next) in action.next_states(state) {
+                match transitions
+                    .iter_mut()
+                    .find(|t| &t.from == state && t.to == next)
+                {
+                    Some(transition) => {
+                        transition.label.push_str(", ");
+                        transition.label.push_str(&label);
+                    }
+                    None => transitions.push(Transition {
+                        from: state.clone(),
+                        to: next,
+                        label,
+                    }),
+                }
+            }
+        }
+        transitions
+    }
+
+    /// Render the workflow state machine using the Graphviz DOT language
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph {:?} {{", self.operation.to_string());
+        for state in self.state_names() {
+            let shape = match state.as_str() {
+                "init" => "circle",
+                "successful" | "failed" => "doublecircle",
+                _ => "box",
+            };
+            let _ = writeln!(dot, "  {state:?} [shape={shape}];");
+        }
+        for transition in self.transitions() {
+            let _ = writeln!(
+                dot,
+                "  {:?} -> {:?} [label={:?}];",
+                transition.from, transition.to, transition.label
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the workflow state machine as a Mermaid state diagram
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::new();
+        mermaid.push_str("stateDiagram-v2\n");
+        for state in self.state_names() {
+            let _ = writeln!(mermaid, "  state \"{state}\" as {}", mermaid_id(state));
+        }
+        mermaid.push_str("  [*] --> init\n");
+        for transition in self.transitions() {
+            let _ = writeln!(
+                mermaid,
+                "  {} --> {}: {}",
+                mermaid_id(&transition.from),
+                mermaid_id(&transition.to),
+                transition.label.replace(':', " ")
+            );
+        }
+        for terminal in ["successful", "failed"] {
+            if self.states.contains_key(terminal) {
+                let _ = writeln!(mermaid, "  {terminal} --> [*]");
+            }
+        }
+        mermaid
+    }
+}
+
+/// Mermaid state ids cannot contain punctuation
+fn mermaid_id(state: &str) -> String {
+    state
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow() -> OperationWorkflow {
+        toml::from_str(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "device-restart"
+
+[device-restart]
+action = "restart"
+on_exec = "waiting"
+on_success = "successful"
+on_error = "failed"
+
+[waiting]
+action = "await-agent-restart"
+on_success = "successful"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn list_transitions() {
+        let transitions: Vec<(String, String, String)> = workflow()
+            .transitions()
+            .into_iter()
+            .map(|t| (t.from, t.to, t.label))
+            .collect();
+        let expected = [
+            ("init", "device-restart", "proceed"),
+            ("device-restart", "waiting", "on_exec"),
+            ("device-restart", "successful", "on_success"),
+            ("device-restart", "failed", "on_error"),
+            ("waiting", "successful", "on_success"),
+            ("waiting", "failed", "on_timeout"),
+        ]
+        .map(|(from, to, label)| (from.to_string(), to.to_string(), label.to_string()));
+        assert_eq!(transitions, expected);
+    }
+
+    #[test]
+    fn render_dot_graph() {
+        let dot = workflow().to_dot();
+        assert!(dot.starts_with("digraph \"check\" {\n"));
+        assert!(dot.contains("  \"init\" [shape=circle];\n"));
+        assert!(dot.contains("  \"failed\" [shape=doublecircle];\n"));
+        assert!(dot.contains("  \"init\" -> \"device-restart\" [label=\"proceed\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn render_mermaid_graph() {
+        let mermaid = workflow().to_mermaid();
+        assert!(mermaid.starts_with("stateDiagram-v2\n"));
+        assert!(mermaid.contains("  state \"device-restart\" as device_restart\n"));
+        assert!(mermaid.contains("  [*] --> init\n"));
+        assert!(mermaid.contains("  init --> device_restart: proceed\n"));
+        assert!(mermaid.contains("  successful --> [*]\n"));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::workflow::GenericCommandState;
+use crate::workflow::GenericStateUpdate;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A `when` clause, routing a command to a next state when its payload matches a guard
+///
+/// ```toml
+/// action = "proceed"
+/// when = [
+///     { path = ".payload.kind", equals = "firmware", next = "install_firmware" },
+///     { path = ".payload.size", greater_than = 1000, next = { status = "failed", reason = "too large" } },
+///     { next = "install_software" },
+/// ]
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WhenClause {
+    /// The condition to be satisfied by the command state; `None` for a clause that always applies
+    pub guard: Option<JsonPathGuard>,
+
+    /// The next state, when the guard is satisfied
+    pub next: GenericStateUpdate,
+}
+
+/// A comparison of the value found at some path of a command state
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JsonPathGuard {
+    /// Path to the value to be checked, e.g. `.payload.x.y`
+    pub path: String,
+
+    /// The comparison applied to this value
+    pub predicate: Predicate,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Predicate {
+    Equals(Value),
+    NotEquals(Value),
+    GreaterThan(Value),
+    LessThan(Value),
+    Exists(bool),
+}
+
+impl WhenClause {
+    /// Return the first clause matching the command state
+    pub fn select<'a>(
+        clauses: &'a [WhenClause],
+        state: &GenericCommandState,
+    ) -> Option<&'a WhenClause> {
+        clauses.iter().find(|clause| clause.matches(state))
+    }
+
+    pub fn matches(&self, state: &GenericCommandState) -> bool {
+        match &self.guard {
+            None => true,
+            Some(guard) => guard.matches(state),
+        }
+    }
+}
+
+impl JsonPathGuard {
+    pub fn new(path: &str, predicate: Predicate) -> Self {
+        // The path can be given with or without the `${...}` wrapper used by script arguments
+        let path = path
+            .strip_prefix("${")
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or(path);
+        JsonPathGuard {
+            path: path.to_string(),
+            predicate,
+        }
+    }
+
+    pub fn matches(&self, state: &GenericCommandState) -> bool {
+        let value = state.extract_value(&self.path);
+        match (&self.predicate, value) {
+            (Predicate::Exists(expected), value) => value.is_some() == *expected,
+            (_, None) => false,
+            (Predicate::Equals(expected), Some(value)) => &value == expected,
+            (Predicate::NotEquals(expected), Some(value)) => &value != expected,
+            (Predicate::GreaterThan(bound), Some(value)) => {
+                compare(&value, bound) == Some(Ordering::Greater)
+            }
+            (Predicate::LessThan(bound), Some(value)) => {
+                compare(&value, bound) == Some(Ordering::Less)
+            }
+        }
+    }
+}
+
+/// Compare two numbers or two strings; any other combination is not comparable
+fn compare(value: &Value, bound: &Value) -> Option<Ordering> {
+    match (value, bound) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    #[test]
+    fn guards_are_evaluated_against_the_command_payload() {
+        let state = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/update/123"),
+            status: "init".to_string(),
+            payload: json!({
+                "status": "init",
+                "kind": "firmware",
+                "size": 1024,
+                "options": { "force": true }
+            }),
+        };
+
+        let check = |path: &str, predicate| JsonPathGuard::new(path, predicate).matches(&state);
+
+        assert!(check(".payload.kind", Predicate::Equals(json!("firmware"))));
+        assert!(!check(
+            ".payload.kind",
+            Predicate::Equals(json!("software"))
+        ));
+        assert!(check(
+            ".payload.kind",
+            Predicate::NotEquals(json!("software"))
+        ));
+        assert!(check(
+            "${.payload.size}",
+            Predicate::GreaterThan(json!(1000))
+        ));
+        assert!(!check(".payload.size", Predicate::LessThan(json!(1000))));
+        assert!(check(
+            ".payload.options.force",
+            Predicate::Equals(json!(true))
+        ));
+        assert!(check(".payload.options", Predicate::Exists(true)));
+        assert!(check(".payload.unknown", Predicate::Exists(false)));
+        assert!(!check(".payload.unknown", Predicate::NotEquals(json!(0))));
+        assert!(!check(".payload.kind", Predicate::GreaterThan(json!(0))));
+        assert!(check(
+            ".topic.operation",
+            Predicate::Equals(json!("update"))
+        ));
+    }
+
+    #[test]
+    fn the_first_matching_clause_is_selected() {
+        let state = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/update/123"),
+            status: "init".to_string(),
+            payload: json!({ "status": "init", "size": 10 }),
+        };
+        let clauses = vec![
+            WhenClause {
+                guard: Some(JsonPathGuard::new(
+                    ".payload.size",
+                    Predicate::GreaterThan(json!(100)),
+                )),
+                next: "large".to_string().into(),
+            },
+            WhenClause {
+                guard: Some(JsonPathGuard::new(
+                    ".payload.size",
+                    Predicate::GreaterThan(json!(1)),
+                )),
+                next: "medium".to_string().into(),
+            },
+            WhenClause {
+                guard: None,
+                next: "small".to_string().into(),
+            },
+        ];
+
+        let selected = WhenClause::select(&clauses, &state).unwrap();
+        assert_eq!(selected.next.status, "medium");
+    }
+}
@@ -0,0 +1,442 @@
+use crate::workflow::GenericCommandState;
+use crate::workflow::TopicName;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Name of the file where the agent persists the command history, in its state directory
+///
+/// This file is a log of [HistoryEvent]s, one JSON event per line,
+/// that the agent appends to and compacts from time to time.
+pub const COMMAND_HISTORY_FILE: &str = "workflow-history";
+
+/// A bounded record of the commands processed by the agent, with their state transitions
+///
+/// The pending commands are always kept, while only the latest completed commands are retained.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommandHistory {
+    /// The maximum number of completed commands kept in the history
+    capacity: usize,
+
+    /// The commands, ordered by creation time
+    commands: Vec<CommandRecord>,
+
+    /// The events recorded but not persisted yet
+    new_events: Vec<HistoryEvent>,
+}
+
+/// An update of the command history, as logged in the history file
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    /// A command has been moved to a new state
+    State {
+        topic: TopicName,
+        unix_timestamp: i64,
+        status: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+
+    /// A command has been cleared
+    Cleared { topic: TopicName },
+}
+
+/// The record of a command: its state transitions, from `init` to its clearing
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CommandRecord {
+    /// The command topic
+    pub topic: TopicName,
+
+    /// The operation of the command
+    pub operation: String,
+
+    /// The command id
+    pub cmd_id: String,
+
+    /// The successive states of the command
+    pub transitions: Vec<StateTransition>,
+
+    /// The failure reason, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// Set when the command has been cleared, i.e. is no more pending
+    #[serde(default)]
+    pub completed: bool,
+}
+
+/// A move of a command to a new state
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub unix_timestamp: i64,
+    pub status: String,
+}
+
+impl Default for CommandHistory {
+    fn default() -> Self {
+        CommandHistory::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl CommandHistory {
+    pub const DEFAULT_CAPACITY: usize = 100;
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        CommandHistory {
+            capacity,
+            commands: vec![],
+            new_events: vec![],
+        }
+    }
+
+    /// Rebuild a history from the content of a history file,
+    /// retaining as many completed commands as the given capacity
+    ///
+    /// Lines that are not valid events, as a line truncated by a crash, are ignored.
+    pub fn from_log(log: &str, capacity: usize) -> Self {
+        let mut history = CommandHistory::with_capacity(capacity);
+        for line in log.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(event) => {
+                    history.apply(&event);
+                }
+                Err(err) => warn!("Ignoring invalid command history event {line:?}: {err}"),
+            }
+        }
+        history
+    }
+
+    /// Take the events recorded since the last call, to be appended to the history file
+    pub fn take_new_events(&mut self) -> Vec<HistoryEvent> {
+        std::mem::take(&mut self.new_events)
+    }
+
+    /// The minimal sequence of events rebuilding this history, to compact the history file
+    pub fn events(&self) -> Vec<HistoryEvent> {
+        let mut events = vec![];
+        for record in self.commands.iter() {
+            let last = record.transitions.len().saturating_sub(1);
+            for (i, transition) in record.transitions.iter().enumerate() {
+                events.push(HistoryEvent::State {
+                    topic: record.topic.clone(),
+                    unix_timestamp: transition.unix_timestamp,
+                    status: transition.status.clone(),
+                    reason: if i == last {
+                        record.reason.clone()
+                    } else {
+                        None
+                    },
+                });
+            }
+            if record.completed {
+                events.push(HistoryEvent::Cleared {
+                    topic: record.topic.clone(),
+                });
+            }
+        }
+        events
+    }
+
+    /// Iterate over the recorded commands, from the oldest to the latest
+    pub fn iter(&self) -> impl Iterator<Item = &CommandRecord> {
+        self.commands.iter()
+    }
+
+    /// Find the latest command with the given topic or id
+    pub fn find(&self, topic_or_cmd_id: &str) -> Option<&CommandRecord> {
+        self.commands
+            .iter()
+            .rev()
+            .find(|record| record.topic == topic_or_cmd_id || record.cmd_id == topic_or_cmd_id)
+    }
+
+    /// Record a new state for a command
+    ///
+    /// A new record is created if there is no pending command on that topic.
+    pub fn record_state(&mut self, state: &GenericCommandState) {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        self.record_state_at(state, now)
+    }
+
+    fn record_state_at(&mut self, state: &GenericCommandState, unix_timestamp: i64) {
+        self.record(HistoryEvent::State {
+            topic: state.topic.name.clone(),
+            unix_timestamp,
+            status: state.status.clone(),
+            reason: state.failure_reason(),
+        })
+    }
+
+    /// Mark as completed the pending command on that topic, if any
+    ///
+    /// The oldest completed commands are removed to keep the history within its capacity.
+    pub fn record_clearing(&mut self, topic: &str) {
+        self.record(HistoryEvent::Cleared {
+            topic: topic.to_string(),
+        })
+    }
+
+    fn record(&mut self, event: HistoryEvent) {
+        if self.apply(&event) {
+            self.new_events.push(event)
+        }
+    }
+
+    /// Update the history with an event, returning false if this event changes nothing
+    fn apply(&mut self, event: &HistoryEvent) -> bool {
+        match event {
+            HistoryEvent::State {
+                topic,
+                unix_timestamp,
+                status,
+                reason,
+            } => {
+                let record = match self.pending_record_mut(topic) {
+                    Some(record) => record,
+                    None => {
+                        self.commands.push(CommandRecord::new(topic.clone()));
+                        self.commands.last_mut().unwrap()
+                    }
+                };
+
+                let mut updated = false;
+                if record.status() != Some(status.as_str()) {
+                    record.transitions.push(StateTransition {
+                        unix_timestamp: *unix_timestamp,
+                        status: status.clone(),
+                    });
+                    updated = true;
+                }
+                if reason.is_some() && &record.reason != reason {
+                    record.reason = reason.clone();
+                    updated = true;
+                }
+                updated
+            }
+
+            HistoryEvent::Cleared { topic } => {
+                let Some(record) = self.pending_record_mut(topic) else {
+                    return false;
+                };
+                record.completed = true;
+
+                let completed_count = self.commands.iter().filter(|c| c.completed).count();
+                let mut excess = completed_count.saturating_sub(self.capacity);
+                self.commands.retain(|record| {
+                    if excess > 0 && record.completed {
+                        excess -= 1;
+                        false
+                    } else {
+                        true
+                    }
+                });
+                true
+            }
+        }
+    }
+
+    fn pending_record_mut(&mut self, topic: &str) -> Option<&mut CommandRecord> {
+        self.commands
+            .iter_mut()
+            .rev()
+            .find(|record| !record.completed && record.topic == topic)
+    }
+}
+
+impl CommandRecord {
+    fn new(topic: TopicName) -> Self {
+        let (operation, cmd_id) = match topic.split('/').collect::<Vec<&str>>()[..] {
+            [_, _, _, _, _, "cmd", operation, cmd_id] => {
+                (operation.to_string(), cmd_id.to_string())
+            }
+            _ => (String::new(), String::new()),
+        };
+        CommandRecord {
+            topic,
+            operation,
+            cmd_id,
+            transitions: vec![],
+            reason: None,
+            completed: false,
+        }
+    }
+
+    /// The latest status of the command
+    pub fn status(&self) -> Option<&str> {
+        self.transitions.last().map(|t| t.status.as_str())
+    }
+
+    /// When the command has been created
+    pub fn created_at(&self) -> Option<i64> {
+        self.transitions.first().map(|t| t.unix_timestamp)
+    }
+
+    /// When the command has been moved to its latest status
+    pub fn updated_at(&self) -> Option<i64> {
+        self.transitions.last().map(|t| t.unix_timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    fn state(cmd_id: &str, status: &str) -> GenericCommandState {
+        GenericCommandState {
+            topic: Topic::new_unchecked(&format!("te/device/main///cmd/restart/{cmd_id}")),
+            status: status.to_string(),
+            payload: json!({"status": status}),
+        }
+        .move_to(status.to_string())
+    }
+
+    #[test]
+    fn record_state_transitions() {
+        let mut history = CommandHistory::default();
+        history.record_state_at(&state("123", "init"), 1);
+        history.record_state_at(&state("123", "executing"), 2);
+        history.record_state_at(&state("123", "executing"), 3);
+        history.record_state_at(&state("123", "failed").fail_with("oops".to_string()), 4);
+
+        let record = history.find("123").unwrap();
+        assert_eq!(record.operation, "restart");
+        assert_eq!(record.topic, "te/device/main///cmd/restart/123");
+        assert_eq!(
+            record.transitions,
+            vec![
+                StateTransition {
+                    unix_timestamp: 1,
+                    status: "init".to_string()
+                },
+                StateTransition {
+                    unix_timestamp: 2,
+                    status: "executing".to_string()
+                },
+                StateTransition {
+                    unix_timestamp: 4,
+                    status: "failed".to_string()
+                },
+            ]
+        );
+        assert_eq!(record.reason, Some("oops".to_string()));
+        assert!(!record.completed);
+
+        history.record_clearing("te/device/main///cmd/restart/123");
+        assert!(history.find("123").unwrap().completed);
+    }
+
+    #[test]
+    fn only_the_latest_completed_commands_are_kept() {
+        let mut history = CommandHistory::with_capacity(2);
+        for cmd_id in ["1", "2", "3", "4"] {
+            history.record_state_at(&state(cmd_id, "init"), 1);
+        }
+        for cmd_id in ["1", "3", "4"] {
+            history.record_clearing(&format!("te/device/main///cmd/restart/{cmd_id}"));
+        }
+
+        let cmd_ids: Vec<&str> = history.iter().map(|c| c.cmd_id.as_str()).collect();
+        assert_eq!(cmd_ids, vec!["2", "3", "4"]);
+        assert!(!history.find("2").unwrap().completed);
+    }
+
+    #[test]
+    fn a_command_id_can_be_reused_once_cleared() {
+        let mut history = CommandHistory::default();
+        history.record_state_at(&state("123", "init"), 1);
+        history.record_clearing("te/device/main///cmd/restart/123");
+        history.record_state_at(&state("123", "init"), 2);
+
+        assert_eq!(history.iter().count(), 2);
+        let latest = history.find("123").unwrap();
+        assert!(!latest.completed);
+        assert_eq!(latest.created_at(), Some(2));
+    }
+
+    #[test]
+    fn only_updates_are_logged() {
+        let mut history = CommandHistory::default();
+        history.record_state_at(&state("123", "init"), 1);
+        history.record_state_at(&state("123", "init"), 2);
+        history.record_clearing("te/device/main///cmd/restart/123");
+        history.record_clearing("te/device/main///cmd/restart/123");
+
+        let topic = "te/device/main///cmd/restart/123".to_string();
+        assert_eq!(
+            history.take_new_events(),
+            vec![
+                HistoryEvent::State {
+                    topic: topic.clone(),
+                    unix_timestamp: 1,
+                    status: "init".to_string(),
+                    reason: None
+                },
+                HistoryEvent::Cleared { topic }
+            ]
+        );
+        assert_eq!(history.take_new_events(), vec![]);
+    }
+
+    #[test]
+    fn rebuild_history_from_logged_events() {
+        let mut history = CommandHistory::default();
+        history.record_state_at(&state("123", "init"), 1);
+        history.record_state_at(&state("456", "init"), 2);
+        history.record_state_at(&state("123", "failed").fail_with("oops".to_string()), 3);
+        history.record_clearing("te/device/main///cmd/restart/123");
+        history.record_state_at(&state("123", "init"), 4);
+
+        let log: String = history
+            .take_new_events()
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap() + "\n")
+            .collect();
+        assert_eq!(
+            CommandHistory::from_log(&log, CommandHistory::DEFAULT_CAPACITY),
+            history
+        );
+
+        let compacted: String = history
+            .events()
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap() + "\n")
+            .collect();
+        assert_eq!(
+            CommandHistory::from_log(&compacted, CommandHistory::DEFAULT_CAPACITY),
+            history
+        );
+    }
+
+    #[test]
+    fn invalid_log_lines_are_ignored() {
+        let log = r#"{"event":"state","topic":"te/device/main///cmd/restart/123","unix_timestamp":1,"status":"init"}
+{"event":"cleared","topic":"te/device/main///cmd/restart/123"}
+{"event":"state","topic":"te/device/main///cmd/restart/456","unix_ti"#;
+
+        let history = CommandHistory::from_log(log, CommandHistory::DEFAULT_CAPACITY);
+        let cmd_ids: Vec<&str> = history.iter().map(|c| c.cmd_id.as_str()).collect();
+        assert_eq!(cmd_ids, vec!["123"]);
+        assert!(history.find("123").unwrap().completed);
+    }
+
+    #[test]
+    fn rebuilt_history_is_bounded_by_the_given_capacity() {
+        let mut history = CommandHistory::default();
+        for cmd_id in ["1", "2", "3", "4"] {
+            history.record_state_at(&state(cmd_id, "init"), 1);
+            history.record_clearing(&format!("te/device/main///cmd/restart/{cmd_id}"));
+        }
+        let log: String = history
+            .take_new_events()
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap() + "\n")
+            .collect();
+
+        let history = CommandHistory::from_log(&log, 2);
+        let cmd_ids: Vec<&str> = history.iter().map(|c| c.cmd_id.as_str()).collect();
+        assert_eq!(cmd_ids, vec!["3", "4"]);
+    }
+}
@@ -1,4 +1,8 @@
+pub mod check;
 pub mod error;
+pub mod graph;
+pub mod guard;
+pub mod history;
 mod on_disk;
 pub mod script;
 pub mod state;
@@ -8,11 +12,16 @@ mod toml_config;
 use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
 use crate::mqtt_topics::OperationType;
+pub use check::*;
 pub use error::*;
+pub use graph::*;
+pub use guard::*;
+pub use history::*;
 use mqtt_channel::Message;
 use mqtt_channel::QoS;
 pub use script::*;
 use serde::Deserialize;
+use serde_json::Value;
 pub use state::*;
 use std::collections::HashMap;
 use std::fmt::Display;
@@ -38,6 +47,24 @@ pub struct OperationWorkflow {
 
     /// The states of the state machine
     pub states: HashMap<StateName, OperationAction>,
+
+    /// The maximum number of commands of this operation executed concurrently, if limited
+    pub max_concurrent: Option<usize>,
+
+    /// The order in which the commands queued due to the concurrency limit are started
+    pub queue_order: QueueOrder,
+}
+
+/// The order in which queued commands are started, when the concurrency limit is reached
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOrder {
+    /// The oldest queued command is started first
+    #[default]
+    Fifo,
+
+    /// The latest queued command is started first
+    Lifo,
 }
 
 /// What needs to be done to advance an operation request in some state
@@ -53,6 +80,19 @@ pub enum OperationAction {
     /// ```
     MoveTo(StateName),
 
+    /// Move to the next state selected by the first `when` clause matching the command state,
+    /// falling back to the `on_success` state when no clause applies.
+    ///
+    /// ```toml
+    /// action = "proceed"
+    /// when = [
+    ///     { path = ".payload.kind", equals = "firmware", next = "<state>" },
+    ///     { path = ".payload.size", greater_than = 1000, next = "<state>" },
+    /// ]
+    /// on_success = "<state>"
+    /// ```
+    Branch(Vec<WhenClause>, GenericStateUpdate),
+
     /// The built-in behavior is used
     ///
     /// ```toml
@@ -100,6 +140,28 @@ pub enum OperationAction {
     /// ```
     BgScript(ShellScript, BgExitHandlers),
 
+    /// Trigger a sub-operation and move to the next state from where its outcome will be awaited
+    ///
+    /// The sub-operation command is created on the same target as the invoking command,
+    /// with an initial payload built from the `input` template.
+    ///
+    /// ```toml
+    /// operation = "software_update"
+    /// input.updateList = "${.payload.updateList}"
+    /// on_exec = "<state>"
+    /// ```
+    Operation(OperationType, Value, BgExitHandlers),
+
+    /// Await the completion of the sub-operation triggered by a previous state
+    ///
+    /// ```toml
+    /// action = "await-operation-completion"
+    /// output.version = "${.payload.version}"
+    /// on_success = "<state>"
+    /// on_error = "<state>"
+    /// ```
+    AwaitOperationCompletion(AwaitHandlers),
+
     /// The command has been fully processed and needs to be cleared
     Clear,
 }
@@ -108,11 +170,23 @@ impl Display for OperationAction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             OperationAction::MoveTo(step) => format!("move to {step} state"),
+            OperationAction::Branch(clauses, _) => {
+                format!(
+                    "move to the state selected by {} when clauses",
+                    clauses.len()
+                )
+            }
             OperationAction::BuiltIn => "builtin".to_string(),
             OperationAction::AwaitingAgentRestart { .. } => "awaiting agent restart".to_string(),
             OperationAction::Restart { .. } => "trigger device restart".to_string(),
             OperationAction::Script(script, _) => script.to_string(),
             OperationAction::BgScript(script, _) => script.to_string(),
+            OperationAction::Operation(operation, _, _) => {
+                format!("execute {operation} as a sub-operation")
+            }
+            OperationAction::AwaitOperationCompletion(_) => {
+                "await sub-operation completion".to_string()
+            }
             OperationAction::Clear => "wait for the requester to finalize the command".to_string(),
         };
         f.write_str(&str)
@@ -163,10 +237,39 @@ impl OperationWorkflow {
             built_in: false,
             handlers,
             states,
+            max_concurrent: None,
+            queue_order: QueueOrder::default(),
+        })
+    }
+
+    /// Limit the number of commands of this operation that are executed concurrently
+    ///
+    /// The commands exceeding this limit are moved to a `queued` status
+    /// till the completion of a command under execution.
+    pub fn with_concurrency_limit(
+        self,
+        max_concurrent: Option<usize>,
+        queue_order: QueueOrder,
+    ) -> Result<Self, WorkflowDefinitionError> {
+        if max_concurrent == Some(0) {
+            return Err(WorkflowDefinitionError::InvalidConcurrencyLimit);
+        }
+        if max_concurrent.is_some() && self.states.contains_key(QUEUED) {
+            return Err(WorkflowDefinitionError::ReservedState {
+                state: QUEUED.to_string(),
+            });
+        }
+        Ok(OperationWorkflow {
+            max_concurrent,
+            queue_order,
+            ..self
         })
     }
 
     /// Create a built-in operation workflow
+    ///
+    /// Software updates and restarts are executed one at a time, the other commands not being limited.
+    /// These defaults can be changed by customizing the operation with a workflow file setting `max_concurrent`.
     pub fn built_in(operation: OperationType) -> Self {
         let states = [
             ("init", OperationAction::MoveTo("scheduled".to_string())),
@@ -179,11 +282,19 @@ impl OperationWorkflow {
         .map(|(state, action)| (state.to_string(), action))
         .collect();
 
+        // Software updates and restarts must not be interleaved
+        let max_concurrent = match operation {
+            OperationType::SoftwareUpdate | OperationType::Restart => Some(1),
+            _ => None,
+        };
+
         OperationWorkflow {
             built_in: true,
             operation,
             handlers: DefaultHandlers::default(),
             states,
+            max_concurrent,
+            queue_order: QueueOrder::default(),
         }
     }
 
@@ -250,6 +361,11 @@ impl OperationAction {
                 },
                 handlers.clone(),
             ),
+            OperationAction::Operation(operation, input, handlers) => OperationAction::Operation(
+                operation.clone(),
+                state.inject_values_into_template(input),
+                handlers.clone(),
+            ),
             _ => self.clone(),
         }
     }
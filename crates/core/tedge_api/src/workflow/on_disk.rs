@@ -24,6 +24,8 @@ pub(crate) struct OnDiskCommandStateV1 {
     unix_timestamp: i64,
     status: String,
     payload: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    invoking_command: Option<String>,
 }
 
 impl TryFrom<OnDiskCommandBoard> for CommandBoard {
@@ -46,6 +48,7 @@ impl TryFrom<OnDiskCommandBoardV1> for CommandBoard {
 
     fn try_from(board: OnDiskCommandBoardV1) -> Result<Self, Self::Error> {
         let mut commands = HashMap::new();
+        let mut sub_commands = HashMap::new();
         for (topic_name, command) in board.commands {
             let topic =
                 Topic::new(&topic_name).map_err(|_| CommandBoardTomlError::InvalidTopic {
@@ -62,9 +65,12 @@ impl TryFrom<OnDiskCommandBoardV1> for CommandBoard {
                 status: command.status,
                 payload: command.payload,
             };
+            if let Some(invoking_command) = command.invoking_command {
+                sub_commands.insert(topic_name.clone(), invoking_command);
+            }
             commands.insert(topic_name, (timestamp, state));
         }
-        Ok(CommandBoard::new(commands))
+        Ok(CommandBoard::new(commands, sub_commands))
     }
 }
 
@@ -73,12 +79,14 @@ impl From<CommandBoard> for OnDiskCommandBoardV1 {
         let mut commands = HashMap::new();
         for (timestamp, state) in board.iter() {
             let topic_name = state.topic.name.clone();
+            let invoking_command = board.invoking_command_topic(&topic_name).cloned();
             commands.insert(
                 topic_name,
                 OnDiskCommandStateV1 {
                     unix_timestamp: timestamp.unix_timestamp(),
                     status: state.status.clone(),
                     payload: state.payload.clone(),
+                    invoking_command,
                 },
             );
         }
@@ -1,5 +1,7 @@
+use crate::workflow::GenericCommandState;
 use crate::workflow::GenericStateUpdate;
 use crate::workflow::ScriptDefinitionError;
+use crate::workflow::StateName;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -67,6 +69,7 @@ pub struct ExitHandlers {
     on_exit: Vec<(u8, u8, GenericStateUpdate)>,
     on_stdout: Vec<String>,
     timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
 }
 
 impl ExitHandlers {
@@ -131,9 +134,48 @@ impl ExitHandlers {
             on_exit,
             on_stdout,
             timeout,
+            retry: None,
         })
     }
 
+    pub fn with_retry_policy(self, retry: Option<RetryPolicy>) -> Self {
+        ExitHandlers { retry, ..self }
+    }
+
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+
+    /// Tell if the script has to be run again, given the outcome of the given attempt
+    ///
+    /// A failed attempt is retried as long as the retry policy allows more attempts
+    /// and the exit code is listed by `retry_on`.
+    /// If no `retry_on` codes are given, then any failure not handled by a specific `on_exit` handler is retried,
+    /// including the script being killed on timeout.
+    pub fn should_retry(
+        &self,
+        outcome: &std::io::Result<std::process::Output>,
+        attempt: u32,
+    ) -> bool {
+        let Some(retry) = &self.retry else {
+            return false;
+        };
+        if attempt > retry.retries {
+            return false;
+        }
+        match outcome {
+            Ok(output) => match output.status.code() {
+                None => retry.retry_on.is_empty(),
+                Some(0) => false,
+                Some(code) if retry.retry_on.is_empty() => {
+                    self.state_update_on_error(code as u8).is_none()
+                }
+                Some(code) => retry.retry_on(code as u8),
+            },
+            Err(_) => false,
+        }
+    }
+
     pub fn with_default(mut self, default: &DefaultHandlers) -> Self {
         if self.timeout.is_none() {
             self.timeout = default.timeout
@@ -253,6 +295,47 @@ impl ExitHandlers {
         })
     }
 
+    /// List the states to which a command can be moved by these handlers, along with the handler names
+    ///
+    /// The states listed by `on_stdout` are only the expected ones,
+    /// as the actual next state is provided by the script at runtime.
+    pub fn next_states(&self) -> Vec<(String, StateName)> {
+        let mut next_states = Vec::new();
+        if self.on_success.is_some() || self.on_stdout.is_empty() {
+            next_states.push((
+                "on_success".to_string(),
+                self.state_update_on_success().status,
+            ));
+        }
+        for (from, to, update) in self.on_exit.iter() {
+            if *from == 0 {
+                continue;
+            }
+            let codes = if from == to {
+                format!("{from}")
+            } else {
+                format!("{from}-{to}")
+            };
+            next_states.push((format!("on_exit.{codes}"), update.status.clone()));
+        }
+        for status in self.on_stdout.iter() {
+            next_states.push(("on_stdout".to_string(), status.clone()));
+        }
+        let on_error = self
+            .on_error
+            .as_ref()
+            .map(|update| update.status.clone())
+            .unwrap_or_else(|| "failed".to_string());
+        next_states.push(("on_error".to_string(), on_error));
+        let on_kill = self
+            .on_kill
+            .as_ref()
+            .map(|update| update.status.clone())
+            .unwrap_or_else(|| "failed".to_string());
+        next_states.push(("on_kill".to_string(), on_kill));
+        next_states
+    }
+
     pub fn graceful_timeout(&self) -> Option<Duration> {
         self.timeout
     }
@@ -274,6 +357,71 @@ fn extract_script_output(stdout: String) -> Option<String> {
     None
 }
 
+/// Define how a failing script is retried
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts after the first one
+    pub retries: u32,
+
+    /// The delay before the first retry, doubled for each subsequent retry
+    pub backoff: Duration,
+
+    /// The ranges of exit codes triggering a retry; any error if empty
+    pub retry_on: Vec<(u8, u8)>,
+}
+
+impl RetryPolicy {
+    /// The delay used when no `backoff_second` is provided
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(5);
+
+    /// The upper bound of the delay between two attempts
+    pub const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+    pub fn try_new(
+        retries: u32,
+        backoff: Option<Duration>,
+        retry_on: Vec<(u8, u8)>,
+    ) -> Result<Self, ScriptDefinitionError> {
+        for (from, to) in retry_on.iter() {
+            if to < from {
+                return Err(ScriptDefinitionError::IncorrectRange {
+                    from: *from,
+                    to: *to,
+                });
+            }
+            if *from == 0 {
+                return Err(ScriptDefinitionError::RetryOnSuccess);
+            }
+        }
+
+        Ok(RetryPolicy {
+            retries,
+            backoff: backoff.unwrap_or(Self::DEFAULT_BACKOFF),
+            retry_on,
+        })
+    }
+
+    /// Tell if an exit code triggers a retry
+    pub fn retry_on(&self, code: u8) -> bool {
+        self.retry_on.is_empty()
+            || self
+                .retry_on
+                .iter()
+                .any(|(from, to)| *from <= code && code <= *to)
+    }
+
+    /// The delay to wait before the given attempt
+    ///
+    /// No delay for the first attempt, then `backoff`, `2 * backoff`, `4 * backoff` ... up to [RetryPolicy::MAX_BACKOFF]
+    pub fn delay(&self, attempt: u32) -> Duration {
+        if attempt <= 1 {
+            return Duration::ZERO;
+        }
+        let factor = 2u32.saturating_pow(attempt - 2);
+        min(self.backoff.saturating_mul(factor), Self::MAX_BACKOFF)
+    }
+}
+
 /// Define how to handle a background script
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct BgExitHandlers {
@@ -288,6 +436,67 @@ impl BgExitHandlers {
     }
 }
 
+/// Define how to resume a command when the sub-operation it awaits is completed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AwaitHandlers {
+    pub on_success: GenericStateUpdate,
+    pub on_error: Option<GenericStateUpdate>,
+    pub output: Option<Value>,
+}
+
+impl AwaitHandlers {
+    pub fn try_new(
+        on_success: Option<GenericStateUpdate>,
+        on_error: Option<GenericStateUpdate>,
+        output: Option<Value>,
+    ) -> Result<Self, ScriptDefinitionError> {
+        Ok(AwaitHandlers {
+            on_success: on_success.unwrap_or_else(GenericStateUpdate::successful),
+            on_error,
+            output,
+        })
+    }
+
+    /// Compute the next state of a command, given the final state of the sub-operation it awaits
+    ///
+    /// - The next status is given by `on_success` or `on_error` depending on the sub-operation status.
+    /// - On error, the sub-operation failure reason is used if no reason is given by `on_error`.
+    /// - The `output` template, if any, is populated with values extracted from the sub-operation state
+    ///   and injected into the command payload.
+    pub fn resume(
+        &self,
+        command: GenericCommandState,
+        sub_command: &GenericCommandState,
+    ) -> GenericCommandState {
+        let command = match &self.output {
+            None => command,
+            Some(template) => {
+                let status = command.status.clone();
+                let output = sub_command.inject_values_into_template(template);
+                command.update_with_json(output).move_to(status)
+            }
+        };
+
+        if sub_command.status == "successful" {
+            return command.update(self.on_success.clone());
+        }
+
+        let sub_operation = sub_command.operation().unwrap_or_default();
+        let reason = sub_command
+            .failure_reason()
+            .unwrap_or_else(|| format!("{sub_operation} sub-operation failed"));
+        let update = match &self.on_error {
+            None => GenericStateUpdate::failed(reason),
+            Some(update) if update.reason.is_none() => GenericStateUpdate {
+                status: update.status.clone(),
+                reason: Some(reason),
+            },
+            Some(update) => update.clone(),
+        };
+        command.update(update)
+    }
+}
+
 /// Define default handlers for all state of an operation workflow
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct DefaultHandlers {
@@ -515,6 +724,89 @@ on_exit._ = "oops"
         }
     }
 
+    #[test]
+    fn failed_attempts_are_retried_up_to_the_retry_limit() {
+        let file = r#"
+script = "sh -c 'exit 2'"
+retries = 2
+on_exit.3 = "unexpected"
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        let state = GenericCommandState {
+            topic: mqtt_channel::Topic::new_unchecked("te/device/main///cmd/download/123"),
+            status: "download".to_string(),
+            payload: json!({"status": "download"}),
+        };
+        assert_eq!(state.attempt(), 1);
+
+        let state = state.update_with_script_output(
+            script.command.clone(),
+            script.output(),
+            handlers.clone(),
+        );
+        assert_eq!(state.status, "download");
+        assert_eq!(state.attempt(), 2);
+        assert_eq!(
+            state.payload["retry"],
+            json!({"state": "download", "attempt": 2})
+        );
+
+        let state = state.update_with_script_output(
+            script.command.clone(),
+            script.output(),
+            handlers.clone(),
+        );
+        assert_eq!(state.status, "download");
+        assert_eq!(state.attempt(), 3);
+
+        let state =
+            state.update_with_script_output(script.command.clone(), script.output(), handlers);
+        assert_eq!(state.status, "failed");
+        assert_eq!(state.payload.get("retry"), None);
+    }
+
+    #[test]
+    fn only_the_retry_on_exit_codes_trigger_a_retry() {
+        let file = r#"
+script = "sh -c 'exit 3'"
+retries = 2
+retry_on = [1, 2]
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        assert!(!handlers.should_retry(&script.output(), 1));
+
+        let file = r#"
+script = "sh -c 'exit 2'"
+retries = 2
+retry_on = [1, 2]
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        assert!(handlers.should_retry(&script.output(), 1));
+        assert!(handlers.should_retry(&script.output(), 2));
+        assert!(!handlers.should_retry(&script.output(), 3));
+    }
+
+    #[test]
+    fn exit_codes_with_a_specific_handler_are_not_retried_by_default() {
+        let file = r#"
+script = "sh -c 'exit 3'"
+retries = 2
+on_exit.3 = "skip"
+        "#;
+        let (script, handlers) = script_from_toml(file);
+        assert!(!handlers.should_retry(&script.output(), 1));
+    }
+
+    #[test]
+    fn retry_delay_is_doubled_on_each_attempt() {
+        let retry = RetryPolicy::try_new(10, Some(Duration::from_secs(5)), vec![]).unwrap();
+        assert_eq!(retry.delay(1), Duration::ZERO);
+        assert_eq!(retry.delay(2), Duration::from_secs(5));
+        assert_eq!(retry.delay(3), Duration::from_secs(10));
+        assert_eq!(retry.delay(4), Duration::from_secs(20));
+        assert_eq!(retry.delay(100), RetryPolicy::MAX_BACKOFF);
+    }
+
     fn script_from_toml(file: &str) -> (ShellScript, ExitHandlers) {
         if let OperationAction::Script(script, handlers) =
             toml::from_str(file).expect("Expect TOML input")
@@ -1,4 +1,5 @@
 use crate::workflow::ExitHandlers;
+use crate::workflow::TopicName;
 use crate::workflow::WorkflowExecutionError;
 use mqtt_channel::Message;
 use mqtt_channel::QoS::AtLeastOnce;
@@ -7,6 +8,27 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
 use serde_json::Value;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Payload property used to record the attempt counter of a step with a retry policy
+const RETRY: &str = "retry";
+
+/// Channel, appended to the command topic, used to request the cancellation of a pending command
+const CANCEL: &str = "cancel";
+
+/// Status of a command awaiting its turn, when the concurrency limit of its operation is reached
+pub const QUEUED: &str = "queued";
+
+/// State where a command awaits the opening of its maintenance window
+pub const SCHEDULED: &str = "scheduled";
+
+/// Payload property giving the time before which a scheduled command must not be executed
+const SCHEDULED_AT: &str = "scheduled_at";
+
+/// Payload property giving the time after which a scheduled command must no more be started
+const EXPIRES_AT: &str = "expires_at";
 
 /// Generic command state that can be used to manipulate any type of command payload.
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
@@ -76,14 +98,59 @@ impl GenericCommandState {
     }
 
     /// Update the command state with the outcome of a script
+    ///
+    /// If the script failed and its retry policy allows a new attempt,
+    /// the command stays in the same state with an incremented attempt counter.
     pub fn update_with_script_output(
         self,
         script: String,
         output: std::io::Result<std::process::Output>,
         handlers: ExitHandlers,
     ) -> Self {
+        if handlers.should_retry(&output, self.attempt()) {
+            return self.retry();
+        }
         let json_update = handlers.state_update(&script, output);
-        self.update_with_json(json_update)
+        self.clear_attempts().update_with_json(json_update)
+    }
+
+    /// Return the attempt number of the current step, starting at 1
+    ///
+    /// The attempt counter is recorded in the command payload as `retry = { state, attempt }`,
+    /// so a sequence of retries can be resumed after a restart.
+    pub fn attempt(&self) -> u32 {
+        match self.payload.get(RETRY) {
+            Some(retry)
+                if retry.get("state").and_then(Value::as_str) == Some(self.status.as_str()) =>
+            {
+                retry
+                    .get("attempt")
+                    .and_then(Value::as_u64)
+                    .and_then(|attempt| u32::try_from(attempt).ok())
+                    .unwrap_or(1)
+            }
+            _ => 1,
+        }
+    }
+
+    /// Schedule a new attempt of the current step, incrementing the attempt counter
+    pub fn retry(mut self) -> Self {
+        let attempt = self.attempt() + 1;
+        if let Some(properties) = self.payload.as_object_mut() {
+            properties.insert(
+                RETRY.to_string(),
+                json!({ "state": self.status, "attempt": attempt }),
+            );
+        }
+        self
+    }
+
+    /// Remove the attempt counter, when moving to a new step
+    fn clear_attempts(mut self) -> Self {
+        if let Some(properties) = self.payload.as_object_mut() {
+            properties.remove(RETRY);
+        }
+        self
     }
 
     /// Update the command state with a new status describing the next state
@@ -148,6 +215,50 @@ impl GenericCommandState {
             .unwrap_or_else(|| script_parameter.to_string())
     }
 
+    /// Inject values extracted from the message payload into a JSON template
+    ///
+    /// Any string of the template that is a `${...}` pattern is substituted
+    /// with the JSON value at that path, as for [GenericCommandState::inject_parameter],
+    /// but without converting this value into a string.
+    ///
+    /// `{ "list": "${.payload.updateList}" }` -> `{ "list": [ ... ] }`
+    pub fn inject_values_into_template(&self, template: &Value) -> Value {
+        match template {
+            Value::String(pattern) => pattern
+                .strip_prefix("${")
+                .and_then(|s| s.strip_suffix('}'))
+                .and_then(|path| self.extract_value(path))
+                .unwrap_or_else(|| template.clone()),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.inject_values_into_template(item))
+                    .collect(),
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), self.inject_values_into_template(v)))
+                    .collect(),
+            ),
+            _ => template.clone(),
+        }
+    }
+
+    pub(crate) fn extract_value(&self, path: &str) -> Option<Value> {
+        match path {
+            "." => Some(json!({
+                "topic": self.topic.name,
+                "payload": self.payload
+            })),
+            ".payload" => Some(self.payload.clone()),
+            path => match path.strip_prefix(".payload.") {
+                Some(path) => json_value_excerpt(&self.payload, path).cloned(),
+                None => self.extract(path).map(Value::String),
+            },
+        }
+    }
+
     fn extract(&self, path: &str) -> Option<String> {
         match path {
             "." => Some(
@@ -189,9 +300,108 @@ impl GenericCommandState {
         }
     }
 
+    /// Return the topic of the sub-operation command that this command triggers for the given operation
+    ///
+    /// The sub-operation command is published for the same target,
+    /// using a command id derived from the operation and id of this command:
+    /// `te/device/main///cmd/software_update/sub:firmware_update:123`
+    pub fn sub_command_topic(&self, sub_operation: &str) -> Option<Topic> {
+        match self.topic.name.split('/').collect::<Vec<&str>>()[..] {
+            [root, t1, t2, t3, t4, "cmd", operation, cmd_id] => Topic::new(&format!(
+                "{root}/{t1}/{t2}/{t3}/{t4}/cmd/{sub_operation}/sub:{operation}:{cmd_id}"
+            ))
+            .ok(),
+            _ => None,
+        }
+    }
+
     pub fn is_terminal(&self) -> bool {
         matches!(self.status.as_str(), "successful" | "failed")
     }
+
+    /// Build a request to cancel the pending command published on the given topic
+    ///
+    /// Such a request is not published on the command topic, but on a dedicated `cancel` channel,
+    /// e.g. `te/device/main///cmd/restart/123/cancel`, and is not retained.
+    /// Hence, the cancel requests are only seen by the agent,
+    /// which is the only one to update the command accordingly.
+    pub fn cancel_request(topic: Topic, reason: Option<String>) -> Message {
+        let topic = Topic::new_unchecked(&format!("{}/{CANCEL}", topic.name));
+        let payload = match reason {
+            None => json!({}),
+            Some(reason) => json!({ "reason": reason }),
+        };
+        Message::new(&topic, payload.to_string()).with_qos(AtLeastOnce)
+    }
+
+    /// Extract from a cancel request the topic of the command to be cancelled and the cancellation reason
+    ///
+    /// Return `None` if the message is not a cancel request.
+    pub fn parse_cancel_request(message: &Message) -> Option<(TopicName, Option<String>)> {
+        let topic = message.topic.name.strip_suffix(CANCEL)?.strip_suffix('/')?;
+        let reason = serde_json::from_slice::<Value>(message.payload_bytes())
+            .ok()
+            .and_then(|payload| payload.get("reason")?.as_str().map(str::to_string));
+        Some((topic.to_string(), reason))
+    }
+
+    /// Tell if this command is awaiting its turn to be executed
+    pub fn is_queued(&self) -> bool {
+        self.status == QUEUED
+    }
+
+    /// Return how long a command has to wait in the `scheduled` state for its maintenance window to open
+    ///
+    /// The window is given by two optional RFC3339 timestamps of the command payload:
+    /// `scheduled_at` and `expires_at`.
+    /// A zero delay is returned for a command that is not `scheduled` or whose window is open,
+    /// and an error for a command whose window has closed.
+    pub fn schedule_delay(&self, now: OffsetDateTime) -> Result<Duration, WorkflowExecutionError> {
+        if self.status != SCHEDULED {
+            return Ok(Duration::ZERO);
+        }
+
+        if let Some(expires_at) = self.timestamp_property(EXPIRES_AT)? {
+            if now > expires_at {
+                return Err(WorkflowExecutionError::MissedSchedule {
+                    expires_at: GenericCommandState::extract_text_property(
+                        &self.payload,
+                        EXPIRES_AT,
+                    )
+                    .unwrap_or_default(),
+                });
+            }
+        }
+
+        match self.timestamp_property(SCHEDULED_AT)? {
+            Some(scheduled_at) if scheduled_at > now => Ok((scheduled_at - now).unsigned_abs()),
+            _ => Ok(Duration::ZERO),
+        }
+    }
+
+    /// Tell if this command is `scheduled` and waiting for its maintenance window to open
+    ///
+    /// Such a command is not under execution and doesn't count against the concurrency limit.
+    pub fn is_awaiting_schedule(&self, now: OffsetDateTime) -> bool {
+        matches!(self.schedule_delay(now), Ok(delay) if !delay.is_zero())
+    }
+
+    fn timestamp_property(
+        &self,
+        property: &str,
+    ) -> Result<Option<OffsetDateTime>, WorkflowExecutionError> {
+        let Some(value) = self.payload.get(property) else {
+            return Ok(None);
+        };
+        value
+            .as_str()
+            .and_then(|text| OffsetDateTime::parse(text, &Rfc3339).ok())
+            .map(Some)
+            .ok_or_else(|| WorkflowExecutionError::InvalidSchedule {
+                property: property.to_string(),
+                value: value.to_string(),
+            })
+    }
 }
 
 impl GenericStateUpdate {
@@ -271,6 +481,16 @@ fn json_excerpt(value: &Value, path: &str) -> Option<String> {
     }
 }
 
+fn json_value_excerpt<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    match path.split_once('.') {
+        None if path.is_empty() => Some(value),
+        None => value.get(path),
+        Some((key, path)) => value
+            .get(key)
+            .and_then(|value| json_value_excerpt(value, path)),
+    }
+}
+
 fn json_as_string(value: &Value) -> String {
     match value {
         Value::Null => "null".to_string(),
@@ -286,6 +506,7 @@ mod tests {
     use super::*;
     use mqtt_channel::Topic;
     use serde_json::json;
+    use time::macros::datetime;
 
     #[test]
     fn serde_generic_command_payload() {
@@ -402,6 +623,98 @@ mod tests {
         );
     }
 
+    #[test]
+    fn inject_json_values_into_template() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/make_it/123");
+        let payload = r#"{ "status":"init", "foo":42, "bar": { "extra": [1,2,3] }}"#;
+        let command = mqtt_channel::Message::new(&topic, payload);
+        let cmd = GenericCommandState::from_command_message(&command)
+            .expect("parsing error")
+            .expect("no message");
+
+        let template = json!({
+            "operation": "${.topic.operation}",
+            "foo": "${.payload.foo}",
+            "extra": "${.payload.bar.extra}",
+            "nested": ["${.payload.bar}", "constant"],
+            "unknown": "${.payload.unknown}",
+        });
+        assert_eq!(
+            cmd.inject_values_into_template(&template),
+            json!({
+                "operation": "make_it",
+                "foo": 42,
+                "extra": [1,2,3],
+                "nested": [{ "extra": [1,2,3] }, "constant"],
+                "unknown": "${.payload.unknown}",
+            })
+        );
+    }
+
+    #[test]
+    fn sub_command_topic() {
+        let topic = Topic::new_unchecked("te/device/child///cmd/firmware_update/123");
+        let cmd = GenericCommandState {
+            topic,
+            status: "init".to_string(),
+            payload: json!({"status": "init"}),
+        };
+        assert_eq!(
+            cmd.sub_command_topic("restart"),
+            Some(Topic::new_unchecked(
+                "te/device/child///cmd/restart/sub:firmware_update:123"
+            ))
+        );
+    }
+
+    #[test]
+    fn scheduled_commands_await_their_maintenance_window() {
+        let now = datetime!(2026-10-18 10:00 UTC);
+        let cmd = |payload: Value| GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/software_update/123"),
+            status: payload["status"].as_str().unwrap().to_string(),
+            payload,
+        };
+
+        let before_window = cmd(json!({
+            "status": "scheduled",
+            "scheduled_at": "2026-10-18T12:00:00Z",
+            "expires_at": "2026-10-18T14:00:00Z",
+        }));
+        assert_eq!(
+            before_window.schedule_delay(now).unwrap(),
+            Duration::from_secs(2 * 3600)
+        );
+
+        let in_window = cmd(json!({
+            "status": "scheduled",
+            "scheduled_at": "2026-10-18T09:00:00Z",
+        }));
+        assert_eq!(in_window.schedule_delay(now).unwrap(), Duration::ZERO);
+
+        let not_scheduled = cmd(json!({
+            "status": "executing",
+            "scheduled_at": "2026-10-18T12:00:00Z",
+        }));
+        assert_eq!(not_scheduled.schedule_delay(now).unwrap(), Duration::ZERO);
+
+        let after_window = cmd(json!({
+            "status": "scheduled",
+            "scheduled_at": "2026-10-18T08:00:00Z",
+            "expires_at": "2026-10-18T09:00:00Z",
+        }));
+        assert_eq!(
+            after_window.schedule_delay(now).unwrap_err().to_string(),
+            "The maintenance window closed at 2026-10-18T09:00:00Z"
+        );
+
+        let ill_formed = cmd(json!({
+            "status": "scheduled",
+            "scheduled_at": "tomorrow",
+        }));
+        assert!(ill_formed.schedule_delay(now).is_err());
+    }
+
     trait JsonContent {
         fn to_json(self) -> Value;
     }
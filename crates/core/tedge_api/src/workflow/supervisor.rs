@@ -2,6 +2,8 @@ use crate::workflow::*;
 use log::info;
 use on_disk::OnDiskCommandBoard;
 use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
 
 /// Dispatch actions to operation participants
 #[derive(Default)]
@@ -11,6 +13,12 @@ pub struct WorkflowSupervisor {
 
     /// Operation instances under execution
     commands: CommandBoard,
+
+    /// The state transitions of the pending and latest completed commands
+    history: CommandHistory,
+
+    /// The topics of the queued commands that have been started but whose MQTT echo is still expected
+    started_commands: HashSet<String>,
 }
 
 impl WorkflowSupervisor {
@@ -56,6 +64,21 @@ impl WorkflowSupervisor {
         self.commands = commands
     }
 
+    /// The history of the pending and latest completed commands
+    pub fn command_history(&self) -> &CommandHistory {
+        &self.history
+    }
+
+    /// The history of the commands, to persist its latest updates
+    pub fn command_history_mut(&mut self) -> &mut CommandHistory {
+        &mut self.history
+    }
+
+    /// Restore the command history
+    pub fn load_command_history(&mut self, history: CommandHistory) {
+        self.history = history
+    }
+
     /// List the capabilities provided by the registered workflows
     pub fn capability_messages(&self, schema: &MqttSchema, target: &EntityTopicId) -> Vec<Message> {
         // To ease testing the capability messages are emitted in a deterministic order
@@ -84,11 +107,25 @@ impl WorkflowSupervisor {
             None => {
                 // The command has been cleared
                 self.commands.remove(&message.topic.name);
+                self.history.record_clearing(&message.topic.name);
                 Ok(None)
             }
             Some(command_state) if command_state.status == "init" => {
+                if self.started_commands.remove(&command_state.topic.name)
+                    && self.commands.get(&command_state.topic.name) == Some(&command_state)
+                {
+                    // This is the echo of a queued command that has been started
+                    return Ok(None);
+                }
+
                 // This is a new command request
                 self.commands.insert(command_state.clone())?;
+                self.history.record_state(&command_state);
+                if self.has_reached_concurrency_limit(operation, &command_state.topic.name) {
+                    let queued_command = command_state.move_to(QUEUED.to_string());
+                    self.apply_internal_update(queued_command.clone())?;
+                    return Ok(Some(queued_command));
+                }
                 Ok(Some(command_state))
             }
             Some(_) => {
@@ -103,6 +140,109 @@ impl WorkflowSupervisor {
         }
     }
 
+    /// Update the state of the command board on reception of a sub-operation state
+    ///
+    /// Return the new state of the invoking command, if the sub-operation has been completed
+    /// and the invoking command is awaiting its completion.
+    pub fn apply_sub_command_update(
+        &mut self,
+        message: &Message,
+    ) -> Result<Option<GenericCommandState>, WorkflowExecutionError> {
+        let Some(invoking_topic) = self.commands.invoking_command_topic(&message.topic.name) else {
+            return Ok(None);
+        };
+        let invoking_topic = invoking_topic.clone();
+
+        let Some(sub_command) = GenericCommandState::from_command_message(message)? else {
+            // The sub-operation command has been cleared
+            self.commands.remove(&message.topic.name);
+            self.history.record_clearing(&message.topic.name);
+            return Ok(None);
+        };
+
+        // The sub-operation might be executed by another process: its state has to be tracked here
+        self.commands.update(sub_command.clone())?;
+        self.history.record_state(&sub_command);
+        if !sub_command.is_terminal() {
+            return Ok(None);
+        }
+
+        let Some(invoking_command) = self.commands.get(&invoking_topic).cloned() else {
+            return Ok(None);
+        };
+        match self.get_action(&invoking_command)? {
+            OperationAction::AwaitOperationCompletion(handlers) => {
+                // The sub-operation command is no more pending: it has to be cleared by the invoking command
+                self.commands.remove(&message.topic.name);
+                self.history.record_clearing(&message.topic.name);
+                Ok(Some(handlers.resume(invoking_command, &sub_command)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Create a sub-operation command on behalf of a command under execution
+    ///
+    /// Return the initial state of the sub-operation command, which is registered as pending,
+    /// with a link to the invoking command.
+    pub fn start_sub_command(
+        &mut self,
+        invoking_command: &GenericCommandState,
+        sub_operation: &OperationType,
+        input: Value,
+    ) -> Result<GenericCommandState, WorkflowExecutionError> {
+        let topic = invoking_command
+            .sub_command_topic(&sub_operation.to_string())
+            .ok_or_else(|| WorkflowExecutionError::InvalidCmdTopic {
+                topic: invoking_command.topic.name.clone(),
+            })?;
+        let payload = match input {
+            Value::Object(_) => input,
+            _ => Value::Object(Default::default()),
+        };
+        let sub_command = GenericCommandState {
+            topic,
+            status: "init".to_string(),
+            payload,
+        }
+        .move_to("init".to_string());
+
+        self.commands
+            .insert_sub_command(&invoking_command.topic.name, sub_command.clone())?;
+        self.history.record_state(&sub_command);
+        Ok(sub_command)
+    }
+
+    /// Process a request to cancel a pending command
+    ///
+    /// Return the new state of the cancelled command, i.e. `failed` with a cancellation reason.
+    /// Nothing is returned if the message is not a cancel request
+    /// or if the command is unknown or has already reached a terminal state.
+    pub fn apply_cancel_request(&self, message: &Message) -> Option<GenericCommandState> {
+        let (topic, reason) = GenericCommandState::parse_cancel_request(message)?;
+        let command = self.commands.get(&topic)?;
+        if command.is_terminal() {
+            return None;
+        }
+
+        let reason = match reason {
+            None => "Cancelled".to_string(),
+            Some(reason) => format!("Cancelled: {reason}"),
+        };
+        Some(command.clone().fail_with(reason))
+    }
+
+    /// Tell if a state has been superseded by a terminal state, as when a command has been cancelled
+    ///
+    /// This is notably the case of the late responses sent by the builtin operation actors,
+    /// which keep running when the command they are processing is cancelled.
+    pub fn is_outdated(&self, command_state: &GenericCommandState) -> bool {
+        match self.commands.get(&command_state.topic.name) {
+            Some(current) => current.is_terminal() && current != command_state,
+            None => false,
+        }
+    }
+
     /// Return the action to be performed on a given command state
     pub fn get_action(
         &self,
@@ -129,15 +269,110 @@ impl WorkflowSupervisor {
         &mut self,
         new_command_state: GenericCommandState,
     ) -> Result<(), WorkflowExecutionError> {
+        self.history.record_state(&new_command_state);
         self.commands.update(new_command_state)
     }
 
+    /// Start the queued commands, as far as the concurrency limits of their operations allow
+    ///
+    /// Return the started commands, moved back to their `init` state.
+    pub fn start_queued_commands(&mut self) -> Vec<GenericCommandState> {
+        let now = time::OffsetDateTime::now_utc();
+        let mut started_commands = vec![];
+        for workflow in self.workflows.values() {
+            let Some(max_concurrent) = workflow.max_concurrent else {
+                continue;
+            };
+            let operation = workflow.operation.to_string();
+            let mut running = 0;
+            let mut queued = vec![];
+            for (timestamp, command) in self.commands.iter() {
+                if command.operation().as_ref() != Some(&operation) || command.is_terminal() {
+                    continue;
+                }
+                if command.is_queued() {
+                    queued.push((*timestamp, command.clone()));
+                } else if !command.is_awaiting_schedule(now) {
+                    running += 1;
+                }
+            }
+
+            queued.sort_by(|(t1, c1), (t2, c2)| (t1, &c1.topic.name).cmp(&(t2, &c2.topic.name)));
+            if workflow.queue_order == QueueOrder::Lifo {
+                queued.reverse();
+            }
+            let available = max_concurrent.saturating_sub(running);
+            started_commands.extend(
+                queued
+                    .into_iter()
+                    .take(available)
+                    .map(|(_, command)| command.move_to("init".to_string())),
+            );
+        }
+
+        for command in started_commands.iter() {
+            self.started_commands.insert(command.topic.name.clone());
+            self.history.record_state(command);
+            let _ = self.commands.update(command.clone());
+        }
+        started_commands
+    }
+
+    /// Queue a scheduled command whose maintenance window opens while the concurrency limit is reached
+    ///
+    /// Return the queued state of the command, if it has to wait for the completion of other commands.
+    pub fn queue_over_concurrency_limit(
+        &mut self,
+        command: &GenericCommandState,
+    ) -> Result<Option<GenericCommandState>, WorkflowExecutionError> {
+        if command.status != SCHEDULED {
+            return Ok(None);
+        }
+        let Some(operation) = command.operation() else {
+            return Ok(None);
+        };
+        if !self.has_reached_concurrency_limit(&operation.as_str().into(), &command.topic.name) {
+            return Ok(None);
+        }
+        let queued_command = command.clone().move_to(QUEUED.to_string());
+        self.apply_internal_update(queued_command.clone())?;
+        Ok(Some(queued_command))
+    }
+
+    /// Tell if a new command has to be queued, because too many commands of the same operation are under execution
+    fn has_reached_concurrency_limit(&self, operation: &OperationType, topic: &str) -> bool {
+        let Some(max_concurrent) = self
+            .workflows
+            .get(operation)
+            .and_then(|workflow| workflow.max_concurrent)
+        else {
+            return false;
+        };
+        let operation = operation.to_string();
+        let now = time::OffsetDateTime::now_utc();
+        let running = self
+            .commands
+            .iter()
+            .filter(|(_, command)| {
+                command.topic.name != topic
+                    && command.operation().as_ref() == Some(&operation)
+                    && !command.is_terminal()
+                    && !command.is_awaiting_schedule(now)
+            })
+            .count();
+        running >= max_concurrent
+    }
+
     /// Resume the given command when the agent is restarting after an interruption
     pub fn resume_command(
         &self,
         _timestamp: &Timestamp,
         command: &GenericCommandState,
     ) -> Option<GenericCommandState> {
+        if command.is_queued() {
+            // Queued commands are started along the completion of the commands under execution
+            return None;
+        }
         let Ok(action) = self.get_action(command) else {
             return None;
         };
@@ -166,14 +401,24 @@ pub struct CommandBoard {
     /// TODO: use the timestamp to mark faulty any request making no progress
     #[serde(flatten)]
     commands: HashMap<TopicName, (Timestamp, GenericCommandState)>,
+
+    /// For each sub-operation command, the topic of the command that triggered it
+    #[serde(skip)]
+    sub_commands: HashMap<TopicName, TopicName>,
 }
 
 pub type TopicName = String;
 pub type Timestamp = time::OffsetDateTime;
 
 impl CommandBoard {
-    pub fn new(commands: HashMap<TopicName, (Timestamp, GenericCommandState)>) -> Self {
-        CommandBoard { commands }
+    pub fn new(
+        commands: HashMap<TopicName, (Timestamp, GenericCommandState)>,
+        sub_commands: HashMap<TopicName, TopicName>,
+    ) -> Self {
+        CommandBoard {
+            commands,
+            sub_commands,
+        }
     }
 
     /// Iterate over the pending commands
@@ -181,6 +426,29 @@ impl CommandBoard {
         self.commands.values()
     }
 
+    /// Get the current state of a pending command
+    pub fn get(&self, topic_name: &str) -> Option<&GenericCommandState> {
+        self.commands.get(topic_name).map(|(_, command)| command)
+    }
+
+    /// Return the topic of the command that triggered the given sub-operation command, if any
+    pub fn invoking_command_topic(&self, topic_name: &str) -> Option<&TopicName> {
+        self.sub_commands.get(topic_name)
+    }
+
+    /// Insert a new sub-operation request into the [CommandBoard], linking it to its invoking command
+    pub fn insert_sub_command(
+        &mut self,
+        invoking_topic: &str,
+        sub_command: GenericCommandState,
+    ) -> Result<(), WorkflowExecutionError> {
+        let sub_topic = sub_command.topic.name.clone();
+        self.insert(sub_command)?;
+        self.sub_commands
+            .insert(sub_topic, invoking_topic.to_string());
+        Ok(())
+    }
+
     /// Insert a new operation request into the [CommandBoard]
     ///
     /// Reject the request if there is already an entry with the same command id, but in a different state
@@ -224,5 +492,438 @@ impl CommandBoard {
     /// Remove from the board an operation request
     pub fn remove(&mut self, topic_name: &String) {
         self.commands.remove(topic_name);
+        self.sub_commands.remove(topic_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    #[test]
+    fn resume_invoking_command_on_sub_operation_completion() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "firmware_update"
+
+[init]
+operation = "restart"
+input.reason = "${.payload.version}"
+on_exec = "restarting"
+
+[restarting]
+action = "await-operation-completion"
+output.restarted_at = "${.payload.at}"
+on_success = "successful"
+"#,
+        )
+        .unwrap();
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor.register_custom_workflow(workflow).unwrap();
+        supervisor
+            .register_builtin_workflow(OperationType::Restart)
+            .unwrap();
+
+        let topic = Topic::new_unchecked("te/device/main///cmd/firmware_update/123");
+        let request = Message::new(&topic, r#"{"status":"init", "version":"1.0"}"#);
+        let command = supervisor
+            .apply_external_update(&"firmware_update".into(), &request)
+            .unwrap()
+            .unwrap();
+
+        let OperationAction::Operation(sub_operation, input, handlers) =
+            supervisor.get_action(&command).unwrap()
+        else {
+            panic!("Expect a sub-operation action")
+        };
+        let sub_command = supervisor
+            .start_sub_command(&command, &sub_operation, input)
+            .unwrap();
+        assert_eq!(
+            sub_command.topic.name,
+            "te/device/main///cmd/restart/sub:firmware_update:123"
+        );
+        assert_eq!(
+            sub_command.payload,
+            json!({"status":"init", "reason":"1.0"})
+        );
+
+        // The MQTT echo of the sub-operation request triggers its execution,
+        // even if the restart operation is subject to a concurrency limit
+        assert_eq!(
+            supervisor
+                .apply_external_update(&OperationType::Restart, &sub_command.clone().into_message())
+                .unwrap(),
+            Some(sub_command.clone())
+        );
+        supervisor
+            .apply_internal_update(command.update(handlers.on_exec))
+            .unwrap();
+
+        // The link between the commands is persisted
+        let board: CommandBoard =
+            serde_json::from_str(&serde_json::to_string(supervisor.pending_commands()).unwrap())
+                .unwrap();
+        assert_eq!(
+            board.invoking_command_topic(&sub_command.topic.name),
+            Some(&topic.name)
+        );
+
+        // Intermediate states of the sub-operation are only recorded
+        let executing = Message::new(&sub_command.topic, r#"{"status":"executing"}"#);
+        assert_eq!(
+            supervisor.apply_sub_command_update(&executing).unwrap(),
+            None
+        );
+
+        // On completion of the sub-operation, the invoking command is resumed
+        let successful = Message::new(
+            &sub_command.topic,
+            r#"{"status":"successful", "at":"12:00"}"#,
+        );
+        let resumed_command = supervisor
+            .apply_sub_command_update(&successful)
+            .unwrap()
+            .unwrap();
+        assert_eq!(resumed_command.status, "successful");
+        assert_eq!(
+            resumed_command.payload,
+            json!({"status":"successful", "version":"1.0", "restarted_at":"12:00"})
+        );
+        assert_eq!(
+            supervisor.pending_commands().get(&sub_command.topic.name),
+            None
+        );
+    }
+
+    #[test]
+    fn retry_attempts_are_resumed_after_a_restart() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "download"
+
+[init]
+script = "/some/download.sh ${.payload.url}"
+retries = 3
+on_success = "successful"
+"#,
+        )
+        .unwrap();
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_custom_workflow(workflow.clone())
+            .unwrap();
+
+        let topic = Topic::new_unchecked("te/device/main///cmd/download/123");
+        let request = Message::new(&topic, r#"{"status":"init", "url":"http://x"}"#);
+        let command = supervisor
+            .apply_external_update(&"download".into(), &request)
+            .unwrap()
+            .unwrap();
+        supervisor
+            .apply_internal_update(command.retry().retry())
+            .unwrap();
+
+        // The attempt counter is persisted along the command state
+        let board: CommandBoard =
+            serde_json::from_str(&serde_json::to_string(supervisor.pending_commands()).unwrap())
+                .unwrap();
+        let mut restarted_supervisor = WorkflowSupervisor::default();
+        restarted_supervisor
+            .register_custom_workflow(workflow)
+            .unwrap();
+        restarted_supervisor.load_pending_commands(board);
+
+        let (timestamp, command) = restarted_supervisor
+            .pending_commands()
+            .iter()
+            .next()
+            .cloned()
+            .unwrap();
+        let resumed_command = restarted_supervisor
+            .resume_command(&timestamp, &command)
+            .unwrap();
+        assert_eq!(resumed_command.status, "init");
+        assert_eq!(resumed_command.attempt(), 3);
+    }
+
+    #[test]
+    fn cancel_pending_command() {
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_builtin_workflow(OperationType::Restart)
+            .unwrap();
+
+        let topic = Topic::new_unchecked("te/device/main///cmd/restart/123");
+        let request = Message::new(&topic, r#"{"status":"init"}"#);
+        let command = supervisor
+            .apply_external_update(&OperationType::Restart, &request)
+            .unwrap()
+            .unwrap();
+        let executing = command.move_to("executing".to_string());
+        supervisor.apply_internal_update(executing.clone()).unwrap();
+
+        let cancel =
+            GenericCommandState::cancel_request(topic.clone(), Some("too late".to_string()));
+        assert_eq!(cancel.topic.name, "te/device/main///cmd/restart/123/cancel");
+        assert_eq!(supervisor.apply_cancel_request(&request), None);
+        let cancelled = supervisor.apply_cancel_request(&cancel).unwrap();
+        assert_eq!(cancelled.status, "failed");
+        assert_eq!(
+            cancelled.failure_reason(),
+            Some("Cancelled: too late".to_string())
+        );
+
+        supervisor.apply_internal_update(cancelled.clone()).unwrap();
+        assert!(supervisor.is_outdated(&executing));
+        assert!(supervisor.is_outdated(&executing.clone().move_to("successful".to_string())));
+        assert!(!supervisor.is_outdated(&cancelled));
+
+        // A command that is no more pending cannot be cancelled
+        assert_eq!(supervisor.apply_cancel_request(&cancel), None);
+
+        let record = supervisor.command_history().find("123").unwrap();
+        let statuses: Vec<&str> = record
+            .transitions
+            .iter()
+            .map(|t| t.status.as_str())
+            .collect();
+        assert_eq!(statuses, vec!["init", "executing", "failed"]);
+        assert_eq!(record.reason, Some("Cancelled: too late".to_string()));
+    }
+
+    #[test]
+    fn built_in_software_updates_are_queued_while_one_is_running() {
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_builtin_workflow(OperationType::SoftwareUpdate)
+            .unwrap();
+        supervisor
+            .register_builtin_workflow(OperationType::LogUpload)
+            .unwrap();
+
+        let request = |operation: &str, cmd_id: &str| {
+            Message::new(
+                &Topic::new_unchecked(&format!("te/device/main///cmd/{operation}/{cmd_id}")),
+                r#"{"status":"init"}"#,
+            )
+        };
+
+        let first = supervisor
+            .apply_external_update(
+                &OperationType::SoftwareUpdate,
+                &request("software_update", "1"),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.status, "init");
+        supervisor
+            .apply_internal_update(first.move_to("executing".to_string()))
+            .unwrap();
+
+        let second = supervisor
+            .apply_external_update(
+                &OperationType::SoftwareUpdate,
+                &request("software_update", "2"),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.status, "queued");
+
+        // The other built-in operations are not limited
+        for cmd_id in ["1", "2"] {
+            let command = supervisor
+                .apply_external_update(&OperationType::LogUpload, &request("log_upload", cmd_id))
+                .unwrap()
+                .unwrap();
+            assert_eq!(command.status, "init");
+        }
+    }
+
+    #[test]
+    fn queue_commands_beyond_concurrency_limit() {
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_custom_workflow(
+                OperationWorkflow::built_in(OperationType::SoftwareUpdate)
+                    .with_concurrency_limit(Some(1), QueueOrder::Fifo)
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let mut requests = vec![];
+        for cmd_id in ["1", "2", "3"] {
+            let topic =
+                Topic::new_unchecked(&format!("te/device/main///cmd/software_update/{cmd_id}"));
+            let request = Message::new(&topic, r#"{"status":"init"}"#);
+            requests.push(
+                supervisor
+                    .apply_external_update(&OperationType::SoftwareUpdate, &request)
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+        let statuses: Vec<&str> = requests.iter().map(|c| c.status.as_str()).collect();
+        assert_eq!(statuses, vec!["init", "queued", "queued"]);
+
+        // Nothing can be started while the first command is under execution
+        let executing = requests[0].clone().move_to("executing".to_string());
+        supervisor.apply_internal_update(executing.clone()).unwrap();
+        assert!(supervisor.start_queued_commands().is_empty());
+
+        // On completion, the oldest queued command is started
+        supervisor
+            .apply_internal_update(executing.move_to("successful".to_string()))
+            .unwrap();
+        let started = supervisor.start_queued_commands();
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].topic, requests[1].topic);
+        assert_eq!(started[0].status, "init");
+        assert!(supervisor.start_queued_commands().is_empty());
+
+        // The MQTT echo of the started command is ignored
+        assert_eq!(
+            supervisor
+                .apply_external_update(
+                    &OperationType::SoftwareUpdate,
+                    &started[0].clone().into_message()
+                )
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn commands_awaiting_their_maintenance_window_are_not_counted_as_running() {
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_custom_workflow(
+                OperationWorkflow::built_in(OperationType::SoftwareUpdate)
+                    .with_concurrency_limit(Some(1), QueueOrder::Fifo)
+                    .unwrap(),
+            )
+            .unwrap();
+
+        // A command scheduled for tomorrow
+        let tomorrow = (time::OffsetDateTime::now_utc() + time::Duration::days(1))
+            .format(&time::format_description::well_known::Rfc3339)
+            .unwrap();
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/later");
+        let request = Message::new(
+            &topic,
+            format!(r#"{{"status":"init", "scheduled_at":"{tomorrow}"}}"#),
+        );
+        let later = supervisor
+            .apply_external_update(&OperationType::SoftwareUpdate, &request)
+            .unwrap()
+            .unwrap();
+        let scheduled = later.move_to(SCHEDULED.to_string());
+        supervisor.apply_internal_update(scheduled.clone()).unwrap();
+
+        // doesn't prevent an immediate command to be executed
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/now");
+        let request = Message::new(&topic, r#"{"status":"init"}"#);
+        let now = supervisor
+            .apply_external_update(&OperationType::SoftwareUpdate, &request)
+            .unwrap()
+            .unwrap();
+        assert_eq!(now.status, "init");
+
+        // nor the queued commands to be started
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/next");
+        let request = Message::new(&topic, r#"{"status":"init"}"#);
+        let next = supervisor
+            .apply_external_update(&OperationType::SoftwareUpdate, &request)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.status, "queued");
+        supervisor
+            .apply_internal_update(now.move_to("successful".to_string()))
+            .unwrap();
+        let started = supervisor.start_queued_commands();
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].topic, next.topic);
+
+        // However, the scheduled command is queued if the concurrency limit is reached when its window opens
+        let queued = supervisor
+            .queue_over_concurrency_limit(&scheduled)
+            .unwrap()
+            .unwrap();
+        assert_eq!(queued.status, "queued");
+    }
+
+    #[test]
+    fn queued_commands_are_resumed_after_a_restart() {
+        let file = r#"
+operation = "firmware_update"
+max_concurrent = 1
+queue_order = "lifo"
+
+[init]
+action = "proceed"
+on_success = "successful"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        let operation = workflow.operation.clone();
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor
+            .register_custom_workflow(workflow.clone())
+            .unwrap();
+
+        for cmd_id in ["1", "2", "3"] {
+            let topic =
+                Topic::new_unchecked(&format!("te/device/main///cmd/firmware_update/{cmd_id}"));
+            let request = Message::new(&topic, r#"{"status":"init"}"#);
+            supervisor
+                .apply_external_update(&operation, &request)
+                .unwrap();
+        }
+
+        // Restart the supervisor, with its on-disk command board
+        let on_disk = serde_json::to_string(supervisor.pending_commands()).unwrap();
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor.register_custom_workflow(workflow).unwrap();
+        supervisor.load_pending_commands(serde_json::from_str(&on_disk).unwrap());
+
+        // Only the command under execution is resumed
+        let pending_commands: Vec<_> = supervisor.pending_commands().iter().cloned().collect();
+        let resumed: Vec<_> = pending_commands
+            .iter()
+            .filter_map(|(timestamp, command)| supervisor.resume_command(timestamp, command))
+            .collect();
+        assert_eq!(resumed.len(), 1);
+        assert_eq!(resumed[0].cmd_id(), Some("1".to_string()));
+
+        // On completion, the latest queued command is started first
+        supervisor
+            .apply_internal_update(resumed[0].clone().move_to("successful".to_string()))
+            .unwrap();
+        let started = supervisor.start_queued_commands();
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].cmd_id(), Some("3".to_string()));
+    }
+
+    #[test]
+    fn sub_operation_failure_reason_is_forwarded() {
+        let handlers = AwaitHandlers::try_new(None, None, None).unwrap();
+        let command = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/firmware_update/123"),
+            status: "restarting".to_string(),
+            payload: json!({"status":"restarting"}),
+        };
+        let sub_command = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/restart/sub:firmware_update:123"),
+            status: "failed".to_string(),
+            payload: json!({"status":"failed", "reason":"no reboot"}),
+        };
+
+        let resumed_command = handlers.resume(command, &sub_command);
+        assert_eq!(resumed_command.status, "failed");
+        assert_eq!(
+            resumed_command.failure_reason(),
+            Some("no reboot".to_string())
+        );
     }
 }
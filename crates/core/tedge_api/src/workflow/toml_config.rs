@@ -1,18 +1,27 @@
 use crate::mqtt_topics::OperationType;
+use crate::workflow::AwaitHandlers;
 use crate::workflow::BgExitHandlers;
 use crate::workflow::DefaultHandlers;
 use crate::workflow::ExitHandlers;
 use crate::workflow::GenericStateUpdate;
+use crate::workflow::JsonPathGuard;
 use crate::workflow::OperationAction;
 use crate::workflow::OperationWorkflow;
+use crate::workflow::Predicate;
+use crate::workflow::QueueOrder;
+use crate::workflow::RetryPolicy;
 use crate::workflow::ScriptDefinitionError;
 use crate::workflow::ShellScript;
+use crate::workflow::StateName;
+use crate::workflow::WhenClause;
 use crate::workflow::WorkflowDefinitionError;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
 use serde::Serializer;
+use serde_json::json;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
@@ -29,6 +38,14 @@ pub struct TomlOperationWorkflow {
     /// The operation to which this workflow applies
     pub operation: OperationType,
 
+    /// The maximum number of commands of this operation executed concurrently
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+
+    /// The order in which queued commands are started
+    #[serde(default)]
+    pub queue_order: QueueOrder,
+
     /// Default handlers used to determine the next state from an action outcome
     #[serde(flatten)]
     pub handlers: TomlExitHandlers,
@@ -45,6 +62,18 @@ pub struct TomlOperationState {
     #[serde(default, flatten)]
     pub action: TomlOperationAction,
 
+    /// Template used to build the initial payload of a sub-operation
+    #[serde(default)]
+    pub input: Option<Value>,
+
+    /// Template used to extract values from the state of a completed sub-operation
+    #[serde(default)]
+    pub output: Option<Value>,
+
+    /// Ordered clauses used to select the next state from the command state
+    #[serde(default)]
+    pub when: Vec<TomlWhenClause>,
+
     /// Handlers used to determine the next state from the action outcome
     #[serde(flatten)]
     pub handlers: TomlExitHandlers,
@@ -56,6 +85,7 @@ pub struct TomlOperationState {
 pub enum TomlOperationAction {
     Script(ShellScript),
     BackgroundScript(ShellScript),
+    Operation(OperationType),
     Action(String),
 }
 
@@ -78,6 +108,58 @@ pub enum TomlStateUpdate {
     Detailed(GenericStateUpdate),
 }
 
+/// User-friendly representation of a [WhenClause]
+///
+/// A clause is made of a `next` state and of an optional condition on the value found at some `path`.
+/// The condition is given by one and only one comparison:
+/// `equals`, `not_equals`, `greater_than`, `less_than` or `exists`.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlWhenClause {
+    path: Option<String>,
+    equals: Option<Value>,
+    not_equals: Option<Value>,
+    greater_than: Option<Value>,
+    less_than: Option<Value>,
+    exists: Option<bool>,
+    next: TomlStateUpdate,
+}
+
+impl TryFrom<TomlWhenClause> for WhenClause {
+    type Error = WorkflowDefinitionError;
+
+    fn try_from(input: TomlWhenClause) -> Result<Self, Self::Error> {
+        let next = input.next.into();
+        let mut predicates: Vec<Predicate> = [
+            input.equals.map(Predicate::Equals),
+            input.not_equals.map(Predicate::NotEquals),
+            input.greater_than.map(Predicate::GreaterThan),
+            input.less_than.map(Predicate::LessThan),
+            input.exists.map(Predicate::Exists),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        match (input.path, predicates.len()) {
+            (None, 0) => Ok(WhenClause { guard: None, next }),
+            (None, _) => Err(WorkflowDefinitionError::InvalidWhenClause {
+                reason: "a comparison is given but no path".to_string(),
+            }),
+            (Some(path), 0) => Err(WorkflowDefinitionError::InvalidWhenClause {
+                reason: format!("no comparison is given for {path}"),
+            }),
+            (Some(path), 1) => Ok(WhenClause {
+                guard: Some(JsonPathGuard::new(&path, predicates.remove(0))),
+                next,
+            }),
+            (Some(path), _) => Err(WorkflowDefinitionError::InvalidWhenClause {
+                reason: format!("more than one comparison is given for {path}"),
+            }),
+        }
+    }
+}
+
 impl From<TomlStateUpdate> for GenericStateUpdate {
     fn from(value: TomlStateUpdate) -> Self {
         match value {
@@ -94,6 +176,26 @@ impl TryFrom<TomlOperationState> for OperationAction {
     type Error = WorkflowDefinitionError;
 
     fn try_from(input: TomlOperationState) -> Result<Self, Self::Error> {
+        if !input.when.is_empty() {
+            if !matches!(&input.action, TomlOperationAction::Action(action) if action == "proceed")
+            {
+                return Err(WorkflowDefinitionError::InvalidWhenClause {
+                    reason: "'when' clauses can only be used with the 'proceed' action".to_string(),
+                });
+            }
+            let clauses = input
+                .when
+                .into_iter()
+                .map(WhenClause::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            let otherwise: GenericStateUpdate = input
+                .handlers
+                .on_success
+                .map(|u| u.into())
+                .unwrap_or_else(GenericStateUpdate::successful);
+            return Ok(OperationAction::Branch(clauses, otherwise));
+        }
+
         match input.action {
             TomlOperationAction::Script(script) => {
                 let handlers = TryInto::<ExitHandlers>::try_into(input.handlers)?;
@@ -103,6 +205,11 @@ impl TryFrom<TomlOperationState> for OperationAction {
                 let handlers = TryInto::<BgExitHandlers>::try_into(input.handlers)?;
                 Ok(OperationAction::BgScript(script, handlers))
             }
+            TomlOperationAction::Operation(operation) => {
+                let handlers = TryInto::<BgExitHandlers>::try_into(input.handlers)?;
+                let input = input.input.unwrap_or_else(|| json!({}));
+                Ok(OperationAction::Operation(operation, input, handlers))
+            }
             TomlOperationAction::Action(command) => match command.as_str() {
                 "builtin" => Ok(OperationAction::BuiltIn),
                 "cleanup" => Ok(OperationAction::Clear),
@@ -156,6 +263,12 @@ impl TryFrom<TomlOperationState> for OperationAction {
                         on_timeout,
                     })
                 }
+                "await-operation-completion" => {
+                    let on_success = input.handlers.on_success.map(|u| u.into());
+                    let on_error = input.handlers.on_error.map(|u| u.into());
+                    let handlers = AwaitHandlers::try_new(on_success, on_error, input.output)?;
+                    Ok(OperationAction::AwaitOperationCompletion(handlers))
+                }
                 _ => Err(WorkflowDefinitionError::UnknownAction { action: command }),
             },
         }
@@ -174,8 +287,71 @@ impl TryFrom<TomlOperationWorkflow> for OperationWorkflow {
             states.insert(state, action.with_default(&default_handlers));
         }
 
-        OperationWorkflow::try_new(operation, default_handlers, states)
+        let workflow = OperationWorkflow::try_new(operation, default_handlers, states)?
+            .with_concurrency_limit(input.max_concurrent, input.queue_order)?;
+        check_when_clauses(&workflow.states)?;
+        check_reachable_states(&workflow)?;
+        Ok(workflow)
+    }
+}
+
+/// Check that all the states of a workflow, but the terminal ones, can be reached from the `init` state
+fn check_reachable_states(workflow: &OperationWorkflow) -> Result<(), WorkflowDefinitionError> {
+    let transitions = workflow.transitions();
+    let reachable = OperationWorkflow::reachable_states(&transitions, ["init"]);
+    let unreachable: Vec<StateName> = workflow
+        .state_names()
+        .into_iter()
+        .filter(|state| !matches!(state.as_str(), "successful" | "failed"))
+        .filter(|state| !reachable.contains(state.as_str()))
+        .cloned()
+        .collect();
+
+    if unreachable.is_empty() {
+        Ok(())
+    } else {
+        Err(WorkflowDefinitionError::UnreachableStates {
+            states: unreachable,
+        })
+    }
+}
+
+/// Check that the `when` clauses of a workflow only refer to defined states and can all be applied
+fn check_when_clauses(
+    states: &HashMap<StateName, OperationAction>,
+) -> Result<(), WorkflowDefinitionError> {
+    let mut state_names: Vec<&StateName> = states.keys().collect();
+    state_names.sort();
+
+    for state in state_names {
+        let Some(OperationAction::Branch(clauses, otherwise)) = states.get(state) else {
+            continue;
+        };
+
+        let targets = clauses
+            .iter()
+            .map(|clause| &clause.next.status)
+            .chain(std::iter::once(&otherwise.status));
+        for target in targets {
+            if !states.contains_key(target) {
+                return Err(WorkflowDefinitionError::UnknownTargetState {
+                    state: state.clone(),
+                    target: target.clone(),
+                });
+            }
+        }
+
+        if let Some(index) = clauses.iter().position(|clause| clause.guard.is_none()) {
+            if index + 1 < clauses.len() {
+                return Err(WorkflowDefinitionError::UnreachableWhenClause {
+                    state: state.clone(),
+                    index: index + 1,
+                });
+            }
+        }
     }
+
+    Ok(())
 }
 
 /// User-Friendly representation of an [ExitHandlers]; as used in the operation TOML definition files
@@ -217,6 +393,34 @@ pub struct TomlExitHandlers {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     on_exec: Option<TomlStateUpdate>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retries: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backoff_second: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    retry_on: Vec<TomlExitCodes>,
+}
+
+/// User-friendly representation of [ExitCodes], given either as a number or as a string
+///
+/// `retry_on = [1, "3-5"]`
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum TomlExitCodes {
+    Code(u8),
+    Codes(ExitCodes),
+}
+
+impl From<TomlExitCodes> for ExitCodes {
+    fn from(value: TomlExitCodes) -> Self {
+        match value {
+            TomlExitCodes::Code(code) => ExitCodes::Code(code),
+            TomlExitCodes::Codes(codes) => codes,
+        }
+    }
 }
 
 impl TryFrom<TomlExitHandlers> for ExitHandlers {
@@ -244,10 +448,30 @@ impl TryFrom<TomlExitHandlers> for ExitHandlers {
             })
             .collect();
         let timeout = value.timeout_second.map(Duration::from_secs);
+        let retry = match value.retries {
+            None if value.backoff_second.is_some() || !value.retry_on.is_empty() => {
+                return Err(ScriptDefinitionError::MissingRetries)
+            }
+            None | Some(0) => None,
+            Some(retries) => {
+                let backoff = value.backoff_second.map(Duration::from_secs);
+                let retry_on = value
+                    .retry_on
+                    .into_iter()
+                    .map(|codes| match codes.into() {
+                        ExitCodes::Code(x) => (x, x),
+                        ExitCodes::Range { from, to } => (from, to),
+                        ExitCodes::AnyError => (1, u8::MAX),
+                    })
+                    .collect();
+                Some(RetryPolicy::try_new(retries, backoff, retry_on)?)
+            }
+        };
 
-        ExitHandlers::try_new(
+        Ok(ExitHandlers::try_new(
             on_exit, on_success, on_error, on_kill, on_stdout, wildcard, timeout,
-        )
+        )?
+        .with_retry_policy(retry))
     }
 }
 
@@ -383,6 +607,9 @@ on_kill = { status = "failed", reason = "killed"}         # next status when kil
                 on_timeout: None,
                 on_stdout: Vec::new(),
                 on_exec: None,
+                retries: None,
+                backoff_second: None,
+                retry_on: Vec::new(),
             }
         )
     }
@@ -489,6 +716,46 @@ on_stdout = ["other_successful_state_extracted_from_json"]
         assert_eq!(error, ScriptDefinitionError::DuplicatedOnStdoutHandler)
     }
 
+    #[test]
+    fn parse_retry_policy() {
+        let file = r#"
+retries = 3
+backoff_second = 10
+retry_on = [1, "3-5"]
+        "#;
+        let input: TomlExitHandlers = toml::from_str(file).unwrap();
+        let handlers = TryInto::<ExitHandlers>::try_into(input).unwrap();
+        assert_eq!(
+            handlers.retry_policy(),
+            Some(&RetryPolicy {
+                retries: 3,
+                backoff: Duration::from_secs(10),
+                retry_on: vec![(1, 1), (3, 5)],
+            })
+        );
+    }
+
+    #[test]
+    fn forbid_retry_handlers_without_retries() {
+        let file = r#"
+retry_on = [1]
+        "#;
+        let input: TomlExitHandlers = toml::from_str(file).unwrap();
+        let error = TryInto::<ExitHandlers>::try_into(input).unwrap_err();
+        assert_eq!(error, ScriptDefinitionError::MissingRetries)
+    }
+
+    #[test]
+    fn forbid_retry_on_success() {
+        let file = r#"
+retries = 3
+retry_on = ["0-2"]
+        "#;
+        let input: TomlExitHandlers = toml::from_str(file).unwrap();
+        let error = TryInto::<ExitHandlers>::try_into(input).unwrap_err();
+        assert_eq!(error, ScriptDefinitionError::RetryOnSuccess)
+    }
+
     #[test]
     fn default_handlers() {
         let file = "";
@@ -534,6 +801,247 @@ on_error = "failed_reboot"
         );
     }
 
+    #[test]
+    fn parse_concurrency_limit() {
+        let file = r#"
+operation = "firmware_update"
+max_concurrent = 2
+queue_order = "lifo"
+
+[init]
+action = "proceed"
+on_success = "successful"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(workflow.max_concurrent, Some(2));
+        assert_eq!(workflow.queue_order, QueueOrder::Lifo);
+        assert_eq!(workflow.states.len(), 3);
+
+        let file = r#"
+operation = "firmware_update"
+max_concurrent = 0
+
+[init]
+action = "proceed"
+on_success = "successful"
+"#;
+        let error = toml::from_str::<OperationWorkflow>(file).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("at least one command must be executed at a time"));
+    }
+
+    #[test]
+    fn parse_sub_operation_states() {
+        let file = r#"
+operation = "firmware_update"
+
+[init]
+action = "proceed"
+on_success = "install"
+
+[install]
+operation = "software_update"
+input.updateList = "${.payload.updateList}"
+on_exec = "awaiting_install"
+
+[awaiting_install]
+action = "await-operation-completion"
+output.installed = "${.payload.updateList}"
+on_success = "successful"
+on_error = { status = "failed", reason = "installation failed" }
+"#;
+
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(
+            workflow.states.get("install"),
+            Some(&OperationAction::Operation(
+                OperationType::SoftwareUpdate,
+                json!({ "updateList": "${.payload.updateList}" }),
+                BgExitHandlers {
+                    on_exec: GenericStateUpdate::from("awaiting_install".to_string())
+                }
+            ))
+        );
+        assert_eq!(
+            workflow.states.get("awaiting_install"),
+            Some(&OperationAction::AwaitOperationCompletion(AwaitHandlers {
+                on_success: GenericStateUpdate::successful(),
+                on_error: Some(GenericStateUpdate::failed(
+                    "installation failed".to_string()
+                )),
+                output: Some(json!({ "installed": "${.payload.updateList}" })),
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_when_clauses() {
+        let file = r#"
+operation = "update"
+
+[init]
+action = "proceed"
+when = [
+    { path = ".payload.kind", equals = "firmware", next = "firmware" },
+    { path = ".payload.size", greater_than = 1000, next = { status = "failed", reason = "too large" } },
+    { next = "software" },
+]
+
+[firmware]
+action = "proceed"
+
+[software]
+action = "proceed"
+"#;
+
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(
+            workflow.states.get("init"),
+            Some(&OperationAction::Branch(
+                vec![
+                    WhenClause {
+                        guard: Some(JsonPathGuard::new(
+                            ".payload.kind",
+                            Predicate::Equals(json!("firmware"))
+                        )),
+                        next: "firmware".to_string().into(),
+                    },
+                    WhenClause {
+                        guard: Some(JsonPathGuard::new(
+                            ".payload.size",
+                            Predicate::GreaterThan(json!(1000))
+                        )),
+                        next: GenericStateUpdate::failed("too large".to_string()),
+                    },
+                    WhenClause {
+                        guard: None,
+                        next: "software".to_string().into(),
+                    },
+                ],
+                GenericStateUpdate::successful()
+            ))
+        );
+    }
+
+    #[test]
+    fn reject_when_clauses_targeting_unknown_states() {
+        let file = r#"
+operation = "update"
+
+[init]
+action = "proceed"
+when = [
+    { path = ".payload.kind", equals = "firmware", next = "firmware" },
+]
+on_success = "software"
+
+[software]
+action = "proceed"
+"#;
+
+        let input: TomlOperationWorkflow = toml::from_str(file).unwrap();
+        let error = OperationWorkflow::try_from(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::UnknownTargetState {
+                state: "init".to_string(),
+                target: "firmware".to_string()
+            }
+        )
+    }
+
+    #[test]
+    fn reject_unreachable_when_clauses() {
+        let file = r#"
+operation = "update"
+
+[init]
+action = "proceed"
+when = [
+    { next = "successful" },
+    { path = ".payload.kind", equals = "firmware", next = "failed" },
+]
+"#;
+
+        let input: TomlOperationWorkflow = toml::from_str(file).unwrap();
+        let error = OperationWorkflow::try_from(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::UnreachableWhenClause {
+                state: "init".to_string(),
+                index: 1
+            }
+        )
+    }
+
+    #[test]
+    fn reject_states_not_reachable_from_init() {
+        let file = r#"
+operation = "update"
+
+[init]
+action = "proceed"
+when = [
+    { path = ".payload.kind", equals = "firmware", next = "firmware" },
+]
+on_success = "software"
+
+[software]
+action = "proceed"
+on_success = "successful"
+
+[firmware]
+action = "proceed"
+on_success = "successful"
+
+[orphan]
+action = "proceed"
+on_success = "orphan-child"
+
+[orphan-child]
+action = "proceed"
+on_success = "successful"
+"#;
+
+        let input: TomlOperationWorkflow = toml::from_str(file).unwrap();
+        let error = OperationWorkflow::try_from(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::UnreachableStates {
+                states: vec!["orphan".to_string(), "orphan-child".to_string()]
+            }
+        )
+    }
+
+    #[test]
+    fn reject_ill_formed_when_clauses() {
+        for (clause, reason) in [
+            (
+                r#"{ path = ".payload.x", next = "successful" }"#,
+                "no comparison is given for .payload.x",
+            ),
+            (
+                r#"{ path = ".payload.x", equals = 1, less_than = 2, next = "successful" }"#,
+                "more than one comparison is given for .payload.x",
+            ),
+            (
+                r#"{ equals = 1, next = "successful" }"#,
+                "a comparison is given but no path",
+            ),
+        ] {
+            let file = format!("action = \"proceed\"\nwhen = [ {clause} ]");
+            let input: TomlOperationState = toml::from_str(&file).unwrap();
+            let error = OperationAction::try_from(input).unwrap_err();
+            assert_eq!(
+                error,
+                WorkflowDefinitionError::InvalidWhenClause {
+                    reason: reason.to_string()
+                }
+            )
+        }
+    }
+
     #[test]
     fn reject_script_on_the_failed_state() {
         let file = r#"
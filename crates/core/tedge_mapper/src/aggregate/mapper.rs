@@ -0,0 +1,57 @@
+use crate::core::component::TEdgeComponent;
+use crate::core::mapper::start_basic_actors;
+use async_trait::async_trait;
+use batcher::BatchingActorBuilder;
+use clock::WallClock;
+use std::path::Path;
+use tedge_actors::ConvertingActor;
+use tedge_actors::MessageSink;
+use tedge_aggregation_ext::AggregationConfig;
+use tedge_aggregation_ext::MeasurementSampler;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::TEdgeConfig;
+
+const AGGREGATE_MAPPER_NAME: &str = "tedge-mapper-aggregate";
+
+pub struct AggregateMapper;
+
+#[async_trait]
+impl TEdgeComponent for AggregateMapper {
+    fn session_name(&self) -> &str {
+        AGGREGATE_MAPPER_NAME
+    }
+
+    async fn start(
+        &self,
+        tedge_config: TEdgeConfig,
+        config_dir: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let (mut runtime, mut mqtt_actor) =
+            start_basic_actors(self.session_name(), &tedge_config).await?;
+        let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+        let config = AggregationConfig::load(&config_dir.join("mappers/aggregation.toml"))?;
+
+        let input_topics = mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::Measurement);
+        let sampler =
+            MeasurementSampler::new(mqtt_schema.clone(), config.clone(), Box::new(WallClock));
+        let mut sampler_actor =
+            ConvertingActor::builder("MeasurementSampler", sampler, input_topics);
+        let mut batching_actor =
+            BatchingActorBuilder::default().with_batching_window(config.window_millis());
+
+        sampler_actor.add_input(&mut mqtt_actor);
+        batching_actor.add_input(&mut sampler_actor);
+        mqtt_actor.add_mapped_input(&mut batching_actor, move |batch| {
+            tedge_aggregation_ext::batch_into_mqtt_messages(&mqtt_schema, &config, batch)
+                .into_iter()
+        });
+
+        runtime.spawn(sampler_actor).await?;
+        runtime.spawn(batching_actor).await?;
+        runtime.spawn(mqtt_actor).await?;
+        runtime.run_to_completion().await?;
+        Ok(())
+    }
+}
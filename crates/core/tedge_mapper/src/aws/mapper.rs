@@ -1,53 +1,116 @@
 use crate::core::component::TEdgeComponent;
+use crate::core::filter::FilterRules;
+use crate::core::filter::FilteredMqtt;
+use crate::core::filter::TelemetryFilter;
+use crate::core::mapper::mapper_instance_name;
 use crate::core::mapper::start_basic_actors;
+use crate::core::mapper::store_forward_config;
+use anyhow::Context;
 use async_trait::async_trait;
 use aws_mapper_ext::converter::AwsConverter;
+use aws_mapper_ext::jobs::job_topics;
+use aws_mapper_ext::shadow::shadow_delta_topics;
 use clock::WallClock;
 use mqtt_channel::TopicFilter;
 use std::path::Path;
+use tedge_actors::Builder;
 use tedge_actors::ConvertingActor;
 use tedge_actors::MessageSink;
 use tedge_actors::MessageSource;
 use tedge_actors::NoConfig;
+use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfig;
+use tedge_store_forward_ext::StoreForwardBuilder;
+use tedge_timer_ext::TimerActor;
+use tedge_utils::file::create_directory_with_defaults;
 use tracing::warn;
 
 const AWS_MAPPER_NAME: &str = "tedge-mapper-aws";
 
-pub struct AwsMapper;
+pub struct AwsMapper {
+    mapper_name: String,
+}
+
+impl AwsMapper {
+    pub fn new(profile: Option<&ProfileName>) -> Self {
+        AwsMapper {
+            mapper_name: mapper_instance_name(AWS_MAPPER_NAME, profile),
+        }
+    }
+}
 
 #[async_trait]
 impl TEdgeComponent for AwsMapper {
     fn session_name(&self) -> &str {
-        AWS_MAPPER_NAME
+        &self.mapper_name
     }
 
     async fn start(
         &self,
         tedge_config: TEdgeConfig,
-        _config_dir: &Path,
+        config_dir: &Path,
     ) -> Result<(), anyhow::Error> {
         let (mut runtime, mut mqtt_actor) =
             start_basic_actors(self.session_name(), &tedge_config).await?;
         let clock = Box::new(WallClock);
         let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+
+        let state_dir = config_dir.join(format!(".{}", self.mapper_name));
+        create_directory_with_defaults(&state_dir)?;
+        let store_forward_config = store_forward_config(
+            &self.mapper_name,
+            "aws",
+            &state_dir,
+            tedge_config.aws.store_forward.enable,
+            tedge_config.aws.store_forward.max_messages,
+            tedge_config.aws.store_forward.replay_rate,
+            &mqtt_schema,
+        )?;
+        let store_forward_actor =
+            store_forward_config.map(|config| StoreForwardBuilder::new(config, &mut mqtt_actor));
+
+        let filter_rules = FilterRules::load(&config_dir.join("mappers/aws-filter.toml"))?;
+        let telemetry_filter =
+            TelemetryFilter::new(mqtt_schema.clone(), filter_rules, Box::new(WallClock));
+        let mut timer_actor = TimerActor::builder();
+
+        let device_topic_id: EntityTopicId = tedge_config
+            .mqtt
+            .device_topic_id
+            .parse()
+            .context("Invalid device_topic_id")?;
         let aws_converter = AwsConverter::new(
             tedge_config.aws.mapper.timestamp,
             clock,
             mqtt_schema,
             tedge_config.aws.mapper.timestamp_format,
-        );
+        )
+        .with_device_topic_id(device_topic_id);
         let mut aws_converting_actor = ConvertingActor::builder(
             "AwsConverter",
             aws_converter,
             get_topic_filter(&tedge_config),
         );
 
-        aws_converting_actor.add_input(&mut mqtt_actor);
-        aws_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        let mut filtered_mqtt =
+            FilteredMqtt::new(&mut mqtt_actor, &mut timer_actor, telemetry_filter);
+        aws_converting_actor.add_input(&mut filtered_mqtt);
+        let telemetry_actors = filtered_mqtt.try_build()?;
+        let cloud_publisher = match &store_forward_actor {
+            Some(store_forward_actor) => store_forward_actor.get_sender(),
+            None => mqtt_actor.get_sender(),
+        };
+        aws_converting_actor.register_peer(NoConfig, cloud_publisher);
 
         runtime.spawn(aws_converting_actor).await?;
+        runtime.spawn(telemetry_actors.decoder).await?;
+        runtime.spawn(telemetry_actors.filter).await?;
+        runtime.spawn(timer_actor).await?;
+        if let Some(store_forward_actor) = store_forward_actor {
+            runtime.spawn(store_forward_actor).await?;
+        }
         runtime.spawn(mqtt_actor).await?;
         runtime.run_to_completion().await?;
         Ok(())
@@ -61,5 +124,9 @@ fn get_topic_filter(tedge_config: &TEdgeConfig) -> TopicFilter {
             warn!("The configured topic '{topic}' is invalid and ignored.");
         }
     }
+    topics.add_all(shadow_delta_topics());
+    topics.add_all(job_topics(&MqttSchema::with_root(
+        tedge_config.mqtt.topic_root.clone(),
+    )));
     topics
 }
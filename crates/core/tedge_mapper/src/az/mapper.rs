@@ -1,49 +1,133 @@
 use crate::core::component::TEdgeComponent;
+use crate::core::filter::FilterRules;
+use crate::core::filter::FilteredMqtt;
+use crate::core::filter::TelemetryFilter;
+use crate::core::mapper::mapper_instance_name;
 use crate::core::mapper::start_basic_actors;
+use crate::core::mapper::store_forward_config;
+use anyhow::Context;
 use async_trait::async_trait;
 use az_mapper_ext::converter::AzureConverter;
+use az_mapper_ext::entities;
+use az_mapper_ext::methods::method_topics;
+use az_mapper_ext::twin::twin_topics;
 use clock::WallClock;
 use mqtt_channel::TopicFilter;
 use std::path::Path;
+use tedge_actors::Builder;
 use tedge_actors::ConvertingActor;
 use tedge_actors::MessageSink;
 use tedge_actors::MessageSource;
 use tedge_actors::NoConfig;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityStore;
+use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfig;
+use tedge_store_forward_ext::StoreForwardBuilder;
+use tedge_timer_ext::TimerActor;
+use tedge_utils::file::create_directory_with_defaults;
 use tracing::warn;
 
 const AZURE_MAPPER_NAME: &str = "tedge-mapper-az";
+const EARLY_MESSAGE_BUFFER_SIZE: usize = 100;
 
-pub struct AzureMapper;
+pub struct AzureMapper {
+    mapper_name: String,
+}
+
+impl AzureMapper {
+    pub fn new(profile: Option<&ProfileName>) -> Self {
+        AzureMapper {
+            mapper_name: mapper_instance_name(AZURE_MAPPER_NAME, profile),
+        }
+    }
+}
 
 #[async_trait]
 impl TEdgeComponent for AzureMapper {
     fn session_name(&self) -> &str {
-        AZURE_MAPPER_NAME
+        &self.mapper_name
     }
 
     async fn start(
         &self,
         tedge_config: TEdgeConfig,
-        _config_dir: &Path,
+        config_dir: &Path,
     ) -> Result<(), anyhow::Error> {
         let (mut runtime, mut mqtt_actor) =
             start_basic_actors(self.session_name(), &tedge_config).await?;
         let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+
+        let device_id = tedge_config.device.id.try_read(&tedge_config)?.to_string();
+        let state_dir = config_dir.join(format!(".{}", self.mapper_name));
+        create_directory_with_defaults(&state_dir)?;
+        let device_topic_id: EntityTopicId = tedge_config
+            .mqtt
+            .device_topic_id
+            .parse()
+            .context("Invalid device_topic_id")?;
+        let main_device = EntityRegistrationMessage {
+            topic_id: device_topic_id,
+            ..EntityRegistrationMessage::main_device(device_id)
+        };
+        let entity_store = EntityStore::with_main_device_and_default_service_type(
+            mqtt_schema.clone(),
+            main_device,
+            "service".to_string(),
+            entities::external_id,
+            entities::validate_external_id,
+            EARLY_MESSAGE_BUFFER_SIZE,
+            &state_dir,
+        )?;
+
+        let store_forward_config = store_forward_config(
+            &self.mapper_name,
+            "az",
+            &state_dir,
+            tedge_config.az.store_forward.enable,
+            tedge_config.az.store_forward.max_messages,
+            tedge_config.az.store_forward.replay_rate,
+            &mqtt_schema,
+        )?;
+        let store_forward_actor =
+            store_forward_config.map(|config| StoreForwardBuilder::new(config, &mut mqtt_actor));
+
+        let filter_rules = FilterRules::load(&config_dir.join("mappers/az-filter.toml"))?;
+        let telemetry_filter =
+            TelemetryFilter::new(mqtt_schema.clone(), filter_rules, Box::new(WallClock));
+        let mut timer_actor = TimerActor::builder();
+
         let az_converter = AzureConverter::new(
             tedge_config.az.mapper.timestamp,
             Box::new(WallClock),
             mqtt_schema,
             tedge_config.az.mapper.timestamp_format,
-        );
+            entity_store,
+        )
+        .with_auto_registration(tedge_config.az.entity_store.auto_register)
+        .with_entities_reported_in_twin(tedge_config.az.entity_store.report_in_twin);
         let mut az_converting_actor =
             ConvertingActor::builder("AzConverter", az_converter, get_topic_filter(&tedge_config));
-        az_converting_actor.add_input(&mut mqtt_actor);
+        let mut filtered_mqtt =
+            FilteredMqtt::new(&mut mqtt_actor, &mut timer_actor, telemetry_filter);
+        az_converting_actor.add_input(&mut filtered_mqtt);
+        let telemetry_actors = filtered_mqtt.try_build()?;
 
-        az_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        let cloud_publisher = match &store_forward_actor {
+            Some(store_forward_actor) => store_forward_actor.get_sender(),
+            None => mqtt_actor.get_sender(),
+        };
+        az_converting_actor.register_peer(NoConfig, cloud_publisher);
 
         runtime.spawn(az_converting_actor).await?;
+        runtime.spawn(telemetry_actors.decoder).await?;
+        runtime.spawn(telemetry_actors.filter).await?;
+        runtime.spawn(timer_actor).await?;
+        if let Some(store_forward_actor) = store_forward_actor {
+            runtime.spawn(store_forward_actor).await?;
+        }
         runtime.spawn(mqtt_actor).await?;
         runtime.run_to_completion().await?;
         Ok(())
@@ -57,5 +141,9 @@ fn get_topic_filter(tedge_config: &TEdgeConfig) -> TopicFilter {
             warn!("The configured topic '{topic}' is invalid and ignored.");
         }
     }
+    topics.add_all(twin_topics());
+    topics.add_all(method_topics(&MqttSchema::with_root(
+        tedge_config.mqtt.topic_root.clone(),
+    )));
     topics
 }
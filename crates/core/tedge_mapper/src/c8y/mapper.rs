@@ -1,5 +1,10 @@
 use crate::core::component::TEdgeComponent;
+use crate::core::filter::FilterRules;
+use crate::core::filter::FilteredMqtt;
+use crate::core::filter::TelemetryFilter;
+use crate::core::mapper::mapper_instance_name;
 use crate::core::mapper::start_basic_actors;
+use crate::core::mapper::store_forward_config;
 use anyhow::Context;
 use async_trait::async_trait;
 use c8y_auth_proxy::actor::C8yAuthProxyBuilder;
@@ -9,26 +14,43 @@ use c8y_mapper_ext::actor::C8yMapperBuilder;
 use c8y_mapper_ext::compatibility_adapter::OldAgentAdapter;
 use c8y_mapper_ext::config::C8yMapperConfig;
 use c8y_mapper_ext::converter::CumulocityConverter;
+use clock::WallClock;
 use mqtt_channel::Config;
 use std::path::Path;
+use tedge_actors::Builder;
 use tedge_api::entity_store::EntityExternalId;
 use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfig;
 use tedge_downloader_ext::DownloaderActor;
 use tedge_file_system_ext::FsWatchActorBuilder;
 use tedge_http_ext::HttpActor;
 use tedge_mqtt_ext::MqttActorBuilder;
+use tedge_store_forward_ext::MqttProxy;
+use tedge_store_forward_ext::StoreForwardBuilder;
 use tedge_timer_ext::TimerActor;
 use tedge_uploader_ext::UploaderActor;
+use tedge_utils::file::create_directory_with_defaults;
 
 const CUMULOCITY_MAPPER_NAME: &str = "tedge-mapper-c8y";
 
-pub struct CumulocityMapper;
+pub struct CumulocityMapper {
+    mapper_name: String,
+}
+
+impl CumulocityMapper {
+    pub fn new(profile: Option<&ProfileName>) -> Self {
+        CumulocityMapper {
+            mapper_name: mapper_instance_name(CUMULOCITY_MAPPER_NAME, profile),
+        }
+    }
+}
 
 #[async_trait]
 impl TEdgeComponent for CumulocityMapper {
     fn session_name(&self) -> &str {
-        CUMULOCITY_MAPPER_NAME
+        &self.mapper_name
     }
 
     async fn start(&self, tedge_config: TEdgeConfig, cfg_dir: &Path) -> Result<(), anyhow::Error> {
@@ -47,20 +69,50 @@ impl TEdgeComponent for CumulocityMapper {
         let mut fs_watch_actor = FsWatchActorBuilder::new();
         let mut timer_actor = TimerActor::builder();
 
-        let identity = tedge_config.http.client.auth.identity()?;
+        let identity = tedge_config.http_client_tls_config()?;
         let mut uploader_actor = UploaderActor::new(identity.clone()).builder();
         let mut downloader_actor = DownloaderActor::new(identity).builder();
 
-        let c8y_mapper_config = C8yMapperConfig::from_tedge_config(cfg_dir, &tedge_config)?;
+        let mut c8y_mapper_config = C8yMapperConfig::from_tedge_config(cfg_dir, &tedge_config)?;
+        if self.mapper_name != CUMULOCITY_MAPPER_NAME {
+            // Each profile has its own state, not to be mixed with the state of the default mapper
+            c8y_mapper_config.state_dir = cfg_dir.join(format!(".{}", self.mapper_name));
+        }
+
+        // The messages sent to Cumulocity by the mapper are spooled while the bridge is down
+        create_directory_with_defaults(&c8y_mapper_config.state_dir)?;
+        let store_forward_config = store_forward_config(
+            &self.mapper_name,
+            "c8y",
+            &c8y_mapper_config.state_dir,
+            tedge_config.c8y.store_forward.enable,
+            tedge_config.c8y.store_forward.max_messages,
+            tedge_config.c8y.store_forward.replay_rate,
+            &MqttSchema::with_root(tedge_config.mqtt.topic_root.clone()),
+        )?;
+        let store_forward_actor =
+            store_forward_config.map(|config| StoreForwardBuilder::new(config, &mut mqtt_actor));
+
+        // The telemetry messages are decoded and filtered before reaching the mapper
+        let filter_rules = FilterRules::load(&cfg_dir.join("mappers/c8y-filter.toml"))?;
+        let telemetry_filter = TelemetryFilter::new(
+            MqttSchema::with_root(tedge_config.mqtt.topic_root.clone()),
+            filter_rules,
+            Box::new(WallClock),
+        );
+        let mut filtered_mqtt =
+            FilteredMqtt::new(&mut mqtt_actor, &mut timer_actor, telemetry_filter);
+
         let c8y_mapper_actor = C8yMapperBuilder::try_new(
             c8y_mapper_config,
-            &mut mqtt_actor,
+            &mut MqttProxy::new(&mut filtered_mqtt, store_forward_actor.as_ref()),
             &mut c8y_http_proxy_actor,
             &mut timer_actor,
             &mut uploader_actor,
             &mut downloader_actor,
             &mut fs_watch_actor,
         )?;
+        let telemetry_actors = filtered_mqtt.try_build()?;
 
         // Adaptor translating commands sent on te/device/main///cmd/+/+ into requests on tedge/commands/req/+/+
         // and translating the responses received on tedge/commands/res/+/+ to te/device/main///cmd/+/+
@@ -69,8 +121,10 @@ impl TEdgeComponent for CumulocityMapper {
         // MQTT client dedicated to set service down status on shutdown, using a last-will message
         // A separate MQTT actor/client is required as the last will message of the main MQTT actor
         // is used to send down status to health topic
-        let service_monitor_actor =
-            MqttActorBuilder::new(service_monitor_client_config(&tedge_config)?);
+        let service_monitor_actor = MqttActorBuilder::new(service_monitor_client_config(
+            &self.mapper_name,
+            &tedge_config,
+        )?);
 
         runtime.spawn(mqtt_actor).await?;
         runtime.spawn(jwt_actor).await?;
@@ -80,6 +134,11 @@ impl TEdgeComponent for CumulocityMapper {
         runtime.spawn(fs_watch_actor).await?;
         runtime.spawn(timer_actor).await?;
         runtime.spawn(c8y_mapper_actor).await?;
+        runtime.spawn(telemetry_actors.decoder).await?;
+        runtime.spawn(telemetry_actors.filter).await?;
+        if let Some(store_forward_actor) = store_forward_actor {
+            runtime.spawn(store_forward_actor).await?;
+        }
         runtime.spawn(service_monitor_actor).await?;
         runtime.spawn(uploader_actor).await?;
         runtime.spawn(downloader_actor).await?;
@@ -90,7 +149,10 @@ impl TEdgeComponent for CumulocityMapper {
     }
 }
 
-pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Config, anyhow::Error> {
+pub fn service_monitor_client_config(
+    mapper_name: &str,
+    tedge_config: &TEdgeConfig,
+) -> Result<Config, anyhow::Error> {
     let main_device_xid: EntityExternalId = tedge_config.device.id.try_read(tedge_config)?.into();
     let service_type = &tedge_config.service.ty;
     let service_type = if service_type.is_empty() {
@@ -111,7 +173,7 @@ pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Confi
         .context("Invalid device_topic_id")?;
 
     let mapper_service_topic_id = entity_topic_id
-        .default_service_for_device(CUMULOCITY_MAPPER_NAME)
+        .default_service_for_device(mapper_name)
         .context("Can't derive service name if device topic id not in default scheme")?;
 
     let mapper_service_external_id =
@@ -119,7 +181,7 @@ pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Confi
 
     let last_will_message = c8y_api::smartrest::inventory::service_creation_message(
         mapper_service_external_id.as_ref(),
-        CUMULOCITY_MAPPER_NAME,
+        mapper_name,
         service_type.as_str(),
         "down",
         &[],
@@ -127,7 +189,11 @@ pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Confi
 
     let mqtt_config = tedge_config
         .mqtt_config()?
-        .with_session_name("last_will_c8y_mapper")
+        // e.g. last_will_c8y_mapper@staging for the mapper of the staging profile
+        .with_session_name(format!(
+            "last_will_c8y_mapper{}",
+            mapper_name.trim_start_matches(CUMULOCITY_MAPPER_NAME)
+        ))
         .with_last_will_message(last_will_message);
     Ok(mqtt_config)
 }
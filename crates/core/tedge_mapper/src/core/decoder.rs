@@ -0,0 +1,77 @@
+//! Decoding of the binary-encoded telemetry messages before their processing by a mapper
+//!
+//! The measurements, events and alarms published with a binary payload
+//! (e.g. CBOR payloads published on `te/<entity>/m/<type>.cbor`)
+//! are translated into thin-edge JSON, so the mappers only have to process thin-edge JSON.
+use std::convert::Infallible;
+use tedge_actors::Converter;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::payload_format::decode_message;
+use tedge_mqtt_ext::MqttMessage;
+use tracing::warn;
+
+/// Decode the binary-encoded telemetry messages, forwarding all the other messages unchanged
+pub struct PayloadDecoder {
+    mqtt_schema: MqttSchema,
+}
+
+impl PayloadDecoder {
+    pub fn new(mqtt_schema: MqttSchema) -> Self {
+        PayloadDecoder { mqtt_schema }
+    }
+}
+
+impl Converter for PayloadDecoder {
+    type Input = MqttMessage;
+    type Output = MqttMessage;
+    type Error = Infallible;
+
+    fn convert(&mut self, input: &Self::Input) -> Result<Vec<Self::Output>, Self::Error> {
+        match decode_message(&self.mqtt_schema, input) {
+            None => Ok(vec![input.clone()]),
+            Some(Ok(decoded)) => Ok(vec![decoded]),
+            Some(Err(err)) => {
+                warn!("Ignoring invalid message on {}: {err}", input.topic.name);
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_mqtt_ext::Topic;
+
+    #[test]
+    fn binary_telemetry_messages_are_decoded() {
+        let mut decoder = PayloadDecoder::new(MqttSchema::default());
+
+        // CBOR encoding of {"temperature": 21}
+        let mut cbor = vec![0xa1, 0x6b];
+        cbor.extend_from_slice(b"temperature");
+        cbor.push(0x15);
+
+        let encoded = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment.cbor"),
+            cbor,
+        );
+        let invalid = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment.cbor"),
+            vec![0xff],
+        );
+        let json = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            r#"{"temperature": 21}"#,
+        );
+        assert_eq!(
+            decoder.convert(&encoded).unwrap(),
+            vec![MqttMessage::new(
+                &Topic::new_unchecked("te/device/main///m/environment"),
+                r#"{"temperature":21}"#
+            )]
+        );
+        assert_eq!(decoder.convert(&invalid).unwrap(), vec![]);
+        assert_eq!(decoder.convert(&json).unwrap(), vec![json]);
+    }
+}
@@ -0,0 +1,955 @@
+//! Filtering of the measurements before their translation by a mapper
+//!
+//! The filter rules of a mapper are read from `/etc/tedge/mappers/<cloud>-filter.toml`:
+//!
+//! ```toml
+//! # Drop all the measurements of a chatty child device
+//! [[rules]]
+//! entity = "device/child01//"
+//! drop = true
+//!
+//! # Forward at most one vibration measurement per second,
+//! # and only when a value changed by more than 0.5
+//! [[rules]]
+//! entity = "device/main//"
+//! type = "vibration"
+//! interval = 1.0
+//! deadband = 0.5
+//!
+//! # Forward the average of the environment measurements over one-minute windows
+//! [[rules]]
+//! type = "environment"
+//! aggregate = "avg"
+//! window = 60
+//! ```
+//!
+//! A rule applies to the measurements of the given entity and type, any entity or type if omitted.
+//! Only the first matching rule is applied, and the measurements matching no rules are forwarded unchanged.
+//!
+//! The aggregate of a window is published when the window ends, or when the mapper stops,
+//! and is timestamped with the end time of the window.
+//!
+//! Before being filtered, the binary-encoded telemetry messages are decoded by a [PayloadDecoder].
+use crate::core::decoder::PayloadDecoder;
+use clock::Clock;
+use clock::Timestamp;
+use serde::Deserialize;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::Path;
+use std::time::Duration;
+use tedge_actors::fan_in_message_type;
+use tedge_actors::Builder;
+use tedge_actors::ChannelError;
+use tedge_actors::Converter;
+use tedge_actors::ConvertingActor;
+use tedge_actors::ConvertingActorBuilder;
+use tedge_actors::DynSender;
+use tedge_actors::LinkError;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::Sender;
+use tedge_actors::ServiceProvider;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+use tedge_timer_ext::SetTimeout;
+use tedge_timer_ext::Timeout;
+use time::format_description::well_known::Rfc3339;
+use tracing::warn;
+
+#[derive(thiserror::Error, Debug)]
+pub enum FilterError {
+    #[error("Fail to read the filter rules from {path}: {error}")]
+    FromIo { path: String, error: std::io::Error },
+
+    #[error("Invalid filter rules in {path}: {error}")]
+    FromToml {
+        path: String,
+        error: toml::de::Error,
+    },
+
+    #[error("Invalid filter rule #{index}: {reason}")]
+    InvalidRule { index: usize, reason: String },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRules {
+    #[serde(default)]
+    pub rules: Vec<FilterRule>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRule {
+    /// The entity topic id of the source, e.g. `device/main//`
+    pub entity: Option<String>,
+
+    /// The measurement type
+    #[serde(rename = "type")]
+    pub measurement_type: Option<String>,
+
+    /// Drop all the matching measurements
+    #[serde(default)]
+    pub drop: bool,
+
+    /// The minimum interval in seconds between two forwarded measurements
+    pub interval: Option<f64>,
+
+    /// Forward a measurement only if one of its values changed by more than this deadband
+    pub deadband: Option<f64>,
+
+    /// Forward only an aggregate of the values received over a window
+    pub aggregate: Option<Aggregate>,
+
+    /// The duration in seconds of the aggregation windows
+    pub window: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    Min,
+    Max,
+    Avg,
+}
+
+impl FilterRules {
+    /// Load the rules from a TOML file, no rules being defined if the file doesn't exist
+    pub fn load(path: &Path) -> Result<Self, FilterError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(FilterRules::default())
+            }
+            Err(error) => {
+                return Err(FilterError::FromIo {
+                    path: path.display().to_string(),
+                    error,
+                })
+            }
+        };
+        FilterRules::from_toml(&content).map_err(|err| match err {
+            FilterError::FromToml { error, .. } => FilterError::FromToml {
+                path: path.display().to_string(),
+                error,
+            },
+            err => err,
+        })
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, FilterError> {
+        let mut rules: FilterRules =
+            toml::from_str(content).map_err(|error| FilterError::FromToml {
+                path: "<string>".to_string(),
+                error,
+            })?;
+        for (index, rule) in rules.rules.iter_mut().enumerate() {
+            rule.validate()
+                .map_err(|reason| FilterError::InvalidRule { index, reason })?;
+        }
+        Ok(rules)
+    }
+
+    fn rule_for(&self, entity: &EntityTopicId, measurement_type: &str) -> Option<&FilterRule> {
+        self.rules.iter().find(|rule| {
+            rule.entity.as_ref().map_or(true, |e| e == entity.as_str())
+                && rule
+                    .measurement_type
+                    .as_ref()
+                    .map_or(true, |t| t == measurement_type)
+        })
+    }
+}
+
+impl FilterRule {
+    /// Check the rule, normalizing the entity topic id, e.g. `device/child01` into `device/child01//`
+    fn validate(&mut self) -> Result<(), String> {
+        if let Some(entity) = &mut self.entity {
+            let topic_id: EntityTopicId = entity
+                .parse()
+                .map_err(|err| format!("invalid entity {entity}: {err}"))?;
+            *entity = topic_id.to_string();
+        }
+        for (name, value) in [
+            ("interval", self.interval),
+            ("deadband", self.deadband),
+            ("window", self.window),
+        ] {
+            if matches!(value, Some(value) if !value.is_finite() || value < 0.0) {
+                return Err(format!("{name} must be a finite positive number"));
+            }
+        }
+        if matches!(self.window, Some(window) if window == 0.0) {
+            return Err("window must not be empty".to_string());
+        }
+        match (self.aggregate, self.window) {
+            (Some(_), None) => Err("an aggregate requires a window".to_string()),
+            (None, Some(_)) => Err("a window requires an aggregate".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The end of an aggregation window, as notified by the timer actor
+#[derive(Debug)]
+pub struct WindowEnd;
+
+pub type WindowTimer = SetTimeout<WindowEnd>;
+pub type WindowTimeout = Timeout<WindowEnd>;
+
+fan_in_message_type!(FilterInput[MqttMessage, WindowTimeout] : Debug);
+fan_in_message_type!(FilterOutput[MqttMessage, WindowTimer] : Debug);
+
+/// Filter the measurements according to [FilterRules]
+///
+/// All the other messages are forwarded unchanged.
+/// A timer is requested for each aggregation window,
+/// so the aggregates are published as soon as their windows end,
+/// even if no more measurements are received.
+pub struct TelemetryFilter {
+    mqtt_schema: MqttSchema,
+    rules: FilterRules,
+    clock: Box<dyn Clock>,
+    states: HashMap<(EntityTopicId, String), FilterState>,
+    opened_windows: Vec<Duration>,
+}
+
+/// What is known of the measurements of a given entity and type
+#[derive(Default)]
+struct FilterState {
+    last_forwarded: Option<Timestamp>,
+    last_values: Option<BTreeMap<SeriesKey, f64>>,
+    window: Option<Window>,
+}
+
+/// The group and name of a series, the group being empty for a series that is not part of a group
+type SeriesKey = (String, String);
+
+struct Window {
+    topic: Topic,
+    end: Timestamp,
+    aggregate: Aggregate,
+    series: BTreeMap<SeriesKey, Accumulator>,
+}
+
+struct Accumulator {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: usize,
+}
+
+impl TelemetryFilter {
+    pub fn new(mqtt_schema: MqttSchema, rules: FilterRules, clock: Box<dyn Clock>) -> Self {
+        TelemetryFilter {
+            mqtt_schema,
+            rules,
+            clock,
+            states: HashMap::new(),
+            opened_windows: vec![],
+        }
+    }
+
+    /// Publish the aggregates of the windows that ended
+    pub fn flush_ended_windows(&mut self) -> Vec<MqttMessage> {
+        let now = self.clock.now();
+        self.states
+            .values_mut()
+            .filter(|state| state.window.as_ref().is_some_and(|w| w.end <= now))
+            .filter_map(|state| state.window.take())
+            .map(Window::into_message)
+            .collect()
+    }
+
+    /// Publish the aggregates of all the pending windows
+    pub fn pending_aggregates(&mut self) -> Vec<MqttMessage> {
+        self.states
+            .values_mut()
+            .filter_map(|state| state.window.take())
+            .map(Window::into_message)
+            .collect()
+    }
+
+    /// Filter a message, returning the messages to be forwarded to the mapper
+    pub fn filter_message(&mut self, input: &MqttMessage) -> Vec<MqttMessage> {
+        let Ok((entity, Channel::Measurement { measurement_type })) =
+            self.mqtt_schema.entity_channel_of(&input.topic)
+        else {
+            return vec![input.clone()];
+        };
+        let Some(rule) = self.rules.rule_for(&entity, &measurement_type) else {
+            return vec![input.clone()];
+        };
+        if rule.drop {
+            return vec![];
+        }
+        // Invalid measurements are forwarded for the mapper to report the errors
+        let Some(values) = input.payload_str().ok().and_then(series_values) else {
+            return vec![input.clone()];
+        };
+
+        let now = self.clock.now();
+        let state = self.states.entry((entity, measurement_type)).or_default();
+
+        if let (Some(aggregate), Some(window)) = (rule.aggregate, rule.window) {
+            let mut messages = vec![];
+            if let Some(current) = &state.window {
+                if current.end <= now {
+                    messages.extend(state.window.take().map(Window::into_message));
+                }
+            }
+            if state.window.is_none() {
+                let Some((duration, end)) = window_bounds(now, window) else {
+                    warn!(
+                        "Ignoring measurement on {}: cannot open a window of {window} seconds",
+                        input.topic.name
+                    );
+                    return messages;
+                };
+                self.opened_windows.push(duration);
+                state.window = Some(Window::new(input.topic.clone(), end, aggregate));
+            }
+            if let Some(current) = &mut state.window {
+                current.add(values);
+            }
+            return messages;
+        }
+
+        if let (Some(interval), Some(last_forwarded)) = (rule.interval, state.last_forwarded) {
+            if (now - last_forwarded).as_seconds_f64() < interval {
+                return vec![];
+            }
+        }
+        if let (Some(deadband), Some(last_values)) = (rule.deadband, &state.last_values) {
+            if within_deadband(last_values, &values, deadband) {
+                return vec![];
+            }
+        }
+
+        state.last_forwarded = Some(now);
+        state.last_values = Some(values);
+        vec![input.clone()]
+    }
+}
+
+/// The duration and end of a window opened at `start`, if these can be represented
+fn window_bounds(start: Timestamp, window: f64) -> Option<(Duration, Timestamp)> {
+    let duration = Duration::try_from_secs_f64(window).ok()?;
+    let end = start.checked_add(time::Duration::try_from(duration).ok()?)?;
+    Some((duration, end))
+}
+
+impl Converter for TelemetryFilter {
+    type Input = FilterInput;
+    type Output = FilterOutput;
+    type Error = Infallible;
+
+    fn convert(&mut self, input: &Self::Input) -> Result<Vec<Self::Output>, Self::Error> {
+        let messages = match input {
+            FilterInput::MqttMessage(message) => self.filter_message(message),
+            FilterInput::WindowTimeout(_) => self.flush_ended_windows(),
+        };
+        let timers = self
+            .opened_windows
+            .drain(..)
+            .map(|duration| WindowTimer::new(duration, WindowEnd).into());
+        Ok(messages
+            .into_iter()
+            .map(FilterOutput::from)
+            .chain(timers)
+            .collect())
+    }
+
+    fn shutdown_messages(&mut self) -> Result<Vec<Self::Output>, Self::Error> {
+        Ok(self
+            .pending_aggregates()
+            .into_iter()
+            .map(FilterOutput::from)
+            .collect())
+    }
+}
+
+impl Window {
+    fn new(topic: Topic, end: Timestamp, aggregate: Aggregate) -> Self {
+        Window {
+            topic,
+            end,
+            aggregate,
+            series: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, values: BTreeMap<SeriesKey, f64>) {
+        for (key, value) in values {
+            let acc = self.series.entry(key).or_insert(Accumulator {
+                min: value,
+                max: value,
+                sum: 0.0,
+                count: 0,
+            });
+            acc.min = acc.min.min(value);
+            acc.max = acc.max.max(value);
+            acc.sum += value;
+            acc.count += 1;
+        }
+    }
+
+    fn into_message(self) -> MqttMessage {
+        let mut payload = Map::new();
+        for ((group, name), acc) in self.series {
+            let value = match self.aggregate {
+                Aggregate::Min => acc.min,
+                Aggregate::Max => acc.max,
+                Aggregate::Avg => acc.sum / acc.count as f64,
+            };
+            if group.is_empty() {
+                payload.insert(name, value.into());
+            } else if let Value::Object(group) = payload
+                .entry(group)
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                group.insert(name, value.into());
+            }
+        }
+        if let Ok(time) = self.end.format(&Rfc3339) {
+            payload.insert("time".to_string(), time.into());
+        }
+        MqttMessage::new(&self.topic, Value::Object(payload).to_string())
+    }
+}
+
+/// Extract the numeric values of a thin-edge JSON measurement
+fn series_values(payload: &str) -> Option<BTreeMap<SeriesKey, f64>> {
+    let Ok(Value::Object(measurement)) = serde_json::from_str(payload) else {
+        return None;
+    };
+    let mut values = BTreeMap::new();
+    for (name, value) in measurement {
+        match value {
+            _ if name == "time" => (),
+            Value::Number(number) => {
+                values.insert((String::new(), name), number.as_f64()?);
+            }
+            Value::Object(group) => {
+                for (series, value) in group {
+                    if let Some(value) = value.as_f64() {
+                        values.insert((name.clone(), series), value);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Some(values)
+}
+
+fn within_deadband(
+    last_values: &BTreeMap<SeriesKey, f64>,
+    values: &BTreeMap<SeriesKey, f64>,
+    deadband: f64,
+) -> bool {
+    last_values.len() == values.len()
+        && values.iter().all(|(key, value)| {
+            last_values
+                .get(key)
+                .is_some_and(|last| (value - last).abs() <= deadband)
+        })
+}
+
+/// The MQTT actor as seen by a mapper, the telemetry messages being decoded and filtered before reaching the mapper
+///
+/// The [PayloadDecoder] and the [TelemetryFilter] are owned by the `FilteredMqtt`,
+/// and the actors are only released by [FilteredMqtt::try_build],
+/// once connected to a single mapper.
+pub struct FilteredMqtt<'a, M> {
+    mqtt: &'a mut M,
+    decoder: ConvertingActorBuilder<PayloadDecoder, NoConfig>,
+    filter: ConvertingActorBuilder<TelemetryFilter, NoConfig>,
+    timer_sender: DynSender<WindowTimer>,
+    mappers: usize,
+}
+
+/// The actors processing the telemetry messages before their translation by a mapper
+pub struct TelemetryActors {
+    pub decoder: ConvertingActorBuilder<PayloadDecoder, NoConfig>,
+    pub filter: ConvertingActorBuilder<TelemetryFilter, NoConfig>,
+}
+
+impl<'a, M> FilteredMqtt<'a, M> {
+    pub fn new(
+        mqtt: &'a mut M,
+        timer: &mut impl ServiceProvider<WindowTimer, WindowTimeout, NoConfig>,
+        filter: TelemetryFilter,
+    ) -> Self {
+        let decoder = PayloadDecoder::new(filter.mqtt_schema.clone());
+        let mut decoder = ConvertingActor::builder("PayloadDecoder", decoder, NoConfig);
+        let mut filter = ConvertingActor::builder("TelemetryFilter", filter, NoConfig);
+        filter.add_input(&mut decoder);
+        let timer_sender = timer.connect_consumer(NoConfig, filter_sender(&filter));
+        FilteredMqtt {
+            mqtt,
+            decoder,
+            filter,
+            timer_sender,
+            mappers: 0,
+        }
+    }
+
+    /// Connect the mapper to the filter, returning the sender to be connected to the MQTT actor
+    ///
+    /// Only the first mapper is connected, any other one making [FilteredMqtt::try_build] fail.
+    fn connect_mapper(&mut self, sender: DynSender<MqttMessage>) -> Option<DynSender<MqttMessage>> {
+        self.mappers += 1;
+        if self.mappers > 1 {
+            return None;
+        }
+        let output_sender = FilterOutputSender {
+            mapper: sender,
+            timer: self.timer_sender.sender_clone(),
+        };
+        self.filter.register_peer(NoConfig, Box::new(output_sender));
+        Some(MessageSink::<MqttMessage, NoConfig>::get_sender(
+            &self.decoder,
+        ))
+    }
+}
+
+fn filter_sender<N>(filter: &ConvertingActorBuilder<TelemetryFilter, NoConfig>) -> DynSender<N>
+where
+    N: tedge_actors::Message + Into<FilterInput>,
+{
+    tedge_actors::adapt(&MessageSink::<FilterInput, NoConfig>::get_sender(filter))
+}
+
+impl<'a, M> Builder<TelemetryActors> for FilteredMqtt<'a, M> {
+    type Error = LinkError;
+
+    fn try_build(self) -> Result<TelemetryActors, Self::Error> {
+        match self.mappers {
+            0 => Err(LinkError::MissingPeer {
+                role: "mapper".to_string(),
+            }),
+            1 => Ok(TelemetryActors {
+                decoder: self.decoder,
+                filter: self.filter,
+            }),
+            _ => Err(LinkError::ExcessPeer {
+                role: "mapper".to_string(),
+            }),
+        }
+    }
+}
+
+impl<'a, M> ServiceProvider<MqttMessage, MqttMessage, TopicFilter> for FilteredMqtt<'a, M>
+where
+    M: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+{
+    fn connect_consumer(
+        &mut self,
+        subscriptions: TopicFilter,
+        response_sender: DynSender<MqttMessage>,
+    ) -> DynSender<MqttMessage> {
+        match self.connect_mapper(response_sender) {
+            Some(decoder_sender) => self.mqtt.connect_consumer(subscriptions, decoder_sender),
+            None => Box::new(tedge_actors::NullSender),
+        }
+    }
+}
+
+impl<'a, M> MessageSource<MqttMessage, TopicFilter> for FilteredMqtt<'a, M>
+where
+    M: MessageSource<MqttMessage, TopicFilter>,
+{
+    fn register_peer(&mut self, subscriptions: TopicFilter, sender: DynSender<MqttMessage>) {
+        if let Some(decoder_sender) = self.connect_mapper(sender) {
+            self.mqtt.register_peer(subscriptions, decoder_sender)
+        }
+    }
+}
+
+/// Dispatch the output of the filter: the messages to the mapper and the timer requests to the timer
+struct FilterOutputSender {
+    mapper: DynSender<MqttMessage>,
+    timer: DynSender<WindowTimer>,
+}
+
+#[async_trait::async_trait]
+impl Sender<FilterOutput> for FilterOutputSender {
+    async fn send(&mut self, message: FilterOutput) -> Result<(), ChannelError> {
+        match message {
+            FilterOutput::MqttMessage(message) => self.mapper.send(message).await,
+            FilterOutput::WindowTimer(timer) => self.timer.send(timer).await,
+        }
+    }
+
+    fn sender_clone(&self) -> DynSender<FilterOutput> {
+        Box::new(FilterOutputSender {
+            mapper: self.mapper.sender_clone(),
+            timer: self.timer.sender_clone(),
+        })
+    }
+
+    /// Only the mapper is notified of the end of the filter, the timer being shared with other actors
+    fn close_sender(&mut self) {
+        self.mapper.as_mut().close_sender()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use tedge_actors::test_helpers::MessageReceiverExt;
+    use tedge_actors::Actor;
+    use tedge_actors::MessageReceiver;
+    use tedge_actors::SimpleMessageBoxBuilder;
+    use tedge_timer_ext::TimerActor;
+    use time::macros::datetime;
+    use time::Duration;
+
+    #[derive(Clone)]
+    struct TestClock(Arc<Mutex<Timestamp>>);
+
+    impl TestClock {
+        fn new() -> Self {
+            TestClock(Arc::new(Mutex::new(datetime!(2023-10-18 10:00:00 UTC))))
+        }
+
+        fn advance(&self, seconds: f64) {
+            *self.0.lock().unwrap() += Duration::seconds_f64(seconds);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> Timestamp {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn filter(rules: &str) -> (TelemetryFilter, TestClock) {
+        let clock = TestClock::new();
+        let rules = FilterRules::from_toml(rules).unwrap();
+        let filter = TelemetryFilter::new(MqttSchema::default(), rules, Box::new(clock.clone()));
+        (filter, clock)
+    }
+
+    fn wall_clock_filter(rules: &str) -> TelemetryFilter {
+        let rules = FilterRules::from_toml(rules).unwrap();
+        TelemetryFilter::new(MqttSchema::default(), rules, Box::new(clock::WallClock))
+    }
+
+    fn measurement(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+
+    #[test]
+    fn drop_the_measurements_of_an_entity() {
+        let (mut filter, _) = filter(
+            r#"
+            [[rules]]
+            entity = "device/child01"
+            drop = true
+            "#,
+        );
+
+        let dropped = measurement("te/device/child01///m/env", r#"{"temperature": 21}"#);
+        let kept = measurement("te/device/child02///m/env", r#"{"temperature": 21}"#);
+        let event = measurement("te/device/child01///e/login", r#"{"text": "logged in"}"#);
+        assert_eq!(filter.filter_message(&dropped), vec![]);
+        assert_eq!(filter.filter_message(&kept), vec![kept]);
+        assert_eq!(filter.filter_message(&event), vec![event]);
+    }
+
+    #[test]
+    fn rate_limit_the_measurements_of_a_type() {
+        let (mut filter, clock) = filter(
+            r#"
+            [[rules]]
+            type = "vibration"
+            interval = 1.0
+            "#,
+        );
+
+        let m = measurement("te/device/main///m/vibration", r#"{"x": 1}"#);
+        assert_eq!(filter.filter_message(&m), vec![m.clone()]);
+        clock.advance(0.1);
+        assert_eq!(filter.filter_message(&m), vec![]);
+        clock.advance(0.9);
+        assert_eq!(filter.filter_message(&m), vec![m.clone()]);
+
+        let other_type = measurement("te/device/main///m/environment", r#"{"x": 1}"#);
+        assert_eq!(filter.filter_message(&other_type), vec![other_type]);
+    }
+
+    #[test]
+    fn forward_measurements_only_when_out_of_deadband() {
+        let (mut filter, _) = filter(
+            r#"
+            [[rules]]
+            deadband = 0.5
+            "#,
+        );
+
+        let m1 = measurement(
+            "te/device/main///m/",
+            r#"{"temperature": 21.0, "env": {"humidity": 40}}"#,
+        );
+        let m2 = measurement(
+            "te/device/main///m/",
+            r#"{"temperature": 21.4, "env": {"humidity": 40}}"#,
+        );
+        let m3 = measurement(
+            "te/device/main///m/",
+            r#"{"temperature": 21.4, "env": {"humidity": 41}}"#,
+        );
+        let m4 = measurement("te/device/main///m/", r#"{"temperature": 21.4}"#);
+        assert_eq!(filter.filter_message(&m1), vec![m1]);
+        assert_eq!(filter.filter_message(&m2), vec![]);
+        assert_eq!(filter.filter_message(&m3), vec![m3]);
+        // A change of the set of series is always forwarded
+        assert_eq!(filter.filter_message(&m4), vec![m4]);
+    }
+
+    #[test]
+    fn windows_that_cannot_be_represented_are_not_opened() {
+        let (mut filter, _clock) = filter(
+            r#"
+            [[rules]]
+            aggregate = "avg"
+            window = 1e300
+            "#,
+        );
+
+        let m = measurement("te/device/main///m/env", r#"{"temperature": 20}"#);
+        assert_eq!(filter.filter_message(&m), vec![]);
+        assert!(filter.pending_aggregates().is_empty());
+    }
+
+    #[test]
+    fn aggregate_measurements_over_windows() {
+        let (mut filter, clock) = filter(
+            r#"
+            [[rules]]
+            entity = "device/main//"
+            type = "env"
+            aggregate = "avg"
+            window = 60
+            "#,
+        );
+
+        let topic = "te/device/main///m/env";
+        for value in [20, 22, 24] {
+            let m = measurement(
+                topic,
+                &format!(r#"{{"temperature": {value}, "g": {{"h": 1}}}}"#),
+            );
+            assert_eq!(filter.filter_message(&m), vec![]);
+            clock.advance(20.0);
+        }
+
+        let m = measurement(topic, r#"{"temperature": 30}"#);
+        let aggregates = filter.filter_message(&m);
+        assert_eq!(
+            aggregates,
+            vec![measurement(
+                topic,
+                r#"{"g":{"h":1.0},"temperature":22.0,"time":"2023-10-18T10:01:00Z"}"#
+            )]
+        );
+
+        // The pending window is published on shutdown
+        assert_eq!(
+            filter.pending_aggregates(),
+            vec![measurement(
+                topic,
+                r#"{"temperature":30.0,"time":"2023-10-18T10:02:00Z"}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn publish_aggregates_when_windows_end() {
+        let (mut filter, clock) = filter(
+            r#"
+            [[rules]]
+            type = "env"
+            aggregate = "max"
+            window = 10
+            "#,
+        );
+
+        let topic = "te/device/main///m/env";
+        let m = measurement(topic, r#"{"temperature": 21, "time": 1697623200}"#);
+        assert_eq!(filter.filter_message(&m), vec![]);
+        clock.advance(9.0);
+        assert_eq!(filter.flush_ended_windows(), vec![]);
+
+        clock.advance(1.0);
+        assert_eq!(
+            filter.flush_ended_windows(),
+            vec![measurement(
+                topic,
+                r#"{"temperature":21.0,"time":"2023-10-18T10:00:10Z"}"#
+            )]
+        );
+        assert_eq!(filter.pending_aggregates(), vec![]);
+    }
+
+    #[test]
+    fn window_timers_are_requested_when_windows_are_opened() {
+        let (mut filter, clock) = filter(
+            r#"
+            [[rules]]
+            type = "env"
+            aggregate = "max"
+            window = 10
+            "#,
+        );
+
+        let topic = "te/device/main///m/env";
+        let m: FilterInput = measurement(topic, r#"{"temperature": 21}"#).into();
+        let output = filter.convert(&m).unwrap();
+        assert!(matches!(
+            output.as_slice(),
+            [FilterOutput::WindowTimer(timer)] if timer.duration == std::time::Duration::from_secs(10)
+        ));
+
+        // No timer is requested while the window is open
+        clock.advance(5.0);
+        assert!(filter.convert(&m).unwrap().is_empty());
+
+        // The aggregate is published on timeout
+        clock.advance(5.0);
+        let output = filter
+            .convert(&WindowTimeout::new(WindowEnd).into())
+            .unwrap();
+        assert!(matches!(
+            output.as_slice(),
+            [FilterOutput::MqttMessage(aggregate)] if aggregate.topic.name == topic
+        ));
+    }
+
+    #[test]
+    fn a_filter_is_connected_to_a_single_mapper() {
+        let mut mqtt: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+            SimpleMessageBoxBuilder::new("MQTT", 16);
+        let mut timer = TimerActor::builder();
+        let first: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+            SimpleMessageBoxBuilder::new("FirstMapper", 16);
+        let second: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+            SimpleMessageBoxBuilder::new("SecondMapper", 16);
+
+        let mut filtered_mqtt = FilteredMqtt::new(&mut mqtt, &mut timer, wall_clock_filter(""));
+        filtered_mqtt.connect_consumer(TopicFilter::empty(), first.get_sender());
+        assert!(filtered_mqtt.try_build().is_ok());
+
+        let filtered_mqtt = FilteredMqtt::new(&mut mqtt, &mut timer, wall_clock_filter(""));
+        assert!(matches!(
+            filtered_mqtt.try_build(),
+            Err(LinkError::MissingPeer { .. })
+        ));
+
+        let mut filtered_mqtt = FilteredMqtt::new(&mut mqtt, &mut timer, wall_clock_filter(""));
+        filtered_mqtt.connect_consumer(TopicFilter::empty(), first.get_sender());
+        filtered_mqtt.connect_consumer(TopicFilter::empty(), second.get_sender());
+        assert!(matches!(
+            filtered_mqtt.try_build(),
+            Err(LinkError::ExcessPeer { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn the_actors_publish_aggregates_without_waiting_for_more_measurements() {
+        let filter = wall_clock_filter(
+            r#"
+            [[rules]]
+            aggregate = "avg"
+            window = 0.2
+            "#,
+        );
+        let mut mqtt: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+            SimpleMessageBoxBuilder::new("MQTT", 16);
+        let mut timer = TimerActor::builder();
+        let mapper: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+            SimpleMessageBoxBuilder::new("Mapper", 16);
+        let mut filtered_mqtt = FilteredMqtt::new(&mut mqtt, &mut timer, filter);
+        filtered_mqtt.connect_consumer(TopicFilter::empty(), mapper.get_sender());
+        let actors = filtered_mqtt.build();
+
+        let mut mqtt = mqtt.build();
+        let mut mapper = mapper
+            .build()
+            .with_timeout(std::time::Duration::from_secs(1));
+        let decoder = actors.decoder.build();
+        let filter = actors.filter.build();
+        let timer = timer.build();
+        tokio::spawn(async move { decoder.run().await });
+        tokio::spawn(async move { filter.run().await });
+        tokio::spawn(async move { timer.run().await });
+
+        let topic = "te/device/main///m/env";
+        for value in [20, 22] {
+            mqtt.send(measurement(
+                topic,
+                &format!(r#"{{"temperature": {value}}}"#),
+            ))
+            .await
+            .unwrap();
+        }
+
+        let aggregate = mapper.recv().await.unwrap();
+        assert_eq!(aggregate.topic.name, topic);
+        let payload: Value = serde_json::from_str(aggregate.payload_str().unwrap()).unwrap();
+        assert_eq!(payload["temperature"], 21.0);
+        assert!(payload["time"].is_string());
+    }
+
+    #[test]
+    fn invalid_measurements_are_forwarded_to_the_mapper() {
+        let (mut filter, _) = filter(
+            r#"
+            [[rules]]
+            deadband = 0.5
+            "#,
+        );
+        let invalid = measurement("te/device/main///m/", "not json");
+        assert_eq!(filter.filter_message(&invalid), vec![invalid.clone()]);
+        assert_eq!(filter.filter_message(&invalid), vec![invalid]);
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        assert!(FilterRules::from_toml("[[rules]]\naggregate = \"avg\"").is_err());
+        assert!(FilterRules::from_toml("[[rules]]\nwindow = 10").is_err());
+        assert!(FilterRules::from_toml("[[rules]]\ninterval = -1").is_err());
+        assert!(FilterRules::from_toml("[[rules]]\nentity = \"a/b/c/d/e\"").is_err());
+        assert!(FilterRules::from_toml("[[rules]]\nunknown = 1").is_err());
+        assert!(FilterRules::from_toml("").unwrap().rules.is_empty());
+    }
+
+    #[test]
+    fn reject_non_finite_or_empty_durations() {
+        for setting in ["interval", "deadband"] {
+            for value in ["nan", "inf", "-inf", "-1"] {
+                let toml = format!("[[rules]]\n{setting} = {value}");
+                assert!(FilterRules::from_toml(&toml).is_err(), "{toml}");
+            }
+            assert!(FilterRules::from_toml(&format!("[[rules]]\n{setting} = 0")).is_ok());
+        }
+        for value in ["nan", "inf", "-inf", "-1", "0", "0.0"] {
+            let toml = format!("[[rules]]\naggregate = \"avg\"\nwindow = {value}");
+            assert!(FilterRules::from_toml(&toml).is_err(), "{toml}");
+        }
+        assert!(FilterRules::from_toml("[[rules]]\naggregate = \"avg\"\nwindow = 0.5").is_ok());
+    }
+}
@@ -1,15 +1,23 @@
+use std::path::Path;
 #[cfg(test)]
 use std::result::Result::Ok;
 use tedge_actors::Runtime;
+use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::DeviceTopicId;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::Service;
 use tedge_api::mqtt_topics::ServiceTopicId;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfig;
 use tedge_health_ext::HealthMonitorBuilder;
 use tedge_mqtt_ext::MqttActorBuilder;
+use tedge_mqtt_ext::TopicFilter;
 use tedge_signal_ext::SignalActor;
+use tedge_store_forward_ext::StoreForwardConfig;
+
+const STORE_FORWARD_SPOOL_FILE: &str = "store_forward.jsonl";
+const STORE_FORWARD_METRICS_TYPE: &str = "store_forward";
 
 pub async fn start_basic_actors(
     mapper_name: &str,
@@ -43,6 +51,16 @@ pub async fn start_basic_actors(
     Ok((runtime, mqtt_actor))
 }
 
+/// The name of a mapper instance, suffixed by the cloud profile if any, e.g. `tedge-mapper-c8y@staging`
+///
+/// This name is used for the MQTT session, the health status and the state directory of the mapper.
+pub fn mapper_instance_name(mapper_name: &str, profile: Option<&ProfileName>) -> String {
+    match profile {
+        None => mapper_name.to_string(),
+        Some(profile) => format!("{mapper_name}@{profile}"),
+    }
+}
+
 async fn get_mqtt_actor(
     session_name: &str,
     tedge_config: &TEdgeConfig,
@@ -53,3 +71,37 @@ async fn get_mqtt_actor(
         mqtt_config.with_session_name(session_name),
     ))
 }
+
+/// The configuration of the actor spooling the messages sent to the cloud while the bridge is down
+///
+/// Return `None` when store-and-forward is disabled, the mapper then publishing directly to the MQTT actor.
+pub fn store_forward_config(
+    mapper_name: &str,
+    cloud_prefix: &str,
+    state_dir: &Path,
+    enable: bool,
+    max_messages: u32,
+    replay_rate: u32,
+    mqtt_schema: &MqttSchema,
+) -> Result<Option<StoreForwardConfig>, anyhow::Error> {
+    if !enable {
+        return Ok(None);
+    }
+
+    let bridge = EntityTopicId::default_main_service(&format!("mosquitto-{cloud_prefix}-bridge"))?;
+    let mapper = EntityTopicId::default_main_service(mapper_name)?;
+
+    Ok(Some(StoreForwardConfig {
+        bridge_health_topic: mqtt_schema.topic_for(&bridge, &Channel::Health),
+        spooled_topics: TopicFilter::new(&format!("{cloud_prefix}/#"))?,
+        spool_path: state_dir.join(STORE_FORWARD_SPOOL_FILE),
+        max_messages: max_messages as usize,
+        replay_rate,
+        metrics_topic: Some(mqtt_schema.topic_for(
+            &mapper,
+            &Channel::Measurement {
+                measurement_type: STORE_FORWARD_METRICS_TYPE.to_string(),
+            },
+        )),
+    }))
+}
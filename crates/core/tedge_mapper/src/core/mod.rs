@@ -1,2 +1,5 @@
 pub mod component;
+pub mod decoder;
+pub mod filter;
 pub mod mapper;
+pub mod transform;
@@ -0,0 +1,907 @@
+//! Declarative transformation of arbitrary MQTT messages into thin-edge messages
+//!
+//! The transformation rules are read from the `[[transform]]` tables of the `/etc/tedge/mappers/*.toml` files:
+//!
+//! ```toml
+//! # Translate the JSON payloads published by a sensor on `sensors/<id>/data`
+//! # into measurements of the child device `<id>`
+//! [[transform]]
+//! topic = "sensors/+/data"
+//! output = "te/device/{1}///m/environment"
+//!
+//! [transform.payload]
+//! time = "$.ts"
+//! temperature = "$.readings.temp"
+//! humidity = "$.readings[1]"
+//! source = "sensor {1}"
+//! ```
+//!
+//! - `topic` is the MQTT topic filter of the input messages.
+//! - `output` is the thin-edge topic on which the transformed messages are published.
+//!   The `{1}`, `{2}`, ... placeholders are replaced by the topic segments matched
+//!   by the first, second, ... wildcards of the input topic filter.
+//! - `payload` is the template of the JSON payload of the transformed messages.
+//!   A string starting with `$` is a JSON path, e.g. `$.readings[0].value`, replaced by the value found
+//!   at that path in the JSON input payload, the field being omitted if there is no such value.
+//!   A string starting with `$$` is a literal string starting with a single `$`.
+//!   The other values are copied as is, the `{1}`, `{2}`, ... placeholders being replaced in strings.
+//!   When omitted, the input payload is published unchanged.
+//!
+//! All the rules matching a message are applied, each publishing a transformed message.
+//! The messages published on thin-edge topics are never transformed, to avoid loops.
+//!
+//! The rules are reloaded when a file of the mappers directory is updated.
+//! However, the input topics are subscribed to on start, the MQTT connection not supporting new subscriptions,
+//! so the mapper has to be restarted to transform the messages of a new topic,
+//! unless this topic is covered by the subscriptions made on start (e.g. `sensors/+/data` by `sensors/#`).
+//! A warning is logged for the reloaded rules whose topics are not covered.
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Map;
+use serde_json::Value;
+use std::path::Path;
+use std::path::PathBuf;
+use tedge_actors::adapt;
+use tedge_actors::fan_in_message_type;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::LinkError;
+use tedge_actors::LoggingSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::Sender;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_file_system_ext::FsWatchEvent;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+use tracing::info;
+use tracing::warn;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransformError {
+    #[error("Fail to read the transformation rules from {path}: {error}")]
+    FromIo { path: String, error: std::io::Error },
+
+    #[error("Invalid transformation rules in {path}: {error}")]
+    FromToml {
+        path: String,
+        error: toml::de::Error,
+    },
+
+    #[error("Invalid transformation rule #{index} in {path}: {reason}")]
+    InvalidRule {
+        path: String,
+        index: usize,
+        reason: String,
+    },
+}
+
+/// The content of a rule file, the tables other than `[[transform]]` being used by other mappers
+#[derive(Debug, Default, Deserialize)]
+struct TransformFile {
+    #[serde(default)]
+    transform: Vec<TransformRule>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformRule {
+    /// The topic filter of the input messages
+    pub topic: String,
+
+    /// The thin-edge topic of the transformed messages
+    pub output: String,
+
+    /// The template of the transformed payloads
+    pub payload: Option<Value>,
+}
+
+/// The transformation rules of all the rule files
+#[derive(Debug, Default)]
+pub struct TransformRules {
+    rules: Vec<CompiledRule>,
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    topic: String,
+    filter: Vec<String>,
+    output: Text,
+    payload: Option<Template>,
+}
+
+/// A string with `{n}` placeholders
+#[derive(Debug, PartialEq)]
+struct Text(Vec<TextPart>);
+
+#[derive(Debug, PartialEq)]
+enum TextPart {
+    Literal(String),
+    Capture(usize),
+}
+
+#[derive(Debug, PartialEq)]
+enum Template {
+    Literal(Value),
+    Text(Text),
+    Path(Vec<PathSegment>),
+    Array(Vec<Template>),
+    Object(Vec<(String, Template)>),
+}
+
+#[derive(Debug, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl TransformRules {
+    /// Load the rules of all the TOML files of a directory
+    ///
+    /// The files with invalid rules are ignored, with a warning.
+    pub fn load_dir(mqtt_schema: &MqttSchema, dir: &Path) -> Self {
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| is_rule_file(path))
+                .collect(),
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Fail to read the transformation rules from {dir:?}: {err}");
+                }
+                vec![]
+            }
+        };
+        paths.sort();
+
+        let mut rules = TransformRules::default();
+        for path in paths {
+            match TransformRules::load(mqtt_schema, &path) {
+                Ok(file_rules) => rules.rules.extend(file_rules.rules),
+                Err(err) => warn!("Ignoring the transformation rules of {path:?}: {err}"),
+            }
+        }
+        rules
+    }
+
+    /// Load the rules from a TOML file
+    pub fn load(mqtt_schema: &MqttSchema, path: &Path) -> Result<Self, TransformError> {
+        let content = std::fs::read_to_string(path).map_err(|error| TransformError::FromIo {
+            path: path.display().to_string(),
+            error,
+        })?;
+        TransformRules::from_toml(mqtt_schema, &content).map_err(|err| match err {
+            TransformError::FromToml { error, .. } => TransformError::FromToml {
+                path: path.display().to_string(),
+                error,
+            },
+            TransformError::InvalidRule { index, reason, .. } => TransformError::InvalidRule {
+                path: path.display().to_string(),
+                index,
+                reason,
+            },
+            err => err,
+        })
+    }
+
+    pub fn from_toml(mqtt_schema: &MqttSchema, content: &str) -> Result<Self, TransformError> {
+        let file: TransformFile =
+            toml::from_str(content).map_err(|error| TransformError::FromToml {
+                path: "<string>".to_string(),
+                error,
+            })?;
+        let rules = file
+            .transform
+            .into_iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                CompiledRule::compile(mqtt_schema, rule).map_err(|reason| {
+                    TransformError::InvalidRule {
+                        path: "<string>".to_string(),
+                        index,
+                        reason,
+                    }
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(TransformRules { rules })
+    }
+
+    /// The topic filters of the input messages
+    pub fn subscriptions(&self) -> TopicFilter {
+        let mut subscriptions = TopicFilter::empty();
+        for rule in self.rules.iter() {
+            subscriptions.add_unchecked(&rule.topic);
+        }
+        subscriptions
+    }
+
+    /// Apply all the matching rules to a message
+    pub fn transform(&self, mqtt_schema: &MqttSchema, input: &MqttMessage) -> Vec<MqttMessage> {
+        if is_thin_edge_topic(mqtt_schema, &input.topic.name) {
+            return vec![];
+        }
+        let mut json_payload = None;
+        let mut messages = vec![];
+        for rule in self.rules.iter() {
+            let Some(captures) = captures(&rule.filter, &input.topic.name) else {
+                continue;
+            };
+            let topic = rule.output.render(&captures);
+            let topic = match Topic::new(&topic) {
+                Ok(topic) if mqtt_schema.entity_channel_of(&topic).is_ok() => topic,
+                _ => {
+                    warn!(
+                        "Ignoring the message on {}: {topic} is not a thin-edge topic",
+                        input.topic.name
+                    );
+                    continue;
+                }
+            };
+
+            let Some(template) = &rule.payload else {
+                messages.push(MqttMessage::new(&topic, input.payload_bytes()));
+                continue;
+            };
+            let payload = json_payload.get_or_insert_with(|| {
+                input
+                    .payload_str()
+                    .ok()
+                    .and_then(|payload| serde_json::from_str::<Value>(payload).ok())
+            });
+            let Some(payload) = payload else {
+                warn!(
+                    "Ignoring the message on {} for {}: the payload is not JSON",
+                    input.topic.name, topic.name
+                );
+                continue;
+            };
+            if let Some(output) = template.render(payload, &captures) {
+                messages.push(MqttMessage::new(&topic, output.to_string()));
+            }
+        }
+        messages
+    }
+}
+
+impl CompiledRule {
+    fn compile(mqtt_schema: &MqttSchema, rule: TransformRule) -> Result<Self, String> {
+        TopicFilter::new(&rule.topic)
+            .map_err(|err| format!("invalid topic {}: {err}", rule.topic))?;
+        if is_thin_edge_topic(mqtt_schema, &rule.topic) {
+            return Err(format!(
+                "the input topic {} cannot be a thin-edge topic",
+                rule.topic
+            ));
+        }
+        let filter: Vec<String> = rule.topic.split('/').map(str::to_string).collect();
+        let wildcards = filter
+            .iter()
+            .filter(|segment| *segment == "+" || *segment == "#")
+            .count();
+
+        let output = Text::parse(&rule.output, wildcards)?;
+        let sample_captures = vec!["x".to_string(); wildcards];
+        let sample_topic = output.render(&sample_captures);
+        let is_thin_edge_output = Topic::new(&sample_topic)
+            .is_ok_and(|topic| mqtt_schema.entity_channel_of(&topic).is_ok());
+        if !is_thin_edge_output {
+            return Err(format!(
+                "the output {} is not a thin-edge topic",
+                rule.output
+            ));
+        }
+
+        let payload = rule
+            .payload
+            .map(|payload| Template::compile(payload, wildcards))
+            .transpose()?;
+
+        Ok(CompiledRule {
+            topic: rule.topic,
+            filter,
+            output,
+            payload,
+        })
+    }
+}
+
+impl Text {
+    fn parse(text: &str, wildcards: usize) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let placeholder = rest[start + 1..]
+                .find('}')
+                .map(|end| &rest[start + 1..start + 1 + end])
+                .filter(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()));
+            match placeholder {
+                Some(index) => {
+                    let capture: usize = index
+                        .parse()
+                        .map_err(|_| format!("invalid placeholder {{{index}}}"))?;
+                    if capture == 0 || capture > wildcards {
+                        return Err(format!(
+                            "the placeholder {{{index}}} matches no wildcard of the input topic"
+                        ));
+                    }
+                    literal.push_str(&rest[..start]);
+                    if !literal.is_empty() {
+                        parts.push(TextPart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TextPart::Capture(capture - 1));
+                    rest = &rest[start + index.len() + 2..];
+                }
+                None => {
+                    literal.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(TextPart::Literal(literal));
+        }
+        Ok(Text(parts))
+    }
+
+    fn render(&self, captures: &[String]) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                TextPart::Literal(text) => text.as_str(),
+                TextPart::Capture(index) => captures[*index].as_str(),
+            })
+            .collect()
+    }
+}
+
+impl Template {
+    fn compile(value: Value, wildcards: usize) -> Result<Self, String> {
+        match value {
+            Value::String(text) if text.starts_with("$$") => {
+                Ok(Template::Text(Text::parse(&text[1..], wildcards)?))
+            }
+            Value::String(path) if path.starts_with('$') => Ok(Template::Path(parse_path(&path)?)),
+            Value::String(text) => Ok(Template::Text(Text::parse(&text, wildcards)?)),
+            Value::Array(items) => Ok(Template::Array(
+                items
+                    .into_iter()
+                    .map(|item| Template::compile(item, wildcards))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Value::Object(fields) => Ok(Template::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, Template::compile(value, wildcards)?)))
+                    .collect::<Result<_, String>>()?,
+            )),
+            value => Ok(Template::Literal(value)),
+        }
+    }
+
+    /// Render the template, returning `None` if a JSON path matches nothing
+    fn render(&self, input: &Value, captures: &[String]) -> Option<Value> {
+        match self {
+            Template::Literal(value) => Some(value.clone()),
+            Template::Text(text) => Some(Value::String(text.render(captures))),
+            Template::Path(path) => select(input, path).cloned(),
+            Template::Array(items) => Some(Value::Array(
+                items
+                    .iter()
+                    .filter_map(|item| item.render(input, captures))
+                    .collect(),
+            )),
+            Template::Object(fields) => Some(Value::Object(
+                fields
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.clone(), value.render(input, captures)?))
+                    })
+                    .collect::<Map<_, _>>(),
+            )),
+        }
+    }
+}
+
+/// Parse a JSON path, e.g. `$.readings[0].value` or `$["a.b"]`
+fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let invalid = || format!("invalid JSON path {path}");
+    let mut segments = vec![];
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    while !rest.is_empty() {
+        if let Some(tail) = rest.strip_prefix('.') {
+            let end = tail.find(['.', '[']).unwrap_or(tail.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(PathSegment::Key(tail[..end].to_string()));
+            rest = &tail[end..];
+        } else if let Some(tail) = rest.strip_prefix('[') {
+            let end = tail.find(']').ok_or_else(invalid)?;
+            let index = &tail[..end];
+            let segment = if let Some(key) = index
+                .strip_prefix('"')
+                .and_then(|key| key.strip_suffix('"'))
+                .or_else(|| {
+                    index
+                        .strip_prefix('\'')
+                        .and_then(|key| key.strip_suffix('\''))
+                }) {
+                PathSegment::Key(key.to_string())
+            } else {
+                PathSegment::Index(index.parse().map_err(|_| invalid())?)
+            };
+            segments.push(segment);
+            rest = &tail[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(segments)
+}
+
+fn select<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        PathSegment::Key(key) => value.get(key),
+        PathSegment::Index(index) => value.get(index),
+    })
+}
+
+/// Match a topic against a topic filter, returning the topic segments matched by the wildcards
+fn captures(filter: &[String], topic: &str) -> Option<Vec<String>> {
+    let mut captures = vec![];
+    let mut segments = topic.split('/');
+    for pattern in filter {
+        match pattern.as_str() {
+            "#" => {
+                // `a/#` also matches `a`, capturing an empty string
+                let rest: Vec<&str> = segments.collect();
+                captures.push(rest.join("/"));
+                return Some(captures);
+            }
+            "+" => captures.push(segments.next()?.to_string()),
+            pattern => {
+                if segments.next()? != pattern {
+                    return None;
+                }
+            }
+        }
+    }
+    segments.next().is_none().then_some(captures)
+}
+
+/// Tell if all the topics matched by a topic filter are also matched by a subscription pattern
+fn covers(pattern: &str, filter: &str) -> bool {
+    let mut filter = filter.split('/');
+    for segment in pattern.split('/') {
+        match (segment, filter.next()) {
+            ("#", _) => return true,
+            ("+", Some(other)) if other != "#" => {}
+            (segment, Some(other)) if segment == other => {}
+            _ => return false,
+        }
+    }
+    filter.next().is_none()
+}
+
+fn is_thin_edge_topic(mqtt_schema: &MqttSchema, topic: &str) -> bool {
+    topic
+        .strip_prefix(&mqtt_schema.root)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn is_rule_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+}
+
+fan_in_message_type!(TransformInput[MqttMessage, FsWatchEvent] : Debug);
+
+/// An actor publishing the messages transformed according to [TransformRules]
+pub struct TransformActor {
+    mqtt_schema: MqttSchema,
+    rules_dir: PathBuf,
+    rules: TransformRules,
+    subscriptions: TopicFilter,
+    mqtt_publisher: LoggingSender<MqttMessage>,
+    messages: SimpleMessageBox<TransformInput, NoMessage>,
+}
+
+impl TransformActor {
+    async fn process_file_watch_event(&mut self, event: FsWatchEvent) {
+        let path = match event {
+            FsWatchEvent::Modified(path)
+            | FsWatchEvent::FileCreated(path)
+            | FsWatchEvent::FileDeleted(path) => path,
+            FsWatchEvent::DirectoryCreated(_) | FsWatchEvent::DirectoryDeleted(_) => return,
+        };
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            self.reload_rules();
+        }
+    }
+
+    fn reload_rules(&mut self) {
+        self.rules = TransformRules::load_dir(&self.mqtt_schema, &self.rules_dir);
+        for rule in self.rules.rules.iter() {
+            if !self
+                .subscriptions
+                .patterns
+                .iter()
+                .any(|pattern| covers(pattern, &rule.topic))
+            {
+                warn!(
+                    "The mapper has to be restarted to transform the messages received on {}",
+                    rule.topic
+                );
+            }
+        }
+        info!("Reloaded {} transformation rules", self.rules.rules.len());
+    }
+}
+
+#[async_trait]
+impl Actor for TransformActor {
+    fn name(&self) -> &str {
+        "Transformer"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        while let Some(input) = self.messages.recv().await {
+            match input {
+                TransformInput::MqttMessage(message) => {
+                    for output in self.rules.transform(&self.mqtt_schema, &message) {
+                        self.mqtt_publisher.send(output).await?;
+                    }
+                }
+                TransformInput::FsWatchEvent(event) => {
+                    self.process_file_watch_event(event).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct TransformActorBuilder {
+    mqtt_schema: MqttSchema,
+    rules_dir: PathBuf,
+    rules: TransformRules,
+    subscriptions: TopicFilter,
+    mqtt_publisher: DynSender<MqttMessage>,
+    box_builder: SimpleMessageBoxBuilder<TransformInput, NoMessage>,
+}
+
+impl TransformActorBuilder {
+    /// Load the rules from the given directory, subscribing to their input topics
+    /// and watching the directory for changes
+    pub fn new(
+        mqtt_schema: MqttSchema,
+        rules_dir: PathBuf,
+        mqtt: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+        fs_notify: &mut impl MessageSource<FsWatchEvent, PathBuf>,
+    ) -> Self {
+        let rules = TransformRules::load_dir(&mqtt_schema, &rules_dir);
+        let subscriptions = rules.subscriptions();
+        let box_builder = SimpleMessageBoxBuilder::new("Transformer", 16);
+        let mqtt_publisher =
+            mqtt.connect_consumer(subscriptions.clone(), adapt(&box_builder.get_sender()));
+        fs_notify.register_peer(rules_dir.clone(), adapt(&box_builder.get_sender()));
+
+        TransformActorBuilder {
+            mqtt_schema,
+            rules_dir,
+            rules,
+            subscriptions,
+            mqtt_publisher,
+            box_builder,
+        }
+    }
+}
+
+impl RuntimeRequestSink for TransformActorBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.box_builder.get_signal_sender()
+    }
+}
+
+impl Builder<TransformActor> for TransformActorBuilder {
+    type Error = LinkError;
+
+    fn try_build(self) -> Result<TransformActor, Self::Error> {
+        Ok(TransformActor {
+            mqtt_schema: self.mqtt_schema,
+            rules_dir: self.rules_dir,
+            rules: self.rules,
+            subscriptions: self.subscriptions,
+            mqtt_publisher: LoggingSender::new("Transformer".into(), self.mqtt_publisher),
+            messages: self.box_builder.build(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use tedge_actors::test_helpers::MessageReceiverExt;
+    use tedge_test_utils::fs::TempTedgeDir;
+
+    fn rules(toml: &str) -> TransformRules {
+        TransformRules::from_toml(&MqttSchema::default(), toml).unwrap()
+    }
+
+    fn message(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+
+    fn transform(rules: &TransformRules, input: &MqttMessage) -> Vec<(String, Value)> {
+        rules
+            .transform(&MqttSchema::default(), input)
+            .into_iter()
+            .map(|message| {
+                let payload = serde_json::from_str(message.payload_str().unwrap()).unwrap();
+                (message.topic.name, payload)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transform_a_sensor_payload_into_a_measurement() {
+        let rules = rules(
+            r#"
+            [[transform]]
+            topic = "sensors/+/data"
+            output = "te/device/{1}///m/environment"
+
+            [transform.payload]
+            time = "$.ts"
+            temperature = "$.readings.temp"
+            humidity = "$.readings.values[1]"
+            pressure = "$.readings.pressure"
+            source = "sensor {1}"
+            price = "$$5"
+            location = { lat = "$['gps'].lat", alt = 100 }
+            "#,
+        );
+
+        let input = message(
+            "sensors/s01/data",
+            r#"{"ts": 1701949168, "readings": {"temp": 21.5, "values": [1, 45]}, "gps": {"lat": 50.1}}"#,
+        );
+        assert_eq!(
+            transform(&rules, &input),
+            vec![(
+                "te/device/s01///m/environment".to_string(),
+                json!({
+                    "time": 1701949168,
+                    "temperature": 21.5,
+                    "humidity": 45,
+                    "source": "sensor s01",
+                    "price": "$5",
+                    "location": {"lat": 50.1, "alt": 100},
+                })
+            )]
+        );
+
+        let other = message("sensors/s01/status", r#"{"ts": 1701949168}"#);
+        assert!(transform(&rules, &other).is_empty());
+    }
+
+    #[test]
+    fn all_the_matching_rules_are_applied() {
+        let rules = rules(
+            r##"
+            [[transform]]
+            topic = "factory/+/alert"
+            output = "te/device/main///e/{1}_alert"
+            payload = { text = "$.message" }
+
+            [[transform]]
+            topic = "factory/line1/alert"
+            output = "te/device/line1///a/alert"
+            "##,
+        );
+
+        let input = message("factory/line1/alert", r#"{"message": "too hot"}"#);
+        assert_eq!(
+            transform(&rules, &input),
+            vec![
+                (
+                    "te/device/main///e/line1_alert".to_string(),
+                    json!({"text": "too hot"})
+                ),
+                (
+                    "te/device/line1///a/alert".to_string(),
+                    json!({"message": "too hot"})
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn thin_edge_messages_are_not_transformed() {
+        let rules = rules(
+            r##"
+            [[transform]]
+            topic = "#"
+            output = "te/device/main///e/copy"
+            "##,
+        );
+
+        let input = message("te/device/main///e/copy", r#"{"text": "copy"}"#);
+        assert!(transform(&rules, &input).is_empty());
+
+        let input = message("custom/topic", r#"{"text": "copy"}"#);
+        assert_eq!(transform(&rules, &input).len(), 1);
+    }
+
+    #[test]
+    fn invalid_payloads_and_outputs_are_ignored() {
+        let rules = rules(
+            r##"
+            [[transform]]
+            topic = "sensors/#"
+            output = "te/device/main///m/{1}"
+            payload = { temperature = "$.temp" }
+            "##,
+        );
+
+        let not_json = message("sensors/s01", "21.5 C");
+        let not_a_thin_edge_topic = message("sensors/s01/levels", r#"{"temp": 21.5}"#);
+        assert!(transform(&rules, &not_json).is_empty());
+        assert!(transform(&rules, &not_a_thin_edge_topic).is_empty());
+    }
+
+    #[test]
+    fn a_non_json_payload_is_passed_through_by_the_rules_without_template() {
+        let rules = rules(
+            r##"
+            [[transform]]
+            topic = "sensors/+"
+            output = "te/device/{1}///m/environment"
+            payload = { temperature = "$.temp" }
+
+            [[transform]]
+            topic = "sensors/+"
+            output = "te/device/{1}///e/raw"
+            "##,
+        );
+
+        let not_json = message("sensors/s01", "21.5 C");
+        assert_eq!(
+            rules.transform(&MqttSchema::default(), &not_json),
+            vec![message("te/device/s01///e/raw", "21.5 C")]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        for toml in [
+            "[[transform]]\ntopic = \"a/#/b\"\noutput = \"te/device/main///m/\"",
+            "[[transform]]\ntopic = \"te/device/+///m/raw\"\noutput = \"te/device/main///m/\"",
+            "[[transform]]\ntopic = \"a/+\"\noutput = \"c8y/s/us\"",
+            "[[transform]]\ntopic = \"a/+\"\noutput = \"te/device/{2}///m/\"",
+            "[[transform]]\ntopic = \"a/+\"\noutput = \"te/device/{1}///m/\"\npayload = { x = \"$.a[b]\" }",
+            "[[transform]]\ntopic = \"a\"\noutput = \"te/device/main///m/\"\nunknown = 1",
+        ] {
+            assert!(
+                TransformRules::from_toml(&MqttSchema::default(), toml).is_err(),
+                "{toml} should be rejected"
+            );
+        }
+
+        // The tables used by other mappers are ignored
+        assert!(rules("[[rules]]\ndrop = true").rules.is_empty());
+    }
+
+    #[test]
+    fn match_topic_filters() {
+        let filter = |f: &str| f.split('/').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            captures(&filter("a/+/c/+"), "a/b/c/d"),
+            Some(vec!["b".to_string(), "d".to_string()])
+        );
+        assert_eq!(
+            captures(&filter("a/#"), "a/b/c"),
+            Some(vec!["b/c".to_string()])
+        );
+        assert_eq!(captures(&filter("a/#"), "a"), Some(vec!["".to_string()]));
+        assert_eq!(captures(&filter("a/+"), "a/b/c"), None);
+        assert_eq!(captures(&filter("a/+/c"), "a/b"), None);
+        assert_eq!(captures(&filter("a/b"), "a/c"), None);
+    }
+
+    #[test]
+    fn check_topic_filters_covered_by_subscriptions() {
+        assert!(covers("a/b", "a/b"));
+        assert!(covers("a/+", "a/b"));
+        assert!(covers("a/+", "a/+"));
+        assert!(covers("a/#", "a"));
+        assert!(covers("a/#", "a/+/c"));
+        assert!(covers("a/#", "a/#"));
+        assert!(covers("#", "a/b"));
+        assert!(!covers("a/b", "a/+"));
+        assert!(!covers("a/+", "a/#"));
+        assert!(!covers("a/+", "a/b/c"));
+        assert!(!covers("a/+/c", "a/b"));
+        assert!(!covers("a/b", "a/c"));
+    }
+
+    #[tokio::test]
+    async fn rules_are_reloaded_when_updated() {
+        let ttd = TempTedgeDir::new();
+        let rules_dir = ttd.dir("mappers");
+        rules_dir.file("sensors.toml").with_raw_content(
+            r#"
+            [[transform]]
+            topic = "sensors/+"
+            output = "te/device/{1}///m/"
+            payload = { temperature = "$.t" }
+            "#,
+        );
+
+        let mut mqtt: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+            SimpleMessageBoxBuilder::new("MQTT", 16);
+        let mut fs: SimpleMessageBoxBuilder<NoMessage, FsWatchEvent> =
+            SimpleMessageBoxBuilder::new("FS", 16);
+        let builder = TransformActorBuilder::new(
+            MqttSchema::default(),
+            rules_dir.to_path_buf(),
+            &mut mqtt,
+            &mut fs,
+        );
+        assert_eq!(
+            builder.subscriptions.patterns,
+            vec!["sensors/+".to_string()]
+        );
+
+        let actor = builder.build();
+        let mut mqtt = mqtt.build().with_timeout(Duration::from_secs(1));
+        let mut fs = fs.build();
+        tokio::spawn(async move { actor.run().await });
+
+        mqtt.send(message("sensors/s01", r#"{"t": 21.5}"#))
+            .await
+            .unwrap();
+        let output = mqtt.recv().await.unwrap();
+        assert_eq!(output.topic.name, "te/device/s01///m/");
+        assert_eq!(output.payload_str().unwrap(), r#"{"temperature":21.5}"#);
+
+        rules_dir.file("sensors.toml").with_raw_content(
+            r#"
+            [[transform]]
+            topic = "sensors/+"
+            output = "te/device/{1}///m/"
+            payload = { temp = "$.t" }
+            "#,
+        );
+        fs.send(FsWatchEvent::Modified(
+            rules_dir.path().join("sensors.toml"),
+        ))
+        .await
+        .unwrap();
+        mqtt.send(message("sensors/s01", r#"{"t": 21.5}"#))
+            .await
+            .unwrap();
+        let output = mqtt.recv().await.unwrap();
+        assert_eq!(output.payload_str().unwrap(), r#"{"temp":21.5}"#);
+    }
+}
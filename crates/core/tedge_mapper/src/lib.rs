@@ -1,29 +1,41 @@
+use crate::aggregate::mapper::AggregateMapper;
 use crate::aws::mapper::AwsMapper;
 use crate::az::mapper::AzureMapper;
 use crate::c8y::mapper::CumulocityMapper;
 use crate::collectd::mapper::CollectdMapper;
 use crate::core::component::TEdgeComponent;
+use crate::core::mapper::mapper_instance_name;
+use crate::transform::mapper::TransformMapper;
 use clap::Parser;
 use flockfile::check_another_instance_is_not_running;
 use std::fmt;
 use std::path::PathBuf;
 use tedge_config::system_services::get_log_level;
 use tedge_config::system_services::set_log_level;
+use tedge_config::CloudType;
+use tedge_config::ProfileName;
 use tedge_config::DEFAULT_TEDGE_CONFIG_PATH;
 use tracing::log::warn;
 
+mod aggregate;
 mod aws;
 mod az;
 mod c8y;
 mod collectd;
 mod core;
+mod transform;
 
-fn lookup_component(component_name: &MapperName) -> Box<dyn TEdgeComponent> {
+fn lookup_component(
+    component_name: &MapperName,
+    profile: Option<&ProfileName>,
+) -> Box<dyn TEdgeComponent> {
     match component_name {
-        MapperName::Az => Box::new(AzureMapper),
-        MapperName::Aws => Box::new(AwsMapper),
+        MapperName::Az => Box::new(AzureMapper::new(profile)),
+        MapperName::Aws => Box::new(AwsMapper::new(profile)),
         MapperName::Collectd => Box::new(CollectdMapper),
-        MapperName::C8y => Box::new(CumulocityMapper),
+        MapperName::C8y => Box::new(CumulocityMapper::new(profile)),
+        MapperName::Aggregate => Box::new(AggregateMapper),
+        MapperName::Transform => Box::new(TransformMapper),
     }
 }
 
@@ -59,6 +71,12 @@ pub struct MapperOpt {
     /// WARNING: This is mostly used in testing.
     #[clap(long = "config-dir", default_value = DEFAULT_TEDGE_CONFIG_PATH)]
     pub config_dir: PathBuf,
+
+    /// The cloud profile to be used by the az, aws or c8y mapper, as configured under `<cloud>.profiles.<name>`
+    ///
+    /// The mapper instance of a profile is named after the profile, e.g. `tedge-mapper-c8y@<name>`.
+    #[clap(long, global = true)]
+    pub profile: Option<ProfileName>,
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -67,6 +85,22 @@ pub enum MapperName {
     Aws,
     C8y,
     Collectd,
+    /// Publish periodic aggregates of the measurements
+    Aggregate,
+    /// Transform arbitrary MQTT messages into thin-edge messages
+    Transform,
+}
+
+impl MapperName {
+    /// The cloud of a mapper that can be used with cloud profiles
+    fn cloud_type(&self) -> Option<CloudType> {
+        match self {
+            MapperName::Az => Some(CloudType::Az),
+            MapperName::Aws => Some(CloudType::Aws),
+            MapperName::C8y => Some(CloudType::C8y),
+            MapperName::Collectd | MapperName::Aggregate | MapperName::Transform => None,
+        }
+    }
 }
 
 impl fmt::Display for MapperName {
@@ -76,16 +110,26 @@ impl fmt::Display for MapperName {
             MapperName::Aws => write!(f, "tedge-mapper-aws"),
             MapperName::C8y => write!(f, "tedge-mapper-c8y"),
             MapperName::Collectd => write!(f, "tedge-mapper-collectd"),
+            MapperName::Aggregate => write!(f, "tedge-mapper-aggregate"),
+            MapperName::Transform => write!(f, "tedge-mapper-transform"),
         }
     }
 }
 
 pub async fn run(mapper_opt: MapperOpt) -> anyhow::Result<()> {
-    let component = lookup_component(&mapper_opt.name);
+    let profile = mapper_opt.profile.as_ref();
+    let component = lookup_component(&mapper_opt.name, profile);
 
     let tedge_config_location =
         tedge_config::TEdgeConfigLocation::from_custom_root(&mapper_opt.config_dir);
     let config = tedge_config::TEdgeConfigRepository::new(tedge_config_location.clone()).load()?;
+    let config = match (profile, mapper_opt.name.cloud_type()) {
+        (None, _) => config,
+        (Some(profile), Some(cloud)) => config.profile(cloud, profile)?,
+        (Some(_), None) => {
+            anyhow::bail!("The {} does not support cloud profiles", mapper_opt.name)
+        }
+    };
 
     let log_level = if mapper_opt.debug {
         tracing::Level::DEBUG
@@ -101,7 +145,8 @@ pub async fn run(mapper_opt: MapperOpt) -> anyhow::Result<()> {
     let mut _flock = None;
     if config.run.lock_files {
         let run_dir = config.run.path.as_std_path();
-        _flock = check_another_instance_is_not_running(&mapper_opt.name.to_string(), run_dir)?;
+        let instance_name = mapper_instance_name(&mapper_opt.name.to_string(), profile);
+        _flock = check_another_instance_is_not_running(&instance_name, run_dir)?;
     }
 
     if mapper_opt.init {
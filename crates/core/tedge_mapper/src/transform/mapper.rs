@@ -0,0 +1,47 @@
+use crate::core::component::TEdgeComponent;
+use crate::core::mapper::start_basic_actors;
+use crate::core::transform::TransformActorBuilder;
+use async_trait::async_trait;
+use std::path::Path;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::TEdgeConfig;
+use tedge_file_system_ext::FsWatchActorBuilder;
+use tedge_utils::file::create_directory_with_defaults;
+
+const TRANSFORM_MAPPER_NAME: &str = "tedge-mapper-transform";
+
+pub struct TransformMapper;
+
+#[async_trait]
+impl TEdgeComponent for TransformMapper {
+    fn session_name(&self) -> &str {
+        TRANSFORM_MAPPER_NAME
+    }
+
+    async fn start(
+        &self,
+        tedge_config: TEdgeConfig,
+        config_dir: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let (mut runtime, mut mqtt_actor) =
+            start_basic_actors(self.session_name(), &tedge_config).await?;
+        let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+
+        let rules_dir = config_dir.join("mappers");
+        create_directory_with_defaults(&rules_dir)?;
+
+        let mut fs_watch_actor = FsWatchActorBuilder::new();
+        let transform_actor = TransformActorBuilder::new(
+            mqtt_schema,
+            rules_dir,
+            &mut mqtt_actor,
+            &mut fs_watch_actor,
+        );
+
+        runtime.spawn(transform_actor).await?;
+        runtime.spawn(fs_watch_actor).await?;
+        runtime.spawn(mqtt_actor).await?;
+        runtime.run_to_completion().await?;
+        Ok(())
+    }
+}
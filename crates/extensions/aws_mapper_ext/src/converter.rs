@@ -2,16 +2,23 @@ use clock::Clock;
 use log::error;
 use serde_json::Map;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::convert::Infallible;
 use tedge_actors::Converter;
 use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::GenericCommandState;
 use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
 use tedge_mqtt_ext::Topic;
 use tedge_utils::timestamp::TimeFormat;
 
 use crate::error::ConversionError;
+use crate::jobs;
+use crate::shadow;
 use crate::size_threshold::SizeThreshold;
 
 const AWS_MQTT_THRESHOLD: usize = 1024 * 255;
@@ -23,6 +30,15 @@ pub struct AwsConverter {
     pub(crate) size_threshold: SizeThreshold,
     pub mqtt_schema: MqttSchema,
     pub time_format: TimeFormat,
+
+    /// The topic id of the main device, mapped onto the classic shadow of the thing
+    pub(crate) device_topic_id: EntityTopicId,
+
+    /// The entities seen by the mapper, indexed by the name of their shadow
+    pub(crate) shadows: HashMap<String, EntityTopicId>,
+
+    /// The operations supported by each entity, as registered on `te/<entity>/cmd/<operation>`
+    pub(crate) capabilities: HashSet<(EntityTopicId, OperationType)>,
 }
 
 impl AwsConverter {
@@ -39,6 +55,17 @@ impl AwsConverter {
             size_threshold,
             mqtt_schema: mqtt_schema.clone(),
             time_format,
+            device_topic_id: EntityTopicId::default_main_device(),
+            shadows: HashMap::new(),
+            capabilities: HashSet::new(),
+        }
+    }
+
+    /// Use a main device topic id other than the default `device/main//`
+    pub fn with_device_topic_id(self, device_topic_id: EntityTopicId) -> Self {
+        Self {
+            device_topic_id,
+            ..self
         }
     }
 
@@ -52,7 +79,13 @@ impl AwsConverter {
     fn try_convert(&mut self, input: &MqttMessage) -> Result<Vec<MqttMessage>, ConversionError> {
         let messages = match self.mqtt_schema.entity_channel_of(&input.topic) {
             Ok((source, channel)) => self.try_convert_te_topics(source, channel, input),
-            Err(_) => Ok(vec![]),
+            Err(_) if jobs::is_job_notification(&input.topic) => {
+                self.convert_job_notification(input)
+            }
+            Err(_) => match shadow::delta_shadow_name(&input.topic) {
+                Some(shadow) => self.convert_shadow_delta(shadow, input),
+                None => Ok(vec![]),
+            },
         }?;
 
         for message in &messages {
@@ -87,6 +120,27 @@ impl AwsConverter {
 
             Channel::Health => self.convert_health_message(&source, &channel, input),
 
+            Channel::EntityTwinData { fragment_key } => {
+                self.convert_twin_data(&source, &fragment_key, input)
+            }
+
+            Channel::EntityMetadata => self.convert_entity_metadata(&source, input),
+
+            Channel::CommandMetadata { operation } => {
+                let capability = (source, operation);
+                if input.payload_bytes().is_empty() {
+                    self.capabilities.remove(&capability);
+                } else {
+                    self.capabilities.insert(capability);
+                }
+                Ok(vec![])
+            }
+
+            Channel::Command { cmd_id, .. } => match jobs::job_id(&cmd_id) {
+                Some(job_id) => self.convert_command_state(job_id, input),
+                None => Ok(vec![]),
+            },
+
             _ => Ok(vec![]),
         }
     }
@@ -119,22 +173,218 @@ impl AwsConverter {
         let payload = self.with_timestamp(input)?;
         let source = normalize_name(&source);
         // XXX: should match on `Channel` instead
-        let out_topic = match input.topic.name.split('/').collect::<Vec<_>>()[..] {
-            [_, _, _, _, _, "m", _] => {
-                Topic::new_unchecked(&format!("aws/td/{source}/m/{telemetry_type}"))
+        let (out_topic, is_measurement) = match input.topic.name.split('/').collect::<Vec<_>>()[..]
+        {
+            [_, _, _, _, _, "m", _] => (
+                Topic::new_unchecked(&format!("aws/td/{source}/m/{telemetry_type}")),
+                true,
+            ),
+            [_, _, _, _, _, "e", _] => (
+                Topic::new_unchecked(&format!("aws/td/{source}/e/{telemetry_type}")),
+                false,
+            ),
+            [_, _, _, _, _, "a", _] => (
+                Topic::new_unchecked(&format!("aws/td/{source}/a/{telemetry_type}")),
+                false,
+            ),
+            _ => return Ok(vec![]),
+        };
+
+        // Too large messages are split: measurements by series, events and alarms into chunks
+        if is_measurement {
+            self.size_threshold.split_measurement(&out_topic, payload)
+        } else {
+            let chunk_id = self.clock.now().unix_timestamp_nanos().to_string();
+            self.size_threshold.chunk(&out_topic, &chunk_id, payload)
+        }
+    }
+
+    /// Report a twin fragment in the shadow of the entity
+    ///
+    /// A cleared twin fragment is removed from the reported state.
+    fn convert_twin_data(
+        &mut self,
+        source: &EntityTopicId,
+        fragment_key: &str,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let value = match input.payload_bytes() {
+            [] => Value::Null,
+            payload => serde_json::from_slice(payload)?,
+        };
+        let mut reported = Map::new();
+        reported.insert(fragment_key.to_string(), value);
+
+        let shadow = self.register_shadow(source);
+        let out_topic = shadow::shadow_update_topic(shadow.as_deref());
+        let payload = shadow::reported_state(reported).to_string();
+        Ok(vec![MqttMessage::new(&out_topic, payload)])
+    }
+
+    /// Report the metadata of an entity in its shadow
+    ///
+    /// The named shadow of an entity is deleted when the entity is deregistered.
+    fn convert_entity_metadata(
+        &mut self,
+        source: &EntityTopicId,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let shadow = self.register_shadow(source);
+        if input.payload_bytes().is_empty() {
+            return match shadow {
+                None => Ok(vec![]),
+                Some(name) => {
+                    self.shadows.remove(&name);
+                    Ok(vec![MqttMessage::new(
+                        &shadow::shadow_delete_topic(&name),
+                        "",
+                    )])
+                }
+            };
+        }
+
+        let metadata: Map<String, Value> = serde_json::from_slice(input.payload_bytes())?;
+        let out_topic = shadow::shadow_update_topic(shadow.as_deref());
+        let payload = shadow::reported_state(metadata).to_string();
+        Ok(vec![MqttMessage::new(&out_topic, payload)])
+    }
+
+    /// Translate the delta between the desired and reported states of a shadow into twin updates
+    fn convert_shadow_delta(
+        &mut self,
+        shadow: Option<String>,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let entity = match shadow {
+            None => self.device_topic_id.clone(),
+            Some(name) => match self
+                .shadows
+                .get(&name)
+                .cloned()
+                .or_else(|| shadow::guess_entity(&name))
+            {
+                Some(entity) => entity,
+                None => return Err(ConversionError::UnknownShadow { name }),
+            },
+        };
+
+        let delta: Value = serde_json::from_slice(input.payload_bytes())?;
+        let messages = shadow::desired_properties(&delta)
+            .into_iter()
+            .map(|(fragment_key, value)| {
+                let channel = Channel::EntityTwinData { fragment_key };
+                let topic = self.mqtt_schema.topic_for(&entity, &channel);
+                let payload = match value {
+                    Value::Null => String::new(),
+                    value => value.to_string(),
+                };
+                MqttMessage::new(&topic, payload).with_retain()
+            })
+            .collect();
+        Ok(messages)
+    }
+
+    /// Turn a queued AWS IoT job into a thin-edge command
+    ///
+    /// The job is immediately marked as in progress,
+    /// or as failed if the job document is invalid
+    /// or the operation is not a capability of the target entity.
+    fn convert_job_notification(
+        &mut self,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let Some(job) = jobs::parse_job_notification(input.payload_bytes())? else {
+            return Ok(vec![]);
+        };
+        if !job.is_queued() {
+            // The job has already been turned into a command
+            return Ok(vec![]);
+        }
+
+        let command = job.command(&self.device_topic_id).and_then(|command| {
+            if self
+                .capabilities
+                .contains(&(command.target.clone(), command.operation.clone()))
+            {
+                Ok(command)
+            } else {
+                Err(format!(
+                    "The {} operation is not supported by {}",
+                    command.operation, command.target
+                ))
             }
-            [_, _, _, _, _, "e", _] => {
-                Topic::new_unchecked(&format!("aws/td/{source}/e/{telemetry_type}"))
+        });
+        match command {
+            Ok(command) => {
+                let operation = command.operation.to_string();
+                let command = command.into_message(&self.mqtt_schema, job.cmd_id());
+                let in_progress = jobs::update_job_execution(
+                    &job.job_id,
+                    "IN_PROGRESS",
+                    &[("operation", operation), ("status", "init".to_string())],
+                );
+                Ok(vec![command, in_progress])
             }
-            [_, _, _, _, _, "a", _] => {
-                Topic::new_unchecked(&format!("aws/td/{source}/a/{telemetry_type}"))
+            Err(reason) => {
+                error!("AWS IoT job {} cannot be executed: {reason}", job.job_id);
+                Ok(vec![jobs::update_job_execution(
+                    &job.job_id,
+                    "FAILED",
+                    &[("reason", reason)],
+                )])
             }
-            _ => return Ok(vec![]),
+        }
+    }
+
+    /// Report to AWS the status of a command created for an AWS IoT job
+    ///
+    /// The command is cleared once completed.
+    fn convert_command_state(
+        &mut self,
+        job_id: &str,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let Some(command) = GenericCommandState::from_command_message(input)? else {
+            return Ok(vec![]);
+        };
+        let operation = command.operation().unwrap_or_default();
+        let update = match command.status.as_str() {
+            "init" => return Ok(vec![]),
+            "successful" => {
+                jobs::update_job_execution(job_id, "SUCCEEDED", &[("operation", operation)])
+            }
+            "failed" => {
+                let reason = command.failure_reason().unwrap_or_default();
+                jobs::update_job_execution(
+                    job_id,
+                    "FAILED",
+                    &[("operation", operation), ("reason", reason)],
+                )
+            }
+            status => jobs::update_job_execution(
+                job_id,
+                "IN_PROGRESS",
+                &[("operation", operation), ("status", status.to_string())],
+            ),
         };
 
-        let output = MqttMessage::new(&out_topic, payload);
-        self.size_threshold.validate(&output)?;
-        Ok(vec![output])
+        if command.is_terminal() {
+            let clear_command = MqttMessage::new(&input.topic, "")
+                .with_retain()
+                .with_qos(QoS::AtLeastOnce);
+            Ok(vec![update, clear_command])
+        } else {
+            Ok(vec![update])
+        }
+    }
+
+    /// Record the shadow of an entity, returning the name of this shadow
+    fn register_shadow(&mut self, entity: &EntityTopicId) -> Option<String> {
+        let shadow = shadow::shadow_name(entity, &self.device_topic_id);
+        if let Some(name) = &shadow {
+            self.shadows.insert(name.clone(), entity.clone());
+        }
+        shadow
     }
 
     fn with_timestamp(&self, input: &MqttMessage) -> Result<String, ConversionError> {
@@ -171,7 +421,7 @@ impl AwsConverter {
 //
 // Ref: https://docs.aws.amazon.com/general/latest/gr/iot-core.html -> "Maximum number of slashes in
 // topic and topic filter"
-fn normalize_name(source: &EntityTopicId) -> String {
+pub(crate) fn normalize_name(source: &EntityTopicId) -> String {
     let parts: Vec<&str> = source.as_str().split('/').collect();
     parts
         .iter()
@@ -190,6 +440,10 @@ impl Converter for AwsConverter {
         let messages_or_err = self.try_convert(input);
         Ok(self.wrap_errors(messages_or_err))
     }
+
+    fn init_messages(&mut self) -> Result<Vec<Self::Output>, Self::Error> {
+        Ok(vec![jobs::get_next_job_request()])
+    }
 }
 
 #[cfg(test)]
@@ -276,6 +530,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn split_a_measurement_exceeding_threshold() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        )
+        .with_threshold(SizeThreshold(64));
+
+        let input = r#"{"temperature": 23.0, "pressure": 220.0, "humidity": 45.0, "voltage": 3.3}"#;
+        let messages = converter.try_convert(&new_tedge_message(input)).unwrap();
+
+        assert!(messages.len() > 1);
+        let mut series = Map::new();
+        for message in messages {
+            assert_eq!(message.topic.name, "aws/td/device:main/m/");
+            assert!(message.payload_bytes().len() <= 64);
+            let measurement: Value = serde_json::from_slice(message.payload_bytes()).unwrap();
+            series.extend(measurement.as_object().unwrap().clone());
+        }
+        assert_eq!(
+            Value::Object(series),
+            json!({"temperature": 23.0, "pressure": 220.0, "humidity": 45.0, "voltage": 3.3})
+        );
+    }
+
+    #[test]
+    fn chunk_an_event_exceeding_threshold() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        )
+        .with_threshold(SizeThreshold(128));
+
+        let input = json!({"text": "x".repeat(500)}).to_string();
+        let event = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///e/big"),
+            input.as_str(),
+        );
+        let messages = converter.try_convert(&event).unwrap();
+
+        assert!(messages.len() > 1);
+        let mut payload = String::new();
+        for (index, message) in messages.iter().enumerate() {
+            assert_eq!(message.topic.name, "aws/td/device:main/e/big");
+            assert!(message.payload_bytes().len() <= 128);
+            let chunk: Value = serde_json::from_slice(message.payload_bytes()).unwrap();
+            assert_eq!(chunk["chunk"]["index"], index);
+            assert_eq!(chunk["chunk"]["count"], messages.len());
+            payload.push_str(chunk["data"].as_str().unwrap());
+        }
+        assert_eq!(payload, input);
+    }
+
+    #[test]
+    fn large_integers_booleans_and_arrays_are_forwarded_unchanged() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let input =
+            r#"{"counter":18446744073709551615,"running":true,"vibration":{"spectrum":[0.5,1,2]}}"#;
+
+        let output = converter.convert(&new_tedge_message(input)).unwrap();
+
+        assert_eq!(extract_first_message_payload(output), input);
+    }
+
     #[test]
     fn converting_input_without_timestamp_produces_output_without_timestamp_given_add_timestamp_is_false(
     ) {
@@ -892,4 +1220,369 @@ mod tests {
         let res = result.unwrap();
         assert_eq!(res[0], expected_msg);
     }
+
+    #[test]
+    fn converting_twin_data_into_shadow_reported_state() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///twin/location"),
+            r#"{"lat":48.1,"lon":11.5}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "aws/shadow/update");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"state":{"reported":{"location":{"lat":48.1,"lon":11.5}}}})
+        );
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/child1///twin/firmware"),
+            "",
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "aws/shadow/name/device:child1/update");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"state":{"reported":{"firmware":null}}})
+        );
+    }
+
+    #[test]
+    fn converting_entity_metadata_into_shadow_reported_state() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/factory/shop/plc/1"),
+            r#"{"@type":"child-device","@id":"plc-1","name":"PLC"}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "aws/shadow/name/factory:shop:plc:1/update"
+        );
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"state":{"reported":{"@type":"child-device","@id":"plc-1","name":"PLC"}}})
+        );
+
+        // The named shadow of a deregistered entity is deleted
+        let input = MqttMessage::new(&Topic::new_unchecked("te/factory/shop/plc/1"), "");
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "aws/shadow/name/factory:shop:plc:1/delete"
+        );
+    }
+
+    #[test]
+    fn converting_shadow_delta_into_twin_updates() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let delta = json!({
+            "version": 12,
+            "timestamp": 1697000000,
+            "state": { "config": { "interval": 60 }, "label": null, "@type": "device" }
+        });
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/shadow/update/delta"),
+            delta.to_string(),
+        );
+        let mut output = converter.try_convert(&input).unwrap();
+        output.sort_by(|a, b| a.topic.name.cmp(&b.topic.name));
+        assert_eq!(
+            output,
+            vec![
+                MqttMessage::new(
+                    &Topic::new_unchecked("te/device/main///twin/config"),
+                    r#"{"interval":60}"#
+                )
+                .with_retain(),
+                MqttMessage::new(&Topic::new_unchecked("te/device/main///twin/label"), "")
+                    .with_retain(),
+            ]
+        );
+
+        // The entity of a named shadow is the one seen by the mapper
+        let input = MqttMessage::new(&Topic::new_unchecked("te/factory/shop/plc/1"), "{}");
+        converter.try_convert(&input).unwrap();
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/shadow/name/factory:shop:plc:1/update/delta"),
+            r#"{"state":{"mode":"auto"}}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "te/factory/shop/plc/1/twin/mode");
+        assert_eq!(output[0].payload_str().unwrap(), r#""auto""#);
+
+        // Unless the entity is unknown, in which case the default topic scheme is assumed
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/shadow/name/device:child2/update/delta"),
+            r#"{"state":{"mode":"manual"}}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "te/device/child2///twin/mode");
+    }
+
+    #[test]
+    fn main_device_with_custom_topic_id() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        )
+        .with_device_topic_id("device/gateway//".parse().unwrap());
+
+        // The twin data of the main device are published on the classic shadow
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/gateway///twin/location"),
+            r#"{"lat":48.1}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "aws/shadow/update");
+
+        // The classic shadow delta is applied to the main device
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/shadow/update/delta"),
+            r#"{"state":{"mode":"auto"}}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "te/device/gateway///twin/mode");
+
+        // The main device is the default target of the jobs
+        let capability = MqttMessage::new(
+            &Topic::new_unchecked("te/device/gateway///cmd/restart"),
+            "{}",
+        );
+        converter.try_convert(&capability).unwrap();
+        let notification = json!({
+            "execution": {
+                "jobId": "reboot-1",
+                "status": "QUEUED",
+                "jobDocument": { "operation": "restart" }
+            }
+        });
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/jobs/notify-next"),
+            notification.to_string(),
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "te/device/gateway///cmd/restart/aws-reboot-1"
+        );
+    }
+
+    #[test]
+    fn converting_queued_job_into_command() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let capability =
+            MqttMessage::new(&Topic::new_unchecked("te/device/main///cmd/restart"), "{}");
+        assert!(converter.try_convert(&capability).unwrap().is_empty());
+
+        let notification = json!({
+            "timestamp": 1697000000,
+            "execution": {
+                "jobId": "reboot-1",
+                "status": "QUEUED",
+                "jobDocument": { "operation": "restart" }
+            }
+        });
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/jobs/notify-next"),
+            notification.to_string(),
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(
+            output[0].topic.name,
+            "te/device/main///cmd/restart/aws-reboot-1"
+        );
+        assert!(output[0].retain);
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"init"})
+        );
+        assert_eq!(output[1].topic.name, "aws/jobs/reboot-1/update");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[1].payload_str().unwrap()).unwrap(),
+            json!({"status":"IN_PROGRESS","statusDetails":{"operation":"restart","status":"init"}})
+        );
+
+        // A job already in progress is not triggered twice
+        let notification = json!({
+            "execution": {
+                "jobId": "reboot-1",
+                "status": "IN_PROGRESS",
+                "jobDocument": { "operation": "restart" }
+            }
+        });
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/jobs/notify-next"),
+            notification.to_string(),
+        );
+        assert!(converter.try_convert(&input).unwrap().is_empty());
+    }
+
+    #[test]
+    fn failing_job_with_invalid_document() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let notification = json!({
+            "execution": {
+                "jobId": "job-2",
+                "status": "QUEUED",
+                "jobDocument": { "url": "https://example.com/firmware.bin" }
+            }
+        });
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("aws/jobs/$next/get/accepted"),
+            notification.to_string(),
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "aws/jobs/job-2/update");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"FAILED","statusDetails":{"reason":"Missing operation in the job document"}})
+        );
+    }
+
+    #[test]
+    fn failing_job_not_supported_by_its_target() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+        let capability =
+            MqttMessage::new(&Topic::new_unchecked("te/device/main///cmd/restart"), "{}");
+        converter.try_convert(&capability).unwrap();
+
+        let job = |job_id: &str, document: Value| {
+            let notification = json!({
+                "execution": { "jobId": job_id, "status": "QUEUED", "jobDocument": document }
+            });
+            MqttMessage::new(
+                &Topic::new_unchecked("aws/jobs/notify-next"),
+                notification.to_string(),
+            )
+        };
+
+        // An operation not registered as a capability
+        let output = converter
+            .try_convert(&job("job-1", json!({"operation": "log_upload", "type": "syslog", "uploadUrl": "https://example.com", "dateFrom": "2024-01-01T00:00:00Z", "dateTo": "2024-01-02T00:00:00Z"})))
+            .unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].topic.name, "aws/jobs/job-1/update");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"FAILED","statusDetails":{"reason":"The log_upload operation is not supported by device/main//"}})
+        );
+
+        // An unknown target entity
+        let output = converter
+            .try_convert(&job(
+                "job-2",
+                json!({"operation": "restart", "target": "device/unknown//"}),
+            ))
+            .unwrap();
+        assert_eq!(output.len(), 1);
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"FAILED","statusDetails":{"reason":"The restart operation is not supported by device/unknown//"}})
+        );
+
+        // A capability can be removed
+        let capability =
+            MqttMessage::new(&Topic::new_unchecked("te/device/main///cmd/restart"), "");
+        converter.try_convert(&capability).unwrap();
+        let output = converter
+            .try_convert(&job("job-3", json!({"operation": "restart"})))
+            .unwrap();
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"FAILED","statusDetails":{"reason":"The restart operation is not supported by device/main//"}})
+        );
+    }
+
+    #[test]
+    fn reporting_command_status_to_aws_jobs() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let topic = Topic::new_unchecked("te/device/child1///cmd/software_update/aws-job-3");
+        let input = MqttMessage::new(&topic, r#"{"status":"executing","updateList":[]}"#);
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"IN_PROGRESS","statusDetails":{"operation":"software_update","status":"executing"}})
+        );
+
+        let input = MqttMessage::new(&topic, r#"{"status":"failed","reason":"no space left"}"#);
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].topic.name, "aws/jobs/job-3/update");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"FAILED","statusDetails":{"operation":"software_update","reason":"no space left"}})
+        );
+        assert_eq!(
+            output[1],
+            MqttMessage::new(&topic, "")
+                .with_retain()
+                .with_qos(QoS::AtLeastOnce)
+        );
+
+        // Commands not created from AWS IoT jobs are ignored
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/restart/c8y-123"),
+            r#"{"status":"successful"}"#,
+        );
+        assert!(converter.try_convert(&input).unwrap().is_empty());
+    }
+
+    #[test]
+    fn requesting_pending_jobs_on_start() {
+        let mut converter = AwsConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+        );
+
+        let messages = converter.init_messages().unwrap();
+        assert_eq!(messages[0].topic.name, "aws/jobs/$next/get");
+    }
 }
@@ -24,4 +24,10 @@ pub enum ConversionError {
 
     #[error(transparent)]
     MqttError(#[from] MqttError),
+
+    #[error(transparent)]
+    FromWorkflowExecution(#[from] tedge_api::workflow::WorkflowExecutionError),
+
+    #[error("No entity is known for the shadow: {name}")]
+    UnknownShadow { name: String },
 }
@@ -0,0 +1,519 @@
+//! Mapping of AWS IoT Jobs onto thin-edge commands
+//!
+//! The job executions of the thing are notified by AWS on `$aws/things/<thing>/jobs/notify-next`,
+//! bridged locally as `aws/jobs/notify-next`.
+//! A queued job is turned into a thin-edge command, using the job document to build the command:
+//!
+//! ```json
+//! {
+//!     "operation": "software_update",
+//!     "target": "device/child1//",
+//!     "software": [ ... ]
+//! }
+//! ```
+//!
+//! - `operation` is the name of the thin-edge operation, e.g. `software_update` or `restart`.
+//! - `target` is the entity topic id of the target entity, the main device (`mqtt.device_topic_id`) by default.
+//! - The other properties of the well-known operations (`software_update`, `config_update`,
+//!   `restart` and `log_upload`) are translated into the thin-edge command payload,
+//!   while those of any other operation are copied into the command payload.
+//!
+//! A job whose operation is not a capability of the target entity, as registered on `te/<entity>/cmd/<operation>`,
+//! is immediately marked as failed, as is a job with an invalid document.
+//!
+//! The command status is reported back to AWS using `UpdateJobExecution` requests,
+//! published on `aws/jobs/<jobId>/update`.
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::OperationType;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+
+const JOBS_TOPIC_PREFIX: &str = "aws/jobs";
+const NOTIFY_NEXT_TOPIC: &str = "aws/jobs/notify-next";
+const GET_NEXT_ACCEPTED_TOPIC: &str = "aws/jobs/$next/get/accepted";
+
+/// Prefix of the ids of the commands created from AWS IoT jobs
+const CMD_ID_PREFIX: &str = "aws-";
+
+/// The topics to subscribe to, for the AWS IoT jobs, the associated commands and the capabilities
+pub fn job_topics(mqtt_schema: &MqttSchema) -> TopicFilter {
+    let mut topics = mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::AnyCommand);
+    topics.add_all(mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::AnyCommandMetadata));
+    topics.add_unchecked(NOTIFY_NEXT_TOPIC);
+    topics.add_unchecked(GET_NEXT_ACCEPTED_TOPIC);
+    topics
+}
+
+/// The request sent on start to get the next pending job, if any
+pub fn get_next_job_request() -> MqttMessage {
+    MqttMessage::new(
+        &Topic::new_unchecked(&format!("{JOBS_TOPIC_PREFIX}/$next/get")),
+        "{}",
+    )
+    .with_qos(QoS::AtLeastOnce)
+}
+
+/// Tell if a topic is used by AWS to notify the next pending job
+pub fn is_job_notification(topic: &Topic) -> bool {
+    topic.name == NOTIFY_NEXT_TOPIC || topic.name == GET_NEXT_ACCEPTED_TOPIC
+}
+
+/// A job execution as notified by AWS
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobExecution {
+    pub job_id: String,
+    pub status: String,
+    #[serde(default)]
+    pub job_document: Value,
+}
+
+/// The payload of a `notify-next` or `$next/get/accepted` message
+#[derive(Debug, Deserialize)]
+struct NextJobNotification {
+    execution: Option<JobExecution>,
+}
+
+/// A thin-edge command built from a job document
+#[derive(Debug, Eq, PartialEq)]
+pub struct JobCommand {
+    pub target: EntityTopicId,
+    pub operation: OperationType,
+    pub payload: Map<String, Value>,
+}
+
+/// Extract the job execution, if any, from a job notification
+pub fn parse_job_notification(payload: &[u8]) -> Result<Option<JobExecution>, serde_json::Error> {
+    let notification: NextJobNotification = serde_json::from_slice(payload)?;
+    Ok(notification.execution)
+}
+
+impl JobExecution {
+    /// Tell if the job has still to be started
+    pub fn is_queued(&self) -> bool {
+        self.status == "QUEUED"
+    }
+
+    /// The id of the command created for this job
+    pub fn cmd_id(&self) -> String {
+        format!("{CMD_ID_PREFIX}{}", self.job_id)
+    }
+
+    /// Build the thin-edge command described by the job document, targeting the main device by default
+    pub fn command(&self, main_device: &EntityTopicId) -> Result<JobCommand, String> {
+        let Some(document) = self.job_document.as_object() else {
+            return Err("The job document is not a JSON object".to_string());
+        };
+        let mut payload = document.clone();
+        let operation = match payload.remove("operation") {
+            Some(Value::String(operation)) if !operation.is_empty() => {
+                OperationType::from(operation.as_str())
+            }
+            _ => return Err("Missing operation in the job document".to_string()),
+        };
+        let target = match payload.remove("target") {
+            None => main_device.clone(),
+            Some(Value::String(target)) => target
+                .parse()
+                .map_err(|err| format!("Invalid target {target}: {err}"))?,
+            Some(target) => return Err(format!("Invalid target: {target}")),
+        };
+        let mut payload = match operation {
+            OperationType::Restart => Map::new(),
+            OperationType::SoftwareUpdate => software_update_payload(payload)?,
+            OperationType::ConfigUpdate => config_update_payload(payload)?,
+            OperationType::LogUpload => log_upload_payload(payload)?,
+            _ => payload,
+        };
+        payload.insert("status".to_string(), json!("init"));
+        Ok(JobCommand {
+            target,
+            operation,
+            payload,
+        })
+    }
+}
+
+/// A module to install or remove, as listed by a `software_update` job document
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SoftwareJobItem {
+    name: String,
+    #[serde(rename = "type", default = "default_software_type")]
+    software_type: String,
+    version: Option<String>,
+    url: Option<String>,
+    action: SoftwareJobAction,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SoftwareJobAction {
+    Install,
+    Remove,
+}
+
+fn default_software_type() -> String {
+    tedge_api::DEFAULT.to_string()
+}
+
+/// The document of a `config_update` job
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigUpdateJob {
+    #[serde(rename = "type")]
+    config_type: String,
+    url: String,
+    path: Option<String>,
+}
+
+/// The document of a `log_upload` job
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct LogUploadJob {
+    #[serde(rename = "type")]
+    log_type: String,
+    upload_url: String,
+    date_from: String,
+    date_to: String,
+    search_text: Option<String>,
+    #[serde(default = "default_log_lines")]
+    lines: usize,
+}
+
+fn default_log_lines() -> usize {
+    1000
+}
+
+fn parse_job_document<T: serde::de::DeserializeOwned>(
+    operation: &str,
+    document: Map<String, Value>,
+) -> Result<T, String> {
+    serde_json::from_value(Value::Object(document))
+        .map_err(|err| format!("Invalid {operation} job document: {err}"))
+}
+
+/// Translate `{"software": [{"name", "version", "type", "url", "action"}]}`
+/// into `{"updateList": [{"type", "modules": [{"name", "version", "url", "action"}]}]}`
+fn software_update_payload(document: Map<String, Value>) -> Result<Map<String, Value>, String> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct SoftwareUpdateJob {
+        software: Vec<SoftwareJobItem>,
+    }
+    let job: SoftwareUpdateJob = parse_job_document("software_update", document)?;
+
+    let mut update_list: Vec<(String, Vec<Value>)> = Vec::new();
+    for item in job.software {
+        let mut module = Map::new();
+        module.insert("name".to_string(), json!(item.name));
+        if let Some(version) = item.version {
+            module.insert("version".to_string(), json!(version));
+        }
+        if let Some(url) = item.url {
+            module.insert("url".to_string(), json!(url));
+        }
+        let action = match item.action {
+            SoftwareJobAction::Install => "install",
+            SoftwareJobAction::Remove => "remove",
+        };
+        module.insert("action".to_string(), json!(action));
+
+        match update_list
+            .iter_mut()
+            .find(|(software_type, _)| software_type == &item.software_type)
+        {
+            Some((_, modules)) => modules.push(Value::Object(module)),
+            None => update_list.push((item.software_type, vec![Value::Object(module)])),
+        }
+    }
+
+    let update_list: Vec<Value> = update_list
+        .into_iter()
+        .map(|(software_type, modules)| json!({"type": software_type, "modules": modules}))
+        .collect();
+    let mut payload = Map::new();
+    payload.insert("updateList".to_string(), Value::Array(update_list));
+    Ok(payload)
+}
+
+/// Translate `{"type", "url", "path"}` into `{"type", "remoteUrl", "tedgeUrl", "path"}`
+///
+/// The configuration file is downloaded by the agent directly from the given url.
+fn config_update_payload(document: Map<String, Value>) -> Result<Map<String, Value>, String> {
+    let job: ConfigUpdateJob = parse_job_document("config_update", document)?;
+    let mut payload = Map::new();
+    payload.insert("type".to_string(), json!(job.config_type));
+    payload.insert("remoteUrl".to_string(), json!(job.url));
+    payload.insert("tedgeUrl".to_string(), json!(job.url));
+    if let Some(path) = job.path {
+        payload.insert("path".to_string(), json!(path));
+    }
+    Ok(payload)
+}
+
+/// Translate `{"type", "uploadUrl", "dateFrom", "dateTo", "searchText", "lines"}`
+/// into `{"type", "tedgeUrl", "dateFrom", "dateTo", "searchText", "lines"}`
+///
+/// The log file is uploaded by the agent directly to the given url.
+fn log_upload_payload(document: Map<String, Value>) -> Result<Map<String, Value>, String> {
+    let job: LogUploadJob = parse_job_document("log_upload", document)?;
+    let mut payload = Map::new();
+    payload.insert("type".to_string(), json!(job.log_type));
+    payload.insert("tedgeUrl".to_string(), json!(job.upload_url));
+    payload.insert("dateFrom".to_string(), json!(job.date_from));
+    payload.insert("dateTo".to_string(), json!(job.date_to));
+    if let Some(search_text) = job.search_text {
+        payload.insert("searchText".to_string(), json!(search_text));
+    }
+    payload.insert("lines".to_string(), json!(job.lines));
+    Ok(payload)
+}
+
+impl JobCommand {
+    /// The MQTT message to trigger the command
+    pub fn into_message(self, mqtt_schema: &MqttSchema, cmd_id: String) -> MqttMessage {
+        let channel = Channel::Command {
+            operation: self.operation,
+            cmd_id,
+        };
+        let topic = mqtt_schema.topic_for(&self.target, &channel);
+        MqttMessage::new(&topic, Value::Object(self.payload).to_string())
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce)
+    }
+}
+
+/// Extract the job id from the id of a command created for an AWS IoT job
+pub fn job_id(cmd_id: &str) -> Option<&str> {
+    cmd_id.strip_prefix(CMD_ID_PREFIX)
+}
+
+/// Build an `UpdateJobExecution` request
+///
+/// The status details are given as a JSON object with string values, as expected by AWS.
+pub fn update_job_execution(job_id: &str, status: &str, details: &[(&str, String)]) -> MqttMessage {
+    let topic = Topic::new_unchecked(&format!("{JOBS_TOPIC_PREFIX}/{job_id}/update"));
+    let details: Map<String, Value> = details
+        .iter()
+        .map(|(key, value)| (key.to_string(), Value::String(value.clone())))
+        .collect();
+    let payload = json!({
+        "status": status,
+        "statusDetails": details,
+    });
+    MqttMessage::new(&topic, payload.to_string()).with_qos(QoS::AtLeastOnce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_next_job_notification() {
+        let payload = r#"{
+            "timestamp": 1697000000,
+            "execution": {
+                "jobId": "update-42",
+                "status": "QUEUED",
+                "queuedAt": 1697000000,
+                "versionNumber": 1,
+                "executionNumber": 1,
+                "jobDocument": {
+                    "operation": "software_update",
+                    "target": "device/child1//",
+                    "software": []
+                }
+            }
+        }"#;
+        let job = parse_job_notification(payload.as_bytes()).unwrap().unwrap();
+        assert!(job.is_queued());
+        assert_eq!(job.cmd_id(), "aws-update-42");
+        assert_eq!(
+            job.command(&EntityTopicId::default_main_device()).unwrap(),
+            JobCommand {
+                target: "device/child1//".parse().unwrap(),
+                operation: OperationType::SoftwareUpdate,
+                payload: json!({"status": "init", "updateList": []})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            }
+        );
+
+        // No execution is notified when there is no more pending job
+        let payload = r#"{"timestamp": 1697000000}"#;
+        assert!(parse_job_notification(payload.as_bytes())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn reject_job_documents_without_operation() {
+        let job = JobExecution {
+            job_id: "123".to_string(),
+            status: "QUEUED".to_string(),
+            job_document: json!({"target": "device/main//"}),
+        };
+        assert_eq!(
+            job.command(&EntityTopicId::default_main_device())
+                .unwrap_err(),
+            "Missing operation in the job document"
+        );
+    }
+
+    #[test]
+    fn translate_restart_job_documents() {
+        let command = command_for(json!({"operation": "restart"})).unwrap();
+        assert_eq!(command.target, EntityTopicId::default_main_device());
+        assert_eq!(command.operation, OperationType::Restart);
+        assert_eq!(Value::Object(command.payload), json!({"status": "init"}));
+    }
+
+    #[test]
+    fn translate_software_update_job_documents() {
+        let command = command_for(json!({
+            "operation": "software_update",
+            "target": "device/child1//",
+            "software": [
+                { "name": "nodered", "type": "apt", "version": "1.0.0", "action": "install" },
+                { "name": "collectd", "type": "apt", "action": "remove" },
+                { "name": "app", "url": "https://example.com/app.tar.gz", "action": "install" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(command.target, "device/child1//");
+        assert_eq!(command.operation, OperationType::SoftwareUpdate);
+        assert_eq!(
+            Value::Object(command.payload),
+            json!({
+                "status": "init",
+                "updateList": [
+                    {
+                        "type": "apt",
+                        "modules": [
+                            { "name": "nodered", "version": "1.0.0", "action": "install" },
+                            { "name": "collectd", "action": "remove" }
+                        ]
+                    },
+                    {
+                        "type": "default",
+                        "modules": [
+                            { "name": "app", "url": "https://example.com/app.tar.gz", "action": "install" }
+                        ]
+                    }
+                ]
+            })
+        );
+
+        let error = command_for(json!({
+            "operation": "software_update",
+            "software": [{ "name": "nodered", "action": "upgrade" }]
+        }))
+        .unwrap_err();
+        assert!(
+            error.starts_with("Invalid software_update job document: unknown variant `upgrade`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn translate_config_update_job_documents() {
+        let command = command_for(json!({
+            "operation": "config_update",
+            "type": "mosquitto",
+            "url": "https://example.com/mosquitto.conf"
+        }))
+        .unwrap();
+        assert_eq!(command.operation, OperationType::ConfigUpdate);
+        assert_eq!(
+            Value::Object(command.payload),
+            json!({
+                "status": "init",
+                "type": "mosquitto",
+                "remoteUrl": "https://example.com/mosquitto.conf",
+                "tedgeUrl": "https://example.com/mosquitto.conf"
+            })
+        );
+
+        let error =
+            command_for(json!({"operation": "config_update", "type": "mosquitto"})).unwrap_err();
+        assert_eq!(
+            error,
+            "Invalid config_update job document: missing field `url`"
+        );
+    }
+
+    #[test]
+    fn translate_log_upload_job_documents() {
+        let command = command_for(json!({
+            "operation": "log_upload",
+            "type": "software-management",
+            "uploadUrl": "https://example.com/logs/software-management.log",
+            "dateFrom": "2023-10-01T00:00:00Z",
+            "dateTo": "2023-10-02T00:00:00Z",
+            "searchText": "error"
+        }))
+        .unwrap();
+        assert_eq!(command.operation, OperationType::LogUpload);
+        assert_eq!(
+            Value::Object(command.payload),
+            json!({
+                "status": "init",
+                "type": "software-management",
+                "tedgeUrl": "https://example.com/logs/software-management.log",
+                "dateFrom": "2023-10-01T00:00:00Z",
+                "dateTo": "2023-10-02T00:00:00Z",
+                "searchText": "error",
+                "lines": 1000
+            })
+        );
+
+        let error = command_for(json!({
+            "operation": "log_upload",
+            "type": "software-management",
+            "uploadUrl": "https://example.com/logs/software-management.log",
+            "dateFrom": "2023-10-01T00:00:00Z",
+            "dateTo": "2023-10-02T00:00:00Z",
+            "tedgeUrl": "http://localhost:8000/te/v1/files"
+        }))
+        .unwrap_err();
+        assert!(
+            error.starts_with("Invalid log_upload job document: unknown field `tedgeUrl`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn copy_custom_job_documents() {
+        let command = command_for(json!({
+            "operation": "firmware_update",
+            "url": "https://example.com/firmware.bin"
+        }))
+        .unwrap();
+        assert_eq!(command.operation, OperationType::FirmwareUpdate);
+        assert_eq!(
+            Value::Object(command.payload),
+            json!({"status": "init", "url": "https://example.com/firmware.bin"})
+        );
+    }
+
+    fn command_for(job_document: Value) -> Result<JobCommand, String> {
+        JobExecution {
+            job_id: "123".to_string(),
+            status: "QUEUED".to_string(),
+            job_document,
+        }
+        .command(&EntityTopicId::default_main_device())
+    }
+}
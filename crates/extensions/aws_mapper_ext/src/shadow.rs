@@ -0,0 +1,146 @@
+//! Mapping of the entity twin data and metadata onto AWS IoT Device Shadows
+//!
+//! - The main device is mapped onto the classic shadow of the thing,
+//!   i.e. `$aws/things/<thing>/shadow/update`, bridged locally as `aws/shadow/update`.
+//! - Each other entity is mapped onto a named shadow of the same thing,
+//!   i.e. `$aws/things/<thing>/shadow/name/<entity>/update`,
+//!   bridged locally as `aws/shadow/name/<entity>/update`,
+//!   where `<entity>` is the normalized entity topic id, e.g. `device:child1`.
+//!
+//! The twin fragments and entity metadata are published as reported state,
+//! while the delta between the desired and reported states is translated back into twin updates.
+use crate::converter::normalize_name;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::str::FromStr;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+
+const SHADOW_TOPIC_PREFIX: &str = "aws/shadow";
+const DELTA_TOPIC_SUFFIX: &str = "update/delta";
+
+/// The local topics on which AWS IoT notifies the shadow deltas
+pub fn shadow_delta_topics() -> TopicFilter {
+    let mut topics = TopicFilter::empty();
+    topics.add_unchecked(&format!("{SHADOW_TOPIC_PREFIX}/{DELTA_TOPIC_SUFFIX}"));
+    topics.add_unchecked(&format!(
+        "{SHADOW_TOPIC_PREFIX}/name/+/{DELTA_TOPIC_SUFFIX}"
+    ));
+    topics
+}
+
+/// The name of the shadow of an entity, `None` standing for the classic shadow of the main device
+pub fn shadow_name(entity: &EntityTopicId, main_device: &EntityTopicId) -> Option<String> {
+    if entity == main_device {
+        None
+    } else {
+        Some(normalize_name(entity))
+    }
+}
+
+/// The local topic to update a shadow
+pub fn shadow_update_topic(shadow: Option<&str>) -> Topic {
+    match shadow {
+        None => Topic::new_unchecked(&format!("{SHADOW_TOPIC_PREFIX}/update")),
+        Some(name) => Topic::new_unchecked(&format!("{SHADOW_TOPIC_PREFIX}/name/{name}/update")),
+    }
+}
+
+/// The local topic to delete a named shadow
+pub fn shadow_delete_topic(name: &str) -> Topic {
+    Topic::new_unchecked(&format!("{SHADOW_TOPIC_PREFIX}/name/{name}/delete"))
+}
+
+/// Extract the shadow name from a delta topic
+///
+/// Return `Some(None)` for the classic shadow, `Some(Some(name))` for a named shadow
+/// and `None` if this is not a delta topic.
+pub fn delta_shadow_name(topic: &Topic) -> Option<Option<String>> {
+    let shadow = topic
+        .name
+        .strip_prefix(SHADOW_TOPIC_PREFIX)?
+        .strip_suffix(DELTA_TOPIC_SUFFIX)?;
+    match shadow.split('/').collect::<Vec<_>>()[..] {
+        ["", ""] => Some(None),
+        ["", "name", name, ""] if !name.is_empty() => Some(Some(name.to_string())),
+        _ => None,
+    }
+}
+
+/// Guess the entity of a named shadow, when this entity has not been seen by the mapper
+///
+/// This is only correct for entities using the default topic scheme,
+/// as the empty segments of the entity topic id are dropped by the name normalization.
+pub fn guess_entity(shadow_name: &str) -> Option<EntityTopicId> {
+    let mut segments: Vec<&str> = shadow_name.split(':').collect();
+    if segments.len() > 4 {
+        return None;
+    }
+    segments.resize(4, "");
+    EntityTopicId::from_str(&segments.join("/")).ok()
+}
+
+/// Build a shadow update document reporting the given properties
+pub fn reported_state(properties: Map<String, Value>) -> Value {
+    json!({
+        "state": {
+            "reported": properties
+        }
+    })
+}
+
+/// Extract the desired properties from a shadow delta document
+///
+/// The thin-edge metadata properties, i.e. those starting with `@`, are ignored.
+pub fn desired_properties(delta: &Value) -> Vec<(String, Value)> {
+    let Some(state) = delta.get("state").and_then(|state| state.as_object()) else {
+        return vec![];
+    };
+    state
+        .iter()
+        .filter(|(key, _)| !key.starts_with('@'))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_delta_topics() {
+        assert_eq!(
+            delta_shadow_name(&Topic::new_unchecked("aws/shadow/update/delta")),
+            Some(None)
+        );
+        assert_eq!(
+            delta_shadow_name(&Topic::new_unchecked(
+                "aws/shadow/name/device:child1/update/delta"
+            )),
+            Some(Some("device:child1".to_string()))
+        );
+        assert_eq!(
+            delta_shadow_name(&Topic::new_unchecked("aws/shadow/update/accepted")),
+            None
+        );
+        assert_eq!(
+            delta_shadow_name(&Topic::new_unchecked("aws/shadow/name//update/delta")),
+            None
+        );
+    }
+
+    #[test]
+    fn guess_entities_using_the_default_scheme() {
+        assert_eq!(
+            guess_entity("device:child1"),
+            Some("device/child1//".parse().unwrap())
+        );
+        assert_eq!(
+            guess_entity("device:main:service:collectd"),
+            Some("device/main/service/collectd".parse().unwrap())
+        );
+        assert_eq!(guess_entity("a:b:c:d:e"), None);
+    }
+}
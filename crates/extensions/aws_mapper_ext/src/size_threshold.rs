@@ -1,4 +1,6 @@
 use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_utils::size_threshold::SizeThresholdExceededError;
 
 use super::error::ConversionError;
 
@@ -19,4 +21,35 @@ impl SizeThreshold {
             Ok(())
         }
     }
+
+    /// See [tedge_utils::size_threshold::SizeThreshold::split_measurement]
+    pub fn split_measurement(
+        &self,
+        topic: &Topic,
+        payload: String,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        tedge_utils::size_threshold::SizeThreshold(self.0)
+            .split_measurement(topic, payload)
+            .map_err(|err| exceeded(topic, err))
+    }
+
+    /// See [tedge_utils::size_threshold::SizeThreshold::chunk]
+    pub fn chunk(
+        &self,
+        topic: &Topic,
+        chunk_id: &str,
+        payload: String,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        tedge_utils::size_threshold::SizeThreshold(self.0)
+            .chunk(topic, chunk_id, payload)
+            .map_err(|err| exceeded(topic, err))
+    }
+}
+
+fn exceeded(topic: &Topic, err: SizeThresholdExceededError) -> ConversionError {
+    ConversionError::SizeThresholdExceeded {
+        topic: topic.name.clone(),
+        actual_size: err.size,
+        threshold: err.threshold,
+    }
 }
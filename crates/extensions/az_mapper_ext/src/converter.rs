@@ -1,16 +1,27 @@
+use crate::entities;
 use crate::error::ConversionError;
+use crate::methods;
 use crate::size_threshold::SizeThreshold;
+use crate::twin;
 use clock::Clock;
 use log::error;
+use serde_json::json;
 use serde_json::Map;
 use serde_json::Value;
+use std::collections::HashSet;
 use std::convert::Infallible;
 use tedge_actors::Converter;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityStore;
+use tedge_api::entity_store::EntityType;
 use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::GenericCommandState;
 use tedge_config::timestamp::TimeFormat;
 use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
 use tedge_mqtt_ext::Topic;
 
 const AZ_MQTT_THRESHOLD: usize = 1024 * 128;
@@ -29,6 +40,15 @@ pub struct AzureConverter {
     pub(crate) size_threshold: SizeThreshold,
     pub(crate) mapper_config: MapperConfig,
     pub mqtt_schema: MqttSchema,
+    /// Request id of the last reported-property patch
+    pub(crate) twin_rid: u64,
+    pub(crate) entity_store: EntityStore,
+    /// Register the entities publishing data before being registered
+    pub(crate) auto_register: bool,
+    /// Report the child devices and services in the device twin
+    pub(crate) report_entities: bool,
+    /// The operations supported by each entity, as registered on `te/<entity>/cmd/<operation>`
+    pub(crate) capabilities: HashSet<(EntityTopicId, OperationType)>,
 }
 
 impl AzureConverter {
@@ -37,6 +57,7 @@ impl AzureConverter {
         clock: Box<dyn Clock>,
         mqtt_schema: MqttSchema,
         time_format: TimeFormat,
+        entity_store: EntityStore,
     ) -> Self {
         let mapper_config = MapperConfig {
             out_topic: Topic::new_unchecked("az/messages/events/"),
@@ -49,7 +70,26 @@ impl AzureConverter {
             clock,
             size_threshold,
             mapper_config,
-            mqtt_schema: MqttSchema::default(),
+            mqtt_schema,
+            twin_rid: 0,
+            entity_store,
+            auto_register: true,
+            report_entities: false,
+            capabilities: HashSet::new(),
+        }
+    }
+
+    pub fn with_auto_registration(self, auto_register: bool) -> Self {
+        Self {
+            auto_register,
+            ..self
+        }
+    }
+
+    pub fn with_entities_reported_in_twin(self, report_entities: bool) -> Self {
+        Self {
+            report_entities,
+            ..self
         }
     }
 
@@ -63,7 +103,16 @@ impl AzureConverter {
     fn try_convert(&mut self, input: &MqttMessage) -> Result<Vec<MqttMessage>, ConversionError> {
         let messages = match self.mqtt_schema.entity_channel_of(&input.topic) {
             Ok((entity, channel)) => self.try_convert_te_topics(input, &entity, channel),
-            Err(_) => Ok(Vec::new()),
+            Err(_) if twin::is_desired_patch(&input.topic) => self.convert_desired_patch(input),
+            Err(_) => {
+                if let Some(response) = twin::TwinResponse::parse(&input.topic) {
+                    self.convert_twin_response(response, input)
+                } else if let Some(request) = methods::MethodRequest::parse(&input.topic) {
+                    self.convert_method_request(request, input)
+                } else {
+                    Ok(Vec::new())
+                }
+            }
         }?;
 
         for message in &messages {
@@ -73,10 +122,6 @@ impl AzureConverter {
         Ok(messages)
     }
 
-    // Todo: The device-id,telemetry kind (Meausrement/event/alarm) and telemetry type from the te topic has to be
-    // used to push the telemetry messages on to specific azure topic.
-    // For now all the messages will be sent over az/messages/events/ topic as this is the default mqtt topic for
-    // sending the telemetry on to the azure iot hub.
     fn try_convert_te_topics(
         &mut self,
         input: &MqttMessage,
@@ -89,19 +134,310 @@ impl AzureConverter {
             return Ok(vec![]);
         }
 
+        if channel == Channel::EntityMetadata {
+            return self.convert_entity_registration(entity, input);
+        }
+
+        let mut messages = vec![];
+        if self.entity_store.get(entity).is_none() {
+            if !self.auto_register {
+                // The data will be processed once the entity registered
+                self.entity_store.cache_early_data_message(input.clone());
+                return Ok(vec![]);
+            }
+            for registration in self.entity_store.auto_register_entity(entity)? {
+                messages.append(&mut self.report_entity(&registration.topic_id));
+            }
+        }
+        messages.append(&mut self.convert_data_message(input, entity, channel)?);
+        Ok(messages)
+    }
+
+    // Todo: The telemetry kind (Meausrement/event/alarm) and telemetry type from the te topic has to be
+    // used to push the telemetry messages on to specific azure topic.
+    // For now all the messages will be sent over az/messages/events/ topic as this is the default mqtt topic for
+    // sending the telemetry on to the azure iot hub, the messages of the child devices and services
+    // being tagged with the properties of their source entity.
+    fn convert_data_message(
+        &mut self,
+        input: &MqttMessage,
+        entity: &EntityTopicId,
+        channel: Channel,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
         match &channel {
-            Channel::Measurement { .. }
-            | Channel::Event { .. }
-            | Channel::Alarm { .. }
-            | Channel::Health => {
+            Channel::Measurement { .. } => {
                 let payload = self.with_timestamp(input)?;
-                let output = MqttMessage::new(&self.mapper_config.out_topic, payload);
-                Ok(vec![output])
+                self.size_threshold
+                    .split_measurement(&self.out_topic(entity), payload)
+            }
+            Channel::Event { .. } | Channel::Alarm { .. } | Channel::Health => {
+                let payload = self.with_timestamp(input)?;
+                let chunk_id = self.clock.now().unix_timestamp_nanos().to_string();
+                self.size_threshold
+                    .chunk(&self.out_topic(entity), &chunk_id, payload)
+            }
+            Channel::EntityTwinData { fragment_key }
+                if entity == self.entity_store.main_device() =>
+            {
+                self.convert_twin_data(fragment_key, input)
+            }
+            Channel::EntityTwinData { fragment_key } if self.report_entities => {
+                self.convert_entity_twin_data(entity, fragment_key, input)
+            }
+            Channel::CommandMetadata { operation } => {
+                let capability = (entity.clone(), operation.clone());
+                if input.payload_bytes().is_empty() {
+                    self.capabilities.remove(&capability);
+                } else {
+                    self.capabilities.insert(capability);
+                }
+                Ok(vec![])
             }
+            Channel::Command { cmd_id, .. } => match methods::request_id(cmd_id) {
+                Some(rid) => self.convert_command_state(rid, input),
+                None => Ok(vec![]),
+            },
             _ => Ok(vec![]),
         }
     }
 
+    /// The device-to-cloud topic, tagged with the properties of the source entity if not the main device
+    fn out_topic(&self, entity: &EntityTopicId) -> Topic {
+        match self.entity_store.get(entity) {
+            Some(metadata) if metadata.r#type != EntityType::MainDevice => {
+                entities::tagged_topic(&self.mapper_config.out_topic, metadata)
+            }
+            _ => self.mapper_config.out_topic.clone(),
+        }
+    }
+
+    /// Register an entity, processing the data messages received before the registration
+    ///
+    /// The entity registration is ignored if invalid,
+    /// and an empty payload removes the entity from the device twin.
+    fn convert_entity_registration(
+        &mut self,
+        entity: &EntityTopicId,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        if input.payload_bytes().is_empty() {
+            return Ok(self.report_entity_removal(entity));
+        }
+        let Ok(registration) = EntityRegistrationMessage::try_from(input) else {
+            return Ok(vec![]);
+        };
+
+        let mut messages = vec![];
+        let (affected_entities, pending_entities) = self.entity_store.update(registration)?;
+        if !affected_entities.is_empty() {
+            for pending_entity in pending_entities {
+                messages.append(&mut self.report_entity(&pending_entity.reg_message.topic_id));
+                for data_message in pending_entity.data_messages {
+                    messages.append(&mut self.try_convert(&data_message)?);
+                }
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Report a child device or a service in the device twin, if enabled
+    fn report_entity(&mut self, entity: &EntityTopicId) -> Vec<MqttMessage> {
+        if !self.report_entities {
+            return vec![];
+        }
+        let Some(metadata) = self.entity_store.get(entity) else {
+            return vec![];
+        };
+        if metadata.r#type == EntityType::MainDevice {
+            return vec![];
+        }
+        let parent_xid = metadata
+            .parent
+            .as_ref()
+            .and_then(|parent| self.entity_store.get(parent))
+            .map(|parent| parent.external_id.clone());
+        let description = entities::reported_entity(metadata, parent_xid.as_ref());
+        let patch = entities::entities_patch(&metadata.external_id, description);
+        vec![self.reported_patch(patch)]
+    }
+
+    /// Remove a deregistered child device or service from the device twin, if reported
+    fn report_entity_removal(&mut self, entity: &EntityTopicId) -> Vec<MqttMessage> {
+        if !self.report_entities || entity == self.entity_store.main_device() {
+            return vec![];
+        }
+        let Some(metadata) = self.entity_store.get(entity) else {
+            return vec![];
+        };
+        let patch = entities::entities_patch(&metadata.external_id, Value::Null);
+        vec![self.reported_patch(patch)]
+    }
+
+    /// Report a twin fragment of a child device or a service, along the description of this entity
+    fn convert_entity_twin_data(
+        &mut self,
+        entity: &EntityTopicId,
+        fragment_key: &str,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let value = match input.payload_bytes() {
+            [] => Value::Null,
+            payload => serde_json::from_slice(payload)?,
+        };
+        let Some(metadata) = self.entity_store.get(entity) else {
+            return Ok(vec![]);
+        };
+        let mut description = Map::new();
+        description.insert(fragment_key.to_string(), value);
+        let patch = entities::entities_patch(&metadata.external_id, Value::Object(description));
+        Ok(vec![self.reported_patch(patch)])
+    }
+
+    fn reported_patch(&mut self, properties: Map<String, Value>) -> MqttMessage {
+        self.twin_rid += 1;
+        twin::reported_patch(self.twin_rid, properties)
+    }
+
+    /// Report a twin fragment of the main device as a property of the device twin
+    ///
+    /// A cleared twin fragment is removed from the reported properties.
+    fn convert_twin_data(
+        &mut self,
+        fragment_key: &str,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let value = match input.payload_bytes() {
+            [] => Value::Null,
+            payload => serde_json::from_slice(payload)?,
+        };
+        let mut reported = Map::new();
+        reported.insert(fragment_key.to_string(), value);
+        Ok(vec![self.reported_patch(reported)])
+    }
+
+    /// Publish the desired properties of a patch as twin fragments of the main device
+    fn convert_desired_patch(
+        &mut self,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let patch: Value = serde_json::from_slice(input.payload_bytes())?;
+        Ok(self.twin_updates(&patch))
+    }
+
+    /// Apply the desired properties of the full twin received on start
+    fn convert_twin_response(
+        &mut self,
+        response: twin::TwinResponse,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        if !response.is_success() {
+            return Err(ConversionError::TwinRequestFailed {
+                rid: response.rid,
+                status: response.status,
+            });
+        }
+        if !response.is_full_twin() {
+            return Ok(vec![]);
+        }
+        let twin: Value = serde_json::from_slice(input.payload_bytes())?;
+        match twin.get("desired") {
+            Some(desired) => Ok(self.twin_updates(desired)),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn twin_updates(&self, desired: &Value) -> Vec<MqttMessage> {
+        let main_device = self.entity_store.main_device();
+        twin::desired_properties(desired)
+            .into_iter()
+            .map(|(key, value)| {
+                let channel = Channel::EntityTwinData { fragment_key: key };
+                let topic = self.mqtt_schema.topic_for(main_device, &channel);
+                let payload = match value {
+                    Value::Null => String::new(),
+                    value => value.to_string(),
+                };
+                MqttMessage::new(&topic, payload)
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce)
+            })
+            .collect()
+    }
+
+    /// Trigger on the target entity the command requested by a direct method
+    ///
+    /// The methods that cannot be processed are immediately answered with an error status.
+    fn convert_method_request(
+        &mut self,
+        request: methods::MethodRequest,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let error_response = |status, reason: String| {
+            let error = json!({ "reason": reason });
+            Ok(vec![methods::method_response(&request.rid, status, &error)])
+        };
+
+        let mut payload = match methods::MethodPayload::parse(input.payload_bytes()) {
+            Ok(payload) => payload,
+            Err(err) => return error_response(400, format!("Invalid method payload: {err}")),
+        };
+        let target = match payload.target.take() {
+            None => self.entity_store.main_device().clone(),
+            Some(external_id) => match self
+                .entity_store
+                .get_by_external_id(&external_id.as_str().into())
+            {
+                Some(entity) => entity.topic_id.clone(),
+                None => {
+                    return error_response(404, format!("Unknown target entity: {external_id}"))
+                }
+            },
+        };
+        if !self
+            .capabilities
+            .contains(&(target.clone(), request.operation()))
+        {
+            return error_response(
+                501,
+                format!(
+                    "The {} operation is not supported by {target}",
+                    request.method
+                ),
+            );
+        }
+
+        let cmd_id = request.cmd_id(self.clock.now().unix_timestamp());
+        Ok(vec![request.command(
+            &self.mqtt_schema,
+            &target,
+            cmd_id,
+            payload,
+        )])
+    }
+
+    /// Respond to a direct method, once the command created for this method is completed
+    ///
+    /// The command is then cleared.
+    fn convert_command_state(
+        &mut self,
+        rid: &str,
+        input: &MqttMessage,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let Some(command) = GenericCommandState::from_command_message(input)? else {
+            return Ok(vec![]);
+        };
+        let status = match command.status.as_str() {
+            "successful" => 200,
+            "failed" => 500,
+            _ => return Ok(vec![]),
+        };
+        let response = methods::method_response(rid, status, &command.payload);
+        let clear_command = MqttMessage::new(&input.topic, "")
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce);
+        Ok(vec![response, clear_command])
+    }
+
     fn with_timestamp(&mut self, input: &MqttMessage) -> Result<String, ConversionError> {
         let time_format = self.mapper_config.time_format;
         let mut payload: Map<String, Value> = serde_json::from_slice(input.payload.as_bytes())?;
@@ -142,6 +478,10 @@ impl Converter for AzureConverter {
 
         Ok(self.wrap_errors(messages_or_err))
     }
+
+    fn init_messages(&mut self) -> Result<Vec<Self::Output>, Self::Error> {
+        Ok(vec![twin::get_twin_request()])
+    }
 }
 
 #[cfg(test)]
@@ -151,6 +491,7 @@ mod tests {
     use assert_json_diff::*;
     use assert_matches::*;
     use serde_json::json;
+    use tedge_test_utils::fs::TempTedgeDir;
     use test_case::test_case;
     use time::macros::datetime;
 
@@ -162,6 +503,19 @@ mod tests {
         }
     }
 
+    fn new_entity_store(tmp_dir: &TempTedgeDir) -> EntityStore {
+        EntityStore::with_main_device_and_default_service_type(
+            MqttSchema::default(),
+            EntityRegistrationMessage::main_device("test-device".to_string()),
+            "service".to_string(),
+            entities::external_id,
+            entities::validate_external_id,
+            5,
+            tmp_dir.path(),
+        )
+        .unwrap()
+    }
+
     fn new_tedge_message(input: &str) -> MqttMessage {
         MqttMessage::new(&Topic::new_unchecked("te/device/main///m/"), input)
     }
@@ -172,11 +526,13 @@ mod tests {
 
     #[test]
     fn convert_error() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             true,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = "Invalid JSON";
@@ -192,11 +548,13 @@ mod tests {
 
     #[test]
     fn try_convert_invalid_json_returns_error() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = "This is not Thin Edge JSON";
@@ -207,11 +565,13 @@ mod tests {
 
     #[test]
     fn try_convert_exceeding_threshold_returns_error() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         )
         .with_threshold(SizeThreshold(1));
 
@@ -230,14 +590,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn split_a_measurement_exceeding_threshold() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        )
+        .with_threshold(SizeThreshold(64));
+
+        let input = r#"{"temperature": 23.0, "pressure": 220.0, "humidity": 45.0, "voltage": 3.3}"#;
+        let messages = converter.try_convert(&new_tedge_message(input)).unwrap();
+
+        assert!(messages.len() > 1);
+        let mut series = Map::new();
+        for message in messages {
+            assert_eq!(message.topic.name, "az/messages/events/");
+            assert!(message.payload_bytes().len() <= 64);
+            let measurement: Value = serde_json::from_slice(message.payload_bytes()).unwrap();
+            series.extend(measurement.as_object().unwrap().clone());
+        }
+        assert_eq!(
+            Value::Object(series),
+            json!({"temperature": 23.0, "pressure": 220.0, "humidity": 45.0, "voltage": 3.3})
+        );
+    }
+
+    #[test]
+    fn chunk_an_event_exceeding_threshold() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        )
+        .with_threshold(SizeThreshold(128));
+
+        let input = json!({"text": "x".repeat(500)}).to_string();
+        let event = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///e/big"),
+            input.as_str(),
+        );
+        let messages = converter.try_convert(&event).unwrap();
+
+        assert!(messages.len() > 1);
+        let mut payload = String::new();
+        for (index, message) in messages.iter().enumerate() {
+            assert_eq!(message.topic.name, "az/messages/events/");
+            assert!(message.payload_bytes().len() <= 128);
+            let chunk: Value = serde_json::from_slice(message.payload_bytes()).unwrap();
+            assert_eq!(chunk["chunk"]["index"], index);
+            assert_eq!(chunk["chunk"]["count"], messages.len());
+            payload.push_str(chunk["data"].as_str().unwrap());
+        }
+        assert_eq!(payload, input);
+    }
+
+    #[test]
+    fn large_integers_booleans_and_arrays_are_forwarded_unchanged() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        let input =
+            r#"{"counter":18446744073709551615,"running":true,"vibration":{"spectrum":[0.5,1,2]}}"#;
+
+        let output = converter.convert(&new_tedge_message(input)).unwrap();
+
+        assert_eq!(extract_first_message_payload(output), input);
+    }
+
     #[test]
     fn converting_input_without_timestamp_produces_output_without_timestamp_given_add_timestamp_is_false(
     ) {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{
@@ -260,11 +702,13 @@ mod tests {
     #[test]
     fn converting_input_with_timestamp_produces_output_with_timestamp_given_add_timestamp_is_false()
     {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{
@@ -289,11 +733,13 @@ mod tests {
     #[test]
     fn converting_input_with_timestamp_produces_output_with_timestamp_given_add_timestamp_is_true()
     {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             true,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{
@@ -318,11 +764,13 @@ mod tests {
     #[test]
     fn converting_input_with_unix_timestamp_produces_output_with_rfc3339_timestamp_given_add_timestamp_is_true(
     ) {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             true,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{
@@ -346,11 +794,13 @@ mod tests {
 
     #[test]
     fn converting_input_with_unix_timestamp_preserved() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             true,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{
@@ -375,11 +825,13 @@ mod tests {
     #[test]
     fn converting_input_without_timestamp_produces_output_with_timestamp_given_add_timestamp_is_true(
     ) {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             true,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{
@@ -408,19 +860,19 @@ mod tests {
     )]
     #[test_case(
         "te/device/child///m/m_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fchild%2F%2F&entityType=child-device&externalId=test-device%3Adevice%3Achild",
         r#"{"temperature":23.0,"time":"2021-04-08T00:00:00+05:00"}"#
         ; "child device measurement"
     )]
     #[test_case(
         "te/device/main/service/m_service/m/m_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fmain%2Fservice%2Fm_service&entityType=service&externalId=test-device%3Adevice%3Amain%3Aservice%3Am_service",
         r#"{"temperature":23.0,"time":"2021-04-08T00:00:00+05:00"}"#
         ; "main device service measurement"
     )]
     #[test_case(
         "te/device/child/service/c_service/m/m_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fchild%2Fservice%2Fc_service&entityType=service&externalId=test-device%3Adevice%3Achild%3Aservice%3Ac_service",
         r#"{"temperature":23.0,"time":"2021-04-08T00:00:00+05:00"}"#
         ; "child device service measurement"
     )]
@@ -432,19 +884,19 @@ mod tests {
     )]
     #[test_case(
         "te/device/child///e/e_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fchild%2F%2F&entityType=child-device&externalId=test-device%3Adevice%3Achild",
         r#"{"text":"someone logged-in","time":"2021-04-08T00:00:00+05:00"}"#
         ; "child device event"
     )]
     #[test_case(
         "te/device/main/service/m_service/e/e_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fmain%2Fservice%2Fm_service&entityType=service&externalId=test-device%3Adevice%3Amain%3Aservice%3Am_service",
         r#"{"text":"someone logged-in","time":"2021-04-08T00:00:00+05:00"}"#
         ; "main device service event"
     )]
     #[test_case(
         "te/device/child/service/c_service/e/e_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fchild%2Fservice%2Fc_service&entityType=service&externalId=test-device%3Adevice%3Achild%3Aservice%3Ac_service",
         r#"{"text":"someone logged-in","time":"2021-04-08T00:00:00+05:00"}"#
         ; "child device service event"
     )]
@@ -456,28 +908,30 @@ mod tests {
     )]
     #[test_case(
         "te/device/child///a/a_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fchild%2F%2F&entityType=child-device&externalId=test-device%3Adevice%3Achild",
         r#"{"severity":"critical","time":"2021-04-08T00:00:00+05:00"}"#
         ; "child device alarm"
     )]
     #[test_case(
         "te/device/main/service/m_service/a/a_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fmain%2Fservice%2Fm_service&entityType=service&externalId=test-device%3Adevice%3Amain%3Aservice%3Am_service",
         r#"{"severity":"critical","time":"2021-04-08T00:00:00+05:00"}"#
         ; "main device service alarm"
     )]
     #[test_case(
         "te/device/child/service/c_service/a/a_type",
-        "az/messages/events/",
+        "az/messages/events/entity=device%2Fchild%2Fservice%2Fc_service&entityType=service&externalId=test-device%3Adevice%3Achild%3Aservice%3Ac_service",
         r#"{"severity":"critical","time":"2021-04-08T00:00:00+05:00"}"#
         ; "child device service alarm"
     )]
     fn converting_az_telemetry(input_topic: &str, output_topic: &str, input: &str) {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             true,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
         let input_message = MqttMessage::new(&Topic::new_unchecked(input_topic), input);
 
@@ -489,11 +943,13 @@ mod tests {
 
     #[test]
     fn converting_bridge_health_status() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = "0";
@@ -507,11 +963,13 @@ mod tests {
 
     #[test]
     fn converting_service_health_status_up_message() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Unix,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{"pid":1234,"status":"up","time":1694586060}"#;
@@ -520,18 +978,23 @@ mod tests {
             input,
         ));
 
-        let expected_msg = MqttMessage::new(&Topic::new_unchecked("az/messages/events/"), input);
+        let expected_msg = MqttMessage::new(
+            &Topic::new_unchecked("az/messages/events/entity=device%2Fmain%2Fservice%2Ftedge-mapper-az&entityType=service&externalId=test-device%3Adevice%3Amain%3Aservice%3Atedge-mapper-az"),
+            input,
+        );
         let res = result.unwrap();
         assert_eq!(res[0], expected_msg);
     }
 
     #[test]
     fn converting_service_health_status_down_message() {
+        let tmp_dir = TempTedgeDir::new();
         let mut converter = AzureConverter::new(
             false,
             Box::new(TestClock),
             MqttSchema::default(),
             TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
         );
 
         let input = r#"{"pid":1234,"status":"up"}"#;
@@ -540,8 +1003,479 @@ mod tests {
             input,
         ));
 
-        let expected_msg = MqttMessage::new(&Topic::new_unchecked("az/messages/events/"), input);
+        let expected_msg = MqttMessage::new(
+            &Topic::new_unchecked("az/messages/events/entity=device%2Fmain%2Fservice%2Ftedge-mapper-az&entityType=service&externalId=test-device%3Adevice%3Amain%3Aservice%3Atedge-mapper-az"),
+            input,
+        );
         let res = result.unwrap();
         assert_eq!(res[0], expected_msg);
     }
+
+    #[test]
+    fn converting_twin_data_into_reported_properties() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///twin/firmware"),
+            r#"{"version":"1.2"}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "az/twin/PATCH/properties/reported/?$rid=1"
+        );
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"firmware":{"version":"1.2"}})
+        );
+
+        // A cleared fragment is removed from the reported properties
+        let input = MqttMessage::new(&Topic::new_unchecked("te/device/main///twin/firmware"), "");
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "az/twin/PATCH/properties/reported/?$rid=2"
+        );
+        assert_eq!(output[0].payload_str().unwrap(), r#"{"firmware":null}"#);
+
+        // The twin data of the other entities are not reported
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/child1///twin/firmware"),
+            r#"{"version":"1.2"}"#,
+        );
+        assert!(converter.try_convert(&input).unwrap().is_empty());
+    }
+
+    #[test]
+    fn converting_desired_properties_into_twin_data() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/twin/PATCH/properties/desired/?$version=5"),
+            r#"{"interval":60,"location":null,"$version":5}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output,
+            vec![
+                MqttMessage::new(
+                    &Topic::new_unchecked("te/device/main///twin/interval"),
+                    "60"
+                )
+                .with_retain()
+                .with_qos(QoS::AtLeastOnce),
+                MqttMessage::new(&Topic::new_unchecked("te/device/main///twin/location"), "")
+                    .with_retain()
+                    .with_qos(QoS::AtLeastOnce),
+            ]
+        );
+    }
+
+    #[test]
+    fn applying_desired_properties_of_the_full_twin_on_start() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        let messages = converter.init_messages().unwrap();
+        assert_eq!(messages[0].topic.name, "az/twin/GET/?$rid=tedge-get-twin");
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/twin/res/200/?$rid=tedge-get-twin"),
+            r#"{"desired":{"interval":60,"$version":5},"reported":{"interval":30,"$version":2}}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output,
+            vec![MqttMessage::new(
+                &Topic::new_unchecked("te/device/main///twin/interval"),
+                "60"
+            )
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce)]
+        );
+
+        // The responses to reported-property patches are ignored
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/twin/res/204/?$rid=1&$version=3"),
+            "",
+        );
+        assert!(converter.try_convert(&input).unwrap().is_empty());
+
+        // The rejected requests are reported as errors
+        let input = MqttMessage::new(&Topic::new_unchecked("az/twin/res/400/?$rid=2"), "");
+        assert_matches!(
+            converter.try_convert(&input),
+            Err(ConversionError::TwinRequestFailed { status: 400, .. })
+        );
+    }
+
+    #[test]
+    fn main_device_with_custom_topic_id() {
+        let tmp_dir = TempTedgeDir::new();
+        let entity_store = EntityStore::with_main_device_and_default_service_type(
+            MqttSchema::default(),
+            EntityRegistrationMessage {
+                topic_id: "device/gateway//".parse().unwrap(),
+                ..EntityRegistrationMessage::main_device("test-device".to_string())
+            },
+            "service".to_string(),
+            entities::external_id,
+            entities::validate_external_id,
+            5,
+            tmp_dir.path(),
+        )
+        .unwrap();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            entity_store,
+        );
+
+        // The twin data of the main device are reported
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/gateway///twin/firmware"),
+            r#"{"version":"1.2"}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "az/twin/PATCH/properties/reported/?$rid=1"
+        );
+
+        // The desired properties are applied to the main device
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/twin/PATCH/properties/desired/?$version=5"),
+            r#"{"interval":60,"$version":5}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "te/device/gateway///twin/interval");
+
+        // The main device is the default target of the direct methods
+        let capability = MqttMessage::new(
+            &Topic::new_unchecked("te/device/gateway///cmd/restart"),
+            "{}",
+        )
+        .with_retain();
+        converter.try_convert(&capability).unwrap();
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/restart/?$rid=1f"),
+            "{}",
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].topic.name,
+            "te/device/gateway///cmd/restart/az-1617822000-1f"
+        );
+    }
+
+    #[test]
+    fn converting_direct_methods_into_commands() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        let capability =
+            MqttMessage::new(&Topic::new_unchecked("te/device/main///cmd/restart"), "{}")
+                .with_retain();
+        assert!(converter.try_convert(&capability).unwrap().is_empty());
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/restart/?$rid=1f"),
+            r#"{"delay":10}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(
+            output[0].topic.name,
+            "te/device/main///cmd/restart/az-1617822000-1f"
+        );
+        assert!(output[0].retain);
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"init","delay":10})
+        );
+
+        // Nothing is sent back till the command is completed
+        let topic = Topic::new_unchecked("te/device/main///cmd/restart/az-1617822000-1f");
+        let input = MqttMessage::new(&topic, r#"{"status":"executing","delay":10}"#);
+        assert!(converter.try_convert(&input).unwrap().is_empty());
+
+        let input = MqttMessage::new(&topic, r#"{"status":"successful","delay":10}"#);
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].topic.name, "az/methods/res/200/?$rid=1f");
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"successful","delay":10})
+        );
+        assert_eq!(
+            output[1],
+            MqttMessage::new(&topic, "")
+                .with_retain()
+                .with_qos(QoS::AtLeastOnce)
+        );
+
+        // Commands not created from direct methods are ignored
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/restart/c8y-123"),
+            r#"{"status":"successful"}"#,
+        );
+        assert!(converter.try_convert(&input).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unsupported_direct_methods_are_rejected() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        // No command is created for an operation not registered as a capability
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/reboot/?$rid=1"),
+            "{}",
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].topic.name, "az/methods/res/501/?$rid=1");
+
+        // Nor for an unknown target entity
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/restart/?$rid=2"),
+            r#"{"@target":"unknown"}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].topic.name, "az/methods/res/404/?$rid=2");
+
+        // A capability can be removed
+        let capability = Topic::new_unchecked("te/device/main///cmd/restart");
+        converter
+            .try_convert(&MqttMessage::new(&capability, "{}"))
+            .unwrap();
+        converter
+            .try_convert(&MqttMessage::new(&capability, ""))
+            .unwrap();
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/restart/?$rid=3"),
+            "{}",
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "az/methods/res/501/?$rid=3");
+    }
+
+    #[test]
+    fn direct_methods_can_target_child_devices() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        );
+
+        let registration = MqttMessage::new(
+            &Topic::new_unchecked("te/device/plc1//"),
+            r#"{"@type":"child-device","@id":"plc1"}"#,
+        )
+        .with_retain();
+        converter.try_convert(&registration).unwrap();
+        let capability =
+            MqttMessage::new(&Topic::new_unchecked("te/device/plc1///cmd/restart"), "{}")
+                .with_retain();
+        converter.try_convert(&capability).unwrap();
+
+        // The main device doesn't support this operation
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/restart/?$rid=1f"),
+            r#"{"delay":10}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output[0].topic.name, "az/methods/res/501/?$rid=1f");
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("az/methods/POST/restart/?$rid=2f"),
+            r#"{"@target":"plc1","delay":10}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(
+            output[0].topic.name,
+            "te/device/plc1///cmd/restart/az-1617822000-2f"
+        );
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"status":"init","delay":10})
+        );
+
+        // The response is sent once the command of the child device is completed
+        let topic = Topic::new_unchecked("te/device/plc1///cmd/restart/az-1617822000-2f");
+        let input = MqttMessage::new(&topic, r#"{"status":"failed","reason":"busy"}"#);
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].topic.name, "az/methods/res/500/?$rid=2f");
+    }
+
+    #[test]
+    fn reporting_child_devices_in_the_device_twin() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        )
+        .with_entities_reported_in_twin(true);
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/plc1//"),
+            r#"{"@type":"child-device","name":"PLC 1"}"#,
+        )
+        .with_retain();
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 1);
+        assert_eq!(
+            output[0].topic.name,
+            "az/twin/PATCH/properties/reported/?$rid=1"
+        );
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"entities":{"test-device:device:plc1":{
+                "@topic-id":"device/plc1//",
+                "@type":"child-device",
+                "@parent":"test-device",
+                "name":"PLC 1"
+            }}})
+        );
+
+        // The twin data of the child device are reported along the child device
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/plc1///twin/firmware"),
+            r#"{"version":"2.1"}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"entities":{"test-device:device:plc1":{"firmware":{"version":"2.1"}}}})
+        );
+
+        // A deregistered child device is removed from the device twin
+        let input = MqttMessage::new(&Topic::new_unchecked("te/device/plc1//"), "").with_retain();
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(
+            output[0].payload_str().unwrap(),
+            r#"{"entities":{"test-device:device:plc1":null}}"#
+        );
+    }
+
+    #[test]
+    fn auto_registered_services_are_reported_with_their_parent() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        )
+        .with_entities_reported_in_twin(true);
+
+        let input = MqttMessage::new(
+            &Topic::new_unchecked("te/device/plc1/service/modbus/m/"),
+            r#"{"temperature":23.0}"#,
+        );
+        let output = converter.try_convert(&input).unwrap();
+        assert_eq!(output.len(), 3);
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"entities":{"test-device:device:plc1":{
+                "@topic-id":"device/plc1//",
+                "@type":"child-device",
+                "@parent":"test-device",
+                "name":"plc1"
+            }}})
+        );
+        assert_json_eq!(
+            serde_json::from_str::<Value>(output[1].payload_str().unwrap()).unwrap(),
+            json!({"entities":{"test-device:device:plc1:service:modbus":{
+                "@topic-id":"device/plc1/service/modbus",
+                "@type":"service",
+                "@parent":"test-device:device:plc1",
+                "name":"modbus",
+                "type":"service"
+            }}})
+        );
+        assert_eq!(
+            output[2].topic.name,
+            "az/messages/events/entity=device%2Fplc1%2Fservice%2Fmodbus&entityType=service&externalId=test-device%3Adevice%3Aplc1%3Aservice%3Amodbus"
+        );
+    }
+
+    #[test]
+    fn data_of_unregistered_entities_are_processed_once_registered() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut converter = AzureConverter::new(
+            false,
+            Box::new(TestClock),
+            MqttSchema::default(),
+            TimeFormat::Rfc3339,
+            new_entity_store(&tmp_dir),
+        )
+        .with_auto_registration(false);
+
+        let measurement = MqttMessage::new(
+            &Topic::new_unchecked("te/device/plc1///m/"),
+            r#"{"temperature":23.0}"#,
+        );
+        assert!(converter.try_convert(&measurement).unwrap().is_empty());
+
+        let registration = MqttMessage::new(
+            &Topic::new_unchecked("te/device/plc1//"),
+            r#"{"@type":"child-device","@id":"plc-1"}"#,
+        )
+        .with_retain();
+        let output = converter.try_convert(&registration).unwrap();
+        assert_eq!(
+            output,
+            vec![MqttMessage::new(
+                &Topic::new_unchecked(
+                    "az/messages/events/entity=device%2Fplc1%2F%2F&entityType=child-device&externalId=plc-1"
+                ),
+                r#"{"temperature":23.0}"#
+            )]
+        );
+    }
 }
@@ -0,0 +1,179 @@
+//! Representation of the thin-edge entities in Azure IoT Hub
+//!
+//! The telemetry data of the child devices and services are sent using the device-to-cloud topic
+//! of the main device, tagged with message properties telling the source entity:
+//!
+//! - `entity`: the entity topic id, e.g. `device/child01//`
+//! - `entityType`: `child-device` or `service`
+//! - `externalId`: the entity external id, e.g. `<main-device-id>:device:child01`
+//!
+//! Optionally, the child devices and services are also reported in the device twin of the main device,
+//! under the `entities` property indexed by external id.
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use tedge_api::entity_store::EntityExternalId;
+use tedge_api::entity_store::EntityMetadata;
+use tedge_api::entity_store::InvalidExternalIdError;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_mqtt_ext::Topic;
+
+/// The device twin property under which the entities are reported
+pub const ENTITIES_TWIN_PROPERTY: &str = "entities";
+
+/// The characters accepted by Azure IoT Hub in a device id, along ASCII alphanumeric characters
+///
+/// Some of these characters are not accepted in twin property keys and are encoded by [twin_key].
+const ALLOWED_ID_CHARS: [char; 15] = [
+    '-', '.', '%', '_', '*', '?', '!', '(', ')', ',', ':', '=', '@', '$', '\'',
+];
+
+/// Derive the external id of an entity from its topic id, e.g. `<main-device-id>:device:child01`
+pub fn external_id(
+    entity_topic_id: &EntityTopicId,
+    main_device_xid: &EntityExternalId,
+) -> EntityExternalId {
+    if entity_topic_id.is_default_main_device() {
+        main_device_xid.clone()
+    } else {
+        format!(
+            "{}:{}",
+            main_device_xid.as_ref(),
+            entity_topic_id
+                .to_string()
+                .trim_end_matches('/')
+                .replace('/', ":")
+        )
+        .into()
+    }
+}
+
+/// Check that an external id is a valid Azure IoT Hub device id
+pub fn validate_external_id(id: &str) -> Result<EntityExternalId, InvalidExternalIdError> {
+    match id
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !ALLOWED_ID_CHARS.contains(c))
+    {
+        Some(invalid_char) => Err(InvalidExternalIdError {
+            external_id: id.into(),
+            invalid_char,
+        }),
+        None => Ok(id.into()),
+    }
+}
+
+/// The device-to-cloud topic tagged with the properties of the source entity
+pub fn tagged_topic(out_topic: &Topic, entity: &EntityMetadata) -> Topic {
+    let properties = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("entity", entity.topic_id.as_str())
+        .append_pair("entityType", &entity.r#type.to_string())
+        .append_pair("externalId", entity.external_id.as_ref())
+        .finish();
+    Topic::new_unchecked(&format!("{}{properties}", out_topic.name))
+}
+
+/// The description of an entity as reported in the device twin
+pub fn reported_entity(entity: &EntityMetadata, parent_xid: Option<&EntityExternalId>) -> Value {
+    let mut description = entity.other.clone();
+    description.insert("@topic-id".to_string(), json!(entity.topic_id.as_str()));
+    description.insert("@type".to_string(), json!(entity.r#type.to_string()));
+    if let Some(parent) = parent_xid {
+        description.insert("@parent".to_string(), json!(parent.as_ref()));
+    }
+    Value::Object(description)
+}
+
+/// The key under which an entity is reported in the `entities` twin property
+///
+/// Azure IoT Hub rejects twin property keys containing `.` or `$`, while these are valid in external ids.
+/// These characters are percent-encoded, as is `%` itself to keep the encoding reversible.
+pub fn twin_key(external_id: &EntityExternalId) -> String {
+    let mut key = String::new();
+    for c in external_id.as_ref().chars() {
+        match c {
+            '%' | '.' | '$' => key.push_str(&format!("%{:02X}", c as u32)),
+            c => key.push(c),
+        }
+    }
+    key
+}
+
+/// The reported-property patch updating the description of an entity, `null` removing the entity
+pub fn entities_patch(external_id: &EntityExternalId, entity: Value) -> Map<String, Value> {
+    let mut entities = Map::new();
+    entities.insert(twin_key(external_id), entity);
+    let mut patch = Map::new();
+    patch.insert(ENTITIES_TWIN_PROPERTY.to_string(), Value::Object(entities));
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_api::entity_store::EntityType;
+
+    #[test]
+    fn external_ids_are_prefixed_by_the_main_device_id() {
+        let main: EntityExternalId = "gateway".into();
+        assert_eq!(
+            external_id(&"device/main//".parse().unwrap(), &main),
+            "gateway".into()
+        );
+        assert_eq!(
+            external_id(&"device/child01//".parse().unwrap(), &main),
+            "gateway:device:child01".into()
+        );
+        assert_eq!(
+            external_id(&"device/child01/service/plc".parse().unwrap(), &main),
+            "gateway:device:child01:service:plc".into()
+        );
+    }
+
+    #[test]
+    fn validate_azure_device_ids() {
+        assert!(validate_external_id("gateway:device:child-01").is_ok());
+        assert_eq!(
+            validate_external_id("child 01").unwrap_err().invalid_char,
+            ' '
+        );
+        assert_eq!(
+            validate_external_id("child/01").unwrap_err().invalid_char,
+            '/'
+        );
+    }
+
+    #[test]
+    fn entities_are_reported_under_valid_twin_keys() {
+        assert_eq!(
+            twin_key(&"gateway:device:child-01".into()),
+            "gateway:device:child-01"
+        );
+        assert_eq!(
+            twin_key(&"my.gateway:device:$child50%".into()),
+            "my%2Egateway:device:%24child50%25"
+        );
+        assert_eq!(
+            entities_patch(&"my.gateway".into(), Value::Null),
+            json!({"entities": {"my%2Egateway": null}})
+                .as_object()
+                .unwrap()
+                .clone()
+        );
+    }
+
+    #[test]
+    fn tag_messages_with_the_source_entity() {
+        let entity = EntityMetadata {
+            topic_id: "device/child01//".parse().unwrap(),
+            parent: Some("device/main//".parse().unwrap()),
+            r#type: EntityType::ChildDevice,
+            external_id: "gateway:device:child01".into(),
+            other: Map::new(),
+            twin_data: Map::new(),
+        };
+        assert_eq!(
+            tagged_topic(&Topic::new_unchecked("az/messages/events/"), &entity).name,
+            "az/messages/events/entity=device%2Fchild01%2F%2F&entityType=child-device&externalId=gateway%3Adevice%3Achild01"
+        );
+    }
+}
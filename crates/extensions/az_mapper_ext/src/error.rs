@@ -17,4 +17,13 @@ pub enum ConversionError {
 
     #[error(transparent)]
     FromTimeFormatError(#[from] time::error::Format),
+
+    #[error(transparent)]
+    FromEntityStore(#[from] tedge_api::entity_store::Error),
+
+    #[error(transparent)]
+    FromWorkflowExecution(#[from] tedge_api::workflow::WorkflowExecutionError),
+
+    #[error("The twin request {rid} has been rejected by Azure IoT Hub with status {status}")]
+    TwinRequestFailed { rid: String, status: u16 },
 }
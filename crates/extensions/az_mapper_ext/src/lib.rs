@@ -1,3 +1,6 @@
 pub mod converter;
+pub mod entities;
 pub mod error;
+pub mod methods;
 pub mod size_threshold;
+pub mod twin;
@@ -0,0 +1,188 @@
+//! Mapping of Azure IoT Hub direct methods onto thin-edge commands
+//!
+//! - A direct method invoked on `$iothub/methods/POST/<method>/?$rid=<rid>`
+//!   is turned into a command on `te/<entity>/cmd/<method>/az-<timestamp>-<rid>`,
+//!   the method payload, if a JSON object, being copied into the command payload.
+//!   The target entity is the main device, unless given by its external id
+//!   with the `@target` property of the method payload.
+//! - A method that is not a capability of the target entity, as registered on `te/<entity>/cmd/<method>`,
+//!   is immediately answered with a 501 status, and a method targeting an unknown entity with a 404 status.
+//! - When the command is completed, the final command state is sent back as the method response
+//!   on `$iothub/methods/res/<status>/?$rid=<rid>`, with a 200 status for a successful command
+//!   and a 500 status for a failed one.
+//!
+//! These Azure topics are bridged locally, replacing the `$iothub/` prefix by `az/`.
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::mqtt_topics::OperationType;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+
+const METHOD_REQUEST_TOPIC_PREFIX: &str = "az/methods/POST/";
+
+/// Prefix of the ids of the commands created from direct methods
+const CMD_ID_PREFIX: &str = "az-";
+
+/// The property of a method payload giving the external id of the target entity
+const TARGET_PROPERTY: &str = "@target";
+
+/// The topics to subscribe to, for the direct methods, the associated commands and the capabilities
+pub fn method_topics(mqtt_schema: &MqttSchema) -> TopicFilter {
+    let mut topics = mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::AnyCommand);
+    topics.add_all(mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::AnyCommandMetadata));
+    topics.add_unchecked(&format!("{METHOD_REQUEST_TOPIC_PREFIX}#"));
+    topics
+}
+
+/// A direct method invocation
+#[derive(Debug, Eq, PartialEq)]
+pub struct MethodRequest {
+    pub method: String,
+    pub rid: String,
+}
+
+impl MethodRequest {
+    /// Parse the topic of a direct method request, e.g. `az/methods/POST/restart/?$rid=1`
+    pub fn parse(topic: &Topic) -> Option<Self> {
+        let (method, properties) = topic
+            .name
+            .strip_prefix(METHOD_REQUEST_TOPIC_PREFIX)?
+            .split_once("/?")?;
+        if method.is_empty() || method.contains('/') {
+            return None;
+        }
+        let rid = properties
+            .split('&')
+            .find_map(|property| property.strip_prefix("$rid="))?;
+        if rid.is_empty() {
+            return None;
+        }
+        Some(MethodRequest {
+            method: method.to_string(),
+            rid: rid.to_string(),
+        })
+    }
+
+    /// The id of the command created for this request
+    ///
+    /// The request id is prefixed by a timestamp,
+    /// as the IoT Hub might reuse request ids over reconnections.
+    pub fn cmd_id(&self, unix_timestamp: i64) -> String {
+        format!("{CMD_ID_PREFIX}{unix_timestamp}-{}", self.rid)
+    }
+
+    /// The operation requested by this method
+    pub fn operation(&self) -> OperationType {
+        OperationType::from(self.method.as_str())
+    }
+
+    /// Build the MQTT message to trigger the command on the target entity
+    pub fn command(
+        &self,
+        mqtt_schema: &MqttSchema,
+        target: &EntityTopicId,
+        cmd_id: String,
+        payload: MethodPayload,
+    ) -> MqttMessage {
+        let mut command = payload.properties;
+        command.insert("status".to_string(), json!("init"));
+
+        let channel = Channel::Command {
+            operation: self.operation(),
+            cmd_id,
+        };
+        let topic = mqtt_schema.topic_for(target, &channel);
+        MqttMessage::new(&topic, Value::Object(command).to_string())
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce)
+    }
+}
+
+/// The payload of a direct method
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct MethodPayload {
+    /// The external id of the target entity, if not the main device
+    pub target: Option<String>,
+
+    /// The command properties
+    pub properties: Map<String, Value>,
+}
+
+impl MethodPayload {
+    /// Parse a method payload, ignoring the payloads that are not JSON objects
+    pub fn parse(payload: &[u8]) -> Result<Self, serde_json::Error> {
+        let mut properties = match payload {
+            [] => Map::new(),
+            payload => match serde_json::from_slice(payload)? {
+                Value::Object(properties) => properties,
+                _ => Map::new(),
+            },
+        };
+        let target = match properties.remove(TARGET_PROPERTY) {
+            Some(Value::String(target)) => Some(target),
+            _ => None,
+        };
+        Ok(MethodPayload { target, properties })
+    }
+}
+
+/// Extract the request id from the id of a command created for a direct method
+pub fn request_id(cmd_id: &str) -> Option<&str> {
+    let (_timestamp, rid) = cmd_id.strip_prefix(CMD_ID_PREFIX)?.split_once('-')?;
+    Some(rid)
+}
+
+/// Build the response to a direct method
+pub fn method_response(rid: &str, status: u16, payload: &Value) -> MqttMessage {
+    let topic = Topic::new_unchecked(&format!("az/methods/res/{status}/?$rid={rid}"));
+    MqttMessage::new(&topic, payload.to_string()).with_qos(QoS::AtLeastOnce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_method_requests() {
+        let request =
+            MethodRequest::parse(&Topic::new_unchecked("az/methods/POST/restart/?$rid=1f"))
+                .unwrap();
+        assert_eq!(request.method, "restart");
+        assert_eq!(request.rid, "1f");
+        assert_eq!(request.cmd_id(1697000000), "az-1697000000-1f");
+        assert_eq!(request_id("az-1697000000-1f"), Some("1f"));
+
+        assert_eq!(
+            MethodRequest::parse(&Topic::new_unchecked("az/methods/POST/restart/")),
+            None
+        );
+        assert_eq!(
+            MethodRequest::parse(&Topic::new_unchecked("az/methods/POST//?$rid=1")),
+            None
+        );
+        assert_eq!(request_id("c8y-123"), None);
+    }
+
+    #[test]
+    fn parse_method_payloads() {
+        assert_eq!(MethodPayload::parse(b"").unwrap(), MethodPayload::default());
+        assert_eq!(
+            MethodPayload::parse(b"42").unwrap(),
+            MethodPayload::default()
+        );
+
+        let payload = MethodPayload::parse(br#"{"@target":"child01","delay":10}"#).unwrap();
+        assert_eq!(payload.target, Some("child01".to_string()));
+        assert_eq!(Value::Object(payload.properties), json!({"delay":10}));
+
+        assert!(MethodPayload::parse(b"{").is_err());
+    }
+}
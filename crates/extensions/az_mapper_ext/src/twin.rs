@@ -0,0 +1,133 @@
+//! Mapping of the main device twin data onto the Azure IoT Hub device twin
+//!
+//! - The twin fragments published on `te/device/main///twin/<fragment>` are reported
+//!   as properties of the device twin, using `$iothub/twin/PATCH/properties/reported/?$rid=<rid>`.
+//! - The desired-property patches notified on `$iothub/twin/PATCH/properties/desired/?$version=<version>`
+//!   are published as twin fragments on `te/device/main///twin/<property>`.
+//! - On start, the full device twin is requested, so the desired properties updated
+//!   while the device was offline are applied too.
+//!
+//! These Azure topics are bridged locally, replacing the `$iothub/` prefix by `az/`.
+use serde_json::Map;
+use serde_json::Value;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+
+const REPORTED_PATCH_TOPIC_PREFIX: &str = "az/twin/PATCH/properties/reported/";
+const DESIRED_PATCH_TOPIC_PREFIX: &str = "az/twin/PATCH/properties/desired/";
+const TWIN_RESPONSE_TOPIC_PREFIX: &str = "az/twin/res/";
+
+/// Request id used to get the full device twin on start
+const GET_TWIN_RID: &str = "tedge-get-twin";
+
+/// The local topics on which the IoT Hub notifies the desired properties and the twin responses
+pub fn twin_topics() -> TopicFilter {
+    let mut topics = TopicFilter::empty();
+    topics.add_unchecked(&format!("{DESIRED_PATCH_TOPIC_PREFIX}#"));
+    topics.add_unchecked(&format!("{TWIN_RESPONSE_TOPIC_PREFIX}#"));
+    topics
+}
+
+/// The request sent on start to get the full device twin
+pub fn get_twin_request() -> MqttMessage {
+    let topic = Topic::new_unchecked(&format!("az/twin/GET/?$rid={GET_TWIN_RID}"));
+    MqttMessage::new(&topic, "").with_qos(QoS::AtLeastOnce)
+}
+
+/// Build a patch of the reported properties
+pub fn reported_patch(rid: u64, properties: Map<String, Value>) -> MqttMessage {
+    let topic = Topic::new_unchecked(&format!("{REPORTED_PATCH_TOPIC_PREFIX}?$rid={rid}"));
+    MqttMessage::new(&topic, Value::Object(properties).to_string()).with_qos(QoS::AtLeastOnce)
+}
+
+/// Tell if a topic is used by the IoT Hub to notify a desired-property patch
+pub fn is_desired_patch(topic: &Topic) -> bool {
+    topic.name.starts_with(DESIRED_PATCH_TOPIC_PREFIX)
+}
+
+/// A response of the IoT Hub to a twin request
+#[derive(Debug, Eq, PartialEq)]
+pub struct TwinResponse {
+    pub status: u16,
+    pub rid: String,
+}
+
+impl TwinResponse {
+    /// Parse the topic of a twin response, e.g. `az/twin/res/204/?$rid=3&$version=7`
+    pub fn parse(topic: &Topic) -> Option<Self> {
+        let (status, properties) = topic
+            .name
+            .strip_prefix(TWIN_RESPONSE_TOPIC_PREFIX)?
+            .split_once("/?")?;
+        let status = status.parse().ok()?;
+        let rid = properties
+            .split('&')
+            .find_map(|property| property.strip_prefix("$rid="))?
+            .to_string();
+        Some(TwinResponse { status, rid })
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Tell if this is the response to the request sent on start to get the full twin
+    pub fn is_full_twin(&self) -> bool {
+        self.rid == GET_TWIN_RID
+    }
+}
+
+/// Extract the desired properties from a desired-property patch or from a full device twin
+///
+/// The IoT Hub metadata properties, i.e. those starting with `$`, are ignored.
+pub fn desired_properties(properties: &Value) -> Vec<(String, Value)> {
+    let Some(properties) = properties.as_object() else {
+        return vec![];
+    };
+    properties
+        .iter()
+        .filter(|(key, _)| !key.starts_with('$'))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_twin_responses() {
+        assert_eq!(
+            TwinResponse::parse(&Topic::new_unchecked("az/twin/res/204/?$rid=3&$version=7")),
+            Some(TwinResponse {
+                status: 204,
+                rid: "3".to_string()
+            })
+        );
+        assert_eq!(
+            TwinResponse::parse(&Topic::new_unchecked(
+                "az/twin/res/200/?$rid=tedge-get-twin"
+            )),
+            Some(TwinResponse {
+                status: 200,
+                rid: "tedge-get-twin".to_string()
+            })
+        );
+        assert_eq!(
+            TwinResponse::parse(&Topic::new_unchecked("az/twin/res/200")),
+            None
+        );
+    }
+
+    #[test]
+    fn ignore_iothub_metadata() {
+        let patch = json!({"interval": 60, "$version": 4});
+        assert_eq!(
+            desired_properties(&patch),
+            vec![("interval".to_string(), json!(60))]
+        );
+    }
+}
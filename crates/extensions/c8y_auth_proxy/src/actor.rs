@@ -5,6 +5,7 @@ use axum::async_trait;
 use c8y_http_proxy::credentials::C8YJwtRetriever;
 use c8y_http_proxy::credentials::JwtRetriever;
 use camino::Utf8PathBuf;
+use certificate::KeyProvider;
 use futures::channel::mpsc;
 use futures::StreamExt;
 use tedge_actors::Actor;
@@ -32,7 +33,7 @@ pub struct C8yAuthProxyBuilder {
     signal_sender: mpsc::Sender<RuntimeRequest>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     cert_path: OptionalConfig<Utf8PathBuf>,
-    key_path: OptionalConfig<Utf8PathBuf>,
+    key_path: OptionalConfig<KeyProvider>,
     ca_path: OptionalConfig<Utf8PathBuf>,
 }
 
@@ -48,7 +49,7 @@ impl C8yAuthProxyBuilder {
         let bind = &config.c8y.proxy.bind;
         let (signal_sender, signal_receiver) = mpsc::channel(10);
         let cert_path = config.c8y.proxy.cert_path.clone();
-        let key_path = config.c8y.proxy.key_path.clone();
+        let key_path = config.optional_key_provider(&config.c8y.proxy.key_path)?;
         let ca_path = config.c8y.proxy.ca_path.clone();
 
         Ok(Self {
@@ -92,7 +93,7 @@ pub struct C8yAuthProxy {
     bind_port: u16,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     cert_path: OptionalConfig<Utf8PathBuf>,
-    key_path: OptionalConfig<Utf8PathBuf>,
+    key_path: OptionalConfig<KeyProvider>,
     ca_path: OptionalConfig<Utf8PathBuf>,
 }
 
@@ -14,6 +14,7 @@ use axum::routing::get;
 use axum::Router;
 use axum_tls::config::load_ssl_config;
 use axum_tls::config::PemReader;
+use axum_tls::config::PrivateKeyLoader;
 use axum_tls::config::TrustStoreLoader;
 use axum_tls::start_tls_server;
 use futures::future::BoxFuture;
@@ -41,7 +42,7 @@ impl Server {
         address: IpAddr,
         port: u16,
         cert_path: OptionalConfig<impl PemReader>,
-        key_path: OptionalConfig<impl PemReader>,
+        key_path: OptionalConfig<impl PrivateKeyLoader>,
         ca_path: OptionalConfig<impl TrustStoreLoader>,
     ) -> anyhow::Result<Self> {
         let app = create_app(state);
@@ -29,7 +29,7 @@ use http::status::StatusCode;
 use log::debug;
 use log::error;
 use log::info;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::collections::HashMap;
 use std::future::ready;
 use std::future::Future;
@@ -52,7 +52,7 @@ const RETRY_TIMEOUT_SECS: u64 = 20;
 pub struct C8YHttpProxyActor {
     pub(crate) end_point: C8yEndPoint,
     peers: C8YHttpProxyMessageBox,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 pub struct C8YHttpProxyMessageBox {
@@ -4,7 +4,7 @@ use crate::credentials::JwtResult;
 use crate::credentials::JwtRetriever;
 use crate::messages::C8YRestRequest;
 use crate::messages::C8YRestResult;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::convert::Infallible;
 use std::path::PathBuf;
 use tedge_actors::Builder;
@@ -35,7 +35,7 @@ pub struct C8YHttpConfig {
     pub c8y_host: String,
     pub device_id: String,
     pub tmp_dir: PathBuf,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl TryFrom<&NewTEdgeConfig> for C8YHttpConfig {
@@ -45,7 +45,7 @@ impl TryFrom<&NewTEdgeConfig> for C8YHttpConfig {
         let c8y_host = tedge_config.c8y.http.or_config_not_set()?.to_string();
         let device_id = tedge_config.device.id.try_read(tedge_config)?.to_string();
         let tmp_dir = tedge_config.tmp.path.as_std_path().to_path_buf();
-        let identity = tedge_config.http.client.auth.identity()?;
+        let identity = tedge_config.http_client_tls_config()?;
 
         Ok(Self {
             c8y_host,
@@ -11,6 +11,7 @@ use std::str::FromStr;
 use std::sync::Arc;
 use tedge_api::mqtt_topics::ChannelFilter::Command;
 use tedge_api::mqtt_topics::ChannelFilter::CommandMetadata;
+use tedge_api::mqtt_topics::ChannelFilter::MeasurementMetadata;
 use tedge_api::mqtt_topics::EntityFilter::AnyEntity;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
@@ -144,6 +145,7 @@ impl C8yMapperConfig {
             topics.add_all(mqtt_schema.topics(AnyEntity, Command(cmd.clone())));
             topics.add_all(mqtt_schema.topics(AnyEntity, CommandMetadata(cmd)));
         }
+        topics.add_all(mqtt_schema.topics(AnyEntity, MeasurementMetadata));
 
         if capabilities.log_upload {
             topics.add_all(crate::operations::log_upload::log_upload_topic_filter(
@@ -218,6 +220,7 @@ impl C8yMapperConfig {
             "te/+/+/+/+",
             "te/+/+/+/+/twin/+",
             "te/+/+/+/+/m/+",
+            "te/+/+/+/+/m/+/meta",
             "te/+/+/+/+/e/+",
             "te/+/+/+/+/a/+",
             "te/+/+/+/+/status/health",
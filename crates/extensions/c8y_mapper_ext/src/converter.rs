@@ -9,6 +9,7 @@ use crate::actor::IdUploadRequest;
 use crate::dynamic_discovery::DiscoverOp;
 use crate::error::ConversionError;
 use crate::json;
+use crate::measurement_metadata::MeasurementMetadata;
 use crate::operations::FtsDownloadOperationData;
 use anyhow::anyhow;
 use anyhow::Context;
@@ -108,6 +109,8 @@ const DEFAULT_EVENT_TYPE: &str = "ThinEdgeEvent";
 const FORBIDDEN_ID_CHARS: [char; 3] = ['/', '+', '#'];
 const REQUESTER_NAME: &str = "c8y-mapper";
 const EARLY_MESSAGE_BUFFER_SIZE: usize = 100;
+/// The fields copied into each part of a measurement split to fit under the MQTT message size threshold
+const MEASUREMENT_COMMON_FIELDS: &[&str] = &["type", "time", "externalSource"];
 
 #[derive(Debug)]
 pub struct MapperConfig {
@@ -205,6 +208,9 @@ pub struct CumulocityConverter {
     pub pending_fts_download_operations: HashMap<CmdId, FtsDownloadOperationData>,
 
     pub command_id: IdGenerator,
+
+    /// The measurement metadata registered for each entity and measurement type
+    pub(crate) measurement_metadata: HashMap<EntityTopicId, HashMap<String, MeasurementMetadata>>,
 }
 
 impl CumulocityConverter {
@@ -292,6 +298,7 @@ impl CumulocityConverter {
             pending_upload_operations: HashMap::new(),
             pending_fts_download_operations: HashMap::new(),
             command_id,
+            measurement_metadata: HashMap::new(),
         })
     }
 
@@ -428,9 +435,17 @@ impl CumulocityConverter {
         let mut mqtt_messages: Vec<Message> = Vec::new();
 
         if let Some(entity) = self.entity_store.get(source) {
+            let metadata = self
+                .measurement_metadata
+                .get(source)
+                .and_then(|metadata| metadata.get(measurement_type));
             // Need to check if the input Thin Edge JSON is valid before adding a child ID to list
-            let c8y_json_payload =
-                json::from_thin_edge_json(input.payload_str()?, entity, measurement_type)?;
+            let c8y_json_payload = json::from_thin_edge_json(
+                input.payload_str()?,
+                entity,
+                measurement_type,
+                metadata.unwrap_or(&MeasurementMetadata::default()),
+            )?;
 
             if c8y_json_payload.len() < self.size_threshold.0 {
                 mqtt_messages.push(Message::new(
@@ -438,17 +453,52 @@ impl CumulocityConverter {
                     c8y_json_payload,
                 ));
             } else {
-                return Err(ConversionError::TranslatedSizeExceededThreshold {
-                    payload: input.payload_str()?.chars().take(50).collect(),
-                    topic: input.topic.name.clone(),
-                    actual_size: c8y_json_payload.len(),
-                    threshold: self.size_threshold.0,
-                });
+                // Too large to be sent as a single message: the series are spread over several measurements
+                let c8y_json: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(&c8y_json_payload)?;
+                let parts = SizeThreshold(self.size_threshold.0 - 1)
+                    .split_json_object(&c8y_json, MEASUREMENT_COMMON_FIELDS)
+                    .map_err(|_| ConversionError::TranslatedSizeExceededThreshold {
+                        payload: input
+                            .payload_str()
+                            .unwrap_or_default()
+                            .chars()
+                            .take(50)
+                            .collect(),
+                        topic: input.topic.name.clone(),
+                        actual_size: c8y_json_payload.len(),
+                        threshold: self.size_threshold.0,
+                    })?;
+                for part in parts {
+                    mqtt_messages.push(Message::new(&self.mapper_config.out_topic, part));
+                }
             }
         }
         Ok(mqtt_messages)
     }
 
+    /// Register the metadata of a measurement type, an empty payload removing these metadata
+    fn register_measurement_metadata(
+        &mut self,
+        source: &EntityTopicId,
+        input: &Message,
+        measurement_type: &str,
+    ) -> Result<Vec<Message>, ConversionError> {
+        if input.payload_bytes().is_empty() {
+            if let Some(metadata) = self.measurement_metadata.get_mut(source) {
+                metadata.remove(measurement_type);
+            }
+            return Ok(vec![]);
+        }
+
+        let metadata = MeasurementMetadata::from_json(input.payload_str()?)?;
+        self.measurement_metadata
+            .entry(source.clone())
+            .or_default()
+            .insert(measurement_type.to_string(), metadata);
+        Ok(vec![])
+    }
+
     async fn try_convert_event(
         &mut self,
         source: &EntityTopicId,
@@ -946,6 +996,10 @@ impl CumulocityConverter {
     ) -> Result<Vec<Message>, ConversionError> {
         let mut registration_messages: Vec<Message> = vec![];
         match &channel {
+            Channel::EntityMetadata if message.payload_bytes().is_empty() => {
+                // The entity has been deregistered, so are the metadata of its measurements
+                self.measurement_metadata.remove(&source);
+            }
             Channel::EntityMetadata => {
                 if let Ok(register_message) = EntityRegistrationMessage::try_from(message) {
                     match self.entity_store.update(register_message.clone()) {
@@ -1030,6 +1084,10 @@ impl CumulocityConverter {
                 self.try_convert_measurement(&source, message, measurement_type)
             }
 
+            Channel::MeasurementMetadata { measurement_type } => {
+                self.register_measurement_metadata(&source, message, measurement_type)
+            }
+
             Channel::Event { event_type } => {
                 self.try_convert_event(&source, message, event_type).await
             }
@@ -2224,6 +2282,87 @@ pub(crate) mod tests {
         assert_eq!(out_messages, vec![expected_c8y_json_message.clone()]);
     }
 
+    #[tokio::test]
+    async fn convert_measurement_using_registered_metadata() {
+        let tmp_dir = TempTedgeDir::new();
+        let (mut converter, _http_proxy) = create_c8y_converter(&tmp_dir).await;
+
+        let meta_message = Message::new(
+            &Topic::new_unchecked("te/device/main///m/environment/meta"),
+            r#"{
+                "units": {"temperature": "°C", "location": {"altitude": "m"}},
+                "scale": {"temperature": 0.1},
+                "offset": {"temperature": -40},
+                "max": {"temperature": 125}
+            }"#,
+        )
+        .with_retain();
+        assert!(converter.convert(&meta_message).await.is_empty());
+
+        let in_message = Message::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            r#"{"temperature": 650, "location": {"altitude": 120}, "humidity": 40, "time": "2021-11-16T17:45:40.571760714+01:00"}"#,
+        );
+        let expected_c8y_json_message = Message::new(
+            &Topic::new_unchecked("c8y/measurement/measurements/create"),
+            r#"{"temperature":{"temperature":{"value":25.0,"unit":"°C"}},"location":{"altitude":{"value":120.0,"unit":"m"}},"humidity":{"humidity":{"value":40.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"environment"}"#,
+        );
+        assert_eq!(
+            converter.convert(&in_message).await,
+            vec![expected_c8y_json_message]
+        );
+
+        // Measurements out of range are rejected
+        let in_message = Message::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            r#"{"temperature": 1700}"#,
+        );
+        let output = converter.convert(&in_message).await;
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].topic.name, "te/errors");
+        assert!(output[0]
+            .payload_str()
+            .unwrap()
+            .contains("Measurement temperature is out of range: 130 is not within [-inf, 125]"));
+
+        // The metadata are removed along the entity
+        let child_metadata = Message::new(
+            &Topic::new_unchecked("te/device/child1///m/environment/meta"),
+            r#"{"units": {"temperature": "°C"}}"#,
+        )
+        .with_retain();
+        converter.convert(&child_metadata).await;
+        assert!(converter
+            .measurement_metadata
+            .contains_key(&"device/child1//".parse().unwrap()));
+        let deregistration =
+            Message::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain();
+        converter.convert(&deregistration).await;
+        assert!(!converter
+            .measurement_metadata
+            .contains_key(&"device/child1//".parse().unwrap()));
+
+        // The metadata are removed by an empty retained message
+        let clear_message = Message::new(
+            &Topic::new_unchecked("te/device/main///m/environment/meta"),
+            "",
+        )
+        .with_retain();
+        assert!(converter.convert(&clear_message).await.is_empty());
+        let in_message = Message::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            r#"{"temperature": 1700, "time": "2021-11-16T17:45:40.571760714+01:00"}"#,
+        );
+        let expected_c8y_json_message = Message::new(
+            &Topic::new_unchecked("c8y/measurement/measurements/create"),
+            r#"{"temperature":{"temperature":{"value":1700.0}},"time":"2021-11-16T17:45:40.571760714+01:00","type":"environment"}"#,
+        );
+        assert_eq!(
+            converter.convert(&in_message).await,
+            vec![expected_c8y_json_message]
+        );
+    }
+
     #[tokio::test]
     async fn convert_measurement_with_child_id_with_measurement_type() {
         let tmp_dir = TempTedgeDir::new();
@@ -2483,9 +2622,44 @@ pub(crate) mod tests {
         );
         let result = converter.convert(&big_measurement_message).await;
 
+        // The series are spread over several measurements, each under the threshold
+        assert!(result.len() > 1);
+        let mut series = 0;
+        for message in result {
+            assert_eq!(message.topic.name, "c8y/measurement/measurements/create");
+            assert!(message.payload_bytes().len() < 16184);
+            let measurement: serde_json::Value =
+                serde_json::from_str(message.payload_str().unwrap()).unwrap();
+            assert_eq!(measurement["type"], "ThinEdgeMeasurement");
+            assert!(measurement["time"].is_string());
+            series += measurement
+                .as_object()
+                .unwrap()
+                .keys()
+                .filter(|key| key.starts_with("temperature"))
+                .count();
+        }
+        assert_eq!(series, 10 * 1024 / r#""temperature":25"#.len());
+    }
+
+    #[tokio::test]
+    async fn test_convert_big_measurement_group() {
+        let tmp_dir = TempTedgeDir::new();
+        let (mut converter, _http_proxy) = create_c8y_converter(&tmp_dir).await;
+        let measurement_topic = "te/device/main///m/";
+        // A group is never split, hence is rejected if too large
+        let big_group = create_thin_edge_measurement(10 * 1024);
+        let big_measurement_payload = format!(r#"{{"group":{big_group}}}"#);
+
+        let big_measurement_message = Message::new(
+            &Topic::new_unchecked(measurement_topic),
+            big_measurement_payload,
+        );
+        let result = converter.convert(&big_measurement_message).await;
+
         let payload = result[0].payload_str().unwrap();
         assert!(payload.starts_with(
-        r#"The payload {"temperature0":0,"temperature1":1,"temperature10" received on te/device/main///m/ after translation is"#
+        r#"The payload {"group":{"temperature0":0,"temperature1":1,"tempe received on te/device/main///m/ after translation is"#
     ));
         assert!(payload.ends_with("greater than the threshold size of 16184."));
     }
@@ -2526,13 +2700,23 @@ pub(crate) mod tests {
             big_measurement_payload,
         );
 
-        let result = converter.convert(&big_measurement_message).await;
+        let result: Vec<_> = converter
+            .convert(&big_measurement_message)
+            .await
+            .into_iter()
+            .filter(|m| m.topic.name == "c8y/measurement/measurements/create")
+            .collect();
 
-        let payload = result[0].payload_str().unwrap();
-        assert!(payload.starts_with(
-        r#"The payload {"temperature0":0,"temperature1":1,"temperature10" received on te/device/child1///m/ after translation is"#
-    ));
-        assert!(payload.ends_with("greater than the threshold size of 16184."));
+        assert!(result.len() > 1);
+        for message in result {
+            assert!(message.payload_bytes().len() < 16184);
+            let measurement: serde_json::Value =
+                serde_json::from_str(message.payload_str().unwrap()).unwrap();
+            assert_eq!(
+                measurement["externalSource"]["externalId"],
+                "test-device:device:child1"
+            );
+        }
     }
 
     #[tokio::test]
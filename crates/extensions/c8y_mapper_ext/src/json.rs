@@ -5,6 +5,7 @@
 //!
 //! ```
 //! use c8y_mapper_ext::json::from_thin_edge_json;
+//! use c8y_mapper_ext::measurement_metadata::MeasurementMetadata;
 //! use tedge_api::entity_store::EntityMetadata;
 //! let single_value_thin_edge_json = r#"{
 //!        "time": "2020-06-22T17:03:14.000+02:00",
@@ -12,9 +13,11 @@
 //!        "pressure": 220
 //!     }"#;
 //! let entity = EntityMetadata::main_device("test-device".to_string());
-//! let output = from_thin_edge_json(single_value_thin_edge_json, &entity,"");
+//! let metadata = MeasurementMetadata::default();
+//! let output = from_thin_edge_json(single_value_thin_edge_json, &entity, "", &metadata);
 //! ```
 
+use crate::measurement_metadata::MeasurementMetadata;
 use crate::serializer;
 use clock::Clock;
 use clock::WallClock;
@@ -33,13 +36,16 @@ pub enum CumulocityJsonError {
 }
 
 /// Converts from thin-edge measurement JSON to C8Y measurement JSON
+///
+/// The units, scaling and range registered as metadata for the measurement type are applied.
 pub fn from_thin_edge_json(
     input: &str,
     entity: &EntityMetadata,
     m_type: &str,
+    metadata: &MeasurementMetadata,
 ) -> Result<String, CumulocityJsonError> {
     let timestamp = WallClock.now();
-    let c8y_vec = from_thin_edge_json_with_timestamp(input, timestamp, entity, m_type)?;
+    let c8y_vec = from_thin_edge_json_with_timestamp(input, timestamp, entity, m_type, metadata)?;
     Ok(c8y_vec)
 }
 
@@ -48,8 +54,10 @@ fn from_thin_edge_json_with_timestamp(
     timestamp: OffsetDateTime,
     entity: &EntityMetadata,
     m_type: &str,
+    metadata: &MeasurementMetadata,
 ) -> Result<String, CumulocityJsonError> {
-    let mut serializer = serializer::C8yJsonSerializer::new(timestamp, entity, m_type);
+    let mut serializer =
+        serializer::C8yJsonSerializer::new(timestamp, entity, m_type).with_metadata(metadata);
     parse_str(input, &mut serializer)?;
     Ok(serializer.into_string()?)
 }
@@ -75,8 +83,13 @@ mod tests {
         let timestamp = datetime!(2021-04-08 0:00:0 +05:00);
 
         let entity = EntityMetadata::main_device("foo".to_string());
-        let output =
-            from_thin_edge_json_with_timestamp(single_value_thin_edge_json, timestamp, &entity, "");
+        let output = from_thin_edge_json_with_timestamp(
+            single_value_thin_edge_json,
+            timestamp,
+            &entity,
+            "",
+            &MeasurementMetadata::default(),
+        );
 
         let expected_output = json!({
             "time": timestamp
@@ -112,8 +125,13 @@ mod tests {
         let timestamp = datetime!(2021-04-08 0:00:0 +05:00);
 
         let entity = EntityMetadata::main_device("foo".to_string());
-        let output =
-            from_thin_edge_json_with_timestamp(single_value_thin_edge_json, timestamp, &entity, "");
+        let output = from_thin_edge_json_with_timestamp(
+            single_value_thin_edge_json,
+            timestamp,
+            &entity,
+            "",
+            &MeasurementMetadata::default(),
+        );
 
         let expected_output = json!({
             "time": timestamp
@@ -158,7 +176,12 @@ mod tests {
                   }"#;
 
         let entity = EntityMetadata::main_device("foo".to_string());
-        let output = from_thin_edge_json(single_value_thin_edge_json, &entity, "");
+        let output = from_thin_edge_json(
+            single_value_thin_edge_json,
+            &entity,
+            "",
+            &MeasurementMetadata::default(),
+        );
 
         assert_eq!(
             expected_output.split_whitespace().collect::<String>(),
@@ -181,8 +204,13 @@ mod tests {
         let timestamp = datetime!(2021-04-08 0:00:0 +05:00);
 
         let entity = EntityMetadata::main_device("foo".to_string());
-        let output =
-            from_thin_edge_json_with_timestamp(multi_value_thin_edge_json, timestamp, &entity, "");
+        let output = from_thin_edge_json_with_timestamp(
+            multi_value_thin_edge_json,
+            timestamp,
+            &entity,
+            "",
+            &MeasurementMetadata::default(),
+        );
 
         let expected_output = json!({
             "time": timestamp
@@ -237,7 +265,7 @@ mod tests {
         }"#;
 
         let entity = EntityMetadata::main_device("foo".to_string());
-        let output = from_thin_edge_json(input, &entity, "");
+        let output = from_thin_edge_json(input, &entity, "", &MeasurementMetadata::default());
 
         let actual_output = output.unwrap().split_whitespace().collect::<String>();
 
@@ -270,7 +298,7 @@ mod tests {
                 }}"#, time, measurement, measurement);
 
         let entity = EntityMetadata::main_device("foo".to_string());
-        let output = from_thin_edge_json(input.as_str(), &entity, "").unwrap();
+        let output = from_thin_edge_json(input.as_str(), &entity, "", &MeasurementMetadata::default()).unwrap();
         assert_eq!(
             expected_output.split_whitespace().collect::<String>(),
             output
@@ -318,7 +346,13 @@ mod tests {
     ) {
         let timestamp = datetime!(2021-04-08 0:00:0 +05:00);
         let entity = EntityMetadata::child_device(child_id.to_string()).unwrap();
-        let output = from_thin_edge_json_with_timestamp(thin_edge_json, timestamp, &entity, "");
+        let output = from_thin_edge_json_with_timestamp(
+            thin_edge_json,
+            timestamp,
+            &entity,
+            "",
+            &MeasurementMetadata::default(),
+        );
         assert_json_eq!(
             serde_json::from_str::<serde_json::Value>(output.unwrap().as_str()).unwrap(),
             expected_output
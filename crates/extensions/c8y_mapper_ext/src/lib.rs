@@ -8,6 +8,7 @@ pub mod error;
 mod fragments;
 mod inventory;
 pub mod json;
+pub mod measurement_metadata;
 mod operations;
 mod serializer;
 pub mod service_monitor;
@@ -0,0 +1,219 @@
+//! Measurement metadata registered on `te/<entity>/m/<type>/meta`
+//!
+//! The metadata of a measurement type are given per series,
+//! a series of a group being given by a nested object:
+//!
+//! ```json
+//! {
+//!     "units": { "temperature": "°C", "location": { "altitude": "m" } },
+//!     "scale": { "temperature": 0.1 },
+//!     "offset": { "temperature": -40 },
+//!     "min": { "temperature": -40 },
+//!     "max": { "temperature": 125 }
+//! }
+//! ```
+//!
+//! - `units` are added to the measurement values sent to Cumulocity.
+//! - `scale` and `offset` are used to turn the raw values into actual values: `value * scale + offset`.
+//! - `min` and `max` give the range of the actual values, the measurements out of range being rejected.
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The metadata of a measurement type, indexed by group and series names
+///
+/// The group name of a series that is not part of a group is the empty string.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeasurementMetadata {
+    series: HashMap<String, HashMap<String, SeriesMetadata>>,
+}
+
+/// The metadata of a measurement series
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SeriesMetadata {
+    pub unit: Option<String>,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Measurement {name} is out of range: {value} is not within [{min}, {max}]")]
+pub struct OutOfRange {
+    pub name: String,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl MeasurementMetadata {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let raw: RawMetadata = serde_json::from_str(json)?;
+        let mut metadata = MeasurementMetadata::default();
+        for (group, name, unit) in raw.units.flatten() {
+            metadata.series_mut(group, name).unit = Some(unit);
+        }
+        for (group, name, scale) in raw.scale.flatten() {
+            metadata.series_mut(group, name).scale = Some(scale);
+        }
+        for (group, name, offset) in raw.offset.flatten() {
+            metadata.series_mut(group, name).offset = Some(offset);
+        }
+        for (group, name, min) in raw.min.flatten() {
+            metadata.series_mut(group, name).min = Some(min);
+        }
+        for (group, name, max) in raw.max.flatten() {
+            metadata.series_mut(group, name).max = Some(max);
+        }
+        Ok(metadata)
+    }
+
+    /// The metadata of a series, `group` being empty for a series that is not part of a group
+    pub fn series(&self, group: &str, name: &str) -> Option<&SeriesMetadata> {
+        self.series.get(group)?.get(name)
+    }
+
+    fn series_mut(&mut self, group: String, name: String) -> &mut SeriesMetadata {
+        self.series
+            .entry(group)
+            .or_default()
+            .entry(name)
+            .or_default()
+    }
+}
+
+impl SeriesMetadata {
+    /// Tell if the values of the series have to be scaled or checked, and not only given a unit
+    pub fn applies_to_values(&self) -> bool {
+        self.scale.is_some() || self.offset.is_some() || self.min.is_some() || self.max.is_some()
+    }
+
+    /// Turn a raw value into an actual value, checking this value is within range
+    pub fn apply(&self, name: &str, raw_value: f64) -> Result<f64, OutOfRange> {
+        let value = raw_value * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0);
+        let min = self.min.unwrap_or(f64::NEG_INFINITY);
+        let max = self.max.unwrap_or(f64::INFINITY);
+        if value < min || value > max {
+            return Err(OutOfRange {
+                name: name.to_string(),
+                value,
+                min,
+                max,
+            });
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawMetadata {
+    #[serde(default)]
+    units: PerSeries<String>,
+    #[serde(default)]
+    scale: PerSeries<f64>,
+    #[serde(default)]
+    offset: PerSeries<f64>,
+    #[serde(default)]
+    min: PerSeries<f64>,
+    #[serde(default)]
+    max: PerSeries<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct PerSeries<T>(HashMap<String, SeriesOrGroup<T>>);
+
+impl<T> Default for PerSeries<T> {
+    fn default() -> Self {
+        PerSeries(HashMap::new())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SeriesOrGroup<T> {
+    Series(T),
+    Group(HashMap<String, T>),
+}
+
+impl<T> PerSeries<T> {
+    /// List the values as `(group, series, value)` triples
+    fn flatten(self) -> Vec<(String, String, T)> {
+        let mut values = Vec::new();
+        for (name, value) in self.0 {
+            match value {
+                SeriesOrGroup::Series(value) => values.push((String::new(), name, value)),
+                SeriesOrGroup::Group(group) => {
+                    for (series, value) in group {
+                        values.push((name.clone(), series, value))
+                    }
+                }
+            }
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_metadata_of_series_and_groups() {
+        let metadata = MeasurementMetadata::from_json(
+            r#"{
+                "units": { "temperature": "°C", "location": { "altitude": "m" } },
+                "scale": { "temperature": 0.1 },
+                "offset": { "temperature": -40 },
+                "max": { "temperature": 125 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.series("", "temperature"),
+            Some(&SeriesMetadata {
+                unit: Some("°C".to_string()),
+                scale: Some(0.1),
+                offset: Some(-40.0),
+                min: None,
+                max: Some(125.0),
+            })
+        );
+        assert_eq!(
+            metadata.series("location", "altitude"),
+            Some(&SeriesMetadata {
+                unit: Some("m".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(metadata.series("", "altitude"), None);
+    }
+
+    #[test]
+    fn apply_scale_offset_and_range() {
+        let series = SeriesMetadata {
+            scale: Some(0.1),
+            offset: Some(-40.0),
+            min: Some(-40.0),
+            max: Some(125.0),
+            ..Default::default()
+        };
+        assert_eq!(series.apply("temperature", 650.0), Ok(25.0));
+        assert_eq!(
+            series.apply("temperature", 1700.0),
+            Err(OutOfRange {
+                name: "temperature".to_string(),
+                value: 130.0,
+                min: -40.0,
+                max: 125.0
+            })
+        );
+    }
+
+    #[test]
+    fn reject_invalid_metadata() {
+        assert!(MeasurementMetadata::from_json(r#"{"units": {"temperature": 12}}"#).is_err());
+        assert!(MeasurementMetadata::from_json(r#"{"scale": "0.1"}"#).is_err());
+    }
+}
@@ -1,14 +1,19 @@
+use crate::measurement_metadata::MeasurementMetadata;
+use crate::measurement_metadata::OutOfRange;
 use json_writer::JsonWriter;
 use json_writer::JsonWriterError;
 use tedge_api::entity_store::EntityMetadata;
 use tedge_api::entity_store::EntityType;
+use tedge_api::measurement::MeasurementValue;
 use tedge_api::measurement::MeasurementVisitor;
 use time::format_description;
 use time::OffsetDateTime;
 
-pub struct C8yJsonSerializer {
+pub struct C8yJsonSerializer<'a> {
     json: JsonWriter,
     is_within_group: bool,
+    current_group: String,
+    metadata: Option<&'a MeasurementMetadata>,
     timestamp_present: bool,
     default_timestamp: OffsetDateTime,
     type_present: bool,
@@ -25,6 +30,9 @@ pub enum C8yJsonSerializationError {
 
     #[error("Unexpected measurement name: \"{name}\" is a reserved word.")]
     UnexpectedMeasurementName { name: String },
+
+    #[error(transparent)]
+    MeasurementOutOfRange(#[from] OutOfRange),
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -46,7 +54,7 @@ pub enum MeasurementStreamError {
     UnexpectedStartOfGroup,
 }
 
-impl C8yJsonSerializer {
+impl<'a> C8yJsonSerializer<'a> {
     pub fn new(default_timestamp: OffsetDateTime, entity: &EntityMetadata, m_type: &str) -> Self {
         let capa = 1024; // XXX: Choose a capacity based on expected JSON length.
         let mut json = JsonWriter::with_capacity(capa);
@@ -76,6 +84,8 @@ impl C8yJsonSerializer {
         Self {
             json,
             is_within_group: false,
+            current_group: String::new(),
+            metadata: None,
             timestamp_present: false,
             default_timestamp,
             type_present: false,
@@ -83,6 +93,14 @@ impl C8yJsonSerializer {
         }
     }
 
+    /// Apply the units, scaling and range registered for the measurement type
+    pub fn with_metadata(self, metadata: &'a MeasurementMetadata) -> Self {
+        Self {
+            metadata: Some(metadata),
+            ..self
+        }
+    }
+
     fn end(&mut self) -> Result<(), C8yJsonSerializationError> {
         if self.is_within_group {
             return Err(MeasurementStreamError::UnexpectedEndOfData.into());
@@ -102,21 +120,95 @@ impl C8yJsonSerializer {
         Ok(())
     }
 
-    fn write_value_obj(&mut self, value: f64) -> Result<(), C8yJsonSerializationError> {
+    fn write_value_obj(&mut self, key: &str, value: f64) -> Result<(), C8yJsonSerializationError> {
+        self.write_number_obj(key, &MeasurementValue::Float(value))
+    }
+
+    /// Write the value object of a number, a float or an integer
+    ///
+    /// Integers are written as such, unless scaled or range-checked by the measurement metadata.
+    fn write_number_obj(
+        &mut self,
+        key: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), C8yJsonSerializationError> {
+        let series = self
+            .metadata
+            .and_then(|metadata| metadata.series(&self.current_group, key));
+        let unit = series.and_then(|series| series.unit.clone());
+        let series = series.filter(|series| series.applies_to_values());
+
         self.json.write_open_obj();
         self.json.write_key("value")?;
-        self.json.write_f64(value)?;
+        match (series, value) {
+            (None, MeasurementValue::Int(value)) => self.json.write_i64(*value)?,
+            (None, MeasurementValue::UInt(value)) => self.json.write_u64(*value)?,
+            (series, value) => {
+                let value = value.as_f64().unwrap_or_default();
+                let value = match series {
+                    Some(series) => series.apply(key, value)?,
+                    None => value,
+                };
+                self.json.write_f64(value)?
+            }
+        }
+        if let Some(unit) = unit {
+            self.json.write_key("unit")?;
+            self.json.write_str(&unit)?;
+        }
         self.json.write_close_obj();
         Ok(())
     }
 
+    /// Write the series of a typed value
+    ///
+    /// - integers are written as such,
+    /// - booleans are written as 1 or 0,
+    /// - arrays are written as one series per item, named after the measurement and the item index,
+    ///   e.g. `spectrum_0`, `spectrum_1`, ... using the metadata of the measurement for all the items.
+    fn write_series(
+        &mut self,
+        key: &str,
+        series: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), C8yJsonSerializationError> {
+        match value {
+            MeasurementValue::Bool(value) => {
+                let value = if *value { 1.0 } else { 0.0 };
+                self.json.write_key(series)?;
+                self.write_number_obj(key, &MeasurementValue::Float(value))
+            }
+            MeasurementValue::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    self.write_series(key, &format!("{series}_{index}"), value)?;
+                }
+                Ok(())
+            }
+            value => {
+                self.json.write_key(series)?;
+                self.write_number_obj(key, value)
+            }
+        }
+    }
+
+    fn check_measurement_name(key: &str) -> Result<(), C8yJsonSerializationError> {
+        match key {
+            "type" | "externalSource" => {
+                Err(C8yJsonSerializationError::UnexpectedMeasurementName {
+                    name: key.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn into_string(mut self) -> Result<String, C8yJsonSerializationError> {
         self.end()?;
         Ok(self.json.clone().into_string()?)
     }
 }
 
-impl MeasurementVisitor for C8yJsonSerializer {
+impl MeasurementVisitor for C8yJsonSerializer<'_> {
     type Error = C8yJsonSerializationError;
 
     fn visit_timestamp(&mut self, timestamp: OffsetDateTime) -> Result<(), Self::Error> {
@@ -160,29 +252,33 @@ impl MeasurementVisitor for C8yJsonSerializer {
     }
 
     fn visit_measurement(&mut self, key: &str, value: f64) -> Result<(), Self::Error> {
-        match key {
-            "type" => {
-                return Err(C8yJsonSerializationError::UnexpectedMeasurementName {
-                    name: key.to_string(),
-                });
-            }
-            "externalSource" => {
-                return Err(C8yJsonSerializationError::UnexpectedMeasurementName {
-                    name: key.to_string(),
-                });
-            }
-            _ => {
-                self.json.write_key(key)?;
-
-                if self.is_within_group {
-                    self.write_value_obj(value)?;
-                } else {
-                    self.json.write_open_obj();
-                    self.json.write_key(key)?;
-                    self.write_value_obj(value)?;
-                    self.json.write_close_obj();
-                }
-            }
+        Self::check_measurement_name(key)?;
+        self.json.write_key(key)?;
+
+        if self.is_within_group {
+            self.write_value_obj(key, value)?;
+        } else {
+            self.json.write_open_obj();
+            self.json.write_key(key)?;
+            self.write_value_obj(key, value)?;
+            self.json.write_close_obj();
+        }
+        Ok(())
+    }
+
+    fn visit_typed_measurement(
+        &mut self,
+        key: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
+        Self::check_measurement_name(key)?;
+        if self.is_within_group {
+            self.write_series(key, key, value)?;
+        } else {
+            self.json.write_key(key)?;
+            self.json.write_open_obj();
+            self.write_series(key, key, value)?;
+            self.json.write_close_obj();
         }
         Ok(())
     }
@@ -195,6 +291,7 @@ impl MeasurementVisitor for C8yJsonSerializer {
         self.json.write_key(group)?;
         self.json.write_open_obj();
         self.is_within_group = true;
+        self.current_group = group.to_string();
         Ok(())
     }
 
@@ -205,6 +302,7 @@ impl MeasurementVisitor for C8yJsonSerializer {
 
         self.json.write_close_obj();
         self.is_within_group = false;
+        self.current_group.clear();
         Ok(())
     }
 }
@@ -246,6 +344,63 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serialize_typed_values() -> anyhow::Result<()> {
+        let timestamp = datetime!(2021-06-22 17:03:14.123456789 +05:00);
+
+        let entity = EntityMetadata::main_device("foo".to_string());
+        let metadata = MeasurementMetadata::from_json(
+            r#"{"units": {"counter": "ticks", "vibration": {"spectrum": "g"}}, "scale": {"level": 0.5}}"#,
+        )?;
+        let mut serializer =
+            C8yJsonSerializer::new(timestamp, &entity, "").with_metadata(&metadata);
+        serializer.visit_typed_measurement("counter", &u64::MAX.into())?;
+        serializer.visit_typed_measurement("level", &5_i64.into())?;
+        serializer.visit_typed_measurement("running", &true.into())?;
+        serializer.visit_start_group("vibration")?;
+        serializer.visit_typed_measurement("spectrum", &vec![0.5, 1.5].into())?;
+        serializer.visit_end_group()?;
+
+        let output = serializer.into_string()?;
+
+        let expected_output = json!({
+            "type": "ThinEdgeMeasurement",
+            "time": "2021-06-22T17:03:14.123456789+05:00",
+            "counter": {
+                "counter": {
+                    "value": 18446744073709551615_u64,
+                    "unit": "ticks"
+                }
+            },
+            "level": {
+                "level": {
+                    "value": 2.5
+                }
+            },
+            "running": {
+                "running": {
+                    "value": 1.0
+                }
+            },
+            "vibration": {
+                "spectrum_0": {
+                    "value": 0.5,
+                    "unit": "g"
+                },
+                "spectrum_1": {
+                    "value": 1.5,
+                    "unit": "g"
+                }
+            }
+        });
+
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(&output)?,
+            expected_output
+        );
+        Ok(())
+    }
+
     #[test]
     fn serialize_single_value_message_with_custom_type() -> anyhow::Result<()> {
         let timestamp = datetime!(2021-06-22 17:03:14.123456789 +05:00);
@@ -0,0 +1,186 @@
+use crate::AggregationConfig;
+use crate::MeasurementSample;
+use batcher::BatchDriverOutput;
+use clock::Timestamp;
+use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use time::format_description::well_known::Rfc3339;
+
+/// The statistics of a series over a window
+#[derive(Debug, PartialEq, Serialize)]
+struct SeriesAggregate {
+    min: f64,
+    max: f64,
+    mean: f64,
+    count: usize,
+    last: f64,
+}
+
+/// Translate a batch of samples into aggregated measurements, one per entity and measurement type
+///
+/// Each series is published as a group of `min`, `max`, `mean`, `count` and `last` values.
+/// The series of a group are named after the group and the series, e.g. `location_x`.
+/// The time of an aggregate is the time of the last sample of the window.
+pub fn batch_into_mqtt_messages(
+    mqtt_schema: &MqttSchema,
+    config: &AggregationConfig,
+    batch: BatchDriverOutput<MeasurementSample>,
+) -> Vec<MqttMessage> {
+    let mut samples: Vec<MeasurementSample> = batch.into();
+    samples.sort_by_key(|sample| sample.rank);
+
+    let mut measurements: BTreeMap<(String, String), (EntityTopicId, Vec<MeasurementSample>)> =
+        BTreeMap::new();
+    for sample in samples {
+        measurements
+            .entry((sample.entity.to_string(), sample.measurement_type.clone()))
+            .or_insert_with(|| (sample.entity.clone(), vec![]))
+            .1
+            .push(sample);
+    }
+
+    measurements
+        .into_iter()
+        .map(|((_, measurement_type), (entity, samples))| {
+            let channel = Channel::Measurement {
+                measurement_type: config.aggregate_type(&measurement_type),
+            };
+            let topic = mqtt_schema.topic_for(&entity, &channel);
+            MqttMessage::new(&topic, aggregate_payload(&samples).to_string())
+        })
+        .collect()
+}
+
+fn aggregate_payload(samples: &[MeasurementSample]) -> Value {
+    let mut series: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    let mut last_time: Option<Timestamp> = None;
+    for sample in samples {
+        let name = match &sample.group {
+            None => sample.series.clone(),
+            Some(group) => format!("{group}_{}", sample.series),
+        };
+        series.entry(name).or_default().push(sample.value);
+        last_time = Some(sample.received_at);
+    }
+
+    let mut payload = Map::new();
+    if let Some(time) = last_time.and_then(|time| time.format(&Rfc3339).ok()) {
+        payload.insert("time".to_string(), time.into());
+    }
+    for (name, values) in series {
+        if let Some(aggregate) = SeriesAggregate::of(&values) {
+            if let Ok(aggregate) = serde_json::to_value(aggregate) {
+                payload.insert(name, aggregate);
+            }
+        }
+    }
+    Value::Object(payload)
+}
+
+impl SeriesAggregate {
+    fn of(values: &[f64]) -> Option<Self> {
+        let last = *values.last()?;
+        let count = values.len();
+        Some(SeriesAggregate {
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean: values.iter().sum::<f64>() / count as f64,
+            count,
+            last,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use time::macros::datetime;
+
+    fn sample(
+        rank: u64,
+        entity: &str,
+        measurement_type: &str,
+        group: Option<&str>,
+        series: &str,
+        value: f64,
+    ) -> MeasurementSample {
+        MeasurementSample {
+            rank,
+            entity: entity.parse().unwrap(),
+            measurement_type: measurement_type.to_string(),
+            group: group.map(str::to_string),
+            series: series.to_string(),
+            value,
+            received_at: datetime!(2023-10-18 10:00:00 UTC) + time::Duration::seconds(rank as i64),
+        }
+    }
+
+    #[test]
+    fn aggregate_series_per_entity_and_type() {
+        // The samples are not ordered in a batch
+        let batch = BatchDriverOutput::Batch(vec![
+            sample(2, "device/main//", "environment", None, "temperature", 24.0),
+            sample(0, "device/main//", "environment", None, "temperature", 20.0),
+            sample(
+                1,
+                "device/main//",
+                "environment",
+                Some("location"),
+                "x",
+                1.0,
+            ),
+            sample(3, "device/main//", "environment", None, "temperature", 22.0),
+            sample(4, "device/child01//", "", None, "pressure", 1013.0),
+        ]);
+
+        let messages =
+            batch_into_mqtt_messages(&MqttSchema::default(), &AggregationConfig::default(), batch);
+        let messages: Vec<(&str, Value)> = messages
+            .iter()
+            .map(|m| {
+                (
+                    m.topic.name.as_str(),
+                    serde_json::from_str(m.payload_str().unwrap()).unwrap(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "te/device/child01///m/aggregate",
+                    json!({
+                        "time": "2023-10-18T10:00:04Z",
+                        "pressure": {"min": 1013.0, "max": 1013.0, "mean": 1013.0, "count": 1, "last": 1013.0},
+                    })
+                ),
+                (
+                    "te/device/main///m/environment_aggregate",
+                    json!({
+                        "time": "2023-10-18T10:00:03Z",
+                        "temperature": {"min": 20.0, "max": 24.0, "mean": 22.0, "count": 3, "last": 22.0},
+                        "location_x": {"min": 1.0, "max": 1.0, "mean": 1.0, "count": 1, "last": 1.0},
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_published_on_flush() {
+        let messages = batch_into_mqtt_messages(
+            &MqttSchema::default(),
+            &AggregationConfig::default(),
+            BatchDriverOutput::Flush,
+        );
+        assert!(messages.is_empty());
+    }
+}
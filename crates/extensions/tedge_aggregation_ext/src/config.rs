@@ -0,0 +1,220 @@
+use crate::AggregationError;
+use serde::Deserialize;
+use std::path::Path;
+use tedge_api::mqtt_topics::EntityTopicId;
+
+const DEFAULT_WINDOW: u32 = 60;
+const DEFAULT_TYPE_SUFFIX: &str = "_aggregate";
+
+/// The aggregation settings, as read from `/etc/tedge/mappers/aggregation.toml`
+///
+/// ```toml
+/// # The duration in seconds of the aggregation windows
+/// window = 60
+///
+/// # The suffix appended to the measurement types to name the aggregates
+/// type_suffix = "_aggregate"
+///
+/// # Aggregate all the measurements of the main device
+/// [[entities]]
+/// entity = "device/main//"
+///
+/// # Aggregate only the environment measurements of a child device
+/// [[entities]]
+/// entity = "device/child01//"
+/// types = ["environment"]
+/// ```
+///
+/// When no entities are listed, the measurements of all the entities are aggregated.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregationConfig {
+    #[serde(default = "default_window")]
+    pub window: u32,
+
+    #[serde(default = "default_type_suffix")]
+    pub type_suffix: String,
+
+    #[serde(default)]
+    pub entities: Vec<AggregatedEntity>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregatedEntity {
+    /// The entity topic id, e.g. `device/main//`
+    pub entity: String,
+
+    /// The measurement types to aggregate, all if omitted
+    pub types: Option<Vec<String>>,
+}
+
+fn default_window() -> u32 {
+    DEFAULT_WINDOW
+}
+
+fn default_type_suffix() -> String {
+    DEFAULT_TYPE_SUFFIX.to_string()
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        AggregationConfig {
+            window: DEFAULT_WINDOW,
+            type_suffix: DEFAULT_TYPE_SUFFIX.to_string(),
+            entities: vec![],
+        }
+    }
+}
+
+impl AggregationConfig {
+    /// Load the config from a TOML file, using the default config if the file doesn't exist
+    pub fn load(path: &Path) -> Result<Self, AggregationError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(AggregationConfig::default())
+            }
+            Err(error) => {
+                return Err(AggregationError::FromIo {
+                    path: path.display().to_string(),
+                    error,
+                })
+            }
+        };
+        let mut config: AggregationConfig =
+            toml::from_str(&content).map_err(|error| AggregationError::FromToml {
+                path: path.display().to_string(),
+                error,
+            })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the config, normalizing the entity topic ids, e.g. `device/child01` into `device/child01//`
+    fn validate(&mut self) -> Result<(), AggregationError> {
+        if self.window == 0 {
+            return Err(AggregationError::InvalidConfig {
+                reason: "the window must be at least one second".to_string(),
+            });
+        }
+        if self.type_suffix.is_empty() {
+            return Err(AggregationError::InvalidConfig {
+                reason: "the type suffix cannot be empty".to_string(),
+            });
+        }
+        for entity in self.entities.iter_mut() {
+            let topic_id: EntityTopicId =
+                entity
+                    .entity
+                    .parse()
+                    .map_err(|err| AggregationError::InvalidConfig {
+                        reason: format!("invalid entity {}: {err}", entity.entity),
+                    })?;
+            entity.entity = topic_id.to_string();
+        }
+        Ok(())
+    }
+
+    /// The window duration in milliseconds, as expected by the batcher
+    pub fn window_millis(&self) -> u32 {
+        self.window.saturating_mul(1000)
+    }
+
+    /// Tell if the measurements of the given entity and type have to be aggregated
+    ///
+    /// The aggregates themselves are never aggregated.
+    pub fn is_aggregated(&self, entity: &EntityTopicId, measurement_type: &str) -> bool {
+        if measurement_type.ends_with(&self.type_suffix)
+            || measurement_type == self.aggregate_type("")
+        {
+            return false;
+        }
+        self.entities.is_empty()
+            || self.entities.iter().any(|aggregated| {
+                aggregated.entity == entity.as_str()
+                    && aggregated
+                        .types
+                        .as_ref()
+                        .map_or(true, |types| types.iter().any(|t| t == measurement_type))
+            })
+    }
+
+    /// The measurement type of the aggregates of a measurement type
+    pub fn aggregate_type(&self, measurement_type: &str) -> String {
+        if measurement_type.is_empty() {
+            self.type_suffix.trim_start_matches(['_', '-']).to_string()
+        } else {
+            format!("{measurement_type}{}", self.type_suffix)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> AggregationConfig {
+        let mut config: AggregationConfig = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    #[test]
+    fn aggregate_all_entities_by_default() {
+        let config = config("");
+        assert_eq!(config.window_millis(), 60_000);
+
+        let child = EntityTopicId::default_child_device("child01").unwrap();
+        assert!(config.is_aggregated(&child, "environment"));
+        assert!(config.is_aggregated(&child, ""));
+    }
+
+    #[test]
+    fn aggregates_are_not_aggregated() {
+        let config = config("");
+        let main = EntityTopicId::default_main_device();
+        assert_eq!(
+            config.aggregate_type("environment"),
+            "environment_aggregate"
+        );
+        assert_eq!(config.aggregate_type(""), "aggregate");
+        assert!(!config.is_aggregated(&main, "environment_aggregate"));
+        assert!(!config.is_aggregated(&main, "aggregate"));
+    }
+
+    #[test]
+    fn aggregate_only_the_configured_entities_and_types() {
+        let config = config(
+            r#"
+            [[entities]]
+            entity = "device/main//"
+
+            [[entities]]
+            entity = "device/child01"
+            types = ["environment"]
+            "#,
+        );
+
+        let main = EntityTopicId::default_main_device();
+        let child01 = EntityTopicId::default_child_device("child01").unwrap();
+        let child02 = EntityTopicId::default_child_device("child02").unwrap();
+        assert!(config.is_aggregated(&main, "vibration"));
+        assert!(config.is_aggregated(&child01, "environment"));
+        assert!(!config.is_aggregated(&child01, "vibration"));
+        assert!(!config.is_aggregated(&child02, "environment"));
+    }
+
+    #[test]
+    fn reject_invalid_config() {
+        for toml in [
+            "window = 0",
+            "type_suffix = \"\"",
+            "[[entities]]\nentity = \"a/b/c/d/e\"",
+        ] {
+            let mut config: AggregationConfig = toml::from_str(toml).unwrap();
+            assert!(config.validate().is_err(), "{toml} should be rejected");
+        }
+        assert!(toml::from_str::<AggregationConfig>("unknown = 1").is_err());
+    }
+}
@@ -0,0 +1,14 @@
+#[derive(thiserror::Error, Debug)]
+pub enum AggregationError {
+    #[error("Fail to read the aggregation config from {path}: {error}")]
+    FromIo { path: String, error: std::io::Error },
+
+    #[error("Invalid aggregation config in {path}: {error}")]
+    FromToml {
+        path: String,
+        error: toml::de::Error,
+    },
+
+    #[error("Invalid aggregation config: {reason}")]
+    InvalidConfig { reason: String },
+}
@@ -0,0 +1,20 @@
+//! Aggregation of the measurements over time windows
+//!
+//! The measurements published on `te/+/+/+/+/m/+` are split into samples, one per series,
+//! that are grouped into time windows by a [batcher::BatchDriver].
+//! For each window, an aggregated measurement is published per entity and measurement type,
+//! giving for each series the `min`, `max`, `mean`, `count` and `last` values over the window.
+//!
+//! The aggregates are published on a derived measurement type, e.g. `environment_aggregate` for `environment`,
+//! so they can be forwarded to the cloud while the raw measurements are kept local.
+mod aggregate;
+mod config;
+mod error;
+mod sampler;
+
+pub use aggregate::batch_into_mqtt_messages;
+pub use config::AggregatedEntity;
+pub use config::AggregationConfig;
+pub use error::AggregationError;
+pub use sampler::MeasurementSample;
+pub use sampler::MeasurementSampler;
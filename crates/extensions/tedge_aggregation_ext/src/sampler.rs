@@ -0,0 +1,243 @@
+use crate::AggregationConfig;
+use batcher::Batchable;
+use clock::Clock;
+use clock::Timestamp;
+use log::warn;
+use std::convert::Infallible;
+use tedge_actors::Converter;
+use tedge_api::measurement::MeasurementValue;
+use tedge_api::measurement::MeasurementVisitor;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::parser::parse_payload;
+use tedge_api::payload_format::PayloadFormat;
+use tedge_mqtt_ext::MqttMessage;
+
+/// The value of a series received at a given time
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeasurementSample {
+    /// The rank of the sample, in order of reception
+    pub rank: u64,
+    pub entity: EntityTopicId,
+    pub measurement_type: String,
+    pub group: Option<String>,
+    pub series: String,
+    pub value: f64,
+    pub received_at: Timestamp,
+}
+
+impl Batchable for MeasurementSample {
+    // All the samples received over a window are kept in the same batch,
+    // hence a key unique to each sample.
+    type Key = u64;
+
+    fn key(&self) -> Self::Key {
+        self.rank
+    }
+
+    fn event_time(&self) -> Timestamp {
+        self.received_at
+    }
+}
+
+/// A converter splitting measurements into samples, one per series
+pub struct MeasurementSampler {
+    mqtt_schema: MqttSchema,
+    config: AggregationConfig,
+    clock: Box<dyn Clock>,
+    next_rank: u64,
+}
+
+impl MeasurementSampler {
+    pub fn new(mqtt_schema: MqttSchema, config: AggregationConfig, clock: Box<dyn Clock>) -> Self {
+        MeasurementSampler {
+            mqtt_schema,
+            config,
+            clock,
+            next_rank: 0,
+        }
+    }
+}
+
+impl Converter for MeasurementSampler {
+    type Input = MqttMessage;
+    type Output = MeasurementSample;
+    type Error = Infallible;
+
+    fn convert(&mut self, input: &Self::Input) -> Result<Vec<Self::Output>, Self::Error> {
+        let Ok((entity, Channel::Measurement { measurement_type })) =
+            self.mqtt_schema.entity_channel_of(&input.topic)
+        else {
+            return Ok(vec![]);
+        };
+        // Binary-encoded measurements are aggregated along the JSON ones of the same type
+        let (measurement_type, format) = PayloadFormat::split_type(&measurement_type);
+        if !self.config.is_aggregated(&entity, measurement_type) {
+            return Ok(vec![]);
+        }
+
+        let mut visitor = SeriesCollector::default();
+        let parsed = parse_payload(format, format.payload_of(input), &mut visitor);
+        if let Err(err) = parsed {
+            warn!(
+                "Ignoring invalid measurement on {}: {err}",
+                input.topic.name
+            );
+            return Ok(vec![]);
+        }
+
+        let received_at = self.clock.now();
+        let samples = visitor
+            .values
+            .into_iter()
+            .map(|(group, series, value)| {
+                let rank = self.next_rank;
+                self.next_rank += 1;
+                MeasurementSample {
+                    rank,
+                    entity: entity.clone(),
+                    measurement_type: measurement_type.to_string(),
+                    group,
+                    series,
+                    value,
+                    received_at,
+                }
+            })
+            .collect();
+        Ok(samples)
+    }
+}
+
+/// Collect the numeric values of a thin-edge JSON measurement
+///
+/// Large integers are approximated as floats, booleans are sampled as 1 or 0,
+/// and arrays are ignored as there is no meaningful aggregate over a window.
+#[derive(Default)]
+struct SeriesCollector {
+    group: Option<String>,
+    values: Vec<(Option<String>, String, f64)>,
+}
+
+impl MeasurementVisitor for SeriesCollector {
+    type Error = Infallible;
+
+    fn visit_timestamp(&mut self, _value: Timestamp) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error> {
+        self.values
+            .push((self.group.clone(), name.to_string(), value));
+        Ok(())
+    }
+
+    fn visit_typed_measurement(
+        &mut self,
+        name: &str,
+        value: &MeasurementValue,
+    ) -> Result<(), Self::Error> {
+        let value = match value {
+            MeasurementValue::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+            value => value.as_f64(),
+        };
+        match value {
+            Some(value) => self.visit_measurement(name, value),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_text_property(&mut self, _name: &str, _value: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_start_group(&mut self, group: &str) -> Result<(), Self::Error> {
+        self.group = Some(group.to_string());
+        Ok(())
+    }
+
+    fn visit_end_group(&mut self) -> Result<(), Self::Error> {
+        self.group = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::WallClock;
+    use tedge_mqtt_ext::Topic;
+
+    #[test]
+    fn split_a_measurement_into_samples() {
+        let mut sampler = MeasurementSampler::new(
+            MqttSchema::default(),
+            AggregationConfig::default(),
+            Box::new(WallClock),
+        );
+
+        let measurement = MqttMessage::new(
+            &Topic::new_unchecked("te/device/child01///m/environment"),
+            r#"{"time": "2023-10-18T10:00:00Z", "temperature": 21.5, "location": {"x": 1, "y": 2}, "running": true, "spectrum": [1, 2]}"#,
+        );
+        let samples = sampler.convert(&measurement).unwrap();
+        let series: Vec<_> = samples
+            .iter()
+            .map(|s| (s.rank, s.group.as_deref(), s.series.as_str(), s.value))
+            .collect();
+        assert_eq!(
+            series,
+            vec![
+                (0, None, "temperature", 21.5),
+                (1, Some("location"), "x", 1.0),
+                (2, Some("location"), "y", 2.0),
+                (3, None, "running", 1.0),
+            ]
+        );
+        assert!(samples.iter().all(|s| s.measurement_type == "environment"
+            && s.entity == EntityTopicId::default_child_device("child01").unwrap()));
+    }
+
+    #[test]
+    fn sample_binary_measurements() {
+        let mut sampler = MeasurementSampler::new(
+            MqttSchema::default(),
+            AggregationConfig::default(),
+            Box::new(WallClock),
+        );
+
+        // CBOR encoding of {"temperature": 21}
+        let mut cbor = vec![0xa1, 0x6b];
+        cbor.extend_from_slice(b"temperature");
+        cbor.push(0x15);
+        let measurement = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment.cbor"),
+            cbor,
+        );
+        let samples = sampler.convert(&measurement).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].measurement_type, "environment");
+        assert_eq!(samples[0].series, "temperature");
+        assert_eq!(samples[0].value, 21.0);
+    }
+
+    #[test]
+    fn ignore_aggregates_and_invalid_measurements() {
+        let mut sampler = MeasurementSampler::new(
+            MqttSchema::default(),
+            AggregationConfig::default(),
+            Box::new(WallClock),
+        );
+
+        let aggregate = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment_aggregate"),
+            r#"{"temperature": {"min": 20, "max": 22}}"#,
+        );
+        let invalid = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            r#"{"temperature": 21"#,
+        );
+        assert!(sampler.convert(&aggregate).unwrap().is_empty());
+        assert!(sampler.convert(&invalid).unwrap().is_empty());
+    }
+}
@@ -4,7 +4,7 @@ use download::DownloadError;
 use download::DownloadInfo;
 use download::Downloader;
 use log::info;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::marker::PhantomData;
 use std::path::Path;
 use std::path::PathBuf;
@@ -69,7 +69,7 @@ impl DownloadResponse {
 pub struct DownloaderActor<T> {
     config: ServerConfig,
     key: std::marker::PhantomData<T>,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl<T> Clone for DownloaderActor<T> {
@@ -83,7 +83,7 @@ impl<T> Clone for DownloaderActor<T> {
 }
 
 impl<T: Message + Default> DownloaderActor<T> {
-    pub fn new(identity: Option<Identity>) -> Self {
+    pub fn new(identity: Option<ClientConfig>) -> Self {
         DownloaderActor {
             config: <_>::default(),
             key: PhantomData,
@@ -95,7 +95,7 @@ impl<T: Message + Default> DownloaderActor<T> {
         ServerActorBuilder::new(self.clone(), &ServerConfig::new(), Sequential)
     }
 
-    pub fn with_capacity(self, capacity: usize, identity: Option<Identity>) -> Self {
+    pub fn with_capacity(self, capacity: usize, identity: Option<ClientConfig>) -> Self {
         Self {
             config: self.config.with_capacity(capacity),
             key: self.key,
@@ -0,0 +1,208 @@
+use crate::MessageSpool;
+use crate::StoreForwardConfig;
+use async_trait::async_trait;
+use log::error;
+use log::info;
+use log::warn;
+use serde::Serialize;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::MessageReceiver;
+use tedge_actors::RuntimeError;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_mqtt_ext::MqttMessage;
+use tokio::time::sleep_until;
+use tokio::time::Instant;
+
+/// The number of messages queued, replayed and dropped during an outage of the cloud connection
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct StoreForwardMetrics {
+    pub queued: usize,
+    pub replayed: usize,
+    pub dropped: usize,
+}
+
+pub struct StoreForwardActor {
+    config: StoreForwardConfig,
+    spool: MessageSpool,
+    connected: bool,
+    metrics: StoreForwardMetrics,
+    next_replay: Instant,
+    messages: SimpleMessageBox<MqttMessage, MqttMessage>,
+}
+
+impl StoreForwardActor {
+    pub fn new(
+        config: StoreForwardConfig,
+        spool: MessageSpool,
+        messages: SimpleMessageBox<MqttMessage, MqttMessage>,
+    ) -> Self {
+        // Messages left by a previous run have to be replayed before any new message is forwarded
+        let connected = spool.is_empty();
+        let metrics = StoreForwardMetrics {
+            queued: spool.len(),
+            ..Default::default()
+        };
+        StoreForwardActor {
+            config,
+            spool,
+            connected,
+            metrics,
+            next_replay: Instant::now(),
+            messages,
+        }
+    }
+
+    /// Tell if spooled messages are to be replayed, the cloud connection being up
+    fn is_replaying(&self) -> bool {
+        self.connected && !self.spool.is_empty()
+    }
+
+    async fn process_bridge_status(&mut self, message: &MqttMessage) -> Result<(), RuntimeError> {
+        if is_bridge_up(message) {
+            if !self.connected {
+                info!("The cloud connection is up");
+                self.connected = true;
+                if !self.spool.is_empty() {
+                    info!("Replaying {} spooled messages", self.spool.len());
+                    self.next_replay = Instant::now();
+                } else if self.metrics.dropped > 0 {
+                    self.publish_metrics().await?;
+                }
+            }
+        } else if self.connected {
+            warn!("The cloud connection is down: spooling the cloud-bound messages");
+            self.connected = false;
+        }
+        Ok(())
+    }
+
+    async fn process_outgoing_message(&mut self, message: MqttMessage) -> Result<(), RuntimeError> {
+        // While the spooled messages are replayed, the new messages are spooled behind them
+        if !self.config.spooled_topics.accept(&message) || (self.connected && self.spool.is_empty())
+        {
+            return Ok(self.messages.send(message).await?);
+        }
+
+        match self.spool.push(&message) {
+            Ok(true) => self.metrics.queued += 1,
+            Ok(false) => {
+                if self.metrics.dropped == 0 {
+                    warn!(
+                        "The spool is full ({} messages): dropping the cloud-bound messages",
+                        self.spool.len()
+                    );
+                }
+                self.metrics.dropped += 1;
+            }
+            Err(err) => {
+                error!("Fail to spool a message on {}: {err}", message.topic.name);
+                self.metrics.dropped += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay the oldest spooled message, and publish the metrics of the outage once the spool is empty
+    ///
+    /// The messages are replayed one at a time, at a limited rate,
+    /// so new messages are accepted and spooled behind the backlog in the meantime.
+    ///
+    /// A spool that cannot be read is discarded, rather than stopping the mapper.
+    async fn replay_next(&mut self) -> Result<(), RuntimeError> {
+        match self.spool.front() {
+            Ok(Some(message)) => {
+                self.messages.send(message).await?;
+                self.metrics.replayed += 1;
+                if let Err(err) = self.spool.pop_front() {
+                    error!("Fail to checkpoint the replay of the spooled messages: {err}");
+                }
+            }
+            Ok(None) => (),
+            Err(err) => {
+                error!(
+                    "Fail to read the spooled messages, dropping {} messages: {err}",
+                    self.spool.len()
+                );
+                self.metrics.dropped += self.spool.len();
+                if let Err(err) = self.spool.clear() {
+                    error!("Fail to remove the spooled messages: {err}");
+                }
+            }
+        }
+        if let Some(delay) = self.replay_delay() {
+            self.next_replay = Instant::now() + delay;
+        }
+        if self.spool.is_empty() {
+            self.publish_metrics().await?;
+        }
+        Ok(())
+    }
+
+    fn replay_delay(&self) -> Option<Duration> {
+        match self.config.replay_rate {
+            0 => None,
+            rate => Some(Duration::from_secs(1) / rate),
+        }
+    }
+
+    async fn publish_metrics(&mut self) -> Result<(), RuntimeError> {
+        let metrics = std::mem::take(&mut self.metrics);
+        info!(
+            "Cloud connection outage: {} messages queued, {} replayed, {} dropped",
+            metrics.queued, metrics.replayed, metrics.dropped
+        );
+        if let Some(topic) = &self.config.metrics_topic {
+            let payload = serde_json::to_string(&metrics).expect("Infallible serialization");
+            self.messages.send(MqttMessage::new(topic, payload)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Tell if a bridge health message notifies that the bridge is up
+///
+/// The mosquitto bridge notifications are `1` or `0`,
+/// but the health status of a service is also accepted, e.g. `{"status":"up"}`.
+fn is_bridge_up(message: &MqttMessage) -> bool {
+    match message.payload_str() {
+        Ok("1") => true,
+        Ok(payload) => serde_json::from_str::<serde_json::Value>(payload)
+            .map(|status| status["status"] == "up")
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+#[async_trait]
+impl Actor for StoreForwardActor {
+    fn name(&self) -> &str {
+        "StoreForward"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        loop {
+            let replaying = self.is_replaying();
+            let message = tokio::select! {
+                message = self.messages.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = sleep_until(self.next_replay), if replaying => {
+                    self.replay_next().await?;
+                    continue;
+                }
+            };
+
+            // The mapper never publishes on the bridge health topic,
+            // hence a message on this topic is a notification received from the MQTT actor.
+            if message.topic == self.config.bridge_health_topic {
+                self.process_bridge_status(&message).await?;
+            } else {
+                self.process_outgoing_message(message).await?;
+            }
+        }
+        Ok(())
+    }
+}
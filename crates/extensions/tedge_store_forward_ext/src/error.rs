@@ -0,0 +1,17 @@
+use tedge_actors::RuntimeError;
+use tedge_api::message_log::LogEntryError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StoreForwardError {
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromLogEntry(#[from] LogEntryError),
+}
+
+impl From<StoreForwardError> for RuntimeError {
+    fn from(error: StoreForwardError) -> Self {
+        RuntimeError::ActorError(Box::new(error))
+    }
+}
@@ -0,0 +1,156 @@
+//! Store-and-forward of the cloud-bound messages while the cloud connection is down
+//!
+//! The store-and-forward actor sits between a mapper and the MQTT actor,
+//! watching the health topic of the mosquitto bridge to the cloud.
+//!
+//! - While the bridge is up, the messages published by the mapper are forwarded as is.
+//! - While the bridge is down, the cloud-bound messages are spooled into a bounded on-disk message log,
+//!   the messages being dropped when the spool is full.
+//! - When the bridge is up again, the spooled messages are replayed in order, at a limited rate.
+//!   Meanwhile, the new cloud-bound messages are spooled behind the backlog,
+//!   and the spool is checkpointed so a replay interrupted by a restart is resumed where it stopped.
+//!   A measurement is then published with the number of messages queued, replayed and dropped during the outage.
+mod actor;
+mod error;
+mod spool;
+
+#[cfg(test)]
+mod tests;
+
+pub use actor::StoreForwardActor;
+pub use actor::StoreForwardMetrics;
+pub use error::StoreForwardError;
+pub use spool::MessageSpool;
+
+use std::path::PathBuf;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageSink;
+use tedge_actors::NoConfig;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::ServiceConsumer;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+
+pub struct StoreForwardConfig {
+    /// The topic on which the bridge notifies its connection status, e.g. `te/device/main/service/mosquitto-c8y-bridge/status/health`
+    pub bridge_health_topic: Topic,
+
+    /// The messages to spool while the bridge is down, e.g. `c8y/#`
+    pub spooled_topics: TopicFilter,
+
+    /// The file where the messages are spooled
+    pub spool_path: PathBuf,
+
+    /// The maximum number of spooled messages
+    pub max_messages: usize,
+
+    /// The maximum number of messages replayed per second, 0 meaning no limit
+    pub replay_rate: u32,
+
+    /// The topic on which the metrics of an outage are published, if any
+    pub metrics_topic: Option<Topic>,
+}
+
+pub struct StoreForwardBuilder {
+    config: StoreForwardConfig,
+    spool: MessageSpool,
+    box_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage>,
+}
+
+impl StoreForwardBuilder {
+    pub fn new(
+        config: StoreForwardConfig,
+        mqtt: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+    ) -> Self {
+        let spool = MessageSpool::open(&config.spool_path, config.max_messages);
+        let mut box_builder = SimpleMessageBoxBuilder::new("StoreForward", 16);
+        let health_topic = TopicFilter::new_unchecked(&config.bridge_health_topic.name);
+        box_builder
+            .set_request_sender(mqtt.connect_consumer(health_topic, box_builder.get_sender()));
+
+        StoreForwardBuilder {
+            config,
+            spool,
+            box_builder,
+        }
+    }
+
+    /// A proxy to the MQTT actor for a peer that publishes messages through the store-and-forward actor
+    ///
+    /// The subscriptions of the peer are forwarded to the MQTT actor,
+    /// while the messages published by the peer are sent to the store-and-forward actor.
+    pub fn proxy<'a, M>(&self, mqtt: &'a mut M) -> MqttProxy<'a, M>
+    where
+        M: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+    {
+        MqttProxy::new(mqtt, Some(self))
+    }
+}
+
+impl MessageSink<MqttMessage, NoConfig> for StoreForwardBuilder {
+    fn get_config(&self) -> NoConfig {
+        NoConfig
+    }
+
+    fn get_sender(&self) -> DynSender<MqttMessage> {
+        self.box_builder.get_sender()
+    }
+}
+
+impl RuntimeRequestSink for StoreForwardBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.box_builder.get_signal_sender()
+    }
+}
+
+impl Builder<StoreForwardActor> for StoreForwardBuilder {
+    type Error = StoreForwardError;
+
+    fn try_build(self) -> Result<StoreForwardActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> StoreForwardActor {
+        StoreForwardActor::new(self.config, self.spool, self.box_builder.build())
+    }
+}
+
+/// See [StoreForwardBuilder::proxy]
+pub struct MqttProxy<'a, M> {
+    mqtt: &'a mut M,
+    publisher: Option<DynSender<MqttMessage>>,
+}
+
+impl<'a, M> MqttProxy<'a, M> {
+    /// A proxy publishing via the store-and-forward actor if any, otherwise directly to the MQTT actor
+    pub fn new(mqtt: &'a mut M, store_forward: Option<&StoreForwardBuilder>) -> Self {
+        MqttProxy {
+            mqtt,
+            publisher: store_forward.map(|actor| actor.get_sender()),
+        }
+    }
+}
+
+impl<'a, M> ServiceProvider<MqttMessage, MqttMessage, TopicFilter> for MqttProxy<'a, M>
+where
+    M: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+{
+    fn connect_consumer(
+        &mut self,
+        subscriptions: TopicFilter,
+        response_sender: DynSender<MqttMessage>,
+    ) -> DynSender<MqttMessage> {
+        // When store-and-forward is enabled, the sender returned by the MQTT actor is ignored,
+        // the messages being published via the store-and-forward actor
+        let mqtt_publisher = self.mqtt.connect_consumer(subscriptions, response_sender);
+        match &self.publisher {
+            Some(publisher) => publisher.sender_clone(),
+            None => mqtt_publisher,
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use crate::StoreForwardError;
+use log::warn;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use tedge_api::message_log::LogEntryError;
+use tedge_api::message_log::MessageLogReader;
+use tedge_api::message_log::MessageLogWriter;
+use tedge_mqtt_ext::MqttMessage;
+
+/// A bounded on-disk queue of MQTT messages
+///
+/// The messages are appended to a message log, that is removed once all the messages have been replayed.
+/// The number of messages already replayed is checkpointed in a side file,
+/// so a replay interrupted by a restart is resumed where it stopped.
+/// The spool survives a restart: the messages left by a previous run are counted on open.
+///
+/// The message log is compacted as soon as the replayed entries outnumber the pending ones,
+/// so the file never holds more than twice the capacity of the spool.
+///
+/// A spool file damaged by a power loss is not an error:
+/// an incomplete last entry is truncated on open and the unreadable entries are skipped on replay.
+pub struct MessageSpool {
+    path: PathBuf,
+    writer: Option<MessageLogWriter>,
+    reader: Option<MessageLogReader>,
+    head: Option<MqttMessage>,
+    replayed: usize,
+    len: usize,
+    capacity: usize,
+}
+
+impl MessageSpool {
+    pub fn open(path: impl Into<PathBuf>, capacity: usize) -> Self {
+        let mut spool = MessageSpool {
+            path: path.into(),
+            writer: None,
+            reader: None,
+            head: None,
+            replayed: 0,
+            len: 0,
+            capacity,
+        };
+        spool.replayed = std::fs::read_to_string(spool.checkpoint_path())
+            .ok()
+            .and_then(|checkpoint| checkpoint.trim().parse().ok())
+            .unwrap_or(0);
+        spool.len = match count_entries(&spool.path) {
+            Ok(entries) => entries.saturating_sub(spool.replayed),
+            Err(err) => {
+                warn!(
+                    "Ignoring the spooled messages that cannot be read from {}: {err}",
+                    spool.path.display()
+                );
+                0
+            }
+        };
+        spool
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len >= self.capacity
+    }
+
+    /// Append a message to the spool, unless the spool is full
+    ///
+    /// Return `false` if the message has been dropped because the spool is full.
+    pub fn push(&mut self, message: &MqttMessage) -> Result<bool, StoreForwardError> {
+        if self.is_full() {
+            return Ok(false);
+        }
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => self.writer.insert(MessageLogWriter::open(&self.path)?),
+        };
+        writer.append_message(message)?;
+        self.len += 1;
+        Ok(true)
+    }
+
+    /// Return the oldest message not replayed yet, without removing it from the spool
+    ///
+    /// The entries that cannot be deserialized are skipped with a warning.
+    pub fn front(&mut self) -> Result<Option<MqttMessage>, StoreForwardError> {
+        while self.head.is_none() && !self.is_empty() {
+            let reader = match self.reader.as_mut() {
+                Some(reader) => reader,
+                None => {
+                    let mut reader = MessageLogReader::open(&self.path)?;
+                    for _ in 0..self.replayed {
+                        match reader.next_message() {
+                            Ok(_) | Err(LogEntryError::FromSerdeJson(_, _)) => (),
+                            Err(err) => return Err(err.into()),
+                        }
+                    }
+                    self.reader.insert(reader)
+                }
+            };
+            match reader.next_message() {
+                Ok(Some(message)) => self.head = Some(message),
+                Ok(None) => {
+                    // The message log has been truncated: there is nothing more to replay
+                    self.clear()?;
+                }
+                Err(LogEntryError::FromSerdeJson(err, _)) => {
+                    warn!("Skipping an unreadable spooled message: {err}");
+                    self.advance()?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(self.head.clone())
+    }
+
+    /// Remove the oldest message, once replayed
+    ///
+    /// The spool is checkpointed, so this message will not be replayed again after a restart.
+    pub fn pop_front(&mut self) -> Result<(), StoreForwardError> {
+        if self.head.take().is_none() {
+            return Ok(());
+        }
+        self.advance()
+    }
+
+    /// Read all the spooled messages not replayed yet, in order, skipping the unreadable ones
+    pub fn messages(&self) -> Result<Vec<MqttMessage>, StoreForwardError> {
+        let mut messages = Vec::new();
+        if !self.path.exists() {
+            return Ok(messages);
+        }
+        let mut reader = MessageLogReader::open(&self.path)?;
+        let mut entries = 0;
+        loop {
+            match reader.next_message() {
+                Ok(Some(message)) if entries >= self.replayed => messages.push(message),
+                Ok(Some(_)) | Err(LogEntryError::FromSerdeJson(_, _)) => (),
+                Ok(None) => break,
+                Err(err) => return Err(err.into()),
+            }
+            entries += 1;
+        }
+        Ok(messages)
+    }
+
+    /// Remove all the spooled messages
+    ///
+    /// The spool is emptied even if the files cannot be removed.
+    pub fn clear(&mut self) -> Result<(), StoreForwardError> {
+        self.writer = None;
+        self.reader = None;
+        self.head = None;
+        self.replayed = 0;
+        self.len = 0;
+        for path in [&self.path, &self.checkpoint_path()] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the oldest entry as consumed, checkpointing the replay
+    fn advance(&mut self) -> Result<(), StoreForwardError> {
+        self.replayed += 1;
+        self.len -= 1;
+        if self.is_empty() {
+            self.clear()
+        } else if self.replayed > self.len {
+            self.compact()
+        } else {
+            std::fs::write(self.checkpoint_path(), self.replayed.to_string())?;
+            Ok(())
+        }
+    }
+
+    /// Rewrite the message log without the replayed entries
+    ///
+    /// The checkpoint is removed before the message log is replaced,
+    /// so an interrupted compaction can only lead to messages being replayed twice.
+    fn compact(&mut self) -> Result<(), StoreForwardError> {
+        let compacted_path = self.sibling_path(".compacted");
+        if compacted_path.exists() {
+            std::fs::remove_file(&compacted_path)?;
+        }
+
+        let mut reader = MessageLogReader::open(&self.path)?;
+        let mut writer = MessageLogWriter::open(&compacted_path)?;
+        let mut entries = 0;
+        let mut len = 0;
+        loop {
+            match reader.next_message() {
+                Ok(Some(message)) if entries >= self.replayed => {
+                    writer.append_message(&message)?;
+                    len += 1;
+                }
+                Ok(Some(_)) => (),
+                Ok(None) => break,
+                Err(LogEntryError::FromSerdeJson(err, _)) => {
+                    warn!("Dropping an unreadable spooled message: {err}");
+                }
+                Err(err) => return Err(err.into()),
+            }
+            entries += 1;
+        }
+
+        let checkpoint_path = self.checkpoint_path();
+        if checkpoint_path.exists() {
+            std::fs::remove_file(checkpoint_path)?;
+        }
+        std::fs::rename(&compacted_path, &self.path)?;
+
+        self.writer = None;
+        self.reader = None;
+        self.replayed = 0;
+        self.len = len;
+        Ok(())
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        self.sibling_path(".replayed")
+    }
+
+    fn sibling_path(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(suffix);
+        path.into()
+    }
+}
+
+/// Count the entries of a message log, without deserializing them
+///
+/// An incomplete last line, as left by a power loss while appending, is truncated.
+fn count_entries(path: &Path) -> std::io::Result<usize> {
+    let file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut reader = BufReader::new(&file);
+    let mut lines: usize = 0;
+    let mut complete_len = 0;
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if !buffer.ends_with(b"\n") {
+            warn!("Truncating the incomplete last entry of {}", path.display());
+            file.set_len(complete_len)?;
+            break;
+        }
+        lines += 1;
+        complete_len += read as u64;
+    }
+
+    // The first line is the header of the message log
+    Ok(lines.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_mqtt_ext::Topic;
+    use tempfile::tempdir;
+
+    #[test]
+    fn spooled_messages_are_kept_in_order_up_to_capacity() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let topic = Topic::new_unchecked("c8y/measurement/measurements/create");
+
+        let mut spool = MessageSpool::open(&path, 2);
+        assert!(spool.push(&MqttMessage::new(&topic, "1")).unwrap());
+        assert!(spool.push(&MqttMessage::new(&topic, "2")).unwrap());
+        assert!(!spool.push(&MqttMessage::new(&topic, "3")).unwrap());
+
+        // The spool survives a restart
+        let mut spool = MessageSpool::open(&path, 2);
+        assert_eq!(spool.len(), 2);
+        assert_eq!(
+            spool.messages().unwrap(),
+            vec![MqttMessage::new(&topic, "1"), MqttMessage::new(&topic, "2")]
+        );
+
+        spool.clear().unwrap();
+        assert!(spool.is_empty());
+        assert!(spool.messages().unwrap().is_empty());
+        assert!(spool.push(&MqttMessage::new(&topic, "4")).unwrap());
+        assert_eq!(
+            spool.messages().unwrap(),
+            vec![MqttMessage::new(&topic, "4")]
+        );
+    }
+
+    #[test]
+    fn replayed_messages_are_checkpointed() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let topic = Topic::new_unchecked("c8y/measurement/measurements/create");
+
+        let mut spool = MessageSpool::open(&path, 10);
+        for i in 1..=3 {
+            spool
+                .push(&MqttMessage::new(&topic, i.to_string()))
+                .unwrap();
+        }
+        assert_eq!(spool.front().unwrap(), Some(MqttMessage::new(&topic, "1")));
+        spool.pop_front().unwrap();
+
+        // Messages can be spooled behind the messages being replayed
+        spool.push(&MqttMessage::new(&topic, "4")).unwrap();
+
+        // A replay interrupted by a restart is resumed where it stopped
+        let mut spool = MessageSpool::open(&path, 10);
+        assert_eq!(spool.len(), 3);
+        let mut replayed = vec![];
+        while let Some(message) = spool.front().unwrap() {
+            replayed.push(message);
+            spool.pop_front().unwrap();
+        }
+        assert_eq!(
+            replayed,
+            vec![
+                MqttMessage::new(&topic, "2"),
+                MqttMessage::new(&topic, "3"),
+                MqttMessage::new(&topic, "4")
+            ]
+        );
+
+        // Once all the messages replayed, the spool is removed
+        assert!(spool.is_empty());
+        assert!(!path.exists());
+        assert!(MessageSpool::open(&path, 10).is_empty());
+    }
+
+    #[test]
+    fn a_damaged_spool_is_not_an_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let topic = Topic::new_unchecked("c8y/measurement/measurements/create");
+
+        let mut spool = MessageSpool::open(&path, 10);
+        spool.push(&MqttMessage::new(&topic, "1")).unwrap();
+        spool.push(&MqttMessage::new(&topic, "2")).unwrap();
+        drop(spool);
+
+        // An entry is corrupted and the last append has been interrupted
+        let content = std::fs::read_to_string(&path).unwrap();
+        let corrupted = content.replacen(r#"{"topic""#, r#"{"oops""#, 1);
+        std::fs::write(&path, format!("{corrupted}{{\"topic\":\"c8y/")).unwrap();
+
+        let mut spool = MessageSpool::open(&path, 10);
+        assert_eq!(spool.len(), 2);
+
+        // New messages are appended after the truncated entry
+        spool.push(&MqttMessage::new(&topic, "3")).unwrap();
+
+        // The corrupted entry is skipped
+        let mut replayed = vec![];
+        while let Some(message) = spool.front().unwrap() {
+            replayed.push(message);
+            spool.pop_front().unwrap();
+        }
+        assert_eq!(
+            replayed,
+            vec![MqttMessage::new(&topic, "2"), MqttMessage::new(&topic, "3")]
+        );
+        assert!(spool.is_empty());
+    }
+
+    #[test]
+    fn replayed_messages_are_reclaimed_from_disk() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let topic = Topic::new_unchecked("c8y/measurement/measurements/create");
+
+        let mut spool = MessageSpool::open(&path, 4);
+        for i in 1..=4 {
+            spool
+                .push(&MqttMessage::new(&topic, i.to_string()))
+                .unwrap();
+        }
+
+        // During a long replay, new messages are spooled while older ones are replayed
+        for i in 5..=20 {
+            assert!(spool.front().unwrap().is_some());
+            spool.pop_front().unwrap();
+            assert!(spool
+                .push(&MqttMessage::new(&topic, i.to_string()))
+                .unwrap());
+            assert!(count_entries(&path).unwrap() <= 2 * 4 + 1);
+        }
+
+        // The compacted spool survives a restart
+        let spool = MessageSpool::open(&path, 4);
+        assert_eq!(
+            spool.messages().unwrap(),
+            (17..=20)
+                .map(|i| MqttMessage::new(&topic, i.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
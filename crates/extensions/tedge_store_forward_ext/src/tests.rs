@@ -0,0 +1,247 @@
+use crate::MessageSpool;
+use crate::StoreForwardBuilder;
+use crate::StoreForwardConfig;
+use std::path::Path;
+use std::time::Duration;
+use tedge_actors::test_helpers::MessageReceiverExt;
+use tedge_actors::test_helpers::TimedMessageBox;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::Sender;
+use tedge_actors::ServiceConsumer;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicFilter;
+use tempfile::tempdir;
+
+/// How long to wait for a message, notably to check that no message is published
+const TEST_TIMEOUT: Duration = Duration::from_millis(500);
+const BRIDGE_HEALTH_TOPIC: &str = "te/device/main/service/mosquitto-c8y-bridge/status/health";
+const MEASUREMENT_TOPIC: &str = "c8y/measurement/measurements/create";
+const METRICS_TOPIC: &str = "te/device/main/service/tedge-mapper-c8y/m/store_forward";
+
+#[tokio::test]
+async fn messages_are_forwarded_while_the_bridge_is_up() {
+    let dir = tempdir().unwrap();
+    let (mut mqtt, mut mapper) = spawn_store_forward_actor(dir.path(), 10);
+
+    mqtt.send(bridge_status("1")).await.unwrap();
+    mapper.send(measurement("1")).await.unwrap();
+
+    mqtt.assert_received([measurement("1")]).await;
+}
+
+#[tokio::test]
+async fn messages_are_spooled_while_the_bridge_is_down_and_replayed_in_order() {
+    let dir = tempdir().unwrap();
+    let (mut mqtt, mut mapper) = spawn_store_forward_actor(dir.path(), 10);
+
+    mqtt.send(bridge_status("0")).await.unwrap();
+    mapper.send(measurement("1")).await.unwrap();
+    mapper.send(measurement("2")).await.unwrap();
+
+    // Local messages are not spooled
+    let local_message = MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///cmd/restart/1"),
+        "{}",
+    );
+    mapper.send(local_message.clone()).await.unwrap();
+    mqtt.assert_received([local_message]).await;
+    assert!(mqtt.recv().await.is_none());
+
+    mqtt.send(bridge_status(r#"{"status":"up"}"#))
+        .await
+        .unwrap();
+    mqtt.assert_received([
+        measurement("1"),
+        measurement("2"),
+        metrics(r#"{"queued":2,"replayed":2,"dropped":0}"#),
+    ])
+    .await;
+
+    // Once replayed, the messages are forwarded as is
+    mapper.send(measurement("3")).await.unwrap();
+    mqtt.assert_received([measurement("3")]).await;
+}
+
+#[tokio::test]
+async fn messages_are_dropped_when_the_spool_is_full() {
+    let dir = tempdir().unwrap();
+    let (mut mqtt, mut mapper) = spawn_store_forward_actor(dir.path(), 2);
+
+    mqtt.send(bridge_status("0")).await.unwrap();
+    for i in 1..=4 {
+        mapper.send(measurement(&i.to_string())).await.unwrap();
+    }
+
+    mqtt.send(bridge_status("1")).await.unwrap();
+    mqtt.assert_received([
+        measurement("1"),
+        measurement("2"),
+        metrics(r#"{"queued":2,"replayed":2,"dropped":2}"#),
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn messages_spooled_by_a_previous_run_are_replayed_first() {
+    let dir = tempdir().unwrap();
+    let mut spool = MessageSpool::open(dir.path().join("spool.jsonl"), 10);
+    spool.push(&measurement("1")).unwrap();
+
+    let (mut mqtt, mut mapper) = spawn_store_forward_actor(dir.path(), 10);
+    mapper.send(measurement("2")).await.unwrap();
+    assert!(mqtt.recv().await.is_none());
+
+    mqtt.send(bridge_status("1")).await.unwrap();
+    mqtt.assert_received([
+        measurement("1"),
+        measurement("2"),
+        metrics(r#"{"queued":2,"replayed":2,"dropped":0}"#),
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn new_messages_are_processed_while_the_spooled_messages_are_replayed() {
+    let dir = tempdir().unwrap();
+    let config = StoreForwardConfig {
+        replay_rate: 10,
+        ..config(dir.path(), 10)
+    };
+    let (mut mqtt, mut mapper) = spawn_store_forward_actor_with_config(config);
+
+    mqtt.send(bridge_status("0")).await.unwrap();
+    for i in 1..=3 {
+        mapper.send(measurement(&i.to_string())).await.unwrap();
+    }
+    mqtt.send(bridge_status("1")).await.unwrap();
+
+    // Local messages are not delayed by the replay
+    let local_message = MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///cmd/restart/1"),
+        "{}",
+    );
+    mapper.send(local_message.clone()).await.unwrap();
+
+    // New cloud-bound messages are spooled behind the backlog
+    mapper.send(measurement("4")).await.unwrap();
+
+    let mut received = vec![];
+    while let Some(message) = mqtt.recv().await {
+        received.push(message);
+    }
+    let local_message_position = received.iter().position(|m| m == &local_message);
+    assert!(local_message_position < Some(2), "{received:?}");
+    received.retain(|m| m != &local_message);
+    assert_eq!(
+        received,
+        vec![
+            measurement("1"),
+            measurement("2"),
+            measurement("3"),
+            measurement("4"),
+            metrics(r#"{"queued":4,"replayed":4,"dropped":0}"#),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_proxied_peer_subscribes_to_mqtt_and_publishes_via_the_store_forward_actor() {
+    let dir = tempdir().unwrap();
+    let mut mqtt_builder = SimpleMessageBoxBuilder::new("MQTT", 16);
+    let store_forward = StoreForwardBuilder::new(config(dir.path(), 10), &mut mqtt_builder);
+
+    // A test message box accepts a single subscriber, hence a second one for the proxied peer
+    let mut peer_mqtt_builder = SimpleMessageBoxBuilder::new("Peer MQTT", 16);
+    let mut peer_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+        SimpleMessageBoxBuilder::new("Mapper", 16);
+    let publisher = store_forward
+        .proxy(&mut peer_mqtt_builder)
+        .connect_consumer(
+            TopicFilter::new_unchecked("te/#"),
+            peer_builder.get_sender(),
+        );
+    peer_builder.set_request_sender(publisher);
+    let mut peer = peer_builder.build().with_timeout(TEST_TIMEOUT);
+
+    let actor = store_forward.build();
+    tokio::spawn(async move { actor.run().await });
+    let mut mqtt = mqtt_builder.build().with_timeout(TEST_TIMEOUT);
+    let mut peer_mqtt = peer_mqtt_builder.build();
+
+    // The peer receives the messages of its subscriptions
+    let command = MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///cmd/restart/1"),
+        "{}",
+    );
+    peer_mqtt.send(command.clone()).await.unwrap();
+    peer.assert_received([command]).await;
+
+    // The messages published by the peer go through the store-and-forward actor
+    mqtt.send(bridge_status("0")).await.unwrap();
+    peer.send(measurement("1")).await.unwrap();
+    assert!(mqtt.recv().await.is_none());
+
+    mqtt.send(bridge_status("1")).await.unwrap();
+    mqtt.assert_received([measurement("1")]).await;
+}
+
+fn spawn_store_forward_actor(
+    dir: &Path,
+    max_messages: usize,
+) -> (
+    TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+    DynSender<MqttMessage>,
+) {
+    spawn_store_forward_actor_with_config(config(dir, max_messages))
+}
+
+fn spawn_store_forward_actor_with_config(
+    config: StoreForwardConfig,
+) -> (
+    TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+    DynSender<MqttMessage>,
+) {
+    let mut mqtt_builder = SimpleMessageBoxBuilder::new("MQTT", 16);
+    let store_forward = StoreForwardBuilder::new(config, &mut mqtt_builder);
+    let mapper = store_forward.get_sender();
+
+    let actor = store_forward.build();
+    tokio::spawn(async move { actor.run().await });
+
+    let mqtt = mqtt_builder.build().with_timeout(TEST_TIMEOUT);
+    (mqtt, mapper)
+}
+
+fn config(dir: &Path, max_messages: usize) -> StoreForwardConfig {
+    StoreForwardConfig {
+        bridge_health_topic: Topic::new_unchecked(BRIDGE_HEALTH_TOPIC),
+        spooled_topics: TopicFilter::new_unchecked("c8y/#"),
+        spool_path: dir.join("spool.jsonl"),
+        max_messages,
+        replay_rate: 1000,
+        metrics_topic: Some(Topic::new_unchecked(METRICS_TOPIC)),
+    }
+}
+
+fn bridge_status(payload: &str) -> MqttMessage {
+    MqttMessage::new(&Topic::new_unchecked(BRIDGE_HEALTH_TOPIC), payload)
+}
+
+fn measurement(value: &str) -> MqttMessage {
+    MqttMessage::new(
+        &Topic::new_unchecked(MEASUREMENT_TOPIC),
+        format!(r#"{{"temperature":{{"temperature":{{"value":{value}}}}}}}"#),
+    )
+}
+
+fn metrics(payload: &str) -> MqttMessage {
+    MqttMessage::new(&Topic::new_unchecked(METRICS_TOPIC), payload)
+}
@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use log::info;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use tedge_actors::Sequential;
 use tedge_actors::Server;
 use tedge_actors::ServerActorBuilder;
@@ -66,11 +66,11 @@ pub type UploadResult = Result<UploadResponse, UploadError>;
 #[derive(Debug)]
 pub struct UploaderActor {
     config: ServerConfig,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl UploaderActor {
-    pub fn new(identity: Option<Identity>) -> Self {
+    pub fn new(identity: Option<ClientConfig>) -> Self {
         Self {
             config: ServerConfig::default(),
             identity,
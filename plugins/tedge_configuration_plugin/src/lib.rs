@@ -123,7 +123,7 @@ async fn run_with(
         &mqtt_schema,
         &tedge_config.service,
     );
-    let identity = tedge_config.http.client.auth.identity()?;
+    let identity = tedge_config.http_client_tls_config()?;
 
     let mut downloader_actor = DownloaderActor::new(identity.clone()).builder();
 
@@ -120,7 +120,7 @@ async fn run_with(
         &tedge_config.service,
     );
 
-    let identity = tedge_config.http.client.auth.identity()?;
+    let identity = tedge_config.http_client_tls_config()?;
     let mut uploader_actor = UploaderActor::new(identity).builder();
 
     // Instantiate log manager actor